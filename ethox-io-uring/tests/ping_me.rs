@@ -57,6 +57,7 @@ impl<P: wire::PayloadMut> eth::Send<P> for Dummy {
             dst_addr: self.0,
             src_addr: self.0,
             ethertype: wire::ethernet::EtherType::Ipv4,
+            vlan: None,
             payload: HELLO.len(),
         }).expect("Initialization failed");
 
@@ -71,6 +72,9 @@ impl<P: wire::PayloadMut> eth::Send<P> for Dummy {
 
 impl<P: wire::PayloadMut> eth::Recv<P> for Dummy {
     fn receive(&mut self, packet: eth::InPacket<P>) {
-        assert_eq!(packet.frame.payload().as_slice(), HELLO);
+        // The frame was padded to the minimum ethernet frame length.
+        let (payload, padding) = packet.frame.payload().as_slice().split_at(HELLO.len());
+        assert_eq!(payload, HELLO);
+        assert!(padding.iter().all(|&byte| byte == 0));
     }
 }
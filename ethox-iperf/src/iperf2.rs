@@ -256,6 +256,8 @@ impl Connection {
             dst_addr: config.host.into(),
             dst_port: config.port,
             payload: config.buffer_bytes,
+            fragmentation: ip::Fragmentation::default(),
+            hop_limit: None,
         }
     }
 
@@ -331,6 +333,8 @@ impl ServerConnection {
                 dst_addr: Default::default(),
                 dst_port: 0,
                 payload: 20 + mem::size_of::<WireResult>(),
+                fragmentation: ip::Fragmentation::default(),
+                hop_limit: None,
             },
             packet_size: 0,
             received_bytes: 0,
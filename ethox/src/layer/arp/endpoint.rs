@@ -3,13 +3,15 @@
 //! immediate communication hosts. To make the requests themselves we thus need to be informed
 //! about missing addresses.
 
-use crate::layer::{eth, Result};
-use crate::wire::{arp, ethernet, ip::Address as IpAddress, Payload, PayloadMut};
+use crate::layer::{eth, icmp, DropReason, Result};
+use crate::wire::{arp, ethernet, Payload, PayloadMut};
+use crate::wire::ip::{v4, Address as IpAddress};
+use crate::managed::Slice;
 use crate::time::Instant;
 use crate::layer::ip;
 
 use super::packet::{Controller, In, Init, Raw};
-use super::neighbor::Cache;
+use super::neighbor::{Cache, Error as NeighborError, Neighbor};
 
 /// The persistent data of an arp layer.
 ///
@@ -20,6 +22,22 @@ use super::neighbor::Cache;
 /// layers for handling their protocol specific arp tasks.
 pub struct Endpoint<'data> {
     neighbors: Cache<'data>,
+
+    /// An address to announce at the next send opportunity.
+    announce: Option<v4::Address>,
+
+    /// Networks for which requests are answered on behalf of other hosts.
+    proxy: ProxyConfig<'data>,
+}
+
+/// The networks for which an endpoint answers arp requests as a proxy.
+///
+/// Requests for any address in one of the networks are answered with our own hardware address,
+/// so that we receive the traffic for these hosts, for example to route it to them. Requests for
+/// our own addresses are answered normally regardless of this configuration.
+#[derive(Debug)]
+pub struct ProxyConfig<'data> {
+    networks: Slice<'data, v4::Subnet>,
 }
 
 /// An endpoint borrowed for receiving.
@@ -49,9 +67,58 @@ impl<'data> Endpoint<'data> {
     {
         Endpoint {
             neighbors: neighbors.into(),
+            announce: None,
+            proxy: ProxyConfig::new(Slice::empty()),
         }
     }
 
+    /// The networks for which requests are answered as a proxy.
+    pub fn proxy(&self) -> &ProxyConfig<'data> {
+        &self.proxy
+    }
+
+    /// Change the networks for which requests are answered as a proxy.
+    ///
+    /// By default, no requests are answered as a proxy.
+    pub fn set_proxy(&mut self, proxy: ProxyConfig<'data>) {
+        self.proxy = proxy;
+    }
+
+    /// Announce one of our addresses with a gratuitous arp reply.
+    ///
+    /// The reply is sent at the next send opportunity of a [`Sender`], before any outstanding
+    /// requests, and informs switches and neighbors of the hardware address now owning `addr`.
+    /// Call this after configuring an address and, if desired, periodically. Repeated calls are
+    /// idempotent on the wire: a pending announcement is replaced and each reply sent carries the
+    /// same information. The announcement is discarded when sending if the address is not
+    /// assigned to the ip endpoint used by the sender.
+    ///
+    /// [`Sender`]: struct.Sender.html
+    pub fn announce(&mut self, addr: v4::Address) {
+        self.announce = Some(addr);
+    }
+
+    /// Add a static mapping of an IPv4 address.
+    ///
+    /// The entry never expires and takes precedence over mappings learned from arp traffic. It
+    /// occupies a slot of the neighbor cache until removed with [`remove_static`].
+    ///
+    /// [`remove_static`]: #method.remove_static
+    pub fn add_static(&mut self, ip: v4::Address, mac: ethernet::Address)
+        -> core::result::Result<(), NeighborError>
+    {
+        self.neighbors.fill_static(IpAddress::Ipv4(ip), mac)
+    }
+
+    /// Remove a static mapping previously added with [`add_static`].
+    ///
+    /// [`add_static`]: #method.add_static
+    pub fn remove_static(&mut self, ip: v4::Address)
+        -> core::result::Result<(), NeighborError>
+    {
+        self.neighbors.remove_static(IpAddress::Ipv4(ip))
+    }
+
     /// A receiver that answers arp requests in stead of an ip endpoint.
     ///
     /// Utilizes the address and routing configuration of the endpoint but handles arp traffic
@@ -95,6 +162,27 @@ impl<'data> Endpoint<'data> {
     }
 }
 
+impl<'data> ProxyConfig<'data> {
+    /// Answer requests for addresses in any of the networks.
+    pub fn new<N>(networks: N) -> Self
+        where N: Into<Slice<'data, v4::Subnet>>,
+    {
+        ProxyConfig {
+            networks: networks.into(),
+        }
+    }
+
+    /// The networks for which requests are answered.
+    pub fn networks(&self) -> &[v4::Subnet] {
+        &self.networks
+    }
+
+    /// Check if requests for an address should be answered.
+    pub fn covers(&self, addr: v4::Address) -> bool {
+        self.networks.iter().any(|net| net.contains(addr))
+    }
+}
+
 impl EndpointRef<'_, '_> {
     /// Try to answer or otherwise handle the packet without propagating it upwards.
     ///
@@ -132,6 +220,11 @@ impl EndpointRef<'_, '_> {
             if let arp::Operation::Request = operation {
                 packet.answer()?.send()?;
             }
+        } else if target_protocol_addr.is_unicast() && self.inner.proxy.covers(target_protocol_addr) {
+            // Answer on behalf of the target, but never for the requester itself.
+            if operation == arp::Operation::Request && source_protocol_addr != target_protocol_addr {
+                packet.answer()?.send()?;
+            }
         }
 
         Ok(())
@@ -141,19 +234,26 @@ impl EndpointRef<'_, '_> {
     fn send_oustanding<P: PayloadMut>(&mut self, raw: Raw<P>) -> Result<()> {
         let ts = raw.control.info().timestamp();
 
+        if let Some(addr) = self.inner.announce.take() {
+            if self.ip.accepts(IpAddress::Ipv4(addr)) {
+                return self.send_announcement(raw, addr);
+            }
+        }
+
+        // Probes of neighbor unreachability detection take precedence.
+        if let Some(neighbor) = self.inner.neighbors.next_probe(ts) {
+            return self.send_probe(raw, neighbor);
+        }
+
         // Search through the missing arp entries:
         let unresolved = self.inner.neighbors
             .missing()
             // only those alive and not recently been requested already
             .filter(|missing| missing.is_alive(ts) && missing.looking_for())
-            // … and that are entries for ipv4
-            .filter_map(|missing| match missing.protocol_addr() {
-                IpAddress::Ipv4(addr) => Some(addr),
-                _ => None,
-            })
             // … and for which we can find a link-local outbound route.
-            .filter_map(|addr| {
-                self.ip.find_local_route(IpAddress::Ipv4(addr), ts)
+            .filter_map(|missing| {
+                let addr = missing.protocol_addr();
+                self.ip.find_local_route(addr, ts)
                     .map(|route| (addr, route))
             })
             .next();
@@ -163,30 +263,76 @@ impl EndpointRef<'_, '_> {
             Some(required) => required,
         };
 
-        debug_assert_eq!(route.next_hop, IpAddress::Ipv4(addr));
+        debug_assert_eq!(route.next_hop, addr);
 
-        let ip_src_address = match route.src_addr {
-            IpAddress::Ipv4(addr) => addr,
-            _ => unreachable!("Ipv4 destination routed with non-ipv4 source"),
-        };
+        // Reset the timer for that entry. Should always succeed.
+        let reset = self.inner.neighbors.requesting(addr, ts);
+        debug_assert!(reset.is_ok());
 
-        let mut raw = raw;
+        match (addr, route.src_addr) {
+            (IpAddress::Ipv4(addr), IpAddress::Ipv4(src_addr)) => {
+                self.send_request(raw, src_addr, addr)
+            },
+            (IpAddress::Ipv6(addr), IpAddress::Ipv6(src_addr)) => {
+                let Raw { control, payload } = raw;
+                let raw = eth::RawPacket { control: control.inner, payload };
+                icmp::ndp::solicit(raw, src_addr, addr, None)
+            },
+            _ => unreachable!("Destination routed with source of another protocol"),
+        }
+    }
 
+    /// Send a broadcast arp request for an IPv4 address.
+    fn send_request<P: PayloadMut>(&mut self, mut raw: Raw<P>, src_addr: v4::Address, addr: v4::Address)
+        -> Result<()>
+    {
         let src = raw.control.inner.src_addr();
         let prepared = raw.prepare(Init::EthernetIpv4Request {
             source_hardware_addr: src,
             target_hardware_addr: ethernet::Address::BROADCAST,
-            source_protocol_addr: ip_src_address,
+            source_protocol_addr: src_addr,
             target_protocol_addr: addr,
         })?;
+        prepared.send()
+    }
 
-        // Reset the timer for that entry. Should always succeed.
-        let reset = self.inner.neighbors.requesting(IpAddress::Ipv4(addr), ts);
-        debug_assert!(reset.is_ok());
+    /// Send a gratuitous arp reply for one of our addresses.
+    ///
+    /// See [RFC 5227], section 3.
+    ///
+    /// [RFC 5227]: https://tools.ietf.org/html/rfc5227
+    fn send_announcement<P: PayloadMut>(&mut self, mut raw: Raw<P>, addr: v4::Address)
+        -> Result<()>
+    {
+        let src = raw.control.inner.src_addr();
+        let prepared = raw.prepare(Init::EthernetIpv4Announcement {
+            source_hardware_addr: src,
+            protocol_addr: addr,
+        })?;
+        prepared.send()
+    }
 
-        prepared.send()?;
+    /// Send a unicast neighbor solicitation to a neighbor in the probe state.
+    ///
+    /// See [RFC 4861], section 7.3.3.
+    ///
+    /// [RFC 4861]: https://tools.ietf.org/html/rfc4861
+    fn send_probe<P: PayloadMut>(&mut self, raw: Raw<P>, neighbor: Neighbor) -> Result<()> {
+        let ts = raw.control.info().timestamp();
+        let (addr, dst_mac) = match (neighbor.protocol_addr(), neighbor.hardware_addr()) {
+            (IpAddress::Ipv6(addr), Some(mac)) => (addr, mac),
+            _ => unreachable!("Only resolved ipv6 neighbors are probed"),
+        };
 
-        Ok(())
+        // Without a source address the probe is counted as sent and unanswered.
+        let src_addr = match self.ip.find_local_route(addr.into(), ts).map(|route| route.src_addr) {
+            Some(IpAddress::Ipv6(src_addr)) => src_addr,
+            _ => return Ok(()),
+        };
+
+        let Raw { control, payload } = raw;
+        let raw = eth::RawPacket { control: control.inner, payload };
+        icmp::ndp::solicit(raw, src_addr, addr, Some(dst_mac))
     }
 
     fn update(&mut self, hw_addr: ethernet::Address, prot_addr: IpAddress, time: Instant) -> bool {
@@ -202,13 +348,16 @@ impl EndpointRef<'_, '_> {
 impl<P> eth::Recv<P> for Receiver<'_, '_>
     where P: PayloadMut,
 {
-    fn receive(&mut self, eth::InPacket { control, frame }: eth::InPacket<P>) {
+    fn receive(&mut self, eth::InPacket { mut control, frame }: eth::InPacket<P>) {
         let packet = match frame.repr().ethertype {
-            ethernet::EtherType::Arp => match arp::Packet::new_checked(frame) {
-                Ok(packet) => packet,
-                Err(_) => return,
+            ethernet::EtherType::Arp => match arp::Packet::new_checked(&frame) {
+                Ok(packet) => {
+                    let repr = packet.repr();
+                    arp::Packet::new_unchecked(frame, repr)
+                },
+                Err(err) => return control.report_drop(err.into(), frame.payload_slice()),
             },
-            _ => return,
+            _ => return control.report_drop(DropReason::Unsupported, frame.payload_slice()),
         };
 
         let control = Controller::new(control);
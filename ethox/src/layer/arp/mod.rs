@@ -8,17 +8,22 @@
 mod endpoint;
 mod neighbor;
 mod packet;
+mod probe;
 #[cfg(test)]
 mod tests;
 
-pub use endpoint::{Endpoint, Receiver, Sender};
+pub use endpoint::{Endpoint, ProxyConfig, Receiver, Sender};
 
 pub use neighbor::{
     Neighbor,
     Answer as NeighborAnswer,
     Mapping as NeighborMapping,
+    Reachability as NeighborReachability,
     Cache as NeighborCache,
+    Error as NeighborError,
     Table as NeighborTable,
 };
 
 pub use packet::{Controller, In as InPacket, Init, Out as OutPacket, Raw as RawPacket};
+
+pub use probe::{Probe, ProbeState};
@@ -12,12 +12,47 @@ use crate::wire::{ethernet, ip};
 /// A neighbor mapping translates from a protocol address (IPv4 and IPv6) to a hardware address,
 /// and contains the timestamp past which the mapping should be considered invalid. It also
 /// contains a timestamp at which we should try to update the neighbor mapping by sending out
-/// solicitation requests.
+/// solicitation requests. Entries for IPv6 neighbors additionally track their reachability as
+/// determined by neighbor unreachability detection. Static entries never expire and are not
+/// replaced by dynamically learned mappings.
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Neighbor {
     protocol_addr: ip::Address,
     hardware_addr: Mapping,
     expires_at:    Expiration,
+    reachability:  Reachability,
+    state_until:   Expiration,
+    probes:        u8,
+    is_static:     bool,
+}
+
+/// The reachability state of a resolved neighbor.
+///
+/// Implements the states of neighbor unreachability detection as described in [RFC 4861] section
+/// 7.3.2. This is only tracked for IPv6 neighbors, entries for other protocols are always
+/// considered `Reachable` until they expire. The `Incomplete` state is represented by the
+/// `LookingFor` and `Requesting` mappings instead.
+///
+/// [RFC 4861]: https://tools.ietf.org/html/rfc4861
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Reachability {
+    /// Reachability was confirmed within the reachable time.
+    Reachable,
+
+    /// The reachable time elapsed without confirmation.
+    ///
+    /// The mapping is still used but the next use will start probing.
+    Stale,
+
+    /// The entry was used while stale, probing starts after a short delay.
+    ///
+    /// This gives upper layers the chance to confirm the reachability first.
+    Delay,
+
+    /// Unicast solicitations are sent to the neighbor.
+    ///
+    /// The entry is removed if none of them is answered.
+    Probe,
 }
 
 /// An answer to a neighbor cache lookup.
@@ -51,6 +86,12 @@ pub enum Mapping {
     Requesting,
 }
 
+impl Default for Reachability {
+    fn default() -> Self {
+        Reachability::Reachable
+    }
+}
+
 impl Default for Mapping {
     fn default() -> Self {
         Mapping::LookingFor
@@ -107,6 +148,7 @@ pub enum Error {
 pub struct Cache<'a> {
     storage:      Ordered<'a, Neighbor>,
     silent_until: Instant,
+    lifetime:     Duration,
 }
 
 /// Iterator over missing entries.
@@ -133,6 +175,23 @@ impl<'a> Cache<'a> {
     /// Neighbor entry lifetime, in milliseconds.
     pub(crate) const ENTRY_LIFETIME: Duration = Duration::from_millis(60_000);
 
+    /// Lifetime of an entry whose address is still being resolved.
+    ///
+    /// Determines how soon an unanswered request is repeated.
+    pub(crate) const INCOMPLETE_LIFETIME: Duration = Duration::from_millis(3_000);
+
+    /// Time after a confirmation for which an IPv6 neighbor is considered reachable.
+    pub(crate) const REACHABLE_TIME: Duration = Duration::from_millis(30_000);
+
+    /// Delay before the first probe of a stale entry that was used.
+    pub(crate) const DELAY_FIRST_PROBE_TIME: Duration = Duration::from_millis(5_000);
+
+    /// Time between unicast probes.
+    pub(crate) const RETRANS_TIMER: Duration = Duration::from_millis(1_000);
+
+    /// Number of unanswered unicast probes before the entry is removed.
+    pub(crate) const MAX_UNICAST_SOLICIT: u8 = 3;
+
     /// Create a cache.
     ///
     /// The backing storage is created logically empty.
@@ -149,7 +208,43 @@ impl<'a> Cache<'a> {
     /// currently not checked beforehand!
     // TODO: remove duplicate entires, e.g. `slice::partition_dedup_by_key` once stable.
     pub fn import(storage: Ordered<'a, Neighbor>) -> Self {
-        Cache { storage, silent_until: Instant::from_millis(0) }
+        Cache {
+            storage,
+            silent_until: Instant::from_millis(0),
+            lifetime: Self::ENTRY_LIFETIME,
+        }
+    }
+
+    /// The lifetime of resolved entries.
+    pub fn lifetime(&self) -> Duration {
+        self.lifetime
+    }
+
+    /// Change the lifetime of resolved entries.
+    ///
+    /// Only affects entries filled afterwards. Entries whose address is still being resolved
+    /// always expire after a few seconds, so that unanswered requests are repeated. The default
+    /// lifetime is one minute.
+    pub fn set_lifetime(&mut self, lifetime: Duration) {
+        self.lifetime = lifetime;
+    }
+
+    /// Remove all expired entries.
+    ///
+    /// Expired entries are never returned by a lookup and are the first to be replaced when the
+    /// cache is full, so this is not required for correctness. It frees their slots early and
+    /// returns the number of removed entries.
+    pub fn flush_expired(&mut self, timestamp: Instant) -> usize {
+        let mut removed = 0;
+        while let Some(index) = self.storage.ordered_slice()
+            .iter()
+            .position(|entry| entry.is_expired(timestamp))
+        {
+            self.storage.pop(index)
+                .expect("Entry we just found is valid.");
+            removed += 1;
+        }
+        removed
     }
 
     /// Add a lookup entry.
@@ -160,7 +255,7 @@ impl<'a> Cache<'a> {
         protocol_addr: ip::Address,
         timestamp: Option<Instant>,
     ) -> Result<(), Error> {
-        self.update_or_insert(protocol_addr, Mapping::LookingFor, timestamp)
+        self.update_or_insert(protocol_addr, Mapping::LookingFor, timestamp, false)
     }
 
     /// Indicate an entry is currently being requested.
@@ -171,7 +266,7 @@ impl<'a> Cache<'a> {
         protocol_addr: ip::Address,
         timestamp: Instant,
     ) -> Result<(), Error> {
-        self.update_or_insert(protocol_addr, Mapping::Requesting, Some(timestamp))
+        self.update_or_insert(protocol_addr, Mapping::Requesting, Some(timestamp), false)
     }
 
     /// Add an entry containing a MAC address.
@@ -183,27 +278,71 @@ impl<'a> Cache<'a> {
         hardware_addr: ethernet::Address,
         timestamp: Option<Instant>,
     ) -> Result<(), Error> {
-        self.update_or_insert(protocol_addr, Mapping::Address(hardware_addr), timestamp)
+        self.update_or_insert(protocol_addr, Mapping::Address(hardware_addr), timestamp, false)
+    }
+
+    /// Add a static entry containing a MAC address.
+    ///
+    /// The entry never expires and takes precedence over dynamically learned mappings, which are
+    /// ignored for its protocol address until it is removed with `remove_static`. It is also never
+    /// evicted to make room for other entries.
+    pub fn fill_static(
+        &mut self,
+        protocol_addr: ip::Address,
+        hardware_addr: ethernet::Address,
+    ) -> Result<(), Error> {
+        self.update_or_insert(protocol_addr, Mapping::Address(hardware_addr), None, true)
+    }
+
+    /// Remove a static entry.
+    ///
+    /// Returns `Err(Error::EntryNotFound)` if there is no static entry for the address, dynamic
+    /// entries are left untouched.
+    pub fn remove_static(&mut self, protocol_addr: ip::Address) -> Result<(), Error> {
+        let index = self.find(protocol_addr)?;
+        if !self.storage[index].is_static {
+            return Err(Error::EntryNotFound);
+        }
+
+        self.storage.pop(index)
+            .expect("Entry we just found is valid.");
+        Ok(())
     }
 
     /// Add an entry.
     ///
-    /// Provide the current timestamp or `None` to disable expiration.
+    /// Provide the current timestamp or `None` to disable expiration. A dynamic entry never
+    /// replaces a static one.
     fn update_or_insert(
         &mut self,
         protocol_addr: ip::Address,
         hardware_addr: Mapping,
         timestamp: Option<Instant>,
+        is_static: bool,
     ) -> Result<(), Error> {
         debug_assert!(protocol_addr.is_unicast());
         if let Mapping::Address(hw_addr) = hardware_addr {
             debug_assert!(hw_addr.is_unicast());
         }
 
+        let lifetime = match hardware_addr {
+            Mapping::Address(_) => self.lifetime,
+            Mapping::LookingFor | Mapping::Requesting => Self::INCOMPLETE_LIFETIME,
+        };
+
+        let reachable_until = match (protocol_addr, hardware_addr, timestamp) {
+            (ip::Address::Ipv6(_), Mapping::Address(_), Some(ts)) => Some(ts + Self::REACHABLE_TIME),
+            _ => None,
+        };
+
         let new_neighbor = Neighbor {
             protocol_addr,
             hardware_addr,
-            expires_at: timestamp.map(|ts| ts + Self::ENTRY_LIFETIME).into(),
+            expires_at: timestamp.map(|ts| ts + lifetime).into(),
+            reachability: Reachability::Reachable,
+            state_until: reachable_until.into(),
+            probes: 0,
+            is_static,
         };
 
         // Is this already mapped?
@@ -213,6 +352,11 @@ impl<'a> Cache<'a> {
             let old = self.storage[index];
             assert_eq!(old.protocol_addr, new_neighbor.protocol_addr);
 
+            if old.is_static && !new_neighbor.is_static {
+                // Statically configured, the dynamic mapping is ignored.
+                return Ok(())
+            }
+
             if let (Mapping::Requesting, Mapping::LookingFor) = (old.hardware_addr, new_neighbor.hardware_addr) {
                 if old.expires_at >= Expiration::from(timestamp) {
                     // A not-yet expired request is currently running. Simply do nothing.
@@ -231,10 +375,11 @@ impl<'a> Cache<'a> {
                 entry
             },
             None => {
-                // find the oldest entry.
+                // find the oldest entry, static ones are never evicted.
                 let (idx, oldest) = self.storage.ordered_slice()
                     .iter()
                     .enumerate()
+                    .filter(|(_, neighbor)| !neighbor.is_static)
                     .min_by_key(|(_, neighbor)| neighbor.expires_at)
                     .ok_or(Error::NoSpace)?;
                 if oldest.expires_at > new_neighbor.expires_at {
@@ -254,6 +399,72 @@ impl<'a> Cache<'a> {
             .expect("There was one to insert");
         Ok(())
     }
+
+    /// Confirm that a resolved neighbor is reachable.
+    ///
+    /// This should be called whenever there is proof of two-way communication with the neighbor,
+    /// for example a solicited advertisement or new acknowledgements of a tcp connection. Returns
+    /// `Err(Error::EntryNotFound)` if there is no resolved entry for the address.
+    pub fn confirm(&mut self, protocol_addr: ip::Address, timestamp: Instant) -> Result<(), Error> {
+        let index = self.find(protocol_addr)?;
+        let hardware_addr = self.storage[index].hardware_addr().ok_or(Error::EntryNotFound)?;
+        self.fill(protocol_addr, hardware_addr, Some(timestamp))
+    }
+
+    /// Note that a packet is about to be sent to the neighbor.
+    ///
+    /// A stale entry enters the delay state in which a probe is scheduled unless its reachability
+    /// is confirmed in the meantime.
+    pub fn used(&mut self, protocol_addr: ip::Address, timestamp: Instant) {
+        let index = match self.find(protocol_addr) {
+            Ok(index) => index,
+            Err(_) => return,
+        };
+
+        let mut entry = self.storage[index];
+        if entry.reachability(timestamp) != Reachability::Stale {
+            return;
+        }
+
+        entry.reachability = Reachability::Delay;
+        entry.state_until = Expiration::When(timestamp + Self::DELAY_FIRST_PROBE_TIME);
+        entry.probes = 0;
+        self.storage.replace_at(index, entry)
+            .expect("Protocol address did not change");
+    }
+
+    /// Get the next neighbor that should be probed with a unicast solicitation.
+    ///
+    /// Entries that have not answered any of their probes are removed from the cache first. The
+    /// probe of the returned entry is counted and rescheduled, so the caller is expected to
+    /// actually send it.
+    pub(crate) fn next_probe(&mut self, timestamp: Instant) -> Option<Neighbor> {
+        while let Some(index) = self.storage.ordered_slice()
+            .iter()
+            .position(|entry| entry.is_unreachable(timestamp))
+        {
+            self.storage.pop(index)
+                .expect("Entry we just found is valid.");
+        }
+
+        let index = self.storage.ordered_slice()
+            .iter()
+            .position(|entry| entry.probe_due(timestamp))?;
+
+        let mut entry = self.storage[index];
+        entry.reachability = Reachability::Probe;
+        entry.state_until = Expiration::When(timestamp + Self::RETRANS_TIMER);
+        entry.probes += 1;
+        self.storage.replace_at(index, entry)
+            .expect("Protocol address did not change");
+        Some(entry)
+    }
+
+    fn find(&self, protocol_addr: ip::Address) -> Result<usize, Error> {
+        self.storage.ordered_slice()
+            .binary_search_by_key(&protocol_addr, |neighbor| neighbor.protocol_addr)
+            .map_err(|_| Error::EntryNotFound)
+    }
 }
 
 impl Table {
@@ -307,6 +518,11 @@ impl Table {
         Some(entry.hardware_addr)
     }
 
+    /// Check if any neighbor needs to be probed or removed after failed probes.
+    pub fn probing(&self, timestamp: Instant) -> bool {
+        self.0.iter().any(|entry| entry.probe_due(timestamp) || entry.is_unreachable(timestamp))
+    }
+
     /// An iterator over entries with no response yet.
     pub fn missing(&self) -> Missing {
         Missing {
@@ -344,10 +560,45 @@ impl Neighbor {
         !self.is_alive(ts)
     }
 
+    /// If this entry was statically configured.
+    pub fn is_static(&self) -> bool {
+        self.is_static
+    }
+
     /// If this address mapping is unknown and should be requested.
     pub fn looking_for(&self) -> bool {
         self.hardware_addr == Mapping::LookingFor
     }
+
+    /// The reachability of the neighbor at some point in time.
+    pub fn reachability(&self, ts: Instant) -> Reachability {
+        match self.reachability {
+            Reachability::Reachable if Expiration::When(ts) >= self.state_until
+                => Reachability::Stale,
+            other => other,
+        }
+    }
+
+    /// The number of unicast probes sent since the neighbor was last confirmed.
+    pub fn probes(&self) -> u8 {
+        self.probes
+    }
+
+    fn probe_due(&self, ts: Instant) -> bool {
+        match self.reachability {
+            Reachability::Delay | Reachability::Probe => {
+                Expiration::When(ts) >= self.state_until
+                    && self.probes < Cache::MAX_UNICAST_SOLICIT
+            },
+            _ => false,
+        }
+    }
+
+    fn is_unreachable(&self, ts: Instant) -> bool {
+        self.reachability == Reachability::Probe
+            && self.probes >= Cache::MAX_UNICAST_SOLICIT
+            && Expiration::When(ts) >= self.state_until
+    }
 }
 
 impl Deref for Cache<'_> {
@@ -474,4 +725,71 @@ mod test {
         assert!(cache.fill(MOCK_IP_ADDR_1, HADDR_B, None).is_ok());
         assert!(cache.fill(MOCK_IP_ADDR_2, HADDR_A, None).is_ok());
     }
+
+    #[test]
+    fn unreachability_detection() {
+        let mut cache_storage = [Default::default(); 2];
+        let mut cache = Cache::new(&mut cache_storage[..]);
+        let at = |millis| Instant::from_millis(millis);
+
+        cache.fill(MOCK_IP_ADDR_1, HADDR_A, Some(at(0))).unwrap();
+        assert_eq!(cache[0].reachability(at(0)), Reachability::Reachable);
+        assert_eq!(cache[0].reachability(at(30_000)), Reachability::Stale);
+        assert!(!cache.probing(at(30_000)));
+
+        // Use while stale delays the first probe.
+        cache.used(MOCK_IP_ADDR_1, at(31_000));
+        assert_eq!(cache[0].reachability(at(31_000)), Reachability::Delay);
+        assert_eq!(cache.next_probe(at(32_000)), None);
+
+        // Confirmation makes it reachable again.
+        cache.confirm(MOCK_IP_ADDR_1, at(33_000)).unwrap();
+        assert_eq!(cache[0].reachability(at(33_000)), Reachability::Reachable);
+        cache.used(MOCK_IP_ADDR_1, at(33_000));
+        assert_eq!(cache[0].reachability(at(33_000)), Reachability::Reachable);
+
+        cache.used(MOCK_IP_ADDR_1, at(63_000));
+        assert!(cache.probing(at(68_000)));
+        for probe in 1..=Cache::MAX_UNICAST_SOLICIT {
+            let now = at(67_000 + 1_000*i64::from(probe));
+            let entry = cache.next_probe(now).unwrap();
+            assert_eq!(entry.protocol_addr(), MOCK_IP_ADDR_1);
+            assert_eq!(entry.reachability(now), Reachability::Probe);
+            assert_eq!(entry.probes(), probe);
+            assert_eq!(cache.lookup_pure(MOCK_IP_ADDR_1, now), Some(HADDR_A));
+        }
+
+        assert_eq!(cache.next_probe(at(71_000)), None);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn lifetime() {
+        let mut cache_storage = [Default::default(); 3];
+        let mut cache = Cache::new(&mut cache_storage[..]);
+        cache.set_lifetime(Duration::from_millis(10_000));
+
+        cache.fill(MOCK_IP_ADDR_1, HADDR_A, Some(Instant::from_millis(0)))
+            .unwrap();
+        cache.fill_looking(MOCK_IP_ADDR_2, Some(Instant::from_millis(0)))
+            .unwrap();
+        cache.fill(MOCK_IP_ADDR_3, HADDR_C, None)
+            .unwrap();
+
+        // Pending entries are never resolved.
+        assert_eq!(cache.lookup_pure(MOCK_IP_ADDR_2, Instant::from_millis(0)), None);
+        assert_eq!(cache.lookup(MOCK_IP_ADDR_2, Instant::from_millis(0)), Some(Mapping::LookingFor));
+
+        // And they expire before resolved ones.
+        let pending_expired = Instant::from_millis(0) + Cache::INCOMPLETE_LIFETIME;
+        assert_eq!(cache.lookup(MOCK_IP_ADDR_2, pending_expired), None);
+        assert_eq!(cache.lookup_pure(MOCK_IP_ADDR_1, pending_expired), Some(HADDR_A));
+        assert_eq!(cache.flush_expired(Instant::from_millis(5_000)), 1);
+        assert_eq!(cache.len(), 2);
+
+        assert_eq!(cache.lookup_pure(MOCK_IP_ADDR_1, Instant::from_millis(10_000)), None);
+        assert_eq!(cache.flush_expired(Instant::from_millis(20_000)), 1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.lookup_pure(MOCK_IP_ADDR_3, Instant::from_millis(20_000)), Some(HADDR_C));
+    }
 }
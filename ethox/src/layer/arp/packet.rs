@@ -48,6 +48,16 @@ pub enum Init {
         /// The IPv4 address of the target.
         target_protocol_addr: ip::v4::Address,
     },
+    /// As a gratuitous arp reply announcing an Ethernet-IPv4 translation.
+    ///
+    /// The announced address is used as both the source and target protocol address, and the
+    /// target hardware address is broadcast.
+    EthernetIpv4Announcement {
+        /// The hardware address to announce.
+        source_hardware_addr: ethernet::Address,
+        /// The IPv4 address to announce.
+        protocol_addr: ip::v4::Address,
+    },
 }
 
 impl<'a> Controller<'a> {
@@ -112,6 +122,8 @@ impl<'a, P: PayloadMut> In<'a, P> {
             src_addr: self.control.inner.src_addr(),
             dst_addr: dst_address,
             ethertype: ethernet::EtherType::Arp,
+            // Answer within the same virtual lan.
+            vlan: eth_frame.repr().vlan,
             payload: 28,
         };
 
@@ -166,6 +178,7 @@ impl<'a, P: Payload + PayloadMut> Raw<'a, P> {
             src_addr: lower.control.src_addr(),
             dst_addr: ethernet::Address::BROADCAST,
             ethertype: ethernet::EtherType::Arp,
+            vlan: None,
             payload: 28,
         };
 
@@ -207,6 +220,16 @@ impl Init {
                 target_hardware_addr,
                 target_protocol_addr,
             },
+            Init::EthernetIpv4Announcement {
+                source_hardware_addr,
+                protocol_addr,
+            } => arp::Repr::EthernetIpv4 {
+                operation: arp::Operation::Reply,
+                source_hardware_addr,
+                source_protocol_addr: protocol_addr,
+                target_hardware_addr: ethernet::Address::BROADCAST,
+                target_protocol_addr: protocol_addr,
+            },
         }
     }
 }
@@ -0,0 +1,279 @@
+//! Address conflict detection, as specified in RFC 5227.
+//!
+//! Before using an IPv4 address, a host probes the link for other hosts already using it. The
+//! probes are arp requests with an unspecified sender protocol address, so that they do not
+//! pollute the caches of other hosts. Any arp packet sent from the tentative address, as well as
+//! probes of other hosts for the same address, indicate a conflict.
+use crate::layer::{eth, DropReason, Result};
+use crate::time::{Duration, Instant};
+use crate::wire::{arp, ethernet, ip::v4, Payload, PayloadMut};
+
+use super::packet::{Controller, Init, Raw};
+
+/// A state machine probing for conflicts of a tentative address.
+///
+/// Use it as both an ethernet receiver and sender for the duration of the probe, and `poll` it
+/// to find out if the address may be used.
+///
+/// The timing follows the constants of [RFC 5227], section 1.1, without randomization: the first
+/// probe is sent after `PROBE_WAIT`, the following ones `PROBE_INTERVAL` apart and the address is
+/// available when no conflict was observed until `ANNOUNCE_WAIT` after the last probe. Conflicts
+/// are detected in all stages, including before the first probe was sent.
+///
+/// [RFC 5227]: https://tools.ietf.org/html/rfc5227
+#[derive(Clone, Copy, Debug)]
+pub struct Probe {
+    addr: v4::Address,
+    state: ProbeState,
+    sent: u8,
+    next: Instant,
+}
+
+/// The result of a probe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProbeState {
+    /// No conflict yet but the probe is not finished.
+    Probing,
+
+    /// No conflict was observed, the address may be used.
+    Available,
+
+    /// Another host is using or probing for the address.
+    Conflict(ethernet::Address),
+}
+
+impl Probe {
+    /// Delay before the first probe.
+    pub const PROBE_WAIT: Duration = Duration::from_secs(1);
+
+    /// Number of probes sent.
+    pub const PROBE_NUM: u8 = 3;
+
+    /// Delay between two probes.
+    pub const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Delay after the last probe before the address is available.
+    pub const ANNOUNCE_WAIT: Duration = Duration::from_secs(2);
+
+    /// Start probing for a tentative address.
+    pub fn new(addr: v4::Address, now: Instant) -> Self {
+        Probe {
+            addr,
+            state: ProbeState::Probing,
+            sent: 0,
+            next: now + Self::PROBE_WAIT,
+        }
+    }
+
+    /// The tentative address.
+    pub fn address(&self) -> v4::Address {
+        self.addr
+    }
+
+    /// Check the result of the probe.
+    ///
+    /// Once the probe concluded, with either result, the state does not change anymore.
+    pub fn poll(&mut self, now: Instant) -> ProbeState {
+        if self.state == ProbeState::Probing && self.sent == Self::PROBE_NUM && now >= self.next {
+            self.state = ProbeState::Available;
+        }
+
+        self.state
+    }
+
+    /// Inspect a received arp packet for a conflict.
+    fn observe(&mut self, repr: arp::Repr, own: ethernet::Address) {
+        let (operation, source_hardware_addr, source_protocol_addr, target_protocol_addr) = match repr {
+            arp::Repr::EthernetIpv4 {
+                operation,
+                source_hardware_addr,
+                source_protocol_addr,
+                target_hardware_addr: _,
+                target_protocol_addr,
+            } => (operation, source_hardware_addr, source_protocol_addr, target_protocol_addr),
+            _ => return,
+        };
+
+        // Our own packets, looped back.
+        if self.state != ProbeState::Probing || source_hardware_addr == own {
+            return;
+        }
+
+        // Some host uses the address, in a request or reply.
+        let in_use = source_protocol_addr == self.addr;
+        // Some host probes for the same address.
+        let probing = operation == arp::Operation::Request
+            && source_protocol_addr == v4::Address::UNSPECIFIED
+            && target_protocol_addr == self.addr;
+
+        if in_use || probing {
+            self.state = ProbeState::Conflict(source_hardware_addr);
+        }
+    }
+
+    fn send_probe<P: PayloadMut>(&mut self, mut raw: Raw<P>) -> Result<()> {
+        let now = raw.control.info().timestamp();
+        if self.state != ProbeState::Probing || self.sent == Self::PROBE_NUM || now < self.next {
+            return Ok(());
+        }
+
+        let src = raw.control.inner.src_addr();
+        let prepared = raw.prepare(Init::EthernetIpv4Request {
+            source_hardware_addr: src,
+            source_protocol_addr: v4::Address::UNSPECIFIED,
+            target_hardware_addr: ethernet::Address([0; 6]),
+            target_protocol_addr: self.addr,
+        })?;
+        prepared.send()?;
+
+        self.sent += 1;
+        self.next = now + if self.sent == Self::PROBE_NUM {
+            Self::ANNOUNCE_WAIT
+        } else {
+            Self::PROBE_INTERVAL
+        };
+
+        Ok(())
+    }
+}
+
+impl<P> eth::Recv<P> for Probe
+    where P: PayloadMut,
+{
+    fn receive(&mut self, eth::InPacket { mut control, frame }: eth::InPacket<P>) {
+        let repr = match frame.repr().ethertype {
+            ethernet::EtherType::Arp => match arp::Packet::new_checked(&frame) {
+                Ok(packet) => packet.repr(),
+                Err(err) => return control.report_drop(err.into(), frame.payload_slice()),
+            },
+            _ => return control.report_drop(DropReason::Unsupported, frame.payload_slice()),
+        };
+
+        let own = control.src_addr();
+        self.observe(repr, own);
+    }
+}
+
+impl<P> eth::Send<P> for Probe
+    where P: Payload + PayloadMut,
+{
+    fn send(&mut self, packet: eth::RawPacket<P>) {
+        let eth::RawPacket {
+            control: mut eth_handle,
+            payload,
+        } = packet;
+
+        let control = Controller::new(eth_handle.borrow_mut());
+        let packet = Raw::new(control, payload);
+
+        if let Err(_) = self.send_probe(packet) {
+            // TODO: log error
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::managed::Slice;
+    use crate::nic::{external::External, Device};
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_TENTATIVE: v4::Address = v4::Address::new(10, 0, 0, 1);
+
+    fn at(millis: i64) -> Instant {
+        Instant::from_millis(millis)
+    }
+
+    /// Let the other host send an arp packet to us.
+    fn receive_from_other(probe: &mut Probe, repr: arp::Repr) {
+        let mut nic = External::new_send(Slice::One(vec![0; 64]));
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+        let buffer = nic.get_mut(0).unwrap();
+        let frame = ethernet::frame::new_unchecked_mut(buffer);
+        ethernet::Repr {
+            src_addr: MAC_ADDR_OTHER,
+            dst_addr: ethernet::Address::BROADCAST,
+            ethertype: ethernet::EtherType::Arp,
+            vlan: None,
+        }.emit(frame);
+        repr.emit(arp::packet::new_unchecked_mut(&mut frame.payload_mut_slice()[..28]));
+
+        nic.receive_all();
+        assert_eq!(nic.rx(1, eth.recv(probe)), Ok(1));
+    }
+
+    #[test]
+    fn available() {
+        let mut nic = External::new_send(Slice::One(vec![0; 64]));
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        let mut probe = Probe::new(IP_ADDR_TENTATIVE, at(0));
+
+        // Nothing sent before the initial wait.
+        nic.set_current_time(at(500));
+        assert_eq!(nic.tx(1, eth.send(&mut probe)), Ok(0));
+
+        for sent in 0..3 {
+            let now = at(1_000 + 1_000*sent);
+            assert_eq!(probe.poll(now), ProbeState::Probing);
+            nic.set_current_time(now);
+            nic.reset_send();
+            assert_eq!(nic.tx(1, eth.send(&mut probe)), Ok(1));
+
+            let frame = ethernet::frame::new_checked(&nic.get(0).unwrap()[..]).unwrap();
+            assert_eq!(frame.dst_addr(), ethernet::Address::BROADCAST);
+            let packet = arp::packet::new_checked(frame.payload_slice()).unwrap();
+            assert_eq!(arp::Repr::parse(packet), Ok(arp::Repr::EthernetIpv4 {
+                operation: arp::Operation::Request,
+                source_hardware_addr: MAC_ADDR_HOST,
+                source_protocol_addr: v4::Address::UNSPECIFIED,
+                target_hardware_addr: ethernet::Address([0; 6]),
+                target_protocol_addr: IP_ADDR_TENTATIVE,
+            }));
+        }
+
+        nic.reset_send();
+        assert_eq!(nic.tx(1, eth.send(&mut probe)), Ok(0));
+        assert_eq!(probe.poll(at(4_000)), ProbeState::Probing);
+        assert_eq!(probe.poll(at(5_000)), ProbeState::Available);
+    }
+
+    #[test]
+    fn conflict_reply() {
+        let mut probe = Probe::new(IP_ADDR_TENTATIVE, at(0));
+        receive_from_other(&mut probe, arp::Repr::EthernetIpv4 {
+            operation: arp::Operation::Reply,
+            source_hardware_addr: MAC_ADDR_OTHER,
+            source_protocol_addr: IP_ADDR_TENTATIVE,
+            target_hardware_addr: MAC_ADDR_HOST,
+            target_protocol_addr: v4::Address::UNSPECIFIED,
+        });
+        assert_eq!(probe.poll(at(10_000)), ProbeState::Conflict(MAC_ADDR_OTHER));
+    }
+
+    #[test]
+    fn conflict_probe() {
+        let mut probe = Probe::new(IP_ADDR_TENTATIVE, at(0));
+
+        // Requests for other addresses are fine.
+        receive_from_other(&mut probe, arp::Repr::EthernetIpv4 {
+            operation: arp::Operation::Request,
+            source_hardware_addr: MAC_ADDR_OTHER,
+            source_protocol_addr: v4::Address::UNSPECIFIED,
+            target_hardware_addr: ethernet::Address([0; 6]),
+            target_protocol_addr: v4::Address::new(10, 0, 0, 2),
+        });
+        assert_eq!(probe.poll(at(0)), ProbeState::Probing);
+
+        receive_from_other(&mut probe, arp::Repr::EthernetIpv4 {
+            operation: arp::Operation::Request,
+            source_hardware_addr: MAC_ADDR_OTHER,
+            source_protocol_addr: v4::Address::UNSPECIFIED,
+            target_hardware_addr: ethernet::Address([0; 6]),
+            target_protocol_addr: IP_ADDR_TENTATIVE,
+        });
+        assert_eq!(probe.poll(at(10_000)), ProbeState::Conflict(MAC_ADDR_OTHER));
+    }
+}
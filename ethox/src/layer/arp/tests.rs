@@ -1,7 +1,8 @@
 use crate::managed::Slice;
 use crate::nic::{external::External, Device};
 use crate::layer::{eth, ip as ip_layer, arp as arp_layer};
-use crate::wire::{ethernet, ip, arp};
+use crate::time::Instant;
+use crate::wire::{ethernet, icmpv6, ip, arp};
 
 const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
 const IP_ADDR_HOST: ip::v4::Address = ip::v4::Address::new(127, 0, 0, 1);
@@ -33,6 +34,7 @@ fn simple_arp() {
             src_addr: MAC_ADDR_OTHER,
             dst_addr: MAC_ADDR_HOST,
             ethertype: ethernet::EtherType::Arp,
+            vlan: None,
         }.emit(eth);
         eth.set_dst_addr(MAC_ADDR_HOST);
         eth.set_src_addr(MAC_ADDR_OTHER);
@@ -65,3 +67,293 @@ fn simple_arp() {
     assert_eq!(arp.target_hardware_addr(), MAC_ADDR_OTHER);
     assert_eq!(arp.target_protocol_addr(), IP_ADDR_OTHER);
 }
+
+#[test]
+fn vlan_arp() {
+    const VLAN: ethernet::VlanTag = ethernet::VlanTag {
+        priority: 3,
+        drop_eligible: false,
+        vlan_id: 42,
+    };
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+    let mut neighbors = [arp_layer::Neighbor::default(); 1];
+    let mut routes = [ip_layer::Route::unspecified(); 2];
+    let mut ip = ip_layer::Endpoint::new(ip::Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip_layer::Routes::new(&mut routes[..]),
+        arp_layer::NeighborCache::new(Slice::empty()));
+
+    let mut arp = arp_layer::Endpoint::new(arp_layer::NeighborCache::new(&mut neighbors[..]));
+
+    {
+        // Initialize a tagged request.
+        let buffer = nic.get_mut(0).unwrap();
+        buffer.resize(18 + 28, 0u8);
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        ethernet::Repr {
+            src_addr: MAC_ADDR_OTHER,
+            dst_addr: ethernet::Address::BROADCAST,
+            ethertype: ethernet::EtherType::Arp,
+            vlan: Some(VLAN),
+        }.emit(eth);
+        let arp = arp::packet::new_unchecked_mut(&mut eth.as_bytes_mut()[18..]);
+        arp::Repr::EthernetIpv4 {
+            operation: arp::Operation::Request,
+            source_hardware_addr: MAC_ADDR_OTHER,
+            source_protocol_addr: IP_ADDR_OTHER,
+            target_hardware_addr: ethernet::Address([0; 6]),
+            target_protocol_addr: IP_ADDR_HOST,
+        }.emit(arp);
+    }
+
+    nic.receive_all();
+
+    let recv = nic.rx(1, eth.recv(arp.answer(&mut ip)));
+    assert_eq!(recv, Ok(1));
+
+    let buffer = nic.get_mut(0).unwrap();
+    let frame = ethernet::Frame::new_checked(&buffer[..]).unwrap();
+    assert_eq!(frame.ethertype(), ethernet::EtherType::Vlan);
+    assert_eq!(frame.repr().ethertype, ethernet::EtherType::Arp);
+    assert_eq!(frame.dst_addr(), MAC_ADDR_OTHER);
+    assert_eq!(frame.vlan_id(), Some(42));
+    assert_eq!(frame.priority(), Some(3));
+
+    let arp = arp::packet::new_checked(frame.payload_slice()).unwrap();
+    assert_eq!(arp.operation(), arp::Operation::Reply);
+    assert_eq!(arp.source_hardware_addr(), MAC_ADDR_HOST);
+    assert_eq!(arp.source_protocol_addr(), IP_ADDR_HOST);
+    assert_eq!(arp.target_protocol_addr(), IP_ADDR_OTHER);
+}
+
+#[test]
+fn unicast_probe() {
+    const IP_ADDR_HOST: ip::v6::Address = ip::v6::Address(
+        [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    const IP_ADDR_OTHER: ip::v6::Address = ip::v6::Address(
+        [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+    let mut neighbors = [arp_layer::Neighbor::default(); 1];
+    let neighbors = {
+        let mut cache = arp_layer::NeighborCache::new(&mut neighbors[..]);
+        cache.fill(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER, Some(Instant::from_millis(0))).unwrap();
+        cache
+    };
+    let mut routes = [ip_layer::Route::unspecified(); 2];
+    let mut ip = ip_layer::Endpoint::new(ip::Cidr::new(IP_ADDR_HOST.into(), 64),
+        ip_layer::Routes::new(&mut routes[..]),
+        neighbors);
+
+    let init = ip_layer::Init {
+        source: ip::Subnet::from(ip::v6::Subnet::ANY).into(),
+        dst_addr: IP_ADDR_OTHER.into(),
+        protocol: ip::Protocol::Unknown(0xEF),
+        payload: 10,
+        hop_limit: None,
+        traffic_class: 0,
+    };
+
+    // Using the stale entry still sends to the neighbor but starts the delay.
+    nic.set_current_time(Instant::from_millis(31_000));
+    let sent = nic.tx(1, eth.send(ip.send_with(|packet: ip_layer::RawPacket<_>| {
+        packet.prepare(init).unwrap().send().unwrap();
+    })));
+    assert_eq!(sent, Ok(1));
+
+    // Nothing happens before the first probe is due.
+    nic.set_current_time(Instant::from_millis(32_000));
+    nic.reset_send();
+    let sent = nic.tx(1, eth.send(ip.send_with(|packet: ip_layer::RawPacket<_>| {
+        packet.prepare(init).unwrap().send().unwrap();
+    })));
+    assert_eq!(sent, Ok(1));
+
+    for probe in 0..3 {
+        nic.set_current_time(Instant::from_millis(36_000 + 1_000*probe));
+        nic.reset_send();
+        let sent = nic.tx(1, eth.send(ip.send_with(|_: ip_layer::RawPacket<_>| {
+            panic!("Probe should take precedence");
+        })));
+        assert_eq!(sent, Ok(1));
+
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        assert_eq!(eth.dst_addr(), MAC_ADDR_OTHER);
+        assert_eq!(eth.src_addr(), MAC_ADDR_HOST);
+        assert_eq!(eth.ethertype(), ethernet::EtherType::Ipv6);
+
+        let packet = ip::v6::packet::new_unchecked_mut(eth.payload_mut_slice());
+        assert_eq!(packet.src_addr(), IP_ADDR_HOST);
+        assert_eq!(packet.dst_addr(), IP_ADDR_OTHER);
+        assert_eq!(packet.hop_limit(), icmpv6::NDISC_HOP_LIMIT);
+        let solicit = icmpv6::packet::new_checked(packet.payload_slice()).unwrap();
+        assert_eq!(solicit.msg_type(), icmpv6::Message::NeighborSolicit);
+        assert_eq!(solicit.target_addr(), IP_ADDR_OTHER);
+    }
+
+    // No probe was answered, the neighbor is gone.
+    nic.set_current_time(Instant::from_millis(40_000));
+    nic.reset_send();
+    let _ = nic.tx(1, eth.send(ip.send_with(|_: ip_layer::RawPacket<_>| ())));
+    nic.reset_send();
+    let sent = nic.tx(1, eth.send(ip.send_with(|packet: ip_layer::RawPacket<_>| {
+        assert!(packet.prepare(init).is_err());
+    })));
+    assert_eq!(sent, Ok(0));
+}
+
+#[test]
+fn announce() {
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+    let mut neighbors = [arp_layer::Neighbor::default(); 1];
+    let mut routes = [ip_layer::Route::unspecified(); 2];
+    let mut ip = ip_layer::Endpoint::new(ip::Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip_layer::Routes::new(&mut routes[..]),
+        arp_layer::NeighborCache::new(Slice::empty()));
+
+    let mut arp = arp_layer::Endpoint::new(arp_layer::NeighborCache::new(&mut neighbors[..]));
+
+    // Repeated announcements before sending result in a single reply.
+    arp.announce(IP_ADDR_HOST);
+    arp.announce(IP_ADDR_HOST);
+
+    let sent = nic.tx(1, eth.send(arp.query(&mut ip)));
+    assert_eq!(sent, Ok(1));
+
+    let buffer = nic.get_mut(0).unwrap();
+    let eth_frame = ethernet::frame::new_unchecked_mut(buffer);
+    assert_eq!(eth_frame.dst_addr(), ethernet::Address::BROADCAST);
+    assert_eq!(eth_frame.src_addr(), MAC_ADDR_HOST);
+    assert_eq!(eth_frame.ethertype(), ethernet::EtherType::Arp);
+
+    let arp_packet = arp::packet::new_unchecked_mut(eth_frame.payload_mut_slice());
+    assert_eq!(arp_packet.operation(), arp::Operation::Reply);
+    assert_eq!(arp_packet.source_hardware_addr(), MAC_ADDR_HOST);
+    assert_eq!(arp_packet.source_protocol_addr(), IP_ADDR_HOST);
+    assert_eq!(arp_packet.target_hardware_addr(), ethernet::Address::BROADCAST);
+    assert_eq!(arp_packet.target_protocol_addr(), IP_ADDR_HOST);
+
+    nic.reset_send();
+    let sent = nic.tx(1, eth.send(arp.query(&mut ip)));
+    assert_eq!(sent, Ok(0));
+
+    // Addresses that are not ours are never announced.
+    arp.announce(IP_ADDR_OTHER);
+    let sent = nic.tx(1, eth.send(arp.query(&mut ip)));
+    assert_eq!(sent, Ok(0));
+}
+
+/// Receive an arp request for the target and return the answer, if any.
+fn proxy_request(target: ip::v4::Address) -> Option<arp::Repr> {
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+    let mut neighbors = [arp_layer::Neighbor::default(); 1];
+    let mut routes = [ip_layer::Route::unspecified(); 2];
+    let mut ip = ip_layer::Endpoint::new(ip::Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip_layer::Routes::new(&mut routes[..]),
+        arp_layer::NeighborCache::new(Slice::empty()));
+
+    let mut networks = [
+        ip::v4::Cidr::new(ip::v4::Address::new(127, 0, 0, 0), 8).subnet(),
+        ip::v4::Cidr::new(ip::v4::Address::new(10, 0, 1, 0), 24).subnet(),
+    ];
+    let mut arp = arp_layer::Endpoint::new(arp_layer::NeighborCache::new(&mut neighbors[..]));
+    arp.set_proxy(arp_layer::ProxyConfig::new(&mut networks[..]));
+
+    {
+        let buffer = nic.get_mut(0).unwrap();
+        buffer.resize(14 + 28, 0u8);
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        ethernet::Repr {
+            src_addr: MAC_ADDR_OTHER,
+            dst_addr: ethernet::Address::BROADCAST,
+            ethertype: ethernet::EtherType::Arp,
+            vlan: None,
+        }.emit(eth);
+        let arp = arp::packet::new_unchecked_mut(eth.payload_mut_slice());
+        arp::Repr::EthernetIpv4 {
+            operation: arp::Operation::Request,
+            source_hardware_addr: MAC_ADDR_OTHER,
+            source_protocol_addr: IP_ADDR_OTHER,
+            target_hardware_addr: ethernet::Address([0; 6]),
+            target_protocol_addr: target,
+        }.emit(arp);
+    }
+
+    nic.receive_all();
+    let recv = nic.rx(1, eth.recv(arp.answer(&mut ip)));
+    assert_eq!(recv, Ok(1));
+
+    let queued = nic.queued();
+    assert!(queued.len() <= 1);
+    let frame = ethernet::frame::new_checked(&queued.first()?[..]).unwrap();
+    let repr = arp::Repr::parse(arp::packet::new_checked(frame.payload_slice()).unwrap()).unwrap();
+    Some(repr)
+}
+
+#[test]
+fn proxy() {
+    const IP_ADDR_PROXIED: ip::v4::Address = ip::v4::Address::new(10, 0, 1, 5);
+    const IP_ADDR_OUTSIDE: ip::v4::Address = ip::v4::Address::new(10, 0, 2, 5);
+
+    assert_eq!(proxy_request(IP_ADDR_PROXIED), Some(arp::Repr::EthernetIpv4 {
+        operation: arp::Operation::Reply,
+        source_hardware_addr: MAC_ADDR_HOST,
+        source_protocol_addr: IP_ADDR_PROXIED,
+        target_hardware_addr: MAC_ADDR_OTHER,
+        target_protocol_addr: IP_ADDR_OTHER,
+    }));
+
+    assert_eq!(proxy_request(IP_ADDR_OUTSIDE), None);
+
+    // Our own address is within a proxied network but answered as usual.
+    assert_eq!(proxy_request(IP_ADDR_HOST), Some(arp::Repr::EthernetIpv4 {
+        operation: arp::Operation::Reply,
+        source_hardware_addr: MAC_ADDR_HOST,
+        source_protocol_addr: IP_ADDR_HOST,
+        target_hardware_addr: MAC_ADDR_OTHER,
+        target_protocol_addr: IP_ADDR_OTHER,
+    }));
+}
+
+#[test]
+fn static_entries() {
+    const IP_ADDR_DYNAMIC: ip::v4::Address = ip::v4::Address::new(127, 0, 0, 3);
+    const MAC_ADDR_DYNAMIC: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 3]);
+
+    let mut neighbors = [arp_layer::Neighbor::default(); 2];
+    let mut arp = arp_layer::Endpoint::new(arp_layer::NeighborCache::new(&mut neighbors[..]));
+    let static_addr = ip::Address::Ipv4(IP_ADDR_OTHER);
+    let dynamic_addr = ip::Address::Ipv4(IP_ADDR_DYNAMIC);
+
+    assert_eq!(arp.add_static(IP_ADDR_OTHER, MAC_ADDR_OTHER), Ok(()));
+    let cache = arp.neighbors_mut();
+    cache.fill(dynamic_addr, MAC_ADDR_DYNAMIC, Some(Instant::from_millis(0))).unwrap();
+
+    // Learned mappings do not override the static one.
+    cache.fill(static_addr, MAC_ADDR_DYNAMIC, Some(Instant::from_millis(0))).unwrap();
+    assert_eq!(cache.lookup_pure(static_addr, Instant::from_millis(0)), Some(MAC_ADDR_OTHER));
+
+    let later = Instant::from_millis(0) + cache.lifetime() * 2;
+    assert_eq!(cache.flush_expired(later), 1);
+    assert_eq!(cache.lookup_pure(dynamic_addr, later), None);
+    assert_eq!(cache.lookup_pure(static_addr, later), Some(MAC_ADDR_OTHER));
+
+    // Static entries are not evicted for dynamic ones.
+    cache.fill(dynamic_addr, MAC_ADDR_DYNAMIC, None).unwrap();
+    assert!(cache.fill(ip::Address::Ipv4(IP_ADDR_HOST), MAC_ADDR_HOST, None).is_ok());
+    assert_eq!(cache.lookup_pure(static_addr, later), Some(MAC_ADDR_OTHER));
+
+    assert_eq!(arp.remove_static(IP_ADDR_OTHER), Ok(()));
+    assert_eq!(arp.neighbors().lookup_pure(static_addr, later), None);
+    assert_eq!(arp.remove_static(IP_ADDR_OTHER), Err(arp_layer::NeighborError::EntryNotFound));
+}
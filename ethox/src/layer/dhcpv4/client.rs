@@ -0,0 +1,538 @@
+use crate::layer::{ip, udp, DropReason, FnHandler, Result};
+use crate::time::{Duration, Instant};
+use crate::wire::{dhcpv4, ethernet, Payload, PayloadMut};
+use crate::wire::ip::v4;
+
+/// The parameters requested from servers.
+const PARAMETERS: [u8; 6] = [
+    dhcpv4::option::SUBNET_MASK,
+    dhcpv4::option::ROUTER,
+    dhcpv4::option::DOMAIN_NAME_SERVER,
+    dhcpv4::option::IP_LEASE_TIME,
+    dhcpv4::option::RENEWAL_TIME_VALUE,
+    dhcpv4::option::REBINDING_TIME_VALUE,
+];
+
+/// The time to wait for the first answer, doubled with each retransmission.
+const TIMEOUT: Duration = Duration::from_secs(4);
+
+/// The longest time to wait before retransmitting while no lease is held.
+const MAX_TIMEOUT: Duration = Duration::from_secs(64);
+
+/// The shortest time to wait before retransmitting while extending a lease.
+const MIN_RENEW_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The number of requests sent for an offer before starting over.
+const REQUEST_RETRIES: u8 = 4;
+
+/// The smallest message accepted by all BOOTP relay agents, see RFC1542.
+const MIN_MESSAGE_LEN: usize = 300;
+
+/// A type that can not be instantiated.
+///
+/// Used as the default type of handlers of the receiver and sender that are created without an
+/// upper layer handler. Its methods can never be invoked.
+pub struct NoHandler { _private: Empty, }
+
+/// An empty enum to prove that there is no instance of `NoHandler`.
+enum Empty { }
+
+/// The state of the client, named as in RFC2131.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum State {
+    /// Broadcasting discovers until some server offers an address.
+    Selecting,
+    /// Requesting the offered address from the server.
+    Requesting,
+    /// A lease is held.
+    Bound,
+    /// Extending the lease with the server that granted it.
+    Renewing,
+    /// Extending the lease with any server, after the original one did not answer.
+    Rebinding,
+}
+
+/// The configuration granted by a server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Lease {
+    /// The leased address and the network of the link.
+    ///
+    /// Without a subnet mask option the address is assumed to be alone in its network.
+    pub address: v4::Cidr,
+    /// The default gateway.
+    pub router: Option<v4::Address>,
+    /// The domain name servers, in order of preference.
+    pub dns: [Option<v4::Address>; 3],
+    /// The server that granted the lease.
+    pub server: v4::Address,
+    /// The time for which the address was granted.
+    pub duration: Duration,
+}
+
+/// A change of the lease reported when polling the client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// An address was leased or the lease was extended.
+    Bound {
+        /// The leased address and the network of the link.
+        address: v4::Cidr,
+        /// The default gateway.
+        router: Option<v4::Address>,
+        /// The domain name servers, in order of preference.
+        dns: [Option<v4::Address>; 3],
+        /// The time for which the address was granted, counted from the reception of the lease.
+        lease: Duration,
+    },
+    /// The client started to extend its lease.
+    ///
+    /// The address remains valid until the lease expires.
+    Renewing,
+    /// The lease has expired or was revoked by the server.
+    ///
+    /// The address must no longer be used. The client starts over to acquire a new lease.
+    Expired,
+}
+
+/// A DHCP client for a single interface.
+///
+/// Use it as a udp receiver and sender, and call [`poll`] regularly with the current time. The
+/// client starts discovering servers immediately.
+///
+/// [`poll`]: #method.poll
+pub struct Client {
+    /// The hardware address of the interface, also used as client identifier.
+    hw_addr: ethernet::Address,
+
+    /// The transaction id of the current exchange.
+    xid: u32,
+
+    state: State,
+
+    /// The server and address of the offer being requested.
+    offer: Option<(v4::Address, v4::Address)>,
+
+    /// The lease, while one is held.
+    lease: Option<Lease>,
+
+    /// When to enter the `Renewing` state.
+    renew_at: Instant,
+
+    /// When to enter the `Rebinding` state.
+    rebind_at: Instant,
+
+    /// When the lease ends.
+    expires_at: Instant,
+
+    /// When to retransmit the current message, `None` if it has not yet been sent.
+    deadline: Option<Instant>,
+
+    /// The number of times the current message was sent.
+    retries: u8,
+
+    /// An event from a received packet, returned with the next poll.
+    event: Option<Event>,
+}
+
+/// A client borrowed for receiving.
+///
+/// Handles replies to the client port and passes all other packets on.
+pub struct Receiver<'c, H=NoHandler> {
+    client: &'c mut Client,
+
+    /// The receiver for all other packets.
+    handler: Option<H>,
+}
+
+/// A client borrowed for sending.
+///
+/// Sends messages and retransmissions when they are due and passes all other buffers on.
+pub struct Sender<'c, H=NoHandler> {
+    client: &'c mut Client,
+
+    /// The sender using all remaining buffers.
+    handler: Option<H>,
+}
+
+impl Client {
+    /// Create a client for the interface with some hardware address.
+    ///
+    /// The transaction ids of the client are derived from the seed, which should be chosen
+    /// randomly so that different clients on the same link are unlikely to use the same ids.
+    pub fn new(hw_addr: ethernet::Address, seed: u32) -> Self {
+        Client {
+            hw_addr,
+            // The derivation of further ids would get stuck at zero.
+            xid: seed.max(1),
+            state: State::Selecting,
+            offer: None,
+            lease: None,
+            renew_at: Instant::from_millis(0),
+            rebind_at: Instant::from_millis(0),
+            expires_at: Instant::from_millis(0),
+            deadline: None,
+            retries: 0,
+            event: None,
+        }
+    }
+
+    /// The current state of the client.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// The lease currently held, if any.
+    ///
+    /// A lease is also held while it is being extended in the `Renewing` and `Rebinding` states.
+    pub fn lease(&self) -> Option<&Lease> {
+        self.lease.as_ref()
+    }
+
+    /// The transaction id of the current exchange.
+    pub fn transaction_id(&self) -> u32 {
+        self.xid
+    }
+
+    /// Advance the timers of the lease and return the next event.
+    ///
+    /// Should be called regularly with the current time, at least whenever packets were received.
+    pub fn poll(&mut self, now: Instant) -> Option<Event> {
+        if let Some(event) = self.event.take() {
+            return Some(event);
+        }
+
+        match self.state {
+            State::Selecting => None,
+            State::Requesting => {
+                // The server did not answer our requests, maybe another client got the address.
+                if self.retries >= REQUEST_RETRIES && self.is_due(now) {
+                    self.restart();
+                }
+                None
+            },
+            State::Bound | State::Renewing | State::Rebinding => {
+                if now >= self.expires_at {
+                    self.restart();
+                    return Some(Event::Expired);
+                }
+
+                let bound = self.state == State::Bound;
+                if now >= self.rebind_at && self.state != State::Rebinding {
+                    self.enter(State::Rebinding);
+                } else if now >= self.renew_at && bound {
+                    self.enter(State::Renewing);
+                }
+
+                if bound && self.state != State::Bound {
+                    Some(Event::Renewing)
+                } else {
+                    None
+                }
+            },
+        }
+    }
+
+    /// Receive packets, handling those of the client internally.
+    pub fn answer(&mut self) -> Receiver<'_> {
+        Receiver { client: self, handler: None, }
+    }
+
+    /// Send messages and retransmissions when they are due.
+    pub fn transmit(&mut self) -> Sender<'_> {
+        Sender { client: self, handler: None, }
+    }
+
+    /// Receive packets, passing all packets not for the client to the handler.
+    pub fn recv<H>(&mut self, handler: H) -> Receiver<'_, H> {
+        Receiver { client: self, handler: Some(handler), }
+    }
+
+    /// Receive packets, passing all packets not for the client to a function.
+    pub fn recv_with<H>(&mut self, handler: H) -> Receiver<'_, FnHandler<H>> {
+        self.recv(FnHandler(handler))
+    }
+
+    /// Send messages when due, passing all other buffers to the handler.
+    pub fn send<H>(&mut self, handler: H) -> Sender<'_, H> {
+        Sender { client: self, handler: Some(handler), }
+    }
+
+    /// Send messages when due, passing all other buffers to a function.
+    pub fn send_with<H>(&mut self, handler: H) -> Sender<'_, FnHandler<H>> {
+        self.send(FnHandler(handler))
+    }
+
+    /// Abandon any lease and discover servers again.
+    fn restart(&mut self) {
+        self.offer = None;
+        self.lease = None;
+        self.enter(State::Selecting);
+    }
+
+    /// Start a new exchange in some state.
+    fn enter(&mut self, state: State) {
+        // A xorshift step, keeping the ids of an exchange unpredictable to other hosts.
+        self.xid ^= self.xid << 13;
+        self.xid ^= self.xid >> 17;
+        self.xid ^= self.xid << 5;
+        self.state = state;
+        self.deadline = None;
+        self.retries = 0;
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        match self.deadline {
+            None => true,
+            Some(deadline) => now >= deadline,
+        }
+    }
+
+    /// Process a reply of a server.
+    ///
+    /// Returns `false` if the reply does not belong to the current exchange.
+    fn reply(&mut self, repr: &dhcpv4::Repr, time: Instant) -> bool {
+        if repr.transaction_id != self.xid || repr.client_hardware_address != self.hw_addr {
+            return false;
+        }
+
+        match (self.state, repr.message_type) {
+            (State::Selecting, dhcpv4::MessageType::Offer) => {
+                let server = match repr.server_identifier {
+                    Some(server) if repr.your_ip.is_unicast() => server,
+                    _ => return false,
+                };
+                self.offer = Some((server, repr.your_ip));
+                // The request of the offer is part of the same exchange and keeps the id.
+                self.state = State::Requesting;
+                self.deadline = None;
+                self.retries = 0;
+            },
+            (State::Requesting, dhcpv4::MessageType::Ack)
+            | (State::Renewing, dhcpv4::MessageType::Ack)
+            | (State::Rebinding, dhcpv4::MessageType::Ack) => self.bind(repr, time),
+            (State::Requesting, dhcpv4::MessageType::Nak) => self.restart(),
+            (State::Renewing, dhcpv4::MessageType::Nak)
+            | (State::Rebinding, dhcpv4::MessageType::Nak) => {
+                self.restart();
+                self.event = Some(Event::Expired);
+            },
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Take the lease of an acknowledgement.
+    fn bind(&mut self, repr: &dhcpv4::Repr, time: Instant) {
+        let address = repr.subnet_mask
+            .and_then(|mask| v4::Cidr::from_netmask(repr.your_ip, mask))
+            .unwrap_or_else(|| v4::Cidr::new(repr.your_ip, 32));
+        let server = repr.server_identifier
+            .or_else(|| self.lease.map(|lease| lease.server))
+            .or_else(|| self.offer.map(|(server, _)| server))
+            .unwrap_or(v4::Address::UNSPECIFIED);
+        // Servers must include the lease time, an infinite lease is the closest interpretation.
+        let lease = repr.lease_duration.unwrap_or(u32::MAX);
+        let renew = repr.renew_duration.unwrap_or(lease / 2);
+        let rebind = repr.rebind_duration
+            .unwrap_or_else(|| (u64::from(lease) * 7 / 8) as u32);
+
+        let lease = Lease {
+            address,
+            router: repr.router,
+            dns: repr.dns_servers.unwrap_or([None; 3]),
+            server,
+            duration: Duration::from_secs(lease.into()),
+        };
+
+        self.renew_at = time + Duration::from_secs(renew.into());
+        self.rebind_at = time + Duration::from_secs(rebind.into());
+        self.expires_at = time + lease.duration;
+        self.lease = Some(lease);
+        self.offer = None;
+        self.state = State::Bound;
+        self.deadline = None;
+        self.retries = 0;
+        self.event = Some(Event::Bound {
+            address: lease.address,
+            router: lease.router,
+            dns: lease.dns,
+            lease: lease.duration,
+        });
+    }
+
+    /// The message to send in the current state, with its source and destination.
+    fn message(&self) -> Option<(dhcpv4::Repr<'static>, v4::Address, v4::Address)> {
+        let mut repr = dhcpv4::Repr {
+            message_type: dhcpv4::MessageType::Request,
+            transaction_id: self.xid,
+            client_hardware_address: self.hw_addr,
+            client_ip: v4::Address::UNSPECIFIED,
+            your_ip: v4::Address::UNSPECIFIED,
+            server_ip: v4::Address::UNSPECIFIED,
+            router: None,
+            subnet_mask: None,
+            relay_agent_ip: v4::Address::UNSPECIFIED,
+            broadcast: false,
+            requested_ip: None,
+            client_identifier: Some(self.hw_addr),
+            server_identifier: None,
+            parameter_request_list: Some(&PARAMETERS),
+            dns_servers: None,
+            lease_duration: None,
+            renew_duration: None,
+            rebind_duration: None,
+        };
+
+        let leased = self.lease.map(|lease| lease.address.address());
+        let (src_addr, dst_addr) = match self.state {
+            State::Selecting => {
+                repr.message_type = dhcpv4::MessageType::Discover;
+                repr.broadcast = true;
+                (v4::Address::UNSPECIFIED, v4::Address::BROADCAST)
+            },
+            State::Requesting => {
+                let (server, offered) = self.offer?;
+                repr.requested_ip = Some(offered);
+                repr.server_identifier = Some(server);
+                repr.broadcast = true;
+                (v4::Address::UNSPECIFIED, v4::Address::BROADCAST)
+            },
+            State::Bound => return None,
+            State::Renewing => {
+                repr.client_ip = leased?;
+                (repr.client_ip, self.lease?.server)
+            },
+            State::Rebinding => {
+                repr.client_ip = leased?;
+                (repr.client_ip, v4::Address::BROADCAST)
+            },
+        };
+
+        Some((repr, src_addr, dst_addr))
+    }
+
+    /// The time to wait for an answer to the message sent at some time.
+    fn timeout(&self, time: Instant) -> Duration {
+        let remaining = match self.state {
+            State::Renewing => self.rebind_at,
+            State::Rebinding => self.expires_at,
+            _ => {
+                let backoff = TIMEOUT * (1u32 << self.retries.min(4));
+                return backoff.min(MAX_TIMEOUT);
+            },
+        };
+
+        // Half of the remaining time, as recommended in RFC2131 section 4.4.5.
+        (remaining.saturating_duration_since(time) / 2).max(MIN_RENEW_TIMEOUT)
+    }
+
+    /// Send the current message if it is due.
+    ///
+    /// Returns the buffer if it was not used.
+    fn send_into<'p, P>(&mut self, raw: udp::RawPacket<'p, P>) -> Option<udp::RawPacket<'p, P>>
+        where P: Payload + PayloadMut,
+    {
+        let time = raw.info().timestamp();
+        if !self.is_due(time) {
+            return Some(raw);
+        }
+
+        let (repr, src_addr, dst_addr) = match self.message() {
+            Some(message) => message,
+            None => return Some(raw),
+        };
+
+        // Otherwise try again with the next buffer.
+        if emit(raw, repr, src_addr, dst_addr).is_ok() {
+            self.deadline = Some(time + self.timeout(time));
+            self.retries = self.retries.saturating_add(1);
+        }
+
+        None
+    }
+}
+
+/// Fill a buffer with a message and send it.
+fn emit<P>(raw: udp::RawPacket<P>, repr: dhcpv4::Repr, src_addr: v4::Address, dst_addr: v4::Address)
+    -> Result<()>
+    where P: Payload + PayloadMut,
+{
+    let mut packet = raw.prepare(udp::Init {
+        source: ip::Source::Exact(src_addr.into()),
+        src_port: dhcpv4::CLIENT_PORT,
+        dst_addr: dst_addr.into(),
+        dst_port: dhcpv4::SERVER_PORT,
+        payload: repr.buffer_len().max(MIN_MESSAGE_LEN),
+        fragmentation: ip::Fragmentation::default(),
+        hop_limit: None,
+    })?;
+
+    let payload = packet.packet.payload_mut_slice();
+    // The emitted message is padded with zeros.
+    for byte in payload.iter_mut() {
+        *byte = 0;
+    }
+    repr.emit(dhcpv4::packet::new_unchecked_mut(payload));
+    packet.send()
+}
+
+impl<P, H> udp::Recv<P> for Receiver<'_, H>
+where
+    P: PayloadMut,
+    H: udp::Recv<P>,
+{
+    fn receive(&mut self, packet: udp::Packet<P>) {
+        let udp_repr = packet.packet.repr();
+        if udp_repr.dst_port != dhcpv4::CLIENT_PORT || udp_repr.src_port != dhcpv4::SERVER_PORT {
+            return match self.handler.as_mut() {
+                Some(handler) => handler.receive(packet),
+                None => {
+                    let udp::Packet { mut control, packet } = packet;
+                    control.inner.report_drop(DropReason::Unbound, packet.as_bytes())
+                },
+            };
+        }
+
+        let time = packet.info().timestamp();
+        let client = &mut *self.client;
+        let handled = dhcpv4::packet::new_checked(packet.packet.payload_slice())
+            .and_then(dhcpv4::Repr::parse)
+            .map(|repr| client.reply(&repr, time));
+
+        let reason = match handled {
+            Ok(true) => return,
+            // A reply to another client or out of order.
+            Ok(false) => DropReason::Filtered,
+            Err(err) => err.into(),
+        };
+
+        let udp::Packet { mut control, packet } = packet;
+        control.inner.report_drop(reason, packet.as_bytes())
+    }
+}
+
+impl<P, H> udp::Send<P> for Sender<'_, H>
+where
+    P: Payload + PayloadMut,
+    H: udp::Send<P>,
+{
+    fn send(&mut self, packet: udp::RawPacket<P>) {
+        let unused = self.client.send_into(packet);
+
+        if let (Some(packet), Some(handler)) = (unused, self.handler.as_mut()) {
+            handler.send(packet)
+        }
+    }
+}
+
+impl<P: Payload> udp::Recv<P> for NoHandler {
+    fn receive(&mut self, _: udp::Packet<P>) {
+        match self._private { }
+    }
+}
+
+impl<P: Payload> udp::Send<P> for NoHandler {
+    fn send(&mut self, _: udp::RawPacket<P>) {
+        match self._private { }
+    }
+}
@@ -0,0 +1,38 @@
+//! The Dynamic Host Configuration Protocol for IPv4.
+//!
+//! A client for [RFC2131] acquiring and maintaining the lease of a single address over the udp
+//! layer. The client does not configure the ip endpoint itself, it reports the lease through
+//! events and leaves it to the user to assign the address, routes and name servers.
+//!
+//! ## Structure
+//!
+//! The client is used as a udp receiver and sender, wrapping another handler of that layer to
+//! which all packets not belonging to it are passed. Additionally, it must be polled regularly to
+//! drive the timers of the lease and retrieve the resulting events:
+//!
+//! * [`Event::Bound`] when an address was leased, or the lease was extended.
+//! * [`Event::Renewing`] when the client starts to extend its lease.
+//! * [`Event::Expired`] when the lease ended and the address must no longer be used.
+//!
+//! Before a lease is held, all messages are broadcast from the unspecified address. Replies are
+//! matched by their transaction id and the hardware address of the client. The ethernet endpoint
+//! must accept broadcasts, which it does by default, since the client asks servers to broadcast
+//! their replies.
+//!
+//! [RFC2131]: https://tools.ietf.org/html/rfc2131
+//! [`Event::Bound`]: enum.Event.html#variant.Bound
+//! [`Event::Renewing`]: enum.Event.html#variant.Renewing
+//! [`Event::Expired`]: enum.Event.html#variant.Expired
+mod client;
+#[cfg(test)]
+mod tests;
+
+pub use client::{
+    Client,
+    Event,
+    Lease,
+    NoHandler,
+    Receiver,
+    Sender,
+    State,
+};
@@ -0,0 +1,244 @@
+use super::*;
+use crate::managed::Slice;
+use crate::nic::{external::External, Device};
+use crate::layer::{arp, eth, ip, udp};
+use crate::time::{Duration, Instant};
+use crate::wire::{dhcpv4, ethernet, Checksum};
+use crate::wire::ip::{v4, Protocol};
+
+const MAC_ADDR_CLIENT: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+const MAC_ADDR_SERVER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+const IP_ADDR_SERVER: v4::Address = v4::Address::new(192, 168, 1, 1);
+const IP_ADDR_OFFERED: v4::Address = v4::Address::new(192, 168, 1, 20);
+const IP_ADDR_DNS: v4::Address = v4::Address::new(192, 168, 1, 2);
+
+/// A host without any address, running the client.
+struct Host {
+    eth: eth::Endpoint<'static>,
+    ip: ip::Endpoint<'static>,
+    udp: udp::Endpoint<'static>,
+    dhcp: Client,
+}
+
+impl Host {
+    fn new() -> Self {
+        Host {
+            eth: eth::Endpoint::new(MAC_ADDR_CLIENT),
+            ip: ip::Endpoint::new(Slice::empty(),
+                ip::Routes::new(Slice::empty()),
+                arp::NeighborCache::new(Slice::empty())),
+            udp: udp::Endpoint::new_unfiltered(),
+            dhcp: Client::new(MAC_ADDR_CLIENT, 0x3d1d),
+        }
+    }
+
+    /// Poll the sender at some time, returning the frame if one was sent.
+    fn send(&mut self, time: Instant) -> Option<Vec<u8>> {
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        nic.set_current_time(time);
+        let sent = nic.tx(1, self.eth.send(self.ip.send(self.udp.send(self.dhcp.transmit()))));
+        match sent.unwrap() {
+            0 => None,
+            _ => Some(nic.get(0).unwrap().clone()),
+        }
+    }
+
+    /// Configure the address of a lease, as the application does when the client is bound.
+    fn configure(&mut self, address: v4::Cidr) {
+        self.ip = ip::Endpoint::new(Slice::One(ip::Assignment::new(address.into())),
+            ip::Routes::new(Slice::empty()),
+            arp::NeighborCache::new(Slice::empty()));
+    }
+
+    /// Deliver a reply of the server at some time.
+    fn deliver(&mut self, repr: dhcpv4::Repr, time: Instant) {
+        let mut nic = External::new_recv(Slice::One(reply(repr)));
+        nic.set_current_time(time);
+        let recv = nic.rx(1, self.eth.recv(self.ip.recv(self.udp.recv(self.dhcp.answer()))));
+        assert_eq!(recv, Ok(1));
+    }
+}
+
+/// Check the headers of a message sent by the client and parse it.
+fn message(frame: &[u8]) -> (v4::Repr, dhcpv4::Repr<'_>) {
+    let eth = ethernet::frame::new_checked(frame).unwrap();
+    assert_eq!(eth.src_addr(), MAC_ADDR_CLIENT);
+    let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+    let ip_repr = v4::Repr::parse(ip, Checksum::Manual).unwrap();
+    assert_eq!(ip_repr.protocol, Protocol::Udp);
+    if ip_repr.dst_addr.is_broadcast() {
+        assert_eq!(eth.dst_addr(), ethernet::Address::BROADCAST);
+    }
+    let udp = crate::wire::udp::packet::new_checked(ip.payload_slice()).unwrap();
+    assert_eq!(udp.src_port(), dhcpv4::CLIENT_PORT);
+    assert_eq!(udp.dst_port(), dhcpv4::SERVER_PORT);
+    let dhcp = dhcpv4::packet::new_checked(udp.payload_slice()).unwrap();
+    (ip_repr, dhcpv4::Repr::parse(dhcp).unwrap())
+}
+
+/// Build a broadcast frame from the server containing a reply.
+fn reply(repr: dhcpv4::Repr) -> Vec<u8> {
+    let udp_repr = crate::wire::udp::Repr {
+        src_port: dhcpv4::SERVER_PORT,
+        dst_port: dhcpv4::CLIENT_PORT,
+        length: (8 + repr.buffer_len()) as u16,
+    };
+    let ip_repr = v4::Repr {
+        src_addr: IP_ADDR_SERVER,
+        dst_addr: v4::Address::BROADCAST,
+        protocol: Protocol::Udp,
+        payload_len: udp_repr.buffer_len(),
+        hop_limit: 64,
+    };
+    let eth_repr = ethernet::Repr {
+        src_addr: MAC_ADDR_SERVER,
+        dst_addr: ethernet::Address::BROADCAST,
+        ethertype: ethernet::EtherType::Ipv4,
+        vlan: None,
+    };
+
+    let mut buffer = vec![0; eth_repr.buffer_len(ip_repr.buffer_len() + ip_repr.payload_len)];
+    let eth = ethernet::frame::new_unchecked_mut(&mut buffer);
+    eth_repr.emit(eth);
+    let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+    ip_repr.emit(ip, Checksum::Manual);
+    let udp = crate::wire::udp::packet::new_unchecked_mut(ip.payload_mut_slice());
+    udp_repr.emit(udp, crate::wire::udp::Checksum::Ignored);
+    repr.emit(dhcpv4::packet::new_unchecked_mut(udp.payload_mut_slice()));
+    udp.fill_checksum(IP_ADDR_SERVER.into(), v4::Address::BROADCAST.into());
+    buffer
+}
+
+/// A reply of the server to a message of the client.
+fn answer(request: &dhcpv4::Repr, message_type: dhcpv4::MessageType) -> dhcpv4::Repr<'static> {
+    dhcpv4::Repr {
+        message_type,
+        transaction_id: request.transaction_id,
+        client_hardware_address: request.client_hardware_address,
+        client_ip: v4::Address::UNSPECIFIED,
+        your_ip: IP_ADDR_OFFERED,
+        server_ip: v4::Address::UNSPECIFIED,
+        router: Some(IP_ADDR_SERVER),
+        subnet_mask: Some(v4::Address::new(255, 255, 255, 0)),
+        relay_agent_ip: v4::Address::UNSPECIFIED,
+        broadcast: true,
+        requested_ip: None,
+        client_identifier: None,
+        server_identifier: Some(IP_ADDR_SERVER),
+        parameter_request_list: None,
+        dns_servers: Some([Some(IP_ADDR_DNS), None, None]),
+        lease_duration: Some(3600),
+        renew_duration: None,
+        rebind_duration: None,
+    }
+}
+
+#[test]
+fn acquire_lease() {
+    let mut host = Host::new();
+    let start = Instant::from_secs(0);
+    assert_eq!(host.dhcp.state(), State::Selecting);
+
+    let frame = host.send(start).expect("Discovers servers");
+    let (ip, discover) = message(&frame);
+    assert_eq!(ip.src_addr, v4::Address::UNSPECIFIED);
+    assert_eq!(ip.dst_addr, v4::Address::BROADCAST);
+    assert_eq!(discover.message_type, dhcpv4::MessageType::Discover);
+    assert_eq!(discover.client_hardware_address, MAC_ADDR_CLIENT);
+    assert!(discover.broadcast);
+    // Nothing is retransmitted before the timeout.
+    assert!(host.send(start).is_none());
+
+    // An offer of another exchange is ignored.
+    let mut other = answer(&discover, dhcpv4::MessageType::Offer);
+    other.transaction_id = !discover.transaction_id;
+    host.deliver(other, start);
+    assert_eq!(host.dhcp.state(), State::Selecting);
+
+    host.deliver(answer(&discover, dhcpv4::MessageType::Offer), start);
+    assert_eq!(host.dhcp.state(), State::Requesting);
+    assert_eq!(host.dhcp.poll(start), None);
+
+    let frame = host.send(start).expect("Requests the offer");
+    let (ip, request) = message(&frame);
+    assert_eq!(ip.dst_addr, v4::Address::BROADCAST);
+    assert_eq!(request.message_type, dhcpv4::MessageType::Request);
+    assert_eq!(request.transaction_id, discover.transaction_id);
+    assert_eq!(request.requested_ip, Some(IP_ADDR_OFFERED));
+    assert_eq!(request.server_identifier, Some(IP_ADDR_SERVER));
+
+    host.deliver(answer(&request, dhcpv4::MessageType::Ack), start);
+    assert_eq!(host.dhcp.state(), State::Bound);
+    assert_eq!(host.dhcp.poll(start), Some(Event::Bound {
+        address: v4::Cidr::new(IP_ADDR_OFFERED, 24),
+        router: Some(IP_ADDR_SERVER),
+        dns: [Some(IP_ADDR_DNS), None, None],
+        lease: Duration::from_secs(3600),
+    }));
+    assert_eq!(host.dhcp.poll(start), None);
+    assert!(host.send(start).is_none());
+
+    let lease = host.dhcp.lease().expect("Holds a lease");
+    assert_eq!(lease.address.address(), IP_ADDR_OFFERED);
+    assert_eq!(lease.server, IP_ADDR_SERVER);
+}
+
+#[test]
+fn lease_timers() {
+    let mut host = Host::new();
+    let start = Instant::from_secs(0);
+
+    let frame = host.send(start).unwrap();
+    let (_, discover) = message(&frame);
+    host.deliver(answer(&discover, dhcpv4::MessageType::Offer), start);
+    let frame = host.send(start).unwrap();
+    let (_, request) = message(&frame);
+    host.deliver(answer(&request, dhcpv4::MessageType::Ack), start);
+    assert!(matches!(host.dhcp.poll(start), Some(Event::Bound { .. })));
+    host.configure(host.dhcp.lease().unwrap().address);
+
+    // Renewing starts at half the lease time.
+    let renew = start + Duration::from_secs(1800);
+    assert_eq!(host.dhcp.poll(renew - Duration::from_secs(1)), None);
+    assert_eq!(host.dhcp.poll(renew), Some(Event::Renewing));
+    assert_eq!(host.dhcp.state(), State::Renewing);
+
+    // Rebinding at seven eighths of the lease time broadcasts a request.
+    let rebind = start + Duration::from_secs(3150);
+    assert_eq!(host.dhcp.poll(rebind), None);
+    assert_eq!(host.dhcp.state(), State::Rebinding);
+    let frame = host.send(rebind).expect("Broadcasts a request");
+    let (ip, rebinding) = message(&frame);
+    assert_eq!(ip.dst_addr, v4::Address::BROADCAST);
+    assert_eq!(rebinding.message_type, dhcpv4::MessageType::Request);
+    assert_eq!(rebinding.client_ip, IP_ADDR_OFFERED);
+    assert_eq!(rebinding.requested_ip, None);
+
+    let expiry = start + Duration::from_secs(3600);
+    assert_eq!(host.dhcp.poll(expiry), Some(Event::Expired));
+    assert_eq!(host.dhcp.state(), State::Selecting);
+    assert!(host.dhcp.lease().is_none());
+}
+
+#[test]
+fn nak_revokes_lease() {
+    let mut host = Host::new();
+    let start = Instant::from_secs(0);
+
+    let frame = host.send(start).unwrap();
+    let (_, discover) = message(&frame);
+    host.deliver(answer(&discover, dhcpv4::MessageType::Offer), start);
+    let frame = host.send(start).unwrap();
+    let (_, request) = message(&frame);
+    host.deliver(answer(&request, dhcpv4::MessageType::Ack), start);
+    assert!(matches!(host.dhcp.poll(start), Some(Event::Bound { .. })));
+
+    let renew = start + Duration::from_secs(1800);
+    assert_eq!(host.dhcp.poll(renew), Some(Event::Renewing));
+    let xid = host.dhcp.transaction_id();
+    let mut nak = answer(&discover, dhcpv4::MessageType::Nak);
+    nak.transaction_id = xid;
+    host.deliver(nak, renew);
+    assert_eq!(host.dhcp.poll(renew), Some(Event::Expired));
+    assert_eq!(host.dhcp.state(), State::Selecting);
+}
@@ -1,6 +1,6 @@
-use core::marker::PhantomData;
-
-use crate::layer::FnHandler;
+use crate::layer::{CastPolicy, DropReason, Error, FnHandler, Inspect, Result, StatefulHandler, Tee};
+use crate::layer::trace::{Event, Trace};
+use crate::managed::{List, Slice};
 use crate::wire::{ethernet, Payload, PayloadMut};
 use crate::nic;
 
@@ -11,24 +11,46 @@ use super::packet::{self, Controller};
 ///
 /// This structure contains the ethernet address considered to identify the device on the local
 /// ethernet network. Then all parts of receiving and sending, except physical layer framing, can
-/// be implemented in software.
+/// be implemented in software. Additional addresses can be assigned with [`add_address`] after
+/// providing storage for them. Frames to any of them are received but the primary address given
+/// on construction remains the default source of outgoing frames.
 ///
 /// Note that the ethernet wire layer does **not yet** support giant frames but if it did these
 /// would need to be explicitely enabled here.
 ///
-/// Otherwise, the endpoint holds no configuration state and options. To preserve future
-/// compatibility it nevertheless has a lifetime parameter like other layer's endpoints. (ARP and
-/// ICMP do not use the same reservation since they are less likely to break upper layer code by
-/// having basically no upper layer). This allows introducing new state, as long as there is a
-/// default value with static lifetime—such as is the case for slices.
+/// Apart from the address, the endpoint holds the [`CastPolicy`] of the interface which decides
+/// about accepting broadcasts in this and in the upper layers, and the multicast groups that were
+/// joined on the link. The storage for the groups is empty by default and provided with
+/// [`set_multicast_storage`]. It also holds an optional hook
+/// notified of all packets that any layer drops silently, and an optional tracer notified of the
+/// decisions of all layers. Both are borrowed for the lifetime parameter.
+///
+/// [`CastPolicy`]: ../struct.CastPolicy.html
+/// [`set_multicast_storage`]: #method.set_multicast_storage
+/// [`add_address`]: #method.add_address
 pub struct Endpoint<'a> {
-    /// Our own address.
+    /// Our own primary address.
     ///
     /// We ignored any packets with mismatching destination.
     addr: ethernet::Address,
 
-    /// TODO: figure out if we need any dynamically sized, non-owned data.
-    data: PhantomData<&'a ()>,
+    /// Further unicast addresses at which frames are received.
+    secondary: List<'a, ethernet::Address>,
+
+    /// Acceptance of non-unicast traffic.
+    cast: CastPolicy,
+
+    /// Joined multicast groups, accepted regardless of the policy.
+    multicast: List<'a, ethernet::Address>,
+
+    /// Accept all frames regardless of their destination.
+    promiscuous: bool,
+
+    /// Called with every packet dropped by this or the upper layers.
+    drop_hook: Option<&'a mut dyn FnMut(DropReason, &[u8])>,
+
+    /// Notified of events in this or the upper layers.
+    tracer: Option<&'a mut dyn Trace>,
 }
 
 /// An endpoint borrowed for receiving.
@@ -63,10 +85,160 @@ impl<'a> Endpoint<'a> {
     pub fn new(addr: ethernet::Address) -> Self {
         Endpoint {
             addr,
-            data: PhantomData,
+            secondary: List::new(Slice::empty()),
+            cast: CastPolicy::default(),
+            multicast: List::new(Slice::empty()),
+            promiscuous: false,
+            drop_hook: None,
+            tracer: None,
+        }
+    }
+
+    /// Get the primary address, used as the default source address of frames.
+    pub fn addr(&self) -> ethernet::Address {
+        self.addr
+    }
+
+    /// Provide the storage for additional addresses.
+    ///
+    /// Previously added addresses are forgotten. The primary address does not occupy the storage.
+    pub fn set_address_storage<T>(&mut self, storage: T)
+        where T: Into<Slice<'a, ethernet::Address>>
+    {
+        self.secondary = List::new(storage.into());
+    }
+
+    /// Receive frames sent to an additional unicast address.
+    ///
+    /// Adding an address that is already assigned has no effect. Fails with `Error::Illegal` if
+    /// the address is not unicast and with `Error::Exhausted` if the storage is full.
+    pub fn add_address(&mut self, addr: ethernet::Address) -> Result<()> {
+        if !addr.is_unicast() {
+            return Err(Error::Illegal);
+        }
+
+        if self.has_address(addr) {
+            return Ok(());
+        }
+
+        let place = self.secondary.push().ok_or(Error::Exhausted)?;
+        *place = addr;
+        Ok(())
+    }
+
+    /// Stop receiving frames sent to an additional address.
+    ///
+    /// Returns whether the address had been added. The primary address can not be removed.
+    pub fn remove_address(&mut self, addr: ethernet::Address) -> bool {
+        match self.secondary.iter().position(|own| *own == addr) {
+            Some(pos) => self.secondary.remove_at(pos).is_some(),
+            None => false,
         }
     }
 
+    /// Query whether an address is assigned to the endpoint.
+    pub fn has_address(&self, addr: ethernet::Address) -> bool {
+        self.addr == addr || self.secondary.contains(&addr)
+    }
+
+    /// Get the policy for accepting broadcast traffic.
+    pub fn cast_policy(&self) -> CastPolicy {
+        self.cast
+    }
+
+    /// Change the policy for accepting broadcast traffic.
+    ///
+    /// The policy applies to all layers that receive packets from this endpoint.
+    pub fn set_cast_policy(&mut self, policy: CastPolicy) {
+        self.cast = policy;
+    }
+
+    /// Query whether frames to any destination are received.
+    pub fn promiscuous(&self) -> bool {
+        self.promiscuous
+    }
+
+    /// Receive all frames, regardless of their destination address.
+    ///
+    /// The frames passed to the handler still carry their original destination so that it can
+    /// tell apart those that were actually addressed to the endpoint.
+    pub fn set_promiscuous(&mut self, promiscuous: bool) {
+        self.promiscuous = promiscuous;
+    }
+
+    /// Provide the storage for joined multicast groups.
+    ///
+    /// Previously joined groups are forgotten. The number of groups that can be joined at the
+    /// same time is limited by the length of the storage.
+    pub fn set_multicast_storage<T>(&mut self, storage: T)
+        where T: Into<Slice<'a, ethernet::Address>>
+    {
+        self.multicast = List::new(storage.into());
+    }
+
+    /// Receive frames sent to a multicast group.
+    ///
+    /// Joining a group twice has no effect. Fails with `Error::Illegal` if the address is not a
+    /// multicast address and with `Error::Exhausted` if the storage for groups is full.
+    pub fn join_multicast(&mut self, group: ethernet::Address) -> Result<()> {
+        if !group.is_multicast() || group.is_broadcast() {
+            return Err(Error::Illegal);
+        }
+
+        if self.is_joined(group) {
+            return Ok(());
+        }
+
+        let place = self.multicast.push().ok_or(Error::Exhausted)?;
+        *place = group;
+        Ok(())
+    }
+
+    /// Stop receiving frames sent to a multicast group.
+    ///
+    /// Returns whether the group had been joined. Frames to the group are still accepted when the
+    /// cast policy accepts all link layer multicast.
+    pub fn leave_multicast(&mut self, group: ethernet::Address) -> bool {
+        match self.multicast.iter().position(|joined| *joined == group) {
+            Some(pos) => self.multicast.remove_at(pos).is_some(),
+            None => false,
+        }
+    }
+
+    /// Query whether a multicast group has been joined.
+    pub fn is_joined(&self, group: ethernet::Address) -> bool {
+        self.multicast.contains(&group)
+    }
+
+    /// Register a function to be notified of dropped packets.
+    ///
+    /// All layers receiving through this endpoint report packets that they drop without passing
+    /// them to an upper layer handler, along with the bytes of the packet as seen by the dropping
+    /// layer, starting at its own header. The bytes are empty if they were no longer available.
+    /// Replaces any previously registered hook.
+    pub fn set_drop_hook(&mut self, hook: &'a mut dyn FnMut(DropReason, &[u8])) {
+        self.drop_hook = Some(hook);
+    }
+
+    /// Remove the hook for dropped packets.
+    pub fn clear_drop_hook(&mut self) {
+        self.drop_hook = None;
+    }
+
+    /// Register a tracer for the events of this and the upper layers.
+    ///
+    /// See the [`trace`] module for the events. Replaces any previously registered tracer.
+    ///
+    /// [`trace`]: ../trace/index.html
+    pub fn set_tracer(&mut self, tracer: &'a mut dyn Trace) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Remove the tracer.
+    pub fn clear_tracer(&mut self) {
+        self.tracer = None;
+    }
+
     /// Receive frames using this mutably borrowed endpoint.
     pub fn recv<H>(&mut self, handler: H) -> Receiver<'_, 'a, H> {
         Receiver { endpoint: self.eth(), handler, }
@@ -94,8 +266,32 @@ impl<'a> Endpoint<'a> {
     }
 
     fn accepts(&self, dst_addr: ethernet::Address) -> bool {
-        // TODO: broadcast and multicast
-        self.addr == dst_addr || dst_addr.is_broadcast()
+        if self.promiscuous {
+            return true;
+        }
+
+        if dst_addr.is_broadcast() {
+            return self.cast.link_broadcast;
+        }
+
+        if dst_addr.is_multicast() {
+            return self.cast.link_multicast || self.is_joined(dst_addr);
+        }
+
+        self.has_address(dst_addr)
+    }
+
+    fn dropped(&mut self, reason: DropReason, bytes: &[u8]) {
+        self.trace(Event::Dropped { reason });
+        if let Some(hook) = self.drop_hook.as_mut() {
+            hook(reason, bytes)
+        }
+    }
+
+    fn trace(&mut self, event: Event) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.event(event)
+        }
     }
 }
 
@@ -103,6 +299,18 @@ impl packet::Endpoint for EthEndpoint<'_, '_> {
     fn src_addr(&mut self) -> ethernet::Address {
         self.inner.addr
     }
+
+    fn cast_policy(&self) -> CastPolicy {
+        self.inner.cast
+    }
+
+    fn dropped(&mut self, reason: DropReason, bytes: &[u8]) {
+        self.inner.dropped(reason, bytes)
+    }
+
+    fn trace(&mut self, event: Event) {
+        self.inner.trace(event)
+    }
 }
 
 impl<H, P, T> nic::Recv<H, P> for Receiver<'_, '_, T>
@@ -112,16 +320,24 @@ where
     T: Recv<P>,
 {
     fn receive(&mut self, packet: nic::Packet<H, P>) {
-        let frame = match ethernet::Frame::new_checked(packet.payload) {
-            Ok(frame) => frame,
-            Err(_) => return,
+        let repr = match ethernet::Frame::new_checked(&*packet.payload) {
+            Ok(frame) => frame.repr(),
+            Err(err) => {
+                let bytes = packet.payload.payload().as_slice();
+                return self.endpoint.inner.dropped(err.into(), bytes);
+            },
         };
 
-        let repr = frame.repr();
+        let frame = ethernet::Frame::new_unchecked(packet.payload, repr);
         if !self.endpoint.inner.accepts(repr.dst_addr) {
-            return
+            return self.endpoint.inner.dropped(DropReason::Filtered, frame.as_ref());
         }
 
+        self.endpoint.inner.trace(Event::Received {
+            ethertype: repr.ethertype,
+            len: frame.as_ref().len(),
+        });
+
         let control = Controller {
             nic_handle: packet.handle,
             endpoint: &mut self.endpoint,
@@ -165,6 +381,33 @@ impl<P: Payload, F> Send<P> for FnHandler<F>
     }
 }
 
+impl<P: Payload, S, F> Recv<P> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, packet::In<P>)
+{
+    fn receive(&mut self, frame: packet::In<P>) {
+        (self.handler)(&mut self.state, frame)
+    }
+}
+
+impl<P: Payload, S, F> Send<P> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, packet::Raw<P>)
+{
+    fn send(&mut self, frame: packet::Raw<P>) {
+        (self.handler)(&mut self.state, frame)
+    }
+}
+
+impl<P, A, B> Recv<P> for Tee<A, B>
+where
+    P: Payload,
+    A: for<'a> Inspect<packet::In<'a, P>>,
+    B: for<'a> Inspect<packet::In<'a, P>>,
+{
+    fn receive(&mut self, frame: packet::In<P>) {
+        self.inspect(&frame)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -189,6 +432,7 @@ mod tests {
             src_addr,
             dst_addr: MAC_ADDR_1,
             ethertype: ethernet::EtherType::Unknown(0xBEEF),
+            vlan: None,
             payload: PAYLOAD_BYTES.len(),
         };
         let mut prepared = frame.prepare(init)
@@ -205,6 +449,41 @@ mod tests {
         assert_eq!(frame.frame.payload().as_slice(), &PAYLOAD_BYTES[..]);
     }
 
+    #[test]
+    fn src_addr_override() {
+        const MAC_ADDR_VIRTUAL: ethernet::Address = ethernet::Address([0, 0, 0x5e, 0, 1, 1]);
+
+        let mut endpoint = Endpoint::new(MAC_ADDR_1);
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+
+        let sent = nic.tx(1, endpoint.send_with(|frame: packet::Raw<_>| {
+            let init = Init {
+                src_addr: MAC_ADDR_VIRTUAL,
+                dst_addr: ethernet::Address::BROADCAST,
+                ethertype: ethernet::EtherType::Unknown(0xBEEF),
+                vlan: None,
+                payload: PAYLOAD_BYTES.len(),
+            };
+            let mut prepared = frame.prepare(init).unwrap();
+            prepared
+                .payload_mut_slice()
+                .copy_from_slice(&PAYLOAD_BYTES[..]);
+            prepared.send().unwrap();
+        }));
+        assert_eq!(sent, Ok(1));
+
+        let buffer = nic.get(0).unwrap();
+        let frame = ethernet::frame::new_checked(&buffer[..]).unwrap();
+        assert_eq!(frame.src_addr(), MAC_ADDR_VIRTUAL);
+        assert_eq!(frame.dst_addr(), ethernet::Address::BROADCAST);
+
+        nic.set_one_past_receive(1);
+        let recv = nic.rx(1, endpoint.recv_with(|frame: packet::In<_>| {
+            assert_eq!(frame.frame.repr().src_addr, MAC_ADDR_VIRTUAL);
+        }));
+        assert_eq!(recv, Ok(1));
+    }
+
     #[test]
     fn simple() {
         let mut endpoint = Endpoint::new(MAC_ADDR_1);
@@ -223,4 +502,196 @@ mod tests {
                 .recv_with(simple_recv));
         assert_eq!(recv, Ok(1));
     }
+
+    #[test]
+    fn drop_hook() {
+        use core::cell::Cell;
+        const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+
+        let dropped = Cell::new(None);
+        let mut hook = |reason, bytes: &[u8]| dropped.set(Some((reason, bytes.len())));
+        let mut endpoint = Endpoint::new(MAC_ADDR_1);
+        endpoint.set_drop_hook(&mut hook);
+
+        // Too short to contain an ethernet header.
+        let mut nic = External::new_recv(Slice::One(vec![0; 4]));
+        let recv = nic.rx(1, endpoint.recv_with(simple_recv));
+        assert_eq!(recv, Ok(1));
+        assert_eq!(dropped.take(), Some((DropReason::Malformed, 4)));
+
+        // Addressed to some other host.
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let sent = nic.tx(1, endpoint.send_with(simple_send));
+        assert_eq!(sent, Ok(1));
+        ethernet::frame::new_unchecked_mut(nic.get_mut(0).unwrap())
+            .set_dst_addr(MAC_ADDR_OTHER);
+
+        nic.set_one_past_receive(1);
+        let recv = nic.rx(1, endpoint.recv_with(simple_recv));
+        assert_eq!(recv, Ok(1));
+        assert!(matches!(dropped.take(), Some((DropReason::Filtered, _))));
+
+        // Nothing is reported for delivered frames.
+        ethernet::frame::new_unchecked_mut(nic.get_mut(0).unwrap())
+            .set_dst_addr(MAC_ADDR_1);
+        nic.reset_receive();
+        let recv = nic.rx(1, endpoint.recv_with(simple_recv));
+        assert_eq!(recv, Ok(1));
+        assert_eq!(dropped.take(), None);
+    }
+
+    #[test]
+    fn multicast() {
+        use core::cell::Cell;
+        const MDNS_GROUP: ethernet::Address = ethernet::Address([0x01, 0x00, 0x5e, 0, 0, 0xfb]);
+        const OTHER_GROUP: ethernet::Address = ethernet::Address([0x01, 0x00, 0x5e, 0, 0, 0x01]);
+
+        let dropped = Cell::new(None);
+        let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+        let mut groups = [ethernet::Address::default(); 1];
+        let mut endpoint = Endpoint::new(MAC_ADDR_1);
+        endpoint.set_drop_hook(&mut hook);
+
+        // No storage for groups yet.
+        assert_eq!(endpoint.join_multicast(MDNS_GROUP), Err(Error::Exhausted));
+        endpoint.set_multicast_storage(&mut groups[..]);
+        assert_eq!(endpoint.join_multicast(MAC_ADDR_1), Err(Error::Illegal));
+        assert_eq!(endpoint.join_multicast(MDNS_GROUP), Ok(()));
+        assert_eq!(endpoint.join_multicast(MDNS_GROUP), Ok(()));
+        assert_eq!(endpoint.join_multicast(OTHER_GROUP), Err(Error::Exhausted));
+        assert!(endpoint.is_joined(MDNS_GROUP));
+
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let sent = nic.tx(1, endpoint.send_with(simple_send));
+        assert_eq!(sent, Ok(1));
+
+        // Frames to the joined group are accepted.
+        ethernet::frame::new_unchecked_mut(nic.get_mut(0).unwrap())
+            .set_dst_addr(MDNS_GROUP);
+        nic.set_one_past_receive(1);
+        let recv = nic.rx(1, endpoint.recv_with(simple_recv));
+        assert_eq!(recv, Ok(1));
+        assert_eq!(dropped.take(), None);
+
+        // Frames to a different group are not.
+        ethernet::frame::new_unchecked_mut(nic.get_mut(0).unwrap())
+            .set_dst_addr(OTHER_GROUP);
+        nic.reset_receive();
+        let recv = nic.rx(1, endpoint.recv_with(simple_recv));
+        assert_eq!(recv, Ok(1));
+        assert_eq!(dropped.take(), Some(DropReason::Filtered));
+
+        // Nor are those to a group that was left.
+        assert!(endpoint.leave_multicast(MDNS_GROUP));
+        assert!(!endpoint.leave_multicast(MDNS_GROUP));
+        ethernet::frame::new_unchecked_mut(nic.get_mut(0).unwrap())
+            .set_dst_addr(MDNS_GROUP);
+        nic.reset_receive();
+        let recv = nic.rx(1, endpoint.recv_with(simple_recv));
+        assert_eq!(recv, Ok(1));
+        assert_eq!(dropped.take(), Some(DropReason::Filtered));
+    }
+
+    #[test]
+    fn promiscuous() {
+        use core::cell::Cell;
+        const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+
+        let dropped = Cell::new(None);
+        let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+        let mut endpoint = Endpoint::new(MAC_ADDR_1);
+        endpoint.set_drop_hook(&mut hook);
+
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let sent = nic.tx(1, endpoint.send_with(simple_send));
+        assert_eq!(sent, Ok(1));
+        ethernet::frame::new_unchecked_mut(nic.get_mut(0).unwrap())
+            .set_dst_addr(MAC_ADDR_OTHER);
+
+        // Dropped by default.
+        nic.set_one_past_receive(1);
+        let recv = nic.rx(1, endpoint.recv_with(simple_recv));
+        assert_eq!(recv, Ok(1));
+        assert_eq!(dropped.take(), Some(DropReason::Filtered));
+
+        // Delivered with the original destination when promiscuous.
+        endpoint.set_promiscuous(true);
+        let delivered = Cell::new(None);
+        nic.reset_receive();
+        let recv = nic.rx(1, endpoint.recv_with(|frame: packet::In<_>| {
+            delivered.set(Some(frame.frame.dst_addr()));
+        }));
+        assert_eq!(recv, Ok(1));
+        assert_eq!(dropped.take(), None);
+        assert_eq!(delivered.take(), Some(MAC_ADDR_OTHER));
+    }
+
+    #[test]
+    fn secondary_address() {
+        use core::cell::Cell;
+        const MAC_ADDR_2: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 6]);
+        const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+
+        let dropped = Cell::new(None);
+        let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+        let mut addresses = [ethernet::Address::default(); 1];
+        let mut endpoint = Endpoint::new(MAC_ADDR_1);
+        endpoint.set_drop_hook(&mut hook);
+        endpoint.set_address_storage(&mut addresses[..]);
+
+        assert_eq!(endpoint.add_address(ethernet::Address::BROADCAST), Err(Error::Illegal));
+        assert_eq!(endpoint.add_address(MAC_ADDR_1), Ok(()));
+        assert_eq!(endpoint.add_address(MAC_ADDR_2), Ok(()));
+        assert_eq!(endpoint.add_address(MAC_ADDR_OTHER), Err(Error::Exhausted));
+        assert_eq!(endpoint.addr(), MAC_ADDR_1);
+
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let sent = nic.tx(1, endpoint.send_with(simple_send));
+        assert_eq!(sent, Ok(1));
+        // Sent from the primary address.
+        let frame = ethernet::frame::new_unchecked_mut(nic.get_mut(0).unwrap());
+        assert_eq!(frame.src_addr(), MAC_ADDR_1);
+
+        nic.set_one_past_receive(1);
+        for &(dst_addr, accepted) in &[(MAC_ADDR_1, true), (MAC_ADDR_2, true), (MAC_ADDR_OTHER, false)] {
+            ethernet::frame::new_unchecked_mut(nic.get_mut(0).unwrap())
+                .set_dst_addr(dst_addr);
+            nic.reset_receive();
+            let recv = nic.rx(1, endpoint.recv_with(simple_recv));
+            assert_eq!(recv, Ok(1));
+            let expected = if accepted { None } else { Some(DropReason::Filtered) };
+            assert_eq!(dropped.take(), expected, "Frame to {}", dst_addr);
+        }
+
+        assert!(!endpoint.remove_address(MAC_ADDR_1));
+        assert!(endpoint.remove_address(MAC_ADDR_2));
+        assert!(!endpoint.has_address(MAC_ADDR_2));
+    }
+
+    #[test]
+    fn stateful_handler() {
+        let mut endpoint = Endpoint::new(MAC_ADDR_1);
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+
+        let mut sender = StatefulHandler::new(0, |sent: &mut usize, frame: packet::Raw<_>| {
+            simple_send(frame);
+            *sent += 1;
+        });
+        let sent = nic.tx(1, endpoint.send(&mut sender));
+        assert_eq!(sent, Ok(1));
+        assert_eq!(*sender.state(), 1);
+
+        // The state accumulates over separate runs.
+        nic.set_one_past_receive(1);
+        let mut counter = StatefulHandler::new(0, |count: &mut usize, frame: packet::In<_>| {
+            simple_recv(frame);
+            *count += 1;
+        });
+        for _ in 0..3 {
+            nic.reset_receive();
+            let recv = nic.rx(1, endpoint.recv(&mut counter));
+            assert_eq!(recv, Ok(1));
+        }
+        assert_eq!(counter.into_state(), 3);
+    }
 }
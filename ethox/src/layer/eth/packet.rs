@@ -1,5 +1,6 @@
 use crate::nic;
-use crate::layer::{Error, Result};
+use crate::layer::{CastPolicy, DropReason, Error, Result};
+use crate::layer::trace::Event;
 use crate::wire::{ethernet, Payload, PayloadResult, PayloadMut, PayloadMutExt, Reframe, ReframePayload, payload};
 
 /// An incoming packet.
@@ -45,9 +46,12 @@ pub struct Init {
     /// The ethernet source address to use.
     ///
     /// Most often you'll want to select the address assigned to the ethernet endpoint at which
-    /// responses are to be received. But in theory you are free to use other addresses, for
-    /// example to emulate a very temporary endpoint or use manual addresses for less standard
-    /// compliant networking.
+    /// responses are to be received, available through [`Controller::src_addr`]. But you are free
+    /// to use other addresses, for example to emulate a very temporary endpoint, to bridge frames
+    /// of other hosts or to send from a virtual router address (VRRP). The address is emitted
+    /// as-is and *not* rewritten to the address of the endpoint.
+    ///
+    /// [`Controller::src_addr`]: struct.Controller.html#method.src_addr
     pub src_addr: ethernet::Address,
     /// The destination address for the frame.
     ///
@@ -56,6 +60,10 @@ pub struct Init {
     pub dst_addr: ethernet::Address,
     /// The protocol of the next layer, contained in the frame payload.
     pub ethertype: ethernet::EtherType,
+    /// An optional 802.1Q tag placing the frame into a virtual lan.
+    ///
+    /// The `ethertype` then describes the payload following the tag.
+    pub vlan: Option<ethernet::VlanTag>,
     /// The length in bytes that the payload requires.
     pub payload: usize,
 }
@@ -64,6 +72,15 @@ pub struct Init {
 pub(crate) trait Endpoint{
     /// Get the default source address.
     fn src_addr(&mut self) -> ethernet::Address;
+
+    /// Get the policy for non-unicast traffic.
+    fn cast_policy(&self) -> CastPolicy;
+
+    /// Notify the drop hook of a dropped packet.
+    fn dropped(&mut self, reason: DropReason, bytes: &[u8]);
+
+    /// Notify the tracer of an event.
+    fn trace(&mut self, event: Event);
 }
 
 impl<'a> Controller<'a> {
@@ -97,6 +114,30 @@ impl<'a> Controller<'a> {
         self.endpoint.src_addr()
     }
 
+    /// Get the broadcast policy configured on the ethernet endpoint.
+    ///
+    /// Upper layers should consult this instead of keeping their own configuration so that
+    /// broadcast traffic is treated consistently across layers.
+    pub fn cast_policy(&self) -> CastPolicy {
+        self.endpoint.cast_policy()
+    }
+
+    /// Report a packet that was dropped to the hook of the ethernet endpoint.
+    ///
+    /// Layers call this whenever they discard a packet instead of passing it on. Upper layer code
+    /// may use it to make its own drops observable in the same place.
+    pub fn report_drop(&mut self, reason: DropReason, bytes: &[u8]) {
+        self.endpoint.dropped(reason, bytes)
+    }
+
+    /// Report an event to the tracer of the ethernet endpoint.
+    ///
+    /// Does nothing if no tracer is registered. Upper layer code may use it to make its own
+    /// decisions observable next to those of the stack.
+    pub fn trace(&mut self, event: Event) {
+        self.endpoint.trace(event)
+    }
+
     /// Try to send the packet associated with this controller.
     pub fn send(&mut self) -> Result<()> {
         self.nic_handle.queue()
@@ -122,14 +163,13 @@ impl<'a, P: PayloadMut> In<'a, P> {
     ///
     /// If the length is changed then the longest slice at the end that fits into both
     /// representations is regarded as the payload of the packet.
+    ///
+    /// Fails with `BadSize` if the buffer can not be resized to the new length, for example when
+    /// the answer is longer than a fixed size receive buffer. The frame is then left unchanged.
     pub fn reinit(self, init: Init) -> Result<Out<'a, P>> {
-        let In { control, frame } = self;
-        let new_len = ethernet::frame::buffer_len(init.payload);
-        let new_repr = ethernet::Repr {
-            src_addr: init.src_addr,
-            dst_addr: init.dst_addr,
-            ethertype: init.ethertype,
-        };
+        let In { mut control, frame } = self;
+        let new_repr = init.repr();
+        let new_len = new_repr.buffer_len(init.payload);
         let raw_repr = frame.repr();
         let raw_buffer = frame.into_inner();
 
@@ -150,6 +190,7 @@ impl<'a, P: PayloadMut> In<'a, P> {
         // Now emit the header again:
         new_repr.emit(ethernet::frame::new_unchecked_mut(raw_buffer.payload_mut()));
         let frame = ethernet::Frame::new_unchecked(raw_buffer, new_repr);
+        control.trace(Event::Answered);
 
         Ok(Out {
             control,
@@ -185,14 +226,34 @@ impl<'a, P: Payload> Out<'a, P> {
         let Out { control, frame } = self;
         Raw { control, payload: frame.into_inner() }
     }
-    
+}
+
+impl<'a, P: PayloadMut> Out<'a, P> {
     /// Try to send that packet.
+    ///
+    /// Frames shorter than the ethernet minimum are padded with zeroes first. Upper protocols
+    /// carry their own length fields so the padding is not mistaken for payload by receivers. If
+    /// the buffer can not grow then the frame is sent as is.
     pub fn send(mut self) -> Result<()> {
+        self.pad();
         self.control.send()
     }
-}
 
-impl<'a, P: PayloadMut> Out<'a, P> {
+    fn pad(&mut self) {
+        let frame_len = self.frame.as_ref().len();
+        if frame_len >= ethernet::MIN_FRAME_LEN {
+            return;
+        }
+
+        let payload_len = self.frame.payload_slice().len();
+        let padded_len = payload_len + ethernet::MIN_FRAME_LEN - frame_len;
+        if self.frame.resize(padded_len).is_ok() {
+            for byte in &mut self.frame.payload_mut_slice()[payload_len..] {
+                *byte = 0;
+            }
+        }
+    }
+
     /// A mutable slice containing the payload of the contained protocol.
     ///
     /// Prefer this an `into_raw` and `new_unchecked` in case a temporary reference to the payload
@@ -204,7 +265,14 @@ impl<'a, P: PayloadMut> Out<'a, P> {
 
 impl<'a, P: Payload + PayloadMut> Raw<'a, P> {
     /// Initialize the raw packet buffer to a valid ethernet frame.
+    ///
+    /// Fails with `BadSize` before touching the buffer if the payload exceeds the mtu reported by
+    /// the nic.
     pub fn prepare(self, init: Init) -> Result<Out<'a, P>> {
+        if init.payload > self.control.info().mtu() {
+            return Err(Error::BadSize);
+        }
+
         let mut payload = self.payload;
         let repr = init.initialize(&mut payload)?;
         Ok(Out {
@@ -212,6 +280,30 @@ impl<'a, P: Payload + PayloadMut> Raw<'a, P> {
             frame: ethernet::Frame::new_unchecked(payload, repr),
         })
     }
+
+    /// Send a frame with some payload from the address of the endpoint.
+    ///
+    /// This is a shorthand for preparing the buffer with the configured source address, copying
+    /// the payload and sending it. The ethertype is not interpreted in any way, so this can be
+    /// used to implement custom link layer protocols alongside the other layers. Upper layers
+    /// can give up their raw buffers for this purpose, see `ip::RawPacket::into_eth`.
+    pub fn send_frame(
+        mut self,
+        dst_addr: ethernet::Address,
+        ethertype: ethernet::EtherType,
+        payload: &[u8],
+    ) -> Result<()> {
+        let src_addr = self.control.src_addr();
+        let mut out = self.prepare(Init {
+            src_addr,
+            dst_addr,
+            ethertype,
+            vlan: None,
+            payload: payload.len(),
+        })?;
+        out.payload_mut_slice().copy_from_slice(payload);
+        out.send()
+    }
 }
 
 impl<P: Payload> Payload for Out<'_, P> {
@@ -235,18 +327,25 @@ impl<P: PayloadMut> PayloadMut for Out<'_, P> {
 }
 
 impl Init {
-    fn initialize<P: PayloadMut>(&self, payload: &mut P) -> Result<ethernet::Repr> {
-        let real_len = ethernet::frame::buffer_len(self.payload);
-        let repr = ethernet::Repr {
+    fn repr(&self) -> ethernet::Repr {
+        ethernet::Repr {
             src_addr: self.src_addr,
             dst_addr: self.dst_addr,
             ethertype: self.ethertype,
-        };
+            vlan: self.vlan,
+        }
+    }
+
+    fn initialize<P: PayloadMut>(&self, payload: &mut P) -> Result<ethernet::Repr> {
+        let repr = self.repr();
+        let real_len = repr.buffer_len(self.payload);
 
         payload.resize(real_len)?;
         let ethernet = ethernet::frame::new_unchecked_mut(payload.payload_mut());
-        ethernet.check_len()
-            .map_err(|_| Error::BadSize)?;
+        // The old content may not be a valid frame, so don't rely on `check_len`.
+        if ethernet.as_bytes().len() < real_len {
+            return Err(Error::BadSize);
+        }
         repr.emit(ethernet);
 
         Ok(repr)
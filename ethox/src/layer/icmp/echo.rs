@@ -0,0 +1,431 @@
+use core::time::Duration;
+
+use crate::layer::{ip, DropReason, Error, FnHandler, Result};
+use crate::time::Instant;
+use crate::wire::{icmpv4, icmpv6, Checksum, Payload, PayloadMut};
+use crate::wire::ip::{v6, Address as IpAddress, Protocol as IpProtocol, Repr as IpRepr, Subnet};
+
+use super::endpoint::NoHandler;
+use super::packet::{Controller, EchoPattern, EchoStatus, In};
+use super::Recv;
+
+/// A receiver answering echo requests in place.
+///
+/// Reinitializes the buffer of each echo request into the matching reply, keeping identifier,
+/// sequence number and payload, and queues it right away. This is the same answer the endpoint
+/// gives in its automatic mode. Use it in [manual mode] to answer pings alongside a custom handler
+/// for all other messages, for example to only answer some of the requests.
+///
+/// The icmp layer only passes ICMPv4 messages to its handlers. To also answer ICMPv6 requests,
+/// use the responder as an ip receiver instead, wrapping the receiver of the icmp endpoint. It
+/// then answers requests of both versions and passes all other packets on to the endpoint.
+///
+/// [manual mode]: struct.Endpoint.html#method.manual
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EchoResponder<H> {
+    inner: H,
+}
+
+impl<H> EchoResponder<H> {
+    /// Answer echo requests, passing all other messages on to an inner handler.
+    pub fn new(inner: H) -> Self {
+        EchoResponder { inner }
+    }
+
+    /// Get a reference to the inner handler.
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner handler.
+    pub fn inner_mut(&mut self) -> &mut H {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner handler.
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<P, H> Recv<P> for EchoResponder<H>
+where
+    P: PayloadMut,
+    H: Recv<P>,
+{
+    fn receive(&mut self, packet: In<P>) {
+        match packet.packet.repr() {
+            icmpv4::Repr::EchoRequest { .. } => (),
+            _ => return self.inner.receive(packet),
+        }
+
+        if let Err(_) = packet.answer().and_then(|answer| answer.send()) {
+            // TODO: log error
+        }
+    }
+}
+
+impl<P, H> ip::Recv<P> for EchoResponder<H>
+where
+    P: PayloadMut,
+    H: ip::Recv<P>,
+{
+    fn receive(&mut self, packet: ip::InPacket<P>) {
+        let result = match &packet.packet {
+            ip::IpPacket::V4(v4_packet) => {
+                let checksum = packet.control.info().capabilities().icmpv4().rx_checksum();
+                let repr = match icmpv4::Packet::new_checked(v4_packet, checksum) {
+                    Ok(message) => message.repr(),
+                    Err(_) => return self.inner.receive(packet),
+                };
+                if !matches!(repr, icmpv4::Repr::EchoRequest { .. }) {
+                    return self.inner.receive(packet);
+                }
+
+                let ip::InPacket { control, packet } = packet;
+                let packet = match packet {
+                    ip::IpPacket::V4(packet) => icmpv4::Packet::new_unchecked(packet, repr),
+                    ip::IpPacket::V6(_) => unreachable!("Parsed as ICMPv4"),
+                };
+                let control = Controller { inner: control };
+                In { control, packet }.answer().and_then(|answer| answer.send())
+            },
+            ip::IpPacket::V6(v6_packet) => match echo_request_v6(v6_packet) {
+                Some(request) => answer_v6(packet, request),
+                None => return self.inner.receive(packet),
+            },
+        };
+
+        if result.is_err() {
+            // TODO: log error
+        }
+    }
+}
+
+/// Parse an ICMPv6 echo request directly following the ip header.
+pub(crate) fn echo_request_v6<P: Payload>(packet: &ip::V6Packet<P>) -> Option<icmpv6::Repr> {
+    let repr = packet.repr();
+    if repr.next_header != IpProtocol::Icmpv6 {
+        return None;
+    }
+
+    let checksum = icmpv6::Checksum::Manual {
+        src_addr: repr.src_addr,
+        dst_addr: repr.dst_addr,
+    };
+    let message = icmpv6::packet::new_checked(packet.payload_slice()).ok()?;
+    match icmpv6::Repr::parse(message, checksum).ok()? {
+        request @ icmpv6::Repr::EchoRequest { .. } => Some(request),
+        _ => None,
+    }
+}
+
+/// Answer an ICMPv6 echo request in place.
+///
+/// The reply keeps identifier, sequence number and payload of the request. A request to a group
+/// is answered from one of our own addresses instead of the group address.
+pub(crate) fn answer_v6<P: PayloadMut>(packet: ip::InPacket<P>, request: icmpv6::Repr)
+    -> Result<()>
+{
+    let answer = match request {
+        icmpv6::Repr::EchoRequest { ident, seq_no, payload } => {
+            icmpv6::Repr::EchoReply { ident, seq_no, payload }
+        },
+        _ => return Err(Error::Illegal),
+    };
+
+    let ip_repr = match &packet.packet {
+        ip::IpPacket::V6(packet) => packet.repr(),
+        ip::IpPacket::V4(_) => return Err(Error::Illegal),
+    };
+
+    let source = if ip_repr.dst_addr.is_unicast() {
+        IpAddress::from(ip_repr.dst_addr).into()
+    } else {
+        ip::Source::Mask { subnet: Subnet::Ipv6(v6::Subnet::ANY) }
+    };
+
+    let out = packet.reinit(ip::Init {
+        source,
+        dst_addr: ip_repr.src_addr.into(),
+        protocol: IpProtocol::Icmpv6,
+        payload: answer.buffer_len(),
+        hop_limit: None,
+        traffic_class: 0,
+    })?;
+
+    let (src_addr, dst_addr) = match out.repr() {
+        IpRepr::Ipv6(repr) => (repr.src_addr, repr.dst_addr),
+        _ => return Err(Error::Illegal),
+    };
+
+    // The echoed payload stayed in place, the checksum is computed over it.
+    let ip::InPacket { control, mut packet } = out.into_incoming();
+    let message = icmpv6::packet::new_unchecked_mut(packet.payload_mut().as_mut_slice());
+    answer.emit(message, icmpv6::Checksum::Manual { src_addr, dst_addr });
+    ip::OutPacket::new_unchecked(control, packet).send()
+}
+
+/// A sender of echo requests to a single destination, as in `ping`.
+///
+/// Requests are sent from an ip sender with a payload filled from an [`EchoPattern`]. The
+/// replies are picked from the incoming traffic by an ip receiver that wraps another handler of
+/// that layer, such as the receiver of the icmp endpoint, to which all other packets are passed. A
+/// reply is matched by its source, identifier and the sequence number of the last request. Its
+/// round trip time is measured from the timestamp of the sent request to that of the received
+/// reply, and its payload is verified against the pattern.
+///
+/// Both ICMPv4 and ICMPv6 are supported, the version follows from the destination address.
+/// Replies of ICMPv6 are not otherwise handled by the icmp endpoint so they must be received
+/// through this requester.
+///
+/// [`EchoPattern`]: enum.EchoPattern.html
+#[derive(Clone, Copy, Debug)]
+pub struct EchoRequester<'p> {
+    source: ip::Source,
+    dst_addr: IpAddress,
+    ident: u16,
+    pattern: EchoPattern<'p>,
+    payload_len: usize,
+    /// The sequence number of a request waiting to be sent.
+    queued: Option<u16>,
+    /// The last request sent and its time of sending.
+    outstanding: Option<(u16, Instant)>,
+    /// The reply to the last request, until it is retrieved.
+    reply: Option<EchoReply>,
+}
+
+/// A reply matched to an echo request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EchoReply {
+    /// The sequence number of the answered request.
+    pub seq_no: u16,
+    /// The time between sending the request and receiving the reply.
+    pub rtt: Duration,
+    /// Whether the payload was echoed with its length and matched the pattern.
+    pub intact: bool,
+}
+
+/// An echo requester borrowed for sending.
+pub struct EchoSender<'a, 'p> {
+    requester: &'a mut EchoRequester<'p>,
+}
+
+/// An echo requester borrowed for receiving.
+pub struct EchoReceiver<'a, 'p, H=NoHandler> {
+    requester: &'a mut EchoRequester<'p>,
+
+    /// The receiver for any other packets.
+    handler: Option<H>,
+}
+
+impl<'p> EchoRequester<'p> {
+    /// Create a requester for a destination.
+    ///
+    /// The first request with the sequence number `seq_no` is queued right away. Each request
+    /// carries `payload_len` bytes filled with the pattern.
+    pub fn new(
+        source: ip::Source,
+        dst_addr: IpAddress,
+        ident: u16,
+        seq_no: u16,
+        pattern: EchoPattern<'p>,
+        payload_len: usize,
+    ) -> Self {
+        EchoRequester {
+            source,
+            dst_addr,
+            ident,
+            pattern,
+            payload_len,
+            queued: Some(seq_no),
+            outstanding: None,
+            reply: None,
+        }
+    }
+
+    /// The destination of the requests.
+    pub fn dst_addr(&self) -> IpAddress {
+        self.dst_addr
+    }
+
+    /// The identifier of the requests.
+    pub fn ident(&self) -> u16 {
+        self.ident
+    }
+
+    /// Queue another request with a new sequence number.
+    ///
+    /// A reply to a previous request is no longer accepted once this request is sent.
+    pub fn request(&mut self, seq_no: u16) {
+        self.queued = Some(seq_no);
+    }
+
+    /// The sequence number and time of sending of the request awaiting its reply.
+    pub fn outstanding(&self) -> Option<(u16, Instant)> {
+        self.outstanding
+    }
+
+    /// Retrieve the reply to the last request, if it arrived.
+    pub fn poll(&mut self) -> Option<EchoReply> {
+        self.reply.take()
+    }
+
+    /// A sender for the queued request.
+    pub fn send(&mut self) -> EchoSender<'_, 'p> {
+        EchoSender { requester: self }
+    }
+
+    /// A receiver that only consumes the replies.
+    pub fn answer(&mut self) -> EchoReceiver<'_, 'p> {
+        EchoReceiver { requester: self, handler: None, }
+    }
+
+    /// Receive packets, passing all other packets to a handler.
+    pub fn recv<H>(&mut self, handler: H) -> EchoReceiver<'_, 'p, H> {
+        EchoReceiver { requester: self, handler: Some(handler), }
+    }
+
+    /// Receive packets, passing all other packets to a function.
+    pub fn recv_with<H>(&mut self, handler: H) -> EchoReceiver<'_, 'p, FnHandler<H>> {
+        self.recv(FnHandler(handler))
+    }
+
+    /// Initialize a raw packet to the request and send it.
+    fn send_request<P: PayloadMut>(&self, raw: ip::RawPacket<P>, seq_no: u16) -> Result<Instant> {
+        let (ident, payload) = (self.ident, self.payload_len);
+        let v4_repr = icmpv4::Repr::EchoRequest { ident, seq_no, payload };
+        let v6_repr = icmpv6::Repr::EchoRequest { ident, seq_no, payload };
+        let (protocol, buffer_len) = match self.dst_addr {
+            IpAddress::Ipv6(_) => (IpProtocol::Icmpv6, v6_repr.buffer_len()),
+            _ => (IpProtocol::Icmp, v4_repr.buffer_len()),
+        };
+
+        let out = raw.prepare(ip::Init {
+            source: self.source,
+            dst_addr: self.dst_addr,
+            protocol,
+            payload: buffer_len,
+            hop_limit: None,
+            traffic_class: 0,
+        })?;
+
+        let ip_repr = out.repr();
+        let ip::InPacket { control, mut packet } = out.into_incoming();
+        let now = control.info().timestamp();
+        let capabilities = control.info().capabilities();
+        let message = packet.payload_mut().as_mut_slice();
+        match ip_repr {
+            IpRepr::Ipv6(ip_repr) => {
+                let message = icmpv6::packet::new_unchecked_mut(message);
+                // The checksum covers the payload, it must be present before emitting.
+                self.pattern.fill(message.payload_mut_slice(), now);
+                v6_repr.emit(message, icmpv6::Checksum::Manual {
+                    src_addr: ip_repr.src_addr,
+                    dst_addr: ip_repr.dst_addr,
+                });
+            },
+            _ => {
+                let message = icmpv4::packet::new_unchecked_mut(message);
+                v4_repr.emit(message, Checksum::Ignored);
+                self.pattern.fill(message.payload_mut_slice(), now);
+                if capabilities.icmpv4().tx_checksum().manual() {
+                    message.fill_checksum();
+                }
+            },
+        }
+
+        ip::OutPacket::new_unchecked(control, packet).send()?;
+        Ok(now)
+    }
+
+    /// Check if a packet is the reply to the outstanding request.
+    fn reply_to<P: Payload>(&self, packet: &ip::InPacket<P>) -> Option<EchoReply> {
+        let (seq_no, sent) = self.outstanding?;
+        let capabilities = packet.control.info().capabilities();
+        let (ident, reply_seq_no, payload) = match &packet.packet {
+            ip::IpPacket::V4(packet) => {
+                let repr = packet.repr();
+                if repr.protocol != IpProtocol::Icmp || self.dst_addr != repr.src_addr.into() {
+                    return None;
+                }
+                let checksum = capabilities.icmpv4().rx_checksum();
+                let message = icmpv4::packet::new_checked(packet.payload_slice()).ok()?;
+                match icmpv4::Repr::parse(message, checksum).ok()? {
+                    icmpv4::Repr::EchoReply { ident, seq_no, .. } => {
+                        (ident, seq_no, message.payload_slice())
+                    },
+                    _ => return None,
+                }
+            },
+            ip::IpPacket::V6(packet) => {
+                let repr = packet.repr();
+                if repr.next_header != IpProtocol::Icmpv6 || self.dst_addr != repr.src_addr.into() {
+                    return None;
+                }
+                let checksum = icmpv6::Checksum::Manual {
+                    src_addr: repr.src_addr,
+                    dst_addr: repr.dst_addr,
+                };
+                let message = icmpv6::packet::new_checked(packet.payload_slice()).ok()?;
+                match icmpv6::Repr::parse(message, checksum).ok()? {
+                    icmpv6::Repr::EchoReply { ident, seq_no, .. } => {
+                        (ident, seq_no, message.payload_slice())
+                    },
+                    _ => return None,
+                }
+            },
+        };
+
+        if ident != self.ident || reply_seq_no != seq_no {
+            return None;
+        }
+
+        let arrived = packet.control.info().timestamp();
+        let intact = payload.len() == self.payload_len
+            && matches!(self.pattern.verify(payload), EchoStatus::Intact { .. });
+        Some(EchoReply {
+            seq_no,
+            rtt: arrived - sent,
+            intact,
+        })
+    }
+}
+
+impl<P: PayloadMut> ip::Send<P> for EchoSender<'_, '_> {
+    fn send(&mut self, raw: ip::RawPacket<P>) {
+        let seq_no = match self.requester.queued {
+            Some(seq_no) => seq_no,
+            None => return,
+        };
+
+        // The request stays queued if it could not be sent.
+        if let Ok(sent) = self.requester.send_request(raw, seq_no) {
+            self.requester.queued = None;
+            self.requester.outstanding = Some((seq_no, sent));
+            self.requester.reply = None;
+        }
+    }
+}
+
+impl<P, H> ip::Recv<P> for EchoReceiver<'_, '_, H>
+where
+    P: PayloadMut,
+    H: ip::Recv<P>,
+{
+    fn receive(&mut self, packet: ip::InPacket<P>) {
+        if let Some(reply) = self.requester.reply_to(&packet) {
+            self.requester.outstanding = None;
+            self.requester.reply = Some(reply);
+            return;
+        }
+
+        match self.handler.as_mut() {
+            Some(handler) => handler.receive(packet),
+            None => {
+                let ip::InPacket { mut control, packet } = packet;
+                control.report_drop(DropReason::Unsupported, packet.payload().as_slice())
+            },
+        }
+    }
+}
@@ -1,6 +1,7 @@
-use crate::layer::{self, FnHandler, Result};
-use crate::wire::{icmpv4, ip, Error, Payload, PayloadMut};
+use crate::layer::{self, DropReason, FnHandler, Inspect, Result, StatefulHandler, Tee};
+use crate::wire::{icmpv4, icmpv6, ip, Error, Payload, PayloadMut};
 
+use super::{echo, ndp};
 use super::packet::{Controller, In, Raw};
 use super::{Recv, Send};
 
@@ -162,23 +163,65 @@ where
     P: PayloadMut,
     H: Recv<P>,
 {
-    fn receive(&mut self, layer::ip::InPacket { control, packet }: layer::ip::InPacket<P>) {
+    fn receive(&mut self, layer::ip::InPacket { mut control, packet }: layer::ip::InPacket<P>) {
         let capabilities = control.info().capabilities();
 
         let icmp = match packet {
             layer::ip::IpPacket::V4(packet) => {
                 if packet.repr().protocol != ip::Protocol::Icmp {
-                    return;
+                    return control.report_drop(DropReason::Unsupported, packet.payload_slice());
                 }
 
-                match icmpv4::Packet::new_checked(packet, capabilities.icmpv4().rx_checksum()) {
-                    Ok(packet) => packet,
+                match icmpv4::Packet::new_checked(&packet, capabilities.icmpv4().rx_checksum()) {
+                    Ok(icmp) => {
+                        let repr = icmp.repr();
+                        icmpv4::Packet::new_unchecked(packet, repr)
+                    },
                     Err(Error::Unsupported) => unimplemented!("Forward to upper layer"),
-                    Err(_) => return,
+                    Err(err) => return control.report_drop(err.into(), packet.payload_slice()),
                 }
             },
-            // Handle icmpv6
-            _ => return,
+            layer::ip::IpPacket::V6(packet) => {
+                let ip_repr = packet.repr();
+                if ip_repr.next_header != ip::Protocol::Icmpv6 {
+                    return control.report_drop(DropReason::Unsupported, packet.payload_slice());
+                }
+
+                let checksum = icmpv6::Checksum::Manual {
+                    src_addr: ip_repr.src_addr,
+                    dst_addr: ip_repr.dst_addr,
+                };
+                let message = icmpv6::packet::new_unchecked(packet.payload_slice());
+                let repr = match icmpv6::Repr::parse(message, checksum) {
+                    Ok(repr) => repr,
+                    Err(err) => return control.report_drop(err.into(), packet.payload_slice()),
+                };
+
+                // Neighbor discovery and echo requests are handled without the upper layer.
+                return match repr {
+                    icmpv6::Repr::EchoRequest { .. } if !self.endpoint.inner.manual_echo => {
+                        if self.endpoint.inner.deny_echo {
+                            return;
+                        }
+
+                        let packet = layer::ip::InPacket {
+                            control,
+                            packet: layer::ip::IpPacket::V6(packet),
+                        };
+                        // Nothing more to do if we can not answer.
+                        let _ = echo::answer_v6(packet, repr);
+                    },
+                    icmpv6::Repr::NeighborSolicit { .. } | icmpv6::Repr::NeighborAdvert { .. } => {
+                        let packet = layer::ip::InPacket {
+                            control,
+                            packet: layer::ip::IpPacket::V6(packet),
+                        };
+                        // Rejected messages have been reported, nothing more to do otherwise.
+                        let _ = ndp::receive(packet, repr);
+                    },
+                    _ => control.report_drop(DropReason::Unsupported, packet.payload_slice()),
+                };
+            },
         };
 
         let control = Controller { inner: control };
@@ -194,7 +237,9 @@ where
             (HandlingKind::ToUpperLayer(packet), Some(handler)) => {
                 handler.receive(packet)
             },
-            _ => (),
+            (HandlingKind::ToUpperLayer(In { mut control, packet }), None) => {
+                control.inner.report_drop(DropReason::Unsupported, packet.as_bytes())
+            },
         }
     }
 }
@@ -222,6 +267,12 @@ impl<P: PayloadMut> Recv<P> for NoHandler {
     }
 }
 
+impl<P: Payload> layer::ip::Recv<P> for NoHandler {
+    fn receive(&mut self, _: layer::ip::InPacket<P>) {
+        match self._private { }
+    }
+}
+
 impl<P: Payload, F> Recv<P> for FnHandler<F>
     where F: FnMut(In<P>)
 {
@@ -237,3 +288,30 @@ impl<P: Payload, F> Send<P> for FnHandler<F>
         self.0(frame)
     }
 }
+
+impl<P: Payload, S, F> Recv<P> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, In<P>)
+{
+    fn receive(&mut self, frame: In<P>) {
+        (self.handler)(&mut self.state, frame)
+    }
+}
+
+impl<P: Payload, S, F> Send<P> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, Raw<P>)
+{
+    fn send(&mut self, frame: Raw<P>) {
+        (self.handler)(&mut self.state, frame)
+    }
+}
+
+impl<P, A, B> Recv<P> for Tee<A, B>
+where
+    P: Payload,
+    A: for<'a> Inspect<In<'a, P>>,
+    B: for<'a> Inspect<In<'a, P>>,
+{
+    fn receive(&mut self, frame: In<P>) {
+        self.inspect(&frame)
+    }
+}
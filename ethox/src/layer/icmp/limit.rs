@@ -0,0 +1,166 @@
+use crate::managed::{List, Slice};
+use crate::time::{Instant, TokenBucket};
+use crate::wire::ip::{Address, Cidr, Subnet};
+
+/// A token bucket limiting the rate of outgoing ICMP error messages.
+///
+/// Generating errors in response to received packets makes a node an easy amplifier for an
+/// attacker, as RFC 1812 and RFC 4443 warn. Each message takes one token from a bucket that holds
+/// at most `burst` tokens and is refilled with `rate` tokens per second, as measured by the
+/// timestamps of the received packets. Messages are suppressed while the bucket is empty.
+///
+/// The limit applies either to all messages together or separately to each destination prefix.
+/// In the latter case the buckets are kept in a provided storage. When it is full, a bucket is
+/// only reused for another prefix once it has been refilled completely, so that cycling through
+/// many destinations does not circumvent the limit. Until then, errors to new prefixes are
+/// suppressed.
+#[derive(Debug)]
+pub struct RateLimiter<'a> {
+    rate: u32,
+    burst: u32,
+    key: Key,
+    global: TokenBucket,
+    buckets: List<'a, Bucket>,
+}
+
+/// The state of one token bucket of a `RateLimiter`.
+///
+/// This is only public to allow providing storage for the buckets of each prefix.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bucket {
+    subnet: Option<Subnet>,
+    tokens: TokenBucket,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Key {
+    Global,
+    Prefix {
+        v4: u8,
+        v6: u8,
+    },
+}
+
+impl<'a> RateLimiter<'a> {
+    /// Limit all messages together to `rate` per second, with bursts of up to `burst` messages.
+    pub fn global(rate: u32, burst: u32) -> Self {
+        RateLimiter {
+            rate,
+            burst,
+            key: Key::Global,
+            global: TokenBucket::new(rate, burst),
+            buckets: List::new(Slice::empty()),
+        }
+    }
+
+    /// Limit the messages to each destination prefix separately.
+    ///
+    /// Destinations are grouped by their ipv4 or ipv6 prefix of the given lengths. The storage
+    /// determines the number of prefixes that are tracked at the same time.
+    ///
+    /// # Panics
+    /// This function panics if a prefix length is longer than the addresses of its family.
+    pub fn per_prefix<S>(rate: u32, burst: u32, v4_prefix: u8, v6_prefix: u8, storage: S) -> Self
+        where S: Into<Slice<'a, Bucket>>,
+    {
+        assert!(v4_prefix <= 32, "Invalid ipv4 prefix length");
+        assert!(v6_prefix <= 128, "Invalid ipv6 prefix length");
+        RateLimiter {
+            rate,
+            burst,
+            key: Key::Prefix { v4: v4_prefix, v6: v6_prefix },
+            global: TokenBucket::new(rate, burst),
+            buckets: List::new(storage.into()),
+        }
+    }
+
+    /// The number of tokens refilled per second.
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// The maximum number of tokens in a bucket.
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// Check whether a message to a destination may be sent now, consuming a token if so.
+    pub fn allow(&mut self, dst_addr: Address, now: Instant) -> bool {
+        let (v4, v6) = match self.key {
+            Key::Global => return self.global.take(1, now),
+            Key::Prefix { v4, v6 } => (v4, v6),
+        };
+
+        let prefix_len = match dst_addr {
+            Address::Ipv4(_) => v4,
+            Address::Ipv6(_) => v6,
+            _ => return false,
+        };
+        let subnet = Cidr::new(dst_addr, prefix_len).subnet();
+
+        if let Some(bucket) = self.buckets.iter_mut().find(|bucket| bucket.subnet == Some(subnet)) {
+            return bucket.tokens.take(1, now);
+        }
+
+        let bucket = match self.buckets.push() {
+            Some(bucket) => bucket,
+            None => match self.buckets.iter_mut().find(|bucket| bucket.tokens.is_full(now)) {
+                Some(bucket) => bucket,
+                None => return false,
+            },
+        };
+
+        *bucket = Bucket {
+            subnet: Some(subnet),
+            tokens: TokenBucket::new(self.rate, self.burst),
+        };
+        bucket.tokens.take(1, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::ip::v4;
+
+    const DST_A: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const DST_B: v4::Address = v4::Address::new(10, 0, 1, 1);
+
+    #[test]
+    fn global_burst() {
+        let mut limiter = RateLimiter::global(10, 3);
+        let start = Instant::from_millis(1000);
+
+        let allowed = (0..10)
+            .filter(|_| limiter.allow(DST_A.into(), start))
+            .count();
+        assert_eq!(allowed, 3);
+
+        // Other destinations share the same bucket.
+        assert!(!limiter.allow(DST_B.into(), start));
+
+        // One token is refilled every 100 milliseconds.
+        assert!(!limiter.allow(DST_A.into(), Instant::from_millis(1099)));
+        assert!(limiter.allow(DST_A.into(), Instant::from_millis(1100)));
+        assert!(!limiter.allow(DST_A.into(), Instant::from_millis(1100)));
+    }
+
+    #[test]
+    fn per_prefix() {
+        let mut buckets = [Bucket::default(); 1];
+        let mut limiter = RateLimiter::per_prefix(1, 2, 24, 64, &mut buckets[..]);
+        let start = Instant::from_millis(0);
+
+        assert!(limiter.allow(DST_A.into(), start));
+        assert!(limiter.allow(v4::Address::new(10, 0, 0, 2).into(), start));
+        assert!(!limiter.allow(DST_A.into(), start));
+
+        // No bucket for another prefix while the one in use is not refilled.
+        assert!(!limiter.allow(DST_B.into(), start));
+        assert!(!limiter.allow(DST_B.into(), Instant::from_millis(1000)));
+
+        // After two seconds it is full again and can be reused.
+        assert!(limiter.allow(DST_B.into(), Instant::from_millis(2000)));
+        assert!(limiter.allow(DST_A.into(), Instant::from_millis(4000)));
+    }
+}
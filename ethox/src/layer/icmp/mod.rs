@@ -1,6 +1,7 @@
 //! Receiving and sending Icmp messages.
 //!
-//! Only supports Icmpv4 for now.
+//! Only supports Icmpv4 for now, apart from echo requests and the neighbor discovery of IPv6 which
+//! are handled internally, the latter by the [`ndp`] module.
 //!
 //! Tuned to automate most parts of the icmp procedures *internally*. Nevertheless it will has an
 //! optional interface to forward unhandled messages to a custom receiver. This is in accorance
@@ -24,17 +25,71 @@
 //! nic, it will try to store it into an internal buffer. If there is not enough space it will try
 //! to forward it to the optional upper layer receiver. If that fails, the packet is discarded.
 //!
+//! In manual mode, an [`EchoResponder`] gives the same in-place answers while passing all other
+//! messages on to a custom handler. Wrapped around the receiver of the endpoint, it also answers
+//! the echo requests of ICMPv6 which are not passed to icmp handlers.
+//!
+//! When sending pings, an [`EchoPattern`] can be filled into the request payload. Replies are
+//! passed to the upper layer where [`InPacket::verify_echo`] detects a corrupted payload.
+//! Alternatively, an [`EchoRequester`] sends the requests of a `ping` to one destination over
+//! ICMPv4 or ICMPv6 directly from the ip layer, and picks out the replies to measure their round
+//! trip time.
+//!
+//! [`EchoPattern`]: enum.EchoPattern.html
+//! [`EchoRequester`]: struct.EchoRequester.html
+//! [`EchoResponder`]: struct.EchoResponder.html
+//! [`InPacket::verify_echo`]: struct.InPacket.html#method.verify_echo
+//!
+//! ## Neighbor discovery
+//!
+//! Neighbor solicitations for our own addresses are answered and advertisements update the
+//! neighbor cache of the ip endpoint, see [`ndp`]. Solicitations for unresolved neighbors are sent
+//! by the ip layer alongside its arp requests. Additionally, [`RawPacket::advertise_neighbor`]
+//! announces one of our addresses to all nodes. Router advertisements for address
+//! autoconfiguration are consumed by an [`ndp::Autoconf`].
+//!
+//! [`ndp`]: ndp/index.html
+//! [`ndp::Autoconf`]: ndp/struct.Autoconf.html
+//! [`RawPacket::advertise_neighbor`]: struct.RawPacket.html#method.advertise_neighbor
+//!
+//! ## Rate limiting errors
+//!
+//! A [`RateLimiter`] bounds the rate of ICMP error messages that are generated in response to
+//! received packets, either in total or for each destination prefix. It is installed on the ip
+//! [`Endpoint`] and consulted by all error generators, the ip [`Forwarder`] as well as the
+//! parameter problems of the endpoint itself.
+//!
+//! [`RateLimiter`]: struct.RateLimiter.html
+//! [`Endpoint`]: ../ip/struct.Endpoint.html
+//! [`Forwarder`]: ../ip/struct.Forwarder.html
+//!
 //! ## Other message types
 //!
 //! All other message types can be received in an upper layer or are simply discarded if there is
 //! no upper handler that is ready to inspect packets.
 use crate::wire::Payload;
 
+mod echo;
 mod endpoint;
+mod limit;
+pub mod ndp;
 mod packet;
 #[cfg(test)]
 mod tests;
 
+pub use echo::{
+    EchoReceiver,
+    EchoReply,
+    EchoRequester,
+    EchoResponder,
+    EchoSender,
+};
+
+pub use limit::{
+    Bucket,
+    RateLimiter,
+};
+
 pub use endpoint::{
     Endpoint,
     Receiver,
@@ -43,6 +98,8 @@ pub use endpoint::{
 
 pub use packet::{
     Controller,
+    EchoPattern,
+    EchoStatus,
     Init,
     In as InPacket,
     Out as OutPacket,
@@ -0,0 +1,481 @@
+//! Neighbor discovery for IPv6.
+//!
+//! Resolves IPv6 addresses of on-link neighbors to their hardware addresses as described in
+//! [RFC 4861]. It shares the neighbor cache of the ip endpoint with ARP, so resolved entries
+//! expire and are refreshed in exactly the same manner. Only the messages differ: solicitations
+//! for unresolved addresses are sent to the solicited-node multicast group of the target instead
+//! of the link broadcast.
+//!
+//! Solicitations for one of our addresses are answered with an advertisement carrying the hardware
+//! address of the interface. Advertisements update the entries that are being resolved or that
+//! changed their address, and confirm the reachability of a neighbor when they were solicited.
+//!
+//! Note that the ethernet endpoint must receive the solicited-node groups of the configured
+//! addresses, for example by joining the group returned by [`multicast_mac`] for each of them.
+//!
+//! ## Router advertisements
+//!
+//! Stateless address autoconfiguration ([RFC 4862]) is left to the user, with [`Autoconf`]
+//! wrapping an ip receiver to consume router advertisements. It reports the default routers and
+//! prefixes as events whose routes and address assignments can be applied to the ip endpoint.
+//! Addresses are formed from autonomous prefixes and the interface identifier of the host.
+//!
+//! [RFC 4861]: https://tools.ietf.org/html/rfc4861
+//! [RFC 4862]: https://tools.ietf.org/html/rfc4862
+//! [`multicast_mac`]: fn.multicast_mac.html
+//! [`Autoconf`]: struct.Autoconf.html
+use core::time::Duration;
+
+use crate::layer::{eth, ip, DropReason, Error, FnHandler, Result};
+use crate::time::{Expiration, Instant};
+use crate::wire::{ethernet, icmpv6, Payload, PayloadMut};
+use crate::wire::ip::{v6, Protocol as IpProtocol, Subnet as IpSubnet};
+
+/// Stateless address autoconfiguration from router advertisements.
+///
+/// Used as an ip receiver that wraps another handler of that layer, to which all packets other
+/// than router advertisements are passed. The advertisements are turned into events which must
+/// be retrieved with [`poll`]. Only a few events are queued, an event for the same router or
+/// prefix replaces an older one that was not yet retrieved. Events that find the queue full are
+/// lost and counted in [`overflowed`].
+///
+/// The valid lifetime of an address that is already assigned to the ip endpoint is only shortened
+/// to two hours at most, as specified in [RFC 4862] section 5.5.3 (e). An unauthenticated
+/// advertisement can thus not invalidate the address immediately.
+///
+/// Advertisements are sent to the all-nodes group, the ethernet endpoint must join the group
+/// `33:33:00:00:00:01` to receive them.
+///
+/// [`poll`]: #method.poll
+/// [`overflowed`]: #method.overflowed
+/// [RFC 4862]: https://tools.ietf.org/html/rfc4862
+pub struct Autoconf {
+    interface_id: v6::InterfaceId,
+    events: [Option<Event>; Autoconf::QUEUE_LEN],
+    overflowed: usize,
+}
+
+/// An event derived from a router advertisement.
+#[derive(Clone, Copy, Debug)]
+pub enum Event {
+    /// A router can be used as a default router.
+    ///
+    /// Contains the default route via the router, expiring with the router lifetime. A router
+    /// with a lifetime of zero is no longer a default router, its route then already expired and
+    /// should be removed.
+    Router(ip::Route),
+    /// A prefix was advertised.
+    Prefix {
+        /// The prefix as advertised.
+        info: icmpv6::PrefixInfo,
+        /// The address formed from the prefix, if it is autonomous.
+        ///
+        /// The assignment expires with the lifetimes of the prefix. It should replace an existing
+        /// assignment of the same address to extend its lifetimes.
+        address: Option<ip::Assignment>,
+    },
+}
+
+/// An autoconfiguration borrowed for receiving.
+pub struct Receiver<'a, H=NoHandler> {
+    autoconf: &'a mut Autoconf,
+
+    /// The receiver for any other packets.
+    handler: Option<H>,
+}
+
+/// The default handler type when none has been configured.
+///
+/// No instance can be created, as with the other layers.
+pub struct NoHandler { _private: Empty, }
+
+/// An empty enum to prove that there is no instance of `NoHandler`.
+enum Empty { }
+
+impl Autoconf {
+    /// The number of events that can be queued.
+    const QUEUE_LEN: usize = 4;
+
+    /// The lifetime to which an advertisement may shorten the lifetime of an address.
+    const MIN_VALID_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+
+    /// Create an autoconfiguration for an interface identifier.
+    ///
+    /// The identifier is usually derived from the hardware address of the interface with
+    /// [`InterfaceId::from_vendor_ether`].
+    ///
+    /// [`InterfaceId::from_vendor_ether`]: ../../../wire/ip/v6/struct.InterfaceId.html#method.from_vendor_ether
+    pub fn new(interface_id: v6::InterfaceId) -> Self {
+        Autoconf {
+            interface_id,
+            events: [None; Autoconf::QUEUE_LEN],
+            overflowed: 0,
+        }
+    }
+
+    /// The interface identifier used to form addresses.
+    pub fn interface_id(&self) -> v6::InterfaceId {
+        self.interface_id
+    }
+
+    /// The number of events that were lost because the queue was full.
+    pub fn overflowed(&self) -> usize {
+        self.overflowed
+    }
+
+    /// Retrieve the oldest pending event.
+    pub fn poll(&mut self) -> Option<Event> {
+        let event = self.events[0].take();
+        self.events.rotate_left(1);
+        event
+    }
+
+    /// A receiver that only consumes router advertisements.
+    pub fn answer(&mut self) -> Receiver<'_> {
+        Receiver { autoconf: self, handler: None, }
+    }
+
+    /// Receive packets, passing all other packets to a handler.
+    pub fn recv<H>(&mut self, handler: H) -> Receiver<'_, H> {
+        Receiver { autoconf: self, handler: Some(handler), }
+    }
+
+    /// Receive packets, passing all other packets to a function.
+    pub fn recv_with<H>(&mut self, handler: H) -> Receiver<'_, FnHandler<H>> {
+        self.recv(FnHandler(handler))
+    }
+
+    /// Derive the events of a router advertisement.
+    ///
+    /// The existing assignment of an address is looked up with `assigned`.
+    fn advertised(
+        &mut self,
+        router: v6::Address,
+        message: &icmpv6::packet,
+        now: Instant,
+        assigned: impl Fn(v6::Address) -> Option<ip::Assignment>,
+    ) {
+        let lifetime = Duration::from_secs(message.router_lifetime().into());
+        let mut route = ip::Route::new_ipv6_gateway(router);
+        route.expires_at = Expiration::When(now + lifetime);
+        self.push(Event::Router(route));
+
+        for info in message.prefixes() {
+            // The link-local prefix is never configured from advertisements.
+            if info.prefix.contains(v6::Address::from_link_local_id(self.interface_id)) {
+                continue;
+            }
+
+            if info.preferred_lifetime > info.valid_lifetime {
+                continue;
+            }
+
+            let address = Some(info)
+                .filter(|info| info.autonomous)
+                .and_then(|info| slaac_address(info.prefix, self.interface_id))
+                .map(|cidr| {
+                    let remaining = assigned(cidr.address())
+                        .filter(|assignment| assignment.is_valid(now))
+                        .map(|assignment| assignment.valid_until);
+                    ip::Assignment::with_lifetimes(
+                        cidr.into(),
+                        lifetime_expiration(info.preferred_lifetime, now),
+                        valid_expiration(info.valid_lifetime, remaining, now))
+                });
+
+            self.push(Event::Prefix { info, address });
+        }
+    }
+
+    /// Queue an event, replacing one about the same router or prefix.
+    fn push(&mut self, event: Event) {
+        let slot = self.events.iter()
+            .position(|pending| match (*pending, event) {
+                (None, _) => true,
+                (Some(Event::Router(old)), Event::Router(new)) => old.next_hop == new.next_hop,
+                (Some(Event::Prefix { info: old, .. }), Event::Prefix { info: new, .. })
+                    => old.prefix == new.prefix,
+                _ => false,
+            });
+
+        match slot {
+            Some(slot) => self.events[slot] = Some(event),
+            None => self.overflowed += 1,
+        }
+    }
+}
+
+/// Form the address of an interface in an advertised prefix.
+///
+/// Returns `None` unless the prefix has a length of 64 bits, as required for the interface
+/// identifiers of ethernet.
+pub fn slaac_address(prefix: v6::Subnet, id: v6::InterfaceId) -> Option<v6::Cidr> {
+    v6::Address::from_global_unicast_id(prefix, id)
+        .map(|addr| v6::Cidr::new(addr, prefix.prefix_len()))
+}
+
+/// Convert a lifetime in seconds, where all bits set represents infinity.
+fn lifetime_expiration(lifetime: u32, now: Instant) -> Expiration {
+    match lifetime {
+        u32::MAX => Expiration::Never,
+        secs => Expiration::When(now + Duration::from_secs(secs.into())),
+    }
+}
+
+/// The valid lifetime of an address for an advertised lifetime, see RFC4862 5.5.3 (e).
+///
+/// `remaining` is the expiration of the address if it is already assigned. An advertisement may
+/// extend it but only shorten it to two hours, unless it already ends sooner.
+fn valid_expiration(lifetime: u32, remaining: Option<Expiration>, now: Instant) -> Expiration {
+    let advertised = lifetime_expiration(lifetime, now);
+    let remaining = match remaining {
+        Some(remaining) => remaining,
+        None => return advertised,
+    };
+
+    let min_valid = Expiration::When(now + Autoconf::MIN_VALID_LIFETIME);
+    if advertised > min_valid || advertised > remaining {
+        advertised
+    } else if remaining <= min_valid {
+        remaining
+    } else {
+        min_valid
+    }
+}
+
+/// The solicited-node multicast address to which solicitations for an address are sent.
+///
+/// This is the group `ff02::1:ff00:0/104` combined with the low 24 bits of the address.
+pub fn solicited_node(addr: v6::Address) -> v6::Address {
+    addr.solicited_node_multicast()
+}
+
+/// The ethernet multicast address of an IPv6 multicast group.
+///
+/// The mapping of [RFC 2464], section 7, uses the low 32 bits of the group.
+///
+/// [RFC 2464]: https://tools.ietf.org/html/rfc2464
+pub fn multicast_mac(group: v6::Address) -> ethernet::Address {
+    ethernet::Address::from_multicast(group.into())
+        .expect("Solicitations are only sent to multicast groups")
+}
+
+/// Handle a received neighbor discovery message.
+///
+/// Messages that do not have the hop limit of the link, and were thus possibly forwarded by a
+/// router, are rejected with `Error::Illegal`. Each message that is not processed is reported
+/// with its drop reason, a failure to send the answer is only returned.
+pub(crate) fn receive<P: PayloadMut>(packet: ip::InPacket<P>, repr: icmpv6::Repr) -> Result<()> {
+    let ip::InPacket { mut control, packet } = packet;
+    let ip_repr = match packet {
+        ip::IpPacket::V6(ref packet) => packet.repr(),
+        ip::IpPacket::V4(_) => return Err(Error::Illegal),
+    };
+
+    // Messages that may have been forwarded by a router are not from the link, RFC4861 7.1.
+    if ip_repr.hop_limit != icmpv6::NDISC_HOP_LIMIT {
+        control.report_drop(DropReason::Filtered, packet.payload().as_slice());
+        return Err(Error::Illegal);
+    }
+
+    match repr {
+        icmpv6::Repr::NeighborSolicit { target_addr, lladdr } => {
+            if !is_assigned(&control, target_addr) {
+                return Ok(());
+            }
+
+            let src_addr = ip_repr.src_addr;
+            let (flags, dst_addr, dst_mac) = if src_addr.is_unspecified() {
+                // Duplicate address detection, the sender can not yet be addressed directly.
+                let dst_addr = v6::Address::LINK_LOCAL_ALL_NODES;
+                (icmpv6::NeighborFlags::OVERRIDE, dst_addr, multicast_mac(dst_addr))
+            } else {
+                control.learn(src_addr.into(), lladdr, false);
+                let dst_mac = match lladdr {
+                    Some(lladdr) => lladdr,
+                    None => match control.resolve(src_addr.into()) {
+                        Ok(dst_mac) => dst_mac,
+                        Err(err) => {
+                            let bytes = packet.payload().as_slice();
+                            control.report_drop(DropReason::Unresolved, bytes);
+                            return Err(err);
+                        },
+                    },
+                };
+                let flags = icmpv6::NeighborFlags::SOLICITED | icmpv6::NeighborFlags::OVERRIDE;
+                (flags, src_addr, dst_mac)
+            };
+
+            let frame = packet.into_inner();
+            let vlan = frame.repr().vlan;
+            let mut raw = eth::RawPacket { control: control.eth, payload: frame.into_inner() };
+            let src_mac = raw.control.src_addr();
+            let answer = icmpv6::Repr::NeighborAdvert {
+                flags,
+                target_addr,
+                lladdr: Some(src_mac),
+            };
+            send(raw, Message { src_addr: target_addr, dst_addr, dst_mac, vlan, repr: answer })
+        },
+        icmpv6::Repr::NeighborAdvert { flags, target_addr, lladdr } => {
+            // Only unsolicited advertisements may be sent to a multicast group.
+            if flags.solicited() && ip_repr.dst_addr.is_multicast() {
+                control.report_drop(DropReason::Malformed, packet.payload().as_slice());
+                return Err(Error::Illegal);
+            }
+
+            // Without the override flag an existing mapping is not replaced.
+            if flags.solicited() || flags.override_() {
+                control.learn(target_addr.into(), lladdr, flags.solicited());
+            }
+
+            Ok(())
+        },
+        _ => {
+            control.report_drop(DropReason::Unsupported, packet.payload().as_slice());
+            Err(Error::Illegal)
+        },
+    }
+}
+
+/// Send a neighbor solicitation for an address.
+///
+/// The solicitation is sent to the solicited-node multicast group of the target unless the
+/// hardware address of the neighbor is already known, as for probes of unreachability detection.
+pub(crate) fn solicit<P: PayloadMut>(
+    mut raw: eth::RawPacket<P>,
+    src_addr: v6::Address,
+    target_addr: v6::Address,
+    known: Option<ethernet::Address>,
+) -> Result<()> {
+    let src_mac = raw.control.src_addr();
+    let repr = icmpv6::Repr::NeighborSolicit {
+        target_addr,
+        lladdr: Some(src_mac),
+    };
+
+    let (dst_addr, dst_mac) = match known {
+        Some(dst_mac) => (target_addr, dst_mac),
+        None => {
+            let group = solicited_node(target_addr);
+            (group, multicast_mac(group))
+        },
+    };
+
+    send(raw, Message { src_addr, dst_addr, dst_mac, vlan: None, repr })
+}
+
+/// Send an unsolicited advertisement for one of our addresses to all nodes.
+pub(crate) fn advertise<P: PayloadMut>(mut raw: eth::RawPacket<P>, addr: v6::Address)
+    -> Result<()>
+{
+    let src_mac = raw.control.src_addr();
+    let dst_addr = v6::Address::LINK_LOCAL_ALL_NODES;
+    let repr = icmpv6::Repr::NeighborAdvert {
+        flags: icmpv6::NeighborFlags::OVERRIDE,
+        target_addr: addr,
+        lladdr: Some(src_mac),
+    };
+
+    send(raw, Message { src_addr: addr, dst_addr, dst_mac: multicast_mac(dst_addr), vlan: None, repr })
+}
+
+/// Check if an address is assigned to the ip endpoint.
+pub(crate) fn is_assigned(control: &ip::Controller, addr: v6::Address) -> bool {
+    let subnet = IpSubnet::from(v6::Cidr::new(addr, 128).subnet());
+    control.local_ip(subnet) == Some(addr.into())
+}
+
+/// A neighbor discovery message and its addressing.
+struct Message {
+    src_addr: v6::Address,
+    dst_addr: v6::Address,
+    dst_mac: ethernet::Address,
+    vlan: Option<ethernet::VlanTag>,
+    repr: icmpv6::Repr,
+}
+
+fn send<P: PayloadMut>(mut raw: eth::RawPacket<P>, message: Message) -> Result<()> {
+    let Message { src_addr, dst_addr, dst_mac, vlan, repr } = message;
+    let ip_repr = v6::Repr {
+        src_addr,
+        dst_addr,
+        next_header: IpProtocol::Icmpv6,
+        payload_len: repr.buffer_len(),
+        hop_limit: icmpv6::NDISC_HOP_LIMIT,
+    };
+
+    let src_mac = raw.control.src_addr();
+    let mut out = raw.prepare(eth::Init {
+        src_addr: src_mac,
+        dst_addr: dst_mac,
+        ethertype: ethernet::EtherType::Ipv6,
+        vlan,
+        payload: ip_repr.buffer_len() + repr.buffer_len(),
+    })?;
+
+    let packet = v6::packet::new_unchecked_mut(out.payload_mut_slice());
+    ip_repr.emit(packet);
+    let checksum = icmpv6::Checksum::Manual { src_addr, dst_addr };
+    repr.emit(icmpv6::packet::new_unchecked_mut(packet.payload_mut_slice()), checksum);
+    out.send()
+}
+
+impl<P, H> ip::Recv<P> for Receiver<'_, H>
+where
+    P: PayloadMut,
+    H: ip::Recv<P>,
+{
+    fn receive(&mut self, packet: ip::InPacket<P>) {
+        let advert = match &packet.packet {
+            ip::IpPacket::V6(packet) => router_advert(packet),
+            ip::IpPacket::V4(_) => None,
+        };
+
+        let ip::InPacket { mut control, packet } = match (advert, self.handler.as_mut()) {
+            (Some(router), _) => {
+                let ip::InPacket { mut control, packet } = packet;
+                let message = icmpv6::packet::new_unchecked(packet.payload().as_slice());
+                // Routers are neighbors, remember their address if we are looking for it.
+                if let Ok(lladdr @ Some(_)) = message.lladdr() {
+                    control.learn(router.into(), lladdr, false);
+                }
+                let now = control.info().timestamp();
+                let assigned = |addr: v6::Address| control.assignment(addr.into());
+                return self.autoconf.advertised(router, message, now, assigned);
+            },
+            (None, Some(handler)) => return handler.receive(packet),
+            (None, None) => packet,
+        };
+
+        control.report_drop(DropReason::Unsupported, packet.payload().as_slice())
+    }
+}
+
+/// Check if a packet is a valid router advertisement, returning the router.
+///
+/// See [RFC 4861], section 6.1.2.
+///
+/// [RFC 4861]: https://tools.ietf.org/html/rfc4861
+fn router_advert<P: Payload>(packet: &ip::V6Packet<P>) -> Option<v6::Address> {
+    let repr = packet.repr();
+    if repr.next_header != IpProtocol::Icmpv6
+        || repr.hop_limit != icmpv6::NDISC_HOP_LIMIT
+        || !repr.src_addr.is_link_local()
+    {
+        return None;
+    }
+
+    let message = icmpv6::packet::new_checked(packet.payload_slice()).ok()?;
+    let checksum = icmpv6::Checksum::Manual { src_addr: repr.src_addr, dst_addr: repr.dst_addr };
+    match icmpv6::Repr::parse(message, checksum) {
+        Ok(icmpv6::Repr::RouterAdvert { .. }) => Some(repr.src_addr),
+        _ => None,
+    }
+}
+
+impl<P: Payload> ip::Recv<P> for NoHandler {
+    fn receive(&mut self, _: ip::InPacket<P>) {
+        match self._private { }
+    }
+}
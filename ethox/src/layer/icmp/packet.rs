@@ -1,7 +1,10 @@
 use crate::nic::Info;
-use crate::layer::{Error, Result, ip};
+use crate::layer::{Error, Result, eth, ip};
+use crate::time::Instant;
 use crate::wire::{icmpv4, Checksum, Payload, PayloadMut};
-use crate::wire::ip::{Address as IpAddress, Protocol as IpProtocol};
+use crate::wire::ip::{v6, Address as IpAddress, Protocol as IpProtocol};
+
+use super::ndp;
 
 /// An incoming packet.
 ///
@@ -23,6 +26,8 @@ pub struct In<'a, P: Payload> {
 pub struct Out<'a, P: Payload> {
     control: Controller<'a>,
     packet: icmpv4::Packet<ip::V4Packet<'a, P>>,
+    /// The checksum already matches the message and need not be filled on `send`.
+    checksum_current: bool,
 }
 
 /// A buffer into which a packet can be placed.
@@ -70,6 +75,100 @@ pub enum Init {
     },
 }
 
+/// A known pattern in the payload of echo requests.
+///
+/// The remote echoes the payload unchanged so a reply can be checked against the pattern it was
+/// sent with. This detects replies corrupted on the link or spoofed by a party that only guessed
+/// the identifier and sequence number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EchoPattern<'p> {
+    /// Repeat the bytes over the whole payload.
+    Repeat(&'p [u8]),
+    /// Lead with the time of sending, repeat the bytes over the rest of the payload.
+    ///
+    /// The timestamp is encoded as big-endian milliseconds in the first 8 bytes. It is omitted if
+    /// the payload is too short to hold it.
+    Timestamp(&'p [u8]),
+}
+
+/// The result of checking the payload of an echo reply against an [`EchoPattern`].
+///
+/// Note that a missing reply is not represented. It can only be detected by the sender waiting
+/// for some timeout.
+///
+/// [`EchoPattern`]: enum.EchoPattern.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EchoStatus {
+    /// The payload matched the pattern.
+    Intact {
+        /// The time at which the request was sent, if it was embedded.
+        sent: Option<Instant>,
+    },
+    /// The payload differed from the pattern.
+    Corrupt,
+}
+
+impl EchoPattern<'_> {
+    const TIMESTAMP_LEN: usize = 8;
+
+    /// Fill an echo payload with the pattern.
+    pub fn fill(&self, payload: &mut [u8], now: Instant) {
+        let (bytes, rest) = self.split_mut(payload);
+        if let Some(timestamp) = rest {
+            timestamp.copy_from_slice(&now.total_millis().to_be_bytes());
+        }
+
+        let fill = self.fill_bytes();
+        if fill.is_empty() {
+            return;
+        }
+
+        for (byte, pattern) in bytes.iter_mut().zip(fill.iter().cycle()) {
+            *byte = *pattern;
+        }
+    }
+
+    /// Check an echoed payload against the pattern.
+    pub fn verify(&self, payload: &[u8]) -> EchoStatus {
+        let (bytes, sent) = match *self {
+            EchoPattern::Timestamp(_) if payload.len() >= Self::TIMESTAMP_LEN => {
+                let (timestamp, bytes) = payload.split_at(Self::TIMESTAMP_LEN);
+                let mut millis = [0; Self::TIMESTAMP_LEN];
+                millis.copy_from_slice(timestamp);
+                (bytes, Some(Instant::from_millis(i64::from_be_bytes(millis))))
+            },
+            _ => (payload, None),
+        };
+
+        let fill = self.fill_bytes();
+        let intact = fill.is_empty()
+            || bytes.iter().zip(fill.iter().cycle()).all(|(byte, pattern)| byte == pattern);
+
+        if intact {
+            EchoStatus::Intact { sent }
+        } else {
+            EchoStatus::Corrupt
+        }
+    }
+
+    fn fill_bytes(&self) -> &[u8] {
+        match *self {
+            EchoPattern::Repeat(bytes) | EchoPattern::Timestamp(bytes) => bytes,
+        }
+    }
+
+    /// Split into the repeated portion and the timestamp, if any.
+    fn split_mut<'b>(&self, payload: &'b mut [u8]) -> (&'b mut [u8], Option<&'b mut [u8]>) {
+        match self {
+            EchoPattern::Timestamp(_) if payload.len() >= Self::TIMESTAMP_LEN => {
+                let (timestamp, bytes) = payload.split_at_mut(Self::TIMESTAMP_LEN);
+                (bytes, Some(timestamp))
+            },
+            _ => (payload, None),
+        }
+    }
+}
+
 impl<'a> Controller<'a> {
     /// Get the hardware info for that packet.
     pub fn info(&self) -> &dyn Info {
@@ -96,8 +195,23 @@ impl<'a, P: Payload> In<'a, P> {
     }
 }
 
+impl<'a, P: Payload> In<'a, P> {
+    /// Check the payload of an echo reply against the pattern of the request.
+    ///
+    /// Returns `None` if the packet is not an echo reply.
+    pub fn verify_echo(&self, pattern: &EchoPattern) -> Option<EchoStatus> {
+        match self.packet.repr() {
+            icmpv4::Repr::EchoReply { .. } => Some(pattern.verify(self.packet.payload_slice())),
+            _ => None,
+        }
+    }
+}
+
 impl<'a, P: PayloadMut> In<'a, P> {
     /// Try to answer an icmp ping request in-place.
+    ///
+    /// The reply omits any ip options of the request. Returns `Err(BadSize)` without modifying
+    /// the request if the buffer can not be resized to the shorter reply.
     pub fn answer(self) -> Result<Out<'a, P>> {
         let answer = match self.packet.repr() {
             icmpv4::Repr::EchoRequest { ident, seq_no, payload } => {
@@ -109,6 +223,9 @@ impl<'a, P: PayloadMut> In<'a, P> {
         // Try to reverse the ip packet.
         let ipv4_packet = self.packet.into_inner();
         let ip_repr = ipv4_packet.repr();
+        // Without ip options the message stays in place. Then only its type changes and the
+        // checksum of the request can be updated instead of recomputed over the whole payload.
+        let in_place = usize::from(ipv4_packet.header_len()) == ip_repr.buffer_len();
         let ip_in = ip::InPacket {
             control: self.control.inner,
             packet: ip::IpPacket::V4(ipv4_packet),
@@ -120,13 +237,18 @@ impl<'a, P: PayloadMut> In<'a, P> {
             dst_addr: ip_repr.src_addr.into(),
             protocol: IpProtocol::Icmp,
             payload: ip_repr.payload_len,
+            hop_limit: None,
+            traffic_class: 0,
         })?;
 
         // Temporarily take the packet apart for inner repr.
         let ip::InPacket { control, mut packet } = ip_out.into_incoming();
-        answer.emit(
-            icmpv4::packet::new_unchecked_mut(packet.payload_mut().as_mut_slice()),
-            Checksum::Manual);
+        let message = icmpv4::packet::new_unchecked_mut(packet.payload_mut().as_mut_slice());
+        if in_place {
+            message.update_msg_type(icmpv4::Message::EchoReply, 0);
+        } else {
+            answer.emit(message, Checksum::Ignored);
+        }
         let packet = match packet {
             ip::IpPacket::V4(packet) => packet,
             ip::IpPacket::V6(_) => unreachable!("No icmpv6 outgoing traffic"),
@@ -135,6 +257,7 @@ impl<'a, P: PayloadMut> In<'a, P> {
         Ok(Out {
             control: Controller { inner: control },
             packet: icmpv4::Packet::new_unchecked(packet, answer),
+            checksum_current: in_place,
         })
     }
 }
@@ -142,12 +265,17 @@ impl<'a, P: PayloadMut> In<'a, P> {
 
 impl<'a, P: Payload> Out<'a, P> {
     /// Called last after having initialized the payload.
+    ///
+    /// An answer prepared in place already carries an updated checksum. It is only recomputed if
+    /// the payload was accessed mutably afterwards.
     pub fn send(mut self) -> Result<()>
         where P: PayloadMut,
     {
-        let capabilities = self.control.info().capabilities();
-        let checksum = capabilities.icmpv4().tx_checksum();
-        self.packet.fill_checksum(checksum);
+        if !self.checksum_current {
+            let capabilities = self.control.info().capabilities();
+            let checksum = capabilities.icmpv4().tx_checksum();
+            self.packet.fill_checksum(checksum);
+        }
         let lower = ip::OutPacket::new_unchecked(
             self.control.inner,
             ip::IpPacket::V4(self.packet.into_inner()));
@@ -163,6 +291,7 @@ impl<'a, P: PayloadMut> Out<'a, P> {
     ///
     /// This function will also work for ICMPv6 (currently unimplemented).
     pub fn payload_mut_slice(&mut self) -> &mut [u8] {
+        self.checksum_current = false;
         self.packet.payload_mut_slice()
     }
 }
@@ -188,8 +317,26 @@ impl<'a, P: Payload + PayloadMut> Raw<'a, P> {
         Ok(Out {
             control: Controller { inner: control },
             packet: icmpv4::Packet::new_unchecked(packet, repr),
+            checksum_current: false,
         })
     }
+
+    /// Send an unsolicited neighbor advertisement for one of our IPv6 addresses.
+    ///
+    /// The advertisement is sent to all nodes on the link and announces the hardware address of
+    /// the interface with the override flag set, so that neighbors replace existing cache entries.
+    /// This is the IPv6 equivalent of a gratuitous ARP, useful after an address moved to this host
+    /// during a failover. It is not a response to a solicitation and repeating it is harmless.
+    ///
+    /// Returns `Err(Error::Illegal)` if the address is not assigned to the ip endpoint.
+    pub fn advertise_neighbor(self, addr: v6::Address) -> Result<()> {
+        let Raw { control, payload } = self;
+        if !ndp::is_assigned(&control.inner, addr) {
+            return Err(Error::Illegal);
+        }
+
+        ndp::advertise(eth::RawPacket { control: control.inner.eth, payload }, addr)
+    }
 }
 
 impl Init {
@@ -223,6 +370,8 @@ impl Init {
                     dst_addr,
                     protocol: IpProtocol::Icmp,
                     payload: len,
+                    hop_limit: None,
+                    traffic_class: 0,
                 }
             },
         })
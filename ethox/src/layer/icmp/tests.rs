@@ -1,7 +1,8 @@
 use crate::managed::Slice;
-use crate::nic::{loopback::Loopback, Device};
-use crate::layer::{arp, eth, ip, icmp};
-use crate::wire::{ethernet::Address, ip::Cidr, ip::v4, PayloadMut};
+use crate::nic::{external::External, loopback::Loopback, Device};
+use crate::layer::{arp, eth, ip, icmp, DropReason, FnHandler};
+use crate::wire::{ethernet, ethernet::Address, icmpv4, icmpv6, ip::Cidr, ip::v4, ip::v6};
+use crate::wire::PayloadMut;
 
 const MAC_ADDR_HOST: Address = Address([0, 1, 2, 3, 4, 5]);
 const IP_ADDR_HOST: v4::Address = v4::Address::new(127, 0, 0, 1);
@@ -19,6 +20,23 @@ static PING_BYTES: [u8; 50] =
         0x00, 0xff
     ];
 
+/// An ip endpoint which already knows the link layer addresses of its `neighbors`.
+///
+/// The neighbor cache has room for one more entry.
+fn endpoint<'a, A>(
+    addresses: A,
+    routes: ip::Routes<'a>,
+    neighbors: &[(crate::wire::ip::Address, Address)],
+) -> ip::Endpoint<'a>
+    where A: Into<Slice<'a, ip::Assignment>>,
+{
+    let mut cache = arp::NeighborCache::new(vec![arp::Neighbor::default(); neighbors.len() + 1]);
+    for &(ip_addr, mac_addr) in neighbors {
+        cache.fill(ip_addr, mac_addr, None).unwrap();
+    }
+    ip::Endpoint::new(addresses, routes, cache)
+}
+
 #[test]
 fn answer_ping() {
     let mut nic = Loopback::<Vec<u8>>::new(vec![0; 1 << 12].into());
@@ -47,7 +65,882 @@ fn answer_ping() {
    assert_eq!(recv, Ok(1));
 }
 
-fn queue_ping(nic: &mut Loopback<Vec<u8>>) {
+#[test]
+fn unhandled_ping() {
+    let mut nic = Loopback::<Vec<u8>>::new(vec![0; 1 << 12].into());
+
+    queue_ping(&mut nic);
+
+    let dropped = core::cell::Cell::new(None);
+    let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_drop_hook(&mut hook);
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+
+    // Requests are passed upwards but there is no handler.
+    let mut icmp = icmp::Endpoint::new();
+    icmp.manual(true);
+
+    let recv = nic.rx(1, eth.recv(ip.recv(
+        icmp.answer())));
+
+    assert_eq!(recv, Ok(1));
+    assert_eq!(dropped.get(), Some(DropReason::Unsupported));
+}
+
+#[test]
+fn answer_in_place() {
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+    queue_ping(&mut nic);
+    nic.receive_all();
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+
+    let mut icmp = icmp::Endpoint::new();
+
+    let recv = nic.rx(1, eth.recv(ip.recv(
+        icmp.answer())));
+    assert_eq!(recv, Ok(1));
+
+    // The received buffer itself is now queued, containing the reply.
+    assert_eq!(nic.to_recv(), 0);
+    assert_eq!(nic.queued().len(), 1);
+    let frame = ethernet::frame::new_checked(&nic.queued()[0][..]).unwrap();
+    assert_eq!(frame.dst_addr(), MAC_ADDR_OTHER);
+    let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+    assert_eq!(packet.dst_addr(), IP_ADDR_OTHER);
+    let icmp = icmpv4::packet::new_checked(packet.payload_slice()).unwrap();
+    assert_eq!(icmp.msg_type(), icmpv4::Message::EchoReply);
+    assert_eq!(icmp.payload_slice(), &PING_BYTES[..]);
+    assert!(icmp.verify_checksum());
+}
+
+#[test]
+fn echo_responder() {
+    fn receive(nic: &mut External<Slice<Vec<u8>>>) -> Option<icmpv4::Message> {
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+        let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+            ip::Routes::new(Slice::empty()),
+            &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+
+        let mut icmp = icmp::Endpoint::new();
+        icmp.manual(true);
+
+        let mut unhandled = None;
+        let responder = icmp::EchoResponder::new(FnHandler(|packet: icmp::InPacket<_>| {
+            unhandled = Some(packet.packet.msg_type());
+        }));
+        let recv = nic.rx(1, eth.recv(ip.recv(icmp.recv(responder))));
+        assert_eq!(recv, Ok(1));
+        unhandled
+    }
+
+    // Echo requests are answered in place.
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+    queue_ping(&mut nic);
+    nic.receive_all();
+
+    assert_eq!(receive(&mut nic), None);
+    assert_eq!(nic.queued().len(), 1);
+    let frame = ethernet::frame::new_checked(&nic.queued()[0][..]).unwrap();
+    assert_eq!(frame.dst_addr(), MAC_ADDR_OTHER);
+    let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+    assert_eq!(packet.src_addr(), IP_ADDR_HOST);
+    assert_eq!(packet.dst_addr(), IP_ADDR_OTHER);
+    let icmp = icmpv4::packet::new_checked(packet.payload_slice()).unwrap();
+    assert_eq!(icmp.msg_type(), icmpv4::Message::EchoReply);
+    assert_eq!(icmp.echo_ident(), 0);
+    assert_eq!(icmp.echo_seq_no(), 0);
+    assert_eq!(icmp.payload_slice(), &PING_BYTES[..]);
+    assert!(icmp.verify_checksum());
+
+    // Other messages are passed to the inner handler.
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+    queue_ping(&mut nic);
+    {
+        let frame = ethernet::frame::new_unchecked_mut(nic.get_mut(0).unwrap());
+        let packet = v4::packet::new_unchecked_mut(frame.payload_mut_slice());
+        icmpv4::packet::new_unchecked_mut(packet.payload_mut_slice())
+            .update_msg_type(icmpv4::Message::EchoReply, 0);
+    }
+    nic.receive_all();
+
+    assert_eq!(receive(&mut nic), Some(icmpv4::Message::EchoReply));
+    assert_eq!(nic.queued().len(), 0);
+}
+
+#[test]
+fn echo_responder_v6() {
+    fn receive(frame: Vec<u8>, manual: bool) -> Vec<Vec<u8>> {
+        let mut groups = [Address([0; 6]); 1];
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        eth.set_multicast_storage(&mut groups[..]);
+        eth.join_multicast(icmp::ndp::multicast_mac(v6::Address::LINK_LOCAL_ALL_NODES)).unwrap();
+        let mut ip = endpoint(Cidr::new(IP6_ADDR_HOST.into(), 64),
+            ip::Routes::new(Slice::empty()),
+            &[(IP6_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+        let mut icmp = icmp::Endpoint::new();
+        icmp.manual(manual);
+
+        let mut nic = External::new_send(Slice::One(frame));
+        nic.receive_all();
+        let recv = if manual {
+            let responder = icmp::EchoResponder::new(icmp.answer());
+            nic.rx(1, eth.recv(ip.recv(responder)))
+        } else {
+            nic.rx(1, eth.recv(ip.recv(icmp.answer())))
+        };
+        assert_eq!(recv, Ok(1));
+        nic.queued().to_vec()
+    }
+
+    let request = |dst_mac, dst_addr| {
+        let repr = icmpv6::Repr::EchoRequest { ident: 0x1234, seq_no: 7, payload: 4 };
+        let mut message = vec![0; repr.buffer_len()];
+        let checksum = icmpv6::Checksum::Manual { src_addr: IP6_ADDR_OTHER, dst_addr };
+        let packet = icmpv6::packet::new_unchecked_mut(&mut message);
+        packet.payload_mut_slice().copy_from_slice(b"ping");
+        repr.emit(packet, checksum);
+        icmpv6_frame(dst_mac, dst_addr, &message)
+    };
+
+    let all_nodes = v6::Address::LINK_LOCAL_ALL_NODES;
+    let all_nodes_mac = Address([0x33, 0x33, 0, 0, 0, 1]);
+    for &manual in &[false, true] {
+        for &(dst_mac, dst_addr) in &[(MAC_ADDR_HOST, IP6_ADDR_HOST), (all_nodes_mac, all_nodes)] {
+            let queued = receive(request(dst_mac, dst_addr), manual);
+            assert_eq!(queued.len(), 1);
+            let frame = ethernet::frame::new_checked(&queued[0][..]).unwrap();
+            assert_eq!(frame.dst_addr(), MAC_ADDR_OTHER);
+            let packet = v6::packet::new_checked(frame.payload_slice()).unwrap();
+            // Requests to the group are answered from our own address.
+            assert_eq!(packet.src_addr(), IP6_ADDR_HOST);
+            assert_eq!(packet.dst_addr(), IP6_ADDR_OTHER);
+            let checksum = icmpv6::Checksum::Manual {
+                src_addr: IP6_ADDR_HOST,
+                dst_addr: IP6_ADDR_OTHER,
+            };
+            let message = icmpv6::packet::new_checked(packet.payload_slice()).unwrap();
+            assert_eq!(icmpv6::Repr::parse(message, checksum),
+                Ok(icmpv6::Repr::EchoReply { ident: 0x1234, seq_no: 7, payload: 4 }));
+            assert_eq!(message.payload_slice(), b"ping");
+        }
+    }
+}
+
+/// Answer a ping in place, corrupting the checksum of the request after it was verified.
+///
+/// Returns whether the checksum of the reply is correct.
+fn answer_corrupted(touch_payload: bool) -> bool {
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+    queue_ping(&mut nic);
+    nic.receive_all();
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+
+    let mut icmp = icmp::Endpoint::new();
+    icmp.manual(true);
+
+    let recv = nic.rx(1, eth.recv(ip.recv(icmp.recv_with(|packet: icmp::InPacket<_>| {
+        let icmp::InPacket { control, packet } = packet;
+        let repr = packet.repr();
+        let mut ip = packet.into_inner();
+        let request = icmpv4::packet::new_unchecked_mut(ip.payload_mut().as_mut_slice());
+        request.set_checksum(request.checksum() ^ 0x0100);
+
+        let packet = icmp::InPacket {
+            control,
+            packet: icmpv4::Packet::new_unchecked(ip, repr),
+        };
+        let mut answer = packet.answer().expect("Can answer in place");
+        if touch_payload {
+            answer.payload_mut_slice();
+        }
+        answer.send().expect("Can send the answer");
+    }))));
+    assert_eq!(recv, Ok(1));
+
+    let frame = ethernet::frame::new_checked(&nic.queued()[0][..]).unwrap();
+    let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+    let icmp = icmpv4::packet::new_checked(packet.payload_slice()).unwrap();
+    assert_eq!(icmp.msg_type(), icmpv4::Message::EchoReply);
+    assert_eq!(icmp.payload_slice(), &PING_BYTES[..]);
+    icmp.verify_checksum()
+}
+
+#[test]
+fn answer_fixed_buffer() {
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+    queue_ping(&mut nic);
+
+    // Add an option to the request which the reply does not repeat, so the reply is shorter.
+    let mut original = nic.get(0).unwrap().clone();
+    original.resize(original.len() + 4, 0);
+    {
+        let eth = ethernet::frame::new_unchecked_mut(&mut original);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        let len = usize::from(ip.total_len());
+        let bytes = ip.as_bytes_mut();
+        bytes.copy_within(20..len, 24);
+        bytes[20..24].copy_from_slice(&[0x01, 0x01, 0x01, 0x00]);
+        ip.set_header_len(24);
+        ip.set_total_len(len as u16 + 4);
+        ip.fill_checksum();
+    }
+
+    // A receive buffer that fits the request exactly and can not be resized for the reply.
+    let mut fixed = original.clone();
+    let mut nic = External::new_send(vec![&mut fixed[..]]);
+    nic.receive_all();
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+
+    let mut icmp = icmp::Endpoint::new();
+    icmp.manual(true);
+
+    let recv = nic.rx(1, eth.recv(ip.recv(icmp.recv_with(|packet: icmp::InPacket<_>| {
+        assert!(matches!(packet.answer(), Err(crate::layer::Error::BadSize)));
+    }))));
+    assert_eq!(recv, Ok(1));
+
+    // Nothing was sent and the request is unchanged.
+    assert_eq!(nic.queued().len(), 0);
+    drop(nic);
+    assert_eq!(fixed, original);
+}
+
+#[test]
+fn answer_updates_checksum() {
+    // The reply inherits the corruption, so the message was not summed again.
+    assert!(!answer_corrupted(false));
+    // Access to the payload forces a full recomputation.
+    assert!(answer_corrupted(true));
+}
+
+#[test]
+fn gratuitous_neighbor_advert() {
+    let addr = v6::Address::new(0xfe80, 0, 0, 0, 0x0201, 0x02ff, 0xfe03, 0x0405);
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut ip = endpoint(Cidr::new(addr.into(), 64), ip::Routes::new(Slice::empty()), &[]);
+    let mut icmp = icmp::Endpoint::new();
+
+    // Only our own addresses can be announced.
+    let other = v6::Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+    let sent = nic.tx(1, eth.send(ip.send(icmp.send_with(|raw: icmp::RawPacket<_>| {
+        assert!(raw.advertise_neighbor(other).is_err());
+    }))));
+    assert_eq!(sent, Ok(0));
+
+    let sent = nic.tx(1, eth.send(ip.send(icmp.send_with(|raw: icmp::RawPacket<_>| {
+        raw.advertise_neighbor(addr).expect("Can announce an own address");
+    }))));
+    assert_eq!(sent, Ok(1));
+
+    let frame = ethernet::frame::new_checked(&nic.queued()[0][..]).unwrap();
+    assert_eq!(frame.src_addr(), MAC_ADDR_HOST);
+    assert_eq!(frame.dst_addr(), Address([0x33, 0x33, 0, 0, 0, 1]));
+    assert_eq!(frame.ethertype(), ethernet::EtherType::Ipv6);
+    let packet = v6::packet::new_checked(frame.payload_slice()).unwrap();
+    assert_eq!(packet.src_addr(), addr);
+    assert_eq!(packet.dst_addr(), v6::Address::LINK_LOCAL_ALL_NODES);
+    assert_eq!(packet.hop_limit(), icmpv6::NDISC_HOP_LIMIT);
+    let advert = icmpv6::packet::new_checked(packet.payload_slice()).unwrap();
+    assert!(advert.verify_checksum(addr, v6::Address::LINK_LOCAL_ALL_NODES));
+    assert_eq!(advert.msg_type(), icmpv6::Message::NeighborAdvert);
+    assert!(advert.neighbor_flags().override_());
+    assert!(!advert.neighbor_flags().solicited());
+    assert_eq!(advert.target_addr(), addr);
+    assert_eq!(advert.lladdr(), Ok(Some(MAC_ADDR_HOST)));
+}
+
+const IP6_ADDR_HOST: v6::Address = v6::Address(
+    [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x02, 0x01, 0x02, 0xff, 0xfe, 0x03, 0x04, 0x05]);
+const IP6_ADDR_OTHER: v6::Address = v6::Address(
+    [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+/// Build a neighbor discovery message sent by the other host.
+fn ndisc_frame(dst_mac: Address, dst_addr: v6::Address, repr: icmpv6::Repr) -> Vec<u8> {
+    let mut message = vec![0; repr.buffer_len()];
+    let checksum = icmpv6::Checksum::Manual { src_addr: IP6_ADDR_OTHER, dst_addr };
+    repr.emit(icmpv6::packet::new_unchecked_mut(&mut message), checksum);
+    icmpv6_frame(dst_mac, dst_addr, &message)
+}
+
+/// Wrap an icmpv6 message of the other host into a frame.
+fn icmpv6_frame(dst_mac: Address, dst_addr: v6::Address, message: &[u8]) -> Vec<u8> {
+    let ip_repr = v6::Repr {
+        src_addr: IP6_ADDR_OTHER,
+        dst_addr,
+        next_header: crate::wire::ip::Protocol::Icmpv6,
+        payload_len: message.len(),
+        hop_limit: icmpv6::NDISC_HOP_LIMIT,
+    };
+    let eth_repr = ethernet::Repr {
+        src_addr: MAC_ADDR_OTHER,
+        dst_addr: dst_mac,
+        ethertype: ethernet::EtherType::Ipv6,
+        vlan: None,
+    };
+
+    let mut buffer = vec![0; eth_repr.buffer_len(ip_repr.buffer_len() + message.len())];
+    let frame = ethernet::frame::new_unchecked_mut(&mut buffer);
+    eth_repr.emit(frame);
+    let packet = v6::packet::new_unchecked_mut(frame.payload_mut_slice());
+    ip_repr.emit(packet);
+    packet.payload_mut_slice().copy_from_slice(message);
+    buffer
+}
+
+#[test]
+fn answer_neighbor_solicit() {
+    let group = icmp::ndp::solicited_node(IP6_ADDR_HOST);
+    assert_eq!(group, v6::Address::new(0xff02, 0, 0, 0, 0, 1, 0xff03, 0x0405));
+    let group_mac = icmp::ndp::multicast_mac(group);
+    assert_eq!(group_mac, Address([0x33, 0x33, 0xff, 0x03, 0x04, 0x05]));
+
+    let mut groups = [Address([0; 6]); 1];
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_multicast_storage(&mut groups[..]);
+    eth.join_multicast(group_mac).unwrap();
+
+    let mut ip = endpoint(Cidr::new(IP6_ADDR_HOST.into(), 64),
+        ip::Routes::new(Slice::empty()),
+        &[]);
+    let mut icmp = icmp::Endpoint::new();
+
+    // Solicitations for other targets are not answered.
+    let solicit = icmpv6::Repr::NeighborSolicit {
+        target_addr: IP6_ADDR_OTHER,
+        lladdr: Some(MAC_ADDR_OTHER),
+    };
+    let mut nic = External::new_send(Slice::One(ndisc_frame(group_mac, group, solicit)));
+    nic.receive_all();
+    let recv = nic.rx(1, eth.recv(ip.recv(icmp.answer())));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(nic.queued().len(), 0);
+
+    let solicit = icmpv6::Repr::NeighborSolicit {
+        target_addr: IP6_ADDR_HOST,
+        lladdr: Some(MAC_ADDR_OTHER),
+    };
+    let mut nic = External::new_send(Slice::One(ndisc_frame(group_mac, group, solicit)));
+    nic.receive_all();
+    let recv = nic.rx(1, eth.recv(ip.recv(icmp.answer())));
+    assert_eq!(recv, Ok(1));
+
+    // The advertisement is sent directly to the soliciting host.
+    assert_eq!(nic.queued().len(), 1);
+    let frame = ethernet::frame::new_checked(&nic.queued()[0][..]).unwrap();
+    assert_eq!(frame.src_addr(), MAC_ADDR_HOST);
+    assert_eq!(frame.dst_addr(), MAC_ADDR_OTHER);
+    assert_eq!(frame.ethertype(), ethernet::EtherType::Ipv6);
+    let packet = v6::packet::new_checked(frame.payload_slice()).unwrap();
+    assert_eq!(packet.src_addr(), IP6_ADDR_HOST);
+    assert_eq!(packet.dst_addr(), IP6_ADDR_OTHER);
+    assert_eq!(packet.hop_limit(), icmpv6::NDISC_HOP_LIMIT);
+    let advert = icmpv6::packet::new_checked(packet.payload_slice()).unwrap();
+    assert!(advert.verify_checksum(IP6_ADDR_HOST, IP6_ADDR_OTHER));
+    assert_eq!(advert.msg_type(), icmpv6::Message::NeighborAdvert);
+    assert!(advert.neighbor_flags().solicited());
+    assert!(advert.neighbor_flags().override_());
+    assert_eq!(advert.target_addr(), IP6_ADDR_HOST);
+    assert_eq!(advert.lladdr(), Ok(Some(MAC_ADDR_HOST)));
+}
+
+#[test]
+fn reject_neighbor_discovery() {
+    use crate::layer::DropReason;
+
+    let dropped = core::cell::Cell::new(None);
+    let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+
+    let group = icmp::ndp::solicited_node(IP6_ADDR_HOST);
+    let group_mac = icmp::ndp::multicast_mac(group);
+    let mut groups = [Address([0; 6]); 1];
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_multicast_storage(&mut groups[..]);
+    eth.join_multicast(group_mac).unwrap();
+    eth.set_drop_hook(&mut hook);
+
+    let mut ip = endpoint(Cidr::new(IP6_ADDR_HOST.into(), 64),
+        ip::Routes::new(Slice::empty()),
+        &[]);
+    let mut icmp = icmp::Endpoint::new();
+
+    // A solicitation that was forwarded by a router.
+    let solicit = icmpv6::Repr::NeighborSolicit {
+        target_addr: IP6_ADDR_HOST,
+        lladdr: Some(MAC_ADDR_OTHER),
+    };
+    let mut frame = ndisc_frame(group_mac, group, solicit);
+    let packet = v6::packet::new_unchecked_mut(&mut frame[14..]);
+    packet.set_hop_limit(64);
+    let mut nic = External::new_send(Slice::One(frame));
+    nic.receive_all();
+    let recv = nic.rx(1, eth.recv(ip.recv(icmp.answer())));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(nic.queued().len(), 0);
+    assert_eq!(dropped.get(), Some(DropReason::Filtered));
+
+    // A solicited advertisement must not be sent to a group.
+    let advert = icmpv6::Repr::NeighborAdvert {
+        flags: icmpv6::NeighborFlags::SOLICITED | icmpv6::NeighborFlags::OVERRIDE,
+        target_addr: IP6_ADDR_OTHER,
+        lladdr: Some(MAC_ADDR_OTHER),
+    };
+    dropped.set(None);
+    let mut nic = External::new_recv(Slice::One(ndisc_frame(group_mac, group, advert)));
+    let recv = nic.rx(1, eth.recv(ip.recv(icmp.answer())));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(dropped.get(), Some(DropReason::Malformed));
+}
+
+#[test]
+fn resolve_neighbor() {
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut ip = endpoint(Cidr::new(IP6_ADDR_HOST.into(), 64),
+        ip::Routes::new(Slice::empty()),
+        &[]);
+    let mut icmp = icmp::Endpoint::new();
+
+    let init = ip::Init {
+        source: crate::wire::ip::Address::from(IP6_ADDR_HOST).into(),
+        dst_addr: IP6_ADDR_OTHER.into(),
+        protocol: crate::wire::ip::Protocol::Unknown(0xEF),
+        payload: 10,
+        hop_limit: None,
+        traffic_class: 0,
+    };
+
+    // The first attempt starts the lookup.
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+    let sent = nic.tx(1, eth.send(ip.send_with(|packet: ip::RawPacket<_>| {
+        assert!(packet.prepare(init).is_err());
+    })));
+    assert_eq!(sent, Ok(0));
+
+    // Which sends a solicitation to the solicited-node group of the neighbor.
+    let sent = nic.tx(1, eth.send(ip.send_with(|_: ip::RawPacket<_>| {
+        panic!("Solicitation should take precedence");
+    })));
+    assert_eq!(sent, Ok(1));
+
+    let group = icmp::ndp::solicited_node(IP6_ADDR_OTHER);
+    let frame = ethernet::frame::new_checked(&nic.queued()[0][..]).unwrap();
+    assert_eq!(frame.dst_addr(), Address([0x33, 0x33, 0xff, 0, 0, 1]));
+    let packet = v6::packet::new_checked(frame.payload_slice()).unwrap();
+    assert_eq!(packet.src_addr(), IP6_ADDR_HOST);
+    assert_eq!(packet.dst_addr(), group);
+    let solicit = icmpv6::packet::new_checked(packet.payload_slice()).unwrap();
+    assert!(solicit.verify_checksum(IP6_ADDR_HOST, group));
+    assert_eq!(solicit.msg_type(), icmpv6::Message::NeighborSolicit);
+    assert_eq!(solicit.target_addr(), IP6_ADDR_OTHER);
+    assert_eq!(solicit.lladdr(), Ok(Some(MAC_ADDR_HOST)));
+
+    // The solicited advertisement resolves the address.
+    let advert = icmpv6::Repr::NeighborAdvert {
+        flags: icmpv6::NeighborFlags::SOLICITED | icmpv6::NeighborFlags::OVERRIDE,
+        target_addr: IP6_ADDR_OTHER,
+        lladdr: Some(MAC_ADDR_OTHER),
+    };
+    let mut nic = External::new_recv(Slice::One(ndisc_frame(MAC_ADDR_HOST, IP6_ADDR_HOST, advert)));
+    let recv = nic.rx(1, eth.recv(ip.recv(icmp.answer())));
+    assert_eq!(recv, Ok(1));
+
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+    let sent = nic.tx(1, eth.send(ip.send_with(|packet: ip::RawPacket<_>| {
+        packet.prepare(init).unwrap().send().unwrap();
+    })));
+    assert_eq!(sent, Ok(1));
+    let frame = ethernet::frame::new_checked(&nic.queued()[0][..]).unwrap();
+    assert_eq!(frame.dst_addr(), MAC_ADDR_OTHER);
+}
+
+/// An advertisement of `fe80::1` to all nodes, for the prefix `2001:db8:1::/64`.
+static ROUTER_ADVERT_BYTES: [u8; 64] = [
+    0x86, 0x00, 0xa7, 0x1c, 0x40, 0x00, 0x07, 0x08,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x01, 0x52, 0x54, 0x00, 0x12, 0x34, 0x56,
+    0x05, 0x01, 0x00, 0x00, 0x00, 0x00, 0x05, 0xdc,
+    0x03, 0x04, 0x40, 0xc0, 0x00, 0x01, 0x51, 0x80,
+    0x00, 0x00, 0x38, 0x40, 0x00, 0x00, 0x00, 0x00,
+    0x20, 0x01, 0x0d, 0xb8, 0x00, 0x01, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+#[test]
+fn router_advert_autoconf() {
+    use crate::time::{Duration, Expiration, Instant};
+
+    let all_nodes = v6::Address::LINK_LOCAL_ALL_NODES;
+    let mut groups = [Address([0; 6]); 1];
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_multicast_storage(&mut groups[..]);
+    eth.join_multicast(icmp::ndp::multicast_mac(all_nodes)).unwrap();
+
+    let mut ip = endpoint(Cidr::new(IP6_ADDR_HOST.into(), 64),
+        ip::Routes::new(Slice::empty()),
+        &[]);
+    let mut autoconf = icmp::ndp::Autoconf::new(v6::InterfaceId::from_vendor_ether(MAC_ADDR_HOST));
+
+    let now = Instant::from_secs(100);
+    let frame = icmpv6_frame(Address([0x33, 0x33, 0, 0, 0, 1]), all_nodes, &ROUTER_ADVERT_BYTES);
+    let mut nic = External::new_recv(Slice::One(frame));
+    nic.set_current_time(now);
+    let recv = nic.rx(1, eth.recv(ip.recv(autoconf.recv_with(|_: ip::InPacket<_>| {
+        panic!("Advertisements are consumed");
+    }))));
+    assert_eq!(recv, Ok(1));
+
+    let route = match autoconf.poll() {
+        Some(icmp::ndp::Event::Router(route)) => route,
+        other => panic!("Expected the default router, got {:?}", other),
+    };
+    assert_eq!(route.next_hop, IP6_ADDR_OTHER.into());
+    assert_eq!(route.net, v6::Subnet::ANY.into());
+    assert_eq!(route.expires_at, Expiration::When(now + Duration::from_secs(1800)));
+
+    let prefix = v6::Cidr::new(v6::Address::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0), 64).subnet();
+    let (info, address) = match autoconf.poll() {
+        Some(icmp::ndp::Event::Prefix { info, address }) => (info, address),
+        other => panic!("Expected the prefix, got {:?}", other),
+    };
+    assert_eq!(info.prefix, prefix);
+    assert!(info.on_link && info.autonomous);
+    assert_eq!(info.valid_lifetime, 86400);
+    assert_eq!(info.preferred_lifetime, 14400);
+
+    // The address combines the prefix with our interface identifier.
+    let address = address.expect("Prefix is autonomous");
+    let slaac = v6::Address::new(0x2001, 0xdb8, 1, 0, 0x0201, 0x02ff, 0xfe03, 0x0405);
+    assert_eq!(address.cidr, Cidr::new(slaac.into(), 64));
+    assert_eq!(address.preferred_until, Expiration::When(now + Duration::from_secs(14400)));
+    assert_eq!(address.valid_until, Expiration::When(now + Duration::from_secs(86400)));
+    assert!(autoconf.poll().is_none());
+
+    // The route can be applied directly.
+    let mut routes = [ip::Route::unspecified(); 1];
+    let mut routes = ip::Routes::new(&mut routes[..]);
+    routes.add_route(route).unwrap();
+    let via = routes.lookup(slaac.into(), now).expect("Routes via the router");
+    assert_eq!(via.via_router, IP6_ADDR_OTHER.into());
+}
+
+/// The router advertisement with other lifetimes and a prefix `2001:db8:<net>::/64`.
+fn router_advert(valid: u32, preferred: u32, net: u16) -> Vec<u8> {
+    let mut message = ROUTER_ADVERT_BYTES.to_vec();
+    message[36..40].copy_from_slice(&valid.to_be_bytes());
+    message[40..44].copy_from_slice(&preferred.to_be_bytes());
+    message[52..54].copy_from_slice(&net.to_be_bytes());
+    let packet = icmpv6::packet::new_unchecked_mut(&mut message[..]);
+    packet.fill_checksum(IP6_ADDR_OTHER, v6::Address::LINK_LOCAL_ALL_NODES);
+    icmpv6_frame(Address([0x33, 0x33, 0, 0, 0, 1]), v6::Address::LINK_LOCAL_ALL_NODES, &message)
+}
+
+#[test]
+fn router_advert_lifetimes() {
+    use crate::time::{Duration, Expiration, Instant};
+
+    let mut groups = [Address([0; 6]); 1];
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_multicast_storage(&mut groups[..]);
+    eth.join_multicast(icmp::ndp::multicast_mac(v6::Address::LINK_LOCAL_ALL_NODES)).unwrap();
+
+    let slaac = v6::Address::new(0x2001, 0xdb8, 1, 0, 0x0201, 0x02ff, 0xfe03, 0x0405);
+    let mut addresses = [
+        ip::Assignment::new(Cidr::new(IP6_ADDR_HOST.into(), 64)),
+        ip::Assignment::new(Cidr::new(slaac.into(), 64)),
+    ];
+    let mut ip = endpoint(&mut addresses[..1], ip::Routes::new(Slice::empty()), &[]);
+    let mut autoconf = icmp::ndp::Autoconf::new(v6::InterfaceId::from_vendor_ether(MAC_ADDR_HOST));
+
+    let now = Instant::from_secs(100);
+    let mut advertise = |ip: &mut ip::Endpoint, valid, preferred| {
+        let mut nic = External::new_recv(Slice::One(router_advert(valid, preferred, 1)));
+        nic.set_current_time(now);
+        let recv = nic.rx(1, eth.recv(ip.recv(autoconf.answer())));
+        assert_eq!(recv, Ok(1));
+        assert!(matches!(autoconf.poll(), Some(icmp::ndp::Event::Router(_))));
+        match autoconf.poll() {
+            Some(icmp::ndp::Event::Prefix { address: Some(address), .. }) => address,
+            other => panic!("Expected the address, got {:?}", other),
+        }
+    };
+
+    // A new address takes the advertised lifetime.
+    let address = advertise(&mut ip, 60, 30);
+    assert_eq!(address.valid_until, Expiration::When(now + Duration::from_secs(60)));
+    drop(ip);
+
+    // An assigned address with a longer remaining lifetime is only shortened to two hours.
+    let two_hours = Expiration::When(now + Duration::from_secs(7200));
+    addresses[1].valid_until = Expiration::When(now + Duration::from_secs(86400));
+    let mut ip = endpoint(&mut addresses[..], ip::Routes::new(Slice::empty()), &[]);
+    let address = advertise(&mut ip, 60, 30);
+    assert_eq!(address.cidr, Cidr::new(slaac.into(), 64));
+    assert_eq!(address.preferred_until, Expiration::When(now + Duration::from_secs(30)));
+    assert_eq!(address.valid_until, two_hours);
+    // But always extended.
+    let address = advertise(&mut ip, 172800, 30);
+    assert_eq!(address.valid_until, Expiration::When(now + Duration::from_secs(172800)));
+
+    // A remaining lifetime of less than two hours is kept.
+    ip.addresses_mut()[1].valid_until = Expiration::When(now + Duration::from_secs(600));
+    let address = advertise(&mut ip, 60, 30);
+    assert_eq!(address.valid_until, Expiration::When(now + Duration::from_secs(600)));
+    let address = advertise(&mut ip, 1200, 30);
+    assert_eq!(address.valid_until, Expiration::When(now + Duration::from_secs(1200)));
+}
+
+#[test]
+fn router_advert_overflow() {
+    let mut groups = [Address([0; 6]); 1];
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_multicast_storage(&mut groups[..]);
+    eth.join_multicast(icmp::ndp::multicast_mac(v6::Address::LINK_LOCAL_ALL_NODES)).unwrap();
+
+    let mut ip = endpoint(Cidr::new(IP6_ADDR_HOST.into(), 64),
+        ip::Routes::new(Slice::empty()),
+        &[]);
+    let mut autoconf = icmp::ndp::Autoconf::new(v6::InterfaceId::from_vendor_ether(MAC_ADDR_HOST));
+
+    // The router and three prefixes fill the queue, the router event is replaced each time.
+    for net in 1..=4 {
+        let mut nic = External::new_recv(Slice::One(router_advert(3600, 1800, net)));
+        let recv = nic.rx(1, eth.recv(ip.recv(autoconf.answer())));
+        assert_eq!(recv, Ok(1));
+    }
+    assert_eq!(autoconf.overflowed(), 1);
+
+    assert!(matches!(autoconf.poll(), Some(icmp::ndp::Event::Router(_))));
+    for _ in 1..=3 {
+        assert!(matches!(autoconf.poll(), Some(icmp::ndp::Event::Prefix { .. })));
+    }
+    assert!(autoconf.poll().is_none());
+}
+
+#[test]
+fn verify_echo_pattern() {
+    use crate::time::Instant;
+
+    const PATTERN: icmp::EchoPattern = icmp::EchoPattern::Timestamp(b"ethox");
+    let sent_at = Instant::from_millis(1234);
+
+    let mut nic = Loopback::<Vec<u8>>::new(vec![0; 1 << 12].into());
+    nic.set_current_time(sent_at);
+
+    let mut eth_other = eth::Endpoint::new(MAC_ADDR_OTHER);
+    let mut ip_other = endpoint(Cidr::new(IP_ADDR_OTHER.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_HOST.into(), MAC_ADDR_HOST)]);
+    let mut icmp_other = icmp::Endpoint::new();
+
+    let sent = nic.tx(1, eth_other.send(ip_other.send(
+        icmp_other.send_with(|raw: icmp::RawPacket<_>| {
+            let time = raw.control.info().timestamp();
+            let init = icmp::Init::EchoRequest {
+                source: ip::Source::Exact(IP_ADDR_OTHER.into()),
+                dst_addr: IP_ADDR_HOST.into(),
+                ident: 0,
+                seq_no: 0,
+                payload: 32,
+            };
+            let mut packet = raw.prepare(init)
+                .expect("Can initialize to the host");
+            PATTERN.fill(packet.payload_mut_slice(), time);
+            packet.send().expect("Can send the packet");
+        }))));
+    assert_eq!(sent, Ok(1));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+    let mut icmp = icmp::Endpoint::new();
+
+    // The host answers the ping in place.
+    assert_eq!(nic.rx(1, eth.recv(ip.recv(icmp.answer()))), Ok(1));
+
+    let mut status = None;
+    let recv = nic.rx(1, eth_other.recv(ip_other.recv(
+        icmp_other.recv_with(|packet: icmp::InPacket<_>| {
+            let other = icmp::EchoPattern::Timestamp(b"other");
+            status = Some((packet.verify_echo(&PATTERN), packet.verify_echo(&other)));
+        }))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(status, Some((
+        Some(icmp::EchoStatus::Intact { sent: Some(sent_at) }),
+        Some(icmp::EchoStatus::Corrupt),
+    )));
+}
+
+#[test]
+fn echo_requester_v4() {
+    use crate::time::{Duration, Instant};
+
+    let mut nic = Loopback::<Vec<u8>>::new(vec![0; 1 << 12].into());
+    nic.set_current_time(Instant::from_millis(1000));
+
+    let mut eth_other = eth::Endpoint::new(MAC_ADDR_OTHER);
+    let mut ip_other = endpoint(Cidr::new(IP_ADDR_OTHER.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_HOST.into(), MAC_ADDR_HOST)]);
+    let mut requester = icmp::EchoRequester::new(
+        ip::Source::Exact(IP_ADDR_OTHER.into()),
+        IP_ADDR_HOST.into(), 0x1234, 7, icmp::EchoPattern::Timestamp(b"ethox"), 26);
+
+    let sent = nic.tx(1, eth_other.send(ip_other.send(requester.send())));
+    assert_eq!(sent, Ok(1));
+    assert_eq!(requester.outstanding(), Some((7, Instant::from_millis(1000))));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+    let mut icmp = icmp::Endpoint::new();
+    assert_eq!(nic.rx(1, eth.recv(ip.recv(icmp.answer()))), Ok(1));
+
+    nic.set_current_time(Instant::from_millis(1015));
+    let recv = nic.rx(1, eth_other.recv(ip_other.recv(requester.recv_with(|_: ip::InPacket<_>| {
+        panic!("The reply is consumed");
+    }))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(requester.poll(), Some(icmp::EchoReply {
+        seq_no: 7,
+        rtt: Duration::from_millis(15),
+        intact: true,
+    }));
+    assert_eq!(requester.outstanding(), None);
+    assert_eq!(requester.poll(), None);
+}
+
+#[test]
+fn echo_requester_v6() {
+    use crate::time::{Duration, Instant};
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut ip = endpoint(Cidr::new(IP6_ADDR_HOST.into(), 64),
+        ip::Routes::new(Slice::empty()),
+        &[(IP6_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+    let mut requester = icmp::EchoRequester::new(
+        ip::Source::Exact(IP6_ADDR_HOST.into()),
+        IP6_ADDR_OTHER.into(), 0x1234, 1, icmp::EchoPattern::Repeat(b"ping"), 4);
+
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+    nic.set_current_time(Instant::from_millis(500));
+    assert_eq!(nic.tx(1, eth.send(ip.send(requester.send()))), Ok(1));
+
+    let frame = nic.get(0).unwrap();
+    let frame = ethernet::frame::new_checked(&frame[..]).unwrap();
+    assert_eq!(frame.dst_addr(), MAC_ADDR_OTHER);
+    let packet = v6::packet::new_checked(frame.payload_slice()).unwrap();
+    let checksum = icmpv6::Checksum::Manual {
+        src_addr: IP6_ADDR_HOST,
+        dst_addr: IP6_ADDR_OTHER,
+    };
+    let message = icmpv6::packet::new_checked(packet.payload_slice()).unwrap();
+    assert_eq!(icmpv6::Repr::parse(message, checksum),
+        Ok(icmpv6::Repr::EchoRequest { ident: 0x1234, seq_no: 1, payload: 4 }));
+    assert_eq!(message.payload_slice(), b"ping");
+
+    // Nothing is sent until the next request is queued.
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+    assert_eq!(nic.tx(1, eth.send(ip.send(requester.send()))), Ok(0));
+
+    // A reply with another sequence number is passed on.
+    let reply = |seq_no, payload: &[u8; 4]| {
+        let repr = icmpv6::Repr::EchoReply { ident: 0x1234, seq_no, payload: 4 };
+        let mut message = vec![0; repr.buffer_len()];
+        let checksum = icmpv6::Checksum::Manual {
+            src_addr: IP6_ADDR_OTHER,
+            dst_addr: IP6_ADDR_HOST,
+        };
+        let packet = icmpv6::packet::new_unchecked_mut(&mut message);
+        packet.payload_mut_slice().copy_from_slice(payload);
+        repr.emit(packet, checksum);
+        icmpv6_frame(MAC_ADDR_HOST, IP6_ADDR_HOST, &message)
+    };
+    let mut nic = External::new_recv(Slice::One(reply(0, b"ping")));
+    let mut passed = false;
+    let recv = nic.rx(1, eth.recv(ip.recv(
+        requester.recv_with(|_: ip::InPacket<_>| passed = true))));
+    assert_eq!(recv, Ok(1));
+    assert!(passed);
+    assert_eq!(requester.poll(), None);
+
+    let mut nic = External::new_recv(Slice::One(reply(1, b"ping")));
+    nic.set_current_time(Instant::from_millis(542));
+    assert_eq!(nic.rx(1, eth.recv(ip.recv(requester.answer()))), Ok(1));
+    assert_eq!(requester.poll(), Some(icmp::EchoReply {
+        seq_no: 1,
+        rtt: Duration::from_millis(42),
+        intact: true,
+    }));
+
+    // A reply not matching the pattern is still accepted, but reported.
+    requester.request(2);
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+    nic.set_current_time(Instant::from_millis(1000));
+    assert_eq!(nic.tx(1, eth.send(ip.send(requester.send()))), Ok(1));
+    let mut nic = External::new_recv(Slice::One(reply(2, b"pong")));
+    nic.set_current_time(Instant::from_millis(1010));
+    assert_eq!(nic.rx(1, eth.recv(ip.recv(requester.answer()))), Ok(1));
+    assert_eq!(requester.poll(), Some(icmp::EchoReply {
+        seq_no: 2,
+        rtt: Duration::from_millis(10),
+        intact: false,
+    }));
+}
+
+#[test]
+fn echo_pattern_payloads() {
+    use crate::time::Instant;
+
+    let now = Instant::from_millis(42);
+    let pattern = icmp::EchoPattern::Repeat(&[0xab, 0xcd]);
+    let mut payload = [0; 5];
+    pattern.fill(&mut payload, now);
+    assert_eq!(payload, [0xab, 0xcd, 0xab, 0xcd, 0xab]);
+    assert_eq!(pattern.verify(&payload), icmp::EchoStatus::Intact { sent: None });
+
+    payload[3] ^= 0x1;
+    assert_eq!(pattern.verify(&payload), icmp::EchoStatus::Corrupt);
+
+    // Too short for the timestamp, only the pattern is embedded.
+    let pattern = icmp::EchoPattern::Timestamp(&[0xab, 0xcd]);
+    let mut payload = [0; 5];
+    pattern.fill(&mut payload, now);
+    assert_eq!(payload, [0xab, 0xcd, 0xab, 0xcd, 0xab]);
+    assert_eq!(pattern.verify(&payload), icmp::EchoStatus::Intact { sent: None });
+
+    let mut payload = [0; 10];
+    pattern.fill(&mut payload, now);
+    assert_eq!(payload[8..], [0xab, 0xcd]);
+    assert_eq!(pattern.verify(&payload), icmp::EchoStatus::Intact { sent: Some(now) });
+}
+
+fn queue_ping<D>(nic: &mut D)
+    where D: Device<Payload=Vec<u8>>, D::Handle: Sized,
+{
     fn prepare_ping<P: PayloadMut>(packet: icmp::RawPacket<P>) {
         let init = icmp::Init::EchoRequest {
             source: ip::Source::Exact(IP_ADDR_OTHER.into()),
@@ -86,3 +979,34 @@ fn queue_ping(nic: &mut Loopback<Vec<u8>>) {
         icmp.send_with(prepare_ping)))
     ).expect("Ping can be queued.");
 }
+
+#[test]
+fn trace_answer() {
+    use crate::layer::trace::Event;
+
+    let mut nic = External::new_send(Slice::One(vec![0; 128]));
+    queue_ping(&mut nic);
+    nic.receive_all();
+
+    let mut events = vec![];
+    let mut tracer = |event| events.push(event);
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_tracer(&mut tracer);
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+
+    let mut icmp = icmp::Endpoint::new();
+
+    let recv = nic.rx(1, eth.recv(ip.recv(
+        icmp.answer())));
+    assert_eq!(recv, Ok(1));
+
+    drop(eth);
+    assert_eq!(events, [
+        Event::Received { ethertype: ethernet::EtherType::Ipv4, len: 14 + 20 + 8 + PING_BYTES.len() },
+        Event::Routed { dst_addr: IP_ADDR_OTHER.into(), next_hop: IP_ADDR_OTHER.into() },
+        Event::Answered,
+    ]);
+}
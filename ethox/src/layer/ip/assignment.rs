@@ -0,0 +1,85 @@
+//! Addresses assigned to an interface, with lifetimes as in rfc4862.
+use crate::managed::Slice;
+use crate::time::{Expiration, Instant};
+use crate::wire::ip::{v4, v6, Cidr};
+
+/// An address assigned to the interface of an ip endpoint.
+///
+/// Apart from the address and directly reachable subnet this tracks the lifetimes of the
+/// assignment, as set by stateless address autoconfiguration for example. Addresses configured
+/// manually usually never expire, which is the default when converting from a `Cidr`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Assignment {
+    /// The address and the subnet of the local network.
+    pub cidr: Cidr,
+
+    /// Until when the address is preferred as the source of new communication.
+    ///
+    /// Afterwards the address is deprecated. It is still accepted as a destination but automatic
+    /// source selection only uses it when there is no preferred address that would be suitable.
+    pub preferred_until: Expiration,
+
+    /// Until when the address may be used as a source at all.
+    pub valid_until: Expiration,
+}
+
+impl Assignment {
+    /// An assignment that never expires.
+    pub fn new(cidr: Cidr) -> Self {
+        Assignment {
+            cidr,
+            preferred_until: Expiration::Never,
+            valid_until: Expiration::Never,
+        }
+    }
+
+    /// An assignment with a preferred and valid lifetime.
+    pub fn with_lifetimes(cidr: Cidr, preferred_until: Expiration, valid_until: Expiration)
+        -> Self
+    {
+        Assignment {
+            cidr,
+            preferred_until,
+            valid_until,
+        }
+    }
+
+    /// Check if the address is still preferred at some point in time.
+    pub fn is_preferred(&self, time: Instant) -> bool {
+        Expiration::When(time) <= self.preferred_until && self.is_valid(time)
+    }
+
+    /// Check if the address is deprecated but still valid at some point in time.
+    pub fn is_deprecated(&self, time: Instant) -> bool {
+        self.is_valid(time) && !self.is_preferred(time)
+    }
+
+    /// Check if the address is still valid at some point in time.
+    pub fn is_valid(&self, time: Instant) -> bool {
+        Expiration::When(time) <= self.valid_until
+    }
+}
+
+impl From<Cidr> for Assignment {
+    fn from(cidr: Cidr) -> Self {
+        Assignment::new(cidr)
+    }
+}
+
+impl From<v4::Cidr> for Assignment {
+    fn from(cidr: v4::Cidr) -> Self {
+        Assignment::new(cidr.into())
+    }
+}
+
+impl From<v6::Cidr> for Assignment {
+    fn from(cidr: v6::Cidr) -> Self {
+        Assignment::new(cidr.into())
+    }
+}
+
+impl From<Cidr> for Slice<'_, Assignment> {
+    fn from(cidr: Cidr) -> Self {
+        Slice::One(cidr.into())
+    }
+}
@@ -1,12 +1,14 @@
-use crate::layer::{self, FnHandler};
+use crate::layer::{self, CastPolicy, DropReason, FnHandler, Inspect, StatefulHandler, Tee};
 use crate::layer::{Error, Result};
+use crate::layer::icmp::RateLimiter;
 use crate::managed::Slice;
-use crate::wire::{ip, ethernet, Payload, PayloadMut};
+use crate::wire::{ip, icmpv4, ethernet, Checksum, Payload, PayloadMut};
 use crate::time::Instant;
 
-use super::{Recv, Send};
-use super::packet::{self, Controller, IpPacket, Route};
-use super::route::Routes;
+use super::{Assignment, Recv, Send};
+use super::packet::{self, Controller, Init, IpPacket, Route, Source};
+use super::reassembly::Reassembler;
+use super::route::{Encapsulation, Metadata, RouteInfo, Routes};
 
 /// Handles IP connection states.
 ///
@@ -22,6 +24,12 @@ pub struct Endpoint<'a> {
 
     /// Internal ipv4/ipv6 arp state.
     arp: layer::arp::Endpoint<'a>,
+
+    /// Reassembly of received IPv4 fragments, if enabled.
+    reassembly: Option<Reassembler<'a>>,
+
+    /// Bounds the rate of ICMP error messages, if any.
+    limiter: Option<RateLimiter<'a>>,
 }
 
 /// Routing information of an ip endpoint.
@@ -32,11 +40,81 @@ pub struct Endpoint<'a> {
 /// `'data` is the lifetime of the memory referenced for storing the routing data (address
 /// assignments, routing table).
 pub(crate) struct Routing<'data> {
-    /// Our own address.
-    addr: Slice<'data, ip::Cidr>,
+    /// Our own addresses.
+    addr: Slice<'data, Assignment>,
+
+    /// Additional addresses we accept packets for but do not own exclusively.
+    served: Slice<'data, ip::Address>,
 
     /// Routing information.
     routes: Routes<'data>,
+
+    /// Additional routing tables.
+    tables: Slice<'data, Routes<'data>>,
+
+    /// Chooses the routing table of a packet, if there are additional tables.
+    selector: Option<&'data dyn Fn(Metadata) -> Option<usize>>,
+
+    /// Overrides the next hop chosen by the routing.
+    next_hop_hook: Option<&'data dyn Fn(Metadata, ip::Address) -> Option<ip::Address>>,
+
+    /// How to treat packets with options we do not understand.
+    option_policy: OptionPolicy,
+
+    /// The maximum number of extension headers and encapsulations of a received packet.
+    max_depth: u8,
+
+    /// The largest frame the device can send, including the ethernet header.
+    device_mtu: usize,
+
+    /// The hop limit of sent packets that do not specify their own.
+    hop_limit: u8,
+
+    /// Whether packets to other destinations are passed to the handler for forwarding.
+    forwarding: bool,
+
+    /// Describes the headers added to packets on a path.
+    encapsulation: Option<&'data dyn Fn(Metadata) -> Encapsulation>,
+}
+
+/// The default maximum depth of received packets.
+///
+/// Allows a handful of extension headers or tunnel headers, which is more than legitimate traffic
+/// uses in practice.
+pub const DEFAULT_MAX_DEPTH: u8 = 4;
+
+/// The default size of the largest frame the device can send.
+///
+/// An ethernet payload of 1500 bytes and the 14 byte header, the frame check sequence is not
+/// included.
+pub const DEFAULT_DEVICE_MTU: usize = 1514;
+
+/// The default hop limit of sent packets, the largest possible value.
+pub const DEFAULT_HOP_LIMIT: u8 = u8::MAX;
+
+/// The handling of received IPv4 packets containing unknown options.
+///
+/// Known options are skipped correctly in any case, even if they have no effect on the processing
+/// of the packet. This only concerns options whose type is not recognized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OptionPolicy {
+    /// Ignore the options and deliver the packet normally.
+    #[default]
+    Ignore,
+
+    /// Silently drop the packet.
+    ///
+    /// The drop is still reported as unsupported to the drop hook of the ethernet endpoint.
+    Drop,
+
+    /// Drop the packet and answer with an ICMP parameter problem.
+    ///
+    /// The pointer of the answer refers to the first unknown option. If the answer can not be
+    /// generated, for example because there is no route to the source or the rate limit of ICMP
+    /// errors is exceeded, the packet is dropped silently instead. Packets from a non-unicast
+    /// source are never answered, and packets to a broadcast or multicast address are dropped as
+    /// with `Drop`.
+    ParameterProblem,
 }
 
 /// An endpoint borrowed for receiving.
@@ -79,25 +157,42 @@ impl<'a> Endpoint<'a> {
     /// The neighbors buffer for ARP can be built from an empty slice if it is not needed. This
     /// will however stall send operations indeterminately.
     ///
+    /// The addresses can be given as a single `Cidr`, in which case it never expires, or as a
+    /// slice of [`Assignment`]s with individual lifetimes.
+    ///
     /// # Panics
     /// This method will panic if one of the addresses assigned to the interface is not a unicast
     /// address.
+    ///
+    /// [`Assignment`]: struct.Assignment.html
     pub fn new<A, C, N>(addr: A, routes: C, neighbors: N) -> Self
     where
-        A: Into<Slice<'a, ip::Cidr>>,
+        A: Into<Slice<'a, Assignment>>,
         C: Into<Routes<'a>>,
         N: Into<layer::arp::NeighborCache<'a>>,
     {
         let addresses = addr.into();
         for addr in addresses.iter() {
-            assert!(addr.address().is_unicast());
+            assert!(addr.cidr.address().is_unicast());
         }
         Endpoint {
             routing: Routing {
                 addr: addresses,
+                served: Slice::empty(),
                 routes: routes.into(),
+                tables: Slice::empty(),
+                selector: None,
+                next_hop_hook: None,
+                option_policy: OptionPolicy::default(),
+                max_depth: DEFAULT_MAX_DEPTH,
+                device_mtu: DEFAULT_DEVICE_MTU,
+                hop_limit: DEFAULT_HOP_LIMIT,
+                forwarding: false,
+                encapsulation: None,
             },
             arp: layer::arp::Endpoint::new(neighbors.into()),
+            reassembly: None,
+            limiter: None,
         }
     }
 
@@ -132,7 +227,261 @@ impl<'a> Endpoint<'a> {
         }
     }
 
-    /// Query if the configured addresses contain this destination.
+    /// The addresses assigned to the endpoint.
+    pub fn addresses(&self) -> &[Assignment] {
+        &self.routing.addr
+    }
+
+    /// Mutably access the addresses assigned to the endpoint.
+    ///
+    /// Allows updating the lifetimes of assignments, for example when a router advertisement
+    /// renewed a prefix.
+    ///
+    /// # Panics
+    /// Changing an address to one that is not unicast is not allowed and will lead to panics when
+    /// processing packets.
+    pub fn addresses_mut(&mut self) -> &mut [Assignment] {
+        &mut self.routing.addr
+    }
+
+    /// The shared addresses served by the endpoint.
+    pub fn served(&self) -> &[ip::Address] {
+        &self.routing.served
+    }
+
+    /// Serve additional addresses that are shared with other nodes.
+    ///
+    /// Packets to these addresses are accepted as if they were assigned, and ARP requests for them
+    /// are answered, which is what an anycast address or the active router of a virtual address
+    /// (VRRP) needs. In contrast to the assigned addresses they are never chosen as the source of
+    /// packets and do not make their network directly reachable.
+    ///
+    /// Replaces the previously served addresses. An empty slice stops serving any shared address.
+    ///
+    /// # Panics
+    /// This method will panic if one of the addresses is not a unicast address.
+    pub fn set_served<S>(&mut self, served: S)
+        where S: Into<Slice<'a, ip::Address>>,
+    {
+        let served = served.into();
+        for addr in served.iter() {
+            assert!(addr.is_unicast());
+        }
+        self.routing.served = served;
+    }
+
+    /// The additional routing tables.
+    pub fn tables(&self) -> &[Routes<'a>] {
+        &self.routing.tables
+    }
+
+    /// Mutably access the additional routing tables, for example to update their routes.
+    pub fn tables_mut(&mut self) -> &mut [Routes<'a>] {
+        &mut self.routing.tables
+    }
+
+    /// Route packets with several independent routing tables.
+    ///
+    /// For each outgoing packet the selector is called with its [`Metadata`], most notably the
+    /// mark set with [`Controller::set_mark`]. It returns the index of the table in `tables` to
+    /// consult or `None` for the table the endpoint was created with. A packet selecting a table
+    /// that does not exist has no route. Networks of the assigned addresses are reachable
+    /// directly regardless of the table.
+    ///
+    /// Replaces any previous tables and selector.
+    ///
+    /// [`Metadata`]: struct.Metadata.html
+    /// [`Controller::set_mark`]: struct.Controller.html#method.set_mark
+    pub fn set_tables<T>(&mut self, tables: T, selector: &'a dyn Fn(Metadata) -> Option<usize>)
+        where T: Into<Slice<'a, Routes<'a>>>,
+    {
+        self.routing.tables = tables.into();
+        self.routing.selector = Some(selector);
+    }
+
+    /// Remove the additional routing tables, routing all packets with the main table.
+    pub fn clear_tables(&mut self) {
+        self.routing.tables = Slice::empty();
+        self.routing.selector = None;
+    }
+
+    /// Register a hook that can override the next hop of outgoing packets.
+    ///
+    /// The hook is called with the [`Metadata`] of each packet and the next hop found by the
+    /// routing, either the destination itself in a directly reachable network or a gateway from
+    /// the routing table. It returns the next hop to use instead or `None` to treat the packet as
+    /// unroutable. This runs before the link layer address of the next hop is resolved, and the
+    /// source address is selected to match a changed next hop.
+    ///
+    /// Replaces any previously registered hook.
+    ///
+    /// [`Metadata`]: struct.Metadata.html
+    pub fn set_next_hop_hook(
+        &mut self,
+        hook: &'a dyn Fn(Metadata, ip::Address) -> Option<ip::Address>,
+    ) {
+        self.routing.next_hop_hook = Some(hook);
+    }
+
+    /// Remove the hook overriding the next hop.
+    pub fn clear_next_hop_hook(&mut self) {
+        self.routing.next_hop_hook = None;
+    }
+
+    /// The handling of packets with unknown IPv4 options.
+    pub fn option_policy(&self) -> OptionPolicy {
+        self.routing.option_policy
+    }
+
+    /// Change the handling of packets with unknown IPv4 options.
+    ///
+    /// By default, such options are ignored.
+    pub fn set_option_policy(&mut self, policy: OptionPolicy) {
+        self.routing.option_policy = policy;
+    }
+
+    /// The maximum nesting depth of received packets.
+    pub fn max_depth(&self) -> u8 {
+        self.routing.max_depth
+    }
+
+    /// Change the maximum nesting depth of received packets.
+    ///
+    /// Each IPv6 extension header and each encapsulated IPv4 or IPv6 header in front of the upper
+    /// layer payload counts as one level. Packets nesting more levels are dropped and reported
+    /// to the drop hook of the ethernet endpoint with `DropReason::TooDeep`. This bounds the work
+    /// spent on a single packet by anyone walking the chain of headers, such as a tunnel endpoint.
+    ///
+    /// The default is [`DEFAULT_MAX_DEPTH`].
+    ///
+    /// [`DEFAULT_MAX_DEPTH`]: constant.DEFAULT_MAX_DEPTH.html
+    pub fn set_max_depth(&mut self, depth: u8) {
+        self.routing.max_depth = depth;
+    }
+
+    /// The size of the largest frame the device can send.
+    pub fn device_mtu(&self) -> usize {
+        self.routing.device_mtu
+    }
+
+    /// Change the size of the largest frame the device can send.
+    ///
+    /// This includes the ethernet header but not the frame check sequence. The default is
+    /// [`DEFAULT_DEVICE_MTU`].
+    ///
+    /// [`DEFAULT_DEVICE_MTU`]: constant.DEFAULT_DEVICE_MTU.html
+    pub fn set_device_mtu(&mut self, mtu: usize) {
+        self.routing.device_mtu = mtu;
+    }
+
+    /// The hop limit of sent packets that do not specify their own.
+    pub fn hop_limit(&self) -> u8 {
+        self.routing.hop_limit
+    }
+
+    /// Change the hop limit of sent packets, the TTL for IPv4.
+    ///
+    /// Applies to all packets whose [`Init`] does not set a hop limit, except those to multicast
+    /// groups which stay on the link by default. The default is [`DEFAULT_HOP_LIMIT`].
+    ///
+    /// [`Init`]: struct.Init.html
+    /// [`DEFAULT_HOP_LIMIT`]: constant.DEFAULT_HOP_LIMIT.html
+    pub fn set_hop_limit(&mut self, hop_limit: u8) {
+        self.routing.hop_limit = hop_limit;
+    }
+
+    /// Whether packets to other destinations are received for forwarding.
+    pub fn forwarding(&self) -> bool {
+        self.routing.forwarding
+    }
+
+    /// Receive unicast packets to other destinations instead of dropping them.
+    ///
+    /// The handler then also sees packets that are not addressed to the endpoint. Use a
+    /// [`Forwarder`] to route them back out and to pass the others on to the local handler.
+    /// Disabled by default.
+    ///
+    /// IPv4 fragments to other destinations bypass the reassembler and are handed over one by
+    /// one. Their representation only describes the fragment itself.
+    ///
+    /// [`Forwarder`]: struct.Forwarder.html
+    pub fn set_forwarding(&mut self, forwarding: bool) {
+        self.routing.forwarding = forwarding;
+    }
+
+    /// Register a hook describing the encapsulation of packets on each path.
+    ///
+    /// The hook is called with the [`Metadata`] of a packet by [`egress_mtu`]. Without a hook,
+    /// packets are assumed to be sent in plain untagged ethernet frames.
+    ///
+    /// Replaces any previously registered hook.
+    ///
+    /// [`Metadata`]: struct.Metadata.html
+    /// [`egress_mtu`]: #method.egress_mtu
+    pub fn set_encapsulation_hook(&mut self, hook: &'a dyn Fn(Metadata) -> Encapsulation) {
+        self.routing.encapsulation = Some(hook);
+    }
+
+    /// Remove the hook describing the encapsulation of packets.
+    pub fn clear_encapsulation_hook(&mut self) {
+        self.routing.encapsulation = None;
+    }
+
+    /// Reassemble received IPv4 fragments.
+    ///
+    /// Completed datagrams are handed to the upper layer in the buffer of their last fragment,
+    /// which must be large enough or resizable. Without a reassembler, fragments are dropped.
+    pub fn set_reassembler(&mut self, reassembler: Reassembler<'a>) {
+        self.reassembly = Some(reassembler);
+    }
+
+    /// The reassembler of received fragments, if any.
+    pub fn reassembler(&self) -> Option<&Reassembler<'a>> {
+        self.reassembly.as_ref()
+    }
+
+    /// Stop reassembling fragments, returning the reassembler.
+    pub fn clear_reassembler(&mut self) -> Option<Reassembler<'a>> {
+        self.reassembly.take()
+    }
+
+    /// Limit the rate of all ICMP error messages (unlimited by default).
+    ///
+    /// This covers the parameter problems of the endpoint itself as well as the messages of a
+    /// [`Forwarder`]. The limiter is consulted with the destination of each message and the
+    /// timestamp of the offending packet. Suppressed messages are not sent and the packet is
+    /// simply dropped.
+    ///
+    /// [`Forwarder`]: struct.Forwarder.html
+    pub fn set_rate_limiter(&mut self, limiter: RateLimiter<'a>) {
+        self.limiter = Some(limiter);
+    }
+
+    /// Remove the rate limit of ICMP error messages.
+    pub fn clear_rate_limiter(&mut self) -> Option<RateLimiter<'a>> {
+        self.limiter.take()
+    }
+
+    /// Get a mutable reference to the rate limiter, if any.
+    pub fn rate_limiter_mut(&mut self) -> Option<&mut RateLimiter<'a>> {
+        self.limiter.as_mut()
+    }
+
+    /// The largest ip packet that can be sent to a destination.
+    ///
+    /// Subtracts the ethernet header and the overhead of the [`Encapsulation`] of the path from
+    /// the device mtu. The result includes the fixed ip header, so the upper layer payload is
+    /// further limited by 20 bytes for IPv4 and 40 bytes for IPv6. Returns `None` if the
+    /// destination is not routable or the device mtu does not leave room for any packet. If the
+    /// [`Route`] restricts the mtu of the path then the result is at most that mtu.
+    ///
+    /// [`Encapsulation`]: struct.Encapsulation.html
+    /// [`Route`]: struct.Route.html
+    pub fn egress_mtu(&self, dst_addr: ip::Address, mark: u32, time: Instant) -> Option<usize> {
+        self.routing.egress_mtu(dst_addr, mark, time)
+    }
+
+    /// Query if the configured or served addresses contain this destination.
     pub fn accepts(&self, dst_addr: ip::Address) -> bool {
         self.routing.accepts(dst_addr)
     }
@@ -143,8 +492,58 @@ impl<'a> Endpoint<'a> {
 }
 
 impl Routing<'_> {
+    pub(crate) fn egress_mtu(&self, dst_addr: ip::Address, mark: u32, time: Instant) -> Option<usize> {
+        let route = self.route(dst_addr, mark, time)?;
+        let encapsulation = match self.encapsulation {
+            None => Encapsulation::default(),
+            Some(hook) => hook(Metadata { mark, dst_addr }),
+        };
+
+        self.device_mtu
+            .checked_sub(ethernet::frame::header_len())?
+            .checked_sub(encapsulation.overhead())
+            .map(|mtu| route.mtu.map_or(mtu, |path| mtu.min(path.into())))
+            .filter(|&mtu| mtu > 0)
+    }
+
     pub(crate) fn accepts(&self, dst_addr: ip::Address) -> bool {
-        self.addr.iter().any(|own_addr| own_addr.accepts(dst_addr))
+        self.addr.iter().any(|own_addr| own_addr.cidr.accepts(dst_addr))
+            || self.served.contains(&dst_addr)
+    }
+
+    /// Query if a packet to a destination is passed on to another host instead of received.
+    pub(crate) fn forwards(&self, dst_addr: ip::Address) -> bool {
+        self.forwarding
+            && dst_addr.is_unicast()
+            && !self.is_broadcast(dst_addr)
+            && !self.accepts(dst_addr)
+    }
+
+    /// Query if a destination is accepted under a broadcast policy.
+    pub(crate) fn accepts_with(&self, policy: CastPolicy, dst_addr: ip::Address) -> bool {
+        if self.is_broadcast(dst_addr) {
+            // The limited broadcast also reaches hosts without any address, e.g. during DHCP.
+            return policy.ip_broadcast && (dst_addr.is_broadcast() || self.accepts(dst_addr));
+        }
+
+        self.accepts(dst_addr)
+    }
+
+    /// Check if the address is a broadcast (or link-local all-nodes) address of our networks.
+    pub(crate) fn is_broadcast(&self, dst_addr: ip::Address) -> bool {
+        match dst_addr {
+            ip::Address::Ipv4(addr) => addr.is_broadcast() || self.addr
+                .iter()
+                .filter_map(|assigned| match assigned.cidr {
+                    ip::Cidr::Ipv4(cidr) => cidr.broadcast(),
+                    _ => None,
+                })
+                .any(|broadcast| broadcast.address() == addr),
+            // Only the link-local all-nodes group stands in for a broadcast. Other groups, such as
+            // the solicited-node addresses required by NDP, are left to the multicast policy.
+            ip::Address::Ipv6(addr) => addr == ip::v6::Address::LINK_LOCAL_ALL_NODES,
+            _ => false,
+        }
     }
 
     /// Find the route to use.
@@ -154,41 +553,124 @@ impl Routing<'_> {
     /// * If dst is in the network of an assigned ip then route directly.
     /// * Lookup in routing table for all other addresses.
     ///
-    /// For lack of direct loopback mechanism (TODO) we only implement the second two stages.
-    pub(crate) fn route(&self, dst_addr: ip::Address, time: Instant) -> Option<Route> {
-        if let Some(route) = self.find_local_route(dst_addr, time) {
-            return Some(route)
+    /// For lack of direct loopback mechanism (TODO) we only implement the second two stages. The
+    /// next hop hook, if any, can then override the result.
+    pub(crate) fn route(&self, dst_addr: ip::Address, mark: u32, time: Instant) -> Option<Route> {
+        let meta = Metadata { mark, dst_addr };
+        let route = match self.find_local_route(dst_addr, time) {
+            Some(route) => route,
+            None if dst_addr.is_broadcast() || dst_addr.is_multicast() => {
+                self.find_link_route(dst_addr, time)
+            },
+            None => {
+                let table = self.table(meta)?;
+                self.find_outer_route(table, dst_addr, time)?
+            },
+        };
+
+        let hook = match self.next_hop_hook {
+            None => return Some(route),
+            Some(hook) => hook,
+        };
+
+        let next_hop = hook(meta, route.next_hop)?;
+        if next_hop == route.next_hop {
+            return Some(route);
         }
 
-        self.find_outer_route(dst_addr, time)
+        let src_addr = self.select_source(next_hop, time)?;
+        Some(Route {
+            next_hop,
+            src_addr,
+            mtu: route.mtu,
+        })
     }
 
-    pub(crate) fn find_local_route(&self, dst_addr: ip::Address, _: Instant) -> Option<Route> {
-        let matching_src = self.addr
-            .iter()
-            .filter(|addr| addr.subnet().contains(dst_addr))
-            .nth(0)?;
+    /// Select the routing table for a packet.
+    fn table(&self, meta: Metadata) -> Option<&Routes<'_>> {
+        let selector = match self.selector {
+            None => return Some(&self.routes),
+            Some(selector) => selector,
+        };
+
+        match selector(meta) {
+            None => Some(&self.routes),
+            Some(index) => self.tables.get(index),
+        }
+    }
+
+    pub(crate) fn find_local_route(&self, dst_addr: ip::Address, time: Instant) -> Option<Route> {
+        let src_addr = self.select_source(dst_addr, time)?;
 
         Some(Route {
-            src_addr: matching_src.address(),
+            src_addr,
             next_hop: dst_addr,
+            mtu: None,
         })
     }
 
-    pub(crate) fn find_outer_route(&self, dst_addr: ip::Address, time: Instant) -> Option<Route> {
-        let next_hop = self.routes.lookup(dst_addr, time)?;
-
-        // Which source to use?
+    /// Route the limited broadcast address and multicast groups directly onto the link.
+    ///
+    /// This works even without any assigned address, in which case the unspecified address is
+    /// used as the source. Hosts bootstrapping their configuration, e.g. with DHCP, rely on this.
+    fn find_link_route(&self, dst_addr: ip::Address, time: Instant) -> Route {
+        let ipv6 = matches!(dst_addr, ip::Address::Ipv6(_));
         let src_addr = self.addr
             .iter()
-            .filter(|addr| addr.subnet().contains(next_hop))
-            .nth(0)?;
+            .filter(|addr| addr.is_valid(time))
+            .map(|addr| addr.cidr.address())
+            .find(|addr| match addr {
+                ip::Address::Ipv4(_) => !ipv6,
+                ip::Address::Ipv6(_) => ipv6,
+                _ => false,
+            })
+            .unwrap_or(if ipv6 {
+                ip::Address::Ipv6(ip::v6::Address::UNSPECIFIED)
+            } else {
+                ip::Address::Ipv4(ip::v4::Address::UNSPECIFIED)
+            });
+
+        Route {
+            next_hop: dst_addr,
+            src_addr,
+            mtu: None,
+        }
+    }
+
+    pub(crate) fn find_outer_route(&self, table: &Routes, dst_addr: ip::Address, time: Instant)
+        -> Option<Route>
+    {
+        let RouteInfo { via_router: next_hop, mtu } = table.lookup(dst_addr, time)?;
+        let src_addr = self.select_source(next_hop, time)?;
 
         Some(Route {
             next_hop,
-            src_addr: src_addr.address(),
+            src_addr,
+            mtu,
         })
     }
+
+    /// Select a source address in the same network as a directly reachable host.
+    ///
+    /// Deprecated addresses are only selected if there is no preferred address in that network
+    /// (rfc6724, rule 3) and addresses past their valid lifetime are never selected.
+    fn select_source(&self, neighbor: ip::Address, time: Instant) -> Option<ip::Address> {
+        let mut candidates = self.addr
+            .iter()
+            .filter(|addr| addr.is_valid(time))
+            .filter(|addr| addr.cidr.subnet().contains(neighbor));
+
+        let first = candidates.next()?;
+        let selected = if first.is_preferred(time) {
+            first
+        } else {
+            candidates
+                .find(|addr| addr.is_preferred(time))
+                .unwrap_or(first)
+        };
+
+        Some(selected.cidr.address())
+    }
 }
 
 impl<'data> IpEndpoint<'_, 'data> {
@@ -201,12 +683,12 @@ impl<'data> IpEndpoint<'_, 'data> {
     }
 
     fn into_arp_receiver(&mut self) -> layer::arp::Receiver<'_, 'data> {
-        let Endpoint { routing, arp } = self.inner;
+        let Endpoint { routing, arp, .. } = self.inner;
         arp.answer_for(routing)
     }
 
     fn into_arp_sender(&mut self) -> layer::arp::Sender<'_, 'data> {
-        let Endpoint { routing, arp } = self.inner;
+        let Endpoint { routing, arp, .. } = self.inner;
         arp.query_for(routing)
     }
 }
@@ -215,19 +697,59 @@ impl packet::Endpoint for IpEndpoint<'_, '_> {
     fn local_ip(&self, subnet: ip::Subnet) -> Option<ip::Address> {
         self.inner.routing.addr
             .iter()
-            .cloned()
-            .map(|cidr| cidr.address())
+            .map(|assigned| assigned.cidr.address())
             .filter(|&addr| subnet.contains(addr))
             .nth(0)
     }
 
-    fn route(&self, dst_addr: ip::Address, time: Instant) -> Option<Route> {
-        self.inner.routing.route(dst_addr, time)
+    fn route(&self, dst_addr: ip::Address, mark: u32, time: Instant) -> Option<Route> {
+        self.inner.routing.route(dst_addr, mark, time)
+    }
+
+    fn egress_mtu(&self, dst_addr: ip::Address, mark: u32, time: Instant) -> Option<usize> {
+        self.inner.routing.egress_mtu(dst_addr, mark, time)
+    }
+
+    fn hop_limit(&self) -> u8 {
+        self.inner.routing.hop_limit
+    }
+
+    fn accepts(&self, dst_addr: ip::Address) -> bool {
+        self.inner.routing.accepts(dst_addr)
+    }
+
+    fn assignment(&self, addr: ip::Address) -> Option<Assignment> {
+        self.inner.routing.addr
+            .iter()
+            .find(|assigned| assigned.cidr.address() == addr)
+            .copied()
+    }
+
+    fn allow_error(&mut self, dst_addr: ip::Address, time: Instant) -> bool {
+        match &mut self.inner.limiter {
+            Some(limiter) => limiter.allow(dst_addr, time),
+            None => true,
+        }
     }
 
     fn resolve(&mut self, addr: ip::Address, time: Instant, look: bool) -> Result<ethernet::Address> {
+        // Broadcasts in our networks reach every host on the link and are never looked up.
+        if let ip::Address::Ipv4(_) = addr {
+            if self.inner.routing.is_broadcast(addr) {
+                return Ok(ethernet::Address::BROADCAST);
+            }
+        }
+
+        // Neither are multicast groups, their address is derived from the group.
+        if let Some(group) = ethernet::Address::from_multicast(addr) {
+            return Ok(group);
+        }
+
         match self.neighbors().lookup_pure(addr, time) {
-            Some(addr) => return Ok(addr),
+            Some(hw_addr) => {
+                self.neighbors_mut().used(addr, time);
+                return Ok(hw_addr)
+            },
             None if !look => return Err(Error::Unreachable),
             None => (),
         }
@@ -237,6 +759,22 @@ impl packet::Endpoint for IpEndpoint<'_, '_> {
             Err(_) => Err(Error::Exhausted),
         }
     }
+
+    fn learn(&mut self, addr: ip::Address, hw_addr: Option<ethernet::Address>, time: Instant, confirmed: bool) {
+        let current = match self.neighbors().lookup(addr, time) {
+            Some(layer::arp::NeighborMapping::Address(current)) => Some(current),
+            Some(_) => None,
+            // Not an address we are interested in.
+            None => return,
+        };
+
+        let _ = match (hw_addr, current) {
+            (Some(hw_addr), Some(current)) if hw_addr == current && !confirmed => Ok(()),
+            (Some(hw_addr), _) => self.neighbors_mut().fill(addr, hw_addr, Some(time)),
+            (None, Some(_)) if confirmed => self.neighbors_mut().confirm(addr, time),
+            (None, _) => Ok(()),
+        };
+    }
 }
 
 impl<P, T> layer::eth::Recv<P> for Receiver<'_, '_, T>
@@ -248,35 +786,166 @@ where
         let capabilities = control.info().capabilities();
         let packet = match frame.repr().ethertype {
             ethernet::EtherType::Ipv4 => {
-                match ip::v4::Packet::new_checked(frame, capabilities.ipv4().rx_checksum()) {
-                    Ok(packet) => IpPacket::V4(packet),
-                    Err(_) => return,
+                // Fragments of datagrams to other hosts are forwarded as they are.
+                let transit = self.is_transit_fragment(&frame);
+                let frame = if transit {
+                    frame
+                } else {
+                    match self.reassemble(&mut control, frame) {
+                        Some(frame) => frame,
+                        None => return,
+                    }
+                };
+                let checksum = capabilities.ipv4().rx_checksum();
+                let checked = if transit {
+                    ip::v4::Packet::new_checked_fragment(&frame, checksum)
+                } else {
+                    ip::v4::Packet::new_checked(&frame, checksum)
+                };
+                match checked {
+                    Ok(packet) => {
+                        let repr = packet.repr();
+                        IpPacket::V4(ip::v4::Packet::new_unchecked(frame, repr))
+                    },
+                    Err(err) => return control.report_drop(err.into(), frame.payload_slice()),
                 }
             },
             ethernet::EtherType::Ipv6 => {
-                match ip::v6::Packet::new_checked(frame) {
-                    Ok(packet) => IpPacket::V6(packet),
-                    Err(_) => return,
+                match ip::v6::Packet::new_checked(&frame) {
+                    Ok(packet) => {
+                        let repr = packet.repr();
+                        IpPacket::V6(ip::v6::Packet::new_unchecked(frame, repr))
+                    },
+                    Err(err) => return control.report_drop(err.into(), frame.payload_slice()),
                 }
             },
             ethernet::EtherType::Arp => {
                 return self.endpoint.into_arp_receiver().receive(
                     layer::eth::InPacket { control, frame, });
             }
-            _ => return,
+            _ => return control.report_drop(DropReason::Unsupported, frame.payload_slice()),
         };
 
-        if !self.endpoint.inner.accepts(packet.repr().dst_addr()) {
-            return
+        let dst_addr = packet.repr().dst_addr();
+        let routing = &self.endpoint.inner.routing;
+        let forward = routing.forwarding
+            && dst_addr.is_unicast()
+            && !routing.is_broadcast(dst_addr);
+        if !forward && !routing.accepts_with(control.cast_policy(), dst_addr) {
+            let frame = packet.into_inner();
+            return control.report_drop(DropReason::Filtered, frame.payload_slice());
+        }
+
+        let max_depth = self.endpoint.inner.routing.max_depth;
+        if nesting_depth(packet.repr().protocol(), packet.payload(), max_depth) > max_depth {
+            let frame = packet.into_inner();
+            return control.report_drop(DropReason::TooDeep, frame.payload_slice());
         }
 
-        self.handler.receive(packet::In {
+        let option_policy = self.endpoint.inner.routing.option_policy;
+        let unknown_option = match &packet {
+            IpPacket::V4(packet) => find_unknown_option(packet),
+            IpPacket::V6(_) => None,
+        };
+        // Errors are never answered to a group of hosts.
+        let dst_addr = packet.repr().dst_addr();
+        let answerable = dst_addr.is_unicast()
+            && !self.endpoint.inner.routing.is_broadcast(dst_addr);
+
+        let packet = packet::In {
             control: Controller {
                 eth: control.borrow_mut(),
                 endpoint: &mut self.endpoint,
+                mark: 0,
             },
             packet,
-        })
+        };
+
+        match (unknown_option, option_policy) {
+            (None, _) | (Some(_), OptionPolicy::Ignore) => self.handler.receive(packet),
+            (Some(pointer), OptionPolicy::ParameterProblem) if answerable => {
+                // Nothing more to do if we can not answer.
+                let _ = answer_param_problem(packet, pointer);
+            },
+            (Some(_), _) => {
+                let packet::In { mut control, packet } = packet;
+                let frame = packet.into_inner();
+                control.report_drop(DropReason::Unsupported, frame.payload_slice())
+            },
+        }
+    }
+}
+
+impl<T> Receiver<'_, '_, T> {
+    /// Check if the frame is an IPv4 fragment that is forwarded instead of reassembled.
+    fn is_transit_fragment<P: Payload>(&self, frame: &ethernet::Frame<&mut P>) -> bool {
+        match ip::v4::packet::new_checked(frame.payload_slice()) {
+            Ok(fragment) if fragment.more_frags() || fragment.frag_offset() != 0 => {
+                self.endpoint.inner.routing.forwards(fragment.dst_addr().into())
+            },
+            _ => false,
+        }
+    }
+
+    /// Pass a fragment to the reassembler.
+    ///
+    /// Returns the frame to handle, which contains the complete datagram if the fragment was the
+    /// last missing one. Returns other packets unchanged, including all packets when there is no
+    /// reassembler.
+    fn reassemble<'a, P: PayloadMut>(
+        &mut self,
+        control: &mut layer::eth::Controller,
+        mut frame: ethernet::Frame<&'a mut P>,
+    ) -> Option<ethernet::Frame<&'a mut P>> {
+        let reassembler = match &mut self.endpoint.inner.reassembly {
+            Some(reassembler) => reassembler,
+            None => return Some(frame),
+        };
+
+        let fragment = match ip::v4::packet::new_checked(frame.payload_slice()) {
+            Ok(fragment) if fragment.more_frags() || fragment.frag_offset() != 0 => fragment,
+            // Not a fragment, or so malformed that regular parsing reports it.
+            _ => return Some(frame),
+        };
+
+        let checksum = control.info().capabilities().ipv4().rx_checksum();
+        if checksum.manual() && !fragment.verify_checksum() {
+            control.report_drop(DropReason::Checksum, frame.payload_slice());
+            return None;
+        }
+
+        let dst_addr = ip::Address::Ipv4(fragment.dst_addr());
+        if !self.endpoint.inner.routing.accepts_with(control.cast_policy(), dst_addr) {
+            control.report_drop(DropReason::Filtered, frame.payload_slice());
+            return None;
+        }
+
+        let header_len = usize::from(fragment.header_len());
+        let now = control.info().timestamp();
+        let datagram = match reassembler.insert(fragment, now) {
+            Ok(Some(datagram)) => datagram,
+            Ok(None) => return None,
+            Err(_) => {
+                control.report_drop(DropReason::Reassembly, frame.payload_slice());
+                return None;
+            },
+        };
+
+        let total_len = header_len + datagram.len();
+        let too_large = total_len > usize::from(u16::MAX)
+            || (frame.payload_slice().len() < total_len && frame.resize(total_len).is_err());
+        if too_large {
+            control.report_drop(DropReason::Reassembly, frame.payload_slice());
+            return None;
+        }
+
+        let packet = ip::v4::packet::new_unchecked_mut(frame.payload_mut_slice());
+        packet.as_bytes_mut()[header_len..total_len].copy_from_slice(datagram);
+        packet.set_total_len(total_len as u16);
+        packet.set_more_frags(false);
+        packet.set_frag_offset(0);
+        packet.fill_checksum();
+        Some(frame)
     }
 }
 
@@ -287,7 +956,8 @@ where
 {
     fn send(&mut self, packet: layer::eth::RawPacket<P>) {
         // FIXME: will *always* intercept, even if we can't actually send any arp.
-        if self.endpoint.neighbors().missing().count() > 0 {
+        let time = packet.control.info().timestamp();
+        if self.endpoint.neighbors().missing().count() > 0 || self.endpoint.neighbors().probing(time) {
             return self.endpoint.into_arp_sender().send(packet);
         }
 
@@ -296,7 +966,8 @@ where
         self.handler.send(packet::Raw {
             control: Controller {
                 eth: eth_handle.borrow_mut(),
-                endpoint: &mut self.endpoint
+                endpoint: &mut self.endpoint,
+                mark: 0,
             },
             payload,
         });
@@ -329,6 +1000,101 @@ impl<P> layer::eth::Send<P> for Layer<'_, '_>
     }
 }
 
+/// Count the headers nested in front of the upper layer payload.
+///
+/// Stops counting once `limit` is exceeded, so the work is bounded regardless of the packet. A
+/// truncated header also ends the walk, it is left to the upper layer to reject it.
+fn nesting_depth(mut protocol: ip::Protocol, mut payload: &[u8], limit: u8) -> u8 {
+    let mut depth = 0;
+    while depth <= limit {
+        let (next, len) = match protocol {
+            ip::Protocol::HopByHop | ip::Protocol::Ipv6Route | ip::Protocol::Ipv6Opts => {
+                match payload {
+                    [next, len, ..] => (*next, (usize::from(*len) + 1)*8),
+                    _ => break,
+                }
+            },
+            ip::Protocol::Ipv6Frag => match payload.first() {
+                Some(next) => (*next, 8),
+                None => break,
+            },
+            ip::Protocol::Ipv4 => match (payload.first(), payload.get(9)) {
+                (Some(ihl), Some(next)) => (*next, usize::from(ihl & 0xf)*4),
+                _ => break,
+            },
+            ip::Protocol::Ipv6 => match payload.get(6) {
+                Some(next) => (*next, 40),
+                None => break,
+            },
+            _ => break,
+        };
+
+        depth += 1;
+        protocol = ip::Protocol::from(next);
+        payload = payload.get(len..).unwrap_or(&[]);
+    }
+    depth
+}
+
+/// Find the offset of the first option that we do not understand.
+fn find_unknown_option<P: Payload>(packet: &packet::V4Packet<P>) -> Option<u8> {
+    packet.options()
+        .filter_map(|option| option.ok())
+        .find(|option| matches!(option.kind, ip::v4::OptionType::Unknown(_)))
+        .map(|option| option.offset as u8)
+}
+
+/// Answer an IPv4 packet with a parameter problem pointing into its header.
+///
+/// Like other ICMP errors, the answer is subject to the rate limiter of the endpoint and never
+/// sent to a non-unicast source. The caller ensures the packet was not sent to a group.
+fn answer_param_problem<P: PayloadMut>(mut packet: packet::In<P>, pointer: u8) -> Result<()> {
+    let capabilities = packet.control.info().capabilities();
+    // Quote the complete original header and the first eight bytes of its payload.
+    let mut quote = [0; 60 + 8];
+    let (header, quote_len) = match &packet.packet {
+        IpPacket::V4(ipv4) => {
+            let bytes = ipv4.as_bytes();
+            let len = (usize::from(ipv4.header_len()) + 8)
+                .min(usize::from(ipv4.total_len()))
+                .min(bytes.len());
+            quote[..len].copy_from_slice(&bytes[..len]);
+            (ipv4.repr(), len)
+        },
+        IpPacket::V6(_) => return Err(Error::Illegal),
+    };
+
+    if !header.src_addr.is_unicast() {
+        return Err(Error::Illegal);
+    }
+
+    if !packet.control.allow_error(header.src_addr.into()) {
+        return Err(Error::Exhausted);
+    }
+
+    let mut out = packet.reinit(Init {
+        source: Source::Exact(header.dst_addr.into()),
+        dst_addr: header.src_addr.into(),
+        protocol: ip::Protocol::Icmp,
+        payload: 8 + quote_len,
+        hop_limit: None,
+        traffic_class: 0,
+    })?;
+
+    let repr = icmpv4::Repr::ParamProblem {
+        reason: icmpv4::ParamProblem::AtPointer,
+        pointer,
+        header,
+    };
+    let icmp = icmpv4::packet::new_unchecked_mut(out.payload_mut_slice());
+    repr.emit(icmp, Checksum::Ignored);
+    icmp.payload_mut_slice().copy_from_slice(&quote[..quote_len]);
+    if capabilities.icmpv4().tx_checksum().manual() {
+        icmp.fill_checksum();
+    }
+    out.send()
+}
+
 fn recv_nothing<P: PayloadMut>(_: packet::In<P>) { }
 fn send_nothing<P: PayloadMut>(_: packet::Raw<P>) { }
 
@@ -347,3 +1113,30 @@ impl<P: Payload, F> Send<P> for FnHandler<F>
         self.0(frame)
     }
 }
+
+impl<P: Payload, S, F> Recv<P> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, packet::In<P>)
+{
+    fn receive(&mut self, frame: packet::In<P>) {
+        (self.handler)(&mut self.state, frame)
+    }
+}
+
+impl<P: Payload, S, F> Send<P> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, packet::Raw<P>)
+{
+    fn send(&mut self, frame: packet::Raw<P>) {
+        (self.handler)(&mut self.state, frame)
+    }
+}
+
+impl<P, A, B> Recv<P> for Tee<A, B>
+where
+    P: Payload,
+    A: for<'a> Inspect<packet::In<'a, P>>,
+    B: for<'a> Inspect<packet::In<'a, P>>,
+{
+    fn receive(&mut self, frame: packet::In<P>) {
+        self.inspect(&frame)
+    }
+}
@@ -0,0 +1,370 @@
+//! Forwarding of received packets that are addressed to other hosts.
+//!
+//! A router receives packets for any destination, decrements their hop limit and sends them on
+//! to the next hop of their route. The packet is reused in place, only the ethernet header is
+//! rewritten besides the hop limit and checksum of the ip header.
+//!
+//! Optionally, packets without a route are answered with an ICMP destination unreachable message
+//! to their source, and packets whose hop limit expires with a time exceeded message. The message
+//! quotes the original header and the first eight bytes of its payload and is constructed in the
+//! buffer of the dropped packet. The rate limiter of the ip endpoint bounds the number of these
+//! messages.
+use crate::layer::{eth, DropReason, Error, Result};
+use crate::wire::{ethernet, icmpv4, icmpv6, ip, PayloadMut};
+
+use super::packet::{Controller, In, IpPacket};
+use super::Recv;
+
+/// The longest quote of an ipv4 header with options and eight bytes of its payload.
+const MAX_QUOTE: usize = 60 + 8;
+
+/// A receiver routing packets to other hosts back out.
+///
+/// Enable forwarding with [`Endpoint::set_forwarding`] so that packets to other destinations
+/// reach the handler at all. Packets addressed to the endpoint itself are passed to the wrapped
+/// local handler unchanged.
+///
+/// Packets whose hop limit is exhausted are dropped with `DropReason::HopLimit`, packets without a
+/// route with `DropReason::NoRoute`. While the next hop of a route is still being resolved,
+/// packets are dropped with `DropReason::Unresolved` and no error is reported. Packets exceeding
+/// the mtu of the outgoing path are not fragmented but dropped as `DropReason::Unsupported`. The
+/// source of the first two kinds can be notified with ICMP error messages, see
+/// [`set_report_time_exceeded`] and [`set_report_unreachable`]. Their rate is bounded with
+/// [`Endpoint::set_rate_limiter`].
+///
+/// [`Endpoint::set_rate_limiter`]: struct.Endpoint.html#method.set_rate_limiter
+/// [`set_report_time_exceeded`]: #method.set_report_time_exceeded
+/// [`set_report_unreachable`]: #method.set_report_unreachable
+/// [`Endpoint::set_forwarding`]: struct.Endpoint.html#method.set_forwarding
+#[derive(Debug, Default)]
+pub struct Forwarder<H> {
+    local: H,
+    reports: Reports,
+}
+
+/// The errors that are reported to the source of dropped packets.
+#[derive(Clone, Copy, Debug, Default)]
+struct Reports {
+    unreachable: bool,
+    time_exceeded: bool,
+}
+
+/// The kinds of errors reported to the source of a packet.
+#[derive(Clone, Copy)]
+enum IcmpError {
+    Unreachable,
+    TimeExceeded,
+}
+
+impl<H> Forwarder<H> {
+    /// Forward packets, passing those addressed to the endpoint on to a local handler.
+    pub fn new(local: H) -> Self {
+        Forwarder {
+            local,
+            reports: Reports::default(),
+        }
+    }
+
+    /// Query whether the source of packets without a route is notified.
+    pub fn report_unreachable(&self) -> bool {
+        self.reports.unreachable
+    }
+
+    /// Answer packets without a route with an ICMP destination unreachable message (off by
+    /// default).
+    ///
+    /// The message is sent back to the source of the packet, reusing its buffer. To avoid storms
+    /// of messages, there is no answer to ICMP error messages, non-initial fragments or packets
+    /// from a non-unicast source.
+    pub fn set_report_unreachable(&mut self, report: bool) {
+        self.reports.unreachable = report;
+    }
+
+    /// Query whether the source of packets with an exhausted hop limit is notified.
+    pub fn report_time_exceeded(&self) -> bool {
+        self.reports.time_exceeded
+    }
+
+    /// Answer packets whose hop limit expires with an ICMP time exceeded message (off by default).
+    ///
+    /// This makes the router visible to traceroute. The message is sent from the address that the
+    /// route back to the source uses, usually the one on the interface where the packet arrived.
+    /// The same restrictions as for destination unreachable messages apply.
+    pub fn set_report_time_exceeded(&mut self, report: bool) {
+        self.reports.time_exceeded = report;
+    }
+
+    /// Get a reference to the local handler.
+    pub fn local(&self) -> &H {
+        &self.local
+    }
+
+    /// Get a mutable reference to the local handler.
+    pub fn local_mut(&mut self) -> &mut H {
+        &mut self.local
+    }
+
+    /// Unwrap the local handler.
+    pub fn into_inner(self) -> H {
+        self.local
+    }
+}
+
+impl<P, H> Recv<P> for Forwarder<H>
+where
+    P: PayloadMut,
+    H: Recv<P>,
+{
+    fn receive(&mut self, packet: In<P>) {
+        let dst_addr = packet.packet.repr().dst_addr();
+        if packet.control.accepts(dst_addr) || !dst_addr.is_unicast() {
+            return self.local.receive(packet);
+        }
+
+        if let Err(_) = forward(packet, self.reports) {
+            // TODO: log error
+        }
+    }
+}
+
+/// Send a packet on to its next hop.
+fn forward<P: PayloadMut>(
+    packet: In<P>,
+    reports: Reports,
+) -> Result<()> {
+    let In { mut control, packet } = packet;
+    let repr = packet.repr();
+    let dst_addr = repr.dst_addr();
+    let ethertype = match packet {
+        IpPacket::V4(_) => ethernet::EtherType::Ipv4,
+        IpPacket::V6(_) => ethernet::EtherType::Ipv6,
+    };
+    let mut frame = packet.into_inner();
+
+    if repr.hop_limit() <= 1 {
+        control.report_drop(DropReason::HopLimit, frame.payload_slice());
+        if reports.time_exceeded && may_report(&frame, ethertype) {
+            return report(control, frame, ethertype, IcmpError::TimeExceeded);
+        }
+        return Err(Error::Illegal);
+    }
+
+    let route = match control.route_to(dst_addr) {
+        Ok(route) => route,
+        // A neighbor lookup was started, or the cache is full. The destination may well be
+        // reachable so the source is not notified.
+        Err(err) if control.has_route(dst_addr) => {
+            control.report_drop(DropReason::Unresolved, frame.payload_slice());
+            return Err(err);
+        },
+        Err(err) => {
+            control.report_drop(DropReason::NoRoute, frame.payload_slice());
+            if reports.unreachable && may_report(&frame, ethertype) {
+                return report(control, frame, ethertype, IcmpError::Unreachable);
+            }
+            return Err(err);
+        },
+    };
+
+    let fits = control.egress_mtu(dst_addr)
+        .is_some_and(|mtu| repr.total_len() <= mtu);
+    if !fits {
+        control.report_drop(DropReason::Unsupported, frame.payload_slice());
+        return Err(Error::BadSize);
+    }
+
+    let hop_limit = repr.hop_limit() - 1;
+    match ethertype {
+        ethernet::EtherType::Ipv4 => {
+            let header = ip::v4::packet::new_unchecked_mut(frame.payload_mut_slice());
+            header.set_hop_limit(hop_limit);
+            if control.info().capabilities().ipv4().tx_checksum().manual() {
+                header.fill_checksum();
+            } else {
+                header.set_checksum(0);
+            }
+        },
+        _ => {
+            let header = ip::v6::packet::new_unchecked_mut(frame.payload_mut_slice());
+            header.set_hop_limit(hop_limit);
+        },
+    }
+
+    // Keep the complete frame payload, including any padding, in place.
+    let payload = frame.payload_slice().len();
+    let vlan = frame.repr().vlan;
+    let eth_packet = eth::InPacket {
+        control: control.eth,
+        frame,
+    };
+
+    eth_packet
+        .reinit(eth::Init {
+            src_addr: route.src_mac,
+            dst_addr: route.next_mac,
+            ethertype,
+            vlan,
+            payload,
+        })?
+        .send()
+}
+
+/// Check if a dropped packet may be answered with an ICMP error message.
+fn may_report<P: PayloadMut>(frame: &ethernet::Frame<&mut P>, ethertype: ethernet::EtherType)
+    -> bool
+{
+    match ethertype {
+        ethernet::EtherType::Ipv4 => {
+            let packet = ip::v4::packet::new_unchecked(frame.payload_slice());
+            let src_addr = packet.src_addr();
+            if !src_addr.is_unicast() || packet.frag_offset() != 0 {
+                return false;
+            }
+
+            // Only answer informational messages, unknown types might be errors.
+            match (packet.protocol(), packet.payload_slice().first()) {
+                (ip::Protocol::Icmp, None) => false,
+                (ip::Protocol::Icmp, Some(&msg_type)) => matches!(
+                    icmpv4::Message::from(msg_type),
+                    icmpv4::Message::EchoRequest
+                    | icmpv4::Message::EchoReply
+                    | icmpv4::Message::RouterAdvert
+                    | icmpv4::Message::RouterSolicit
+                    | icmpv4::Message::Timestamp
+                    | icmpv4::Message::TimestampReply
+                    | icmpv4::Message::ExtendedEcho
+                    | icmpv4::Message::ExtendedReply),
+                _ => true,
+            }
+        },
+        _ => {
+            let packet = ip::v6::packet::new_unchecked(frame.payload_slice());
+            if !packet.src_addr().is_unicast() {
+                return false;
+            }
+
+            // Error messages are those with the high bit of the type cleared.
+            match (packet.next_header(), packet.payload_slice().first()) {
+                (ip::Protocol::Icmpv6, Some(&msg_type)) => msg_type & 0x80 != 0,
+                (ip::Protocol::Icmpv6, None) => false,
+                _ => true,
+            }
+        },
+    }
+}
+
+/// Turn a dropped packet into an ICMP error message to its source.
+fn report<'a, P: PayloadMut>(
+    mut control: Controller<'a>,
+    frame: ethernet::Frame<&'a mut P>,
+    ethertype: ethernet::EtherType,
+    error: IcmpError,
+) -> Result<()> {
+    // Copy the quote before the buffer is reused, the message may well be longer than the packet.
+    let mut quote = [0; MAX_QUOTE];
+    let (src_addr, quote_len) = match ethertype {
+        ethernet::EtherType::Ipv4 => {
+            let packet = ip::v4::packet::new_unchecked(frame.payload_slice());
+            let len = usize::from(packet.total_len())
+                .min(usize::from(packet.header_len()) + 8);
+            quote[..len].copy_from_slice(&packet.as_bytes()[..len]);
+            (ip::Address::Ipv4(packet.src_addr()), len)
+        },
+        _ => {
+            let packet = ip::v6::packet::new_unchecked(frame.payload_slice());
+            let len = packet.total_len().min(packet.header_len() + 8);
+            quote[..len].copy_from_slice(&packet.as_bytes()[..len]);
+            (ip::Address::Ipv6(packet.src_addr()), len)
+        },
+    };
+    let quote = &quote[..quote_len];
+
+    if !control.allow_error(src_addr) {
+        return Err(Error::Exhausted);
+    }
+
+    let route = control.route_to(src_addr)?;
+    let hop_limit = control.endpoint.hop_limit();
+    let capabilities = control.info().capabilities();
+    // Both message versions have an eight byte header before the quote.
+    let message_len = 8 + quote.len();
+    let (header_len, ip_src, ip_dst) = match (route.src_addr, src_addr) {
+        (ip::Address::Ipv4(_), ip::Address::Ipv4(_)) => (20, route.src_addr, src_addr),
+        (ip::Address::Ipv6(_), ip::Address::Ipv6(_)) => (40, route.src_addr, src_addr),
+        _ => return Err(Error::Illegal),
+    };
+
+    let vlan = frame.repr().vlan;
+    let eth_packet = eth::InPacket {
+        control: control.eth,
+        frame,
+    };
+
+    let mut out = eth_packet.reinit(eth::Init {
+        src_addr: route.src_mac,
+        dst_addr: route.next_mac,
+        ethertype,
+        vlan,
+        payload: header_len + message_len,
+    })?;
+
+    match (ip_src, ip_dst) {
+        (ip::Address::Ipv4(src_addr), ip::Address::Ipv4(dst_addr)) => {
+            let repr = ip::v4::Repr {
+                src_addr,
+                dst_addr,
+                protocol: ip::Protocol::Icmp,
+                payload_len: message_len,
+                hop_limit,
+            };
+            let packet = ip::v4::packet::new_unchecked_mut(out.payload_mut_slice());
+            repr.emit(packet, capabilities.ipv4().tx_checksum());
+            let message = icmpv4::packet::new_unchecked_mut(packet.payload_mut_slice());
+            let (msg_type, msg_code) = match error {
+                IcmpError::Unreachable => (
+                    icmpv4::Message::DstUnreachable,
+                    icmpv4::DstUnreachable::HostUnreachable.into()),
+                IcmpError::TimeExceeded => (
+                    icmpv4::Message::TimeExceeded,
+                    icmpv4::TimeExceeded::TtlExpired.into()),
+            };
+            message.set_msg_type(msg_type);
+            message.set_msg_code(msg_code);
+            // The rest of the message header is unused.
+            message.as_bytes_mut()[4..8].copy_from_slice(&[0; 4]);
+            message.payload_mut_slice().copy_from_slice(quote);
+            if capabilities.icmpv4().tx_checksum().manual() {
+                message.fill_checksum();
+            } else {
+                message.set_checksum(0);
+            }
+        },
+        (ip::Address::Ipv6(src_addr), ip::Address::Ipv6(dst_addr)) => {
+            let repr = ip::v6::Repr {
+                src_addr,
+                dst_addr,
+                next_header: ip::Protocol::Icmpv6,
+                payload_len: message_len,
+                hop_limit,
+            };
+            let packet = ip::v6::packet::new_unchecked_mut(out.payload_mut_slice());
+            repr.emit(packet);
+            let message = icmpv6::packet::new_unchecked_mut(packet.payload_mut_slice());
+            let (msg_type, msg_code) = match error {
+                // Code 3 is address unreachable.
+                IcmpError::Unreachable => (icmpv6::Message::DstUnreachable, 3),
+                // Code 0 is hop limit exceeded in transit.
+                IcmpError::TimeExceeded => (icmpv6::Message::TimeExceeded, 0),
+            };
+            message.set_msg_type(msg_type);
+            message.set_msg_code(msg_code);
+            message.as_bytes_mut()[4..8].copy_from_slice(&[0; 4]);
+            message.payload_mut_slice().copy_from_slice(quote);
+            // There is no offload for ICMPv6, its checksum is always computed.
+            message.fill_checksum(src_addr, dst_addr);
+        },
+        _ => unreachable!("Checked the address families before"),
+    }
+
+    out.send()
+}
@@ -0,0 +1,115 @@
+//! Fragmentation of outgoing IPv4 datagrams, as specified in RFC 791.
+//!
+//! The ip layer fills exactly one buffer per packet, so a datagram larger than the mtu of its path
+//! is sent as a series of fragments, one for each send opportunity. Each fragment carries a full
+//! copy of the ip header with the same identification and its own offset into the datagram.
+use crate::layer::{Error, Result};
+use crate::wire::{ip::v4, ip::Protocol, PayloadMut};
+
+use super::packet::{Init, Raw, Source};
+
+/// The length of the IPv4 header emitted by the ip layer, which never adds options.
+const HEADER_LEN: usize = 20;
+
+/// A sender of one IPv4 datagram that is fragmented to fit the path mtu.
+///
+/// Use it as an ip sender until `is_done` returns true. The mtu is queried with
+/// [`Controller::egress_mtu`] for every fragment. A datagram that fits into a single packet is
+/// sent unfragmented.
+///
+/// [`Controller::egress_mtu`]: struct.Controller.html#method.egress_mtu
+#[derive(Clone, Copy, Debug)]
+pub struct Fragmenter<'a> {
+    source: Source,
+    dst_addr: v4::Address,
+    protocol: Protocol,
+    ident: u16,
+    payload: &'a [u8],
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> Fragmenter<'a> {
+    /// The largest datagram payload, limited by the 13 bit fragment offset and the total length.
+    pub const MAX_PAYLOAD: usize = 65_515;
+
+    /// Prepare sending a datagram with the given upper layer payload.
+    ///
+    /// The identification must be unique for the source, destination and protocol for the time
+    /// the datagram is alive in the network, so that the receiver does not mix up fragments.
+    pub fn new(
+        source: Source,
+        dst_addr: v4::Address,
+        protocol: Protocol,
+        ident: u16,
+        payload: &'a [u8],
+    ) -> Result<Self> {
+        if payload.len() > Self::MAX_PAYLOAD {
+            return Err(Error::BadSize);
+        }
+
+        Ok(Fragmenter {
+            source,
+            dst_addr,
+            protocol,
+            ident,
+            payload,
+            offset: 0,
+            done: false,
+        })
+    }
+
+    /// The number of payload bytes sent so far.
+    pub fn sent(&self) -> usize {
+        self.offset
+    }
+
+    /// If all fragments have been sent.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn send_fragment<P: PayloadMut>(&mut self, raw: Raw<P>) -> Result<()> {
+        let mtu = raw.control.egress_mtu(self.dst_addr.into())
+            .ok_or(Error::Unreachable)?;
+        // All but the last fragment carry a multiple of eight bytes.
+        let max_len = mtu.checked_sub(HEADER_LEN)
+            .map(|len| len & !7)
+            .filter(|&len| len > 0)
+            .ok_or(Error::BadSize)?;
+
+        let remaining = &self.payload[self.offset..];
+        let len = remaining.len().min(max_len);
+        let more_frags = len < remaining.len();
+
+        let mut prepared = raw.prepare(Init {
+            source: self.source,
+            dst_addr: self.dst_addr.into(),
+            protocol: self.protocol,
+            payload: len,
+            hop_limit: None,
+            traffic_class: 0,
+        })?;
+        if more_frags || self.offset > 0 {
+            prepared.set_fragment(self.ident, self.offset as u16, more_frags);
+        }
+        prepared.payload_mut_slice().copy_from_slice(&remaining[..len]);
+        prepared.send()?;
+
+        self.offset += len;
+        self.done = !more_frags;
+        Ok(())
+    }
+}
+
+impl<P: PayloadMut> super::Send<P> for Fragmenter<'_> {
+    fn send(&mut self, packet: Raw<P>) {
+        if self.is_done() {
+            return;
+        }
+
+        if let Err(_) = self.send_fragment(packet) {
+            // TODO: log error
+        }
+    }
+}
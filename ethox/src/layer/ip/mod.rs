@@ -11,7 +11,9 @@
 //! addresses ([`IpAddress`]) and a unified [`Init`] structure. This generally enables the layer to
 //! transparently dispatch into the desired underlying layer.
 //!
-//! It does **not yet** provide (transparent) fragment reassembly.
+//! Large IPv4 datagrams can be sent in fragments with a [`Fragmenter`]. Received fragments are
+//! transparently reassembled if a [`Reassembler`] is installed with
+//! [`Endpoint::set_reassembler`], and dropped otherwise.
 //!
 //! ## Structure
 //!
@@ -26,7 +28,13 @@
 //! refer to some protocols wrapped into IPv6 but these have not yet been implemented).
 //!
 //! For all other packets the destination addresses are checked against the configured addresses of
-//! the receiving endpoint. They are subsequently forwarded to the upper layer handler.
+//! the receiving endpoint, including shared addresses added with [`Endpoint::set_served`]. They
+//! are subsequently forwarded to the upper layer handler.
+//! Packets nesting more extension headers or encapsulated IP headers than the configured
+//! [`Endpoint::set_max_depth`] are dropped before reaching any handler.
+//!
+//! With [`Endpoint::set_forwarding`] the endpoint acts as a router and also receives unicast
+//! packets to other destinations. A [`Forwarder`] sends these on to their next hop.
 //!
 //! ## Transmitting packets
 //!
@@ -36,7 +44,8 @@
 //! user, in which case it is *not* checked against the configured addresses. The layer will
 //! translate the desired destination address to a corresponding next hop. Control over extension
 //! headers *is not* supported (but you could rewrite the packet buffer after initialization
-//! yourself).
+//! yourself). With [`Endpoint::set_tables`], the next hop can also be looked up in one of several
+//! routing tables, selected by the mark of the packet.
 //!
 //! Note that the configured next hop might be missing a resolved link-layer address. In this case,
 //! the init call will return an error but the request for this resolution is stored in an internal
@@ -44,25 +53,50 @@
 //! buffer begin available and an internal rate limit. Only buffers that are not used for the
 //! purpose of neighbor discovery are available to the upper layers.
 //!
+//! [`Endpoint::set_forwarding`]: struct.Endpoint.html#method.set_forwarding
+//! [`Endpoint::set_max_depth`]: struct.Endpoint.html#method.set_max_depth
+//! [`Endpoint::set_reassembler`]: struct.Endpoint.html#method.set_reassembler
+//! [`Endpoint::set_served`]: struct.Endpoint.html#method.set_served
+//! [`Endpoint::set_tables`]: struct.Endpoint.html#method.set_tables
+//! [`Forwarder`]: struct.Forwarder.html
+//! [`Fragmenter`]: struct.Fragmenter.html
 //! [`Init`]: struct.Init.html
 //! [`IpAddress`]: ../../wire/enum.IpAddress.html
 //! [`IpPacket`]: enum.IpPacket.html
+//! [`Reassembler`]: struct.Reassembler.html
 use crate::wire::Payload;
 
+mod assignment;
 mod endpoint;
+mod forward;
+mod fragment;
 mod packet;
+mod reassembly;
 mod route;
 #[cfg(test)]
 mod tests;
 
+pub use assignment::Assignment;
+
+pub use forward::Forwarder;
+
+pub use fragment::Fragmenter;
+
+pub use reassembly::{Reassembler, ReassemblyBuffer};
+
 pub use endpoint::{
+    DEFAULT_DEVICE_MTU,
+    DEFAULT_HOP_LIMIT,
+    DEFAULT_MAX_DEPTH,
     Endpoint,
+    OptionPolicy,
     Receiver,
     Sender,
 };
 
 pub use packet::{
     Controller,
+    Fragmentation,
     Init,
     IpPacket,
     V4Packet,
@@ -74,7 +108,10 @@ pub use packet::{
 };
 
 pub use route::{
+    Encapsulation,
+    Metadata,
     Route,
+    RouteInfo,
     Routes,
 };
 
@@ -1,4 +1,5 @@
-use crate::layer::{Error, Result, eth};
+use crate::layer::{DropReason, Error, Result, eth};
+use crate::layer::trace::Event;
 use crate::nic::{self, Info};
 use crate::time::Instant;
 use crate::wire::{ethernet, ip};
@@ -44,6 +45,7 @@ pub struct Raw<'a, P: Payload> {
 pub struct Controller<'a> {
     pub(crate) eth: eth::Controller<'a>,
     pub(crate) endpoint: &'a mut dyn Endpoint,
+    pub(crate) mark: u32,
 }
 
 /// An IPv4 packet within an ethernet frame.
@@ -72,45 +74,115 @@ pub struct Init {
     pub protocol: ip::Protocol,
     /// The length to reserved for the payload.
     pub payload: usize,
+    /// The hop limit of the packet, the TTL for IPv4.
+    ///
+    /// When `None` the default hop limit of the endpoint is used. Packets to multicast groups
+    /// instead default to a hop limit of 1 which keeps them on the local link, see [RFC 1112].
+    /// Set it explicitly to reach groups of a wider scope.
+    ///
+    /// [RFC 1112]: https://tools.ietf.org/html/rfc1112
+    pub hop_limit: Option<u8>,
+    /// The IPv4 type of service or the IPv6 traffic class octet.
+    ///
+    /// Holds the differentiated services code point in the upper six bits and the explicit
+    /// congestion notification in the lower two bits. Zero requests the default service.
+    pub traffic_class: u8,
 }
 
 /// A source selector specification.
+///
+/// The selector decides the source address of outgoing packets. The route to the destination
+/// provides a default address, which is used as long as the selector permits it.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Source {
     /// The source address must match a subnet.
+    ///
+    /// The default of the route is used if the subnet contains it, otherwise the first configured
+    /// address in the subnet. Packets are not sent and fail with `Unreachable` when there is no
+    /// such address.
     Mask {
         /// The subnet mask which should contain the source address.
         subnet: ip::Subnet,
     },
 
+    /// Prefer an address from a subnet, otherwise use the default of the route.
+    ///
+    /// Useful on hosts with addresses in several networks, for example to reply from the address
+    /// on the network of the peer. Falls back to the source address the route would select when
+    /// no configured address is contained in the subnet.
+    Prefer {
+        /// The subnet which should preferably contain the source address.
+        subnet: ip::Subnet,
+    },
+
     /// Some preselected address should be used.
     ///
     /// Required for established connections that are identified by an address tuple, such as in
-    /// the case of TCP and UDP.
+    /// the case of TCP and UDP. The address must be a unicast address accepted by the endpoint,
+    /// that is one of its configured or served addresses, or the unspecified address for hosts
+    /// without any address yet. Packets from other addresses are not sent and fail with `Illegal`.
     Exact(ip::Address),
 }
 
+/// Whether a packet may be fragmented on its path.
+///
+/// Corresponds to the inverse of the Don't Fragment flag of IPv4. IPv6 has no such flag as only
+/// the source may fragment there, so the policy has no effect on IPv6 packets.
+///
+/// The policy does not fragment the packet at its source. Under either policy, the ip layer refuses
+/// packets larger than the mtu of the nic or of their [`Route`] with `BadSize`. Larger IPv4
+/// datagrams are sent as a series of fragments with a [`Fragmenter`] instead, which clears the
+/// flag on each fragment. Keeping packets small enough, for example by reacting to ICMP errors of
+/// routers on the path, is up to the upper layers.
+///
+/// [`Route`]: struct.Route.html
+/// [`Fragmenter`]: struct.Fragmenter.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Fragmentation {
+    /// Set the Don't Fragment flag, which routers answer with an ICMP error if the packet exceeds
+    /// the MTU of the next link.
+    #[default]
+    Dont,
+
+    /// Clear the Don't Fragment flag, allowing routers to fragment the packet.
+    Allow,
+}
+
 /// Source and destination chosen for a particular routing.
 pub(crate) struct Route {
     pub(crate) next_hop: ip::Address,
     pub(crate) src_addr: ip::Address,
+    pub(crate) mtu: Option<u16>,
 }
 
 #[derive(Clone, Copy)]
-struct EthRoute {
-    src_mac: ethernet::Address,
-    src_addr: ip::Address,
-    next_mac: ethernet::Address,
+pub(crate) struct EthRoute {
+    pub(crate) src_mac: ethernet::Address,
+    pub(crate) src_addr: ip::Address,
+    pub(crate) next_mac: ethernet::Address,
+    pub(crate) mtu: Option<u16>,
 }
 
 /// The interface to the endpoint.
 pub(crate) trait Endpoint{
     /// Get the ip to use on a link by providing the subnet in which it should be routed.
     fn local_ip(&self, subnet: ip::Subnet) -> Option<ip::Address>;
-    /// Find a Route a destination at the current time.
-    fn route(&self, dst_addr: ip::Address, time: Instant) -> Option<Route>;
+    /// Find a Route a destination for a packet with a mark at the current time.
+    fn route(&self, dst_addr: ip::Address, mark: u32, time: Instant) -> Option<Route>;
+    /// The largest packet that can be sent to a destination with a mark at the current time.
+    fn egress_mtu(&self, dst_addr: ip::Address, mark: u32, time: Instant) -> Option<usize>;
+    /// The hop limit of packets that do not specify their own.
+    fn hop_limit(&self) -> u8;
+    /// Query if the configured or served addresses contain this destination.
+    fn accepts(&self, dst_addr: ip::Address) -> bool;
+    /// Find the assignment of an address to the interface.
+    fn assignment(&self, addr: ip::Address) -> Option<super::Assignment>;
+    /// Consult the rate limiter on whether an ICMP error to a destination may be sent.
+    fn allow_error(&mut self, dst_addr: ip::Address, time: Instant) -> bool;
     /// Resolve an address. If `look` is true, try to actively lookup it up later.
     fn resolve(&mut self, _: ip::Address, _: Instant, look: bool) -> Result<ethernet::Address>;
+    /// Update the mapping of a neighbor, `confirmed` if it was solicited by us.
+    fn learn(&mut self, _: ip::Address, _: Option<ethernet::Address>, _: Instant, confirmed: bool);
 }
 
 impl<'a> Controller<'a> {
@@ -118,7 +190,7 @@ impl<'a> Controller<'a> {
         wrap: impl FnOnce(&'a mut dyn nic::Handle) -> &'a mut dyn nic::Handle,
     ) -> Self {
         let eth = self.eth.wrap(wrap);
-        Controller { eth, endpoint: self.endpoint }
+        Controller { eth, endpoint: self.endpoint, mark: self.mark }
     }
 
     /// Get the hardware info for that packet.
@@ -126,35 +198,119 @@ impl<'a> Controller<'a> {
         self.eth.info()
     }
 
+    /// Report a packet that was dropped to the hook of the ethernet endpoint.
+    pub fn report_drop(&mut self, reason: DropReason, bytes: &[u8]) {
+        self.eth.report_drop(reason, bytes)
+    }
+
     /// Proof to the compiler that we can shorten the lifetime arbitrarily.
     pub fn borrow_mut(&mut self) -> Controller {
         Controller {
             eth: self.eth.borrow_mut(),
             endpoint: self.endpoint,
+            mark: self.mark,
         }
     }
 
+    /// The mark of the packet.
+    pub fn mark(&self) -> u32 {
+        self.mark
+    }
+
+    /// Mark the packet, which can select the routing table used for it.
+    ///
+    /// The mark is only metadata and never appears on the wire. Packets start out with a mark of
+    /// `0`. See [`Endpoint::set_tables`] for how it is used.
+    ///
+    /// [`Endpoint::set_tables`]: struct.Endpoint.html#method.set_tables
+    pub fn set_mark(&mut self, mark: u32) {
+        self.mark = mark;
+    }
+
     /// Get the local endpoint IP to use as source on some subnet.
     pub fn local_ip(&self, subnet: ip::Subnet) -> Option<ip::Address> {
         self.endpoint.local_ip(subnet)
     }
 
+    /// The largest ip packet that can be sent to a destination, see [`Endpoint::egress_mtu`].
+    ///
+    /// Uses the mark of this packet and the current time for the routing decision. The result is
+    /// further limited by the mtu the nic reports for the buffer of this packet.
+    ///
+    /// [`Endpoint::egress_mtu`]: struct.Endpoint.html#method.egress_mtu
+    pub fn egress_mtu(&self, dst_addr: ip::Address) -> Option<usize> {
+        let time = self.info().timestamp();
+        self.endpoint.egress_mtu(dst_addr, self.mark, time)
+            .map(|mtu| mtu.min(self.info().mtu()))
+    }
+
+    /// Query if a destination is one of the configured or served addresses of the endpoint.
+    ///
+    /// Packets to other destinations are only received when forwarding is enabled.
+    pub fn accepts(&self, dst_addr: ip::Address) -> bool {
+        self.endpoint.accepts(dst_addr)
+    }
+
+    /// The assignment of an address to the interface, with its lifetimes.
+    pub(crate) fn assignment(&self, addr: ip::Address) -> Option<super::Assignment> {
+        self.endpoint.assignment(addr)
+    }
+
+    /// Check if an ICMP error message to a destination may be sent now.
+    ///
+    /// Consumes a token of the rate limiter of the endpoint, if it has one.
+    pub(crate) fn allow_error(&mut self, dst_addr: ip::Address) -> bool {
+        let time = self.info().timestamp();
+        self.endpoint.allow_error(dst_addr, time)
+    }
+
     /// Try to initialize the destination from an upper layer protocol address.
     ///
     /// Failure to satisfy the request is clearly signalled. Use the result to initialize the
     /// representation to a valid eth frame.
+    ///
+    /// On a device without hardware addresses, see [`Medium::Ip`], every destination resolves to
+    /// the broadcast address without any lookup.
+    ///
+    /// [`Medium::Ip`]: ../../nic/enum.Medium.html#variant.Ip
     pub fn resolve(&mut self, dst_addr: ip::Address)
         -> Result<ethernet::Address>
     {
+        if self.info().medium() == nic::Medium::Ip {
+            return Ok(ethernet::Address::BROADCAST);
+        }
+
         let time = self.info().timestamp();
         self.endpoint.resolve(dst_addr, time, true)
     }
 
-    fn route_to(&mut self, dst_addr: ip::Address) -> Result<EthRoute> {
+    /// Query if the routing has a next hop for a destination, resolved or not.
+    pub(crate) fn has_route(&self, dst_addr: ip::Address) -> bool {
+        let now = self.eth.info().timestamp();
+        self.endpoint.route(dst_addr, self.mark, now).is_some()
+    }
+
+    /// Update the neighbor cache with a mapping learned from neighbor discovery.
+    ///
+    /// Entries are only created by lookups, other entries are left alone. An unconfirmed mapping
+    /// replaces an unresolved or different address but does not refresh an identical one, a
+    /// confirmed mapping additionally marks the neighbor reachable.
+    pub(crate) fn learn(
+        &mut self,
+        addr: ip::Address,
+        hw_addr: Option<ethernet::Address>,
+        confirmed: bool,
+    ) {
+        let time = self.info().timestamp();
+        self.endpoint.learn(addr, hw_addr, time, confirmed)
+    }
+
+    pub(crate) fn route_to(&mut self, dst_addr: ip::Address) -> Result<EthRoute> {
         let now = self.eth.info().timestamp();
-        let Route { next_hop, src_addr } = self.endpoint
-            .route(dst_addr, now)
+        let Route { next_hop, src_addr, mtu } = self.endpoint
+            .route(dst_addr, self.mark, now)
             .ok_or(Error::Unreachable)?;
+        self.eth.trace(Event::Routed { dst_addr, next_hop });
         let next_mac = self.resolve(next_hop)?;
         let src_mac = self.eth.src_addr();
 
@@ -162,11 +318,49 @@ impl<'a> Controller<'a> {
             src_mac,
             src_addr,
             next_mac,
+            mtu,
         })
     }
+
+    /// Route to a destination, choosing the source address with a selector.
+    pub(crate) fn route_from(&mut self, source: Source, dst_addr: ip::Address)
+        -> Result<EthRoute>
+    {
+        let mut route = self.route_to(dst_addr)?;
+        route.src_addr = self.select_source(source, route.src_addr)?;
+        Ok(route)
+    }
+
+    /// Apply the source selector to the default source address of a route.
+    fn select_source(&self, source: Source, default: ip::Address) -> Result<ip::Address> {
+        match source {
+            Source::Exact(addr) if addr.is_unspecified() => Ok(addr),
+            Source::Exact(addr) if addr.is_unicast() && self.endpoint.accepts(addr) => Ok(addr),
+            Source::Exact(_) => Err(Error::Illegal),
+            Source::Mask { subnet } if subnet.contains(default) => Ok(default),
+            Source::Mask { subnet } => self.local_ip(subnet).ok_or(Error::Unreachable),
+            Source::Prefer { subnet } if subnet.contains(default) => Ok(default),
+            Source::Prefer { subnet } => Ok(self.local_ip(subnet).unwrap_or(default)),
+        }
+    }
 }
 
 impl<'a, P: Payload> In<'a, P> {
+    /// The parsed representation of the packet.
+    pub fn repr(&self) -> ip::Repr {
+        self.packet.repr()
+    }
+
+    /// The address of the sender of the packet.
+    pub fn source_addr(&self) -> ip::Address {
+        self.packet.repr().src_addr()
+    }
+
+    /// The address to which the packet was sent.
+    pub fn dest_addr(&self) -> ip::Address {
+        self.packet.repr().dst_addr()
+    }
+
     /// Deconstruct the packet into the reusable buffer.
     pub fn deinit(self) -> Raw<'a, P>
         where P: PayloadMut,
@@ -180,25 +374,35 @@ impl<'a, P: Payload> In<'a, P> {
 
 impl<'a, P: PayloadMut> In<'a, P> {
     /// Reinitialize the buffer with a packet generated by the library.
+    ///
+    /// All checks are done before the buffer is modified. If the buffer can not be resized to the
+    /// new packet length an error is returned and the contents of the buffer are unchanged. In
+    /// any case, nothing is sent when this returns an error.
     // TODO: guarantee payload preserved?
     pub fn reinit(mut self, init: Init) -> Result<Out<'a, P>> {
-        let route = self.control.route_to(init.dst_addr)?;
-        let lower_init = init.init_eth(route, init.payload)?;
+        let route = self.control.route_from(init.source, init.dst_addr)?;
+        let hop_limit = init.hop_limit_or(self.control.endpoint.hop_limit());
+        let repr = init.ip_repr(route.src_addr, hop_limit)?;
+        let mut lower_init = init.init_eth(route, init.payload)?;
+        let frame = self.packet.into_inner();
+        // Answers stay within the virtual lan of the received packet.
+        lower_init.vlan = frame.repr().vlan;
 
         let eth_packet = eth::InPacket {
             control: self.control.eth,
-            frame: self.packet.into_inner(),
+            frame,
         };
 
         // TODO: optimize in case frame already contains the right IP packet.
         let packet = eth_packet.reinit(lower_init)?;
         let eth::InPacket { control, mut frame } = packet.into_incoming();
-        let repr = init.initialize(route.src_addr, &mut frame)?;
+        init.initialize(&repr, &mut frame);
 
         Ok(Out {
             control: Controller {
                 eth: control,
                 endpoint: self.control.endpoint,
+                mark: self.control.mark,
             },
             packet: IpPacket::new_unchecked(frame, repr),
         })
@@ -251,6 +455,17 @@ impl<'a, P: PayloadMut> Out<'a, P> {
         lower.send()
     }
 
+    /// Mark an IPv4 packet as a fragment of a larger datagram.
+    ///
+    /// The offset is in bytes and must be a multiple of eight unless this is the last fragment.
+    /// This also clears the Don't Fragment flag. Has no effect on IPv6 packets.
+    pub(crate) fn set_fragment(&mut self, ident: u16, offset: u16, more_frags: bool) {
+        if let IpPacket::V4(packet) = &mut self.packet {
+            packet.set_dont_frag(false);
+            packet.set_fragment(ident, more_frags, offset);
+        }
+    }
+
     /// A mutable slice containing the payload of the contained protocol.
     ///
     /// This returns the IPv4 and IPv6 payload respectively. Note that the checksum is finalized
@@ -268,9 +483,29 @@ impl<'a, P: Payload + PayloadMut> Raw<'a, P> {
         &self.control
     }
 
+    /// Use the buffer for an ethernet frame instead of an ip packet.
+    ///
+    /// Allows sending frames of other link layer protocols from an ip sender.
+    pub fn into_eth(self) -> eth::RawPacket<'a, P> {
+        eth::RawPacket {
+            control: self.control.eth,
+            payload: self.payload,
+        }
+    }
+
     /// Initialize to a valid ip packet.
+    ///
+    /// Fails with `BadSize` before touching the buffer if the packet would exceed the mtu reported
+    /// by the nic.
     pub fn prepare(mut self, init: Init) -> Result<Out<'a, P>> {
-        let route = self.control.route_to(init.dst_addr)?;
+        // Fail early, before a neighbor lookup may be started for the route.
+        if init.packet_len() > self.control.info().mtu() {
+            return Err(Error::BadSize);
+        }
+
+        let route = self.control.route_from(init.source, init.dst_addr)?;
+        let hop_limit = init.hop_limit_or(self.control.endpoint.hop_limit());
+        let repr = init.ip_repr(route.src_addr, hop_limit)?;
         let lower_init = init.init_eth(route, init.payload)?;
 
         let lower = eth::RawPacket {
@@ -280,12 +515,13 @@ impl<'a, P: Payload + PayloadMut> Raw<'a, P> {
 
         let packet = lower.prepare(lower_init)?;
         let eth::InPacket { control, mut frame } = packet.into_incoming();
-        let repr = init.initialize(route.src_addr, &mut frame)?;
+        init.initialize(&repr, &mut frame);
 
         Ok(Out {
             control: Controller {
                 eth: control,
                 endpoint: self.control.endpoint,
+                mark: self.control.mark,
             },
             packet: IpPacket::new_unchecked(frame, repr),
         })
@@ -293,23 +529,61 @@ impl<'a, P: Payload + PayloadMut> Raw<'a, P> {
 }
 
 impl Init {
-    fn initialize(&self, src_addr: ip::Address, payload: &mut impl PayloadMut) -> Result<ip::Repr> {
-        let repr = self.ip_repr(src_addr)?;
+    fn initialize(&self, repr: &ip::Repr, payload: &mut impl PayloadMut) {
+        let buffer = payload.payload_mut().as_mut_slice();
         // Emit the packet but ignore the checksum for now. it is filled in later when calling
         // `OutPacket::send`.
-        repr.emit(payload.payload_mut().as_mut_slice(), Checksum::Ignored);
-        Ok(repr)
+        repr.emit(&mut *buffer, Checksum::Ignored);
+        match repr {
+            ip::Repr::Ipv4(_) => {
+                let packet = ip::v4::packet::new_unchecked_mut(buffer);
+                packet.set_dscp(self.traffic_class >> 2);
+                packet.set_ecn(self.traffic_class);
+            },
+            ip::Repr::Ipv6(_) => {
+                ip::v6::packet::new_unchecked_mut(buffer).set_traffic_class(self.traffic_class);
+            },
+            _ => (),
+        }
+    }
+
+    /// The length of the packet including the ip header.
+    fn packet_len(&self) -> usize {
+        match self.dst_addr {
+            // TODO: non-hardcode for extension headers.
+            ip::Address::Ipv6(_) => self.payload.saturating_add(40),
+            _ => self.payload.saturating_add(20),
+        }
+    }
+
+    /// The hop limit of the packet, given the default of the endpoint.
+    fn hop_limit_or(&self, default: u8) -> u8 {
+        match self.hop_limit {
+            Some(hop_limit) => hop_limit,
+            None if self.dst_addr.is_multicast() => 1,
+            None => default,
+        }
     }
 
     /// Resolve the ip representation without initializing the packet.
-    fn ip_repr(&self, src_addr: ip::Address) -> Result<ip::Repr> {
+    fn ip_repr(&self, src_addr: ip::Address, hop_limit: u8) -> Result<ip::Repr> {
         let repr = ip::Repr::Unspecified {
             src_addr,
             dst_addr: self.dst_addr,
-            hop_limit: u8::max_value(),
+            hop_limit,
             protocol: self.protocol,
             payload_len: self.payload,
         };
+
+        // Both length fields are 16 bits wide, the one of IPv4 also covers the header.
+        let length = match self.dst_addr {
+            ip::Address::Ipv6(_) => self.payload,
+            _ => self.packet_len(),
+        };
+        if length > usize::from(u16::MAX) {
+            return Err(Error::BadSize);
+        }
+
         repr.lower(&[]).ok_or(Error::Illegal)
     }
 
@@ -329,6 +603,7 @@ impl Init {
                 Protocol::Ipv4 => ethernet::EtherType::Ipv4,
                 Protocol::Ipv6 => ethernet::EtherType::Ipv6,
             },
+            vlan: None,
             // TODO: use the methods provided from `wire::*Repr`.
             payload: match protocol {
                 Protocol::Ipv4 => payload + 20,
@@ -336,6 +611,14 @@ impl Init {
                 Protocol::Ipv6 => payload + 40,
             },
         };
+
+        // Packets are not fragmented implicitly, those too large for the path are refused.
+        if let Some(mtu) = route.mtu {
+            if eth_init.payload > usize::from(mtu) {
+                return Err(Error::BadSize);
+            }
+        }
+
         Ok(eth_init)
     }
 }
@@ -375,6 +658,19 @@ impl<'a, P: Payload> IpPacket<'a, P> {
     pub fn into_raw(self) -> &'a mut P {
         self.into_inner().into_inner()
     }
+
+    /// Change whether the packet may be fragmented on its path.
+    ///
+    /// The checksum of an IPv4 header must be filled again afterwards, which happens when sending
+    /// the packet.
+    pub fn set_fragmentation(&mut self, fragmentation: Fragmentation)
+        where P: PayloadMut,
+    {
+        match self {
+            IpPacket::V4(packet) => packet.set_dont_frag(fragmentation == Fragmentation::Dont),
+            IpPacket::V6(_) => (),
+        }
+    }
 }
 
 impl<'a, P: Payload> Payload for IpPacket<'a, P> {
@@ -0,0 +1,225 @@
+//! Reassembly of incoming IPv4 fragments, as specified in RFC 791 and RFC 815.
+//!
+//! Fragments are copied into one of a small number of buffers, one for each datagram in progress.
+//! The ip endpoint consults its [`Reassembler`] for each received fragment and, once a datagram
+//! is complete, writes it into the buffer of the last fragment. It is then handled like any other
+//! packet, so that upper layers never observe fragments.
+//!
+//! [`Reassembler`]: struct.Reassembler.html
+use crate::layer::{Error, Result};
+use crate::managed::Slice;
+use crate::time::{Duration, Instant};
+use crate::wire::ip::{v4, Protocol};
+
+/// Number of words in the bitmap of received blocks, covering the largest possible datagram.
+const BITMAP_WORDS: usize = 65_536 / 8 / 64;
+
+/// Identifies the fragments belonging to the same datagram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Key {
+    src_addr: v4::Address,
+    dst_addr: v4::Address,
+    protocol: Protocol,
+    ident: u16,
+}
+
+/// The storage for one datagram in progress.
+///
+/// Datagrams longer than the buffer are dropped. The buffer is reused once the datagram is
+/// complete, dropped or timed out.
+#[derive(Debug)]
+pub struct ReassemblyBuffer<'a> {
+    buffer: Slice<'a, u8>,
+    key: Option<Key>,
+    /// One bit for each block of eight bytes that was received.
+    received: [u64; BITMAP_WORDS],
+    /// The length of the datagram, known once the last fragment arrived.
+    total_len: Option<usize>,
+    deadline: Instant,
+}
+
+/// Reassembles IPv4 datagrams from their fragments.
+///
+/// Install it with [`Endpoint::set_reassembler`]. A datagram is dropped when it is not complete
+/// within the configured timeout, when any of its fragments overlap, or when it does not fit into
+/// the buffer. When all buffers are in use a new datagram evicts the one that was started first.
+///
+/// [`Endpoint::set_reassembler`]: struct.Endpoint.html#method.set_reassembler
+#[derive(Debug)]
+pub struct Reassembler<'a> {
+    buffers: Slice<'a, ReassemblyBuffer<'a>>,
+    timeout: Duration,
+}
+
+impl<'a> ReassemblyBuffer<'a> {
+    /// Create an unused buffer with storage for the payload of a datagram.
+    pub fn new<B>(buffer: B) -> Self
+        where B: Into<Slice<'a, u8>>,
+    {
+        ReassemblyBuffer {
+            buffer: buffer.into(),
+            key: None,
+            received: [0; BITMAP_WORDS],
+            total_len: None,
+            deadline: Instant::from_millis(0),
+        }
+    }
+
+    /// If a datagram is currently being reassembled in this buffer.
+    pub fn is_used(&self) -> bool {
+        self.key.is_some()
+    }
+
+    fn start(&mut self, key: Key, deadline: Instant) {
+        self.key = Some(key);
+        self.received = [0; BITMAP_WORDS];
+        self.total_len = None;
+        self.deadline = deadline;
+    }
+
+    fn any_received(&self, blocks: core::ops::Range<usize>) -> bool {
+        blocks.into_iter().any(|block| self.received[block / 64] & (1 << (block % 64)) != 0)
+    }
+
+    fn all_received(&self, blocks: core::ops::Range<usize>) -> bool {
+        blocks.into_iter().all(|block| self.received[block / 64] & (1 << (block % 64)) != 0)
+    }
+
+    fn mark_received(&mut self, blocks: core::ops::Range<usize>) {
+        for block in blocks {
+            self.received[block / 64] |= 1 << (block % 64);
+        }
+    }
+
+    /// Add a fragment, returning the length of the datagram if it is complete.
+    fn add(&mut self, offset: usize, payload: &[u8], more_frags: bool) -> Result<Option<usize>> {
+        let end = offset + payload.len();
+        if end > self.buffer.len() {
+            return Err(Error::BadSize);
+        }
+
+        let blocks = offset / 8..end.div_ceil(8);
+        if self.any_received(blocks.clone()) {
+            return Err(Error::Illegal);
+        }
+
+        match self.total_len {
+            // Fragments must not extend past the end of the datagram.
+            Some(total_len) if end > total_len || !more_frags => return Err(Error::Illegal),
+            // Nor may the end be before any already received data.
+            None if !more_frags && self.any_received(blocks.end..8*BITMAP_WORDS) => {
+                return Err(Error::Illegal)
+            },
+            _ => (),
+        }
+
+        self.buffer[offset..end].copy_from_slice(payload);
+        self.mark_received(blocks);
+        if !more_frags {
+            self.total_len = Some(end);
+        }
+
+        Ok(self.total_len.filter(|&total_len| self.all_received(0..total_len.div_ceil(8))))
+    }
+}
+
+impl<'a> Reassembler<'a> {
+    /// The default time in which all fragments of a datagram must arrive.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+    /// Create a reassembler from a number of buffers.
+    pub fn new<B>(buffers: B) -> Self
+        where B: Into<Slice<'a, ReassemblyBuffer<'a>>>,
+    {
+        Reassembler {
+            buffers: buffers.into(),
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// The time in which all fragments of a datagram must arrive.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Change the time in which all fragments of a datagram must arrive.
+    ///
+    /// Only affects datagrams started afterwards.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// The number of datagrams currently in progress.
+    pub fn in_progress(&self) -> usize {
+        self.buffers.iter().filter(|buffer| buffer.is_used()).count()
+    }
+
+    /// Drop all datagrams that timed out, returning their number.
+    pub fn flush_expired(&mut self, now: Instant) -> usize {
+        let mut dropped = 0;
+        for buffer in self.buffers.iter_mut() {
+            if buffer.is_used() && buffer.deadline <= now {
+                buffer.key = None;
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+
+    /// Add a received fragment.
+    ///
+    /// Returns the payload of the datagram once it is complete, and frees its buffer. Datagrams
+    /// with invalid or overlapping fragments are dropped. If all buffers are in use, the oldest
+    /// datagram is dropped instead and `Err(Error::Exhausted)` is returned.
+    pub(crate) fn insert(&mut self, fragment: &v4::packet, now: Instant) -> Result<Option<&[u8]>> {
+        self.flush_expired(now);
+
+        let key = Key {
+            src_addr: fragment.src_addr(),
+            dst_addr: fragment.dst_addr(),
+            protocol: fragment.protocol(),
+            ident: fragment.ident(),
+        };
+        let offset = usize::from(fragment.frag_offset());
+        let payload = fragment.payload_slice();
+        let more_frags = fragment.more_frags();
+
+        // All but the last fragment carry a multiple of eight bytes.
+        if more_frags && payload.len() & 7 != 0 {
+            return Err(Error::Illegal);
+        }
+
+        let index = match self.buffers.iter().position(|buffer| buffer.key == Some(key)) {
+            Some(index) => index,
+            None => self.start(key, now)?,
+        };
+
+        let buffer = &mut self.buffers[index];
+        match buffer.add(offset, payload, more_frags) {
+            Ok(Some(total_len)) => {
+                buffer.key = None;
+                Ok(Some(&buffer.buffer[..total_len]))
+            },
+            Ok(None) => Ok(None),
+            Err(err) => {
+                buffer.key = None;
+                Err(err)
+            },
+        }
+    }
+
+    /// Find a buffer for a new datagram.
+    fn start(&mut self, key: Key, now: Instant) -> Result<usize> {
+        let deadline = now + self.timeout;
+        if let Some(index) = self.buffers.iter().position(|buffer| !buffer.is_used()) {
+            self.buffers[index].start(key, deadline);
+            return Ok(index);
+        }
+
+        if let Some(oldest) = self.buffers.iter_mut().min_by_key(|buffer| buffer.deadline) {
+            oldest.key = None;
+        }
+
+        Err(Error::Exhausted)
+    }
+}
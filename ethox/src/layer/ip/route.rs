@@ -20,6 +20,28 @@ pub struct Route {
 
     /// Expired routes are never considered.
     pub expires_at: Expiration,
+
+    /// Until when this route is preferred over equally specific ones.
+    ///
+    /// A route past this point is still used, but only if no other matching route with the same
+    /// prefix length is preferred at that time. This allows a backup route to take over from a
+    /// primary one without removing it.
+    pub preferred_until: Expiration,
+
+    /// The mtu of the path via this route, including the ip header.
+    ///
+    /// `None` uses the mtu of the link. Packets sent via this route must not be larger.
+    pub mtu: Option<u16>,
+}
+
+/// The result of a routing table lookup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RouteInfo {
+    /// The next hop towards the destination.
+    pub via_router: Address,
+
+    /// The mtu of the path, if the route restricts it.
+    pub mtu: Option<u16>,
 }
 
 impl Route {
@@ -32,6 +54,8 @@ impl Route {
             net: Cidr::new(Address::v4(0, 0, 0, 0), 0).subnet(),
             next_hop: Address::Unspecified,
             expires_at: Expiration::Never,
+            preferred_until: Expiration::Never,
+            mtu: None,
         }
     }
 
@@ -44,6 +68,8 @@ impl Route {
             net: Cidr::new(Address::v4(0, 0, 0, 0), 0).subnet(),
             next_hop: Address::v4(0, 0, 0, 0).into(),
             expires_at: Expiration::Never,
+            preferred_until: Expiration::Never,
+            mtu: None,
         }
     }
 
@@ -53,6 +79,8 @@ impl Route {
             net: Cidr::new(Address::v6(0, 0, 0, 0, 0, 0, 0, 0), 0).subnet(),
             next_hop: Address::v6(0, 0, 0, 0, 0, 0, 0, 0).into(),
             expires_at: Expiration::Never,
+            preferred_until: Expiration::Never,
+            mtu: None,
         }
     }
 
@@ -65,6 +93,8 @@ impl Route {
             net: Cidr::new(Address::v4(0, 0, 0, 0), 0).subnet(),
             next_hop: gateway.into(),
             expires_at: Expiration::Never,
+            preferred_until: Expiration::Never,
+            mtu: None,
         }
     }
 
@@ -77,10 +107,64 @@ impl Route {
             net: Cidr::new(Address::v6(0, 0, 0, 0, 0, 0, 0, 0), 0).subnet(),
             next_hop: gateway.into(),
             expires_at: Expiration::Never,
+            preferred_until: Expiration::Never,
+            mtu: None,
         }
     }
 }
 
+/// The metadata of a packet available to routing decisions.
+///
+/// See [`Endpoint::set_tables`], [`Endpoint::set_next_hop_hook`] and
+/// [`Endpoint::set_encapsulation_hook`].
+///
+/// [`Endpoint::set_tables`]: struct.Endpoint.html#method.set_tables
+/// [`Endpoint::set_next_hop_hook`]: struct.Endpoint.html#method.set_next_hop_hook
+/// [`Endpoint::set_encapsulation_hook`]: struct.Endpoint.html#method.set_encapsulation_hook
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Metadata {
+    /// The mark of the packet, set with [`Controller::set_mark`].
+    ///
+    /// [`Controller::set_mark`]: struct.Controller.html#method.set_mark
+    pub mark: u32,
+
+    /// The destination address of the packet.
+    pub dst_addr: Address,
+}
+
+/// The headers added to packets between the ip layer and the device.
+///
+/// Describes one path for [`Endpoint::egress_mtu`]. The ethernet header itself is always
+/// accounted for and not part of this description.
+///
+/// [`Endpoint::egress_mtu`]: struct.Endpoint.html#method.egress_mtu
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Encapsulation {
+    /// The number of 802.1Q tags in the ethernet header.
+    pub vlan_tags: u8,
+
+    /// The length of all tunnel headers wrapped around the packet, in bytes.
+    ///
+    /// This includes the outer ip header and any transport or tunnel protocol header, for example
+    /// 20 bytes of IPv4 and 4 bytes of GRE.
+    pub tunnel_len: usize,
+
+    /// The length of the IPv6 extension headers of the packet, in bytes.
+    pub extension_len: usize,
+}
+
+impl Encapsulation {
+    /// The length of a single 802.1Q tag.
+    pub const VLAN_TAG_LEN: usize = 4;
+
+    /// The total length of the headers, excluding the ethernet header.
+    pub fn overhead(&self) -> usize {
+        usize::from(self.vlan_tags) * Self::VLAN_TAG_LEN
+            + self.tunnel_len
+            + self.extension_len
+    }
+}
+
 /// A routing table.
 ///
 /// # Examples
@@ -142,36 +226,77 @@ impl<'a> Routes<'a> {
         }
     }
 
-    /// Find the next hop for a destination address.
+    /// Remove a route for a network.
     ///
-    /// The timestamp ensures that only valid entries are used. If multiple matching routes are
-    /// found then the one with the shortest subnet prefix is preferred.
+    /// Returns the removed route, or `None` if there was no route for exactly that network. If
+    /// there are multiple routes for the network then only the first one is removed. The order of
+    /// the remaining routes is kept.
+    pub fn remove(&mut self, net: Subnet) -> Option<Route> {
+        let pos = self.storage.iter().position(|route| route.net == net)?;
+        self.storage.remove_at(pos).map(|route| *route)
+    }
+
+    /// Remove all expired routes.
+    ///
+    /// Expired routes are never used by a lookup but still occupy their slot in the storage,
+    /// eventually leading to `Error::Exhausted` when adding routes. Returns the number of removed
+    /// routes.
+    pub fn prune_expired(&mut self, now: Instant) -> usize {
+        let mut removed = 0;
+        while let Some(pos) = self.storage.iter()
+            .position(|route| Expiration::When(now) > route.expires_at)
+        {
+            self.storage.remove_at(pos)
+                .expect("Route we just found is valid.");
+            removed += 1;
+        }
+        removed
+    }
+
+    /// Iterate over the installed routes and their networks.
+    pub fn iter(&self) -> impl Iterator<Item=(&Subnet, &Route)> {
+        self.storage.iter().map(|route| (&route.net, route))
+    }
+
+    /// Find the next hop and path mtu for a destination address.
+    ///
+    /// The timestamp ensures that only valid entries are used. Only routes of the address family
+    /// of `addr` are considered, and if multiple of them match then the one with the longest
+    /// subnet prefix is preferred. Among equally specific routes, one that is still preferred at
+    /// `timestamp` is chosen over those past their `preferred_until`. This does not depend on the
+    /// order of the routes in the table, except that the first one is used when all else is equal.
     pub fn lookup(&self, addr: Address, timestamp: Instant)
-        -> Option<Address>
+        -> Option<RouteInfo>
     {
         assert!(addr.is_unicast());
 
         // The rules say to find the subnet with longest prefix.
-        let mut best_match = None;
+        let mut best_match: Option<&Route> = None;
         for route in self.storage.iter() {
             // Ignored expired routes.
             if Expiration::When(timestamp) > route.expires_at {
                 continue;
             }
 
-            // Ignored routes with mismatching net.
+            // Ignored routes with mismatching net, including those of the other address family.
             if !route.net.contains(addr) {
                 continue;
             }
 
-            // Fill the best_match if none at all yet.
-            let best = best_match.get_or_insert(route);
-            // Prefer shortest route. Fails if just filled.
-            if best.net.prefix_len() < route.net.prefix_len() {
-                *best = route;
+            // Prefer the longest prefix, then a preferred route, keeping the earlier one on ties.
+            let rank = |route: &Route| {
+                let preferred = Expiration::When(timestamp) <= route.preferred_until;
+                (route.net.prefix_len(), preferred)
+            };
+            match best_match {
+                Some(best) if rank(best) >= rank(route) => (),
+                _ => best_match = Some(route),
             }
         }
-        best_match.map(|route| route.next_hop)
+        best_match.map(|route| RouteInfo {
+            via_router: route.next_hop,
+            mtu: route.mtu,
+        })
     }
 }
 
@@ -205,51 +330,187 @@ mod test {
 
     use self::mock::*;
 
+    fn next_hop(routes: &Routes, addr: Address, timestamp: Instant) -> Option<Address> {
+        routes.lookup(addr, timestamp).map(|info| info.via_router)
+    }
+
     #[test]
     fn test_fill() {
         let routes_storage = vec![Route::ipv4_invalid(); 3];
         let mut routes = Routes::new(routes_storage);
 
-        assert_eq!(routes.lookup(ADDR_1A.into(), Instant::from_millis(0)), None);
-        assert_eq!(routes.lookup(ADDR_1B.into(), Instant::from_millis(0)), None);
-        assert_eq!(routes.lookup(ADDR_1C.into(), Instant::from_millis(0)), None);
-        assert_eq!(routes.lookup(ADDR_2A.into(), Instant::from_millis(0)), None);
-        assert_eq!(routes.lookup(ADDR_2B.into(), Instant::from_millis(0)), None);
+        assert_eq!(next_hop(&routes, ADDR_1A.into(), Instant::from_millis(0)), None);
+        assert_eq!(next_hop(&routes, ADDR_1B.into(), Instant::from_millis(0)), None);
+        assert_eq!(next_hop(&routes, ADDR_1C.into(), Instant::from_millis(0)), None);
+        assert_eq!(next_hop(&routes, ADDR_2A.into(), Instant::from_millis(0)), None);
+        assert_eq!(next_hop(&routes, ADDR_2B.into(), Instant::from_millis(0)), None);
 
         let route = Route {
             net: cidr_1().subnet().into(),
             next_hop: ADDR_1A.into(),
             expires_at: Expiration::Never,
+            preferred_until: Expiration::Never,
+            mtu: None,
         };
 
         routes.add_route(route)
             .expect("Can add single route");
 
-        assert_eq!(routes.lookup(ADDR_1A.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
-        assert_eq!(routes.lookup(ADDR_1B.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
-        assert_eq!(routes.lookup(ADDR_1C.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
-        assert_eq!(routes.lookup(ADDR_2A.into(), Instant::from_millis(0)), None);
-        assert_eq!(routes.lookup(ADDR_2B.into(), Instant::from_millis(0)), None);
+        assert_eq!(next_hop(&routes, ADDR_1A.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
+        assert_eq!(next_hop(&routes, ADDR_1B.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
+        assert_eq!(next_hop(&routes, ADDR_1C.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
+        assert_eq!(next_hop(&routes, ADDR_2A.into(), Instant::from_millis(0)), None);
+        assert_eq!(next_hop(&routes, ADDR_2B.into(), Instant::from_millis(0)), None);
 
         let route2 = Route {
             net: cidr_2().subnet().into(),
             next_hop: ADDR_2A.into(),
             expires_at: Expiration::When(Instant::from_millis(10)),
+            preferred_until: Expiration::Never,
+            mtu: None,
         };
 
         routes.add_route(route2)
             .expect("Can add second route");
 
-        assert_eq!(routes.lookup(ADDR_1A.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
-        assert_eq!(routes.lookup(ADDR_1B.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
-        assert_eq!(routes.lookup(ADDR_1C.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
-        assert_eq!(routes.lookup(ADDR_2A.into(), Instant::from_millis(0)), Some(ADDR_2A.into()));
-        assert_eq!(routes.lookup(ADDR_2B.into(), Instant::from_millis(0)), Some(ADDR_2A.into()));
-
-        assert_eq!(routes.lookup(ADDR_1A.into(), Instant::from_millis(10)), Some(ADDR_1A.into()));
-        assert_eq!(routes.lookup(ADDR_1B.into(), Instant::from_millis(10)), Some(ADDR_1A.into()));
-        assert_eq!(routes.lookup(ADDR_1C.into(), Instant::from_millis(10)), Some(ADDR_1A.into()));
-        assert_eq!(routes.lookup(ADDR_2A.into(), Instant::from_millis(10)), Some(ADDR_2A.into()));
-        assert_eq!(routes.lookup(ADDR_2B.into(), Instant::from_millis(10)), Some(ADDR_2A.into()));
+        assert_eq!(next_hop(&routes, ADDR_1A.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
+        assert_eq!(next_hop(&routes, ADDR_1B.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
+        assert_eq!(next_hop(&routes, ADDR_1C.into(), Instant::from_millis(0)), Some(ADDR_1A.into()));
+        assert_eq!(next_hop(&routes, ADDR_2A.into(), Instant::from_millis(0)), Some(ADDR_2A.into()));
+        assert_eq!(next_hop(&routes, ADDR_2B.into(), Instant::from_millis(0)), Some(ADDR_2A.into()));
+
+        assert_eq!(next_hop(&routes, ADDR_1A.into(), Instant::from_millis(10)), Some(ADDR_1A.into()));
+        assert_eq!(next_hop(&routes, ADDR_1B.into(), Instant::from_millis(10)), Some(ADDR_1A.into()));
+        assert_eq!(next_hop(&routes, ADDR_1C.into(), Instant::from_millis(10)), Some(ADDR_1A.into()));
+        assert_eq!(next_hop(&routes, ADDR_2A.into(), Instant::from_millis(10)), Some(ADDR_2A.into()));
+        assert_eq!(next_hop(&routes, ADDR_2B.into(), Instant::from_millis(10)), Some(ADDR_2A.into()));
+    }
+
+    #[test]
+    fn longest_prefix() {
+        let routes_storage = vec![Route::unspecified(); 4];
+        let mut routes = Routes::new(routes_storage);
+
+        let gateway_8 = Address::v4(192, 168, 0, 8);
+        let gateway_16 = Address::v4(192, 168, 0, 16);
+
+        // The ipv6 route in between must not influence the ipv4 decision.
+        routes.add_route(Route {
+            net: Cidr::new(Address::v4(10, 0, 0, 0), 8).subnet(),
+            next_hop: gateway_8,
+            expires_at: Expiration::Never,
+            preferred_until: Expiration::Never,
+            mtu: None,
+        }).unwrap();
+        routes.add_route(Route {
+            net: cidr_1().subnet().into(),
+            next_hop: ADDR_1A.into(),
+            expires_at: Expiration::Never,
+            preferred_until: Expiration::Never,
+            mtu: None,
+        }).unwrap();
+        routes.add_route(Route {
+            net: Cidr::new(Address::v4(10, 1, 0, 0), 16).subnet(),
+            next_hop: gateway_16,
+            expires_at: Expiration::Never,
+            preferred_until: Expiration::Never,
+            mtu: None,
+        }).unwrap();
+        routes.add_route(Route::new_ipv6_gateway(ADDR_2A)).unwrap();
+
+        let now = Instant::from_millis(0);
+        assert_eq!(next_hop(&routes, Address::v4(10, 1, 2, 3), now), Some(gateway_16));
+        assert_eq!(next_hop(&routes, Address::v4(10, 2, 2, 3), now), Some(gateway_8));
+        assert_eq!(next_hop(&routes, Address::v4(11, 1, 2, 3), now), None);
+        assert_eq!(next_hop(&routes, ADDR_1B.into(), now), Some(ADDR_1A.into()));
+        assert_eq!(next_hop(&routes, ADDR_2B.into(), now), Some(ADDR_2A.into()));
+    }
+
+    #[test]
+    fn preferred_route() {
+        let routes_storage = vec![Route::unspecified(); 3];
+        let mut routes = Routes::new(routes_storage);
+
+        let primary = v4::Address::new(192, 168, 0, 1);
+        let backup = v4::Address::new(192, 168, 0, 2);
+
+        routes.add_route(Route {
+            preferred_until: Expiration::When(Instant::from_millis(0)),
+            ..Route::new_ipv4_gateway(backup)
+        }).unwrap();
+        routes.add_route(Route {
+            preferred_until: Expiration::When(Instant::from_millis(100)),
+            expires_at: Expiration::When(Instant::from_millis(200)),
+            ..Route::new_ipv4_gateway(primary)
+        }).unwrap();
+
+        let addr = Address::v4(10, 1, 2, 3);
+        assert_eq!(next_hop(&routes, addr, Instant::from_millis(50)), Some(primary.into()));
+        // Both are not preferred, the first one is used.
+        assert_eq!(next_hop(&routes, addr, Instant::from_millis(150)), Some(backup.into()));
+        assert_eq!(next_hop(&routes, addr, Instant::from_millis(250)), Some(backup.into()));
+
+        // A more specific route wins regardless of preference.
+        routes.add_route(Route {
+            net: Cidr::new(Address::v4(10, 0, 0, 0), 8).subnet(),
+            next_hop: Address::v4(192, 168, 0, 3),
+            expires_at: Expiration::Never,
+            preferred_until: Expiration::When(Instant::from_millis(0)),
+            mtu: None,
+        }).unwrap();
+        assert_eq!(next_hop(&routes, addr, Instant::from_millis(50)), Some(Address::v4(192, 168, 0, 3)));
+    }
+
+    #[test]
+    fn remove() {
+        let routes_storage = vec![Route::unspecified(); 3];
+        let mut routes = Routes::new(routes_storage);
+
+        let net_8 = Cidr::new(Address::v4(10, 0, 0, 0), 8).subnet();
+        let route_8 = Route {
+            net: net_8,
+            next_hop: Address::v4(192, 168, 0, 8),
+            expires_at: Expiration::Never,
+            preferred_until: Expiration::Never,
+            mtu: None,
+        };
+        routes.add_route(Route::new_ipv4_gateway(v4::Address::new(192, 168, 0, 1))).unwrap();
+        routes.add_route(route_8).unwrap();
+        routes.add_route(Route::new_ipv6_gateway(ADDR_2A)).unwrap();
+        assert_eq!(routes.iter().count(), 3);
+
+        let now = Instant::from_millis(0);
+        assert_eq!(next_hop(&routes, Address::v4(10, 1, 2, 3), now), Some(route_8.next_hop));
+
+        let removed = routes.remove(net_8).unwrap();
+        assert_eq!(removed.next_hop, route_8.next_hop);
+        assert!(routes.remove(net_8).is_none());
+        assert_eq!(next_hop(&routes, Address::v4(10, 1, 2, 3), now), Some(Address::v4(192, 168, 0, 1)));
+
+        let nets: Vec<_> = routes.iter().map(|(net, _)| *net).collect();
+        assert_eq!(nets, [Route::ipv4_invalid().net, Route::ipv6_invalid().net]);
+    }
+
+    #[test]
+    fn prune_expired() {
+        let routes_storage = vec![Route::unspecified(); 3];
+        let mut routes = Routes::new(routes_storage);
+
+        let expiring = |millis, gateway| Route {
+            expires_at: Expiration::When(Instant::from_millis(millis)),
+            ..Route::new_ipv4_gateway(gateway)
+        };
+        routes.add_route(expiring(10, v4::Address::new(192, 168, 0, 1))).unwrap();
+        routes.add_route(expiring(100, v4::Address::new(192, 168, 0, 2))).unwrap();
+        routes.add_route(expiring(20, v4::Address::new(192, 168, 0, 3))).unwrap();
+        assert!(routes.add_route(Route::unspecified()).is_err());
+
+        assert_eq!(routes.prune_expired(Instant::from_millis(50)), 2);
+        let remaining: Vec<_> = routes.iter().map(|(_, route)| route.next_hop).collect();
+        assert_eq!(remaining, [Address::v4(192, 168, 0, 2)]);
+        assert_eq!(routes.prune_expired(Instant::from_millis(50)), 0);
+
+        // The freed slots are usable again.
+        assert!(routes.add_route(Route::unspecified()).is_ok());
     }
 }
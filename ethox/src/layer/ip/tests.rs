@@ -129,6 +129,8 @@ impl<P: PayloadMut> ip::Send<P> for SimpleSend {
             dst_addr: self.dst_addr,
             payload: PAYLOAD_BYTES.len(),
             protocol: Protocol::Unknown(0xEF),
+            hop_limit: None,
+            traffic_class: 0,
         };
         let mut prepared = packet.prepare(init)
             .expect("Found no valid routes");
@@ -139,3 +141,1706 @@ impl<P: PayloadMut> ip::Send<P> for SimpleSend {
             .expect("Could actuall egress packet");
     }
 }
+
+/// An endpoint which already knows the link layer addresses of its `neighbors`.
+///
+/// The neighbor cache has room for one more entry.
+fn endpoint<'a, A>(
+    addresses: A,
+    routes: ip::Routes<'a>,
+    neighbors: &[(Address, ethernet::Address)],
+) -> ip::Endpoint<'a>
+    where A: Into<Slice<'a, Assignment>>,
+{
+    let mut cache = arp::NeighborCache::new(vec![arp::Neighbor::default(); neighbors.len() + 1]);
+    for &(ip_addr, mac_addr) in neighbors {
+        cache.fill(ip_addr, mac_addr, None).unwrap();
+    }
+    ip::Endpoint::new(addresses, routes, cache)
+}
+
+/// Receive a packet with the given destinations and count how many reach the ip handler.
+fn recv_cast(policy: crate::layer::CastPolicy, mac_dst: ethernet::Address, ip_dst: v4::Address)
+    -> usize
+{
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: v4::Address = v4::Address::new(10, 0, 0, 2);
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_cast_policy(policy);
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+
+    let sent = nic.tx(1, eth.send(ip.send(SimpleSend {
+        dst_addr: IP_ADDR_OTHER.into(),
+    })));
+    assert_eq!(sent, Ok(1));
+
+    {
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(mac_dst);
+        eth.set_src_addr(MAC_ADDR_OTHER);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(ip_dst);
+        ip.set_src_addr(IP_ADDR_OTHER);
+        ip.fill_checksum();
+    }
+
+    nic.receive_all();
+
+    let mut count = 0;
+    let recv = nic.rx(1, eth.recv(ip.recv_with(|_: InPacket<_>| count += 1)));
+    assert_eq!(recv, Ok(1));
+    count
+}
+
+#[test]
+fn broadcast_policy() {
+    use crate::layer::CastPolicy;
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const IP_ADDR_DIRECTED: v4::Address = v4::Address::new(10, 0, 0, 255);
+
+    let default = CastPolicy::default();
+    assert_eq!(recv_cast(default, MAC_ADDR_HOST, IP_ADDR_HOST), 1);
+    assert_eq!(recv_cast(default, ethernet::Address::BROADCAST, IP_ADDR_HOST), 1);
+    assert_eq!(recv_cast(default, ethernet::Address::BROADCAST, v4::Address::BROADCAST), 1);
+    assert_eq!(recv_cast(default, ethernet::Address::BROADCAST, IP_ADDR_DIRECTED), 1);
+
+    // Link layer broadcast still reaches ip, but ip drops its own broadcasts.
+    let no_ip = CastPolicy { ip_broadcast: false, ..default };
+    assert_eq!(recv_cast(no_ip, MAC_ADDR_HOST, IP_ADDR_HOST), 1);
+    assert_eq!(recv_cast(no_ip, ethernet::Address::BROADCAST, IP_ADDR_HOST), 1);
+    assert_eq!(recv_cast(no_ip, ethernet::Address::BROADCAST, v4::Address::BROADCAST), 0);
+    assert_eq!(recv_cast(no_ip, ethernet::Address::BROADCAST, IP_ADDR_DIRECTED), 0);
+    assert_eq!(recv_cast(no_ip, MAC_ADDR_HOST, IP_ADDR_DIRECTED), 0);
+
+    // Nothing sent to the link broadcast arrives at ip, regardless of its destination.
+    let no_link = CastPolicy { link_broadcast: false, ..default };
+    assert_eq!(recv_cast(no_link, MAC_ADDR_HOST, IP_ADDR_HOST), 1);
+    assert_eq!(recv_cast(no_link, ethernet::Address::BROADCAST, IP_ADDR_HOST), 0);
+    assert_eq!(recv_cast(no_link, ethernet::Address::BROADCAST, v4::Address::BROADCAST), 0);
+    assert_eq!(recv_cast(no_link, MAC_ADDR_HOST, v4::Address::BROADCAST), 1);
+}
+
+/// Receive an IPv6 packet to the given destination and count how many reach the ip handler.
+fn recv_cast_v6(policy: crate::layer::CastPolicy, ip_dst: v6::Address) -> usize {
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v6::Address =
+        v6::Address::from_link_local_id(v6::InterfaceId::from_generated_ether(MAC_ADDR_HOST));
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: v6::Address =
+        v6::Address::from_link_local_id(v6::InterfaceId::from_generated_ether(MAC_ADDR_OTHER));
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_cast_policy(policy);
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 64),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+
+    let sent = nic.tx(1, eth.send(ip.send(SimpleSend {
+        dst_addr: IP_ADDR_OTHER.into(),
+    })));
+    assert_eq!(sent, Ok(1));
+
+    {
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(MAC_ADDR_HOST);
+        eth.set_src_addr(MAC_ADDR_OTHER);
+        let ip = v6::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(ip_dst);
+        ip.set_src_addr(IP_ADDR_OTHER);
+    }
+
+    nic.receive_all();
+
+    let mut count = 0;
+    let recv = nic.rx(1, eth.recv(ip.recv_with(|_: InPacket<_>| count += 1)));
+    assert_eq!(recv, Ok(1));
+    count
+}
+
+#[test]
+fn broadcast_policy_v6() {
+    use crate::layer::CastPolicy;
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v6::Address =
+        v6::Address::from_link_local_id(v6::InterfaceId::from_generated_ether(MAC_ADDR_HOST));
+    let solicited = IP_ADDR_HOST.solicited_node_multicast();
+
+    let default = CastPolicy::default();
+    assert_eq!(recv_cast_v6(default, IP_ADDR_HOST), 1);
+    assert_eq!(recv_cast_v6(default, solicited), 1);
+    assert_eq!(recv_cast_v6(default, v6::Address::LINK_LOCAL_ALL_NODES), 1);
+
+    // Only the all-nodes group counts as broadcast, neighbor discovery keeps working.
+    let no_ip = CastPolicy { ip_broadcast: false, ..default };
+    assert_eq!(recv_cast_v6(no_ip, IP_ADDR_HOST), 1);
+    assert_eq!(recv_cast_v6(no_ip, solicited), 1);
+    assert_eq!(recv_cast_v6(no_ip, v6::Address::LINK_LOCAL_ALL_NODES), 0);
+}
+
+/// Receive a packet to `ip_dst` on an endpoint serving a shared address.
+fn recv_served(ip_dst: v4::Address) -> (usize, Option<crate::layer::DropReason>) {
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const IP_ADDR_ANYCAST: v4::Address = v4::Address::new(192, 0, 2, 53);
+
+    let dropped = core::cell::Cell::new(None);
+    let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_drop_hook(&mut hook);
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+    let mut served = [Address::from(IP_ADDR_ANYCAST)];
+    ip.set_served(&mut served[..]);
+    assert_eq!(ip.served(), &[Address::from(IP_ADDR_ANYCAST)]);
+
+    let sent = nic.tx(1, eth.send(ip.send(SimpleSend {
+        dst_addr: IP_ADDR_OTHER.into(),
+    })));
+    assert_eq!(sent, Ok(1));
+
+    {
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(MAC_ADDR_HOST);
+        eth.set_src_addr(MAC_ADDR_OTHER);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(ip_dst);
+        ip.set_src_addr(IP_ADDR_OTHER);
+        ip.fill_checksum();
+    }
+
+    nic.receive_all();
+
+    let mut count = 0;
+    let recv = nic.rx(1, eth.recv(ip.recv_with(|packet: InPacket<_>| {
+        assert_eq!(packet.packet.repr().dst_addr(), Address::from(ip_dst));
+        count += 1;
+    })));
+    assert_eq!(recv, Ok(1));
+    (count, dropped.get())
+}
+
+#[test]
+fn serve_anycast() {
+    use crate::layer::DropReason;
+
+    // The assigned address still works.
+    assert_eq!(recv_served(v4::Address::new(10, 0, 0, 1)), (1, None));
+    assert_eq!(recv_served(v4::Address::new(192, 0, 2, 53)), (1, None));
+    // Neither assigned nor served, and not within an assigned network either.
+    assert_eq!(recv_served(v4::Address::new(192, 0, 2, 54)), (0, Some(DropReason::Filtered)));
+    assert_eq!(recv_served(v4::Address::new(10, 0, 0, 3)), (0, Some(DropReason::Filtered)));
+}
+
+/// Receive a mangled packet and return the reason it was dropped for, if any.
+fn recv_dropped(mangle: impl FnOnce(&mut ethernet::frame)) -> Option<crate::layer::DropReason> {
+    recv_dropped_with(ip::DEFAULT_MAX_DEPTH, mangle)
+}
+
+fn recv_dropped_with(max_depth: u8, mangle: impl FnOnce(&mut ethernet::frame))
+    -> Option<crate::layer::DropReason>
+{
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: v4::Address = v4::Address::new(10, 0, 0, 2);
+
+    let dropped = core::cell::Cell::new(None);
+    let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_drop_hook(&mut hook);
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+    ip.set_max_depth(max_depth);
+
+    let sent = nic.tx(1, eth.send(ip.send(SimpleSend {
+        dst_addr: IP_ADDR_OTHER.into(),
+    })));
+    assert_eq!(sent, Ok(1));
+
+    {
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(MAC_ADDR_HOST);
+        eth.set_src_addr(MAC_ADDR_OTHER);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(IP_ADDR_HOST);
+        ip.set_src_addr(IP_ADDR_OTHER);
+        ip.fill_checksum();
+        mangle(eth);
+    }
+
+    nic.receive_all();
+
+    let recv = nic.rx(1, eth.recv(ip.recv_with(|_: InPacket<_>| ())));
+    assert_eq!(recv, Ok(1));
+    dropped.get()
+}
+
+#[test]
+fn drop_reasons() {
+    use crate::layer::DropReason;
+
+    assert_eq!(recv_dropped(|_| ()), None);
+    assert_eq!(recv_dropped(|eth| {
+        eth.set_ethertype(ethernet::EtherType::Unknown(0xBEEF));
+    }), Some(DropReason::Unsupported));
+    assert_eq!(recv_dropped(|eth| {
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(v4::Address::new(10, 0, 0, 3));
+        ip.fill_checksum();
+    }), Some(DropReason::Filtered));
+    assert_eq!(recv_dropped(|eth| {
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(v4::Address::new(10, 0, 0, 3));
+    }), Some(DropReason::Checksum));
+    assert_eq!(recv_dropped(|eth| {
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_total_len(2000);
+        ip.fill_checksum();
+    }), Some(DropReason::Malformed));
+}
+
+#[test]
+fn drop_inconsistent_headers() {
+    use crate::layer::DropReason;
+
+    // Total length shorter than the header.
+    assert_eq!(recv_dropped(|eth| {
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_total_len(16);
+        ip.fill_checksum();
+    }), Some(DropReason::Malformed));
+    // Header length shorter than the fixed header.
+    assert_eq!(recv_dropped(|eth| {
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_header_len(16);
+        ip.fill_checksum();
+    }), Some(DropReason::Malformed));
+    // Header length longer than the total length.
+    assert_eq!(recv_dropped(|eth| {
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_header_len(60);
+        ip.set_total_len(40);
+        ip.fill_checksum();
+    }), Some(DropReason::Malformed));
+    // An IPv6 header in an IPv4 ethernet frame.
+    assert_eq!(recv_dropped(|eth| {
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_version(6);
+        ip.fill_checksum();
+    }), Some(DropReason::Malformed));
+}
+
+/// Replace the payload of the packet with a chain of headers.
+///
+/// Encapsulated IPv4 headers have no options, extension headers have the minimum length.
+fn nest(eth: &mut ethernet::frame, headers: &[Protocol]) {
+    let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+    let unknown = Protocol::Unknown(0xEF);
+    ip.set_protocol(headers.first().cloned().unwrap_or(unknown));
+    ip.fill_checksum();
+
+    let mut payload = ip.payload_mut_slice();
+    for (idx, header) in headers.iter().enumerate() {
+        let next = headers.get(idx + 1).cloned().unwrap_or(unknown).into();
+        let len = match header {
+            Protocol::Ipv4 => {
+                payload[0] = 0x45;
+                payload[9] = next;
+                20
+            },
+            _ => {
+                payload[0] = next;
+                payload[1] = 0;
+                8
+            },
+        };
+        let end = len.min(payload.len());
+        payload = &mut payload[end..];
+    }
+}
+
+#[test]
+fn drop_too_deep() {
+    use crate::layer::DropReason;
+    let opts = Protocol::Ipv6Opts;
+
+    assert_eq!(recv_dropped(|eth| nest(eth, &[opts; 4])), None);
+    assert_eq!(recv_dropped(|eth| nest(eth, &[opts; 5])), Some(DropReason::TooDeep));
+    assert_eq!(recv_dropped(|eth| nest(eth, &[Protocol::Ipv4, opts, opts, opts])), None);
+    assert_eq!(recv_dropped(|eth| nest(eth, &[Protocol::Ipv4, opts, opts, opts, opts])),
+        Some(DropReason::TooDeep));
+
+    // The limit is configurable, down to no nesting at all.
+    assert_eq!(recv_dropped_with(6, |eth| nest(eth, &[opts; 6])), None);
+    assert_eq!(recv_dropped_with(5, |eth| nest(eth, &[opts; 6])), Some(DropReason::TooDeep));
+    assert_eq!(recv_dropped_with(0, |eth| nest(eth, &[])), None);
+    assert_eq!(recv_dropped_with(0, |eth| nest(eth, &[Protocol::Ipv4])),
+        Some(DropReason::TooDeep));
+}
+
+/// Send a packet to a neighbor at some time and return the selected source address.
+fn selected_source(addresses: &mut [Assignment], time: crate::time::Instant) -> v4::Address {
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: v4::Address = v4::Address::new(10, 0, 0, 2);
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    nic.set_current_time(time);
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+    let mut ip = endpoint(addresses,
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+
+    let sent = nic.tx(1, eth.send(ip.send(SimpleSend {
+        dst_addr: IP_ADDR_OTHER.into(),
+    })));
+    assert_eq!(sent, Ok(1));
+
+    let buffer = nic.get(0).unwrap();
+    let eth = ethernet::frame::new_checked(&buffer[..]).unwrap();
+    v4::packet::new_checked(eth.payload_slice()).unwrap().src_addr()
+}
+
+#[test]
+fn deprecated_source() {
+    use crate::time::{Expiration, Instant};
+
+    const IP_ADDR_OLD: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const IP_ADDR_NEW: v4::Address = v4::Address::new(10, 0, 0, 5);
+
+    let deprecated_at = Expiration::When(Instant::from_secs(10));
+    let invalid_at = Expiration::When(Instant::from_secs(20));
+    let mut addresses = [
+        Assignment::with_lifetimes(Cidr::new(IP_ADDR_OLD.into(), 24), deprecated_at, invalid_at),
+        Assignment::new(Cidr::new(IP_ADDR_NEW.into(), 24)),
+    ];
+
+    // While both are preferred, the first is used.
+    assert_eq!(selected_source(&mut addresses, Instant::from_secs(5)), IP_ADDR_OLD);
+    // The preferred one wins over a deprecated one with the same scope.
+    assert_eq!(selected_source(&mut addresses, Instant::from_secs(15)), IP_ADDR_NEW);
+
+    // Deprecated addresses are still used if nothing else is suitable.
+    addresses[1].preferred_until = deprecated_at;
+    assert_eq!(selected_source(&mut addresses, Instant::from_secs(15)), IP_ADDR_OLD);
+    // But invalid ones are never used.
+    assert_eq!(selected_source(&mut addresses, Instant::from_secs(25)), IP_ADDR_NEW);
+}
+
+#[test]
+fn select_source() {
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const IP_ADDR_OTHER_NET: v4::Address = v4::Address::new(192, 168, 1, 1);
+    const MAC_ADDR_ROUTER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_ROUTER: v4::Address = v4::Address::new(10, 0, 0, 254);
+    const IP_ADDR_REMOTE: v4::Address = v4::Address::new(172, 16, 0, 1);
+
+    fn send_from<P: PayloadMut>(source: ip::Source, expected: crate::layer::Result<()>)
+        -> impl FnMut(RawPacket<P>)
+    {
+        move |packet: RawPacket<P>| {
+            let init = ip::Init {
+                source,
+                dst_addr: IP_ADDR_REMOTE.into(),
+                protocol: Protocol::Unknown(0xEF),
+                payload: 0,
+                hop_limit: None,
+                traffic_class: 0,
+            };
+            let result = packet.prepare(init).and_then(OutPacket::send);
+            assert_eq!(result, expected);
+        }
+    }
+
+    let mut routes = [ip::Route::unspecified(); 1];
+    let mut routes = ip::Routes::new(&mut routes[..]);
+    routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_ROUTER)).unwrap();
+
+    let mut addresses = [
+        Assignment::new(Cidr::new(IP_ADDR_HOST.into(), 24)),
+        Assignment::new(Cidr::new(IP_ADDR_OTHER_NET.into(), 24)),
+    ];
+    let mut nic = External::new_send(vec![vec![0; 128]; 5]);
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut ip = endpoint(&mut addresses[..], routes, &[(IP_ADDR_ROUTER.into(), MAC_ADDR_ROUTER)]);
+
+    // The address on the preferred subnet is chosen over the one on the link of the router.
+    let other_net = Cidr::new(IP_ADDR_OTHER_NET.into(), 24).subnet();
+    let prefer = ip::Source::Prefer { subnet: other_net };
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_from(prefer, Ok(()))))), Ok(1));
+    // Without a matching address the route decides.
+    let remote_net = Cidr::new(IP_ADDR_REMOTE.into(), 16).subnet();
+    let prefer = ip::Source::Prefer { subnet: remote_net };
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_from(prefer, Ok(()))))), Ok(1));
+    // In contrast to a mask which must be matched.
+    let mask = ip::Source::Mask { subnet: remote_net };
+    let unreachable = Err(crate::layer::Error::Unreachable);
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_from(mask, unreachable)))), Ok(0));
+    let mask = ip::Source::Mask { subnet: other_net };
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_from(mask, Ok(()))))), Ok(1));
+
+    // An exact address must be one of our own, or unspecified.
+    let exact = ip::Source::Exact(IP_ADDR_OTHER_NET.into());
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_from(exact, Ok(()))))), Ok(1));
+    let exact = ip::Source::Exact(v4::Address::UNSPECIFIED.into());
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_from(exact, Ok(()))))), Ok(1));
+    let exact = ip::Source::Exact(IP_ADDR_ROUTER.into());
+    let illegal = Err(crate::layer::Error::Illegal);
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_from(exact, illegal)))), Ok(0));
+
+    let queued = nic.queued();
+    assert_eq!(queued.len(), 5);
+    let sources: Vec<_> = queued.iter()
+        .map(|frame| {
+            let eth = ethernet::frame::new_checked(&frame[..]).unwrap();
+            assert_eq!(eth.dst_addr(), MAC_ADDR_ROUTER);
+            v4::packet::new_checked(eth.payload_slice()).unwrap().src_addr()
+        })
+        .collect();
+    assert_eq!(sources, [
+        IP_ADDR_OTHER_NET,
+        IP_ADDR_HOST,
+        IP_ADDR_OTHER_NET,
+        IP_ADDR_OTHER_NET,
+        v4::Address::UNSPECIFIED,
+    ]);
+}
+
+/// Receive a packet with an unknown option under some policy.
+///
+/// Returns the number of packets delivered, the drop reason if any and the buffer afterwards.
+fn recv_unknown_option(policy: OptionPolicy)
+    -> (usize, Option<crate::layer::DropReason>, Vec<u8>)
+{
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: v4::Address = v4::Address::new(10, 0, 0, 2);
+
+    let dropped = core::cell::Cell::new(None);
+    let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    eth.set_drop_hook(&mut hook);
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+    ip.set_option_policy(policy);
+
+    let sent = nic.tx(1, eth.send(ip.send(SimpleSend {
+        dst_addr: IP_ADDR_OTHER.into(),
+    })));
+    assert_eq!(sent, Ok(1));
+
+    {
+        let buffer = nic.get_mut(0).unwrap();
+        buffer.resize(buffer.len() + 4, 0);
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(MAC_ADDR_HOST);
+        eth.set_src_addr(MAC_ADDR_OTHER);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(IP_ADDR_HOST);
+        ip.set_src_addr(IP_ADDR_OTHER);
+        // Insert an unknown option, with the copied flag set, in front of the payload.
+        let bytes = ip.as_bytes_mut();
+        bytes.copy_within(20..20 + PAYLOAD_BYTES.len(), 24);
+        bytes[20..24].copy_from_slice(&[0x9e, 4, 0, 0]);
+        ip.set_header_len(24);
+        ip.set_total_len(24 + PAYLOAD_BYTES.len() as u16);
+        ip.fill_checksum();
+    }
+
+    nic.receive_all();
+
+    let mut delivered = 0;
+    let recv = nic.rx(1, eth.recv(ip.recv_with(|packet: InPacket<_>| {
+        assert_eq!(packet.packet.payload().as_slice(), &PAYLOAD_BYTES[..]);
+        delivered += 1;
+    })));
+    assert_eq!(recv, Ok(1));
+    (delivered, dropped.get(), nic.get(0).unwrap().clone())
+}
+
+#[test]
+fn unknown_option_policy() {
+    use crate::layer::DropReason;
+    use crate::wire::icmpv4;
+
+    let (delivered, dropped, _) = recv_unknown_option(OptionPolicy::Ignore);
+    assert_eq!(delivered, 1);
+    assert_eq!(dropped, None);
+
+    let (delivered, dropped, _) = recv_unknown_option(OptionPolicy::Drop);
+    assert_eq!(delivered, 0);
+    assert_eq!(dropped, Some(DropReason::Unsupported));
+
+    let (delivered, dropped, answer) = recv_unknown_option(OptionPolicy::ParameterProblem);
+    assert_eq!(delivered, 0);
+    assert_eq!(dropped, None);
+
+    let eth = ethernet::frame::new_checked(&answer[..]).unwrap();
+    assert_eq!(eth.dst_addr(), ethernet::Address([6, 5, 4, 3, 2, 1]));
+    let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+    assert_eq!(ip.protocol(), Protocol::Icmp);
+    assert_eq!(ip.dst_addr(), v4::Address::new(10, 0, 0, 2));
+    assert_eq!(ip.src_addr(), v4::Address::new(10, 0, 0, 1));
+    let icmp = icmpv4::packet::new_checked(ip.payload_slice()).unwrap();
+    match icmpv4::Repr::parse(icmp, crate::wire::Checksum::Manual) {
+        Ok(icmpv4::Repr::ParamProblem { reason, pointer, header }) => {
+            assert_eq!(reason, icmpv4::ParamProblem::AtPointer);
+            assert_eq!(pointer, 20);
+            assert_eq!(header.src_addr, v4::Address::new(10, 0, 0, 2));
+        },
+        other => panic!("Expected a parameter problem, got {:?}", other),
+    }
+    // The quote contains the original option.
+    assert_eq!(&icmp.payload_slice()[20..24], &[0x9e, 4, 0, 0]);
+}
+
+/// Receive copies of a packet with an unknown option and count the parameter problems sent.
+fn count_param_problems(
+    packets: Vec<Vec<u8>>,
+    limiter: Option<crate::layer::icmp::RateLimiter>,
+) -> usize {
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: v4::Address = v4::Address::new(10, 0, 0, 2);
+
+    let count = packets.len();
+    let mut nic = External::new_send(Slice::Many(packets));
+    nic.receive_all();
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+    ip.set_option_policy(OptionPolicy::ParameterProblem);
+    if let Some(limiter) = limiter {
+        ip.set_rate_limiter(limiter);
+    }
+
+    for _ in 0..count {
+        let recv = nic.rx(1, eth.recv(ip.recv_with(|_: InPacket<_>| {
+            panic!("Packet with unknown option was delivered");
+        })));
+        assert_eq!(recv, Ok(1));
+    }
+    nic.queued().len()
+}
+
+#[test]
+fn param_problem_restrictions() {
+    use crate::layer::icmp::RateLimiter;
+
+    let (_, _, original) = recv_unknown_option(OptionPolicy::Ignore);
+    assert_eq!(count_param_problems(vec![original.clone(); 3], None), 3);
+
+    // The answers share the rate limit of all ICMP errors of the endpoint.
+    let limiter = RateLimiter::global(1, 2);
+    assert_eq!(count_param_problems(vec![original.clone(); 3], Some(limiter)), 2);
+
+    // Packets to a broadcast address are never answered.
+    let mut broadcast = original.clone();
+    {
+        let eth = ethernet::frame::new_unchecked_mut(&mut broadcast);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(v4::Address::new(10, 0, 0, 255));
+        ip.fill_checksum();
+    }
+    assert_eq!(count_param_problems(vec![broadcast], None), 0);
+}
+
+#[test]
+fn param_problem_fixed_buffer() {
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: v4::Address = v4::Address::new(10, 0, 0, 2);
+
+    // The received packet is left alone by the default policy.
+    let (_, _, original) = recv_unknown_option(OptionPolicy::Ignore);
+    // A receive buffer that fits the packet exactly and can not be resized for the answer.
+    let mut fixed = original.clone();
+    let mut nic = External::new_send(vec![&mut fixed[..]]);
+    nic.receive_all();
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+    ip.set_option_policy(OptionPolicy::ParameterProblem);
+
+    let recv = nic.rx(1, eth.recv(ip.recv_with(|_: InPacket<_>| {
+        panic!("Packet with unknown option was delivered");
+    })));
+    assert_eq!(recv, Ok(1));
+
+    // The answer did not fit, nothing was sent and the packet is unchanged.
+    assert_eq!(nic.queued().len(), 0);
+    drop(nic);
+    assert_eq!(fixed, original);
+}
+
+#[test]
+fn route_by_mark() {
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_MAIN: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_MAIN: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const MAC_ADDR_MARKED: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 2]);
+    const IP_ADDR_MARKED: v4::Address = v4::Address::new(10, 0, 0, 3);
+    const IP_ADDR_REMOTE: v4::Address = v4::Address::new(192, 168, 0, 1);
+
+    fn send_marked<P: PayloadMut>(mark: u32) -> impl FnMut(RawPacket<P>) {
+        move |mut packet: RawPacket<P>| {
+            packet.control.set_mark(mark);
+            let init = ip::Init {
+                source: Subnet::from(v4::Subnet::ANY).into(),
+                dst_addr: IP_ADDR_REMOTE.into(),
+                protocol: Protocol::Unknown(0xEF),
+                payload: 0,
+                hop_limit: None,
+                traffic_class: 0,
+            };
+            let result = packet.prepare(init).and_then(OutPacket::send);
+            match mark {
+                0 | 1 => assert_eq!(result, Ok(())),
+                _ => assert_eq!(result, Err(crate::layer::Error::Unreachable)),
+            }
+        }
+    }
+
+    let selector = |meta: ip::Metadata| match meta.mark {
+        0 => None,
+        1 => Some(0),
+        // No such table.
+        _ => Some(1),
+    };
+
+    let mut main = [ip::Route::unspecified(); 1];
+    let mut main = ip::Routes::new(&mut main[..]);
+    main.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_MAIN)).unwrap();
+    let mut marked = [ip::Route::unspecified(); 1];
+    let mut tables = [ip::Routes::new(&mut marked[..])];
+    tables[0].add_route(ip::Route::new_ipv4_gateway(IP_ADDR_MARKED)).unwrap();
+
+    let mut nic = External::new_send(vec![vec![0; 128]; 3]);
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        main,
+        &[(IP_ADDR_MAIN.into(), MAC_ADDR_MAIN), (IP_ADDR_MARKED.into(), MAC_ADDR_MARKED)]);
+    ip.set_tables(&mut tables[..], &selector);
+
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_marked(0)))), Ok(1));
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_marked(1)))), Ok(1));
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_marked(2)))), Ok(0));
+
+    let queued = nic.queued();
+    assert_eq!(queued.len(), 2);
+    let first = ethernet::frame::new_checked(&queued[0][..]).unwrap();
+    assert_eq!(first.dst_addr(), MAC_ADDR_MAIN);
+    let second = ethernet::frame::new_checked(&queued[1][..]).unwrap();
+    assert_eq!(second.dst_addr(), MAC_ADDR_MARKED);
+
+    // Without the tables the mark has no effect.
+    ip.clear_tables();
+    assert_eq!(ip.tables().len(), 0);
+}
+
+#[test]
+fn next_hop_hook() {
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_GATEWAY: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_GATEWAY: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const MAC_ADDR_ALTERNATE: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 2]);
+    const IP_ADDR_ALTERNATE: v4::Address = v4::Address::new(10, 0, 0, 3);
+    const IP_ADDR_STEERED: v4::Address = v4::Address::new(192, 168, 0, 1);
+    const IP_ADDR_DEFAULT: v4::Address = v4::Address::new(192, 168, 0, 2);
+    const IP_ADDR_BLOCKED: v4::Address = v4::Address::new(192, 168, 0, 3);
+
+    fn send_to<P: PayloadMut>(dst_addr: v4::Address) -> impl FnMut(RawPacket<P>) {
+        move |packet: RawPacket<P>| {
+            let init = ip::Init {
+                source: Subnet::from(v4::Subnet::ANY).into(),
+                dst_addr: dst_addr.into(),
+                protocol: Protocol::Unknown(0xEF),
+                payload: 0,
+                hop_limit: None,
+                traffic_class: 0,
+            };
+            let result = packet.prepare(init).and_then(OutPacket::send);
+            if dst_addr == IP_ADDR_BLOCKED {
+                assert_eq!(result, Err(crate::layer::Error::Unreachable));
+            } else {
+                assert_eq!(result, Ok(()));
+            }
+        }
+    }
+
+    let hook = |meta: ip::Metadata, next_hop: Address| {
+        assert_eq!(next_hop, IP_ADDR_GATEWAY.into());
+        match meta.dst_addr {
+            Address::Ipv4(IP_ADDR_STEERED) => Some(IP_ADDR_ALTERNATE.into()),
+            Address::Ipv4(IP_ADDR_BLOCKED) => None,
+            _ => Some(next_hop),
+        }
+    };
+
+    let mut routes = [ip::Route::unspecified(); 1];
+    let mut routes = ip::Routes::new(&mut routes[..]);
+    routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_GATEWAY)).unwrap();
+
+    let mut nic = External::new_send(vec![vec![0; 128]; 3]);
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        routes,
+        &[
+            (IP_ADDR_GATEWAY.into(), MAC_ADDR_GATEWAY),
+            (IP_ADDR_ALTERNATE.into(), MAC_ADDR_ALTERNATE),
+        ]);
+    ip.set_next_hop_hook(&hook);
+
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_to(IP_ADDR_STEERED)))), Ok(1));
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_to(IP_ADDR_DEFAULT)))), Ok(1));
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_to(IP_ADDR_BLOCKED)))), Ok(0));
+
+    let queued = nic.queued();
+    assert_eq!(queued.len(), 2);
+    let steered = ethernet::frame::new_checked(&queued[0][..]).unwrap();
+    assert_eq!(steered.dst_addr(), MAC_ADDR_ALTERNATE);
+    let packet = v4::packet::new_checked(steered.payload_slice()).unwrap();
+    assert_eq!(packet.dst_addr(), IP_ADDR_STEERED);
+    assert_eq!(packet.src_addr(), IP_ADDR_HOST);
+    let default = ethernet::frame::new_checked(&queued[1][..]).unwrap();
+    assert_eq!(default.dst_addr(), MAC_ADDR_GATEWAY);
+}
+
+#[test]
+fn egress_mtu() {
+    use crate::time::Instant;
+
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const IP_ADDR_PLAIN: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const IP_ADDR_TAGGED: v4::Address = v4::Address::new(10, 0, 0, 3);
+    const IP_ADDR_TUNNELED: v4::Address = v4::Address::new(10, 0, 0, 4);
+    const IP_ADDR_UNROUTABLE: v4::Address = v4::Address::new(192, 168, 0, 1);
+
+    let hook = |meta: ip::Metadata| match meta.dst_addr {
+        Address::Ipv4(IP_ADDR_TAGGED) => ip::Encapsulation { vlan_tags: 1, ..Default::default() },
+        // An IPv4 header and a GRE header, in a tagged frame.
+        Address::Ipv4(IP_ADDR_TUNNELED) => ip::Encapsulation {
+            vlan_tags: 1,
+            tunnel_len: 24,
+            ..Default::default()
+        },
+        _ => ip::Encapsulation::default(),
+    };
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24), ip::Routes::new(Slice::empty()), &[]);
+    let now = Instant::from_millis(0);
+
+    assert_eq!(ip.egress_mtu(IP_ADDR_PLAIN.into(), 0, now), Some(1500));
+    assert_eq!(ip.egress_mtu(IP_ADDR_TAGGED.into(), 0, now), Some(1500));
+    assert_eq!(ip.egress_mtu(IP_ADDR_UNROUTABLE.into(), 0, now), None);
+
+    ip.set_encapsulation_hook(&hook);
+    assert_eq!(ip.egress_mtu(IP_ADDR_PLAIN.into(), 0, now), Some(1500));
+    assert_eq!(ip.egress_mtu(IP_ADDR_TAGGED.into(), 0, now), Some(1496));
+    assert_eq!(ip.egress_mtu(IP_ADDR_TUNNELED.into(), 0, now), Some(1472));
+
+    ip.set_device_mtu(9014);
+    assert_eq!(ip.egress_mtu(IP_ADDR_TUNNELED.into(), 0, now), Some(8972));
+    ip.set_device_mtu(40);
+    assert_eq!(ip.egress_mtu(IP_ADDR_TUNNELED.into(), 0, now), None);
+}
+
+#[test]
+fn route_mtu() {
+    use crate::time::Instant;
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_GATEWAY: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_GATEWAY: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const IP_ADDR_REMOTE: v4::Address = v4::Address::new(192, 168, 0, 1);
+
+    fn send_len(payload: usize, expected: crate::layer::Result<()>)
+        -> impl FnMut(RawPacket<Vec<u8>>)
+    {
+        move |packet: RawPacket<Vec<u8>>| {
+            let init = ip::Init {
+                source: Subnet::from(v4::Subnet::ANY).into(),
+                dst_addr: IP_ADDR_REMOTE.into(),
+                payload,
+                protocol: Protocol::Unknown(0xEF),
+                hop_limit: None,
+                traffic_class: 0,
+            };
+            let result = packet.prepare(init).and_then(OutPacket::send);
+            assert_eq!(result, expected);
+        }
+    }
+
+    let mut routes = [ip::Route::unspecified(); 1];
+    let mut routes = ip::Routes::new(&mut routes[..]);
+    routes.add_route(ip::Route {
+        mtu: Some(576),
+        ..ip::Route::new_ipv4_gateway(IP_ADDR_GATEWAY)
+    }).unwrap();
+
+    let mut nic = External::new_send(vec![vec![0; 1024]; 2]);
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        routes,
+        &[(IP_ADDR_GATEWAY.into(), MAC_ADDR_GATEWAY)]);
+    let now = Instant::from_millis(0);
+
+    assert_eq!(ip.egress_mtu(IP_ADDR_REMOTE.into(), 0, now), Some(576));
+    // The local network is not restricted.
+    assert_eq!(ip.egress_mtu(IP_ADDR_GATEWAY.into(), 0, now), Some(1500));
+
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_len(556, Ok(()))))), Ok(1));
+    assert_eq!(nic.tx(1, eth.send(ip.send_with(send_len(557, Err(crate::layer::Error::BadSize))))), Ok(0));
+}
+
+#[test]
+fn traffic_class() {
+    const MAC_ADDR_SRC: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_SRC: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_DST: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_DST: v4::Address = v4::Address::new(10, 0, 0, 2);
+    /// Expedited forwarding, with an ECN capable transport.
+    const DSCP_EF: u8 = 0x2e;
+    const ECN_ECT0: u8 = 0b10;
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_DST.into(), MAC_ADDR_DST)]);
+
+    let sent = nic.tx(1, eth.send(ip.send_with(|packet: RawPacket<_>| {
+        let init = ip::Init {
+            source: Subnet::from(v4::Subnet::ANY).into(),
+            dst_addr: IP_ADDR_DST.into(),
+            payload: PAYLOAD_BYTES.len(),
+            protocol: Protocol::Unknown(0xEF),
+            hop_limit: None,
+            traffic_class: DSCP_EF << 2 | ECN_ECT0,
+        };
+        let mut prepared = packet.prepare(init).unwrap();
+        prepared.payload_mut_slice().copy_from_slice(&PAYLOAD_BYTES[..]);
+        prepared.send().unwrap();
+    })));
+    assert_eq!(sent, Ok(1));
+
+    let buffer = nic.get_mut(0).unwrap();
+    let eth = ethernet::frame::new_checked(&buffer[..]).unwrap();
+    let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+    assert_eq!(ip.dscp(), DSCP_EF);
+    assert_eq!(ip.ecn(), ECN_ECT0);
+    // The checksum covers the type of service.
+    assert!(ip.verify_checksum());
+}
+
+#[test]
+fn forward() {
+    use crate::layer::{DropReason, FnHandler};
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 3);
+    const MAC_ADDR_ROUTER: ethernet::Address = ethernet::Address([0, 0, 0, 0, 0, 1]);
+    const IP_ADDR_ROUTER: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_GATEWAY: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_GATEWAY: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const IP_ADDR_REMOTE: v4::Address = v4::Address::new(192, 168, 0, 5);
+
+    // The host sends everything via the router.
+    let mut host_nic = External::new_send(vec![vec![0; 1024]; 3]);
+    {
+        let mut routes = [ip::Route::unspecified(); 1];
+        let mut routes = ip::Routes::new(&mut routes[..]);
+        routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_ROUTER)).unwrap();
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+            routes,
+            &[(IP_ADDR_ROUTER.into(), MAC_ADDR_ROUTER)]);
+
+        let packets = [
+            (IP_ADDR_REMOTE, 64),
+            (IP_ADDR_REMOTE, 1),
+            (IP_ADDR_ROUTER, 64),
+        ];
+        for (dst_addr, hop_limit) in packets {
+            let sent = host_nic.tx(1, eth.send(ip.send_with(|packet: RawPacket<_>| {
+                let init = ip::Init {
+                    source: Subnet::from(v4::Subnet::ANY).into(),
+                    dst_addr: dst_addr.into(),
+                    payload: PAYLOAD_BYTES.len(),
+                    protocol: Protocol::Unknown(0xEF),
+                    hop_limit: Some(hop_limit),
+                    traffic_class: 0,
+                };
+                let mut prepared = packet.prepare(init).unwrap();
+                prepared.payload_mut_slice().copy_from_slice(&PAYLOAD_BYTES[..]);
+                prepared.send().unwrap();
+            })));
+            assert_eq!(sent, Ok(1));
+        }
+    }
+
+    // The router receives all of them on its own nic.
+    let mut router_nic = External::new_recv(host_nic.queued().to_vec());
+    let dropped = core::cell::Cell::new(None);
+    let mut local = 0;
+    {
+        let mut routes = [ip::Route::unspecified(); 1];
+        let mut routes = ip::Routes::new(&mut routes[..]);
+        routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_GATEWAY)).unwrap();
+        let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+        let mut eth = eth::Endpoint::new(MAC_ADDR_ROUTER);
+        eth.set_drop_hook(&mut hook);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_ROUTER.into(), 24),
+            routes,
+            &[(IP_ADDR_GATEWAY.into(), MAC_ADDR_GATEWAY)]);
+        ip.set_forwarding(true);
+
+        let mut forwarder = ip::Forwarder::new(FnHandler(|_: InPacket<_>| local += 1));
+        for _ in 0..3 {
+            let recv = router_nic.rx(1, eth.recv(ip.recv(&mut forwarder)));
+            assert_eq!(recv, Ok(1));
+        }
+    }
+
+    assert_eq!(local, 1);
+    assert_eq!(dropped.get(), Some(DropReason::HopLimit));
+
+    // Only the first packet was routed on to the gateway.
+    assert_eq!(router_nic.queued().len(), 1);
+    let frame = ethernet::frame::new_checked(&router_nic.queued()[0][..]).unwrap();
+    assert_eq!(frame.src_addr(), MAC_ADDR_ROUTER);
+    assert_eq!(frame.dst_addr(), MAC_ADDR_GATEWAY);
+    let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+    assert!(packet.verify_checksum());
+    assert_eq!(packet.src_addr(), IP_ADDR_HOST);
+    assert_eq!(packet.dst_addr(), IP_ADDR_REMOTE);
+    assert_eq!(packet.hop_limit(), 63);
+    assert_eq!(packet.payload_slice(), &PAYLOAD_BYTES[..]);
+}
+
+#[test]
+fn forward_fragments() {
+    use crate::layer::FnHandler;
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 3);
+    const MAC_ADDR_ROUTER: ethernet::Address = ethernet::Address([0, 0, 0, 0, 0, 1]);
+    const IP_ADDR_ROUTER: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_GATEWAY: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_GATEWAY: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const IP_ADDR_REMOTE: v4::Address = v4::Address::new(192, 168, 0, 5);
+
+    let payload: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+
+    // The host sends a fragmented datagram via the router.
+    let mut host_nic = External::new_send(vec![vec![0; 1514]; 3]);
+    {
+        let mut routes = [ip::Route::unspecified(); 1];
+        let mut routes = ip::Routes::new(&mut routes[..]);
+        routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_ROUTER)).unwrap();
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+            routes,
+            &[(IP_ADDR_ROUTER.into(), MAC_ADDR_ROUTER)]);
+        let mut fragmenter = ip::Fragmenter::new(
+            Subnet::from(v4::Subnet::ANY).into(),
+            IP_ADDR_REMOTE,
+            Protocol::Udp,
+            0x1234,
+            &payload).unwrap();
+        while !fragmenter.is_done() {
+            assert_eq!(host_nic.tx(1, eth.send(ip.send(&mut fragmenter))), Ok(1));
+        }
+    }
+    assert_eq!(host_nic.queued().len(), 3);
+
+    // The router reassembles datagrams to itself but passes these on one by one.
+    let mut router_nic = External::new_recv(host_nic.queued().to_vec());
+    {
+        let mut routes = [ip::Route::unspecified(); 1];
+        let mut routes = ip::Routes::new(&mut routes[..]);
+        routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_GATEWAY)).unwrap();
+        let mut eth = eth::Endpoint::new(MAC_ADDR_ROUTER);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_ROUTER.into(), 24),
+            routes,
+            &[(IP_ADDR_GATEWAY.into(), MAC_ADDR_GATEWAY)]);
+        ip.set_forwarding(true);
+        ip.set_reassembler(ip::Reassembler::new(vec![ip::ReassemblyBuffer::new(vec![0; 4096])]));
+
+        let mut forwarder = ip::Forwarder::new(FnHandler(|_: InPacket<_>| {
+            panic!("Nothing is addressed to the router");
+        }));
+        for _ in 0..3 {
+            let recv = router_nic.rx(1, eth.recv(ip.recv(&mut forwarder)));
+            assert_eq!(recv, Ok(1));
+        }
+    }
+
+    // Answered buffers are queued in reverse order of reception.
+    let mut queued = router_nic.queued().to_vec();
+    queued.reverse();
+    assert_eq!(queued.len(), 3);
+    let mut reassembled = vec![];
+    for (idx, buffer) in queued.iter().enumerate() {
+        let frame = ethernet::frame::new_checked(&buffer[..]).unwrap();
+        assert_eq!(frame.dst_addr(), MAC_ADDR_GATEWAY);
+        let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+        assert!(packet.verify_checksum());
+        assert_eq!(packet.dst_addr(), IP_ADDR_REMOTE);
+        assert_eq!(packet.hop_limit(), DEFAULT_HOP_LIMIT - 1);
+        assert_eq!(packet.ident(), 0x1234);
+        assert_eq!(packet.more_frags(), idx < 2);
+        assert_eq!(usize::from(packet.frag_offset()), reassembled.len());
+        reassembled.extend_from_slice(packet.payload_slice());
+    }
+    assert_eq!(reassembled, payload);
+}
+
+#[test]
+fn forward_unreachable() {
+    use crate::layer::{DropReason, FnHandler};
+    use crate::wire::icmpv4;
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 3);
+    const MAC_ADDR_ROUTER: ethernet::Address = ethernet::Address([0, 0, 0, 0, 0, 1]);
+    const IP_ADDR_ROUTER: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const IP_ADDR_REMOTE: v4::Address = v4::Address::new(192, 168, 0, 5);
+
+    // The host sends a packet and an icmp error message via the router.
+    let mut host_nic = External::new_send(vec![vec![0; 1024]; 2]);
+    {
+        let mut routes = [ip::Route::unspecified(); 1];
+        let mut routes = ip::Routes::new(&mut routes[..]);
+        routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_ROUTER)).unwrap();
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+            routes,
+            &[(IP_ADDR_ROUTER.into(), MAC_ADDR_ROUTER)]);
+
+        for protocol in [Protocol::Unknown(0xEF), Protocol::Icmp] {
+            let sent = host_nic.tx(1, eth.send(ip.send_with(|packet: RawPacket<_>| {
+                let init = ip::Init {
+                    source: Subnet::from(v4::Subnet::ANY).into(),
+                    dst_addr: IP_ADDR_REMOTE.into(),
+                    payload: PAYLOAD_BYTES.len(),
+                    protocol,
+                    hop_limit: None,
+                    traffic_class: 0,
+                };
+                let mut prepared = packet.prepare(init).unwrap();
+                let payload = prepared.payload_mut_slice();
+                payload.copy_from_slice(&PAYLOAD_BYTES[..]);
+                // Looks like a destination unreachable message.
+                payload[0] = icmpv4::Message::DstUnreachable.into();
+                prepared.send().unwrap();
+            })));
+            assert_eq!(sent, Ok(1));
+        }
+    }
+
+    // The router has no route to the remote.
+    let mut router_nic = External::new_recv(host_nic.queued().to_vec());
+    let dropped = core::cell::Cell::new(None);
+    {
+        let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+        let mut eth = eth::Endpoint::new(MAC_ADDR_ROUTER);
+        eth.set_drop_hook(&mut hook);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_ROUTER.into(), 24),
+            ip::Routes::new(Slice::empty()),
+            &[(IP_ADDR_HOST.into(), MAC_ADDR_HOST)]);
+        ip.set_forwarding(true);
+
+        let mut forwarder = ip::Forwarder::new(FnHandler(|_: InPacket<_>| {
+            panic!("Nothing is addressed to the router");
+        }));
+        assert!(!forwarder.report_unreachable());
+        forwarder.set_report_unreachable(true);
+        for _ in 0..2 {
+            let recv = router_nic.rx(1, eth.recv(ip.recv(&mut forwarder)));
+            assert_eq!(recv, Ok(1));
+            assert_eq!(dropped.take(), Some(DropReason::NoRoute));
+        }
+    }
+
+    // Only the first packet is answered.
+    assert_eq!(router_nic.queued().len(), 1);
+    let original = ethernet::frame::new_checked(&host_nic.queued()[0][..]).unwrap();
+    let frame = ethernet::frame::new_checked(&router_nic.queued()[0][..]).unwrap();
+    assert_eq!(frame.src_addr(), MAC_ADDR_ROUTER);
+    assert_eq!(frame.dst_addr(), MAC_ADDR_HOST);
+    let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+    assert!(packet.verify_checksum());
+    assert_eq!(packet.src_addr(), IP_ADDR_ROUTER);
+    assert_eq!(packet.dst_addr(), IP_ADDR_HOST);
+    assert_eq!(packet.protocol(), Protocol::Icmp);
+    let message = icmpv4::packet::new_checked(packet.payload_slice()).unwrap();
+    assert!(message.verify_checksum());
+    assert_eq!(message.msg_type(), icmpv4::Message::DstUnreachable);
+    assert_eq!(message.msg_code(), icmpv4::DstUnreachable::HostUnreachable.into());
+    // The quote is the original header and eight bytes of its payload.
+    assert_eq!(message.payload_slice(), &original.payload_slice()[..20 + 8]);
+}
+
+#[test]
+fn forward_unresolved() {
+    use crate::layer::{DropReason, FnHandler};
+    use crate::wire::Checksum;
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 3);
+    const MAC_ADDR_ROUTER: ethernet::Address = ethernet::Address([0, 0, 0, 0, 0, 1]);
+    const IP_ADDR_ROUTER: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const IP_ADDR_GATEWAY: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const IP_ADDR_REMOTE: v4::Address = v4::Address::new(192, 168, 0, 5);
+
+    let mut router_nic = External::new_recv(vec![vec![0; 1024]]);
+    {
+        let frame = ethernet::frame::new_unchecked_mut(router_nic.get_mut(0).unwrap());
+        frame.set_dst_addr(MAC_ADDR_ROUTER);
+        frame.set_src_addr(MAC_ADDR_HOST);
+        frame.set_ethertype(ethernet::EtherType::Ipv4);
+        v4::Repr {
+            src_addr: IP_ADDR_HOST,
+            dst_addr: IP_ADDR_REMOTE,
+            protocol: Protocol::Unknown(0xEF),
+            payload_len: PAYLOAD_BYTES.len(),
+            hop_limit: 64,
+        }.emit(v4::packet::new_unchecked_mut(frame.payload_mut_slice()), Checksum::Manual);
+    }
+
+    // The router has a route via the gateway but does not know its hardware address yet.
+    let dropped = core::cell::Cell::new(None);
+    {
+        let mut routes = [ip::Route::unspecified(); 1];
+        let mut routes = ip::Routes::new(&mut routes[..]);
+        routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_GATEWAY)).unwrap();
+        let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+        let mut eth = eth::Endpoint::new(MAC_ADDR_ROUTER);
+        eth.set_drop_hook(&mut hook);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_ROUTER.into(), 24),
+            routes,
+            &[(IP_ADDR_HOST.into(), MAC_ADDR_HOST)]);
+        ip.set_forwarding(true);
+
+        let mut forwarder = ip::Forwarder::new(FnHandler(|_: InPacket<_>| {
+            panic!("Nothing is addressed to the router");
+        }));
+        forwarder.set_report_unreachable(true);
+        let recv = router_nic.rx(1, eth.recv(ip.recv(&mut forwarder)));
+        assert_eq!(recv, Ok(1));
+    }
+
+    // The packet is dropped without claiming that the remote is unreachable.
+    assert_eq!(dropped.get(), Some(DropReason::Unresolved));
+    assert_eq!(router_nic.queued().len(), 0);
+}
+
+#[test]
+fn forward_time_exceeded() {
+    use crate::layer::{DropReason, FnHandler};
+    use crate::wire::icmpv4;
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 3);
+    const MAC_ADDR_ROUTER: ethernet::Address = ethernet::Address([0, 0, 0, 0, 0, 1]);
+    const IP_ADDR_ROUTER: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_GATEWAY: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_GATEWAY: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const IP_ADDR_REMOTE: v4::Address = v4::Address::new(192, 168, 0, 5);
+
+    // The host sends a packet that expires at the router.
+    let mut host_nic = External::new_send(vec![vec![0; 1024]; 1]);
+    {
+        let mut routes = [ip::Route::unspecified(); 1];
+        let mut routes = ip::Routes::new(&mut routes[..]);
+        routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_ROUTER)).unwrap();
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+            routes,
+            &[(IP_ADDR_ROUTER.into(), MAC_ADDR_ROUTER)]);
+
+        let sent = host_nic.tx(1, eth.send(ip.send_with(|packet: RawPacket<_>| {
+            let init = ip::Init {
+                source: Subnet::from(v4::Subnet::ANY).into(),
+                dst_addr: IP_ADDR_REMOTE.into(),
+                payload: PAYLOAD_BYTES.len(),
+                protocol: Protocol::Udp,
+                hop_limit: Some(1),
+                traffic_class: 0,
+            };
+            let mut prepared = packet.prepare(init).unwrap();
+            prepared.payload_mut_slice().copy_from_slice(&PAYLOAD_BYTES[..]);
+            prepared.send().unwrap();
+        })));
+        assert_eq!(sent, Ok(1));
+    }
+
+    let mut router_nic = External::new_recv(host_nic.queued().to_vec());
+    let dropped = core::cell::Cell::new(None);
+    {
+        let mut routes = [ip::Route::unspecified(); 1];
+        let mut routes = ip::Routes::new(&mut routes[..]);
+        routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_GATEWAY)).unwrap();
+        let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+        let mut eth = eth::Endpoint::new(MAC_ADDR_ROUTER);
+        eth.set_drop_hook(&mut hook);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_ROUTER.into(), 24),
+            routes,
+            &[(IP_ADDR_GATEWAY.into(), MAC_ADDR_GATEWAY), (IP_ADDR_HOST.into(), MAC_ADDR_HOST)]);
+        ip.set_forwarding(true);
+
+        let mut forwarder = ip::Forwarder::new(FnHandler(|_: InPacket<_>| {
+            panic!("Nothing is addressed to the router");
+        }));
+        assert!(!forwarder.report_time_exceeded());
+        forwarder.set_report_time_exceeded(true);
+        let recv = router_nic.rx(1, eth.recv(ip.recv(&mut forwarder)));
+        assert_eq!(recv, Ok(1));
+    }
+
+    assert_eq!(dropped.get(), Some(DropReason::HopLimit));
+    assert_eq!(router_nic.queued().len(), 1);
+    let original = ethernet::frame::new_checked(&host_nic.queued()[0][..]).unwrap();
+    let frame = ethernet::frame::new_checked(&router_nic.queued()[0][..]).unwrap();
+    assert_eq!(frame.src_addr(), MAC_ADDR_ROUTER);
+    assert_eq!(frame.dst_addr(), MAC_ADDR_HOST);
+    let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+    assert!(packet.verify_checksum());
+    assert_eq!(packet.src_addr(), IP_ADDR_ROUTER);
+    assert_eq!(packet.dst_addr(), IP_ADDR_HOST);
+    assert_eq!(packet.protocol(), Protocol::Icmp);
+    let message = icmpv4::packet::new_checked(packet.payload_slice()).unwrap();
+    assert!(message.verify_checksum());
+    assert_eq!(message.msg_type(), icmpv4::Message::TimeExceeded);
+    assert_eq!(message.msg_code(), icmpv4::TimeExceeded::TtlExpired.into());
+    assert_eq!(message.payload_slice(), &original.payload_slice()[..20 + 8]);
+}
+
+#[test]
+fn forward_checksum_offload() {
+    use crate::layer::FnHandler;
+    use crate::nic::Capabilities;
+    use crate::wire::icmpv4;
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 3);
+    const MAC_ADDR_ROUTER: ethernet::Address = ethernet::Address([0, 0, 0, 0, 0, 1]);
+    const IP_ADDR_ROUTER: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_GATEWAY: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_GATEWAY: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const IP_ADDR_REMOTE: v4::Address = v4::Address::new(192, 168, 0, 5);
+
+    // The host sends one packet that is forwarded and one that expires at the router.
+    let mut host_nic = External::new_send(vec![vec![0; 1024]; 2]);
+    {
+        let mut routes = [ip::Route::unspecified(); 1];
+        let mut routes = ip::Routes::new(&mut routes[..]);
+        routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_ROUTER)).unwrap();
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+            routes,
+            &[(IP_ADDR_ROUTER.into(), MAC_ADDR_ROUTER)]);
+
+        for hop_limit in [64, 1] {
+            let sent = host_nic.tx(1, eth.send(ip.send_with(|packet: RawPacket<_>| {
+                let init = ip::Init {
+                    source: Subnet::from(v4::Subnet::ANY).into(),
+                    dst_addr: IP_ADDR_REMOTE.into(),
+                    payload: PAYLOAD_BYTES.len(),
+                    protocol: Protocol::Udp,
+                    hop_limit: Some(hop_limit),
+                    traffic_class: 0,
+                };
+                let mut prepared = packet.prepare(init).unwrap();
+                prepared.payload_mut_slice().copy_from_slice(&PAYLOAD_BYTES[..]);
+                prepared.send().unwrap();
+            })));
+            assert_eq!(sent, Ok(1));
+        }
+    }
+
+    let route = |capabilities: Capabilities| {
+        let mut router_nic = External::new_recv(host_nic.queued().to_vec());
+        router_nic.set_capabilities(capabilities);
+        let mut routes = [ip::Route::unspecified(); 1];
+        let mut routes = ip::Routes::new(&mut routes[..]);
+        routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_GATEWAY)).unwrap();
+        let mut eth = eth::Endpoint::new(MAC_ADDR_ROUTER);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_ROUTER.into(), 24),
+            routes,
+            &[(IP_ADDR_GATEWAY.into(), MAC_ADDR_GATEWAY), (IP_ADDR_HOST.into(), MAC_ADDR_HOST)]);
+        ip.set_forwarding(true);
+
+        let mut forwarder = ip::Forwarder::new(FnHandler(|_: InPacket<_>| {
+            panic!("Nothing is addressed to the router");
+        }));
+        forwarder.set_report_time_exceeded(true);
+        for _ in 0..2 {
+            let recv = router_nic.rx(1, eth.recv(ip.recv(&mut forwarder)));
+            assert_eq!(recv, Ok(1));
+        }
+
+        // Answered in place, the buffers are queued in reverse order of their reception.
+        let queued = router_nic.queued();
+        assert_eq!(queued.len(), 2);
+        (queued[1].clone(), queued[0].clone())
+    };
+
+    for offloaded in [false, true] {
+        let capabilities = if offloaded {
+            Capabilities::offloaded()
+        } else {
+            Capabilities::no_support()
+        };
+        let (forwarded, report) = route(capabilities);
+
+        // The forwarded header had its hop limit changed.
+        let frame = ethernet::frame::new_checked(&forwarded[..]).unwrap();
+        assert_eq!(frame.dst_addr(), MAC_ADDR_GATEWAY);
+        let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+        assert_eq!(packet.hop_limit(), 63);
+        assert_eq!(packet.verify_checksum(), !offloaded);
+        assert_eq!(packet.checksum() == 0, offloaded);
+
+        // The error message is built from scratch.
+        let frame = ethernet::frame::new_checked(&report[..]).unwrap();
+        assert_eq!(frame.dst_addr(), MAC_ADDR_HOST);
+        let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+        assert_eq!(packet.protocol(), Protocol::Icmp);
+        assert_eq!(packet.verify_checksum(), !offloaded);
+        assert_eq!(packet.checksum() == 0, offloaded);
+        let message = icmpv4::packet::new_checked(packet.payload_slice()).unwrap();
+        assert_eq!(message.msg_type(), icmpv4::Message::TimeExceeded);
+        assert_eq!(message.verify_checksum(), !offloaded);
+        assert_eq!(message.checksum() == 0, offloaded);
+    }
+}
+
+#[test]
+fn forward_rate_limited() {
+    use crate::layer::{icmp, DropReason, FnHandler};
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 3);
+    const MAC_ADDR_ROUTER: ethernet::Address = ethernet::Address([0, 0, 0, 0, 0, 1]);
+    const IP_ADDR_ROUTER: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const IP_ADDR_REMOTE: v4::Address = v4::Address::new(192, 168, 0, 5);
+
+    // The host sends a burst of packets without a route.
+    let mut host_nic = External::new_send(vec![vec![0; 1024]; 4]);
+    {
+        let mut routes = [ip::Route::unspecified(); 1];
+        let mut routes = ip::Routes::new(&mut routes[..]);
+        routes.add_route(ip::Route::new_ipv4_gateway(IP_ADDR_ROUTER)).unwrap();
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+            routes,
+            &[(IP_ADDR_ROUTER.into(), MAC_ADDR_ROUTER)]);
+
+        for _ in 0..4 {
+            let sent = host_nic.tx(1, eth.send(ip.send_with(|packet: RawPacket<_>| {
+                let init = ip::Init {
+                    source: Subnet::from(v4::Subnet::ANY).into(),
+                    dst_addr: IP_ADDR_REMOTE.into(),
+                    payload: PAYLOAD_BYTES.len(),
+                    protocol: Protocol::Udp,
+                    hop_limit: None,
+                    traffic_class: 0,
+                };
+                let mut prepared = packet.prepare(init).unwrap();
+                prepared.payload_mut_slice().copy_from_slice(&PAYLOAD_BYTES[..]);
+                prepared.send().unwrap();
+            })));
+            assert_eq!(sent, Ok(1));
+        }
+    }
+
+    let mut router_nic = External::new_recv(host_nic.queued().to_vec());
+    let dropped = core::cell::Cell::new(0);
+    {
+        let mut hook = |reason, _: &[u8]| {
+            assert_eq!(reason, DropReason::NoRoute);
+            dropped.set(dropped.get() + 1);
+        };
+        let mut eth = eth::Endpoint::new(MAC_ADDR_ROUTER);
+        eth.set_drop_hook(&mut hook);
+        let mut ip = endpoint(Cidr::new(IP_ADDR_ROUTER.into(), 24),
+            ip::Routes::new(Slice::empty()),
+            &[(IP_ADDR_HOST.into(), MAC_ADDR_HOST)]);
+        ip.set_forwarding(true);
+        ip.set_rate_limiter(icmp::RateLimiter::global(1, 2));
+
+        let mut forwarder = ip::Forwarder::new(FnHandler(|_: InPacket<_>| {
+            panic!("Nothing is addressed to the router");
+        }));
+        forwarder.set_report_unreachable(true);
+        for _ in 0..4 {
+            let recv = router_nic.rx(1, eth.recv(ip.recv(&mut forwarder)));
+            assert_eq!(recv, Ok(1));
+        }
+    }
+
+    // All packets are dropped but only the burst is answered.
+    assert_eq!(dropped.get(), 4);
+    assert_eq!(router_nic.queued().len(), 2);
+    for queued in router_nic.queued() {
+        let frame = ethernet::frame::new_checked(&queued[..]).unwrap();
+        let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+        assert_eq!(packet.dst_addr(), IP_ADDR_HOST);
+        assert_eq!(packet.protocol(), Protocol::Icmp);
+    }
+}
+
+#[test]
+fn fragment_send() {
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: v4::Address = v4::Address::new(10, 0, 0, 2);
+
+    let payload: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+
+    let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        &[(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER)]);
+
+    let mut nic = External::new_send(vec![vec![0; 1514]; 4]);
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut fragmenter = ip::Fragmenter::new(
+        Subnet::from(v4::Subnet::ANY).into(),
+        IP_ADDR_OTHER,
+        Protocol::Udp,
+        0x1234,
+        &payload).unwrap();
+
+    for _ in 0..3 {
+        assert!(!fragmenter.is_done());
+        assert_eq!(nic.tx(1, eth.send(ip.send(&mut fragmenter))), Ok(1));
+    }
+    assert!(fragmenter.is_done());
+    assert_eq!(fragmenter.sent(), 3000);
+    assert_eq!(nic.tx(1, eth.send(ip.send(&mut fragmenter))), Ok(0));
+
+    let queued = nic.queued();
+    assert_eq!(queued.len(), 3);
+
+    let mut reassembled = vec![];
+    for (idx, buffer) in queued.iter().enumerate() {
+        let frame = ethernet::frame::new_checked(&buffer[..]).unwrap();
+        let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+        assert!(packet.verify_checksum());
+        assert_eq!(packet.ident(), 0x1234);
+        assert!(!packet.dont_frag());
+        assert_eq!(packet.more_frags(), idx < 2);
+        assert_eq!(usize::from(packet.frag_offset()), reassembled.len());
+        reassembled.extend_from_slice(packet.payload_slice());
+    }
+    assert_eq!(reassembled, payload);
+}
+
+mod reassembly {
+    use super::*;
+    use crate::layer::DropReason;
+    use crate::time::{Duration, Instant};
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: v4::Address = v4::Address::new(10, 0, 0, 2);
+
+    struct Fragment<'a> {
+        ident: u16,
+        offset: usize,
+        more_frags: bool,
+        data: &'a [u8],
+    }
+
+    /// The outcome of receiving one fragment.
+    #[derive(Debug, PartialEq, Eq)]
+    enum Outcome {
+        Datagram(Vec<u8>),
+        Dropped(DropReason),
+        Stored,
+    }
+
+    fn receive(ip: &mut ip::Endpoint, millis: i64, fragment: Fragment) -> Outcome {
+        let dropped = core::cell::Cell::new(None);
+        let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+
+        let mut nic = External::new_recv(vec![vec![0; 1024]]);
+        nic.set_current_time(Instant::from_millis(millis));
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        eth.set_drop_hook(&mut hook);
+
+        {
+            let frame = ethernet::frame::new_unchecked_mut(nic.get_mut(0).unwrap());
+            frame.set_dst_addr(MAC_ADDR_HOST);
+            frame.set_src_addr(MAC_ADDR_OTHER);
+            frame.set_ethertype(ethernet::EtherType::Ipv4);
+            v4::Repr {
+                src_addr: IP_ADDR_OTHER,
+                dst_addr: IP_ADDR_HOST,
+                protocol: Protocol::Unknown(0xEF),
+                payload_len: fragment.data.len(),
+                hop_limit: 64,
+            }.emit(v4::packet::new_unchecked_mut(frame.payload_mut_slice()), crate::wire::Checksum::Ignored);
+            let packet = v4::packet::new_unchecked_mut(frame.payload_mut_slice());
+            packet.set_ident(fragment.ident);
+            packet.set_more_frags(fragment.more_frags);
+            packet.set_frag_offset(fragment.offset as u16);
+            packet.fill_checksum();
+            packet.payload_mut_slice().copy_from_slice(fragment.data);
+        }
+
+        let mut datagram = None;
+        let recv = nic.rx(1, eth.recv(ip.recv_with(|packet: InPacket<_>| {
+            datagram = Some(packet.packet.payload().as_slice().to_vec());
+        })));
+        assert_eq!(recv, Ok(1));
+
+        match (datagram, dropped.get()) {
+            (Some(datagram), None) => Outcome::Datagram(datagram),
+            (None, Some(reason)) => Outcome::Dropped(reason),
+            (None, None) => Outcome::Stored,
+            (Some(_), Some(_)) => unreachable!("Packet both handled and dropped"),
+        }
+    }
+
+    fn payload() -> Vec<u8> {
+        (0..40).map(|i| i as u8).collect()
+    }
+
+    fn part(payload: &[u8], ident: u16, offset: usize, len: usize) -> Fragment {
+        let end = (offset + len).min(payload.len());
+        Fragment { ident, offset, more_frags: end < payload.len(), data: &payload[offset..end] }
+    }
+
+    fn with_reassembler<R>(buffers: usize, test: impl FnOnce(&mut ip::Endpoint) -> R) -> R {
+        let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+            ip::Routes::new(Slice::empty()),
+            &[]);
+        let buffers: Vec<_> = (0..buffers)
+            .map(|_| ip::ReassemblyBuffer::new(vec![0; 512]))
+            .collect();
+        ip.set_reassembler(ip::Reassembler::new(buffers));
+        test(&mut ip)
+    }
+
+    #[test]
+    fn disabled() {
+        let payload = payload();
+        let mut ip = endpoint(Cidr::new(IP_ADDR_HOST.into(), 24),
+            ip::Routes::new(Slice::empty()),
+            &[]);
+        assert_eq!(receive(&mut ip, 0, part(&payload, 1, 0, 16)), Outcome::Dropped(DropReason::Unsupported));
+    }
+
+    #[test]
+    fn in_order() {
+        let payload = payload();
+        with_reassembler(1, |ip| {
+            assert_eq!(receive(ip, 0, part(&payload, 1, 0, 16)), Outcome::Stored);
+            assert_eq!(receive(ip, 0, part(&payload, 1, 16, 16)), Outcome::Stored);
+            assert_eq!(receive(ip, 0, part(&payload, 1, 32, 16)), Outcome::Datagram(payload.clone()));
+            assert_eq!(ip.reassembler().unwrap().in_progress(), 0);
+        });
+    }
+
+    #[test]
+    fn out_of_order() {
+        let payload = payload();
+        with_reassembler(2, |ip| {
+            assert_eq!(receive(ip, 0, part(&payload, 1, 32, 16)), Outcome::Stored);
+            // Interleaved with another datagram.
+            assert_eq!(receive(ip, 0, part(&payload, 2, 16, 16)), Outcome::Stored);
+            assert_eq!(receive(ip, 0, part(&payload, 1, 0, 16)), Outcome::Stored);
+            assert_eq!(receive(ip, 0, part(&payload, 1, 16, 16)), Outcome::Datagram(payload.clone()));
+            assert_eq!(ip.reassembler().unwrap().in_progress(), 1);
+        });
+    }
+
+    #[test]
+    fn timeout() {
+        let payload = payload();
+        with_reassembler(1, |ip| {
+            let timeout = ip.reassembler().unwrap().timeout();
+            assert_eq!(timeout, Duration::from_secs(15));
+
+            assert_eq!(receive(ip, 0, part(&payload, 1, 0, 16)), Outcome::Stored);
+            assert_eq!(receive(ip, 0, part(&payload, 1, 32, 16)), Outcome::Stored);
+            // The first fragments were dropped, this starts a new datagram.
+            assert_eq!(receive(ip, 15_000, part(&payload, 1, 16, 16)), Outcome::Stored);
+            assert_eq!(ip.reassembler().unwrap().in_progress(), 1);
+            assert_eq!(receive(ip, 15_000, part(&payload, 1, 0, 16)), Outcome::Stored);
+        });
+    }
+
+    #[test]
+    fn overlap() {
+        let payload = payload();
+        with_reassembler(1, |ip| {
+            assert_eq!(receive(ip, 0, part(&payload, 1, 0, 24)), Outcome::Stored);
+            assert_eq!(receive(ip, 0, part(&payload, 1, 16, 16)), Outcome::Dropped(DropReason::Reassembly));
+            // The whole datagram was dropped.
+            assert_eq!(ip.reassembler().unwrap().in_progress(), 0);
+            assert_eq!(receive(ip, 0, part(&payload, 1, 24, 16)), Outcome::Stored);
+        });
+    }
+
+    #[test]
+    fn exhausted() {
+        let payload = payload();
+        with_reassembler(1, |ip| {
+            assert_eq!(receive(ip, 0, part(&payload, 1, 0, 16)), Outcome::Stored);
+            assert_eq!(receive(ip, 10, part(&payload, 2, 0, 16)), Outcome::Dropped(DropReason::Reassembly));
+            // The oldest datagram was dropped to make room.
+            assert_eq!(ip.reassembler().unwrap().in_progress(), 0);
+            assert_eq!(receive(ip, 10, part(&payload, 2, 0, 16)), Outcome::Stored);
+            assert_eq!(receive(ip, 10, part(&payload, 2, 16, 24)), Outcome::Datagram(payload.clone()));
+        });
+    }
+}
@@ -0,0 +1,325 @@
+//! Limits the rate of received packets per flow.
+//!
+//! The limit layer wraps the receiver of another layer, similar to the [`loss`] layer. Each
+//! packet is assigned to a flow by a key function, for example by its source address or by the
+//! address and port tuple. Every flow owns a token bucket that refills at a configured rate up to
+//! a maximum burst. A packet consumes one token and is dropped if the bucket of its flow is empty,
+//! while packets of all other flows are unaffected.
+//!
+//! The buckets are kept in bounded storage provided by the user, ordered by the last use of the
+//! flow. A flow that is not yet known gets a fresh, full bucket and evicts the least recently seen
+//! flow when the storage is full. Since a bucket of an idle flow refills completely after a while,
+//! evicting it does not change the treatment of that flow.
+//!
+//! Dropped packets are reported to the drop hook of the ethernet endpoint as rate limited.
+//!
+//! [`loss`]: ../loss/index.html
+use crate::layer::{eth, ip, udp, DropReason};
+use crate::managed::Slice;
+use crate::time::{Instant, TokenBucket};
+use crate::wire::Payload;
+
+/// The token bucket of a single flow.
+///
+/// The storage of a [`FlowLimit`] consists of these. A default bucket is unused.
+///
+/// [`FlowLimit`]: struct.FlowLimit.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Bucket<K> {
+    /// The flow owning the bucket, `None` if unused.
+    key: Option<K>,
+
+    /// Available tokens, one for each packet.
+    tokens: TokenBucket,
+}
+
+/// Token buckets for a bounded number of flows.
+///
+/// See the [module level documentation][mod] for details.
+///
+/// [mod]: index.html
+#[derive(Debug)]
+pub struct FlowLimit<'a, K> {
+    /// Buckets, the most recently used flow first.
+    buckets: Slice<'a, Bucket<K>>,
+
+    /// Packets per second added to each bucket.
+    rate: u32,
+
+    /// The maximum number of packets in a bucket.
+    burst: u32,
+}
+
+/// A receiver dropping packets of flows that exceed their rate.
+///
+/// Create it with [`FlowLimit::limit`]. Implements the receive traits of the ethernet, ip and udp
+/// layers, given a key function for the packets of that layer.
+///
+/// [`FlowLimit::limit`]: struct.FlowLimit.html#method.limit
+pub struct Limited<'a, 'data, K, F, I> {
+    limit: &'a mut FlowLimit<'data, K>,
+
+    /// Determines the flow of a packet.
+    key: F,
+
+    /// The receiver of all admitted packets.
+    handler: I,
+}
+
+impl<K> Default for Bucket<K> {
+    fn default() -> Self {
+        Bucket {
+            key: None,
+            tokens: TokenBucket::default(),
+        }
+    }
+}
+
+impl<K> Bucket<K> {
+    /// The flow owning this bucket, if the bucket is in use.
+    pub fn key(&self) -> Option<&K> {
+        self.key.as_ref()
+    }
+}
+
+impl<'a, K: PartialEq> FlowLimit<'a, K> {
+    /// Create a limit with room for as many flows as the storage holds.
+    ///
+    /// Each flow may receive `rate` packets per second on average and up to `burst` packets at
+    /// once. The content of the storage is overwritten.
+    pub fn new<S>(storage: S, rate: u32, burst: u32) -> Self
+        where S: Into<Slice<'a, Bucket<K>>>,
+    {
+        let mut buckets = storage.into();
+        buckets.iter_mut().for_each(|bucket| bucket.key = None);
+        FlowLimit {
+            buckets,
+            rate,
+            burst,
+        }
+    }
+
+    /// The buckets of all tracked flows, the most recently used first.
+    pub fn buckets(&self) -> &[Bucket<K>] {
+        &self.buckets
+    }
+
+    /// Decide if a packet of a flow is admitted at some time, consuming a token if it is.
+    pub fn admit(&mut self, key: K, now: Instant) -> bool {
+        if self.buckets.is_empty() {
+            return false;
+        }
+
+        // Move the bucket of the flow, or the least recently used one, to the front.
+        let known = self.buckets.iter()
+            .position(|bucket| bucket.key.as_ref() == Some(&key));
+        let (idx, known) = match known {
+            Some(idx) => (idx, true),
+            None => (self.buckets.len() - 1, false),
+        };
+        self.buckets[..=idx].rotate_right(1);
+
+        let bucket = &mut self.buckets[0];
+        if !known {
+            *bucket = Bucket {
+                key: Some(key),
+                tokens: TokenBucket::new(self.rate, self.burst),
+            };
+        }
+
+        bucket.tokens.take(1, now)
+    }
+
+    /// Limit the packets received by a handler, assigning them to flows with a key function.
+    pub fn limit<F, I>(&mut self, key: F, handler: I) -> Limited<'_, 'a, K, F, I> {
+        Limited {
+            limit: self,
+            key,
+            handler,
+        }
+    }
+}
+
+impl<P, K, F, I> eth::Recv<P> for Limited<'_, '_, K, F, I>
+where
+    P: Payload,
+    K: PartialEq,
+    F: FnMut(&eth::InPacket<P>) -> K,
+    I: eth::Recv<P>,
+{
+    fn receive(&mut self, packet: eth::InPacket<P>) {
+        let key = (self.key)(&packet);
+        let time = packet.control.info().timestamp();
+        if self.limit.admit(key, time) {
+            return self.handler.receive(packet);
+        }
+
+        let eth::InPacket { mut control, frame } = packet;
+        control.report_drop(DropReason::RateLimited, frame.as_bytes())
+    }
+}
+
+impl<P, K, F, I> ip::Recv<P> for Limited<'_, '_, K, F, I>
+where
+    P: Payload,
+    K: PartialEq,
+    F: FnMut(&ip::InPacket<P>) -> K,
+    I: ip::Recv<P>,
+{
+    fn receive(&mut self, packet: ip::InPacket<P>) {
+        let key = (self.key)(&packet);
+        let time = packet.control.info().timestamp();
+        if self.limit.admit(key, time) {
+            return self.handler.receive(packet);
+        }
+
+        let ip::InPacket { mut control, packet } = packet;
+        control.report_drop(DropReason::RateLimited, packet.into_inner().payload_slice())
+    }
+}
+
+impl<P, K, F, I> udp::Recv<P> for Limited<'_, '_, K, F, I>
+where
+    P: Payload,
+    K: PartialEq,
+    F: FnMut(&udp::Packet<P>) -> K,
+    I: udp::Recv<P>,
+{
+    fn receive(&mut self, packet: udp::Packet<P>) {
+        let key = (self.key)(&packet);
+        let time = packet.control.info().timestamp();
+        if self.limit.admit(key, time) {
+            return self.handler.receive(packet);
+        }
+
+        let udp::Packet { mut control, packet } = packet;
+        control.inner.report_drop(DropReason::RateLimited, packet.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::managed::Slice;
+    use crate::nic::{external::External, Device};
+    use crate::layer::{arp, eth, ip, DropReason, FnHandler};
+    use crate::time::Instant;
+    use crate::wire::{ethernet, ip::Address, ip::Cidr, ip::Protocol, ip::v4};
+
+    use super::{Bucket, FlowLimit};
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_FLOOD: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const IP_ADDR_OTHER: v4::Address = v4::Address::new(10, 0, 0, 3);
+
+    #[test]
+    fn refill() {
+        let mut buckets = [Bucket::default(); 2];
+        let mut limit = FlowLimit::new(&mut buckets[..], 10, 2);
+        let start = Instant::from_millis(0);
+
+        assert!(limit.admit(1, start));
+        assert!(limit.admit(1, start));
+        assert!(!limit.admit(1, start));
+        // One token every 100 milliseconds.
+        assert!(!limit.admit(1, Instant::from_millis(99)));
+        assert!(limit.admit(1, Instant::from_millis(100)));
+        // The bucket never holds more than the burst.
+        assert!(limit.admit(1, Instant::from_secs(10)));
+        assert!(limit.admit(1, Instant::from_secs(10)));
+        assert!(!limit.admit(1, Instant::from_secs(10)));
+    }
+
+    #[test]
+    fn evict_least_recent() {
+        let mut buckets = [Bucket::default(); 2];
+        let mut limit = FlowLimit::new(&mut buckets[..], 0, 1);
+        let now = Instant::from_millis(0);
+
+        assert!(limit.admit(1, now));
+        assert!(limit.admit(2, now));
+        assert!(!limit.admit(1, now));
+        // Evicts the flow 2 which was used less recently.
+        assert!(limit.admit(3, now));
+        assert!(!limit.admit(1, now));
+        assert!(limit.admit(2, now));
+        assert_eq!(limit.buckets()[0].key(), Some(&2));
+        assert_eq!(limit.buckets()[1].key(), Some(&1));
+    }
+
+    #[test]
+    fn flood_one_source() {
+        const PACKETS: usize = 12;
+        const FLOOD: usize = 8;
+
+        let mut nic = External::new_send(vec![vec![0; 128]; PACKETS]);
+        let mut eth = eth::Endpoint::new(MAC_ADDR_OTHER);
+        let mut neighbors = [arp::Neighbor::default(); 1];
+        let neighbors = {
+            let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+            eth_cache.fill(IP_ADDR_HOST.into(), MAC_ADDR_HOST, None).unwrap();
+            eth_cache
+        };
+        let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_FLOOD.into(), 24),
+            ip::Routes::new(Slice::empty()),
+            neighbors);
+
+        for idx in 0..PACKETS {
+            let sent = nic.tx(1, eth.send(ip.send_with(|packet: ip::RawPacket<_>| {
+                let mut packet = packet.prepare(ip::Init {
+                    source: ip::Source::Exact(IP_ADDR_FLOOD.into()),
+                    dst_addr: IP_ADDR_HOST.into(),
+                    protocol: Protocol::Unknown(0xEF),
+                    payload: 16,
+                    hop_limit: None,
+                    traffic_class: 0,
+                }).unwrap();
+                packet.payload_mut_slice().copy_from_slice(&[0; 16]);
+                packet.send().unwrap();
+            })));
+            assert_eq!(sent, Ok(1));
+
+            if idx >= FLOOD {
+                let eth = ethernet::frame::new_unchecked_mut(nic.get_mut(idx).unwrap());
+                let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+                ip.set_src_addr(IP_ADDR_OTHER);
+                ip.fill_checksum();
+            }
+        }
+        nic.receive_all();
+
+        let mut dropped = 0;
+        let mut hook = |reason, _: &[u8]| {
+            assert_eq!(reason, DropReason::RateLimited);
+            dropped += 1;
+        };
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        eth.set_drop_hook(&mut hook);
+        let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_HOST.into(), 24),
+            ip::Routes::new(Slice::empty()),
+            arp::NeighborCache::new(Slice::empty()));
+
+        let mut buckets = [Bucket::default(); 4];
+        let mut limit = FlowLimit::new(&mut buckets[..], 1, 4);
+        let mut flood = 0;
+        let mut other = 0;
+
+        for _ in 0..PACKETS {
+            let source = |packet: &ip::InPacket<_>| packet.packet.repr().src_addr();
+            let recv = nic.rx(1, eth.recv(ip.recv(limit.limit(source,
+                FnHandler(|packet: ip::InPacket<_>| match packet.packet.repr().src_addr() {
+                    Address::Ipv4(IP_ADDR_FLOOD) => flood += 1,
+                    Address::Ipv4(IP_ADDR_OTHER) => other += 1,
+                    _ => unreachable!(),
+                })))));
+            assert_eq!(recv, Ok(1));
+        }
+
+        // Only the burst of the flooding source passes, the other source is unaffected.
+        assert_eq!(flood, 4);
+        assert_eq!(other, PACKETS - FLOOD);
+        drop(eth);
+        assert_eq!(dropped, FLOOD - 4);
+    }
+}
@@ -0,0 +1,257 @@
+use core::cmp;
+
+use crate::layer::{Error, Result};
+use crate::managed::{Ordered, Slice};
+use crate::nic::{self, Capabilities};
+use crate::nic::common::{EnqueueFlag, PacketInfo};
+use crate::time::{Duration, Expiration, Instant};
+use crate::wire::{Payload, PayloadMut};
+
+use super::Xoroshiro256;
+
+/// Holds packets back to simulate latency and jitter.
+///
+/// Each received packet is copied into one of a fixed number of buffers and released at a deadline
+/// computed from the base latency and a pseudo-random jitter. Packets are released strictly in
+/// the order of their deadlines, packets with the same deadline in the order they were delayed.
+///
+/// Released packets are not associated with a device anymore and can not be answered in place.
+/// Their handle always refuses to queue them.
+pub struct Delay<'r, C> {
+    queue: Ordered<'r, Delayed<C>>,
+    latency: Duration,
+    jitter: Duration,
+    prng: Xoroshiro256,
+    next_seq: u64,
+}
+
+/// A buffer holding a delayed packet.
+///
+/// The buffer is kept and reused for later packets once the packet has been released.
+pub struct Delayed<C> {
+    release: Instant,
+    seq: u64,
+    buffer: C,
+}
+
+impl<'r, C: PayloadMut> Delay<'r, C> {
+    /// Create a delay queue with space for as many packets as buffers provided.
+    ///
+    /// Each packet is released after `latency` plus a uniformly chosen jitter of at most `jitter`.
+    /// The `seed` initializes the generator of the jitter such that runs are reproducible.
+    pub fn new<S>(buffers: S, latency: Duration, jitter: Duration, seed: u64) -> Self
+        where S: Into<Slice<'r, Delayed<C>>>
+    {
+        Delay {
+            queue: Ordered::new(buffers),
+            latency,
+            jitter,
+            prng: Xoroshiro256::new(seed),
+            next_seq: 0,
+        }
+    }
+
+    /// The number of packets currently held back.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Check if no packets are held back.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// The deadline of the next packet to be released.
+    pub fn next_release(&self) -> Expiration {
+        self.queue.first().map(|delayed| delayed.release).into()
+    }
+
+    /// Hold back a copy of a packet that arrived at `now`.
+    ///
+    /// Returns `Exhausted` if all buffers are already in use. Errors from resizing the buffer to
+    /// the length of the packet are passed on, in both cases the packet is not delayed. Jitter is
+    /// only rolled for delayed packets so that a seed reproduces the same delays regardless.
+    pub fn push(&mut self, now: Instant, packet: &[u8]) -> Result<()> {
+        let seq = self.next_seq;
+
+        let slot = self.queue.init().ok_or(Error::Exhausted)?;
+        slot.buffer.resize(packet.len())?;
+        slot.buffer.payload_mut().as_mut_slice().copy_from_slice(packet);
+
+        let release = now + self.latency + self.roll_jitter();
+        let slot = self.queue.init().expect("Slot was free");
+        slot.release = release;
+        slot.seq = seq;
+
+        self.queue.push();
+        self.next_seq = seq.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Release up to `max` packets whose deadline has passed at `now` to the `receptor`.
+    ///
+    /// Returns the number of released packets.
+    pub fn poll(
+        &mut self,
+        now: Instant,
+        max: usize,
+        mut receptor: impl nic::Recv<EnqueueFlag, C>,
+    ) -> usize {
+        let info = PacketInfo {
+            timestamp: now,
+            capabilities: Capabilities::no_support(),
+        };
+
+        let mut count = 0;
+        while count < max {
+            match self.queue.first() {
+                Some(delayed) if delayed.release <= now => (),
+                _ => break,
+            }
+
+            // Popping moves the packet just behind the queue, where it is the next to be reused.
+            self.queue.pop(0);
+            let delayed = self.queue.init()
+                .expect("Popped packet is the next free buffer");
+
+            let mut handle = EnqueueFlag::not_possible(info);
+            receptor.receive(nic::Packet {
+                handle: &mut handle,
+                payload: &mut delayed.buffer,
+            });
+
+            count += 1;
+        }
+
+        count
+    }
+
+    fn roll_jitter(&mut self) -> Duration {
+        let max = self.jitter.as_millis() as u64;
+        match max.checked_add(1) {
+            Some(range) => Duration::from_millis(self.prng.next() % range),
+            None => self.jitter,
+        }
+    }
+}
+
+impl<C> Delayed<C> {
+    /// Provide a buffer for a delayed packet.
+    pub fn new(buffer: C) -> Self {
+        Delayed {
+            release: Instant::from_millis(0),
+            seq: 0,
+            buffer,
+        }
+    }
+}
+
+/// Delays all received packets, dropping them if no buffer is left.
+impl<H, P, C> nic::Recv<H, P> for Delay<'_, C>
+where
+    H: nic::Handle + ?Sized,
+    P: Payload + ?Sized,
+    C: PayloadMut,
+{
+    fn receive(&mut self, packet: nic::Packet<H, P>) {
+        let now = packet.handle.info().timestamp();
+        let _ = self.push(now, packet.payload.payload().as_slice());
+    }
+}
+
+impl<C> cmp::PartialEq for Delayed<C> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.release, self.seq) == (other.release, other.seq)
+    }
+}
+
+impl<C> cmp::Eq for Delayed<C> { }
+
+impl<C> cmp::PartialOrd for Delayed<C> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<C> cmp::Ord for Delayed<C> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        (self.release, self.seq).cmp(&(other.release, other.seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::FnHandler;
+    use crate::nic::Handle;
+
+    fn buffers(count: usize) -> Vec<Delayed<Vec<u8>>> {
+        (0..count).map(|_| Delayed::new(vec![])).collect()
+    }
+
+    #[test]
+    fn latency() {
+        let latency = Duration::from_millis(50);
+        let mut delay = Delay::new(buffers(1), latency, Duration::from_millis(0), 0);
+        assert_eq!(delay.push(Instant::from_millis(0), b"delayed"), Ok(()));
+        assert_eq!(delay.push(Instant::from_millis(0), b"lost"), Err(Error::Exhausted));
+        assert_eq!(delay.next_release(), Expiration::When(Instant::from_millis(50)));
+
+        let mut released = vec![];
+        let mut collect = FnHandler(|packet: nic::Packet<EnqueueFlag, Vec<u8>>| {
+            assert_eq!(packet.handle.queue(), Err(Error::Exhausted));
+            released.push(packet.payload.clone());
+        });
+
+        assert_eq!(delay.poll(Instant::from_millis(49), 4, &mut collect), 0);
+        assert_eq!(delay.poll(Instant::from_millis(50), 4, &mut collect), 1);
+        assert!(delay.is_empty());
+        assert_eq!(released, [b"delayed".to_vec()]);
+    }
+
+    #[test]
+    fn jitter_order() {
+        let mut delay = Delay::new(buffers(16),
+            Duration::from_millis(20), Duration::from_millis(10), 0x5eed);
+        for idx in 0..16u8 {
+            assert_eq!(delay.push(Instant::from_millis(0), &[idx]), Ok(()));
+        }
+        assert_eq!(delay.push(Instant::from_millis(0), &[16]), Err(Error::Exhausted));
+
+        let mut releases = vec![];
+        for now in 0..=30 {
+            let now = Instant::from_millis(now);
+            delay.poll(now, 16, FnHandler(|packet: nic::Packet<EnqueueFlag, Vec<u8>>| {
+                releases.push((packet.handle.info().timestamp(), packet.payload[0]));
+            }));
+        }
+
+        assert!(delay.is_empty());
+        assert_eq!(releases.len(), 16);
+        assert!(releases.iter().all(|&(at, _)| at >= Instant::from_millis(20)));
+        // Not all packets keep their original order.
+        assert!(releases.windows(2).any(|pair| pair[0].1 > pair[1].1));
+    }
+
+    #[test]
+    fn rejected_keeps_jitter() {
+        let delays = |reject: bool| {
+            let mut delay = Delay::new(buffers(1),
+                Duration::from_millis(20), Duration::from_millis(10), 0x5eed);
+            let mut releases = vec![];
+            for idx in 0..8u8 {
+                let now = Instant::from_millis(100*i64::from(idx));
+                assert_eq!(delay.push(now, &[idx]), Ok(()));
+                if reject {
+                    assert_eq!(delay.push(now, &[0xff]), Err(Error::Exhausted));
+                }
+                releases.push(delay.next_release());
+                let drain = FnHandler(|_: nic::Packet<_, _>| ());
+                delay.poll(now + Duration::from_millis(30), 1, drain);
+            }
+            releases
+        };
+
+        assert_eq!(delays(true), delays(false));
+    }
+}
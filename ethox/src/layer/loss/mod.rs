@@ -1,11 +1,26 @@
 //! Simulates packet loss.
 //!
 //! The loss layer is a simple wrapper around another layer which simulates a lossy connection.
-//! This works by dropping ingress packets or canceling the sending of egress packets.
+//! This works by dropping ingress packets or canceling the sending of egress packets. Latency and
+//! jitter of a connection can be simulated by holding back received packets in a [`Delay`] while
+//! a [`Reorder`] swaps some packets with their successor. A [`Shaper`] limits the bandwidth of
+//! sent packets.
+//!
+//! [`Delay`]: struct.Delay.html
+//! [`Reorder`]: struct.Reorder.html
+//! [`Shaper`]: struct.Shaper.html
 use crate::nic;
 use crate::layer::{eth, ip};
 use crate::wire::Payload;
 
+mod delay;
+mod reorder;
+mod shaper;
+
+pub use self::delay::{Delay, Delayed};
+pub use self::reorder::{Reorder, Reordered};
+pub use self::shaper::{Deferred, Shaped, Shaper};
+
 /// Simple pseudo-random loss.
 ///
 /// Can simulate burst-losses and uniform losses by dropping packets based on a pulse design.
@@ -25,6 +40,10 @@ pub struct PrngLoss {
     ///
     /// Xoroshiro256**, yes this is far too good.
     pub prng: Xoroshiro256,
+    /// The number of packets dropped so far.
+    ///
+    /// Counts each decision to drop, both for ingress packets and for canceled transmissions.
+    pub dropped: u64,
 }
 
 /// An adaptor simulating loss to and from the wrapped layer.
@@ -70,6 +89,7 @@ impl PrngLoss {
             reset: 0,
             lossrate: rate,
             prng: Xoroshiro256::new(seed),
+            dropped: 0,
         }
     }
 
@@ -91,6 +111,7 @@ impl PrngLoss {
             // Packet always lost when pulse condition is true.
             lossrate: Some(u32::max_value()),
             prng: Xoroshiro256::new(0),
+            dropped: 0,
         }
     }
 
@@ -103,7 +124,12 @@ impl PrngLoss {
             .unwrap_or(self.reset);
         self.count = ncount;
 
-        !in_window || !fate_drop
+        let pass = !in_window || !fate_drop;
+        if !pass {
+            self.dropped += 1;
+        }
+
+        pass
     }
 
     /// Generate the next value of the prng.
@@ -344,6 +370,35 @@ where
 #[cfg(test)]
 mod tests {
     use super::PrngLoss;
+    use crate::layer::FnHandler;
+    use crate::nic::{self, external::External, Device};
+
+    /// Receive 100 numbered packets through a uniform loss of a quarter of packets.
+    fn receive_numbered(seed: u64) -> (Vec<u8>, u64) {
+        let buffers = (0..100).map(|idx| vec![idx]).collect::<Vec<_>>();
+        let mut nic = External::new_recv(buffers);
+        let mut prng = PrngLoss::uniform(Some(!0 >> 2), seed);
+        let mut passed = vec![];
+
+        for _ in 0..100 {
+            let recv = nic.rx(1, prng.lossy(FnHandler(|packet: nic::Packet<_, Vec<u8>>| {
+                passed.push(packet.payload[0]);
+            })));
+            assert_eq!(recv, Ok(1));
+        }
+
+        (passed, prng.dropped)
+    }
+
+    #[test]
+    fn seeded() {
+        let (passed, dropped) = receive_numbered(0x5eed);
+        assert_eq!(passed.len() as u64 + dropped, 100);
+        assert_eq!(dropped, 21);
+
+        // The same seed drops the very same packets.
+        assert_eq!(receive_numbered(0x5eed), (passed, dropped));
+    }
 
     #[test]
     fn pulsed() {
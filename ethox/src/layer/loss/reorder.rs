@@ -0,0 +1,183 @@
+use crate::nic::{self, Capabilities};
+use crate::nic::common::{EnqueueFlag, PacketInfo};
+use crate::wire::{Payload, PayloadMut};
+
+use super::Xoroshiro256;
+
+/// Simulates reordering by swapping pseudo-randomly chosen packets with their successor.
+///
+/// A chosen packet is copied into a single buffer and held back until the next packet has been
+/// received, then it is released right after it. No packet is ever dropped, a packet that can not
+/// be copied into the buffer is passed on in order instead.
+///
+/// Released packets are not associated with a device anymore and can not be answered in place.
+/// Their handle always refuses to queue them.
+pub struct Reorder<C> {
+    buffer: C,
+    holding: bool,
+    /// Reorder rate as a (0, 32)-bit fixed point number.
+    rate: u32,
+    prng: Xoroshiro256,
+    swaps: u64,
+}
+
+/// An adaptor reordering the packets received by the wrapped handler.
+///
+/// The wrapped handler receives both the original packets and the released packets, which are
+/// provided in the buffer of the `Reorder`.
+pub struct Reordered<'a, C, I>(pub I, pub &'a mut Reorder<C>);
+
+impl<C: PayloadMut> Reorder<C> {
+    /// Create a reordering simulator holding back packets in `buffer`.
+    ///
+    /// The `rate` is the probability for each packet to be held back as a (0, 32)-bit fixed point
+    /// number. The `seed` initializes the generator such that runs are reproducible.
+    pub fn new(buffer: C, rate: u32, seed: u64) -> Self {
+        Reorder {
+            buffer,
+            holding: false,
+            rate,
+            prng: Xoroshiro256::new(seed),
+            swaps: 0,
+        }
+    }
+
+    /// Wrap a handler to reorder the packets it receives.
+    pub fn reordered<I>(&mut self, handler: I) -> Reordered<'_, C, I> {
+        Reordered(handler, self)
+    }
+
+    /// The number of packets that were released after their successor.
+    pub fn swaps(&self) -> u64 {
+        self.swaps
+    }
+
+    /// Check if a packet is currently held back.
+    pub fn is_holding(&self) -> bool {
+        self.holding
+    }
+
+    /// Release a held back packet without waiting for its successor.
+    ///
+    /// Useful at the end of a stream. Returns `true` if a packet was released.
+    pub fn flush(&mut self, info: PacketInfo, mut receptor: impl nic::Recv<EnqueueFlag, C>)
+        -> bool
+    {
+        if !self.holding {
+            return false;
+        }
+
+        self.release(info, &mut receptor);
+        true
+    }
+
+    /// Try to hold back a copy of the packet.
+    fn hold(&mut self, packet: &[u8]) -> bool {
+        if self.buffer.resize(packet.len()).is_err() {
+            return false;
+        }
+
+        self.buffer.payload_mut().as_mut_slice().copy_from_slice(packet);
+        self.holding = true;
+        true
+    }
+
+    fn release(&mut self, info: PacketInfo, receptor: &mut impl nic::Recv<EnqueueFlag, C>) {
+        let mut handle = EnqueueFlag::not_possible(info);
+        self.holding = false;
+        receptor.receive(nic::Packet {
+            handle: &mut handle,
+            payload: &mut self.buffer,
+        });
+    }
+
+    /// Determine if the next packet should be held back.
+    fn roll(&mut self) -> bool {
+        ((self.prng.next() & u64::from(!0u32)) as u32) < self.rate
+    }
+}
+
+impl<H, P, C, I> nic::Recv<H, P> for Reordered<'_, C, I>
+where
+    H: nic::Handle + ?Sized,
+    P: Payload + ?Sized,
+    C: PayloadMut,
+    I: nic::Recv<H, P> + nic::Recv<EnqueueFlag, C>,
+{
+    fn receive(&mut self, packet: nic::Packet<H, P>) {
+        let reorder = &mut *self.1;
+
+        if !reorder.holding && reorder.roll() && reorder.hold(packet.payload.payload().as_slice()) {
+            return;
+        }
+
+        let info = PacketInfo {
+            timestamp: packet.handle.info().timestamp(),
+            capabilities: Capabilities::no_support(),
+        };
+        self.0.receive(packet);
+
+        if reorder.holding {
+            reorder.release(info, &mut self.0);
+            reorder.swaps += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nic::{external::External, Device, Handle};
+    use crate::time::Instant;
+
+    /// Collects the number in the first byte of all received packets.
+    #[derive(Default)]
+    struct Numbers(Vec<u8>);
+
+    impl<H: Handle + ?Sized, P: Payload + ?Sized> nic::Recv<H, P> for Numbers {
+        fn receive(&mut self, packet: nic::Packet<H, P>) {
+            self.0.push(packet.payload.payload().as_slice()[0]);
+        }
+    }
+
+    fn info() -> PacketInfo {
+        PacketInfo {
+            timestamp: Instant::from_millis(0),
+            capabilities: Capabilities::no_support(),
+        }
+    }
+
+    fn receive_numbered(count: u8, reorder: &mut Reorder<Vec<u8>>) -> Numbers {
+        let buffers = (0..count).map(|idx| vec![idx]).collect::<Vec<_>>();
+        let mut nic = External::new_recv(buffers);
+        let mut numbers = Numbers::default();
+
+        for _ in 0..count {
+            assert_eq!(nic.rx(1, reorder.reordered(&mut numbers)), Ok(1));
+        }
+
+        numbers
+    }
+
+    #[test]
+    fn swapped() {
+        let mut reorder = Reorder::new(vec![], !0 >> 3, 0x243f_6a88_85a3_08d3);
+        let mut numbers = receive_numbered(10, &mut reorder);
+        assert_eq!(numbers.0, [1, 0, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(reorder.swaps(), 1);
+        assert!(!reorder.flush(info(), &mut numbers));
+    }
+
+    #[test]
+    fn flush_held() {
+        // Holds back every packet that is not released right away.
+        let mut reorder = Reorder::new(vec![], !0, 0);
+        let mut numbers = receive_numbered(5, &mut reorder);
+        assert_eq!(numbers.0, [1, 0, 3, 2]);
+        assert!(reorder.is_holding());
+
+        assert!(reorder.flush(info(), &mut numbers));
+        assert_eq!(numbers.0, [1, 0, 3, 2, 4]);
+        assert_eq!(reorder.swaps(), 2);
+    }
+}
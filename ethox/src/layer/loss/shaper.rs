@@ -0,0 +1,169 @@
+use crate::layer::{Error, Result};
+use crate::nic;
+use crate::time::{Instant, TokenBucket};
+use crate::wire::Payload;
+
+/// A token bucket limiting the bandwidth of sent packets.
+///
+/// The bucket refills at a configured rate of bytes per second up to a maximum burst. Each sent
+/// frame consumes as many tokens as its full length in bytes. That length is only known once the
+/// frame is complete, after it was queued. A frame is thus queued whenever the bucket is not
+/// empty, and the last frame may overdraw the bucket. Later frames wait until the debt is repaid.
+/// A frame that finds the bucket empty fails to queue with `Exhausted` and is counted as
+/// throttled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Shaper {
+    /// Available tokens, one for each byte.
+    tokens: TokenBucket,
+    /// The number of frames that were not sent.
+    throttled: u64,
+}
+
+/// An adaptor shaping the packets sent by the wrapped sender.
+///
+/// The sender sees a [`Deferred`] handle which refuses to queue while the bucket is empty. The
+/// tokens for a queued frame are consumed after the sender has finished preparing it, when its
+/// length is known.
+///
+/// [`Deferred`]: struct.Deferred.html
+pub struct Shaped<'a, I>(pub I, pub &'a mut Shaper);
+
+/// A handle queueing packets only while the shaper has tokens left.
+///
+/// Its info is the one of the wrapped handle.
+pub struct Deferred<'a, H: ?Sized> {
+    handle: &'a mut H,
+    shaper: &'a mut Shaper,
+    queued: bool,
+}
+
+impl Shaper {
+    /// Create a shaper sending `rate` bytes per second on average and up to `burst` bytes at once.
+    pub fn new(rate: u32, burst: u32) -> Self {
+        Shaper {
+            tokens: TokenBucket::new(rate, burst),
+            throttled: 0,
+        }
+    }
+
+    /// Wrap a sender to shape the packets it sends.
+    pub fn shape<I>(&mut self, sender: I) -> Shaped<'_, I> {
+        Shaped(sender, self)
+    }
+
+    /// The number of frames that were not sent due to a lack of tokens.
+    pub fn throttled(&self) -> u64 {
+        self.throttled
+    }
+
+    /// Consume the tokens for a frame of `len` bytes at some time.
+    ///
+    /// Returns `Exhausted` and consumes nothing if not enough tokens are available.
+    pub fn admit(&mut self, len: usize, now: Instant) -> Result<()> {
+        if !self.tokens.take(len as u64, now) {
+            self.throttled += 1;
+            return Err(Error::Exhausted);
+        }
+
+        Ok(())
+    }
+
+    /// Check if a frame of yet unknown length may be sent at some time.
+    fn permits(&mut self, now: Instant) -> bool {
+        if self.tokens.is_empty(now) {
+            self.throttled += 1;
+            return false;
+        }
+
+        true
+    }
+}
+
+impl<H, P, I> nic::Send<H, P> for Shaped<'_, I>
+where
+    H: nic::Handle + ?Sized,
+    P: Payload + ?Sized,
+    I: for<'h> nic::Send<Deferred<'h, H>, P>,
+{
+    fn send(&mut self, packet: nic::Packet<H, P>) {
+        let nic::Packet { handle, payload } = packet;
+        let mut deferred = Deferred {
+            handle,
+            shaper: &mut *self.1,
+            queued: false,
+        };
+
+        self.0.send(nic::Packet {
+            handle: &mut deferred,
+            payload: &mut *payload,
+        });
+
+        if deferred.queued {
+            let len = payload.payload().as_slice().len();
+            self.1.tokens.overdraw(len as u64);
+        }
+    }
+}
+
+impl<H: nic::Handle + ?Sized> nic::Handle for Deferred<'_, H> {
+    fn queue(&mut self) -> Result<()> {
+        let now = self.handle.info().timestamp();
+        if !self.shaper.permits(now) {
+            return Err(Error::Exhausted);
+        }
+
+        self.handle.queue()?;
+        self.queued = true;
+        Ok(())
+    }
+
+    fn info(&self) -> &dyn nic::Info {
+        self.handle.info()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nic::{external::External, Device};
+    use crate::time::Duration;
+
+    /// Send complete frames, recording the result of queueing each.
+    #[derive(Default)]
+    struct Frames(Vec<Result<()>>);
+
+    impl<H: nic::Handle + ?Sized> nic::Send<H, Vec<u8>> for Frames {
+        fn send(&mut self, packet: nic::Packet<H, Vec<u8>>) {
+            self.0.push(packet.handle.queue());
+        }
+    }
+
+    #[test]
+    fn throttled() {
+        let mut nic = External::new_send(vec![vec![0; 100]; 8]);
+        let mut shaper = Shaper::new(1000, 300);
+
+        // The burst allows three frames at once, the others are refused.
+        let mut frames = Frames::default();
+        assert_eq!(nic.tx(5, shaper.shape(&mut frames)), Ok(3));
+        let refused = Err(Error::Exhausted);
+        assert_eq!(frames.0, [Ok(()), Ok(()), Ok(()), refused, refused]);
+        assert_eq!(shaper.throttled(), 2);
+        assert_eq!(shaper.admit(100, Instant::from_millis(0)), Err(Error::Exhausted));
+
+        // Some tokens allow another frame, which overdraws the bucket.
+        nic.set_current_time(Instant::from_millis(50));
+        assert_eq!(nic.tx(1, shaper.shape(Frames::default())), Ok(1));
+
+        // The debt is repaid first.
+        nic.set_current_time(Instant::from_millis(100));
+        let mut frames = Frames::default();
+        assert_eq!(nic.tx(1, shaper.shape(&mut frames)), Ok(0));
+        assert_eq!(frames.0, [refused]);
+
+        nic.set_current_time(Instant::from_millis(100) + Duration::from_millis(50));
+        assert_eq!(nic.tx(5, shaper.shape(Frames::default())), Ok(1));
+        assert_eq!(nic.queued().len(), 5);
+        assert_eq!(shaper.throttled(), 8);
+    }
+}
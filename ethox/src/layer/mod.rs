@@ -134,12 +134,17 @@
 //! Might also save on capability information and timestamp queries.
 
 pub mod arp;
+pub mod dhcpv4;
 pub mod eth;
 pub mod icmp;
 pub mod ip;
+pub mod limit;
 pub mod loss;
 pub mod udp;
 pub mod tcp;
+pub mod tftp;
+pub mod trace;
+pub mod vrrp;
 
 /// A shortened result type for a generic layer operation.
 pub type Result<T> = core::result::Result<T, Error>;
@@ -176,12 +181,209 @@ pub enum Error {
     // TODO
 }
 
+/// The reason for a layer to silently drop a received packet.
+///
+/// Reported to the drop hook of the ethernet endpoint, see [`eth::Endpoint::set_drop_hook`].
+/// Packets that are handed to an upper layer handler are never reported, even if that handler
+/// then decides to ignore them.
+///
+/// [`eth::Endpoint::set_drop_hook`]: eth/struct.Endpoint.html#method.set_drop_hook
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// The packet was valid but not addressed to us or rejected by the configured policy.
+    Filtered,
+
+    /// The packet could not be parsed, for example because it was truncated.
+    Malformed,
+
+    /// The checksum of the packet was incorrect.
+    Checksum,
+
+    /// The packet uses a protocol or feature that the layer does not handle.
+    Unsupported,
+
+    /// No route was found for the packet.
+    NoRoute,
+
+    /// No port or connection is bound to the destination of the packet.
+    Unbound,
+
+    /// The packet exceeded a configured rate limit.
+    RateLimited,
+
+    /// The packet nests more headers or encapsulations than the configured maximum.
+    TooDeep,
+
+    /// A fragment could not be reassembled.
+    ///
+    /// Its datagram overlapped, did not fit into a reassembly buffer or all buffers were in use.
+    Reassembly,
+
+    /// The hop limit of a packet to forward was exhausted.
+    HopLimit,
+
+    /// The link layer address of the next hop of a packet to forward is not known yet.
+    ///
+    /// A lookup of the neighbor has been started, later packets on the path can be sent once it
+    /// is answered.
+    Unresolved,
+}
+
 /// A standard wrapper for a function implementing receive or send traits.
 ///
 /// Keeps the type alias overhead low by providing a single wrapper type that implements the send
 /// and receive traits for all layers, where applicable.
 pub struct FnHandler<F>(pub F);
 
+/// A wrapper for a function that carries explicit state between its calls.
+///
+/// Implements the send and receive traits for the same layers as [`FnHandler`], with the function
+/// receiving a mutable reference to the state alongside each packet. Pass the handler by mutable
+/// reference to inspect the state after a run, or recover it with [`into_state`]. This avoids
+/// closures that capture counters or collected packets by reference.
+///
+/// [`FnHandler`]: struct.FnHandler.html
+/// [`into_state`]: #method.into_state
+pub struct StatefulHandler<S, F> {
+    pub(crate) state: S,
+    pub(crate) handler: F,
+}
+
+/// A handler that inspects packets without consuming them.
+///
+/// Implemented by [`FnHandler`] and [`StatefulHandler`] for functions taking a reference to the
+/// packet, and used by [`Tee`] to run several handlers on a single packet.
+///
+/// [`FnHandler`]: struct.FnHandler.html
+/// [`StatefulHandler`]: struct.StatefulHandler.html
+/// [`Tee`]: struct.Tee.html
+pub trait Inspect<T: ?Sized> {
+    /// Look at one packet.
+    fn inspect(&mut self, packet: &T);
+}
+
+/// A receiver running two handlers on each incoming packet.
+///
+/// Implements the receive trait of the ethernet, ip, udp and icmp layers by calling `A` and then
+/// `B` with a borrowed view of the same packet, for example to both count and log all traffic.
+/// Handlers are combined by passing them by mutable reference or by nesting tees.
+///
+/// Handlers that mutate, answer or forward a packet can not be teed since this consumes the
+/// packet. Neither handler gets the packet itself and it is released after both have seen it.
+/// Use [`nic::recv_tee`] to observe packets before they are handled by a consuming receiver.
+///
+/// [`nic::recv_tee`]: ../nic/fn.recv_tee.html
+pub struct Tee<A, B> {
+    pub(crate) first: A,
+    pub(crate) second: B,
+}
+
+impl<A, B> Tee<A, B> {
+    /// Run `first` and then `second` on each packet.
+    pub fn new(first: A, second: B) -> Self {
+        Tee { first, second }
+    }
+
+    /// Unwrap both handlers.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+
+    /// Present a packet to both handlers.
+    pub(crate) fn inspect<T: ?Sized>(&mut self, packet: &T)
+        where A: Inspect<T>, B: Inspect<T>,
+    {
+        self.first.inspect(packet);
+        self.second.inspect(packet);
+    }
+}
+
+impl<T: ?Sized, F> Inspect<T> for FnHandler<F>
+    where F: FnMut(&T)
+{
+    fn inspect(&mut self, packet: &T) {
+        self.0(packet)
+    }
+}
+
+impl<T: ?Sized, S, F> Inspect<T> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, &T)
+{
+    fn inspect(&mut self, packet: &T) {
+        (self.handler)(&mut self.state, packet)
+    }
+}
+
+impl<T: ?Sized, I> Inspect<T> for &'_ mut I
+    where I: Inspect<T>
+{
+    fn inspect(&mut self, packet: &T) {
+        (**self).inspect(packet)
+    }
+}
+
+impl<S, F> StatefulHandler<S, F> {
+    /// Wrap a function with its initial state.
+    pub fn new(state: S, handler: F) -> Self {
+        StatefulHandler { state, handler }
+    }
+
+    /// Get a reference to the state.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Get a mutable reference to the state.
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// Unwrap the accumulated state.
+    pub fn into_state(self) -> S {
+        self.state
+    }
+}
+
+/// Acceptance policy for traffic not directed at a single host.
+///
+/// The policy is configured on the ethernet endpoint and consulted by all layers above it through
+/// the ethernet [`Controller`]. This ensures that the decision whether to accept a broadcast is
+/// made consistently instead of separately in each layer. The default accepts both link layer and
+/// network layer broadcasts but no link layer multicast.
+///
+/// [`Controller`]: eth/struct.Controller.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CastPolicy {
+    /// Accept frames sent to the ethernet broadcast address.
+    ///
+    /// Note that turning this off also stops all ARP requests from reaching the ip layer.
+    pub link_broadcast: bool,
+
+    /// Accept frames sent to any ethernet multicast address.
+    ///
+    /// Frames to groups joined on the ethernet endpoint are accepted regardless, this is a coarse
+    /// alternative to group membership. Protocols such as VRRP which communicate over a link
+    /// layer multicast group require either.
+    pub link_multicast: bool,
+
+    /// Accept IP packets sent to a broadcast address.
+    ///
+    /// For IPv4 these are the limited broadcast address and the directed broadcast addresses of
+    /// all configured subnets. For IPv6, which has no broadcast, this refers to the link-local
+    /// all-nodes address `ff02::1` instead. Other multicast groups are unaffected.
+    pub ip_broadcast: bool,
+}
+
+impl Default for CastPolicy {
+    fn default() -> Self {
+        CastPolicy {
+            link_broadcast: true,
+            link_multicast: false,
+            ip_broadcast: true,
+        }
+    }
+}
+
 /// Can convert from a wire error.
 ///
 /// This indicates some layer tried to operate on a packet but failed.
@@ -191,6 +393,18 @@ impl From<crate::wire::Error> for Error {
     }
 }
 
+/// Classify the error of parsing a packet.
+impl From<crate::wire::Error> for DropReason {
+    fn from(err: crate::wire::Error) -> Self {
+        use crate::wire::Error;
+        match err {
+            Error::WrongChecksum => DropReason::Checksum,
+            Error::Unrecognized | Error::Unsupported => DropReason::Unsupported,
+            _ => DropReason::Malformed,
+        }
+    }
+}
+
 /// Can convert from a payload error.
 ///
 /// One common cause is failure to resize the buffer to the necessary size.
@@ -17,6 +17,21 @@ use super::endpoint::{
     Slot,
     SlotKey};
 
+/// The largest receive window that can be indicated, with the maximum window scale of 14.
+pub(crate) const MAX_WINDOW: u32 = (u16::max_value() as u32) << 14;
+
+/// The lower bound of the retransmission timeout, RFC6298 (2.4).
+pub(crate) const MIN_RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The upper bound of the retransmission timeout when backing off, RFC6298 (2.5).
+pub(crate) const MAX_RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The clock granularity assumed for the variation term, `G` in RFC6298.
+const CLOCK_GRANULARITY: Duration = Duration::from_millis(1);
+
+/// The number of segments in flight that can be queued for retransmission.
+pub(crate) const RETRANSMIT_QUEUE_LEN: usize = 16;
+
 /// The state of a connection.
 ///
 /// Includes current state machine state, the configuration state that is required to stay constant
@@ -72,12 +87,26 @@ pub struct Connection {
     /// For compliance with RFC1122 this MUST NOT be greater than 500ms but it could be smaller.
     pub ack_timeout: Duration,
 
-    /// When to start retransmission and/or detect a loss.
+    /// When to retransmit our SYN, or to delete the connection after time wait.
+    ///
+    /// Segments with data are timed on their own, see `retransmit`.
     pub retransmission_timer: Instant,
 
-    /// The duration of the retransmission timer.
+    /// The retransmission timeout of newly sent segments.
+    ///
+    /// Computed from the round-trip time estimate. Each segment in flight then doubles its own
+    /// timeout for each expiration.
     pub retransmission_timeout: Duration,
 
+    /// The round-trip time estimation.
+    pub round_trip: RoundTrip,
+
+    /// The segments in flight, timed for retransmission.
+    pub retransmit: RetransmitQueue,
+
+    /// Probing of the remote while the connection is idle.
+    pub keepalive: Keepalive,
+
     /// Timeout of no packets in either direction after which restart is used.
     ///
     /// This will only occur if no data is to be transmitted in either direction as otherwise we
@@ -87,9 +116,15 @@ pub struct Connection {
 
     /// If we are permitted to use SACKs.
     ///
-    /// This is true if the SYN packet allowed it in its options since we support it [WIP].
+    /// This is true if the SYN packet allowed it in its options since we always offer it, RFC2018.
     pub selective_acknowledgements: bool,
 
+    /// If small segments are sent without delay.
+    ///
+    /// Disables Nagle's algorithm which otherwise holds back a segment smaller than the maximum
+    /// segment size while data is unacknowledged, RFC1122. This is the analogue of `TCP_NODELAY`.
+    pub nodelay: bool,
+
     /// Counter of duplicated acks.
     pub duplicate_ack: u8,
 
@@ -138,6 +173,17 @@ pub struct Send {
     /// Guaranteed to be at most 14 so that shifting the window in a `u32`/`i32` is always safe.
     pub window_scale: u8,
 
+    /// The send buffer size.
+    ///
+    /// Bounds the number of bytes in flight regardless of the window indicated by the receiver.
+    /// This is the analogue of `SO_SNDBUF`.
+    pub buffer: u32,
+
+    /// Blocks of data selectively acknowledged by the receiver.
+    ///
+    /// Each lies after `unacked` and not beyond `next`. These need not be retransmitted.
+    pub sacked: [Option<(tcp::SeqNumber, tcp::SeqNumber)>; 3],
+
     /// The initial sequence number.
     ///
     /// This is read-only and only kept for potentially reading it for debugging later. It
@@ -146,6 +192,93 @@ pub struct Send {
     pub initial_seq: tcp::SeqNumber,
 }
 
+/// The round-trip time estimation for the retransmission timeout.
+///
+/// Follows the algorithm of Jacobson and Karels, as specified in RFC6298. One segment at a time is
+/// timed and, per Karn's algorithm, never one that was retransmitted.
+#[derive(Clone, Copy, Debug, Default, Hash)]
+pub struct RoundTrip {
+    /// The smoothed round-trip time.
+    ///
+    /// `None` until the first measurement. In RFC6298 this is referred to as `SRTT`.
+    pub smoothed: Option<Duration>,
+
+    /// The round-trip time variation.
+    ///
+    /// In RFC6298 this is referred to as `RTTVAR`.
+    pub variation: Duration,
+
+    /// The segment being timed.
+    ///
+    /// The sequence number acknowledging it completely and the time at which it was sent.
+    pub timed: Option<(tcp::SeqNumber, Instant)>,
+}
+
+/// The segments in flight, queued for retransmission.
+///
+/// Segments are keyed by their sequence number and each has its own retransmission timer. The
+/// timeout starts out as the one of the connection and is doubled for each expiration, up to a
+/// cap. The data itself stays in the send buffer of the user, only the sequence space of each
+/// segment is queued. The fixed capacity bounds the number of segments in flight.
+#[derive(Clone, Copy, Debug, Default, Hash)]
+pub struct RetransmitQueue {
+    /// The queued segments, ordered by sequence number and followed by empty entries.
+    pub segments: [Option<Outstanding>; RETRANSMIT_QUEUE_LEN],
+}
+
+/// A segment in flight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Outstanding {
+    /// The first sequence number that is not yet acknowledged.
+    pub seq: tcp::SeqNumber,
+
+    /// The number of data bytes from `seq` on.
+    pub data_len: usize,
+
+    /// If the segment ends with our FIN.
+    pub fin: bool,
+
+    /// When the segment is retransmitted unless it has been acknowledged.
+    pub deadline: Instant,
+
+    /// The current retransmission timeout of the segment.
+    pub timeout: Duration,
+}
+
+/// The segments whose retransmission timer expired at some point in time.
+///
+/// Created by [`Connection::poll`]. The timer of each yielded segment is restarted with a doubled
+/// timeout, segments that are not consumed stay expired.
+///
+/// [`Connection::poll`]: struct.Connection.html#method.poll
+#[derive(Debug)]
+pub struct Retransmit<'a> {
+    connection: &'a mut Connection,
+    now: Instant,
+}
+
+/// Keepalive probing of an idle connection, RFC1122 4.2.3.6.
+///
+/// Probes are only sent while no data is in flight, otherwise the retransmission timeout detects
+/// an unresponsive remote. Each probe repeats the last acknowledged sequence number so that the
+/// remote answers with an ACK.
+#[derive(Clone, Copy, Debug, Default, Hash)]
+pub struct Keepalive {
+    /// The idle time without incoming segments after which to probe.
+    ///
+    /// Probing is disabled if this is `None`, which is the default.
+    pub idle: Option<Duration>,
+
+    /// The time between unanswered probes.
+    pub interval: Duration,
+
+    /// The number of unanswered probes after which the connection is deemed dead.
+    pub probes: u8,
+
+    /// The number of probes sent since the last incoming segment.
+    pub sent: u8,
+}
+
 /// The connection state relevant for incoming segments.
 #[derive(Clone, Copy, Debug, Hash)]
 pub struct Receive {
@@ -162,7 +295,7 @@ pub struct Receive {
     /// relative to this value instead of `next`. In Linux, this is called `wup`.
     pub acked: tcp::SeqNumber,
 
-    /// The time the last segment was sent.
+    /// The time the last acceptable segment arrived.
     pub last_time: Instant,
 
     /// The receive window size indicated by us.
@@ -176,6 +309,18 @@ pub struct Receive {
     /// Guaranteed to be at most 14 so that shifting the window in a `u32`/`i32` is always safe.
     pub window_scale: u8,
 
+    /// The receive buffer size.
+    ///
+    /// Bounds the window indicated to the remote, independent of the space the receive buffer
+    /// reports. This is the analogue of `SO_RCVBUF`. It never exceeds the largest window that can
+    /// be expressed with `window_scale`.
+    pub buffer: u32,
+
+    /// Blocks of data received beyond missing data.
+    ///
+    /// Reported to the remote in our segments if selective acknowledgements were negotiated.
+    pub sack: [Option<(tcp::SeqNumber, tcp::SeqNumber)>; 3],
+
     /// The initial receive sequence number.
     ///
     /// This is read-only and only kept for potentially reading it for debugging later. It
@@ -261,6 +406,12 @@ pub struct Signals {
     /// Whether the Operator could send data.
     pub may_send: bool,
 
+    /// The connection just became established.
+    pub established: bool,
+
+    /// A listening connection received a SYN and left its listen state.
+    pub syn_received: bool,
+
     /// Need to send some tcp answer.
     ///
     /// Since TCP must assume every packet to be potentially lost it is likely technically fine
@@ -346,6 +497,12 @@ pub struct OutSignals {
     /// Some packets (ACKs or during connection closing) are only generated after the data of an
     /// incoming segment has been read.
     pub segment: Option<Segment>,
+
+    /// The retransmission timer expired.
+    pub timeout: bool,
+
+    /// The connection was aborted without notifying the remote.
+    pub reset: bool,
 }
 
 /// An internal, lifetime erased trait for controlling connections of an `Endpoint`.
@@ -424,8 +581,12 @@ impl Connection {
             ack_timeout: Duration::from_millis(0),
             retransmission_timer: Instant::from_millis(0),
             retransmission_timeout: Duration::from_millis(0),
+            round_trip: RoundTrip::default(),
+            retransmit: RetransmitQueue::default(),
+            keepalive: Keepalive::default(),
             restart_timeout: Duration::from_millis(0),
             selective_acknowledgements: false,
+            nodelay: false,
             duplicate_ack: 0,
             send: Send {
                 unacked: tcp::SeqNumber::default(),
@@ -434,6 +595,8 @@ impl Connection {
                 unsent: 0,
                 window: 0,
                 window_scale: 0,
+                buffer: 0,
+                sacked: [None; 3],
                 initial_seq: tcp::SeqNumber::default(),
             },
             recv: Receive {
@@ -442,6 +605,8 @@ impl Connection {
                 last_time: Instant::from_millis(0),
                 window: 0,
                 window_scale: 0,
+                buffer: 0,
+                sack: [None; 3],
                 initial_seq: tcp::SeqNumber::default(),
             },
         }
@@ -453,11 +618,63 @@ impl Connection {
             State::Closed => self.arrives_closed(incoming),
             State::Listen => self.arrives_listen(incoming, entry),
             State::SynSent => self.arrives_syn_sent(incoming, entry),
+            State::SynReceived => self.arrives_syn_received(incoming, entry),
             State::Established | State::FinWait => self.arrives_established(incoming, entry),
             _ => unimplemented!(),
         }
     }
 
+    /// Change the send buffer size.
+    ///
+    /// Takes effect immediately, a smaller buffer only stops new data from being sent until
+    /// enough of the data in flight has been acknowledged.
+    pub fn set_send_buffer(&mut self, size: usize) {
+        self.send.set_buffer(size);
+    }
+
+    /// Choose whether to send small segments immediately.
+    ///
+    /// By default, Nagle's algorithm coalesces small writes while earlier data is unacknowledged.
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.nodelay = nodelay;
+    }
+
+    /// Configure keepalive probes.
+    ///
+    /// After `idle` time without any incoming segment a probe is sent, and repeated every
+    /// `interval` while unanswered. Once `probes` of them went unanswered the connection is
+    /// closed. Passing `None` disables probing.
+    pub fn set_keepalive(&mut self, idle: Option<Duration>, interval: Duration, probes: u8) {
+        self.keepalive = Keepalive {
+            idle,
+            interval,
+            probes,
+            sent: 0,
+        };
+    }
+
+    /// Offer a fixed window scale in the SYN.
+    ///
+    /// Only has an effect before the SYN has been sent. The receive buffer is capped to the
+    /// largest window that the scale can express.
+    pub fn set_window_scale(&mut self, scale: u8) {
+        if let State::Closed | State::Listen = self.current {
+            self.recv.init_scale(scale);
+        }
+    }
+
+    /// Change the receive buffer size.
+    ///
+    /// Before the SYN has been sent this also chooses the window scale. Afterwards, the buffer can
+    /// only grow as far as the negotiated scale allows. The indicated window is adjusted the next
+    /// time the receive buffer is consulted.
+    pub fn set_recv_buffer(&mut self, size: usize) {
+        match self.current {
+            State::Closed | State::Listen => self.recv.init_buffer(size),
+            _ => self.recv.set_buffer(size),
+        }
+    }
+
     /// Realize the effect of opening SYN packet.
     pub fn open(&mut self, time: Instant, entry: EntryKey)
         -> Result<(), crate::layer::Error>
@@ -482,40 +699,9 @@ impl Connection {
     /// Except when an RST flag is already set on the received packet. Probably the easiest packet
     /// flow.
     fn arrives_closed(&mut self, incoming: &InPacket) -> Signals {
-        let segment = &incoming.segment;
         let mut signals = Signals::default();
-        if segment.flags.rst() {
-            // Avoid answering with RST when packet has RST set.
-            // TODO: debug counters or tracing
-            return signals;
-        }
-
-        if let Some(ack_number) = segment.ack_number {
-            signals.answer = Some(InnerRepr {
-                flags: tcp::Flags::RST,
-                seq_number: ack_number,
-                ack_number: None,
-                window_len: 0,
-                window_scale: None,
-                max_seg_size: None,
-                sack_permitted: false,
-                sack_ranges: [None; 3],
-                payload_len: 0,
-            }.send_back(segment));
-        } else {
-            signals.answer = Some(InnerRepr {
-                flags: tcp::Flags::RST,
-                seq_number: tcp::SeqNumber(0),
-                ack_number: Some(segment.seq_number + segment.sequence_len()),
-                window_len: 0,
-                window_scale: None,
-                max_seg_size: None,
-                sack_permitted: false,
-                sack_ranges: [None; 3],
-                payload_len: 0,
-            }.send_back(segment));
-        }
-
+        // TODO: debug counters or tracing when the packet had RST set.
+        signals.answer = reset_closed(&incoming.segment);
         return signals;
     }
 
@@ -561,29 +747,86 @@ impl Connection {
         let current_four = entry.four_tuple();
         let new_four = FourTuple {
             remote: *from,
+            remote_port: segment.src_port,
             .. current_four
         };
         entry.set_four_tuple(new_four);
         self.recv.next = segment.seq_number + 1;
         self.recv.initial_seq = segment.seq_number;
+        self.negotiate_scaling(segment);
+        self.selective_acknowledgements = segment.sack_permitted;
 
+        // TODO: better mss
+        self.sender_maximum_segment_size = segment.max_seg_size
+            .unwrap_or(536)
+            .max(536);
+        self.receiver_maximum_segment_size = self.sender_maximum_segment_size;
+
+        // The sequence number is chosen for the complete tuple, RFC6528.
         let isn = entry.initial_seq_num(*time);
         self.send.next = isn + 1;
         self.send.unacked = isn;
         self.send.initial_seq = isn;
 
-        signals.answer = Some(InnerRepr {
-            flags: tcp::Flags::RST,
-            seq_number: isn,
-            ack_number: Some(self.ack_all()),
-            window_len: self.recv.window,
-            window_scale: None,
-            max_seg_size: None,
-            sack_permitted: false,
-            sack_ranges: [None; 3],
-            payload_len: 0,
-        }.send_to(new_four));
+        self.change_state(State::SynReceived);
+        self.rearm_retransmission_timer(*time);
+        signals.syn_received = true;
+        signals.answer = Some(self.send_open(true, new_four));
+        signals
+    }
+
+    /// Handle an incoming packet in SynReceived state.
+    ///
+    /// Waits for the ACK of our SYN, retransmitting the SYN-ACK if the SYN is repeated.
+    fn arrives_syn_received(&mut self, incoming: &InPacket, entry: EntryKey)
+        -> Signals
+    {
+        let InPacket { segment, from: _, time: _, } = incoming;
+
+        if segment.flags.rst() {
+            if self.recv.in_window(segment.seq_number) {
+                return self.remote_reset_connection();
+            }
+            return Signals::default();
+        }
+
+        if segment.flags.syn() {
+            // The SYN-ACK got lost and the remote repeats its SYN.
+            if segment.seq_number == self.recv.initial_seq && segment.ack_number.is_none() {
+                let mut signals = Signals::default();
+                signals.answer = Some(self.send_open(true, entry.four_tuple()));
+                return signals;
+            }
+
+            return self.signal_reset_connection(segment, entry);
+        }
 
+        let ack = match segment.ack_number {
+            None => return Signals::default(),
+            Some(ack) => ack,
+        };
+
+        if ack != self.send.next {
+            // Acks something we did not send. Reset without affecting the connection.
+            let mut signals = Signals::default();
+            signals.answer = Some(InnerRepr {
+                flags: tcp::Flags::RST,
+                seq_number: ack,
+                ack_number: None,
+                window_len: 0,
+                window_scale: None,
+                max_seg_size: None,
+                sack_permitted: false,
+                sack_ranges: [None; 3],
+                payload_len: 0,
+            }.send_back(segment));
+            return signals;
+        }
+
+        self.change_state(State::Established);
+        // The ack of the SYN and any data are processed as on the open connection.
+        let mut signals = self.arrives_established(incoming, entry);
+        signals.established = true;
         signals
     }
 
@@ -631,8 +874,8 @@ impl Connection {
 
         self.recv.initial_seq = segment.seq_number;
         self.recv.next = segment.seq_number + 1;
-        self.send.window = segment.window_len;
-        self.send.window_scale = segment.window_scale.unwrap_or(0);
+        self.negotiate_scaling(segment);
+        self.selective_acknowledgements = segment.sack_permitted;
 
         // TODO: better mss
         self.sender_maximum_segment_size = segment.max_seg_size
@@ -654,10 +897,13 @@ impl Connection {
         }
 
         self.change_state(State::Established);
+        self.recv.last_time = *time;
         // The rfc would immediately ack etc. We may want to send data and that requires the
         // cooperation of io. Defer but mark as ack required immediately.
         self.ack_timer = Expiration::When(*time);
-        return Signals::default();
+        let mut signals = Signals::default();
+        signals.established = true;
+        return signals;
     }
 
     fn arrives_established(&mut self, incoming: &InPacket, entry: EntryKey) -> Signals {
@@ -675,6 +921,10 @@ impl Connection {
             return self.signal_ack_all(entry.four_tuple());
         }
 
+        // The remote is alive.
+        self.recv.last_time = *time;
+        self.keepalive.sent = 0;
+
         if segment.flags.syn() {
             debug_assert!(self.recv.in_window(segment.seq_number));
 
@@ -694,6 +944,8 @@ impl Connection {
                 // Ignore the packet but we ack back the previous state.
                 return self.signal_ack_all(entry.four_tuple());
             },
+            // Segments with data, or without any data in flight, are no duplicates, RFC5681.
+            AckUpdate::Duplicate if segment.payload_len > 0 || self.send.in_flight() == 0 => (),
             AckUpdate::Duplicate => {
                 self.duplicate_ack = self.duplicate_ack.saturating_add(1);
                 /*
@@ -711,9 +963,15 @@ impl Connection {
                 }
                 self.send.window = segment.window_len;
                 self.window_update(segment, new_bytes);
+                self.round_trip_update(ack, *time);
+                self.retransmit.acknowledge(ack);
             },
         }
 
+        if self.selective_acknowledgements {
+            self.send.update_sacked(&segment.sack_ranges);
+        }
+
         // URG lol
 
         let segment_ack = ReceivedSegment {
@@ -813,24 +1071,64 @@ impl Connection {
             window_scale: None,
             max_seg_size: None,
             sack_permitted: false,
-            sack_ranges: [None; 3],
+            sack_ranges: self.sack_ranges(),
             payload_len: 0,
         }.send_to(remote)
     }
 
+    /// The SACK option blocks for data buffered beyond `recv.next`.
+    fn sack_ranges(&self) -> [Option<(u32, u32)>; 3] {
+        let mut ranges = [None; 3];
+        if !self.selective_acknowledgements {
+            return ranges;
+        }
+
+        let next = self.recv.next;
+        for (range, block) in ranges.iter_mut().zip(&self.recv.sack) {
+            *range = block
+                .filter(|&(begin, end)| next < begin && begin < end)
+                .map(|(begin, end)| (begin.0 as u32, end.0 as u32));
+        }
+        ranges
+    }
+
+    /// Apply the window scale option of the remote's SYN, RFC7323.
+    ///
+    /// Scaling applies in both directions only if both sides offered it, otherwise both windows
+    /// are unscaled. The window of the SYN itself is never scaled.
+    fn negotiate_scaling(&mut self, segment: &tcp::Repr) {
+        match segment.window_scale {
+            // Larger shifts are treated as the maximum of 14.
+            Some(scale) => self.send.window_scale = scale.min(14),
+            None => {
+                self.send.window_scale = 0;
+                self.recv.disable_scaling();
+            },
+        }
+
+        // Rounds down until the next segment indicates the scaled window.
+        self.send.window = segment.window_len >> self.send.window_scale;
+    }
+
     /// Send a SYN.
     ///
     /// If `ack` is true then it also acknowledges received segments (i.e. this is a passive open).
+    /// The window scale and SACK-permitted options are then only included if the remote offered
+    /// them as well.
     fn send_open(&mut self, ack: bool, to: FourTuple) -> tcp::Repr {
         let ack_number = if ack { Some(self.ack_all()) } else { None };
+        // Without an offer from the remote both scales are zero.
+        let scaling = !ack || self.recv.window_scale != 0 || self.send.window_scale != 0;
         InnerRepr {
             flags: tcp::Flags::SYN,
             seq_number: self.send.initial_seq,
             ack_number,
-            window_len: 0,
-            window_scale: Some(self.send.window_scale),
+            // The window of a SYN is never scaled.
+            window_len: u16::try_from(self.recv.window())
+                .unwrap_or_else(|_| u16::max_value()),
+            window_scale: if scaling { Some(self.recv.window_scale) } else { None },
             max_seg_size: None,
-            sack_permitted: false,
+            sack_permitted: !ack || self.selective_acknowledgements,
             sack_ranges: [None; 3],
             payload_len: 0,
         }.send_to(to)
@@ -840,14 +1138,40 @@ impl Connection {
     ///
     /// May choose to send an empty range for cases where there is no data to send but a delayed
     /// ACK is expected.
-    pub fn next_send_segment(&mut self, mut available: AvailableBytes, time: Instant, entry: EntryKey)
+    pub fn next_send_segment(&mut self, available: AvailableBytes, time: Instant, entry: EntryKey)
+        -> OutSignals
+    {
+        // Only the retransmissions rearm the timers here.
+        let timer = self.retransmission_timer;
+        let expired = self.retransmit.expired(time);
+        let mut signals = self.select_next_segment(available, time, entry);
+        signals.timeout = (timer <= time && self.retransmission_timer != timer)
+            || self.retransmit.expired(time) < expired;
+        signals
+    }
+
+    /// Iterate the segments in flight whose retransmission timer has expired at `now`.
+    ///
+    /// The segments are yielded in the order of their sequence numbers and should be sent again.
+    /// The timeout of each is then doubled, up to a cap, and its timer restarted. Segments that
+    /// are acknowledged before their timer expires are never offered.
+    pub fn poll(&mut self, now: Instant) -> Retransmit<'_> {
+        Retransmit {
+            connection: self,
+            now,
+        }
+    }
+
+    fn select_next_segment(&mut self, mut available: AvailableBytes, time: Instant, entry: EntryKey)
         -> OutSignals
     {
         match self.current {
             State::Established | State::CloseWait => {
-                self.select_send_segment(available, time, entry)
-                    .map(OutSignals::segment)
-                    .unwrap_or_else(OutSignals::none)
+                let tuple = entry.four_tuple();
+                match self.select_send_segment(available, time, entry) {
+                    Some(segment) => OutSignals::segment(segment),
+                    None => self.select_keepalive(time, tuple),
+                }
             },
             // When we have already sent our FIN, never send *new* data.
             State::FinWait | State::Closing | State::LastAck => {
@@ -891,10 +1215,10 @@ impl Connection {
             return self.fast_retransmit(available, time, entry);
         }
 
-        if self.retransmission_timer < time {
-            // Choose segments to retransmit, in contrast to `fast_retransmit` this may influence
-            // multiple next packets.
-            return self.timeout_retransmit(available, time, entry);
+        if let Some(segment) = self.poll(time).next() {
+            // Retransmit the segment, in contrast to `fast_retransmit` other expired segments
+            // follow as the next packets.
+            return self.timeout_retransmit(segment, available, entry.four_tuple());
         }
 
         // That's funny. Even if we have sent a FIN, the other side could decrease their window
@@ -903,11 +1227,18 @@ impl Connection {
             // TODO: congestion flow control
             // .min(self.flow_control.congestion_window);
         let sent = self.send.in_flight();
-        let max_sent = window.min(byte_window);
+        let max_sent = window.min(byte_window).min(self.send.buffer);
+
+        let mss = u32::from(self.sender_maximum_segment_size);
+        let end = sent.saturating_add(mss).min(max_sent);
+        // Nagle's algorithm, RFC1122 4.2.3.4. A small segment waits for the data in flight to be
+        // acknowledged, more data may be written in the meantime.
+        let delayed = !self.nodelay && sent > 0 && end.saturating_sub(sent) < mss
+            && !(available.fin && end as usize == available.total);
 
-        if sent < max_sent {
+        // Without room in the retransmission queue new data waits for acknowledgements.
+        if sent < max_sent && !delayed && !self.retransmit.is_full() {
             // Send one new segment of new data.
-            let end = sent.saturating_add(self.sender_maximum_segment_size.into()).min(max_sent);
             // UNWRAP: Available was larger than `end` so these will not fail (even on 16-bit
             // platforms where the buffer may be smaller than the `u32` window). Math:
             // `sent_u32 <= end_u32 <= available_u32 <= available_usize`
@@ -932,8 +1263,22 @@ impl Connection {
             if is_fin {
                 repr.flags = tcp::Flags::FIN;
             }
+            // Push when this segment empties the send buffer, RFC1122 4.2.2.2.
+            repr.flags.set_psh(end == available.total);
+
+            let timeout = self.retransmission_timeout.max(MIN_RETRANSMISSION_TIMEOUT);
+            self.retransmit.push(Outstanding {
+                seq: self.send.next,
+                data_len: range.len(),
+                fin: is_fin,
+                deadline: time + timeout,
+                timeout,
+            });
 
             self.send.next = self.send.next + range.len() + usize::from(is_fin);
+            if self.round_trip.timed.is_none() {
+                self.round_trip.timed = Some((self.send.next, time));
+            }
 
             return Some(Segment {
                 repr,
@@ -950,6 +1295,41 @@ impl Connection {
         None
     }
 
+    fn select_keepalive(&mut self, time: Instant, tuple: FourTuple) -> OutSignals {
+        let idle = match self.keepalive.idle {
+            Some(idle) => idle,
+            None => return OutSignals::none(),
+        };
+
+        // Retransmissions take care of detecting a dead remote.
+        if self.send.in_flight() != 0 {
+            return OutSignals::none();
+        }
+
+        let keepalive = &mut self.keepalive;
+        let due = self.recv.last_time + idle + keepalive.interval * u32::from(keepalive.sent);
+        if time < due {
+            return OutSignals::none();
+        }
+
+        if keepalive.sent >= keepalive.probes {
+            // No answer to any probe, give up on the connection.
+            self.change_state(State::Closed);
+            let mut signals = OutSignals::none();
+            signals.delete = true;
+            signals.reset = true;
+            return signals;
+        }
+
+        keepalive.sent += 1;
+        let mut repr = self.repr_ack_all(tuple);
+        repr.seq_number = self.send.next - 1;
+        OutSignals::segment(Segment {
+            repr,
+            range: 0..0,
+        })
+    }
+
     fn select_syn_retransmit(&mut self, time: Instant, entry: EntryKey)
         -> Option<Segment>
     {
@@ -977,11 +1357,24 @@ impl Connection {
         self.segment_retransmit(available, entry.four_tuple())
     }
 
-    fn timeout_retransmit(&mut self, available: AvailableBytes, time: Instant, entry: EntryKey)
-        -> Option<Segment>
+    fn timeout_retransmit(&mut self, segment: Outstanding, available: AvailableBytes,
+        tuple: FourTuple) -> Option<Segment>
     {
-        self.rearm_retransmission_timer(time);
-        self.segment_retransmit(available, entry.four_tuple())
+        // The data of the segment is buffered after the acknowledged part.
+        let begin = (segment.seq - self.send.unacked).min(available.total);
+        let range = begin..(begin + segment.data_len).min(available.total);
+        let is_fin = segment.fin && range.end == available.total;
+
+        let mut repr = self.repr_ack_all(tuple);
+        repr.flags.set_fin(is_fin);
+        repr.flags.set_psh(range.end == available.total);
+        repr.seq_number = segment.seq;
+        repr.payload_len = range.len() as u16;
+
+        Some(Segment {
+            repr,
+            range,
+        })
     }
 
     fn segment_retransmit(&mut self, available: AvailableBytes, tuple: FourTuple) -> Option<Segment> {
@@ -999,9 +1392,11 @@ impl Connection {
             return None;
         }
 
+        // Stop in front of data the receiver already holds.
         let to_send = self.send.window()
             .min(u32::from(self.sender_maximum_segment_size))
-            .min(byte_window);
+            .min(byte_window)
+            .min(self.send.unsacked_len());
 
         if to_send == 0 {
             return None;
@@ -1012,6 +1407,7 @@ impl Connection {
 
         let mut repr = self.repr_ack_all(tuple);
         repr.flags.set_fin(is_fin);
+        repr.flags.set_psh(range.end == available.total);
         repr.seq_number = self.send.unacked;
         repr.payload_len = to_send as u16;
 
@@ -1034,10 +1430,14 @@ impl Connection {
             Some(segment) => OutSignals {
                 segment: Some(segment),
                 delete: false,
+                timeout: false,
+                reset: false,
             },
             None => OutSignals {
                 delete: time >= self.retransmission_timer,
                 segment: None,
+                timeout: false,
+                reset: false,
             },
         }
     }
@@ -1092,6 +1492,21 @@ impl Connection {
         self.ack_timer = self.ack_timer.min(new_timer);
     }
 
+    /// Acknowledge a received segment that was buffered beyond missing data.
+    ///
+    /// The remote is informed immediately by a duplicate ACK, as recommended by RFC5681, which may
+    /// trigger a fast retransmit of the missing data.
+    pub fn set_recv_gap(&mut self, meta: ReceivedSegment) {
+        self.ack_timer = Expiration::When(meta.timestamp);
+    }
+
+    /// Update the blocks of data received beyond missing data.
+    ///
+    /// These are reported in the SACK option of subsequent segments if the remote permitted it.
+    pub fn set_recv_sack(&mut self, blocks: [Option<(tcp::SeqNumber, tcp::SeqNumber)>; 3]) {
+        self.recv.sack = blocks;
+    }
+
     /// Get the sequence number of the last byte acknowledged by the other side.
     ///
     /// Always points into the byte sequence space by offsetting a missing SYN in case none has
@@ -1136,7 +1551,50 @@ impl Connection {
     }
 
     fn rearm_retransmission_timer(&mut self, time: Instant) {
-        self.retransmission_timer = time + self.retransmission_timeout;
+        self.retransmission_timer = time + self.retransmission_timeout.max(MIN_RETRANSMISSION_TIMEOUT);
+    }
+
+    /// Take the next expired segment that the remote is missing and restart its timer.
+    fn expire_segment(&mut self, now: Instant) -> Option<Outstanding> {
+        let mut segment = self.retransmit.expire(now)?;
+        // The remote may still discard selectively acknowledged data, keep their timers running.
+        while self.send.is_sacked(segment.seq, segment.end()) {
+            segment = self.retransmit.expire(now)?;
+        }
+
+        // Karn's algorithm, a retransmitted segment is not timed.
+        match self.round_trip.timed {
+            Some((end, _)) if segment.seq < end && end <= segment.end() => {
+                self.round_trip.timed = None;
+            },
+            _ => (),
+        }
+        Some(segment)
+    }
+
+    /// Measure the round-trip time with an ack and recompute the retransmission timeout.
+    fn round_trip_update(&mut self, ack: tcp::SeqNumber, time: Instant) {
+        let rtt = match self.round_trip.timed {
+            Some((seq, sent)) if seq <= ack => time - sent,
+            _ => return,
+        };
+
+        self.round_trip.timed = None;
+        let (smoothed, variation) = match self.round_trip.smoothed {
+            // RFC6298 (2.2), the first measurement.
+            None => (rtt, rtt / 2),
+            // RFC6298 (2.3) with alpha = 1/8 and beta = 1/4.
+            Some(smoothed) => {
+                let deviation = smoothed.abs_diff(rtt);
+                (smoothed * 7 / 8 + rtt / 8, self.round_trip.variation * 3 / 4 + deviation / 4)
+            },
+        };
+
+        self.round_trip.smoothed = Some(smoothed);
+        self.round_trip.variation = variation;
+        self.retransmission_timeout = (smoothed + CLOCK_GRANULARITY.max(variation * 4))
+            .max(MIN_RETRANSMISSION_TIMEOUT)
+            .min(MAX_RETRANSMISSION_TIMEOUT);
     }
 
     pub(crate) fn change_state(&mut self, new: State) {
@@ -1150,24 +1608,138 @@ impl Connection {
     }
 }
 
+/// The answer to a segment arriving for a closed connection.
+///
+/// This is a reset, except when the segment had RST set itself.
+pub(crate) fn reset_closed(segment: &tcp::Repr) -> Option<tcp::Repr> {
+    if segment.flags.rst() {
+        // Avoid answering with RST when packet has RST set.
+        return None;
+    }
+
+    if let Some(ack_number) = segment.ack_number {
+        Some(InnerRepr {
+            flags: tcp::Flags::RST,
+            seq_number: ack_number,
+            ack_number: None,
+            window_len: 0,
+            window_scale: None,
+            max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges: [None; 3],
+            payload_len: 0,
+        }.send_back(segment))
+    } else {
+        Some(InnerRepr {
+            flags: tcp::Flags::RST,
+            seq_number: tcp::SeqNumber(0),
+            ack_number: Some(segment.seq_number + segment.sequence_len()),
+            window_len: 0,
+            window_scale: None,
+            max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges: [None; 3],
+            payload_len: 0,
+        }.send_back(segment))
+    }
+}
+
 impl Receive {
     fn in_window(&self, seq: tcp::SeqNumber) -> bool {
         self.next.contains_in_window(seq, self.window.into())
     }
 
     /// Setup the window based on an incoming (unscaled) window field.
+    ///
+    /// The window is additionally bounded by the receive buffer size.
     pub fn update_window(&mut self, window: usize) {
         let max = u32::from(u16::max_value()) << self.window_scale;
         let capped = u32::try_from(window)
             .unwrap_or_else(|_| u32::max_value())
-            .min(max);
+            .min(max)
+            .min(self.buffer);
         let scaled_down = (capped >> self.window_scale)
             + u32::from(capped % (1 << self.window_scale) != 0);
         self.window = u16::try_from(scaled_down).unwrap();
     }
+
+    /// Choose the window scale and the initial window for a receive buffer size.
+    ///
+    /// The scale is the smallest one with which the whole buffer can be indicated.
+    fn init_buffer(&mut self, size: usize) {
+        let size = u32::try_from(size)
+            .unwrap_or_else(|_| u32::max_value())
+            .min(MAX_WINDOW);
+        self.window_scale = (0..14)
+            .find(|&scale| u32::from(u16::max_value()) << scale >= size)
+            .unwrap_or(14);
+        self.buffer = size;
+        self.update_window(usize::try_from(size).unwrap_or_else(|_| usize::max_value()));
+    }
+
+    /// Use a fixed window scale instead of the smallest one fitting the buffer.
+    ///
+    /// The buffer is capped to the largest window expressible with that scale.
+    fn init_scale(&mut self, scale: u8) {
+        self.window_scale = scale.min(14);
+        let size = usize::try_from(self.buffer).unwrap_or_else(|_| usize::max_value());
+        self.set_buffer(size);
+        self.update_window(size);
+    }
+
+    /// Change the receive buffer size while keeping the window scale.
+    fn set_buffer(&mut self, size: usize) {
+        let max = u32::from(u16::max_value()) << self.window_scale;
+        self.buffer = u32::try_from(size)
+            .unwrap_or_else(|_| u32::max_value())
+            .min(max);
+    }
+
+    /// Fall back to an unscaled window when the remote did not offer window scaling.
+    fn disable_scaling(&mut self) {
+        let window = usize::try_from(self.window())
+            .unwrap_or_else(|_| usize::max_value());
+        self.window_scale = 0;
+        self.buffer = self.buffer.min(u16::max_value().into());
+        self.update_window(window);
+    }
+
+    /// Get the actual window (combination of indicated window and scale).
+    fn window(&self) -> u32 {
+        u32::from(self.window) << self.window_scale
+    }
 }
 
 impl Send {
+    /// Take the SACK blocks of an incoming segment, RFC2018.
+    ///
+    /// Blocks that do not lie within the data in flight are ignored.
+    fn update_sacked(&mut self, ranges: &[Option<(u32, u32)>; 3]) {
+        let (unacked, next) = (self.unacked, self.next);
+        for (block, range) in self.sacked.iter_mut().zip(ranges) {
+            *block = range
+                .map(|(begin, end)| (tcp::SeqNumber(begin as i32), tcp::SeqNumber(end as i32)))
+                .filter(|&(begin, end)| unacked < begin && begin < end && end <= next);
+        }
+    }
+
+    /// The number of bytes from `unacked` up to the first selectively acknowledged block.
+    fn unsacked_len(&self) -> u32 {
+        self.sacked.iter()
+            .flatten()
+            // AS: bounded by the data in flight which fits a `u32`.
+            .map(|&(begin, _)| (begin - self.unacked) as u32)
+            .min()
+            .unwrap_or_else(u32::max_value)
+    }
+
+    /// Check if a range of data in flight lies within a selectively acknowledged block.
+    fn is_sacked(&self, begin: tcp::SeqNumber, end: tcp::SeqNumber) -> bool {
+        self.sacked.iter()
+            .flatten()
+            .any(|&(sack_begin, sack_end)| sack_begin <= begin && end <= sack_end)
+    }
+
     fn incoming_ack(&mut self, seq: tcp::SeqNumber) -> AckUpdate {
         if seq < self.unacked {
             AckUpdate::TooLow
@@ -1188,6 +1760,11 @@ impl Send {
         u32::from(self.window) << self.window_scale
     }
 
+    /// Change the send buffer size.
+    fn set_buffer(&mut self, size: usize) {
+        self.buffer = u32::try_from(size).unwrap_or_else(|_| u32::max_value());
+    }
+
     /// Get the segments in flight.
     fn in_flight(&self) -> u32 {
         assert!(self.unacked <= self.next);
@@ -1195,6 +1772,75 @@ impl Send {
     }
 }
 
+impl RetransmitQueue {
+    /// Check if no further segment can be queued.
+    pub fn is_full(&self) -> bool {
+        self.segments[RETRANSMIT_QUEUE_LEN - 1].is_some()
+    }
+
+    /// The number of segments whose timer has expired at `now`.
+    pub fn expired(&self, now: Instant) -> usize {
+        self.segments.iter()
+            .flatten()
+            .filter(|segment| segment.deadline <= now)
+            .count()
+    }
+
+    /// Queue a segment sent after all queued ones.
+    fn push(&mut self, segment: Outstanding) {
+        if let Some(entry) = self.segments.iter_mut().find(|entry| entry.is_none()) {
+            *entry = Some(segment);
+        }
+    }
+
+    /// Remove acknowledged segments and cut a partially acknowledged one.
+    fn acknowledge(&mut self, ack: tcp::SeqNumber) {
+        let acked = self.segments.iter()
+            .take_while(|entry| matches!(entry, Some(segment) if segment.end() <= ack))
+            .count();
+        self.segments.rotate_left(acked);
+        for entry in &mut self.segments[RETRANSMIT_QUEUE_LEN - acked..] {
+            *entry = None;
+        }
+
+        if let Some(first) = &mut self.segments[0] {
+            if first.seq < ack {
+                let acked = (ack - first.seq).min(first.data_len);
+                first.seq = ack;
+                first.data_len -= acked;
+            }
+        }
+    }
+
+    /// Take the first expired segment and restart its timer.
+    fn expire(&mut self, now: Instant) -> Option<Outstanding> {
+        let segment = self.segments.iter_mut()
+            .flatten()
+            .find(|segment| segment.deadline <= now)?;
+        // RFC6298 (5.5), back off the timer.
+        segment.timeout = segment.timeout
+            .saturating_mul(2)
+            .min(MAX_RETRANSMISSION_TIMEOUT);
+        segment.deadline = now + segment.timeout;
+        Some(*segment)
+    }
+}
+
+impl Outstanding {
+    /// The sequence number after the segment, including a FIN.
+    pub fn end(&self) -> tcp::SeqNumber {
+        self.seq + self.data_len + usize::from(self.fin)
+    }
+}
+
+impl Iterator for Retransmit<'_> {
+    type Item = Outstanding;
+
+    fn next(&mut self) -> Option<Outstanding> {
+        self.connection.expire_segment(self.now)
+    }
+}
+
 impl ReceivedSegment {
     /// Compute the total length in sequence space, including SYN or FIN.
     pub fn sequence_len(&self) -> usize {
@@ -1206,13 +1852,15 @@ impl ReceivedSegment {
     /// Only ack part of the segment until some sequence point.
     ///
     /// Takes care of removing the FIN flag if the acked part does not cover every data byte until
-    /// that point.
+    /// that point. An ack beyond the segment extends it to cover data that was reassembled from
+    /// earlier segments.
     pub fn acked_until(&self, ack: tcp::SeqNumber) -> Self {
         ReceivedSegment {
             syn: self.syn,
             fin: self.fin && ack + 1 >= self.sequence_end(),
             begin: self.begin,
-            data_len: self.data_len,
+            // Data reassembled from earlier segments is acknowledged along with this one.
+            data_len: if ack > self.sequence_end() { ack - self.data_begin() } else { self.data_len },
             timestamp: self.timestamp,
         }
     }
@@ -1249,6 +1897,8 @@ impl OutSignals {
         OutSignals {
             segment: Some(segment),
             delete: false,
+            timeout: false,
+            reset: false,
         }
     }
 }
@@ -1369,7 +2019,9 @@ mod tests {
     use crate::layer::tcp::IsnGenerator;
     use crate::time::Instant;
     use crate::wire::ip::Address;
-    use super::{AvailableBytes, Connection};
+    use crate::time::Duration;
+    use crate::wire::tcp;
+    use super::{AvailableBytes, Connection, InPacket, Range, State};
 
     struct NoRemap;
 
@@ -1405,4 +2057,414 @@ mod tests {
         let available = AvailableBytes { fin: false, total: 0 };
         let _resent = connection.next_send_segment(available, time_resend, entry);
     }
+
+    #[test]
+    fn scaled_recv_window() {
+        let mut small = simple_connection();
+        small.set_recv_buffer(1 << 15);
+        small.recv.update_window(usize::max_value());
+        assert_eq!(small.recv.window_scale, 0);
+        assert_eq!(small.recv.window(), 1 << 15);
+
+        let mut large = simple_connection();
+        large.set_recv_buffer(1 << 20);
+        large.recv.update_window(usize::max_value());
+        assert_eq!(large.recv.window_scale, 5);
+        assert_eq!(large.recv.window, 1 << 15);
+        assert_eq!(large.recv.window(), 1 << 20);
+
+        // The remote did not offer scaling.
+        large.recv.disable_scaling();
+        assert_eq!(large.recv.window_scale, 0);
+        assert_eq!(large.recv.window(), u16::max_value().into());
+    }
+
+    #[test]
+    fn push_last_segment() {
+        let mut connection = simple_connection();
+        connection.current = State::Established;
+        connection.sender_maximum_segment_size = 100;
+        connection.send.window = u16::max_value();
+        connection.send.buffer = u32::max_value();
+        connection.retransmission_timer = Instant::from_secs(100);
+        connection.restart_timeout = Duration::from_secs(100);
+        // The tail of the write is not held back for coalescing.
+        connection.set_nodelay(true);
+
+        let isn = IsnGenerator::from_key(0, 0);
+        let mut no_remap = NoRemap;
+        let mut four = FourTuple {
+            local: Address::v4(192, 0, 10, 1),
+            remote: Address::v4(192, 0, 10, 2),
+            local_port: 80,
+            remote_port: 80,
+        };
+
+        // Flush a write of two and a half segments.
+        let available = AvailableBytes { fin: false, total: 250 };
+        let mut pushed = vec![];
+        for _ in 0..3 {
+            let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+            let segment = connection.next_send_segment(available, Instant::from_secs(0), entry)
+                .segment
+                .expect("Has data to send");
+            assert!(!segment.repr.flags.fin());
+            pushed.push((segment.range, segment.repr.flags.psh()));
+        }
+
+        assert_eq!(pushed, [(0..100, false), (100..200, false), (200..250, true)]);
+    }
+
+    fn established_connection() -> Connection {
+        let mut connection = simple_connection();
+        connection.current = State::Established;
+        connection.sender_maximum_segment_size = 100;
+        connection.send.window = u16::max_value();
+        connection.send.buffer = u32::max_value();
+        connection.restart_timeout = Duration::from_secs(100);
+        connection.retransmission_timeout = Duration::from_secs(1);
+        connection
+    }
+
+    #[test]
+    fn retransmit_backoff() {
+        let mut connection = established_connection();
+        let isn = IsnGenerator::from_key(0, 0);
+        let mut no_remap = NoRemap;
+        let mut four = FourTuple {
+            local: Address::v4(192, 0, 10, 1),
+            remote: Address::v4(192, 0, 10, 2),
+            local_port: 80,
+            remote_port: 80,
+        };
+
+        let available = AvailableBytes { fin: false, total: 200 };
+        let mut send = |connection: &mut Connection, millis| {
+            let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+            connection.next_send_segment(available, Instant::from_millis(millis), entry)
+        };
+
+        let first = send(&mut connection, 0).segment.expect("Has data to send");
+        assert_eq!(first.range, 0..100);
+        let second = send(&mut connection, 500).segment.expect("Has data to send");
+        assert_eq!(second.range, 100..200);
+
+        // The ACKs are withheld, nothing is offered before the timeout.
+        assert!(send(&mut connection, 999).segment.is_none());
+        assert_eq!(connection.poll(Instant::from_millis(999)).next(), None);
+
+        // Each segment times out on its own.
+        let signals = send(&mut connection, 1000);
+        assert!(signals.timeout);
+        let resent = signals.segment.expect("Retransmits the first segment");
+        assert_eq!(resent.range, 0..100);
+        assert_eq!(resent.repr.seq_number, first.repr.seq_number);
+        assert!(!send(&mut connection, 1000).timeout);
+
+        let signals = send(&mut connection, 1500);
+        assert!(signals.timeout);
+        let resent = signals.segment.expect("Retransmits the second segment");
+        assert_eq!(resent.range, 100..200);
+        assert_eq!(resent.repr.seq_number, second.repr.seq_number);
+
+        // Each expiration doubles the timeout of the segment.
+        let expired = |connection: &mut Connection, millis| connection
+            .poll(Instant::from_millis(millis))
+            .map(|segment| (segment.seq, segment.timeout))
+            .collect::<Vec<_>>();
+        assert_eq!(expired(&mut connection, 2999), []);
+        assert_eq!(expired(&mut connection, 3500), [
+            (first.repr.seq_number, Duration::from_secs(4)),
+            (second.repr.seq_number, Duration::from_secs(4)),
+        ]);
+        assert_eq!(expired(&mut connection, 7499), []);
+
+        // Up to a cap.
+        for _ in 0..10 {
+            let now = connection.retransmit.segments[0].unwrap().deadline;
+            assert_eq!(connection.poll(now).count(), 2);
+        }
+        let segment = connection.retransmit.segments[0].unwrap();
+        assert_eq!(segment.timeout, Duration::from_secs(60));
+        // The timeout for new segments is unaffected.
+        assert_eq!(connection.retransmission_timeout, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn ack_stops_retransmit() {
+        let mut connection = established_connection();
+        let isn = IsnGenerator::from_key(0, 0);
+        let mut no_remap = NoRemap;
+        let mut four = FourTuple {
+            local: Address::v4(192, 0, 10, 1),
+            remote: Address::v4(192, 0, 10, 2),
+            local_port: 80,
+            remote_port: 80,
+        };
+
+        let available = AvailableBytes { fin: false, total: 200 };
+        let mut sent = vec![];
+        for _ in 0..2 {
+            let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+            sent.push(connection.next_send_segment(available, Instant::from_millis(0), entry)
+                .segment
+                .expect("Has data to send"));
+        }
+
+        // Acknowledges the first and half of the second segment.
+        let ack = InPacket {
+            segment: tcp::Repr {
+                src_port: 80,
+                dst_port: 80,
+                seq_number: connection.recv.next,
+                flags: tcp::Flags::default(),
+                ack_number: Some(sent[0].repr.seq_number + 150),
+                window_len: u16::max_value(),
+                window_scale: None,
+                max_seg_size: None,
+                sack_permitted: false,
+                sack_ranges: [None; 3],
+                payload_len: 0,
+            },
+            from: four.remote,
+            time: Instant::from_millis(200),
+        };
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        let signals = connection.arrives(&ack, entry);
+        assert!(signals.answer.is_none());
+
+        // The first segment is no longer outstanding, only the rest of the second is resent.
+        let available = AvailableBytes { fin: false, total: 50 };
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        let resent = connection.next_send_segment(available, Instant::from_millis(1000), entry)
+            .segment
+            .expect("Retransmits the second segment");
+        assert_eq!(resent.repr.seq_number, sent[1].repr.seq_number + 50);
+        assert_eq!(resent.range, 0..50);
+        assert_eq!(connection.poll(Instant::from_secs(100)).count(), 1);
+        // The round trip was measured.
+        assert_eq!(connection.round_trip.smoothed, Some(Duration::from_millis(200)));
+        assert_eq!(connection.round_trip.variation, Duration::from_millis(100));
+        assert_eq!(connection.retransmission_timeout, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn sacked_not_retransmitted() {
+        let mut connection = established_connection();
+        connection.selective_acknowledgements = true;
+        let isn = IsnGenerator::from_key(0, 0);
+        let mut no_remap = NoRemap;
+        let mut four = FourTuple {
+            local: Address::v4(192, 0, 10, 1),
+            remote: Address::v4(192, 0, 10, 2),
+            local_port: 80,
+            remote_port: 80,
+        };
+
+        let available = AvailableBytes { fin: false, total: 300 };
+        let start = connection.send.next;
+        for _ in 0..3 {
+            let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+            connection.next_send_segment(available, Instant::from_millis(0), entry)
+                .segment
+                .expect("Has data to send");
+        }
+        assert_eq!(connection.send.next, start + 300);
+
+        // The first segment was lost but the remote holds the others.
+        let block = ((start + 100).0 as u32, (start + 300).0 as u32);
+        let ack = InPacket {
+            segment: tcp::Repr {
+                src_port: 80,
+                dst_port: 80,
+                seq_number: connection.recv.next,
+                flags: tcp::Flags::default(),
+                ack_number: Some(start),
+                window_len: u16::max_value(),
+                window_scale: None,
+                max_seg_size: None,
+                sack_permitted: false,
+                sack_ranges: [Some(block), None, None],
+                payload_len: 0,
+            },
+            from: four.remote,
+            time: Instant::from_millis(200),
+        };
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        let signals = connection.arrives(&ack, entry);
+        assert!(signals.answer.is_none());
+        assert_eq!(connection.send.sacked, [Some((start + 100, start + 300)), None, None]);
+
+        // Only the missing data is retransmitted.
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        let resent = connection.next_send_segment(available, Instant::from_millis(1000), entry)
+            .segment
+            .expect("Retransmits the segment");
+        assert_eq!(resent.repr.seq_number, start);
+        assert_eq!(resent.range, 0..100);
+
+        // The segments held by the remote are not, although their timers expired as well.
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        let signals = connection.next_send_segment(available, Instant::from_millis(1000), entry);
+        assert!(signals.segment.is_none());
+    }
+
+    /// Write a full segment and then two small chunks, returning the data sent after each.
+    fn small_writes(nodelay: bool) -> Vec<Option<Range<usize>>> {
+        let mut connection = established_connection();
+        connection.set_nodelay(nodelay);
+        let isn = IsnGenerator::from_key(0, 0);
+        let mut no_remap = NoRemap;
+        let mut four = FourTuple {
+            local: Address::v4(192, 0, 10, 1),
+            remote: Address::v4(192, 0, 10, 2),
+            local_port: 80,
+            remote_port: 80,
+        };
+
+        let start = connection.send.next;
+        let available = AvailableBytes { fin: false, total: 100 };
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        connection.next_send_segment(available, Instant::from_millis(0), entry)
+            .segment
+            .expect("Has data to send");
+
+        let mut sent = vec![];
+        for &total in &[110, 120] {
+            let available = AvailableBytes { fin: false, total };
+            let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+            let signals = connection.next_send_segment(available, Instant::from_millis(0), entry);
+            sent.push(signals.segment
+                .map(|segment| segment.range)
+                .filter(|range| !range.is_empty()));
+        }
+
+        let ack = InPacket {
+            segment: tcp::Repr {
+                src_port: 80,
+                dst_port: 80,
+                seq_number: connection.recv.next,
+                flags: tcp::Flags::default(),
+                ack_number: Some(start + 100),
+                window_len: u16::max_value(),
+                window_scale: None,
+                max_seg_size: None,
+                sack_permitted: false,
+                sack_ranges: [None; 3],
+                payload_len: 0,
+            },
+            from: four.remote,
+            time: Instant::from_millis(200),
+        };
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        let signals = connection.arrives(&ack, entry);
+        assert!(signals.answer.is_none());
+
+        // The acknowledged data is no longer available.
+        let available = AvailableBytes { fin: false, total: 20 };
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        let signals = connection.next_send_segment(available, Instant::from_millis(200), entry);
+        sent.push(signals.segment
+            .map(|segment| segment.range)
+            .filter(|range| !range.is_empty()));
+        sent
+    }
+
+    #[test]
+    fn nagle_coalesces() {
+        // Both chunks wait for the ack and are sent as one segment.
+        assert_eq!(small_writes(false), [None, None, Some(0..20)]);
+    }
+
+    #[test]
+    fn nodelay_sends_immediately() {
+        assert_eq!(small_writes(true), [Some(100..110), Some(110..120), None]);
+    }
+
+    #[test]
+    fn keepalive_probes() {
+        let mut connection = established_connection();
+        connection.set_keepalive(Some(Duration::from_secs(10)), Duration::from_secs(1), 2);
+        let isn = IsnGenerator::from_key(0, 0);
+        let mut no_remap = NoRemap;
+        let mut four = FourTuple {
+            local: Address::v4(192, 0, 10, 1),
+            remote: Address::v4(192, 0, 10, 2),
+            local_port: 80,
+            remote_port: 80,
+        };
+
+        let available = AvailableBytes { fin: false, total: 0 };
+        let mut poll = |connection: &mut Connection, millis| {
+            let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+            connection.next_send_segment(available, Instant::from_millis(millis), entry)
+        };
+
+        assert!(poll(&mut connection, 9_999).segment.is_none());
+        let probe = poll(&mut connection, 10_000).segment.expect("Sends a probe");
+        assert_eq!(probe.repr.seq_number, connection.send.next - 1);
+        assert_eq!(probe.repr.ack_number, Some(connection.recv.next));
+        assert_eq!(probe.range, 0..0);
+
+        // Retried once per interval.
+        assert!(poll(&mut connection, 10_999).segment.is_none());
+        assert!(poll(&mut connection, 11_000).segment.is_some());
+
+        // The last probe went unanswered.
+        let signals = poll(&mut connection, 12_000);
+        assert!(signals.segment.is_none());
+        assert!(signals.delete);
+        assert!(signals.reset);
+        assert_eq!(connection.current, State::Closed);
+    }
+
+    #[test]
+    fn keepalive_answered() {
+        let mut connection = established_connection();
+        connection.set_keepalive(Some(Duration::from_secs(10)), Duration::from_secs(1), 1);
+        let isn = IsnGenerator::from_key(0, 0);
+        let mut no_remap = NoRemap;
+        let mut four = FourTuple {
+            local: Address::v4(192, 0, 10, 1),
+            remote: Address::v4(192, 0, 10, 2),
+            local_port: 80,
+            remote_port: 80,
+        };
+
+        let available = AvailableBytes { fin: false, total: 0 };
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        connection.next_send_segment(available, Instant::from_millis(10_000), entry)
+            .segment
+            .expect("Sends a probe");
+
+        let ack = InPacket {
+            segment: tcp::Repr {
+                src_port: 80,
+                dst_port: 80,
+                seq_number: connection.recv.next,
+                flags: tcp::Flags::default(),
+                ack_number: Some(connection.send.next),
+                window_len: u16::max_value(),
+                window_scale: None,
+                max_seg_size: None,
+                sack_permitted: false,
+                sack_ranges: [None; 3],
+                payload_len: 0,
+            },
+            from: four.remote,
+            time: Instant::from_millis(10_500),
+        };
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        let signals = connection.arrives(&ack, entry);
+        assert!(signals.answer.is_none());
+
+        // The idle time starts over.
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        let signals = connection.next_send_segment(available, Instant::from_millis(11_000), entry);
+        assert!(!signals.delete);
+        assert_eq!(connection.current, State::Established);
+        let entry = EntryKey::fake(&mut no_remap, &isn, &mut four);
+        let signals = connection.next_send_segment(available, Instant::from_millis(20_500), entry);
+        assert!(signals.segment.is_some());
+    }
 }
@@ -9,18 +9,24 @@
 //! Selective ACKs: https://tools.ietf.org/html/rfc2018
 //! RST handling specifically: https://www.snellman.net/blog/archive/2016-02-01-tcp-rst/
 //!     OS comparison in particular
+use core::convert::TryFrom;
+
 use crate::layer::ip;
 use crate::managed::{Map, SlotMap, slotmap::Key};
 use crate::wire::{ip::Address, tcp::SeqNumber, tcp::Packet as TcpPacket};
-use crate::wire::PayloadMut;
+use crate::wire::{Payload, PayloadMut};
 use crate::time::{Duration, Expiration, Instant};
 
 use super::connection::{
     Connection,
     Flow,
+    Keepalive,
     Send,
     State,
-    Receive};
+    Receive,
+    RetransmitQueue,
+    RoundTrip,
+    MAX_WINDOW};
 use super::packet::{In, Raw};
 use super::siphash::IsnGenerator;
 
@@ -29,6 +35,12 @@ pub struct Endpoint<'a> {
     ports: Map<'a, FourTuple, Key>,
     states: SlotMap<'a, Slot>,
     isn_generator: IsnGenerator,
+    /// The send buffer size of new connections.
+    send_buffer: usize,
+    /// The receive buffer size of new connections.
+    recv_buffer: usize,
+    /// The fixed window scale offered by new connections.
+    window_scale: Option<u8>,
 }
 
 /// The TCP connection identifier, with four components.
@@ -75,7 +87,7 @@ pub struct Slot {
 ///
 /// Useful for storing in other structs to reference the connection at another point in time. Note
 /// that the index will be invalidated when the connection itself is closed.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct SlotKey {
     key: Key,
 }
@@ -189,6 +201,95 @@ impl Endpoint<'_> {
         self.entry(key)
     }
 
+    /// The send buffer size of new connections.
+    pub fn send_buffer_size(&self) -> usize {
+        self.send_buffer
+    }
+
+    /// Set the send buffer size of new connections.
+    ///
+    /// Limits the number of unacknowledged bytes in flight on a connection, much like `SO_SNDBUF`.
+    /// The actual data is still provided by the [`SendBuf`] of the connection. The size is capped
+    /// to the largest possible window and defaults to that as well.
+    ///
+    /// [`SendBuf`]: trait.SendBuf.html
+    pub fn set_send_buffer_size(&mut self, size: usize) {
+        let max = usize::try_from(u32::max_value()).unwrap_or_else(|_| usize::max_value());
+        self.send_buffer = size.min(max);
+    }
+
+    /// The receive buffer size of new connections.
+    pub fn recv_buffer_size(&self) -> usize {
+        self.recv_buffer
+    }
+
+    /// Set the receive buffer size of new connections.
+    ///
+    /// Limits the window indicated to the remote, much like `SO_RCVBUF`. It also determines the
+    /// window scale offered in the SYN so that the whole buffer can be indicated. The size is
+    /// capped to the largest window expressible with window scaling. The default of `65535`
+    /// bytes does not require scaling.
+    pub fn set_recv_buffer_size(&mut self, size: usize) {
+        let max = usize::try_from(MAX_WINDOW).unwrap_or_else(|_| usize::max_value());
+        self.recv_buffer = size.min(max);
+    }
+
+    /// The fixed window scale offered by new connections, if any.
+    pub fn window_scale(&self) -> Option<u8> {
+        self.window_scale
+    }
+
+    /// Offer a fixed window scale in the SYN of new connections.
+    ///
+    /// By default, the scale is the smallest one with which the whole receive buffer can be
+    /// indicated. A fixed shift is capped to the maximum of `14` and in turn caps the receive
+    /// buffer to the largest window that it can express. The scale only applies if the remote
+    /// offers window scaling as well, otherwise both directions use unscaled windows.
+    pub fn set_window_scale(&mut self, scale: Option<u8>) {
+        self.window_scale = scale.map(|scale| scale.min(14));
+    }
+
+    /// Change the buffer sizes of an existing connection.
+    ///
+    /// Once the SYN has been sent the window scale is fixed and the receive buffer can only grow
+    /// as far as that scale allows. The indicated window follows the next time the receive
+    /// buffer of the connection is consulted. Returns `Err(Illegal)` if the connection does not
+    /// exist.
+    pub fn resize_buffers(&mut self, index: SlotKey, send: usize, recv: usize)
+        -> Result<(), crate::layer::Error>
+    {
+        let slot = self.get_mut(index).ok_or(crate::layer::Error::Illegal)?;
+        slot.connection.set_send_buffer(send);
+        slot.connection.set_recv_buffer(recv);
+        Ok(())
+    }
+
+    /// Choose whether an existing connection sends small segments immediately.
+    ///
+    /// Enabling this disables Nagle's algorithm, much like `TCP_NODELAY`. By default, small writes
+    /// are coalesced while earlier data is unacknowledged. Returns `Err(Illegal)` if the
+    /// connection does not exist.
+    pub fn set_nodelay(&mut self, index: SlotKey, nodelay: bool)
+        -> Result<(), crate::layer::Error>
+    {
+        let slot = self.get_mut(index).ok_or(crate::layer::Error::Illegal)?;
+        slot.connection.set_nodelay(nodelay);
+        Ok(())
+    }
+
+    /// Configure keepalive probes of an existing connection.
+    ///
+    /// After `idle` time without incoming segments the connection probes the remote, repeating
+    /// every `interval`, and is closed after `probes` unanswered probes. Probing is disabled by
+    /// default or when `idle` is `None`. Returns `Err(Illegal)` if the connection does not exist.
+    pub fn set_keepalive(&mut self, index: SlotKey, idle: Option<Duration>, interval: Duration, probes: u8)
+        -> Result<(), crate::layer::Error>
+    {
+        let slot = self.get_mut(index).ok_or(crate::layer::Error::Illegal)?;
+        slot.connection.set_keepalive(idle, interval, probes);
+        Ok(())
+    }
+
     /// Forcibly drop a connection.
     ///
     /// Note that this will *not* send any termination messages and *not* wait for a graceful exit
@@ -269,7 +370,7 @@ impl Endpoint<'_> {
     /// The raw method is near useless, transition the connection to an appropriate state
     /// afterwards.
     fn create_connection(&mut self) -> Connection {
-        Connection {
+        let mut connection = Connection {
             current: State::Closed,
             previous: State::Closed,
             flow_control: Flow {
@@ -285,8 +386,12 @@ impl Endpoint<'_> {
             ack_timeout: Duration::from_millis(500),
             retransmission_timer: Instant::from_millis(0),
             retransmission_timeout: Duration::from_millis(3000),
+            round_trip: RoundTrip::default(),
+            retransmit: RetransmitQueue::default(),
+            keepalive: Keepalive::default(),
             restart_timeout: Duration::from_millis(30000),
             selective_acknowledgements: false,
+            nodelay: false,
             duplicate_ack: 0,
             send: Send {
                 unacked: SeqNumber::default(),
@@ -295,6 +400,8 @@ impl Endpoint<'_> {
                 unsent: 0,
                 window: 0,
                 window_scale: 0,
+                buffer: 0,
+                sacked: [None; 3],
                 initial_seq: SeqNumber::default(),
             },
             recv: Receive {
@@ -303,9 +410,18 @@ impl Endpoint<'_> {
                 last_time: Instant::from_millis(0),
                 window: 0,
                 window_scale: 0,
+                buffer: 0,
+                sack: [None; 3],
                 initial_seq: SeqNumber::default(),
             },
+        };
+
+        connection.set_send_buffer(self.send_buffer);
+        connection.set_recv_buffer(self.recv_buffer);
+        if let Some(scale) = self.window_scale {
+            connection.set_window_scale(scale);
         }
+        connection
     }
 
     fn initial_seq_num(&mut self, id: FourTuple, time: Instant) -> SeqNumber {
@@ -322,6 +438,23 @@ impl Slot {
         self.addr
     }
 
+    /// The send buffer size of the connection.
+    pub fn send_buffer_size(&self) -> usize {
+        // AS: at most `u32::max_value()` which was a valid `usize` when set.
+        self.connection.send.buffer as usize
+    }
+
+    /// The receive buffer size of the connection.
+    pub fn recv_buffer_size(&self) -> usize {
+        // AS: at most `MAX_WINDOW` which was a valid `usize` when set.
+        self.connection.recv.buffer as usize
+    }
+
+    /// Whether the connection sends small segments without delay.
+    pub fn nodelay(&self) -> bool {
+        self.connection.nodelay
+    }
+
     /// Returns a reference to the connection contained in the slot.
     pub(crate) fn connection(&self) -> &Connection {
         &self.connection
@@ -341,6 +474,9 @@ impl<'ep> Endpoint<'ep> {
             ports,
             states,
             isn_generator,
+            send_buffer: usize::try_from(u32::max_value()).unwrap_or_else(|_| usize::max_value()),
+            recv_buffer: u16::max_value().into(),
+            window_scale: None,
         }
     }
 
@@ -501,10 +637,12 @@ where
         let capabilities = control.info().capabilities();
         let checksum = capabilities.tcp().rx_checksum(repr);
 
-        let packet = match TcpPacket::new_checked(packet, checksum) {
-            Ok(packet) => packet,
-            // TODO: error logging.
-            Err(_) => return (),
+        let packet = match TcpPacket::new_checked(&packet, checksum) {
+            Ok(tcp) => {
+                let repr = tcp.repr();
+                TcpPacket::new_unchecked(packet, repr)
+            },
+            Err(err) => return control.report_drop(err.into(), packet.payload().as_slice()),
         };
 
         let arrived = match In::from_arriving(self.endpoint.inner, control.borrow_mut(), packet) {
@@ -429,4 +429,16 @@ impl<B: BorrowMut<[u8]>> RecvBuf for RecvInto<B> {
     fn window(&self) -> usize {
         self.buffer.borrow()[self.mark..].len()
     }
+
+    /// Report the lowest blocks in the reassembly buffer.
+    fn sack(&self) -> [Option<(SeqNumber, SeqNumber)>; 3] {
+        let mut blocks = [None; 3];
+        if let Some(complete) = self.complete {
+            // AS: the ranges are bounded by the buffer length which is a valid `usize`.
+            for (block, (begin, end)) in blocks.iter_mut().zip(self.asm.iter()) {
+                *block = Some((complete + begin as usize, complete + end as usize));
+            }
+        }
+        blocks
+    }
 }
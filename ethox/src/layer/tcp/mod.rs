@@ -61,9 +61,11 @@
 //! remaining a question of policy within the tcp layer. You create a listening socket and with it
 //! reserve one connection state for a port but it will accept only a single (successful)
 //! connection attempt. The handler of the then produced [`Open`] packet needs to create more
-//! reserved connection states.
+//! reserved connection states. A [`Listener`] does this for a port and hands each established
+//! connection to an accept handler.
 //!
 //! [`Open`]: struct.Open.html
+//! [`Listener`]: struct.Listener.html
 //!
 //! ## Deviations
 //!
@@ -105,6 +107,8 @@ mod packet;
 mod socket;
 
 mod siphash;
+#[cfg(test)]
+mod tests;
 
 pub use connection::{
     AvailableBytes,
@@ -127,7 +131,9 @@ pub use packet::{
     UserSignals};
 
 pub use socket::{
-    Client};
+    Accept,
+    Client,
+    Listener};
 
 // publically exposed for initialization.
 pub use siphash::IsnGenerator;
@@ -3,10 +3,12 @@
 //! The interface differs from other layers in that the `In` packet has many different variants it
 //! represents, depending on the state of the underlying connection.
 use crate::layer;
+use crate::layer::trace::Event;
 use crate::wire::{Payload, PayloadMut};
 use crate::wire::{ip, tcp};
 
 use super::connection::{AvailableBytes, Endpoint, InPacket, Operator, OutSignals, ReceivedSegment, Segment, Signals};
+use super::connection::reset_closed;
 use super::endpoint::{FourTuple, SlotKey};
 
 /// An incoming tcp packet.
@@ -76,6 +78,15 @@ pub trait RecvBuf {
     /// Shrinking the window size without having accepted new data is allowed but strongly
     /// discouraged.
     fn window(&self) -> usize;
+
+    /// Get the blocks of data received beyond missing data.
+    ///
+    /// These are reported to the remote in selective acknowledgements, RFC2018, as the sequence
+    /// numbers of the first and past-the-end byte of each block. The default reports none which is
+    /// appropriate for buffers that discard out-of-order data.
+    fn sack(&self) -> [Option<(tcp::SeqNumber, tcp::SeqNumber)>; 3] {
+        [None; 3]
+    }
 }
 
 /// Informational signals to the user.
@@ -98,6 +109,15 @@ pub struct UserSignals {
     /// There is new data to be read.
     pub data: bool,
 
+    /// The handshake completed and the connection was just established.
+    pub established: bool,
+
+    /// A listening connection received a connection attempt and started its handshake.
+    ///
+    /// The connection is bound to the remote from now on. Its local address and port are free
+    /// again to listen for the next attempt.
+    pub handshake: bool,
+
     /// A listening socket returned to its listen state.
     ///
     /// WIP: this is not implemented yet and always `false`.
@@ -135,7 +155,6 @@ pub struct Sending<'a> {
 ///
 /// Same as `Sending`, the packet has already been prepared and queue.
 pub struct Closing<'a> {
-    #[allow(dead_code)] // This attribute exists for parity with other message structs.
     endpoint: &'a mut dyn Endpoint,
     previous: SlotKey,
     signals: UserSignals,
@@ -313,6 +332,27 @@ impl<'a, P: PayloadMut> In<'a, P> {
         }
     }
 
+    /// Open a port for listening while handling a received packet.
+    ///
+    /// Works like [`Raw::listen`] but does not have to wait for a buffer to send. A listener can
+    /// use this to be ready for the next connection attempt before the current packet is handled.
+    ///
+    /// [`Raw::listen`]: struct.Raw.html#method.listen
+    pub fn listen(&mut self, addr: ip::Address, port: u16)
+        -> Result<SlotKey, crate::layer::Error>
+    {
+        let endpoint: &mut dyn Endpoint = match self {
+            In::Sending(sending) => sending.operator.endpoint,
+            In::Open(open) => open.operator.endpoint,
+            In::Closing(closing) => closing.endpoint,
+            In::Closed(closed) => closed.endpoint,
+            In::Stray(stray) => stray.endpoint,
+        };
+
+        endpoint.listen(addr, port)
+            .ok_or(crate::layer::Error::Exhausted)
+    }
+
     /// Get a descriptor for state changes that would usually send a signal to the user.
     pub fn user_signals(&self) -> UserSignals {
         match self {
@@ -338,8 +378,13 @@ impl<'a, P: PayloadMut> Open<'a, P> {
 
         if let OpenPacket::In { tcp, segment } = &self.packet {
             with.receive(tcp.payload_slice(), *segment);
-            let progress = segment.acked_until(with.ack());
-            connection.set_recv_ack(progress);
+            let ack = with.ack();
+            connection.set_recv_sack(with.sack());
+            if ack < segment.data_begin() {
+                connection.set_recv_gap(*segment);
+            } else {
+                connection.set_recv_ack(segment.acked_until(ack));
+            }
         }
     }
 
@@ -350,7 +395,7 @@ impl<'a, P: PayloadMut> Open<'a, P> {
     ///
     /// Any data that is currently held as an incoming packet will be lost, even if this method fails.
     pub fn write(self, with: &mut impl SendBuf) -> Result<Result<Sending<'a>, Closing<'a>>, crate::layer::Error> {
-        let Open { ip, mut operator, signals: mut user, packet, } = self;
+        let Open { mut ip, mut operator, signals: mut user, packet, } = self;
         let payload: &'a mut P = match packet {
             OpenPacket::In { tcp, .. } | OpenPacket::Control { tcp }
                 => tcp.into_inner().into_inner().into_inner(),
@@ -361,8 +406,12 @@ impl<'a, P: PayloadMut> Open<'a, P> {
         with.ack(tcp_seq);
         let available = with.available();
         let time = ip.info().timestamp();
+        let capabilities = ip.info().capabilities();
 
         let signals = operator.next_send_segment(available, time);
+        if signals.timeout {
+            ip.eth.trace(Event::TimerFired);
+        }
         user.update(&signals);
 
         if let Some(Segment { repr, range }) = signals.segment {
@@ -376,7 +425,7 @@ impl<'a, P: PayloadMut> Open<'a, P> {
             let ip_repr = out_ip.repr();
             let mut tcp = tcp::Packet::new_unchecked(out_ip.payload_mut_slice(), repr);
             with.fill(tcp.payload_mut_slice(), tcp_seq + range.start);
-            tcp.fill_checksum(ip_repr.src_addr(), ip_repr.dst_addr());
+            tcp.fill_checksum_with(capabilities.tcp().tx_checksum(ip_repr));
 
             out_ip.send()?;
         }
@@ -433,6 +482,21 @@ impl<'a, P: PayloadMut> Raw<'a, P> {
         })
     }
 
+    /// Open a port for listening.
+    ///
+    /// The connection state accepts a single connection attempt from any remote to the exact local
+    /// address and port. Its SYN is answered automatically and the packet completing the
+    /// handshake arrives as an `Open` packet with [`UserSignals::established`] set. The buffer
+    /// itself is not used and remains available.
+    ///
+    /// [`UserSignals::established`]: struct.UserSignals.html#structfield.established
+    pub fn listen(&mut self, addr: ip::Address, port: u16)
+        -> Result<SlotKey, crate::layer::Error>
+    {
+        self.endpoint.listen(addr, port)
+            .ok_or(crate::layer::Error::Exhausted)
+    }
+
     /// Attach to an existing connection.
     ///
     /// If successful, this return an `Open` packet with which you can send data on the connection.
@@ -508,6 +572,20 @@ impl<'a, P: PayloadMut> Closed<'a, P> {
 }
 
 impl<'a, P: PayloadMut> Stray<'a, P> {
+    /// Reject the segment with a reset.
+    ///
+    /// This is the answer that RFC793 mandates for a segment to a closed port, in particular for a
+    /// SYN to a port without a listening connection. Segments with RST set are never answered and
+    /// are only dropped.
+    pub fn reset(self) -> Result<(), crate::layer::Error> {
+        let answer = match reset_closed(&self.tcp.repr()) {
+            Some(answer) => answer,
+            None => return Ok(()),
+        };
+
+        control_answer(self.tcp, answer, self.ip)
+    }
+
     /// Unwrap the packet buffer for reuse.
     ///
     /// There was no connection that the packet belonged to and thus no response required. This
@@ -530,13 +608,15 @@ impl UserSignals {
         UserSignals {
             reset: signals.reset,
             data: signals.receive.is_some(),
+            established: signals.established,
+            handshake: signals.syn_received,
             half_closed: false,
             relisten: false,
         }
     }
 
-    fn update(&mut self, _signals: &OutSignals) {
-        // TODO: anything to set?
+    fn update(&mut self, signals: &OutSignals) {
+        self.reset |= signals.reset;
     }
 }
 
@@ -547,6 +627,7 @@ fn control_answer<'a, P: PayloadMut>(
 ) -> Result<(), crate::layer::Error> {
     assert_eq!(answer.payload_len, 0, "Control answer can not handle data");
 
+    let capabilities = ip.info().capabilities();
     let raw_buffer = tcp.into_inner();
     let ip_repr = raw_buffer.repr();
     let ip_payload_len = answer.header_len();
@@ -562,13 +643,15 @@ fn control_answer<'a, P: PayloadMut>(
         dst_addr: ip_repr.src_addr(),
         protocol: ip::Protocol::Tcp,
         payload: ip_payload_len,
+        hop_limit: None,
+        traffic_class: 0,
     })?.into_incoming();
 
     // FIXME: make initialization nicer.
     let raw_packet = tcp::Packet::new_unchecked(&mut packet, answer.clone());
     answer.emit(raw_packet);
     let mut raw_packet = tcp::Packet::new_unchecked(&mut packet, answer.clone());
-    raw_packet.fill_checksum(ip_repr.src_addr(), ip_repr.dst_addr());
+    raw_packet.fill_checksum_with(capabilities.tcp().tx_checksum(ip_repr));
 
     layer::ip::OutPacket::new_unchecked(control, packet)
         .send()
@@ -586,6 +669,8 @@ fn prepare<'a, P: PayloadMut>(
         source: layer::ip::Source::Exact(tuple.local),
         protocol: ip::Protocol::Tcp,
         payload: repr.header_len() + usize::from(repr.payload_len),
+        hop_limit: None,
+        traffic_class: 0,
     })?;
 
     let layer::ip::InPacket { control, mut packet } = init_ip.into_incoming();
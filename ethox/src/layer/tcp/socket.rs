@@ -3,7 +3,9 @@
 //! An actual socket layer requires allocation all buffers and depends on a few details in the
 //! layer below and these do not (that was not the end goal but some may be added in the future),
 //! but it tries to give a slightly more familiar interface.
-use super::{InPacket, RawPacket, Recv, RecvBuf, Send, SendBuf, SlotKey};
+use super::{io, InPacket, Open, RawPacket, Recv, RecvBuf, Send, SendBuf, SlotKey};
+use crate::layer::{FnHandler, StatefulHandler};
+use crate::managed::{List, Slice};
 use crate::wire::{ip::Address, PayloadMut};
 
 /// A tcp handler for a client (actively opened connection).
@@ -29,6 +31,47 @@ pub struct Client<R, S> {
     send: S,
 }
 
+/// A tcp handler for a server port (passively opened connections).
+///
+/// Keeps one connection state listening on a local address and port. The SYN of a remote is
+/// answered automatically with a SYN-ACK whose initial sequence number is derived from the
+/// connection tuple, as the endpoint's [`IsnGenerator`] does for all connections. Once the
+/// handshake completes, the `Open` packet of the established connection is handed to the accept
+/// handler. From there on, the connection is independent of the listener and should be served
+/// through its [`SlotKey`], for example by attaching to it during a later send.
+///
+/// As soon as a connection attempt arrives, another connection state is reserved to listen for
+/// the next one. The number of connections that may be in their handshake at the same time is
+/// the backlog of the listener, see [`with_backlog`]. Only when the backlog is full are further
+/// attempts rejected, until a handshake completes or fails. If the endpoint has no free
+/// connection state, the port is listened on again during the next send.
+///
+/// Segments that do not belong to any connection are rejected with a reset, like the RFC
+/// mandates for closed ports.
+///
+/// [`IsnGenerator`]: struct.IsnGenerator.html
+/// [`SlotKey`]: struct.SlotKey.html
+/// [`with_backlog`]: #method.with_backlog
+pub struct Listener<'a, A> {
+    local: Address,
+    port: u16,
+    /// The connection state waiting for the next connection attempt.
+    listening: Option<SlotKey>,
+    /// The connections in their handshake, at most as many as the backlog.
+    handshakes: List<'a, SlotKey>,
+    /// The handshake whose SYN-ACK is retransmitted during the next send.
+    next: usize,
+    accept: A,
+}
+
+/// A handler for newly established connections.
+pub trait Accept<P: PayloadMut> {
+    /// Take over a connection whose handshake just completed.
+    ///
+    /// The packet is the one that acknowledged our SYN and may already contain data.
+    fn accept(&mut self, connection: Open<P>);
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 enum ClientState {
     Uninstantiated {
@@ -186,3 +229,165 @@ where
         let _ = open.write(&mut self.send);
     }
 }
+
+impl<A> Listener<'_, A> {
+    /// Create a listener for a port on an exact local address.
+    ///
+    /// The port is not reserved before the first send. The listener has a backlog of a single
+    /// connection attempt.
+    pub fn new(local: Address, port: u16, accept: A) -> Self {
+        Listener::with_backlog(local, port, accept, Slice::One(SlotKey::default()))
+    }
+}
+
+impl<'a, A> Listener<'a, A> {
+    /// Create a listener that keeps several connection attempts in their handshake.
+    ///
+    /// The length of the storage is the backlog, it must not be empty.
+    pub fn with_backlog<S>(local: Address, port: u16, accept: A, storage: S) -> Self
+        where S: Into<Slice<'a, SlotKey>>,
+    {
+        Listener {
+            local,
+            port,
+            listening: None,
+            handshakes: List::new(storage.into()),
+            next: 0,
+            accept,
+        }
+    }
+
+    /// Get a reference to the accept handler.
+    pub fn accept(&self) -> &A {
+        &self.accept
+    }
+
+    /// Get a mutable reference to the accept handler.
+    pub fn accept_mut(&mut self) -> &mut A {
+        &mut self.accept
+    }
+
+    /// Get the key of the connection state that is listening.
+    ///
+    /// Returns `None` when the port is not reserved currently.
+    pub fn connection_key(&self) -> Option<SlotKey> {
+        self.listening
+    }
+
+    /// Get the keys of the connections that are in their handshake.
+    pub fn handshakes(&self) -> &[SlotKey] {
+        self.handshakes.as_slice()
+    }
+
+    /// The maximum number of connections in their handshake.
+    pub fn backlog(&self) -> usize {
+        self.handshakes.capacity()
+    }
+
+    /// Listen on the port again if nothing is listening and the backlog has room.
+    fn relisten<P: PayloadMut>(&mut self, packet: &mut InPacket<P>) {
+        if self.listening.is_none() && self.handshakes.len() < self.backlog() {
+            self.listening = packet.listen(self.local, self.port).ok();
+        }
+    }
+}
+
+impl<A, P> Recv<P> for &'_ mut Listener<'_, A>
+where
+    A: Accept<P>,
+    P: PayloadMut,
+{
+    fn receive(&mut self, packet: InPacket<P>) {
+        let mut packet = match packet {
+            InPacket::Stray(stray) => {
+                // TODO: error handling.
+                let _ = stray.reset();
+                return;
+            },
+            other => other,
+        };
+
+        // Not a packet for one of our connections. Ignore.
+        let key = match packet.key() {
+            Some(key) => key,
+            None => return,
+        };
+        let signals = packet.user_signals();
+
+        if self.listening == Some(key) {
+            if signals.handshake {
+                // We only listen while the backlog has room.
+                self.listening = None;
+                if let Some(slot) = self.handshakes.push() {
+                    *slot = key;
+                }
+                self.relisten(&mut packet);
+            }
+            return;
+        }
+
+        let position = match self.handshakes.iter().position(|&pending| pending == key) {
+            Some(position) => position,
+            None => return,
+        };
+
+        let established = match packet {
+            InPacket::Open(_) if signals.established => true,
+            // The handshake failed.
+            InPacket::Closed(_) | InPacket::Closing(_) => false,
+            _ => return,
+        };
+
+        self.handshakes.remove_at(position);
+        self.relisten(&mut packet);
+        if let (true, InPacket::Open(open)) = (established, packet) {
+            self.accept.accept(open);
+        }
+    }
+}
+
+impl<A, P> Send<P> for &'_ mut Listener<'_, A>
+where
+    P: PayloadMut,
+{
+    fn send(&mut self, mut packet: RawPacket<P>) {
+        if self.listening.is_none() && self.handshakes.len() < self.backlog() {
+            // TODO: error handling.
+            self.listening = packet.listen(self.local, self.port).ok();
+        }
+
+        if self.handshakes.is_empty() {
+            return;
+        }
+
+        // Retransmits the SYN-ACK of one handshake after the other.
+        let position = self.next % self.handshakes.len();
+        self.next = position + 1;
+        let key = self.handshakes[position];
+        match packet.attach(key) {
+            // TODO: error handling.
+            Ok(open) => { let _ = open.write(&mut io::Empty::default()); },
+            Err(_) => { self.handshakes.remove_at(position); },
+        }
+    }
+}
+
+impl<P, F> Accept<P> for FnHandler<F>
+where
+    P: PayloadMut,
+    F: FnMut(Open<P>),
+{
+    fn accept(&mut self, connection: Open<P>) {
+        self.0(connection)
+    }
+}
+
+impl<P, S, F> Accept<P> for StatefulHandler<S, F>
+where
+    P: PayloadMut,
+    F: FnMut(&mut S, Open<P>),
+{
+    fn accept(&mut self, connection: Open<P>) {
+        (self.handler)(&mut self.state, connection)
+    }
+}
@@ -5,3 +5,521 @@
 //! top of tcp and test against other implementations. Due to the abundance of options and allowed
 //! implementation specific behaviour it has proven quite hard to conduct this as a black-box test.
 //! Hence, see also the example binary for tcp echo.
+use crate::managed::{List, Map, Slice, SlotMap};
+use crate::nic::{external::External, Capabilities, Device};
+use crate::layer::{arp, eth, ip, tcp, DropReason, FnHandler, StatefulHandler};
+use crate::time::Instant;
+use crate::wire::{ethernet, ip::v4, ip::Cidr, ip::Protocol, PayloadMut};
+use crate::wire::tcp as tcp_wire;
+
+const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+const IP_ADDR_HOST: v4::Address = v4::Address::new(127, 0, 0, 1);
+const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+const IP_ADDR_OTHER: v4::Address = v4::Address::new(127, 0, 0, 2);
+
+/// A receiver that should never be called.
+struct NoDelivery;
+
+impl<P: PayloadMut> tcp::Recv<P> for NoDelivery {
+    fn receive(&mut self, _: tcp::InPacket<P>) {
+        panic!("Segment was unexpectedly delivered");
+    }
+}
+
+/// The link and ip endpoints of a host which already knows the link address of `neighbor`.
+fn link<'a>(
+    mac_addr: ethernet::Address,
+    ip_addr: v4::Address,
+    (neighbor_ip, neighbor_mac): (v4::Address, ethernet::Address),
+) -> (eth::Endpoint<'a>, ip::Endpoint<'a>) {
+    let mut neighbors = arp::NeighborCache::new(vec![arp::Neighbor::default(); 1]);
+    neighbors.fill(neighbor_ip.into(), neighbor_mac, None).unwrap();
+    let ip = ip::Endpoint::new(Cidr::new(ip_addr.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        neighbors);
+    (eth::Endpoint::new(mac_addr), ip)
+}
+
+/// The endpoints of the host, with room for `connections` in its tcp endpoint.
+fn host<'a>(connections: usize) -> (eth::Endpoint<'a>, ip::Endpoint<'a>, tcp::Endpoint<'a>) {
+    let (eth, ip) = link(MAC_ADDR_HOST, IP_ADDR_HOST, (IP_ADDR_OTHER, MAC_ADDR_OTHER));
+    let tcp = tcp::Endpoint::new(
+        Map::Pairs(List::new(Slice::Many(vec![Default::default(); connections]))),
+        SlotMap::new(
+            Slice::Many(vec![Default::default(); connections]),
+            Slice::Many(vec![Default::default(); connections])),
+        tcp::IsnGenerator::from_secret_key_bytes([0; 16]),
+    );
+    (eth, ip, tcp)
+}
+
+#[test]
+fn truncated_segment() {
+    fn send_truncated<P: PayloadMut>(packet: ip::RawPacket<P>) {
+        let init = ip::Init {
+            source: ip::Source::Exact(IP_ADDR_HOST.into()),
+            dst_addr: IP_ADDR_OTHER.into(),
+            protocol: Protocol::Tcp,
+            // Shorter than a tcp header.
+            payload: 4,
+            hop_limit: None,
+            traffic_class: 0,
+        };
+        packet.prepare(init)
+            .expect("Can initialize to the other host")
+            .send()
+            .expect("Can send the packet");
+    }
+
+    let dropped = core::cell::Cell::new(None);
+    let mut hook = |reason, bytes: &[u8]| dropped.set(Some((reason, bytes.len())));
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let (mut eth, mut ip, mut tcp) = host(1);
+    eth.set_drop_hook(&mut hook);
+
+    let sent = nic.tx(1, eth.send(ip.send_with(send_truncated)));
+    assert_eq!(sent, Ok(1));
+
+    {
+        // Retarget the packet to self.
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(MAC_ADDR_HOST);
+        eth.set_src_addr(MAC_ADDR_OTHER);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(IP_ADDR_HOST);
+        ip.set_src_addr(IP_ADDR_OTHER);
+        ip.fill_checksum();
+    }
+
+    nic.receive_all();
+
+    let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(NoDelivery))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(dropped.get(), Some((DropReason::Malformed, 4)));
+}
+
+/// Open a connection and return the SYN that was sent.
+fn opening_syn(recv_buffer: usize) -> tcp_wire::Repr {
+    let buffer = opening_frame(recv_buffer, Capabilities::no_support());
+    let eth = ethernet::frame::new_checked(&buffer[..]).unwrap();
+    let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+    let tcp = tcp_wire::Packet::new_checked(ip.payload_slice(), tcp_wire::Checksum::Ignored)
+        .unwrap();
+    tcp.repr()
+}
+
+/// Send the SYN of a new connection, on a nic with some capabilities.
+fn opening_frame(recv_buffer: usize, capabilities: Capabilities) -> Vec<u8> {
+    struct Open;
+
+    impl<P: PayloadMut> tcp::Send<P> for Open {
+        fn send(&mut self, raw: tcp::RawPacket<P>) {
+            let sent = raw.open(IP_ADDR_OTHER.into(), 80)
+                .expect("Can open a connection")
+                .write(&mut tcp::io::Empty::default())
+                .expect("Can send the SYN");
+            assert!(sent.is_ok(), "Connection is not closed");
+        }
+    }
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    nic.set_capabilities(capabilities);
+    let (mut eth, mut ip, mut tcp) = host(1);
+    tcp.set_recv_buffer_size(recv_buffer);
+
+    let sent = nic.tx(1, eth.send(ip.send(tcp.send(Open))));
+    assert_eq!(sent, Ok(1));
+    nic.get(0).unwrap().clone()
+}
+
+#[test]
+fn checksum_offload() {
+    for offloaded in [false, true] {
+        let capabilities = if offloaded {
+            Capabilities::offloaded()
+        } else {
+            Capabilities::no_support()
+        };
+        let buffer = opening_frame(8192, capabilities);
+        let eth = ethernet::frame::new_checked(&buffer[..]).unwrap();
+        let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+        assert_eq!(ip.verify_checksum(), !offloaded);
+        assert_eq!(ip.checksum() == 0, offloaded);
+        let tcp = tcp_wire::Packet::new_checked(ip.payload_slice(), tcp_wire::Checksum::Ignored)
+            .unwrap();
+        let valid = tcp.verify_checksum(IP_ADDR_HOST.into(), IP_ADDR_OTHER.into());
+        assert_eq!(valid, !offloaded);
+        assert_eq!(tcp.checksum() == 0, offloaded);
+    }
+}
+
+#[test]
+fn recv_buffer_window() {
+    let small = opening_syn(8192);
+    assert!(small.flags.syn());
+    assert_eq!(small.window_len, 8192);
+    assert_eq!(small.window_scale, Some(0));
+
+    // The window of the SYN itself is never scaled, the buffer shows in the scale.
+    let large = opening_syn(1 << 20);
+    assert_eq!(large.window_len, u16::max_value());
+    assert_eq!(large.window_scale, Some(5));
+
+    // Capped to the largest expressible window.
+    let huge = opening_syn(usize::max_value());
+    assert_eq!(huge.window_scale, Some(14));
+}
+
+/// Prepare a frame with a segment from the other host.
+fn remote_segment(repr: tcp_wire::Repr) -> Vec<u8> {
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let (mut eth, mut ip) = link(MAC_ADDR_OTHER, IP_ADDR_OTHER, (IP_ADDR_HOST, MAC_ADDR_HOST));
+
+    let sent = nic.tx(1, eth.send(ip.send_with(|packet: ip::RawPacket<_>| {
+        let init = ip::Init {
+            source: ip::Source::Exact(IP_ADDR_OTHER.into()),
+            dst_addr: IP_ADDR_HOST.into(),
+            protocol: Protocol::Tcp,
+            payload: repr.buffer_len(),
+            hop_limit: None,
+            traffic_class: 0,
+        };
+        let mut prepared = packet.prepare(init).unwrap();
+        let payload = prepared.payload_mut_slice();
+        repr.emit(tcp_wire::Packet::new_unchecked(&mut *payload, repr));
+        tcp_wire::Packet::new_unchecked(payload, repr)
+            .fill_checksum(IP_ADDR_OTHER.into(), IP_ADDR_HOST.into());
+        prepared.send().unwrap();
+    })));
+    assert_eq!(sent, Ok(1));
+
+    nic.get_mut(0).unwrap().clone()
+}
+
+/// Parse the segment in a frame sent by the host.
+fn host_segment(frame: &[u8]) -> tcp_wire::Repr {
+    let eth = ethernet::frame::new_checked(frame).unwrap();
+    assert_eq!(eth.dst_addr(), MAC_ADDR_OTHER);
+    let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+    assert_eq!(ip.dst_addr(), IP_ADDR_OTHER);
+    let checksum = tcp_wire::Checksum::Manual {
+        src_addr: IP_ADDR_HOST.into(),
+        dst_addr: IP_ADDR_OTHER.into(),
+    };
+    tcp_wire::Packet::new_checked(ip.payload_slice(), checksum)
+        .unwrap()
+        .repr()
+}
+
+#[test]
+fn passive_open() {
+    const REMOTE_PORT: u16 = 4000;
+    const REMOTE_ISN: tcp_wire::SeqNumber = tcp_wire::SeqNumber(1000);
+
+    let (mut eth, mut ip, mut tcp) = host(1);
+
+    let accepted = core::cell::Cell::new(None);
+    let mut listener = tcp::Listener::new(IP_ADDR_HOST.into(), 80, FnHandler(|open: tcp::Open<_>| {
+        accepted.set(Some(open.key()));
+    }));
+
+    // The port is reserved during a send, without using the buffer.
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let _ = nic.tx(1, eth.send(ip.send(tcp.send(&mut listener))));
+    let key = listener.connection_key().expect("Listening on the port");
+
+    let syn = tcp_wire::Repr {
+        src_port: REMOTE_PORT,
+        dst_port: 80,
+        seq_number: REMOTE_ISN,
+        flags: tcp_wire::Flags::SYN,
+        ack_number: None,
+        window_len: 1024,
+        window_scale: None,
+        max_seg_size: None,
+        sack_permitted: false,
+        sack_ranges: [None; 3],
+        payload_len: 0,
+    };
+
+    let mut nic = External::new_recv(vec![remote_segment(syn)]);
+    let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut listener))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(nic.queued().len(), 1);
+
+    // Answered with a SYN-ACK, its sequence number chosen for the connection tuple.
+    let syn_ack = host_segment(&nic.queued()[0]);
+    let isn = tcp::IsnGenerator::from_secret_key_bytes([0; 16]).get_isn(tcp::FourTuple {
+        local: IP_ADDR_HOST.into(),
+        remote: IP_ADDR_OTHER.into(),
+        local_port: 80,
+        remote_port: REMOTE_PORT,
+    }, Instant::from_millis(0));
+    assert!(syn_ack.flags.syn());
+    assert!(!syn_ack.flags.rst());
+    assert_eq!(syn_ack.src_port, 80);
+    assert_eq!(syn_ack.dst_port, REMOTE_PORT);
+    assert_eq!(syn_ack.seq_number, isn);
+    assert_eq!(syn_ack.ack_number, Some(REMOTE_ISN + 1));
+    assert_eq!(accepted.get(), None);
+
+    // The ack of our SYN completes the handshake.
+    let ack = tcp_wire::Repr {
+        flags: tcp_wire::Flags::default(),
+        seq_number: REMOTE_ISN + 1,
+        ack_number: Some(isn + 1),
+        ..syn
+    };
+    let mut nic = External::new_recv(vec![remote_segment(ack)]);
+    let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut listener))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(accepted.get(), Some(key));
+    assert_eq!(listener.connection_key(), None);
+
+    // A SYN to another port is rejected.
+    let stray = tcp_wire::Repr {
+        dst_port: 81,
+        ..syn
+    };
+    let mut nic = External::new_recv(vec![remote_segment(stray)]);
+    let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut listener))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(nic.queued().len(), 1);
+    let rst = host_segment(&nic.queued()[0]);
+    assert!(rst.flags.rst());
+    assert!(!rst.flags.syn());
+    assert_eq!(rst.src_port, 81);
+    assert_eq!(rst.ack_number, Some(REMOTE_ISN + 1));
+}
+
+#[test]
+fn listen_backlog() {
+    const REMOTE_ISN: tcp_wire::SeqNumber = tcp_wire::SeqNumber(1000);
+
+    let (mut eth, mut ip, mut tcp) = host(4);
+
+    let accepted = StatefulHandler::new(vec![], |keys: &mut Vec<_>, open: tcp::Open<_>| {
+        keys.push(open.key())
+    });
+    let mut listener = tcp::Listener::with_backlog(
+        IP_ADDR_HOST.into(),
+        80,
+        accepted,
+        vec![tcp::SlotKey::default(); 2]);
+    assert_eq!(listener.backlog(), 2);
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let _ = nic.tx(1, eth.send(ip.send(tcp.send(&mut listener))));
+    assert!(listener.connection_key().is_some());
+
+    let syn = |src_port| tcp_wire::Repr {
+        src_port,
+        dst_port: 80,
+        seq_number: REMOTE_ISN,
+        flags: tcp_wire::Flags::SYN,
+        ack_number: None,
+        window_len: 1024,
+        window_scale: None,
+        max_seg_size: None,
+        sack_permitted: false,
+        sack_ranges: [None; 3],
+        payload_len: 0,
+    };
+
+    // Two clients connect at the same time, a third exceeds the backlog.
+    let mut nic = External::new_recv(vec![
+        remote_segment(syn(4000)),
+        remote_segment(syn(4001)),
+        remote_segment(syn(4002)),
+    ]);
+    for _ in 0..3 {
+        let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut listener))));
+        assert_eq!(recv, Ok(1));
+    }
+    assert_eq!(listener.handshakes().len(), 2);
+    assert_eq!(listener.connection_key(), None);
+
+    let mut answers: Vec<_> = nic.queued().iter().map(|frame| host_segment(frame)).collect();
+    answers.sort_by_key(|answer| answer.dst_port);
+    assert_eq!(answers.len(), 3);
+    assert!(answers[0].flags.syn() && !answers[0].flags.rst());
+    assert!(answers[1].flags.syn() && !answers[1].flags.rst());
+    assert!(answers[2].flags.rst());
+
+    // Both handshakes complete, and the port is listened on again.
+    let acks: Vec<_> = answers[..2].iter()
+        .map(|syn_ack| remote_segment(tcp_wire::Repr {
+            flags: tcp_wire::Flags::default(),
+            seq_number: REMOTE_ISN + 1,
+            ack_number: Some(syn_ack.seq_number + 1),
+            ..syn(syn_ack.dst_port)
+        }))
+        .collect();
+    let mut nic = External::new_recv(acks);
+    for _ in 0..2 {
+        let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut listener))));
+        assert_eq!(recv, Ok(1));
+    }
+    assert!(listener.handshakes().is_empty());
+    assert!(listener.connection_key().is_some());
+    assert_eq!(listener.accept().state().len(), 2);
+}
+
+/// Accept a connection from the other host, returning the SYN-ACK and the established state.
+fn accept_scaled(window_scale: Option<u8>) -> (tcp_wire::Repr, tcp::Slot) {
+    let (mut eth, mut ip, mut tcp) = host(1);
+    tcp.set_recv_buffer_size(1 << 20);
+    tcp.set_window_scale(Some(7));
+
+    let mut listener = tcp::Listener::new(IP_ADDR_HOST.into(), 80, FnHandler(|_: tcp::Open<_>| ()));
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let _ = nic.tx(1, eth.send(ip.send(tcp.send(&mut listener))));
+    let key = listener.connection_key().expect("Listening on the port");
+
+    let syn = tcp_wire::Repr {
+        src_port: 4000,
+        dst_port: 80,
+        seq_number: tcp_wire::SeqNumber(1000),
+        flags: tcp_wire::Flags::SYN,
+        ack_number: None,
+        window_len: 1000,
+        window_scale,
+        max_seg_size: None,
+        sack_permitted: false,
+        sack_ranges: [None; 3],
+        payload_len: 0,
+    };
+    let mut nic = External::new_recv(vec![remote_segment(syn)]);
+    let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut listener))));
+    assert_eq!(recv, Ok(1));
+    let syn_ack = host_segment(&nic.queued()[0]);
+
+    let ack = tcp_wire::Repr {
+        flags: tcp_wire::Flags::default(),
+        seq_number: syn.seq_number + 1,
+        ack_number: Some(syn_ack.seq_number + 1),
+        window_scale: None,
+        ..syn
+    };
+    let mut nic = External::new_recv(vec![remote_segment(ack)]);
+    let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut listener))));
+    assert_eq!(recv, Ok(1));
+
+    (syn_ack, *tcp.get(key).expect("Connection was established"))
+}
+
+#[test]
+fn window_scale_negotiation() {
+    let (syn_ack, slot) = accept_scaled(Some(7));
+    // The window of the SYN-ACK itself is not scaled.
+    assert_eq!(syn_ack.window_scale, Some(7));
+    assert_eq!(syn_ack.window_len, u16::max_value());
+
+    // All later windows are shifted, in both directions.
+    let connection = slot.connection();
+    assert_eq!(connection.send.window_scale, 7);
+    assert_eq!(u32::from(connection.send.window) << connection.send.window_scale, 1000 << 7);
+    assert_eq!(connection.recv.window_scale, 7);
+    assert_eq!(u32::from(connection.recv.window) << connection.recv.window_scale, 1 << 20);
+}
+
+#[test]
+fn window_scale_fallback() {
+    let (syn_ack, slot) = accept_scaled(None);
+    // The remote did not offer scaling, so neither do we.
+    assert_eq!(syn_ack.window_scale, None);
+
+    let connection = slot.connection();
+    assert_eq!(connection.send.window_scale, 0);
+    assert_eq!(connection.send.window, 1000);
+    assert_eq!(connection.recv.window_scale, 0);
+    assert_eq!(connection.recv.window, u16::max_value());
+}
+
+/// Reads all data of open connections into a reassembling buffer, acking it.
+struct Reassemble(tcp::io::RecvInto<Vec<u8>>);
+
+impl<P: PayloadMut> tcp::Recv<P> for &'_ mut Reassemble {
+    fn receive(&mut self, packet: tcp::InPacket<P>) {
+        if let tcp::InPacket::Open(mut open) = packet {
+            open.read(&mut self.0);
+            let _ = open.write(&mut tcp::io::Empty::default());
+        }
+    }
+}
+
+#[test]
+fn selective_ack() {
+    const REMOTE_ISN: tcp_wire::SeqNumber = tcp_wire::SeqNumber(1000);
+
+    let (mut eth, mut ip, mut tcp) = host(1);
+
+    let mut listener = tcp::Listener::new(IP_ADDR_HOST.into(), 80, FnHandler(|_: tcp::Open<_>| ()));
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let _ = nic.tx(1, eth.send(ip.send(tcp.send(&mut listener))));
+
+    let syn = tcp_wire::Repr {
+        src_port: 4000,
+        dst_port: 80,
+        seq_number: REMOTE_ISN,
+        flags: tcp_wire::Flags::SYN,
+        ack_number: None,
+        window_len: 1024,
+        window_scale: None,
+        max_seg_size: None,
+        sack_permitted: true,
+        sack_ranges: [None; 3],
+        payload_len: 0,
+    };
+    let mut nic = External::new_recv(vec![remote_segment(syn)]);
+    let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut listener))));
+    assert_eq!(recv, Ok(1));
+    let syn_ack = host_segment(&nic.queued()[0]);
+    // We agree to use selective acknowledgements.
+    assert!(syn_ack.sack_permitted);
+
+    let ack = tcp_wire::Repr {
+        flags: tcp_wire::Flags::default(),
+        seq_number: REMOTE_ISN + 1,
+        ack_number: Some(syn_ack.seq_number + 1),
+        sack_permitted: false,
+        ..syn
+    };
+    let mut nic = External::new_recv(vec![remote_segment(ack)]);
+    let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut listener))));
+    assert_eq!(recv, Ok(1));
+
+    let mut reader = Reassemble(tcp::io::RecvInto::new(vec![0; 1024]));
+    let data = |offset: usize| tcp_wire::Repr {
+        seq_number: REMOTE_ISN + 1 + offset,
+        payload_len: 100,
+        ..ack
+    };
+
+    // The first segment arrives in order.
+    let mut nic = External::new_recv(vec![remote_segment(data(0))]);
+    let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut reader))));
+    assert_eq!(recv, Ok(1));
+    let in_order = host_segment(&nic.queued()[0]);
+    assert_eq!(in_order.ack_number, Some(REMOTE_ISN + 101));
+    assert_eq!(in_order.sack_ranges, [None; 3]);
+
+    // The third is buffered and reported beyond the missing one.
+    let mut nic = External::new_recv(vec![remote_segment(data(200))]);
+    let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut reader))));
+    assert_eq!(recv, Ok(1));
+    let gap = host_segment(&nic.queued()[0]);
+    assert_eq!(gap.ack_number, Some(REMOTE_ISN + 101));
+    let block = ((REMOTE_ISN + 201).0 as u32, (REMOTE_ISN + 301).0 as u32);
+    assert_eq!(gap.sack_ranges, [Some(block), None, None]);
+
+    // Filling the gap acknowledges everything.
+    let mut nic = External::new_recv(vec![remote_segment(data(100))]);
+    let recv = nic.rx(1, eth.recv(ip.recv(tcp.recv(&mut reader))));
+    assert_eq!(recv, Ok(1));
+    let complete = host_segment(&nic.queued()[0]);
+    assert_eq!(complete.ack_number, Some(REMOTE_ISN + 301));
+    assert_eq!(complete.sack_ranges, [None; 3]);
+}
@@ -0,0 +1,227 @@
+use crate::layer::{udp, DropReason, Error, FnHandler, Result};
+use crate::time::Duration;
+use crate::wire::{ip::Address, tftp, Payload, PayloadMut};
+
+use super::Files;
+use super::transfer::{self, NoHandler, State, Transfer};
+
+/// A TFTP client.
+///
+/// Reads or writes one file at a time from a server. Start a transfer with [`get`] or [`put`] and
+/// then use the client as a udp receiver and sender until the [`state`] is no longer `Busy`.
+///
+/// [`get`]: #method.get
+/// [`put`]: #method.put
+/// [`state`]: #method.state
+pub struct Client<'a, F> {
+    /// The local port and transfer identifier.
+    port: u16,
+
+    /// The source and destination of transferred data.
+    files: F,
+
+    /// The mode of requests.
+    mode: tftp::Mode,
+
+    /// The time to wait for the server before retransmitting.
+    timeout: Duration,
+
+    /// The state of the last transfer.
+    state: State,
+
+    /// The last transfer, kept after completion to answer retransmissions of the server.
+    transfer: Option<Transfer<'a>>,
+}
+
+/// A client borrowed for receiving.
+///
+/// Handles packets to the port of the client and passes all others on.
+pub struct Receiver<'c, 'a, F, H=NoHandler> {
+    client: &'c mut Client<'a, F>,
+
+    /// The receiver for all other packets.
+    handler: Option<H>,
+}
+
+/// A client borrowed for sending.
+///
+/// Sends requests and retransmissions when they are due and passes all other buffers on.
+pub struct Sender<'c, 'a, F, H=NoHandler> {
+    client: &'c mut Client<'a, F>,
+
+    /// The sender using all remaining buffers.
+    handler: Option<H>,
+}
+
+impl<'a, F: Files> Client<'a, F> {
+    /// Create a client using some local port.
+    ///
+    /// Requests are made in octet mode, with a timeout of one second.
+    pub fn new(port: u16, files: F) -> Self {
+        Client {
+            port,
+            files,
+            mode: tftp::Mode::Octet,
+            timeout: transfer::TIMEOUT,
+            state: State::Idle,
+            transfer: None,
+        }
+    }
+
+    /// Change the mode of future requests.
+    pub fn set_mode(&mut self, mode: tftp::Mode) {
+        self.mode = mode;
+    }
+
+    /// Change the time to wait for an answer before retransmitting.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// The state of the current or last transfer.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Access the files of the client.
+    pub fn files(&self) -> &F {
+        &self.files
+    }
+
+    /// Mutably access the files of the client.
+    pub fn files_mut(&mut self) -> &mut F {
+        &mut self.files
+    }
+
+    /// Start reading a file from a server.
+    ///
+    /// The file is opened for writing with the same name. The request is sent with the next
+    /// buffer of the sender.
+    ///
+    /// Returns `Err(Error::Exhausted)` if a transfer is already in progress. If the file can not
+    /// be opened, the state changes to the returned error code and `Err(Error::Illegal)` is
+    /// returned.
+    pub fn get(&mut self, server: Address, filename: &'a [u8]) -> Result<()> {
+        let mode = self.mode;
+        self.start(server, tftp::Repr::Read { filename, mode })
+    }
+
+    /// Start writing a file to a server.
+    ///
+    /// The file is opened for reading with the same name. Otherwise, this behaves exactly as
+    /// [`get`].
+    ///
+    /// [`get`]: #method.get
+    pub fn put(&mut self, server: Address, filename: &'a [u8]) -> Result<()> {
+        let mode = self.mode;
+        self.start(server, tftp::Repr::Write { filename, mode })
+    }
+
+    /// Receive packets, handling those of the transfer internally.
+    pub fn answer(&mut self) -> Receiver<'_, 'a, F> {
+        Receiver { client: self, handler: None, }
+    }
+
+    /// Send requests and retransmissions when they are due.
+    pub fn poll(&mut self) -> Sender<'_, 'a, F> {
+        Sender { client: self, handler: None, }
+    }
+
+    /// Receive packets, passing all packets not for the client to the handler.
+    pub fn recv<H>(&mut self, handler: H) -> Receiver<'_, 'a, F, H> {
+        Receiver { client: self, handler: Some(handler), }
+    }
+
+    /// Receive packets, passing all packets not for the client to a function.
+    pub fn recv_with<H>(&mut self, handler: H) -> Receiver<'_, 'a, F, FnHandler<H>> {
+        self.recv(FnHandler(handler))
+    }
+
+    /// Send requests and retransmissions when due, passing all other buffers to the handler.
+    pub fn send<H>(&mut self, handler: H) -> Sender<'_, 'a, F, H> {
+        Sender { client: self, handler: Some(handler), }
+    }
+
+    /// Send requests and retransmissions when due, passing all other buffers to a function.
+    pub fn send_with<H>(&mut self, handler: H) -> Sender<'_, 'a, F, FnHandler<H>> {
+        self.send(FnHandler(handler))
+    }
+
+    fn start(&mut self, server: Address, request: tftp::Repr<'a>) -> Result<()> {
+        if self.state == State::Busy {
+            return Err(Error::Exhausted);
+        }
+
+        let (filename, write) = match request {
+            tftp::Repr::Read { filename, .. } => (filename, true),
+            tftp::Repr::Write { filename, .. } => (filename, false),
+            _ => return Err(Error::Illegal),
+        };
+
+        if let Err(code) = self.files.open(filename, write) {
+            self.state = State::Error(code);
+            self.transfer = None;
+            return Err(Error::Illegal);
+        }
+
+        self.state = State::Busy;
+        self.transfer = Some(Transfer::request(self.port, server, tftp::PORT, request));
+        Ok(())
+    }
+}
+
+impl<P, F, H> udp::Recv<P> for Receiver<'_, '_, F, H>
+where
+    P: PayloadMut,
+    F: Files,
+    H: udp::Recv<P>,
+{
+    fn receive(&mut self, packet: udp::Packet<P>) {
+        let client = &mut *self.client;
+        let transfer = match client.transfer.as_mut() {
+            Some(transfer) if packet.packet.repr().dst_port == client.port => transfer,
+            _ => return match self.handler.as_mut() {
+                Some(handler) => handler.receive(packet),
+                None => {
+                    let udp::Packet { mut control, packet } = packet;
+                    control.inner.report_drop(DropReason::Unbound, packet.as_bytes())
+                },
+            },
+        };
+
+        let src = transfer::source(&packet);
+        let files = &mut client.files;
+        let action = transfer::parse(&packet, |repr, data| {
+            transfer.receive(files, src, repr, data)
+        });
+
+        match action {
+            Some(action) => transfer::act(
+                action, packet, transfer, &mut client.files, &mut client.state, client.timeout),
+            None => {
+                let udp::Packet { mut control, packet } = packet;
+                control.inner.report_drop(DropReason::Malformed, packet.as_bytes())
+            },
+        }
+    }
+}
+
+impl<P, F, H> udp::Send<P> for Sender<'_, '_, F, H>
+where
+    P: Payload + PayloadMut,
+    F: Files,
+    H: udp::Send<P>,
+{
+    fn send(&mut self, packet: udp::RawPacket<P>) {
+        let client = &mut *self.client;
+        let unused = match client.transfer.as_mut() {
+            Some(transfer) => transfer::poll(
+                packet, transfer, &mut client.files, &mut client.state, client.timeout),
+            None => Some(packet),
+        };
+
+        if let (Some(packet), Some(handler)) = (unused, self.handler.as_mut()) {
+            handler.send(packet)
+        }
+    }
+}
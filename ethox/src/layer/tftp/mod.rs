@@ -0,0 +1,90 @@
+//! The Trivial File Transfer Protocol.
+//!
+//! A minimal client and server for [RFC1350] over the udp layer. Both sides handle a single
+//! transfer at a time, in octet or netascii mode without any conversion of the data. The files
+//! themselves are provided by the user through the [`Files`] trait, for example backed by a flash
+//! region when used for firmware updates.
+//!
+//! ## Structure
+//!
+//! The client and the server are used as udp receivers and senders, wrapping another handler of
+//! that layer to which all packets not belonging to them are passed. They can be stacked to run
+//! both on the same udp endpoint.
+//!
+//! Transfers proceed in lock-step. Each data block is acknowledged before the next one is sent and
+//! a block shorter than 512 bytes ends the transfer. The receiver answers in place by reusing the
+//! buffer of the received packet, so that a transfer makes progress even when no further buffers
+//! are available for sending. The sender retransmits the last packet when no answer has arrived
+//! within the timeout and abandons the transfer after a few retries. Duplicate acknowledgements are
+//! never answered, avoiding the Sorcerer's Apprentice bug.
+//!
+//! [RFC1350]: https://tools.ietf.org/html/rfc1350
+//! [`Files`]: trait.Files.html
+use crate::wire::tftp::ErrorCode;
+
+mod client;
+mod server;
+mod transfer;
+#[cfg(test)]
+mod tests;
+
+pub use client::{
+    Client,
+    Receiver as ClientReceiver,
+    Sender as ClientSender,
+};
+
+pub use server::{
+    Server,
+    Receiver as ServerReceiver,
+    Sender as ServerSender,
+};
+
+pub use transfer::{
+    NoHandler,
+    State,
+};
+
+/// Access to the files of a client or server.
+///
+/// At most one file is open at a time. Reads and writes of a transfer happen in order but a block
+/// may be read again when it needs to be retransmitted.
+pub trait Files {
+    /// Open a file for a transfer.
+    ///
+    /// The file is written to if `write` is set and read otherwise. An error is reported to the
+    /// remote and aborts the transfer.
+    fn open(&mut self, filename: &[u8], write: bool) -> Result<(), ErrorCode>;
+
+    /// Read a block of the open file at some offset.
+    ///
+    /// Returns the number of bytes read, which is only smaller than the buffer at the end of the
+    /// file.
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, ErrorCode>;
+
+    /// Write a block of data to the open file at some offset.
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Close the open file.
+    ///
+    /// The `complete` argument indicates if the transfer was successful.
+    fn close(&mut self, complete: bool);
+}
+
+impl<F: Files + ?Sized> Files for &'_ mut F {
+    fn open(&mut self, filename: &[u8], write: bool) -> Result<(), ErrorCode> {
+        (**self).open(filename, write)
+    }
+
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, ErrorCode> {
+        (**self).read(offset, buffer)
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), ErrorCode> {
+        (**self).write(offset, data)
+    }
+
+    fn close(&mut self, complete: bool) {
+        (**self).close(complete)
+    }
+}
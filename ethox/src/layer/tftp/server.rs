@@ -0,0 +1,233 @@
+use crate::layer::{udp, DropReason, FnHandler};
+use crate::time::Duration;
+use crate::wire::{tftp, Payload, PayloadMut};
+
+use super::Files;
+use super::transfer::{self, NoHandler, State, Transfer};
+
+/// A TFTP server.
+///
+/// Listens for requests on the well-known port and serves one transfer at a time. Requests
+/// arriving while a transfer is in progress are rejected with an error.
+pub struct Server<F> {
+    /// The port on which requests are accepted.
+    port: u16,
+
+    /// The local port used for transfers, our transfer identifier.
+    transfer_port: u16,
+
+    /// The files that can be read and written.
+    files: F,
+
+    /// The time to wait for the client before retransmitting.
+    timeout: Duration,
+
+    /// The state of the last transfer.
+    state: State,
+
+    /// The last transfer, kept after completion to answer retransmissions of the client.
+    transfer: Option<Transfer<'static>>,
+}
+
+/// A server borrowed for receiving.
+///
+/// Handles requests and packets of the transfer and passes all others on.
+pub struct Receiver<'s, F, H=NoHandler> {
+    server: &'s mut Server<F>,
+
+    /// The receiver for all other packets.
+    handler: Option<H>,
+}
+
+/// A server borrowed for sending.
+///
+/// Sends retransmissions when they are due and passes all other buffers on.
+pub struct Sender<'s, F, H=NoHandler> {
+    server: &'s mut Server<F>,
+
+    /// The sender using all remaining buffers.
+    handler: Option<H>,
+}
+
+impl<F: Files> Server<F> {
+    /// Create a server, using a local port for the transfers.
+    ///
+    /// The server accepts requests on the standard port 69, with a timeout of one second.
+    pub fn new(transfer_port: u16, files: F) -> Self {
+        Server {
+            port: tftp::PORT,
+            transfer_port,
+            files,
+            timeout: transfer::TIMEOUT,
+            state: State::Idle,
+            transfer: None,
+        }
+    }
+
+    /// Change the port on which requests are accepted.
+    pub fn set_port(&mut self, port: u16) {
+        self.port = port;
+    }
+
+    /// Change the time to wait for an answer before retransmitting.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// The state of the current or last transfer.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Access the files of the server.
+    pub fn files(&self) -> &F {
+        &self.files
+    }
+
+    /// Mutably access the files of the server.
+    pub fn files_mut(&mut self) -> &mut F {
+        &mut self.files
+    }
+
+    /// Receive packets, handling requests and transfers internally.
+    pub fn answer(&mut self) -> Receiver<'_, F> {
+        Receiver { server: self, handler: None, }
+    }
+
+    /// Send retransmissions when they are due.
+    pub fn poll(&mut self) -> Sender<'_, F> {
+        Sender { server: self, handler: None, }
+    }
+
+    /// Receive packets, passing all packets not for the server to the handler.
+    pub fn recv<H>(&mut self, handler: H) -> Receiver<'_, F, H> {
+        Receiver { server: self, handler: Some(handler), }
+    }
+
+    /// Receive packets, passing all packets not for the server to a function.
+    pub fn recv_with<H>(&mut self, handler: H) -> Receiver<'_, F, FnHandler<H>> {
+        self.recv(FnHandler(handler))
+    }
+
+    /// Send retransmissions when due, passing all other buffers to the handler.
+    pub fn send<H>(&mut self, handler: H) -> Sender<'_, F, H> {
+        Sender { server: self, handler: Some(handler), }
+    }
+
+    /// Send retransmissions when due, passing all other buffers to a function.
+    pub fn send_with<H>(&mut self, handler: H) -> Sender<'_, F, FnHandler<H>> {
+        self.send(FnHandler(handler))
+    }
+
+    /// Handle a packet sent to the request port.
+    fn request<P: PayloadMut>(&mut self, packet: udp::Packet<P>) {
+        let src = transfer::source(&packet);
+        let busy = self.state == State::Busy;
+
+        if busy && self.transfer.as_ref().map(Transfer::remote) == Some(src) {
+            // A retransmitted request, the answer will be retransmitted as well.
+            return;
+        }
+
+        let files = &mut self.files;
+        let opened = transfer::parse(&packet, |repr, _| {
+            let (filename, write) = match repr {
+                tftp::Repr::Read { filename, .. } => (filename, false),
+                tftp::Repr::Write { filename, .. } => (filename, true),
+                _ => return Err((tftp::ErrorCode::IllegalOperation, &b"Not a request"[..])),
+            };
+            if busy {
+                return Err((tftp::ErrorCode::Undefined, &b"Busy"[..]));
+            }
+            files.open(filename, write)
+                .map(|()| !write)
+                .map_err(|code| (code, &b"Can not open file"[..]))
+        });
+
+        let sending = match opened {
+            None => {
+                let udp::Packet { mut control, packet } = packet;
+                return control.inner.report_drop(DropReason::Malformed, packet.as_bytes());
+            },
+            Some(Err((code, message))) => {
+                let _ = transfer::answer_error(packet, code, message);
+                return;
+            },
+            Some(Ok(sending)) => sending,
+        };
+
+        let local = packet.packet.get_ref().repr().dst_addr();
+        let time = packet.info().timestamp();
+        let mut transfer = Transfer::answer(local, self.transfer_port, src.0, src.1, sending);
+        self.state = State::Busy;
+
+        if let Err(code) = transfer.send(&mut self.files, |init| packet.reinit(init), time, self.timeout) {
+            self.state = State::Error(code);
+            self.files.close(false);
+        }
+
+        self.transfer = Some(transfer);
+    }
+}
+
+impl<P, F, H> udp::Recv<P> for Receiver<'_, F, H>
+where
+    P: PayloadMut,
+    F: Files,
+    H: udp::Recv<P>,
+{
+    fn receive(&mut self, packet: udp::Packet<P>) {
+        let server = &mut *self.server;
+        let dst_port = packet.packet.repr().dst_port;
+
+        if dst_port == server.port {
+            return server.request(packet);
+        }
+
+        let transfer = match server.transfer.as_mut() {
+            Some(transfer) if dst_port == transfer.local_port() => transfer,
+            _ => return match self.handler.as_mut() {
+                Some(handler) => handler.receive(packet),
+                None => {
+                    let udp::Packet { mut control, packet } = packet;
+                    control.inner.report_drop(DropReason::Unbound, packet.as_bytes())
+                },
+            },
+        };
+
+        let src = transfer::source(&packet);
+        let files = &mut server.files;
+        let action = transfer::parse(&packet, |repr, data| {
+            transfer.receive(files, src, repr, data)
+        });
+
+        match action {
+            Some(action) => transfer::act(
+                action, packet, transfer, &mut server.files, &mut server.state, server.timeout),
+            None => {
+                let udp::Packet { mut control, packet } = packet;
+                control.inner.report_drop(DropReason::Malformed, packet.as_bytes())
+            },
+        }
+    }
+}
+
+impl<P, F, H> udp::Send<P> for Sender<'_, F, H>
+where
+    P: Payload + PayloadMut,
+    F: Files,
+    H: udp::Send<P>,
+{
+    fn send(&mut self, packet: udp::RawPacket<P>) {
+        let server = &mut *self.server;
+        let unused = match server.transfer.as_mut() {
+            Some(transfer) => transfer::poll(
+                packet, transfer, &mut server.files, &mut server.state, server.timeout),
+            None => Some(packet),
+        };
+
+        if let (Some(packet), Some(handler)) = (unused, self.handler.as_mut()) {
+            handler.send(packet)
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::nic::{loopback::Loopback, Device};
+use crate::layer::{arp, eth, ip, udp, Error};
+use crate::time::{Duration, Instant};
+use crate::wire::{ethernet::Address, ip::Cidr, ip::v4, tftp::ErrorCode};
+
+use super::{Client, Files, Server, State};
+
+const MAC_ADDR_HOST: Address = Address([0, 1, 2, 3, 4, 5]);
+const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+
+const CLIENT_PORT: u16 = 4096;
+const TRANSFER_PORT: u16 = 1069;
+
+/// In-memory files, checking that all writes happen in order.
+#[derive(Default)]
+struct Store {
+    files: Vec<(Vec<u8>, Vec<u8>)>,
+    open: Option<usize>,
+    writes: usize,
+    closed: Option<bool>,
+}
+
+enum Op {
+    Get,
+    Put,
+}
+
+impl Store {
+    fn with(filename: &[u8], content: Vec<u8>) -> Self {
+        Store {
+            files: vec![(filename.to_vec(), content)],
+            ..Store::default()
+        }
+    }
+
+    fn file(&self, filename: &[u8]) -> Option<&[u8]> {
+        self.files.iter()
+            .find(|(name, _)| name == filename)
+            .map(|(_, content)| &content[..])
+    }
+}
+
+impl Files for Store {
+    fn open(&mut self, filename: &[u8], write: bool) -> Result<(), ErrorCode> {
+        let existing = self.files.iter().position(|(name, _)| name == filename);
+        let index = match (existing, write) {
+            (Some(index), false) => index,
+            (None, false) => return Err(ErrorCode::FileNotFound),
+            (Some(_), true) => return Err(ErrorCode::FileExists),
+            (None, true) => {
+                self.files.push((filename.to_vec(), Vec::new()));
+                self.files.len() - 1
+            },
+        };
+        self.open = Some(index);
+        self.closed = None;
+        Ok(())
+    }
+
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, ErrorCode> {
+        let content = &self.files[self.open.unwrap()].1;
+        let remaining = &content[offset.min(content.len())..];
+        let len = remaining.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&remaining[..len]);
+        Ok(len)
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), ErrorCode> {
+        let content = &mut self.files[self.open.unwrap()].1;
+        assert_eq!(offset, content.len(), "Data written out of order");
+        content.extend_from_slice(data);
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn close(&mut self, complete: bool) {
+        self.open = None;
+        self.closed = Some(complete);
+    }
+}
+
+fn content(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Run a transfer between a client and a server on the same host.
+///
+/// The `drop` packet received on the loopback is lost, counting from zero.
+fn transfer(client: &mut Store, server: &mut Store, op: Op, drop: Option<usize>) -> (State, State) {
+    let mut nic = Loopback::<Vec<u8>>::new(vec![0; 1 << 12].into());
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_HOST.into(), MAC_ADDR_HOST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_HOST.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+    let mut udp = udp::Endpoint::new_unfiltered();
+
+    let mut server = Server::new(TRANSFER_PORT, server);
+    let mut client = Client::new(CLIENT_PORT, client);
+    match op {
+        Op::Get => client.get(IP_ADDR_HOST.into(), b"file"),
+        Op::Put => client.put(IP_ADDR_HOST.into(), b"file"),
+    }.expect("Can start the transfer");
+
+    let mut time = Instant::from_millis(0);
+    let mut received = 0;
+
+    for _ in 0..1000 {
+        let sent = match nic.tx(1, eth.send(ip.send(udp.send(server.send(client.poll()))))) {
+            // All buffers of the loopback are still in flight.
+            Err(Error::Exhausted) => 0,
+            other => other.unwrap(),
+        };
+        let recv = if drop == Some(received) {
+            nic.rx(1, eth.recv(ip.recv(udp.recv_with(|_: udp::Packet<_>| ()))))
+        } else {
+            nic.rx(1, eth.recv(ip.recv(udp.recv(server.recv(client.answer())))))
+        }.unwrap();
+        received += recv;
+
+        if sent == 0 && recv == 0 {
+            if client.state() != State::Busy && server.state() != State::Busy {
+                return (client.state(), server.state());
+            }
+
+            // Nothing in flight, wait for a retransmission.
+            time = time + Duration::from_secs(2);
+            nic.set_current_time(time);
+        }
+    }
+
+    panic!("Transfer did not finish");
+}
+
+#[test]
+fn get_file() {
+    let mut client = Store::default();
+    let mut server = Store::with(b"file", content(1300));
+
+    let states = transfer(&mut client, &mut server, Op::Get, None);
+    assert_eq!(states, (State::Done, State::Done));
+    assert_eq!(client.file(b"file"), Some(&content(1300)[..]));
+    assert_eq!(client.writes, 3);
+    assert_eq!(client.closed, Some(true));
+    assert_eq!(server.closed, Some(true));
+}
+
+#[test]
+fn put_file() {
+    let mut client = Store::with(b"file", content(1300));
+    let mut server = Store::default();
+
+    let states = transfer(&mut client, &mut server, Op::Put, None);
+    assert_eq!(states, (State::Done, State::Done));
+    assert_eq!(server.file(b"file"), Some(&content(1300)[..]));
+    assert_eq!(server.writes, 3);
+}
+
+#[test]
+fn final_empty_block() {
+    let mut client = Store::default();
+    let mut server = Store::with(b"file", content(1024));
+
+    let states = transfer(&mut client, &mut server, Op::Get, None);
+    assert_eq!(states, (State::Done, State::Done));
+    assert_eq!(client.file(b"file"), Some(&content(1024)[..]));
+    // Two full blocks and the terminating empty block.
+    assert_eq!(client.writes, 3);
+}
+
+#[test]
+fn file_not_found() {
+    let mut client = Store::default();
+    let mut server = Store::default();
+
+    let states = transfer(&mut client, &mut server, Op::Get, None);
+    assert_eq!(states, (State::Error(ErrorCode::FileNotFound), State::Idle));
+    assert_eq!(client.closed, Some(false));
+}
+
+#[test]
+fn retransmit_lost() {
+    // Lose every packet of the transfer once, one at a time.
+    for drop in 0..8 {
+        let mut client = Store::default();
+        let mut server = Store::with(b"file", content(1300));
+
+        let states = transfer(&mut client, &mut server, Op::Get, Some(drop));
+        assert_eq!(states, (State::Done, State::Done), "Dropped packet {}", drop);
+        assert_eq!(client.file(b"file"), Some(&content(1300)[..]));
+        // Every block was written exactly once.
+        assert_eq!(client.writes, 3);
+
+        let mut client = Store::with(b"file", content(1300));
+        let mut server = Store::default();
+
+        let states = transfer(&mut client, &mut server, Op::Put, Some(drop));
+        assert_eq!(states, (State::Done, State::Done), "Dropped packet {}", drop);
+        assert_eq!(server.file(b"file"), Some(&content(1300)[..]));
+        assert_eq!(server.writes, 3);
+    }
+}
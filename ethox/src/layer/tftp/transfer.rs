@@ -0,0 +1,450 @@
+use crate::layer::{ip, udp, Result};
+use crate::time::{Duration, Instant};
+use crate::wire::{tftp, Payload, PayloadMut};
+use crate::wire::ip::{v4, v6, Address, Subnet};
+
+use super::Files;
+
+/// The default time to wait for an answer before retransmitting.
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The number of retransmissions before a transfer is abandoned.
+pub(crate) const RETRIES: u8 = 5;
+
+/// A type that can not be instantiated.
+///
+/// Used as the default type of handlers of the receiver and sender that are created without an
+/// upper layer handler. Its methods can never be invoked.
+pub struct NoHandler { _private: Empty, }
+
+/// An empty enum to prove that there is no instance of `NoHandler`.
+enum Empty { }
+
+/// The state of a client or server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    /// No transfer has been started yet.
+    Idle,
+    /// A transfer is in progress.
+    Busy,
+    /// The last transfer completed successfully.
+    Done,
+    /// The last transfer was aborted with an error, either sent or received.
+    Error(tftp::ErrorCode),
+    /// The last transfer was abandoned since the remote stopped answering.
+    TimedOut,
+}
+
+/// The packet of a transfer that is awaiting an answer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Current<'a> {
+    /// The request of a client, before the server has answered.
+    Request(tftp::Repr<'a>),
+    /// A data block.
+    Data { block: u16 },
+    /// The acknowledgement of a data block.
+    Ack { block: u16 },
+}
+
+/// What to do after processing a packet of a transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Action {
+    /// Not a packet of the transfer, or an old duplicate.
+    Ignore,
+    /// Answer with the next packet of the transfer.
+    Answer,
+    /// Answer the sender of the packet with an error.
+    Error {
+        code: tftp::ErrorCode,
+        message: &'static [u8],
+        /// If the error terminates the transfer.
+        abort: bool,
+    },
+    /// The remote has acknowledged the last block.
+    Complete,
+    /// The remote has aborted the transfer.
+    Aborted(tftp::ErrorCode),
+}
+
+/// The lock-step state of one transfer, shared by client and server.
+///
+/// Only the side currently expecting an answer retransmits after a timeout. Packets are never
+/// sent in response to duplicates of an acknowledgement which avoids the Sorcerer's Apprentice
+/// bug of RFC1123.
+pub(crate) struct Transfer<'a> {
+    source: ip::Source,
+    local_port: u16,
+    remote_addr: Address,
+    remote_port: u16,
+    /// If we send the data of the file.
+    sending: bool,
+    current: Current<'a>,
+    /// The offset of the current data block when sending, or of the next when receiving.
+    offset: usize,
+    /// If the current block is the last one.
+    last: bool,
+    /// When to retransmit the current packet, `None` if it has not yet been sent.
+    deadline: Option<Instant>,
+    retries: u8,
+}
+
+impl<'a> Transfer<'a> {
+    /// A client transfer, starting with a request to the server.
+    pub(crate) fn request(local_port: u16, server: Address, server_port: u16, request: tftp::Repr<'a>)
+        -> Self
+    {
+        Transfer {
+            source: any_source(server),
+            local_port,
+            remote_addr: server,
+            remote_port: server_port,
+            sending: matches!(request, tftp::Repr::Write { .. }),
+            current: Current::Request(request),
+            offset: 0,
+            last: false,
+            deadline: None,
+            retries: 0,
+        }
+    }
+
+    /// A server transfer, answering a request.
+    ///
+    /// Starts with the first data block for a read request and its acknowledgement for a write
+    /// request.
+    pub(crate) fn answer(local: Address, local_port: u16, client: Address, client_port: u16, sending: bool)
+        -> Self
+    {
+        Transfer {
+            source: ip::Source::Exact(local),
+            local_port,
+            remote_addr: client,
+            remote_port: client_port,
+            sending,
+            current: if sending {
+                Current::Data { block: 1 }
+            } else {
+                Current::Ack { block: 0 }
+            },
+            offset: 0,
+            last: false,
+            deadline: None,
+            retries: 0,
+        }
+    }
+
+    /// The local port of the transfer.
+    pub(crate) fn local_port(&self) -> u16 {
+        self.local_port
+    }
+
+    /// The address and port of the remote.
+    pub(crate) fn remote(&self) -> (Address, u16) {
+        (self.remote_addr, self.remote_port)
+    }
+
+    /// Check if all data has been transferred.
+    ///
+    /// The receiving side should keep the transfer around for a while after it has completed, to
+    /// acknowledge the last block again if its acknowledgement was lost.
+    pub(crate) fn is_complete(&self) -> bool {
+        match self.current {
+            Current::Ack { .. } => self.last,
+            _ => false,
+        }
+    }
+
+    /// Process a received packet.
+    pub(crate) fn receive(
+        &mut self,
+        files: &mut impl Files,
+        src: (Address, u16),
+        repr: tftp::Repr,
+        data: &[u8],
+    ) -> Action {
+        let (src_addr, src_port) = src;
+        if src_addr != self.remote_addr {
+            return Action::Ignore;
+        }
+
+        if let Current::Request(_) = self.current {
+            // Learn the transfer identifier of the server.
+            self.remote_port = src_port;
+        } else if src_port != self.remote_port {
+            return Action::Error {
+                code: tftp::ErrorCode::UnknownTid,
+                message: b"Unknown transfer ID",
+                abort: false,
+            };
+        }
+
+        match repr {
+            tftp::Repr::Error { code, .. } => Action::Aborted(code),
+            tftp::Repr::Data { block, len } if !self.sending => {
+                let expected = match self.current {
+                    Current::Ack { block } => block.wrapping_add(1),
+                    _ => 1,
+                };
+
+                if block == expected && !self.last {
+                    if let Err(code) = files.write(self.offset, data) {
+                        return Action::Error {
+                            code,
+                            message: b"Write failed",
+                            abort: true,
+                        };
+                    }
+                    self.offset += len;
+                    self.current = Current::Ack { block };
+                    self.last = len < tftp::BLOCK_SIZE;
+                    self.retries = 0;
+                    Action::Answer
+                } else if self.current == (Current::Ack { block }) {
+                    // Our acknowledgement was lost.
+                    Action::Answer
+                } else {
+                    Action::Ignore
+                }
+            },
+            tftp::Repr::Ack { block } if self.sending => match self.current {
+                Current::Request(_) if block == 0 => {
+                    self.current = Current::Data { block: 1 };
+                    self.retries = 0;
+                    Action::Answer
+                },
+                Current::Data { block: current } if current == block => {
+                    if self.last {
+                        return Action::Complete;
+                    }
+                    self.current = Current::Data { block: block.wrapping_add(1) };
+                    self.offset += tftp::BLOCK_SIZE;
+                    self.retries = 0;
+                    Action::Answer
+                },
+                // Duplicates are not answered, only the timeout triggers a retransmission.
+                _ => Action::Ignore,
+            },
+            _ => Action::Error {
+                code: tftp::ErrorCode::IllegalOperation,
+                message: b"Unexpected packet",
+                abort: true,
+            },
+        }
+    }
+
+    /// Check if the current packet should be (re-)transmitted, counting the retransmission.
+    ///
+    /// Returns `Err` if the transfer should be abandoned as the retries are exhausted.
+    pub(crate) fn due(&mut self, time: Instant) -> core::result::Result<bool, ()> {
+        match self.deadline {
+            None => Ok(true),
+            Some(deadline) if deadline > time => Ok(false),
+            Some(_) if self.retries >= RETRIES => Err(()),
+            Some(_) => {
+                self.retries += 1;
+                Ok(true)
+            },
+        }
+    }
+
+    /// Send the current packet into a buffer prepared with `prepare`.
+    ///
+    /// Returns the error code if the file could not be read. An error packet is then sent instead
+    /// and the transfer should be aborted.
+    pub(crate) fn send<'p, P>(
+        &mut self,
+        files: &mut impl Files,
+        prepare: impl FnOnce(udp::Init) -> Result<udp::Packet<'p, P>>,
+        time: Instant,
+        timeout: Duration,
+    ) -> core::result::Result<(), tftp::ErrorCode>
+        where P: Payload + PayloadMut + 'p,
+    {
+        let mut buffer = [0; tftp::BLOCK_SIZE];
+        let (repr, len) = match self.current {
+            Current::Request(repr) => (repr, 0),
+            Current::Ack { block } => (tftp::Repr::Ack { block }, 0),
+            Current::Data { block } => match files.read(self.offset, &mut buffer) {
+                Ok(len) => {
+                    let len = len.min(tftp::BLOCK_SIZE);
+                    self.last = len < tftp::BLOCK_SIZE;
+                    (tftp::Repr::Data { block, len }, len)
+                },
+                Err(code) => {
+                    let repr = tftp::Repr::Error { code, message: b"Read failed" };
+                    let _ = prepare(self.init(repr.buffer_len()))
+                        .and_then(|packet| emit(packet, repr, &[]));
+                    return Err(code);
+                },
+            },
+        };
+
+        let sent = prepare(self.init(repr.buffer_len()))
+            .and_then(|packet| emit(packet, repr, &buffer[..len]));
+
+        // Otherwise try again when polled.
+        if sent.is_ok() {
+            self.deadline = Some(time + timeout);
+        }
+
+        Ok(())
+    }
+
+    fn init(&self, payload: usize) -> udp::Init {
+        udp::Init {
+            source: self.source,
+            src_port: self.local_port,
+            dst_addr: self.remote_addr,
+            dst_port: self.remote_port,
+            payload,
+            fragmentation: ip::Fragmentation::default(),
+            hop_limit: None,
+        }
+    }
+}
+
+/// Perform the action for a received packet of a transfer.
+pub(crate) fn act<P: PayloadMut>(
+    action: Action,
+    packet: udp::Packet<P>,
+    transfer: &mut Transfer,
+    files: &mut impl Files,
+    state: &mut State,
+    timeout: Duration,
+) {
+    // After completion, only lost acknowledgements are answered again.
+    match (*state, action) {
+        (State::Busy, _) | (State::Done, Action::Answer) => (),
+        _ => return,
+    }
+
+    match action {
+        Action::Ignore => (),
+        Action::Answer => {
+            let time = packet.info().timestamp();
+            match transfer.send(files, |init| packet.reinit(init), time, timeout) {
+                Err(code) => abort(state, files, State::Error(code)),
+                Ok(()) if transfer.is_complete() && *state == State::Busy => {
+                    *state = State::Done;
+                    files.close(true);
+                },
+                Ok(()) => (),
+            }
+        },
+        Action::Error { code, message, abort: aborts } => {
+            let _ = answer_error(packet, code, message);
+            if aborts {
+                abort(state, files, State::Error(code));
+            }
+        },
+        Action::Complete => {
+            *state = State::Done;
+            files.close(true);
+        },
+        Action::Aborted(code) => abort(state, files, State::Error(code)),
+    }
+}
+
+/// Retransmit the current packet of a transfer if it is due.
+///
+/// Returns the buffer if it was not used.
+pub(crate) fn poll<'p, P: Payload + PayloadMut>(
+    raw: udp::RawPacket<'p, P>,
+    transfer: &mut Transfer,
+    files: &mut impl Files,
+    state: &mut State,
+    timeout: Duration,
+) -> Option<udp::RawPacket<'p, P>> {
+    if *state != State::Busy {
+        return Some(raw);
+    }
+
+    let time = raw.info().timestamp();
+    match transfer.due(time) {
+        Ok(true) => (),
+        Ok(false) => return Some(raw),
+        Err(()) => {
+            abort(state, files, State::TimedOut);
+            return Some(raw);
+        },
+    }
+
+    if let Err(code) = transfer.send(files, |init| raw.prepare(init), time, timeout) {
+        abort(state, files, State::Error(code));
+    }
+
+    None
+}
+
+fn abort(state: &mut State, files: &mut impl Files, reason: State) {
+    *state = reason;
+    files.close(false);
+}
+
+/// Parse a udp packet as TFTP and pass it to a function, along with its data.
+///
+/// Malformed packets are silently dropped.
+pub(crate) fn parse<P: Payload, T>(
+    packet: &udp::Packet<P>,
+    with: impl FnOnce(tftp::Repr, &[u8]) -> T,
+) -> Option<T> {
+    let payload = packet.packet.payload_slice();
+    let tftp = tftp::packet::new_checked(payload).ok()?;
+    let repr = tftp::Repr::parse(tftp).ok()?;
+    Some(with(repr, tftp.data()))
+}
+
+/// The source address and port of a received packet.
+pub(crate) fn source<P: Payload>(packet: &udp::Packet<P>) -> (Address, u16) {
+    (packet.packet.get_ref().repr().src_addr(), packet.packet.repr().src_port)
+}
+
+/// Answer a received packet with an error.
+pub(crate) fn answer_error<P: PayloadMut>(
+    packet: udp::Packet<P>,
+    code: tftp::ErrorCode,
+    message: &[u8],
+) -> Result<()> {
+    let ip_repr = packet.packet.get_ref().repr();
+    let udp_repr = packet.packet.repr();
+    let repr = tftp::Repr::Error { code, message };
+    let packet = packet.reinit(udp::Init {
+        source: ip::Source::Exact(ip_repr.dst_addr()),
+        src_port: udp_repr.dst_port,
+        dst_addr: ip_repr.src_addr(),
+        dst_port: udp_repr.src_port,
+        payload: repr.buffer_len(),
+        fragmentation: ip::Fragmentation::default(),
+        hop_limit: None,
+    })?;
+    emit(packet, repr, &[])
+}
+
+/// Fill a prepared udp packet and send it.
+fn emit<P: PayloadMut>(mut packet: udp::Packet<P>, repr: tftp::Repr, data: &[u8]) -> Result<()> {
+    let tftp = tftp::packet::new_unchecked_mut(packet.packet.payload_mut_slice());
+    repr.emit(tftp);
+    if let tftp::Repr::Data { .. } = repr {
+        tftp.data_mut().copy_from_slice(data);
+    }
+    packet.send()
+}
+
+/// Select any source address of the same family as the destination.
+fn any_source(dst_addr: Address) -> ip::Source {
+    match dst_addr {
+        Address::Ipv6(_) => Subnet::from(v6::Subnet::ANY).into(),
+        _ => Subnet::from(v4::Subnet::ANY).into(),
+    }
+}
+
+impl<P: Payload> udp::Recv<P> for NoHandler {
+    fn receive(&mut self, _: udp::Packet<P>) {
+        match self._private { }
+    }
+}
+
+impl<P: Payload> udp::Send<P> for NoHandler {
+    fn send(&mut self, _: udp::RawPacket<P>) {
+        match self._private { }
+    }
+}
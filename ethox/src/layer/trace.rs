@@ -0,0 +1,84 @@
+//! Structured events at the decision points of the stack.
+//!
+//! A tracer is installed on the ethernet endpoint with [`eth::Endpoint::set_tracer`] and is then
+//! notified by all layers above it. Without a tracer, reporting an event is a single branch on an
+//! empty `Option` and the event itself is a small `Copy` value, so there is no formatting or other
+//! work done on behalf of tracing.
+//!
+//! Any `FnMut(Event)` is a tracer. This is the intended way to forward events to a logging crate
+//! or a custom sink on embedded targets, for example:
+//!
+//! ```
+//! use ethox::layer::{eth, trace::Event};
+//! # use ethox::wire::ethernet::Address;
+//!
+//! let mut count = 0;
+//! let mut tracer = |event: Event| if let Event::Dropped { .. } = event { count += 1 };
+//!
+//! let mut eth = eth::Endpoint::new(Address([0, 1, 2, 3, 4, 5]));
+//! eth.set_tracer(&mut tracer);
+//! ```
+//!
+//! [`eth::Endpoint::set_tracer`]: ../eth/struct.Endpoint.html#method.set_tracer
+use core::fmt;
+
+use crate::layer::DropReason;
+use crate::wire::{ethernet, ip};
+
+/// A sink for events of the stack.
+pub trait Trace {
+    /// Called once for each event, in the order in which they occur.
+    fn event(&mut self, event: Event);
+}
+
+/// An event at one of the decision points of the stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A frame was accepted by the ethernet layer.
+    Received {
+        /// The protocol of the frame payload.
+        ethertype: ethernet::EtherType,
+        /// The length of the whole frame.
+        len: usize,
+    },
+
+    /// The ip layer chose a route for an outgoing packet.
+    Routed {
+        /// The destination of the packet.
+        dst_addr: ip::Address,
+        /// The neighbor the packet is sent to.
+        next_hop: ip::Address,
+    },
+
+    /// A received packet was dropped.
+    ///
+    /// Reported for the same packets as the drop hook of the ethernet endpoint.
+    Dropped {
+        /// Why the packet was dropped.
+        reason: DropReason,
+    },
+
+    /// The buffer of a received packet was reused for an outgoing packet, such as an answer.
+    Answered,
+
+    /// A retransmission timer expired.
+    TimerFired,
+}
+
+impl<F: FnMut(Event)> Trace for F {
+    fn event(&mut self, event: Event) {
+        self(event)
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Received { ethertype, len } => write!(f, "received {} frame of {} bytes", ethertype, len),
+            Event::Routed { dst_addr, next_hop } => write!(f, "routed {} via {}", dst_addr, next_hop),
+            Event::Dropped { reason } => write!(f, "dropped: {:?}", reason),
+            Event::Answered => write!(f, "answered"),
+            Event::TimerFired => write!(f, "timer fired"),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::layer::{ip, DropReason, Result};
+use crate::wire::{Payload, PayloadMut};
+use crate::wire::ip::Address;
+
+use super::{Init, Packet, RawPacket, Recv};
+
+/// A udp socket connected to a single remote peer.
+///
+/// Remembers the local and remote address and port so that datagrams can be sent without filling
+/// a complete [`Init`] each time, similar to calling `connect` on a POSIX udp socket. When the
+/// local source is a subnet instead of an exact address, the ip layer chooses a matching source
+/// address for each packet.
+///
+/// [`Init`]: struct.Init.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Connection {
+    source: ip::Source,
+    src_port: u16,
+    dst_addr: Address,
+    dst_port: u16,
+}
+
+/// A receiver only passing on datagrams of a connection.
+///
+/// Datagrams from other peers or to other local ports are dropped as `DropReason::Filtered`.
+pub struct Connected<'a, H> {
+    connection: &'a Connection,
+    handler: H,
+}
+
+impl Connection {
+    /// Connect a local source and port to a remote peer.
+    pub fn new(source: ip::Source, src_port: u16, dst_addr: Address, dst_port: u16) -> Self {
+        Connection {
+            source,
+            src_port,
+            dst_addr,
+            dst_port,
+        }
+    }
+
+    /// The local source selection.
+    pub fn source(&self) -> ip::Source {
+        self.source
+    }
+
+    /// The local port.
+    pub fn src_port(&self) -> u16 {
+        self.src_port
+    }
+
+    /// The address of the remote peer.
+    pub fn dst_addr(&self) -> Address {
+        self.dst_addr
+    }
+
+    /// The port of the remote peer.
+    pub fn dst_port(&self) -> u16 {
+        self.dst_port
+    }
+
+    /// An initializer for a datagram to the peer, with default ip options.
+    pub fn init(&self, payload: usize) -> Init {
+        Init {
+            source: self.source,
+            src_port: self.src_port,
+            dst_addr: self.dst_addr,
+            dst_port: self.dst_port,
+            payload,
+            fragmentation: ip::Fragmentation::default(),
+            hop_limit: None,
+        }
+    }
+
+    /// Send a datagram with some payload to the peer.
+    pub fn send<P>(&self, raw: RawPacket<P>, payload: &[u8]) -> Result<()>
+        where P: Payload + PayloadMut,
+    {
+        let mut packet = raw.prepare(self.init(payload.len()))?;
+        packet.packet.payload_mut_slice().copy_from_slice(payload);
+        packet.send()
+    }
+
+    /// Query if a received datagram belongs to this connection.
+    ///
+    /// It must originate from the peer and be addressed to the local port and source.
+    pub fn accepts<P: Payload>(&self, packet: &Packet<P>) -> bool {
+        let ip_repr = packet.packet.get_ref().repr();
+        let repr = packet.packet.repr();
+        let local = match self.source {
+            ip::Source::Exact(addr) => ip_repr.dst_addr() == addr,
+            ip::Source::Mask { subnet } => subnet.contains(ip_repr.dst_addr()),
+            // Any local address is acceptable, the subnet is only a preference.
+            ip::Source::Prefer { .. } => true,
+        };
+
+        local
+            && repr.dst_port == self.src_port
+            && ip_repr.src_addr() == self.dst_addr
+            && repr.src_port == self.dst_port
+    }
+
+    /// Receive only the datagrams of this connection with a handler.
+    pub fn recv<H>(&self, handler: H) -> Connected<'_, H> {
+        Connected {
+            connection: self,
+            handler,
+        }
+    }
+}
+
+impl<P, H> Recv<P> for Connected<'_, H>
+where
+    P: Payload,
+    H: Recv<P>,
+{
+    fn receive(&mut self, packet: Packet<P>) {
+        if !self.connection.accepts(&packet) {
+            let Packet { mut control, packet } = packet;
+            return control.inner.report_drop(DropReason::Filtered, packet.as_bytes());
+        }
+
+        self.handler.receive(packet)
+    }
+}
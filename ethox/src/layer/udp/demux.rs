@@ -0,0 +1,101 @@
+use crate::layer::{DropReason, Error, Result};
+use crate::managed::Map;
+use crate::wire::Payload;
+
+use super::{Packet, Recv};
+
+/// Dispatches received packets to handlers by their destination port.
+///
+/// Allows several independent services behind a single udp endpoint. Packets to a port without a
+/// bound handler are passed to the catch-all handler if one is set, and are dropped as
+/// `DropReason::Unbound` otherwise.
+pub struct Demux<'a, H> {
+    ports: Map<'a, u16, H>,
+    catch_all: Option<H>,
+}
+
+impl<'a, H> Demux<'a, H> {
+    /// Create a demultiplexer storing its bindings in a map.
+    ///
+    /// All ports initially in the map are bound to their handlers.
+    pub fn new(ports: Map<'a, u16, H>) -> Self {
+        Demux {
+            ports,
+            catch_all: None,
+        }
+    }
+
+    /// Bind a handler to a destination port.
+    ///
+    /// Fails with `Error::Illegal` if the port is already bound and with `Error::Exhausted` if the
+    /// map has no space for another binding.
+    pub fn bind(&mut self, port: u16, handler: H) -> Result<()> {
+        if self.ports.get(&port).is_some() {
+            return Err(Error::Illegal);
+        }
+
+        let vacant = self.ports
+            .entry(port)
+            .vacant()
+            .ok_or(Error::Exhausted)?;
+        vacant.insert(handler);
+        Ok(())
+    }
+
+    /// Remove the handler bound to a port.
+    ///
+    /// Returns whether a handler was bound.
+    pub fn unbind(&mut self, port: u16) -> bool {
+        match self.ports.entry(port).occupied() {
+            Some(occupied) => {
+                occupied.remove();
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Get the handler bound to a port.
+    pub fn get(&self, port: u16) -> Option<&H> {
+        self.ports.get(&port)
+    }
+
+    /// Get a mutable reference to the handler bound to a port.
+    pub fn get_mut(&mut self, port: u16) -> Option<&mut H> {
+        self.ports.get_mut(&port)
+    }
+
+    /// Set a handler for all packets to unbound ports.
+    ///
+    /// Replaces any previously set catch-all handler.
+    pub fn set_catch_all(&mut self, handler: H) {
+        self.catch_all = Some(handler);
+    }
+
+    /// Remove the catch-all handler, dropping packets to unbound ports again.
+    pub fn clear_catch_all(&mut self) -> Option<H> {
+        self.catch_all.take()
+    }
+}
+
+impl<P, H> Recv<P> for Demux<'_, H>
+where
+    P: Payload,
+    H: Recv<P>,
+{
+    fn receive(&mut self, packet: Packet<P>) {
+        let dst_port = packet.packet.repr().dst_port;
+        let handler = match self.ports.get_mut(&dst_port) {
+            Some(handler) => handler,
+            None => match self.catch_all.as_mut() {
+                Some(handler) => handler,
+                None => {
+                    let Packet { mut control, packet } = packet;
+                    return control.inner.report_drop(DropReason::Unbound, packet.as_bytes());
+                },
+            },
+        };
+
+        handler.receive(packet)
+    }
+}
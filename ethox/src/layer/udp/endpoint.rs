@@ -1,4 +1,4 @@
-use crate::layer::{self, FnHandler};
+use crate::layer::{self, DropReason, FnHandler, Inspect, StatefulHandler, Tee};
 use crate::managed::Slice;
 use crate::wire::{ip as ip, udp, Payload, PayloadMut};
 
@@ -108,26 +108,28 @@ where
     P: Payload,
     H: Recv<P>,
 {
-    fn receive(&mut self, layer::ip::InPacket { control, packet }: layer::ip::InPacket<P>) {
+    fn receive(&mut self, layer::ip::InPacket { mut control, packet }: layer::ip::InPacket<P>) {
         let capabilities = control.info().capabilities();
         let checksum = capabilities.udp().rx_checksum(packet.repr());
 
-        let packet = match packet.repr().protocol() {
+        let repr = match packet.repr().protocol() {
             ip::Protocol::Udp => {
-                match udp::Packet::new_checked(packet, checksum) {
-                    Ok(packet) => packet,
-                    Err(_) => return,
+                match udp::Packet::new_checked(&packet, checksum) {
+                    Ok(packet) => packet.repr(),
+                    Err(err) => return control.report_drop(err.into(), packet.payload().as_slice()),
                 }
             },
-            _ => return,
+            _ => return control.report_drop(DropReason::Unsupported, packet.payload().as_slice()),
         };
 
-        if !self.endpoint.inner.accepts(packet.repr().dst_port) {
+        if !self.endpoint.inner.accepts(repr.dst_port) {
             // FIXME: we might send ICMP unreachable but may want to have a silent configuration
             // that does not.
-            return
+            return control.report_drop(DropReason::Unbound, packet.payload().as_slice());
         }
 
+        let packet = udp::Packet::new_unchecked(packet, repr);
+
         let control = Controller { inner: control };
         let packet = Packet { control, packet };
         self.handler.receive(packet);
@@ -163,3 +165,30 @@ impl<P: Payload, F> Send<P> for FnHandler<F>
         self.0(frame)
     }
 }
+
+impl<P: Payload, S, F> Recv<P> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, Packet<P>)
+{
+    fn receive(&mut self, frame: Packet<P>) {
+        (self.handler)(&mut self.state, frame)
+    }
+}
+
+impl<P: Payload, S, F> Send<P> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, RawPacket<P>)
+{
+    fn send(&mut self, frame: RawPacket<P>) {
+        (self.handler)(&mut self.state, frame)
+    }
+}
+
+impl<P, A, B> Recv<P> for Tee<A, B>
+where
+    P: Payload,
+    A: for<'a> Inspect<Packet<'a, P>>,
+    B: for<'a> Inspect<Packet<'a, P>>,
+{
+    fn receive(&mut self, frame: Packet<P>) {
+        self.inspect(&frame)
+    }
+}
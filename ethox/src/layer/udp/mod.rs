@@ -5,13 +5,25 @@
 //! possible to respond dynamically at any port without settting up logic prior to a packet
 //! arriving (e.g. dynamic port knocking) but also simplifies implementation by enforcing clear cut
 //! separation of concerns.
+//!
+//! A [`Demux`] is one such routing layer, dispatching packets to separate handlers by port. A
+//! [`Connection`] remembers a single remote peer, similar to a connected socket.
+//!
+//! [`Connection`]: struct.Connection.html
+//! [`Demux`]: struct.Demux.html
 use crate::wire::Payload;
 
+mod connection;
+mod demux;
 mod endpoint;
 mod packet;
 #[cfg(test)]
 mod tests;
 
+pub use connection::{Connected, Connection};
+
+pub use demux::Demux;
+
 pub use endpoint::{
     Endpoint,
     Receiver,
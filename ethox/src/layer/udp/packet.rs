@@ -3,7 +3,7 @@ use core::convert::TryFrom;
 use crate::nic::Info;
 use crate::layer::{Error, Result, ip};
 use crate::wire::{Payload, PayloadMut};
-use crate::wire::{udp, ip::Address, ip::Protocol};
+use crate::wire::{udp, ip::Address, ip::Protocol, ip::Repr as IpRepr};
 
 /// An incoming UDP packet.
 pub struct Packet<'a, P: Payload> {
@@ -53,6 +53,8 @@ pub struct Controller<'a> {
 ///         dst_addr: Address::v4(192, 168, 0, 1),
 ///         dst_port: 43,
 ///         payload: HELLO.len(),
+///         fragmentation: ip::Fragmentation::default(),
+///         hop_limit: None,
 ///     };
 ///
 ///     let mut out = raw.prepare(init)?;
@@ -74,6 +76,17 @@ pub struct Init {
     pub dst_port: u16,
     /// The length of the payload which is sent.
     pub payload: usize,
+    /// Whether routers may fragment the datagram, passed to the ip layer below.
+    ///
+    /// The default forbids fragmentation, as in all other ip packets sent by the library.
+    /// Protocols performing path MTU discovery on their own rely on this.
+    pub fragmentation: ip::Fragmentation,
+    /// The hop limit of the datagram, or `None` for the default of the ip endpoint.
+    ///
+    /// Datagrams to multicast groups default to a hop limit of 1 instead, see [`ip::Init`].
+    ///
+    /// [`ip::Init`]: ../ip/struct.Init.html#structfield.hop_limit
+    pub hop_limit: Option<u8>,
 }
 
 impl<'a> Controller<'a> {
@@ -92,12 +105,123 @@ impl<'a> Controller<'a> {
 
 impl<'a, P: Payload> Packet<'a, P> {
     /// Reinitialize the buffer with a packet generated by the library.
+    ///
+    /// If the new headers have the same length as the current ones, for example when answering
+    /// with a payload of the same length, the packet is updated in place. Only the header fields
+    /// are rewritten and the payload is left untouched. Otherwise the buffer is prepared anew.
+    ///
+    /// The checksum is cleared and computed over the payload by `send`, as the payload may be
+    /// modified in between. Answers with the unchanged payload should use [`reflect`] instead,
+    /// which updates the checksum incrementally without rescanning the payload.
+    ///
+    /// [`reflect`]: #method.reflect
     pub fn reinit(self, init: Init) -> Result<Packet<'a, P>>
         where P: PayloadMut
     {
-        // TODO: optimize this? If the previous headers have correct sizes, do not overwrite the
-        // contents of the packet and sparsely update fields.
-        self.deinit().prepare(init)
+        if !self.same_layout(&init) {
+            return self.deinit().prepare(init);
+        }
+
+        self.rewrite(init, false)
+    }
+
+    /// Rewrite the headers in place, the layout must stay the same.
+    ///
+    /// With `incremental` the checksum is updated to the changed ports and addresses, otherwise
+    /// it is cleared.
+    fn rewrite(self, init: Init, incremental: bool) -> Result<Packet<'a, P>>
+        where P: PayloadMut
+    {
+        let length = self.packet.repr().length;
+        let old_repr = self.packet.get_ref().repr();
+        let lower = ip::InPacket {
+            control: self.control.inner,
+            packet: self.packet.into_inner(),
+        };
+
+        let prepared = lower.reinit(init.ip_init()?)?;
+        let ip::InPacket { control, mut packet } = prepared.into_incoming();
+        packet.set_fragmentation(init.fragmentation);
+
+        // The length is unchanged, only the ports need to be updated.
+        let repr = udp::Repr {
+            src_port: init.src_port,
+            dst_port: init.dst_port,
+            length,
+        };
+        let new_repr = packet.repr();
+        let header = udp::packet::new_unchecked_mut(packet.payload_mut().as_mut_slice());
+        header.update_ports(repr.src_port, repr.dst_port);
+        if incremental {
+            header.update_pseudo_header(
+                (old_repr.src_addr(), old_repr.dst_addr()),
+                (new_repr.src_addr(), new_repr.dst_addr()));
+        } else {
+            header.set_checksum(0);
+        }
+
+        Ok(Packet {
+            control: Controller { inner: control },
+            packet: udp::Packet::new_unchecked(packet, repr),
+        })
+    }
+
+    /// Answer with the unchanged payload, for example to echo a request.
+    ///
+    /// The packet is reinitialized in place and sent. Its checksum is only updated incrementally
+    /// for the changed ports and addresses, without reading the payload. This requires a checksum
+    /// that was valid when received. A packet whose sender did not compute a checksum gets a full
+    /// one if required.
+    ///
+    /// Returns `BadSize` without sending if the headers for `init` do not have the same length as
+    /// the current ones, or if it describes a payload of different length.
+    pub fn reflect(self, init: Init) -> Result<()>
+        where P: PayloadMut
+    {
+        if !self.same_layout(&init) {
+            return Err(Error::BadSize);
+        }
+
+        let received = udp::packet::new_unchecked(self.packet.get_ref().payload().as_slice())
+            .checksum();
+        let mut packet = self.rewrite(init, true)?;
+
+        if received == 0 {
+            let capabilities = packet.control.info().capabilities();
+            let ip_repr = packet.packet.get_ref().repr();
+            let checksum = capabilities.udp().tx_checksum(ip_repr);
+            packet.packet.fill_checksum(checksum);
+        }
+
+        let lower = ip::OutPacket::new_unchecked(
+            packet.control.inner,
+            packet.packet.into_inner());
+        lower.send()
+    }
+
+    /// Check if the headers for `init` have the same length as the current headers.
+    fn same_layout(&self, init: &Init) -> bool {
+        let ip_repr = self.packet.get_ref().repr();
+        let udp_len = usize::from(self.packet.repr().length);
+        let (header_len, frame_len) = match self.packet.get_ref() {
+            ip::IpPacket::V4(packet) => (
+                usize::from(packet.header_len()),
+                packet.get_ref().payload_slice().len(),
+            ),
+            ip::IpPacket::V6(packet) => (
+                ip_repr.buffer_len(),
+                packet.get_ref().payload_slice().len(),
+            ),
+        };
+        let same_family = matches!((ip_repr.dst_addr(), init.dst_addr),
+            (Address::Ipv4(_), Address::Ipv4(_)) | (Address::Ipv6(_), Address::Ipv6(_)));
+
+        // Options and padding of the frame would not be preserved.
+        same_family
+            && header_len == ip_repr.buffer_len()
+            && ip_repr.payload_len() == udp_len
+            && frame_len == header_len + udp_len
+            && init.payload.checked_add(8) == Some(udp_len)
     }
 
     /// Get the hardware info for that packet.
@@ -105,6 +229,43 @@ impl<'a, P: Payload> Packet<'a, P> {
         self.control.info()
     }
 
+    /// The parsed representation of the enclosing ip packet.
+    pub fn ip_repr(&self) -> IpRepr {
+        self.packet.get_ref().repr()
+    }
+
+    /// The parsed representation of the udp header.
+    pub fn repr(&self) -> udp::Repr {
+        self.packet.repr()
+    }
+
+    /// The address of the sender of the datagram.
+    ///
+    /// Together with [`src_port`] this is where a reply should be sent to.
+    ///
+    /// [`src_port`]: #method.src_port
+    pub fn source_addr(&self) -> Address {
+        self.ip_repr().src_addr()
+    }
+
+    /// The address to which the datagram was sent.
+    ///
+    /// This is not necessarily a local address, it might as well be a broadcast address or a
+    /// multicast group.
+    pub fn dest_addr(&self) -> Address {
+        self.ip_repr().dst_addr()
+    }
+
+    /// The port of the sender of the datagram.
+    pub fn src_port(&self) -> u16 {
+        self.packet.repr().src_port
+    }
+
+    /// The port to which the datagram was sent.
+    pub fn dst_port(&self) -> u16 {
+        self.packet.repr().dst_port
+    }
+
     /// Unwrap the raw packet buffer.
     ///
     /// This does not modify the contents of the buffer but it will drop the state derived from
@@ -141,6 +302,17 @@ impl<'a, P: Payload + PayloadMut> RawPacket<'a, P> {
         self.control.info()
     }
 
+    /// Use the buffer for a packet of another ip protocol.
+    ///
+    /// Together with `ip::RawPacket::into_eth` this allows sending arbitrary ethernet frames from
+    /// a udp sender, for example for a custom link layer discovery protocol.
+    pub fn into_ip(self) -> ip::RawPacket<'a, P> {
+        ip::RawPacket {
+            control: self.control.inner,
+            payload: self.payload,
+        }
+    }
+
     /// Initialize to a valid ip packet.
     pub fn prepare(self, init: Init) -> Result<Packet<'a, P>> {
         let lower = ip::RawPacket {
@@ -148,19 +320,9 @@ impl<'a, P: Payload + PayloadMut> RawPacket<'a, P> {
             payload: self.payload,
         };
 
-        let packet_len = init.payload
-            .checked_add(8)
-            .ok_or(Error::BadSize)?;
-
-        let lower_init = ip::Init {
-            source: init.source,
-            dst_addr: init.dst_addr,
-            protocol: Protocol::Udp,
-            payload: packet_len,
-        };
-
-        let prepared = lower.prepare(lower_init)?;
+        let prepared = lower.prepare(init.ip_init()?)?;
         let ip::InPacket { control, mut packet } = prepared.into_incoming();
+        packet.set_fragmentation(init.fragmentation);
         let repr = init.initialize(&mut packet)?;
 
         // Reconstruct the control.
@@ -174,13 +336,35 @@ impl<'a, P: Payload + PayloadMut> RawPacket<'a, P> {
 }
 
 impl Init {
+    /// The initializer of the ip packet containing the udp packet.
+    fn ip_init(&self) -> Result<ip::Init> {
+        let packet_len = self.packet_len()?;
+
+        Ok(ip::Init {
+            source: self.source,
+            dst_addr: self.dst_addr,
+            protocol: Protocol::Udp,
+            payload: packet_len.into(),
+            hop_limit: self.hop_limit,
+            traffic_class: 0,
+        })
+    }
+
+    /// The length of the udp packet, which must fit the 16-bit length field of the header.
+    ///
+    /// An empty payload results in a packet consisting of only the 8 byte header.
+    fn packet_len(&self) -> Result<u16> {
+        self.payload
+            .checked_add(8)
+            .and_then(|len| u16::try_from(len).ok())
+            .ok_or(Error::BadSize)
+    }
+
     fn initialize(&self, payload: &mut impl PayloadMut) -> Result<udp::Repr> {
         let repr = udp::Repr {
             src_port: self.src_port,
             dst_port: self.dst_port,
-            // Can't overflow, already inited ip with that length.
-            length: u16::try_from(self.payload + 8)
-                .map_err(|_| Error::BadSize)?,
+            length: self.packet_len()?,
         };
 
         // Assumes length was already dealt with.
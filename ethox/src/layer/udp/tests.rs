@@ -1,8 +1,9 @@
 use crate::managed::Slice;
-use crate::nic::{external::External, Device};
-use crate::layer::{arp, eth, ip, udp};
+use crate::nic::{self, external::{self, External}, loopback::Loopback, Device};
+use crate::layer::{arp, eth, ip, udp, FnHandler, StatefulHandler, Tee};
+use crate::time::Instant;
 use crate::wire::{ethernet, Payload, PayloadMut};
-use crate::wire::ip::{v4, Cidr, Subnet};
+use crate::wire::ip::{v4, v6, Cidr, Subnet};
 
 const MAC_ADDR_SRC: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
 const IP_ADDR_SRC: v4::Address = v4::Address::new(127, 0, 0, 1);
@@ -25,6 +26,8 @@ fn simple_send<P: PayloadMut>(frame: udp::RawPacket<P>) {
         dst_addr: IP_ADDR_DST.into(),
         dst_port: 80,
         payload: PAYLOAD_BYTES.len(),
+        fragmentation: ip::Fragmentation::default(),
+        hop_limit: None,
     };
     let mut prepared = frame.prepare(init)
         .expect("Found no valid routes");
@@ -83,3 +86,1227 @@ fn simple() {
         udp.recv_with(simple_recv))));
    assert_eq!(recv, Ok(1)); 
 }
+
+#[test]
+fn four_tuple() {
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        neighbors);
+    let mut udp = udp::Endpoint::new(80);
+
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+        let init = udp::Init {
+            source: ip::Source::Exact(IP_ADDR_SRC.into()),
+            src_port: 4242,
+            dst_addr: IP_ADDR_DST.into(),
+            dst_port: 80,
+            payload: PAYLOAD_BYTES.len(),
+            fragmentation: ip::Fragmentation::default(),
+            hop_limit: None,
+        };
+        let mut prepared = raw.prepare(init).expect("Found no valid routes");
+        prepared.packet.payload_mut().copy_from_slice(&PAYLOAD_BYTES[..]);
+        prepared.send().expect("Could egress packet");
+    }))));
+    assert_eq!(sent, Ok(1));
+
+    {
+        // Retarget the packet to self, the udp checksum stays valid.
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(MAC_ADDR_SRC);
+        eth.set_src_addr(MAC_ADDR_DST);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(IP_ADDR_SRC);
+        ip.set_src_addr(IP_ADDR_DST);
+        ip.fill_checksum();
+    }
+
+    nic.receive_all();
+
+    let mut tuple = None;
+    let recv = nic.rx(1, eth.recv(ip.recv(udp.recv_with(|packet: udp::Packet<_>| {
+        assert_eq!(packet.ip_repr().protocol(), crate::wire::ip::Protocol::Udp);
+        assert_eq!(usize::from(packet.repr().length), 8 + PAYLOAD_BYTES.len());
+        let source = (packet.source_addr(), packet.src_port());
+        tuple = Some((source.0, source.1, packet.dest_addr(), packet.dst_port()));
+    }))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(tuple, Some((IP_ADDR_DST.into(), 4242, IP_ADDR_SRC.into(), 80)));
+}
+
+#[test]
+fn checksum_offload() {
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let mut routes = [ip::Route::unspecified(); 2];
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut routes[..]),
+        {
+            let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+            eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+            eth_cache
+        });
+    let mut udp = udp::Endpoint::new(80);
+
+    let mut send = |capabilities: Option<nic::Capabilities>| {
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        if let Some(capabilities) = capabilities {
+            nic.set_capabilities(capabilities);
+        }
+        assert_eq!(nic.personality().capabilities().ipv4().tx_checksum().manual(),
+            capabilities.is_none());
+        let sent = nic.tx(1, eth.send(ip.send(udp.send_with(simple_send))));
+        assert_eq!(sent, Ok(1));
+        nic.get(0).unwrap().clone()
+    };
+
+    // The default computes the header checksum in software.
+    let software = send(None);
+    let frame = ethernet::frame::new_checked(&software[..]).unwrap();
+    let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+    assert!(packet.verify_checksum());
+    assert_ne!(packet.checksum(), 0);
+
+    // The offloaded checksum is left zeroed for the nic.
+    let offloaded = send(Some(nic::Capabilities::offloaded()));
+    let frame = ethernet::frame::new_checked(&offloaded[..]).unwrap();
+    let packet = v4::packet::new_checked(frame.payload_slice()).unwrap();
+    assert_eq!(packet.checksum(), 0);
+
+    // Otherwise the frames are identical.
+    let checksum = ethernet::frame::header_len() + 10;
+    assert_eq!(software[..checksum], offloaded[..checksum]);
+    assert_eq!(software[checksum + 2..], offloaded[checksum + 2..]);
+
+    // The datagram checksum is optional over IPv4 and left out either way.
+    let udp = crate::wire::udp::packet::new_checked(packet.payload_slice()).unwrap();
+    assert_eq!(udp.checksum(), 0);
+
+    // Over IPv6 it is mandatory, and computed unless offloaded.
+    const IP6_ADDR_SRC: v6::Address = v6::Address(
+        [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    const IP6_ADDR_DST: v6::Address = v6::Address(
+        [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP6_ADDR_SRC.into(), 64),
+        ip::Routes::new(Slice::empty()),
+        {
+            let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+            eth_cache.fill(IP6_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+            eth_cache
+        });
+    let mut udp = udp::Endpoint::new(80);
+
+    for offloaded in [false, true] {
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        if offloaded {
+            nic.set_capabilities(nic::Capabilities::offloaded());
+        }
+        let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+            let init = udp::Init {
+                source: Subnet::from(v6::Subnet::ANY).into(),
+                src_port: 80,
+                dst_addr: IP6_ADDR_DST.into(),
+                dst_port: 80,
+                payload: PAYLOAD_BYTES.len(),
+                fragmentation: ip::Fragmentation::default(),
+                hop_limit: None,
+            };
+            let mut prepared = raw.prepare(init).unwrap();
+            prepared.packet.payload_mut().copy_from_slice(&PAYLOAD_BYTES[..]);
+            prepared.send().unwrap();
+        }))));
+        assert_eq!(sent, Ok(1));
+
+        let frame = ethernet::frame::new_checked(&nic.get(0).unwrap()[..]).unwrap();
+        let packet = v6::packet::new_checked(frame.payload_slice()).unwrap();
+        let udp = crate::wire::udp::packet::new_checked(packet.payload_slice()).unwrap();
+        let valid = udp.verify_checksum(IP6_ADDR_SRC.into(), IP6_ADDR_DST.into());
+        assert_eq!(valid, !offloaded);
+        assert_eq!(udp.checksum() == 0, offloaded);
+    }
+}
+
+#[test]
+fn oversized() {
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    // Room for the headers and the payload but not more.
+    let mut nic = External::new_send(Slice::One(vec![0xa5; 14 + 20 + 8 + PAYLOAD_BYTES.len()]));
+    assert_eq!(nic.personality().mtu(), Some(20 + 8 + PAYLOAD_BYTES.len()));
+
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+        let init = udp::Init {
+            source: Subnet::from(v4::Subnet::ANY).into(),
+            src_port: 80,
+            dst_addr: IP_ADDR_DST.into(),
+            dst_port: 80,
+            payload: PAYLOAD_BYTES.len() + 1,
+            fragmentation: ip::Fragmentation::default(),
+            hop_limit: None,
+        };
+        assert_eq!(raw.prepare(init).err(), Some(crate::layer::Error::BadSize));
+    }))));
+    assert_eq!(sent, Ok(0));
+    assert!(nic.get(0).unwrap().iter().all(|&byte| byte == 0xa5));
+
+    // A payload that fits is still sent.
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(simple_send))));
+    assert_eq!(sent, Ok(1));
+}
+
+#[test]
+fn payload_bounds() {
+    const IP6_ADDR_SRC: v6::Address = v6::Address(
+        [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    const IP6_ADDR_DST: v6::Address = v6::Address(
+        [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+    const MAX_PAYLOAD: usize = 65527;
+
+    fn send_len<P: PayloadMut>(payload: usize, expected: crate::layer::Result<()>)
+        -> impl FnMut(udp::RawPacket<P>)
+    {
+        move |raw: udp::RawPacket<P>| {
+            let init = udp::Init {
+                source: Subnet::from(v6::Subnet::ANY).into(),
+                src_port: 80,
+                dst_addr: IP6_ADDR_DST.into(),
+                dst_port: 80,
+                payload,
+                fragmentation: ip::Fragmentation::default(),
+                hop_limit: None,
+            };
+            let result = raw.prepare(init).and_then(udp::Packet::send);
+            assert_eq!(result, expected);
+        }
+    }
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP6_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP6_ADDR_SRC.into(), 64),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    // Room for the largest possible datagram, so only the length field limits it.
+    let mut nic = External::new_send(Slice::One(vec![0; 14 + 40 + 8 + MAX_PAYLOAD + 1]));
+
+    let udp_length = |buffer: &[u8]| {
+        let frame = ethernet::frame::new_checked(buffer).unwrap();
+        let packet = v6::packet::new_checked(frame.payload_slice()).unwrap();
+        assert_eq!(usize::from(packet.payload_len()), packet.payload_slice().len());
+        crate::wire::udp::packet::new_checked(packet.payload_slice()).unwrap().len()
+    };
+
+    // An empty datagram consists of only the header.
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(send_len(0, Ok(()))))));
+    assert_eq!(sent, Ok(1));
+    assert_eq!(udp_length(nic.get(0).unwrap()), 8);
+
+    nic.reset_send();
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(send_len(MAX_PAYLOAD, Ok(()))))));
+    assert_eq!(sent, Ok(1));
+    assert_eq!(udp_length(nic.get(0).unwrap()), u16::MAX);
+
+    // One more byte does not fit the length field and must not wrap around.
+    let too_long = Err(crate::layer::Error::BadSize);
+    nic.reset_send();
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(send_len(MAX_PAYLOAD + 1, too_long)))));
+    assert_eq!(sent, Ok(0));
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(send_len(usize::MAX, too_long)))));
+    assert_eq!(sent, Ok(0));
+}
+
+#[test]
+fn hardware_timestamp() {
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    let sent = nic.tx(1, eth.send(ip.send(
+        udp.send_with(|packet: udp::RawPacket<_>| {
+            assert_eq!(packet.info().hardware_timestamp(), None);
+            simple_send(packet)
+        }))));
+    assert_eq!(sent, Ok(1));
+
+    {
+        // Retarget the packet to self.
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(MAC_ADDR_SRC);
+        eth.set_src_addr(MAC_ADDR_DST);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(IP_ADDR_SRC);
+        ip.set_src_addr(IP_ADDR_DST);
+        ip.fill_checksum();
+    }
+
+    nic.receive_all();
+    nic.set_hardware_timestamp(Some(Instant::from_millis(42)));
+
+    let mut arrival = None;
+    let recv = nic.rx(1, eth.recv(ip.recv(
+        udp.recv_with(|packet: udp::Packet<_>| {
+            arrival = packet.control.info().hardware_timestamp();
+        }))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(arrival, Some(Instant::from_millis(42)));
+}
+
+#[test]
+fn unbound_port() {
+    use crate::layer::DropReason;
+
+    let dropped = core::cell::Cell::new(None);
+    let mut hook = |reason, bytes: &[u8]| dropped.set(Some((reason, bytes.len())));
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+    eth.set_drop_hook(&mut hook);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    let sent = nic.tx(1, eth.send(ip.send(
+        udp.send_with(simple_send))));
+    assert_eq!(sent, Ok(1));
+
+    {
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(MAC_ADDR_SRC);
+        eth.set_src_addr(MAC_ADDR_DST);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(IP_ADDR_SRC);
+        ip.set_src_addr(IP_ADDR_DST);
+        ip.fill_checksum();
+    }
+
+    nic.receive_all();
+
+    // Nothing is listening on port 80 anymore.
+    let mut udp = udp::Endpoint::new(8080);
+    let recv = nic.rx(1, eth.recv(ip.recv(
+        udp.recv_with(|_: udp::Packet<_>| panic!("Packet to unbound port delivered")))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(dropped.get(), Some((DropReason::Unbound, 8 + PAYLOAD_BYTES.len())));
+}
+
+#[test]
+fn raw_frame() {
+    const ETHERTYPE: ethernet::EtherType = ethernet::EtherType::Unknown(0xBEEF);
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = arp::NeighborCache::new(&mut neighbors[..]);
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+        raw.into_ip()
+            .into_eth()
+            .send_frame(MAC_ADDR_SRC, ETHERTYPE, &PAYLOAD_BYTES[..])
+            .expect("Can send a raw frame");
+    }))));
+    assert_eq!(sent, Ok(1));
+
+    nic.receive_all();
+
+    let mut received = false;
+    let recv = nic.rx(1, eth.recv_with(|frame: eth::InPacket<_>| {
+        assert_eq!(frame.frame.repr().src_addr, MAC_ADDR_SRC);
+        assert_eq!(frame.frame.repr().ethertype, ETHERTYPE);
+        assert_eq!(frame.frame.payload().as_slice(), &PAYLOAD_BYTES[..]);
+        received = true;
+    }));
+    assert_eq!(recv, Ok(1));
+    assert!(received);
+}
+
+#[test]
+fn fire_and_reclaim() {
+    let mut pool = vec![vec![0; 1024]];
+    let address = pool[0].as_ptr();
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    for _ in 0..3 {
+        let buffer = pool.pop().expect("Buffer was reclaimed");
+        let sent = external::fire(buffer, eth.send(ip.send(udp.send_with(simple_send))),
+            |buffer| pool.push(buffer));
+        assert_eq!(sent, Ok(1));
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool[0].as_ptr(), address);
+
+        let frame = ethernet::frame::new_checked(&pool[0][..]).unwrap();
+        let ip = v4::packet::new_checked(frame.payload_slice()).unwrap();
+        assert_eq!(&ip.payload_slice()[8..], &PAYLOAD_BYTES[..]);
+    }
+
+    // The buffer is returned even if nothing was sent.
+    let buffer = pool.pop().unwrap();
+    let sent = external::fire(buffer, eth.send(ip.send(udp.send_with(|_: udp::RawPacket<_>| ()))),
+        |buffer| pool.push(buffer));
+    assert_eq!(sent, Ok(0));
+    assert_eq!(pool.len(), 1);
+}
+
+#[test]
+fn tee_sniffer() {
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    let sent = nic.tx(1, eth.send(ip.send(
+        udp.send_with(simple_send))));
+    assert_eq!(sent, Ok(1));
+
+    {
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(MAC_ADDR_SRC);
+        eth.set_src_addr(MAC_ADDR_DST);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(IP_ADDR_SRC);
+        ip.set_src_addr(IP_ADDR_DST);
+        ip.fill_checksum();
+    }
+
+    nic.receive_all();
+
+    let mut delivered = None;
+    let sniff = |sniffed: &mut Option<Vec<u8>>, _: &dyn nic::Info, payload: &Vec<u8>| {
+        let frame = ethernet::frame::new_checked(&payload[..]).unwrap();
+        let ip = v4::packet::new_checked(frame.payload_slice()).unwrap();
+        *sniffed = Some(ip.payload_slice()[8..].to_vec());
+    };
+    let mut sniffer = StatefulHandler::new(None, sniff);
+
+    let recv = nic.rx(1, nic::recv_tee(&mut sniffer, eth.recv(ip.recv(
+        udp.recv_with(|packet: udp::Packet<_>| {
+            delivered = Some(packet.packet.payload_slice().to_vec());
+        })))));
+    assert_eq!(recv, Ok(1));
+
+    let sniffed = sniffer.into_state();
+    assert_eq!(sniffed.as_ref().map(Vec::as_slice), Some(&PAYLOAD_BYTES[..]));
+    assert_eq!(sniffed, delivered);
+}
+
+#[test]
+fn tee_handlers() {
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        neighbors);
+    let mut udp = udp::Endpoint::new(80);
+
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(simple_send))));
+    assert_eq!(sent, Ok(1));
+
+    {
+        // Retarget the packet to self.
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(MAC_ADDR_SRC);
+        eth.set_src_addr(MAC_ADDR_DST);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        ip.set_dst_addr(IP_ADDR_SRC);
+        ip.set_src_addr(IP_ADDR_DST);
+        ip.fill_checksum();
+    }
+
+    nic.receive_all();
+
+    let mut counter = StatefulHandler::new(0, |count: &mut usize, _: &udp::Packet<_>| *count += 1);
+    let mut validated = None;
+    let validator = FnHandler(|packet: &udp::Packet<_>| {
+        validated = Some(packet.packet.payload_slice() == &PAYLOAD_BYTES[..]);
+    });
+
+    let recv = nic.rx(1, eth.recv(ip.recv(udp.recv(Tee::new(&mut counter, validator)))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(counter.into_state(), 1);
+    assert_eq!(validated, Some(true));
+}
+
+#[test]
+fn fragmentation_policy() {
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    let mut send = |fragmentation, payload| {
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let mut result = None;
+        let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+            let init = udp::Init {
+                source: Subnet::from(v4::Subnet::ANY).into(),
+                src_port: 80,
+                dst_addr: IP_ADDR_DST.into(),
+                dst_port: 80,
+                payload,
+                fragmentation,
+                hop_limit: None,
+            };
+            result = Some(raw.prepare(init).and_then(udp::Packet::send));
+        }))));
+        let result = result.unwrap();
+        assert_eq!(sent, Ok(if result.is_ok() { 1 } else { 0 }));
+
+        result.map(|()| {
+            let buffer = nic.get_mut(0).unwrap();
+            let eth = ethernet::frame::new_unchecked_mut(buffer);
+            let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+            assert!(ip.verify_checksum());
+            ip.dont_frag()
+        })
+    };
+
+    assert_eq!(send(ip::Fragmentation::default(), PAYLOAD_BYTES.len()), Ok(true));
+    assert_eq!(send(ip::Fragmentation::Dont, PAYLOAD_BYTES.len()), Ok(true));
+    assert_eq!(send(ip::Fragmentation::Allow, PAYLOAD_BYTES.len()), Ok(false));
+
+    // The largest datagram for the mtu of the buffer, after the ethernet, ip and udp headers.
+    let largest = 1024 - 14 - 20 - 8;
+    assert_eq!(send(ip::Fragmentation::Dont, largest), Ok(true));
+    assert_eq!(send(ip::Fragmentation::Allow, largest), Ok(false));
+
+    // Larger datagrams are refused under both policies, the ip layer does not fragment them.
+    let bad_size = Err(crate::layer::Error::BadSize);
+    assert_eq!(send(ip::Fragmentation::Dont, largest + 1), bad_size);
+    assert_eq!(send(ip::Fragmentation::Allow, largest + 1), bad_size);
+}
+
+#[test]
+fn hop_limit() {
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+    ip.set_hop_limit(64);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    let mut send = |hop_limit| {
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+            let init = udp::Init {
+                source: Subnet::from(v4::Subnet::ANY).into(),
+                src_port: 80,
+                dst_addr: IP_ADDR_DST.into(),
+                dst_port: 80,
+                payload: PAYLOAD_BYTES.len(),
+                fragmentation: ip::Fragmentation::default(),
+                hop_limit,
+            };
+            raw.prepare(init)
+                .and_then(udp::Packet::send)
+                .expect("Can send the datagram");
+        }))));
+        assert_eq!(sent, Ok(1));
+
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+        assert!(ip.verify_checksum());
+        ip.hop_limit()
+    };
+
+    assert_eq!(send(Some(1)), 1);
+    assert_eq!(send(None), 64);
+}
+
+#[test]
+fn padding() {
+    const SHORT: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+        let init = udp::Init {
+            source: Subnet::from(v4::Subnet::ANY).into(),
+            src_port: 80,
+            dst_addr: IP_ADDR_DST.into(),
+            dst_port: 80,
+            payload: SHORT.len(),
+            fragmentation: ip::Fragmentation::default(),
+            hop_limit: None,
+        };
+        let mut prepared = raw.prepare(init).expect("Can prepare the datagram");
+        prepared.packet.payload_mut_slice().copy_from_slice(&SHORT[..]);
+        prepared.send().expect("Can send the datagram");
+    }))));
+    assert_eq!(sent, Ok(1));
+
+    {
+        // Padded to the minimum frame length, the length fields are unchanged.
+        let buffer = nic.get_mut(0).unwrap();
+        assert_eq!(buffer.len(), ethernet::MIN_FRAME_LEN);
+        assert!(buffer[14 + 20 + 8 + SHORT.len()..].iter().all(|&byte| byte == 0));
+
+        let eth = ethernet::frame::new_unchecked_mut(buffer);
+        eth.set_dst_addr(MAC_ADDR_SRC);
+        eth.set_src_addr(MAC_ADDR_DST);
+        let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+        assert_eq!(usize::from(ip.total_len()), 20 + 8 + SHORT.len());
+        ip.set_dst_addr(IP_ADDR_SRC);
+        ip.set_src_addr(IP_ADDR_DST);
+        ip.fill_checksum();
+        let udp = crate::wire::udp::packet::new_unchecked(ip.payload_slice());
+        assert_eq!(usize::from(udp.len()), 8 + SHORT.len());
+    }
+
+    // The receiver does not regard the padding as payload.
+    nic.receive_all();
+    let mut received = None;
+    let recv = nic.rx(1, eth.recv(ip.recv(udp.recv_with(|packet: udp::Packet<_>| {
+        received = Some(packet.packet.payload().as_slice().to_vec());
+    }))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(received.as_deref(), Some(&SHORT[..]));
+}
+
+#[test]
+fn demux() {
+    use crate::layer::DropReason;
+    use crate::managed::{List, Map};
+
+    #[derive(Clone, Default)]
+    struct Count(usize);
+
+    impl<P: Payload> udp::Recv<P> for Count {
+        fn receive(&mut self, packet: udp::Packet<P>) {
+            assert_eq!(packet.packet.payload().as_slice(), &PAYLOAD_BYTES[..]);
+            self.0 += 1;
+        }
+    }
+
+    let dropped = core::cell::Cell::new(None);
+    let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+    eth.set_drop_hook(&mut hook);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new_unfiltered();
+
+    let mut demux = udp::Demux::new(Map::Pairs(List::new(Slice::Many(vec![Default::default(); 2]))));
+    assert_eq!(demux.bind(53, Count::default()), Ok(()));
+    assert_eq!(demux.bind(67, Count::default()), Ok(()));
+    assert_eq!(demux.bind(53, Count::default()), Err(crate::layer::Error::Illegal));
+    assert_eq!(demux.bind(68, Count::default()), Err(crate::layer::Error::Exhausted));
+
+    // Send a packet to ourselves and receive it with the demultiplexer.
+    let mut deliver = |dst_port| {
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+            let init = udp::Init {
+                source: Subnet::from(v4::Subnet::ANY).into(),
+                src_port: 1024,
+                dst_addr: IP_ADDR_DST.into(),
+                dst_port,
+                payload: PAYLOAD_BYTES.len(),
+                fragmentation: ip::Fragmentation::default(),
+                hop_limit: None,
+            };
+            let mut prepared = raw.prepare(init).unwrap();
+            prepared.packet.payload_mut().copy_from_slice(&PAYLOAD_BYTES[..]);
+            prepared.send().unwrap();
+        }))));
+        assert_eq!(sent, Ok(1));
+
+        {
+            let buffer = nic.get_mut(0).unwrap();
+            let eth = ethernet::frame::new_unchecked_mut(buffer);
+            eth.set_dst_addr(MAC_ADDR_SRC);
+            eth.set_src_addr(MAC_ADDR_DST);
+            let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+            ip.set_dst_addr(IP_ADDR_SRC);
+            ip.set_src_addr(IP_ADDR_DST);
+            ip.fill_checksum();
+        }
+
+        nic.receive_all();
+        let recv = nic.rx(1, eth.recv(ip.recv(udp.recv(&mut demux))));
+        assert_eq!(recv, Ok(1));
+    };
+
+    deliver(53);
+    deliver(53);
+    deliver(67);
+    deliver(99);
+
+    assert_eq!(demux.get(53).map(|count| count.0), Some(2));
+    assert_eq!(demux.get(67).map(|count| count.0), Some(1));
+    assert_eq!(dropped.get(), Some(DropReason::Unbound));
+
+    // The port is free again after unbinding.
+    assert!(demux.unbind(53));
+    assert!(!demux.unbind(53));
+    assert!(demux.get(53).is_none());
+    assert_eq!(demux.bind(99, Count::default()), Ok(()));
+}
+
+#[test]
+fn checksum() {
+    use crate::layer::DropReason;
+
+    let dropped = core::cell::Cell::new(None);
+    let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+    eth.set_drop_hook(&mut hook);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    // Send a packet to ourselves, with a modified udp header, and check if it is delivered.
+    let mut deliver = |modify: &dyn Fn(&mut crate::wire::udp::packet)| {
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let sent = nic.tx(1, eth.send(ip.send(udp.send_with(simple_send))));
+        assert_eq!(sent, Ok(1));
+
+        {
+            let buffer = nic.get_mut(0).unwrap();
+            let eth = ethernet::frame::new_unchecked_mut(buffer);
+            eth.set_dst_addr(MAC_ADDR_SRC);
+            eth.set_src_addr(MAC_ADDR_DST);
+            let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+            ip.set_dst_addr(IP_ADDR_SRC);
+            ip.set_src_addr(IP_ADDR_DST);
+            ip.fill_checksum();
+            modify(crate::wire::udp::packet::new_unchecked_mut(ip.payload_mut_slice()));
+        }
+
+        nic.receive_all();
+        let mut delivered = false;
+        dropped.set(None);
+        let recv = nic.rx(1, eth.recv(ip.recv(udp.recv_with(|_: udp::Packet<_>| delivered = true))));
+        assert_eq!(recv, Ok(1));
+        delivered
+    };
+
+    let fill = |udp: &mut crate::wire::udp::packet| {
+        udp.fill_checksum(IP_ADDR_DST.into(), IP_ADDR_SRC.into());
+    };
+
+    // A valid checksum.
+    assert!(deliver(&fill));
+    assert_eq!(dropped.get(), None);
+
+    // A corrupted packet, the checksum does not match its payload.
+    assert!(!deliver(&|udp| {
+        fill(udp);
+        udp.payload_mut_slice()[0] ^= 0xff;
+    }));
+    assert_eq!(dropped.get(), Some(DropReason::Checksum));
+
+    // Over IPv4 a zero checksum was not computed by the sender.
+    assert!(deliver(&|udp| udp.set_checksum(0)));
+    assert_eq!(dropped.get(), None);
+}
+
+#[test]
+fn reinit_in_place() {
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    // Receive a packet from port 1024 and answer it with a payload of the given length.
+    let mut answer = |payload: usize, reflect: bool| {
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+            let init = udp::Init {
+                source: Subnet::from(v4::Subnet::ANY).into(),
+                src_port: 1024,
+                dst_addr: IP_ADDR_DST.into(),
+                dst_port: 80,
+                payload: PAYLOAD_BYTES.len(),
+                fragmentation: ip::Fragmentation::default(),
+                hop_limit: None,
+            };
+            let mut prepared = raw.prepare(init).unwrap();
+            prepared.packet.payload_mut().copy_from_slice(&PAYLOAD_BYTES[..]);
+            prepared.send().unwrap();
+        }))));
+        assert_eq!(sent, Ok(1));
+
+        {
+            let buffer = nic.get_mut(0).unwrap();
+            let eth = ethernet::frame::new_unchecked_mut(buffer);
+            eth.set_dst_addr(MAC_ADDR_SRC);
+            eth.set_src_addr(MAC_ADDR_DST);
+            let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+            ip.set_dst_addr(IP_ADDR_SRC);
+            ip.set_src_addr(IP_ADDR_DST);
+            ip.fill_checksum();
+            // Not filled for ipv4 by default but should be preserved.
+            let udp = crate::wire::udp::packet::new_unchecked_mut(ip.payload_mut_slice());
+            udp.fill_checksum(IP_ADDR_DST.into(), IP_ADDR_SRC.into());
+        }
+
+        nic.receive_all();
+        let recv = nic.rx(1, eth.recv(ip.recv(udp.recv_with(|packet: udp::Packet<_>| {
+            let init = udp::Init {
+                source: Subnet::from(v4::Subnet::ANY).into(),
+                src_port: 80,
+                dst_addr: IP_ADDR_DST.into(),
+                dst_port: packet.packet.repr().src_port,
+                payload,
+                fragmentation: ip::Fragmentation::default(),
+                hop_limit: None,
+            };
+            if reflect {
+                return packet.reflect(init).unwrap();
+            }
+            let mut answer = packet.reinit(init).unwrap();
+            if payload == PAYLOAD_BYTES.len() {
+                assert_eq!(answer.packet.payload_slice(), &PAYLOAD_BYTES[..]);
+                answer.packet.payload_mut_slice().reverse();
+            }
+            answer.send().unwrap();
+        }))));
+        assert_eq!(recv, Ok(1));
+
+        let frame = ethernet::frame::new_checked(&nic.queued()[0][..]).unwrap();
+        assert_eq!(frame.dst_addr(), MAC_ADDR_DST);
+        let ip = v4::packet::new_checked(frame.payload_slice()).unwrap();
+        assert!(ip.verify_checksum());
+        assert_eq!(ip.src_addr(), IP_ADDR_SRC);
+        assert_eq!(ip.dst_addr(), IP_ADDR_DST);
+        let udp = crate::wire::udp::packet::new_checked(ip.payload_slice()).unwrap();
+        assert_eq!(udp.src_port(), 80);
+        assert_eq!(udp.dst_port(), 1024);
+        if reflect {
+            // Updated incrementally from the checksum of the request.
+            assert!(udp.verify_checksum(IP_ADDR_SRC.into(), IP_ADDR_DST.into()));
+        } else {
+            // Not filled for ipv4 by default, a stale checksum must not be kept.
+            assert_eq!(udp.checksum(), 0);
+        }
+        udp.payload_slice().to_vec()
+    };
+
+    // Same length and an unchanged payload, reflecting only updates the checksum incrementally.
+    assert_eq!(answer(PAYLOAD_BYTES.len(), true), &PAYLOAD_BYTES[..]);
+    // The payload is preserved by the in place update but then modified before sending.
+    let mut reversed = PAYLOAD_BYTES.to_vec();
+    reversed.reverse();
+    assert_eq!(answer(PAYLOAD_BYTES.len(), false), reversed);
+    // A different length is prepared anew.
+    assert_eq!(answer(8, false).len(), 8);
+}
+
+#[test]
+fn connection() {
+    use crate::layer::DropReason;
+
+    let dropped = core::cell::Cell::new(None);
+    let mut hook = |reason, _: &[u8]| dropped.set(Some(reason));
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+    eth.set_drop_hook(&mut hook);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(1024);
+
+    // The local address is left to the ip layer.
+    let connection = udp::Connection::new(
+        Subnet::from(v4::Subnet::ANY).into(), 1024,
+        IP_ADDR_DST.into(), 53);
+
+    // Send a datagram and let the peer answer it from the given port.
+    let mut exchange = |answer_port: u16| {
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+            connection.send(raw, &PAYLOAD_BYTES[..]).unwrap();
+        }))));
+        assert_eq!(sent, Ok(1));
+
+        {
+            let buffer = nic.get_mut(0).unwrap();
+            let eth = ethernet::frame::new_unchecked_mut(buffer);
+            eth.set_dst_addr(MAC_ADDR_SRC);
+            eth.set_src_addr(MAC_ADDR_DST);
+            let ip = v4::packet::new_unchecked_mut(eth.payload_mut_slice());
+            assert_eq!(ip.src_addr(), IP_ADDR_SRC);
+            ip.set_dst_addr(IP_ADDR_SRC);
+            ip.set_src_addr(IP_ADDR_DST);
+            ip.fill_checksum();
+            let udp = crate::wire::udp::packet::new_unchecked_mut(ip.payload_mut_slice());
+            assert_eq!(udp.src_port(), 1024);
+            assert_eq!(udp.dst_port(), 53);
+            assert_eq!(udp.payload_slice(), &PAYLOAD_BYTES[..]);
+            udp.set_src_port(answer_port);
+            udp.set_dst_port(1024);
+        }
+
+        nic.receive_all();
+        let mut delivered = false;
+        dropped.set(None);
+        let recv = nic.rx(1, eth.recv(ip.recv(udp.recv(
+            connection.recv(FnHandler(|_: udp::Packet<_>| delivered = true))))));
+        assert_eq!(recv, Ok(1));
+        delivered
+    };
+
+    assert!(exchange(53));
+    assert_eq!(dropped.get(), None);
+
+    assert!(!exchange(54));
+    assert_eq!(dropped.get(), Some(DropReason::Filtered));
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+fn loopback() {
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let neighbors = {
+        let mut eth_cache = arp::NeighborCache::new(&mut neighbors[..]);
+        eth_cache.fill(IP_ADDR_DST.into(), MAC_ADDR_DST, None).unwrap();
+        eth_cache
+    };
+    let mut ip = [ip::Route::unspecified(); 2];
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(&mut ip[..]),
+        neighbors);
+
+    let mut udp = udp::Endpoint::new(80);
+
+    // The frame as it would go out on the wire.
+    let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+    let sent = nic.tx(1, eth.send(ip.send(
+        udp.send_with(simple_send))));
+    assert_eq!(sent, Ok(1));
+    let frame = nic.get_mut(0).unwrap().clone();
+
+    let mut nic = Loopback::with_capacity(4, 1514);
+    let sent = nic.tx(1, eth.send(ip.send(
+        udp.send_with(simple_send))));
+    assert_eq!(sent, Ok(1));
+
+    let mut received = None;
+    let recv = nic.rx(1, FnHandler(|packet: nic::Packet<_, Vec<u8>>| {
+        received = Some(packet.payload.clone());
+    }));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(received, Some(frame));
+}
+
+#[test]
+fn ip_medium() {
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+    // Without a link layer there are no neighbors to resolve.
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        arp::NeighborCache::new(Slice::empty()));
+    let mut udp = udp::Endpoint::new(80);
+
+    let mut device = Loopback::new(Slice::Many(vec![vec![0; 1500]; 4]));
+    device.set_medium(nic::Medium::Ip);
+    assert_eq!(device.personality().medium(), nic::Medium::Ip);
+    let mut nic = nic::Framed::new(device, MAC_ADDR_SRC);
+
+    // A datagram to ourselves.
+    let send_self = |raw: udp::RawPacket<_>| {
+        let init = udp::Init {
+            source: Subnet::from(v4::Subnet::ANY).into(),
+            src_port: 80,
+            dst_addr: IP_ADDR_SRC.into(),
+            dst_port: 80,
+            payload: PAYLOAD_BYTES.len(),
+            fragmentation: ip::Fragmentation::default(),
+            hop_limit: None,
+        };
+        let mut prepared = raw.prepare(init).expect("Needs no neighbor");
+        prepared.packet.payload_mut().copy_from_slice(&PAYLOAD_BYTES[..]);
+        prepared.send().expect("Could queue the packet");
+    };
+
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(send_self))));
+    assert_eq!(sent, Ok(1));
+
+    // The device only ever sees the bare ip packet.
+    let mut packet = None;
+    let recv = nic.inner_mut().rx(1, FnHandler(|raw: nic::Packet<_, Vec<u8>>| {
+        packet = Some(raw.payload.clone());
+    }));
+    assert_eq!(recv, Ok(1));
+    let packet = packet.expect("Received the packet");
+    assert_eq!(packet.len(), 20 + 8 + PAYLOAD_BYTES.len());
+    let repr = v4::packet::new_checked(&packet[..]).unwrap();
+    assert_eq!(repr.src_addr(), IP_ADDR_SRC);
+    assert_eq!(repr.dst_addr(), IP_ADDR_SRC);
+
+    // And the round trip through the stack delivers the datagram.
+    let sent = nic.tx(1, eth.send(ip.send(udp.send_with(send_self))));
+    assert_eq!(sent, Ok(1));
+    let mut delivered = 0;
+    let recv = nic.rx(1, eth.recv(ip.recv(udp.recv_with(|packet: udp::Packet<_>| {
+        assert_eq!(packet.source_addr(), IP_ADDR_SRC.into());
+        simple_recv(packet);
+        delivered += 1;
+    }))));
+    assert_eq!(recv, Ok(1));
+    assert_eq!(delivered, 1);
+    assert_eq!(nic.dropped(), 0);
+}
+
+#[test]
+fn broadcast() {
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+    // Without any neighbor entries, resolving an address would fail.
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        arp::NeighborCache::new(Slice::empty()));
+    let mut udp = udp::Endpoint::new(80);
+
+    let mut send = |dst_addr: v4::Address| {
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+            let init = udp::Init {
+                source: Subnet::from(v4::Subnet::ANY).into(),
+                src_port: 80,
+                dst_addr: dst_addr.into(),
+                dst_port: 80,
+                payload: PAYLOAD_BYTES.len(),
+                fragmentation: ip::Fragmentation::default(),
+                hop_limit: None,
+            };
+            let mut prepared = raw.prepare(init)
+                .expect("Broadcasts need no neighbor");
+            prepared.packet.payload_mut().copy_from_slice(&PAYLOAD_BYTES[..]);
+            prepared.send().expect("Could egress the broadcast");
+        }))));
+        assert_eq!(sent, Ok(1));
+
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_checked(&buffer[..]).unwrap();
+        let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+        assert_eq!(ip.src_addr(), IP_ADDR_SRC);
+        assert_eq!(ip.dst_addr(), dst_addr);
+        eth.dst_addr()
+    };
+
+    assert_eq!(send(v4::Address::BROADCAST), ethernet::Address::BROADCAST);
+    // The broadcast address of the network of the source.
+    assert_eq!(send(v4::Address::new(127, 0, 0, 255)), ethernet::Address::BROADCAST);
+}
+
+#[test]
+fn multicast() {
+    let mut eth = eth::Endpoint::new(MAC_ADDR_SRC);
+    let mut ip = ip::Endpoint::new(Cidr::new(IP_ADDR_SRC.into(), 24),
+        ip::Routes::new(Slice::empty()),
+        arp::NeighborCache::new(Slice::empty()));
+    let mut udp = udp::Endpoint::new(80);
+
+    let mut send = |dst_addr: v4::Address, hop_limit: Option<u8>| {
+        let mut nic = External::new_send(Slice::One(vec![0; 1024]));
+        let sent = nic.tx(1, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<_>| {
+            let init = udp::Init {
+                source: Subnet::from(v4::Subnet::ANY).into(),
+                src_port: 80,
+                dst_addr: dst_addr.into(),
+                dst_port: 80,
+                payload: PAYLOAD_BYTES.len(),
+                fragmentation: ip::Fragmentation::default(),
+                hop_limit,
+            };
+            let mut prepared = raw.prepare(init)
+                .expect("Groups need no neighbor");
+            prepared.packet.payload_mut().copy_from_slice(&PAYLOAD_BYTES[..]);
+            prepared.send().expect("Could egress the datagram");
+        }))));
+        assert_eq!(sent, Ok(1));
+
+        let buffer = nic.get_mut(0).unwrap();
+        let eth = ethernet::frame::new_checked(&buffer[..]).unwrap();
+        let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+        assert_eq!(ip.src_addr(), IP_ADDR_SRC);
+        assert_eq!(ip.dst_addr(), dst_addr);
+        (eth.dst_addr(), ip.hop_limit())
+    };
+
+    // Only the low 23 bits of the group are mapped.
+    assert_eq!(send(v4::Address::new(239, 1, 2, 3), None),
+        (ethernet::Address([0x01, 0x00, 0x5e, 0x01, 0x02, 0x03]), 1));
+    assert_eq!(send(v4::Address::new(239, 129, 2, 3), Some(16)),
+        (ethernet::Address([0x01, 0x00, 0x5e, 0x01, 0x02, 0x03]), 16));
+    assert_eq!(send(v4::Address::new(224, 0, 0, 251), Some(255)),
+        (ethernet::Address([0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb]), 255));
+}
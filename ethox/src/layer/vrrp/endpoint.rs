@@ -0,0 +1,443 @@
+use crate::layer::{eth, DropReason, FnHandler, Result};
+use crate::managed::Slice;
+use crate::nic::Capabilities;
+use crate::time::{Duration, Instant};
+use crate::wire::{ethernet, ip::v4, vrrp, Checksum, Payload, PayloadMut};
+use crate::wire::ip::Protocol;
+
+/// A type that can not be instantiated.
+///
+/// Used as the default type of handlers of the receiver and sender that are created without an
+/// upper layer handler. Its methods can never be invoked.
+pub struct NoHandler { _private: Empty, }
+
+/// An empty enum to prove that there is no instance of `NoHandler`.
+enum Empty { }
+
+/// The state of a virtual router.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum State {
+    /// The router does not participate.
+    Initialize,
+    /// Monitors the master and takes over when it fails.
+    Backup,
+    /// Responsible for the virtual addresses and sends advertisements.
+    Master,
+}
+
+/// A virtual router.
+///
+/// Holds the configuration of the router for one virtual router identifier as well as the state
+/// machine of the protocol. Call [`start`] to begin participating.
+///
+/// [`start`]: #method.start
+pub struct Endpoint<'data> {
+    /// The virtual router identifier.
+    vrid: u8,
+
+    /// Our priority in the master election.
+    priority: u8,
+
+    /// The protocol version to use.
+    version: vrrp::Version,
+
+    /// The primary address of the interface, source of advertisements.
+    src_addr: v4::Address,
+
+    /// The virtual addresses associated with this router.
+    addresses: Slice<'data, v4::Address>,
+
+    /// Our advertisement interval in centiseconds.
+    interval: u16,
+
+    /// The advertisement interval of the master, in centiseconds.
+    master_interval: u16,
+
+    /// Whether a higher priority backup will take over from a lower priority master.
+    preempt: bool,
+
+    /// The current state of the router.
+    state: State,
+
+    /// Send an advertisement with priority zero and stop participating.
+    release: bool,
+
+    /// The next advertisement as master or the master down timer as backup.
+    timer: Instant,
+}
+
+/// An endpoint borrowed for receiving.
+///
+/// Handles advertisements of the virtual router and passes all other frames on.
+pub struct Receiver<'a, 'data, H=NoHandler> {
+    endpoint: &'a mut Endpoint<'data>,
+
+    /// The receiver for all other frames.
+    handler: Option<H>,
+}
+
+/// An endpoint borrowed for sending.
+///
+/// Sends advertisements when they are due and passes all other buffers on.
+pub struct Sender<'a, 'data, H=NoHandler> {
+    endpoint: &'a mut Endpoint<'data>,
+
+    /// The sender using all remaining buffers.
+    handler: Option<H>,
+}
+
+impl<'data> Endpoint<'data> {
+    /// Create a virtual router.
+    ///
+    /// The router uses version 3 of the protocol with an advertisement interval of one second and
+    /// preemption enabled. The source address should be the primary address of the interface on
+    /// the link.
+    ///
+    /// # Panics
+    /// This method panics if there are no or more than 255 virtual addresses or the virtual
+    /// router identifier is `0`.
+    pub fn new<A>(vrid: u8, priority: u8, src_addr: v4::Address, addresses: A) -> Self
+        where A: Into<Slice<'data, v4::Address>>,
+    {
+        let addresses = addresses.into();
+        assert!(vrid != 0, "Virtual router identifier must not be zero");
+        assert!(!addresses.is_empty() && addresses.len() <= 255);
+
+        Endpoint {
+            vrid,
+            priority,
+            version: vrrp::Version::V3,
+            src_addr,
+            addresses,
+            interval: 100,
+            master_interval: 100,
+            preempt: true,
+            state: State::Initialize,
+            release: false,
+            timer: Instant::from_millis(0),
+        }
+    }
+
+    /// Change the version of the protocol.
+    ///
+    /// # Panics
+    /// This method panics if the version is not supported.
+    pub fn set_version(&mut self, version: vrrp::Version) {
+        match version {
+            vrrp::Version::V2 | vrrp::Version::V3 => self.version = version,
+            vrrp::Version::Unknown(_) => panic!("Unsupported version"),
+        }
+    }
+
+    /// Change the advertisement interval.
+    ///
+    /// The interval has a granularity of centiseconds in version 3 and seconds in version 2.
+    pub fn set_interval(&mut self, interval: Duration) {
+        let centis = interval.as_millis() / 10;
+        let centis = centis.clamp(1, 0xfff) as u16;
+        self.interval = centis;
+        self.master_interval = centis;
+    }
+
+    /// Change our priority in the master election.
+    ///
+    /// Takes effect with the next advertisement that is sent or received.
+    pub fn set_priority(&mut self, priority: u8) {
+        self.priority = priority;
+    }
+
+    /// Choose whether to take over from a master with lower priority.
+    pub fn set_preempt(&mut self, preempt: bool) {
+        self.preempt = preempt;
+    }
+
+    /// The virtual router identifier.
+    pub fn vrid(&self) -> u8 {
+        self.vrid
+    }
+
+    /// The current state of the state machine.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Query if we are currently the master.
+    pub fn is_master(&self) -> bool {
+        self.state == State::Master
+    }
+
+    /// The virtual addresses that are owned by the master.
+    pub fn addresses(&self) -> &[v4::Address] {
+        &self.addresses
+    }
+
+    /// The virtual router MAC address.
+    ///
+    /// This is the source address for all frames sent by the master on behalf of the virtual
+    /// router.
+    pub fn virtual_mac(&self) -> ethernet::Address {
+        ethernet::Address([0x00, 0x00, 0x5e, 0x00, 0x01, self.vrid])
+    }
+
+    /// Start participating in the protocol.
+    ///
+    /// The owner of the virtual addresses (priority 255) immediately becomes the master, all
+    /// other routers start as backups.
+    pub fn start(&mut self, time: Instant) {
+        if self.state != State::Initialize {
+            return;
+        }
+
+        self.release = false;
+        if self.priority == vrrp::PRIORITY_OWNER {
+            self.state = State::Master;
+            self.timer = time;
+        } else {
+            self.master_interval = self.interval;
+            self.state = State::Backup;
+            self.timer = time + self.master_down_interval();
+        }
+    }
+
+    /// Stop participating in the protocol.
+    ///
+    /// A master will send one last advertisement with priority zero through the next sender so
+    /// that a backup can take over quickly. A backup stops immediately.
+    pub fn shutdown(&mut self) {
+        match self.state {
+            State::Master => self.release = true,
+            _ => self.state = State::Initialize,
+        }
+    }
+
+    /// The interval after which a backup declares the master as down.
+    pub fn master_down_interval(&self) -> Duration {
+        let interval = Duration::from_millis(10*u64::from(self.master_interval));
+        3*interval + self.skew_time()
+    }
+
+    /// Skew added to the master down interval, such that higher priorities are faster.
+    pub fn skew_time(&self) -> Duration {
+        let weight = 256 - u64::from(self.priority);
+        match self.version {
+            vrrp::Version::V2 => Duration::from_millis(1000*weight/256),
+            _ => Duration::from_millis(10*weight*u64::from(self.master_interval)/256),
+        }
+    }
+
+    /// Receive frames, handling advertisements internally.
+    pub fn answer(&mut self) -> Receiver<'_, 'data> {
+        Receiver { endpoint: self, handler: None, }
+    }
+
+    /// Send advertisements when they are due.
+    pub fn advertise(&mut self) -> Sender<'_, 'data> {
+        Sender { endpoint: self, handler: None, }
+    }
+
+    /// Receive frames, passing all unhandled frames to the handler.
+    pub fn recv<H>(&mut self, handler: H) -> Receiver<'_, 'data, H> {
+        Receiver { endpoint: self, handler: Some(handler), }
+    }
+
+    /// Receive frames, passing all unhandled frames to a function.
+    pub fn recv_with<H>(&mut self, handler: H) -> Receiver<'_, 'data, FnHandler<H>> {
+        self.recv(FnHandler(handler))
+    }
+
+    /// Send advertisements when due, passing all other buffers to the handler.
+    pub fn send<H>(&mut self, handler: H) -> Sender<'_, 'data, H> {
+        Sender { endpoint: self, handler: Some(handler), }
+    }
+
+    /// Send advertisements when due, passing all other buffers to a function.
+    pub fn send_with<H>(&mut self, handler: H) -> Sender<'_, 'data, FnHandler<H>> {
+        self.send(FnHandler(handler))
+    }
+
+    /// Process a received advertisement of our virtual router.
+    fn advertised(&mut self, src_addr: v4::Address, repr: vrrp::Repr, time: Instant) {
+        match self.state {
+            State::Initialize => (),
+            State::Backup => if repr.priority == vrrp::PRIORITY_RELEASE {
+                self.timer = time + self.skew_time();
+            } else if !self.preempt || repr.priority >= self.priority {
+                if let vrrp::Version::V3 = self.version {
+                    self.master_interval = repr.interval.max(1);
+                }
+                self.timer = time + self.master_down_interval();
+            },
+            State::Master => if repr.priority == vrrp::PRIORITY_RELEASE {
+                // Another router has just given up, assert that we are the master.
+                self.timer = time;
+            } else if repr.priority > self.priority
+                || (repr.priority == self.priority && src_addr > self.src_addr)
+            {
+                if let vrrp::Version::V3 = self.version {
+                    self.master_interval = repr.interval.max(1);
+                }
+                self.state = State::Backup;
+                self.timer = time + self.master_down_interval();
+            },
+        }
+    }
+
+    /// Check if an advertisement is due, updating the state if necessary.
+    fn advertisement_due(&mut self, time: Instant) -> bool {
+        match self.state {
+            State::Initialize => false,
+            State::Master => self.release || self.timer <= time,
+            State::Backup if self.timer <= time => {
+                self.state = State::Master;
+                true
+            },
+            State::Backup => false,
+        }
+    }
+
+    /// Fill a buffer with an advertisement.
+    fn advertise_into<P: PayloadMut>(&mut self, raw: eth::RawPacket<P>) -> Result<()> {
+        let time = raw.control.info().timestamp();
+        let capabilities = raw.control.info().capabilities();
+        let repr = vrrp::Repr {
+            version: self.version,
+            vrid: self.vrid,
+            priority: if self.release { vrrp::PRIORITY_RELEASE } else { self.priority },
+            // Asserted on construction.
+            addr_count: self.addresses.len() as u8,
+            interval: self.interval,
+        };
+        let ip_repr = v4::Repr {
+            src_addr: self.src_addr,
+            dst_addr: vrrp::MULTICAST_ADDR,
+            protocol: Protocol::Vrrp,
+            payload_len: repr.buffer_len(),
+            hop_limit: vrrp::HOP_LIMIT,
+        };
+
+        let mut prepared = raw.prepare(eth::Init {
+            src_addr: self.virtual_mac(),
+            dst_addr: vrrp::MULTICAST_MAC,
+            ethertype: ethernet::EtherType::Ipv4,
+            vlan: None,
+            payload: ip_repr.buffer_len() + ip_repr.payload_len,
+        })?;
+
+        let ip = v4::packet::new_unchecked_mut(prepared.payload_mut_slice());
+        ip_repr.emit(ip, capabilities.ipv4().tx_checksum());
+        let packet = vrrp::packet::new_unchecked_mut(ip.payload_mut_slice());
+        repr.emit(packet);
+        for (idx, &addr) in self.addresses.iter().enumerate() {
+            packet.set_addr(idx as u8, addr);
+        }
+        if capabilities.vrrp().tx_checksum().manual() {
+            packet.fill_checksum(ip_repr.src_addr, ip_repr.dst_addr);
+        } else {
+            packet.set_checksum(0);
+        }
+
+        prepared.send()?;
+
+        if self.release {
+            self.release = false;
+            self.state = State::Initialize;
+        } else {
+            self.timer = time + Duration::from_millis(10*u64::from(self.interval));
+        }
+
+        Ok(())
+    }
+}
+
+/// Extract the source address and header of an advertisement.
+fn parse_advertisement(frame: &[u8], capabilities: Capabilities)
+    -> Option<(v4::Address, vrrp::Repr)>
+{
+    let ip = v4::packet::new_checked(frame).ok()?;
+    let ip_repr = v4::Repr::parse(ip, capabilities.ipv4().rx_checksum()).ok()?;
+    if ip_repr.protocol != Protocol::Vrrp {
+        return None;
+    }
+
+    let packet = vrrp::packet::new_checked(ip.payload_slice()).ok()?;
+    let checksum = match capabilities.vrrp().rx_checksum() {
+        Checksum::Manual => vrrp::Checksum::Manual {
+            src_addr: ip_repr.src_addr,
+            dst_addr: ip_repr.dst_addr,
+        },
+        Checksum::Ignored => vrrp::Checksum::Ignored,
+    };
+    let repr = vrrp::Repr::parse(packet, checksum).ok()?;
+
+    // Advertisements must not have been forwarded.
+    if ip_repr.hop_limit != vrrp::HOP_LIMIT || ip_repr.dst_addr != vrrp::MULTICAST_ADDR {
+        return None;
+    }
+
+    Some((ip_repr.src_addr, repr))
+}
+
+impl<P, H> eth::Recv<P> for Receiver<'_, '_, H>
+where
+    P: Payload,
+    H: eth::Recv<P>,
+{
+    fn receive(&mut self, packet: eth::InPacket<P>) {
+        let advertisement = match packet.frame.repr().ethertype {
+            ethernet::EtherType::Ipv4 => {
+                let capabilities = packet.control.info().capabilities();
+                parse_advertisement(packet.frame.payload_slice(), capabilities)
+            },
+            _ => None,
+        };
+
+        match advertisement {
+            Some((src_addr, repr)) if repr.vrid == self.endpoint.vrid => {
+                // Silently discard advertisements of another version.
+                if repr.version == self.endpoint.version {
+                    let time = packet.control.info().timestamp();
+                    self.endpoint.advertised(src_addr, repr, time);
+                }
+            },
+            _ => match self.handler.as_mut() {
+                Some(handler) => handler.receive(packet),
+                None => {
+                    let eth::InPacket { mut control, frame } = packet;
+                    control.report_drop(DropReason::Unsupported, frame.as_ref())
+                },
+            },
+        }
+    }
+}
+
+impl<P, H> eth::Send<P> for Sender<'_, '_, H>
+where
+    P: Payload + PayloadMut,
+    H: eth::Send<P>,
+{
+    fn send(&mut self, packet: eth::RawPacket<P>) {
+        let time = packet.control.info().timestamp();
+        if self.endpoint.advertisement_due(time) {
+            if self.endpoint.advertise_into(packet).is_err() {
+                // TODO: log error
+            }
+            return;
+        }
+
+        if let Some(handler) = self.handler.as_mut() {
+            handler.send(packet)
+        }
+    }
+}
+
+impl<P: Payload> eth::Recv<P> for NoHandler {
+    fn receive(&mut self, _: eth::InPacket<P>) {
+        match self._private { }
+    }
+}
+
+impl<P: Payload> eth::Send<P> for NoHandler {
+    fn send(&mut self, _: eth::RawPacket<P>) {
+        match self._private { }
+    }
+}
@@ -0,0 +1,45 @@
+//! The Virtual Router Redundancy Protocol.
+//!
+//! Several routers on a link share a set of virtual IPv4 addresses. One of them, the master, is
+//! responsible for forwarding traffic sent to these addresses and periodically announces this in
+//! advertisements. The others are backups and take over when the advertisements stop. Both
+//! versions 2 ([RFC3768]) and 3 ([RFC5798]) are supported for IPv4.
+//!
+//! ## Structure
+//!
+//! The endpoint holds the configuration and the state machine of a single virtual router. It is
+//! used as an ethernet layer receiver and sender, wrapping another handler of that layer (usually
+//! an ip endpoint) to which all frames not handled by the virtual router are passed. Since
+//! advertisements are sent to a link layer multicast group, the ethernet endpoint must be
+//! configured to accept multicast frames with [`CastPolicy::link_multicast`].
+//!
+//! ## Receiving
+//!
+//! Advertisements of the same virtual router are processed as described in the standard. A master
+//! that receives an advertisement of higher priority steps down to become a backup and a backup
+//! learns the advertisement interval of the current master. All other packets are passed on.
+//!
+//! ## Sending
+//!
+//! The sender checks the timers of the state machine before passing a buffer on. If the master has
+//! not been heard from for the master down interval, a backup becomes the master. While in the
+//! master state, the sender uses buffers to emit advertisements at the configured interval. These
+//! are sent from the virtual router MAC address (`00:00:5e:00:01:{vrid}`) using the source
+//! override of [`eth::Init`]. The same address should be used by the user for all traffic sent on
+//! behalf of the virtual addresses while master.
+//!
+//! [RFC3768]: https://tools.ietf.org/html/rfc3768
+//! [RFC5798]: https://tools.ietf.org/html/rfc5798
+//! [`CastPolicy::link_multicast`]: ../struct.CastPolicy.html#structfield.link_multicast
+//! [`eth::Init`]: ../eth/struct.Init.html
+mod endpoint;
+#[cfg(test)]
+mod tests;
+
+pub use endpoint::{
+    Endpoint,
+    NoHandler,
+    Receiver,
+    Sender,
+    State,
+};
@@ -0,0 +1,175 @@
+use super::*;
+use crate::managed::Slice;
+use crate::nic::{external::External, Device};
+use crate::layer::{eth, CastPolicy};
+use crate::time::Instant;
+use crate::wire::{ethernet, ip::v4, vrrp};
+
+const VRID: u8 = 7;
+const VIRTUAL_ADDR: v4::Address = v4::Address::new(10, 0, 0, 1);
+
+/// A virtual router with its own link layer.
+struct Router {
+    eth: eth::Endpoint<'static>,
+    vrrp: Endpoint<'static>,
+}
+
+impl Router {
+    fn new(mac: ethernet::Address, addr: v4::Address, priority: u8) -> Self {
+        let mut eth = eth::Endpoint::new(mac);
+        eth.set_cast_policy(CastPolicy {
+            link_multicast: true,
+            ..CastPolicy::default()
+        });
+        Router {
+            eth,
+            vrrp: Endpoint::new(VRID, priority, addr, VIRTUAL_ADDR),
+        }
+    }
+
+    /// Poll the sender at some time, returning the advertisement if one was sent.
+    fn poll(&mut self, time: Instant) -> Option<Vec<u8>> {
+        let mut nic = External::new_send(Slice::One(vec![0; 128]));
+        nic.set_current_time(time);
+        let sent = nic.tx(1, self.eth.send(self.vrrp.advertise())).unwrap();
+        match sent {
+            0 => None,
+            _ => Some(nic.get(0).unwrap().clone()),
+        }
+    }
+
+    /// Deliver a frame at some time.
+    fn deliver(&mut self, frame: Vec<u8>, time: Instant) {
+        let mut nic = External::new_recv(Slice::One(frame));
+        nic.set_current_time(time);
+        let recv = nic.rx(1, self.eth.recv(self.vrrp.answer()));
+        assert_eq!(recv, Ok(1));
+    }
+}
+
+fn check_advertisement(frame: &[u8], src_addr: v4::Address, priority: u8) {
+    let eth = ethernet::frame::new_checked(frame).unwrap();
+    assert_eq!(eth.src_addr(), ethernet::Address([0x00, 0x00, 0x5e, 0x00, 0x01, VRID]));
+    assert_eq!(eth.dst_addr(), vrrp::MULTICAST_MAC);
+    let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+    assert_eq!(ip.src_addr(), src_addr);
+    assert_eq!(ip.dst_addr(), vrrp::MULTICAST_ADDR);
+    assert_eq!(ip.hop_limit(), vrrp::HOP_LIMIT);
+    let packet = vrrp::packet::new_checked(ip.payload_slice()).unwrap();
+    let repr = vrrp::Repr::parse(packet, vrrp::Checksum::Manual {
+        src_addr: ip.src_addr(),
+        dst_addr: ip.dst_addr(),
+    }).unwrap();
+    assert_eq!(repr.vrid, VRID);
+    assert_eq!(repr.priority, priority);
+    assert_eq!(packet.addr(0), VIRTUAL_ADDR);
+}
+
+#[test]
+fn election() {
+    const ADDR_HIGH: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const ADDR_LOW: v4::Address = v4::Address::new(10, 0, 0, 3);
+
+    let mut high = Router::new(ethernet::Address([0, 1, 2, 3, 4, 5]), ADDR_HIGH, 200);
+    let mut low = Router::new(ethernet::Address([6, 5, 4, 3, 2, 1]), ADDR_LOW, 100);
+
+    let start = Instant::from_millis(0);
+    high.vrrp.start(start);
+    low.vrrp.start(start);
+    assert_eq!(high.vrrp.state(), State::Backup);
+    assert_eq!(low.vrrp.state(), State::Backup);
+
+    // Nobody is master yet.
+    assert!(high.poll(start).is_none());
+    assert!(low.poll(start).is_none());
+
+    // The higher priority has a shorter master down interval.
+    assert!(high.vrrp.master_down_interval() < low.vrrp.master_down_interval());
+    let takeover = start + high.vrrp.master_down_interval();
+    let advertisement = high.poll(takeover).expect("Higher priority becomes master");
+    assert!(high.vrrp.is_master());
+    check_advertisement(&advertisement, ADDR_HIGH, 200);
+
+    // The lower priority resets its timer when it hears the master.
+    low.deliver(advertisement, takeover);
+    assert_eq!(low.vrrp.state(), State::Backup);
+    let later = start + low.vrrp.master_down_interval();
+    assert!(low.poll(later).is_none());
+    assert_eq!(low.vrrp.state(), State::Backup);
+
+    // The master continues advertising at its interval.
+    let next = takeover + crate::time::Duration::from_secs(1);
+    let advertisement = high.poll(next).expect("Master advertises periodically");
+    check_advertisement(&advertisement, ADDR_HIGH, 200);
+    low.deliver(advertisement, next);
+
+    // When the master is gone, the backup takes over.
+    let down = next + low.vrrp.master_down_interval();
+    let advertisement = low.poll(down).expect("Backup takes over");
+    assert!(low.vrrp.is_master());
+    check_advertisement(&advertisement, ADDR_LOW, 100);
+}
+
+#[test]
+fn preempt_and_release() {
+    const ADDR_HIGH: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const ADDR_LOW: v4::Address = v4::Address::new(10, 0, 0, 3);
+
+    let mut high = Router::new(ethernet::Address([0, 1, 2, 3, 4, 5]), ADDR_HIGH, 200);
+    let mut low = Router::new(ethernet::Address([6, 5, 4, 3, 2, 1]), ADDR_LOW, 100);
+
+    // The lower priority starts first and becomes master.
+    let start = Instant::from_millis(0);
+    low.vrrp.start(start);
+    let takeover = start + low.vrrp.master_down_interval();
+    assert!(low.poll(takeover).is_some());
+    assert!(low.vrrp.is_master());
+
+    // The higher priority joins later and preempts the master.
+    high.vrrp.start(takeover);
+    let preempt = takeover + high.vrrp.master_down_interval();
+    let advertisement = high.poll(preempt).expect("Higher priority becomes master");
+    low.deliver(advertisement, preempt);
+    assert_eq!(low.vrrp.state(), State::Backup);
+
+    // A master shutting down releases immediately.
+    high.vrrp.shutdown();
+    let release = high.poll(preempt).expect("Master sends a release advertisement");
+    check_advertisement(&release, ADDR_HIGH, vrrp::PRIORITY_RELEASE);
+    assert_eq!(high.vrrp.state(), State::Initialize);
+
+    // The backup takes over after only the skew time.
+    low.deliver(release, preempt);
+    let skew = preempt + low.vrrp.skew_time();
+    assert!(low.poll(skew).is_some());
+    assert!(low.vrrp.is_master());
+}
+
+#[test]
+fn checksum_offload() {
+    use crate::nic::Capabilities;
+
+    const ADDR: v4::Address = v4::Address::new(10, 0, 0, 2);
+
+    for offloaded in [false, true] {
+        let mut router = Router::new(ethernet::Address([0, 1, 2, 3, 4, 5]), ADDR, 200);
+        let start = Instant::from_millis(0);
+        router.vrrp.start(start);
+
+        let mut nic = External::new_send(Slice::One(vec![0; 128]));
+        if offloaded {
+            nic.set_capabilities(Capabilities::offloaded());
+        }
+        nic.set_current_time(start + router.vrrp.master_down_interval());
+        let sent = nic.tx(1, router.eth.send(router.vrrp.advertise()));
+        assert_eq!(sent, Ok(1));
+
+        let eth = ethernet::frame::new_checked(&nic.get(0).unwrap()[..]).unwrap();
+        let ip = v4::packet::new_checked(eth.payload_slice()).unwrap();
+        assert_eq!(ip.verify_checksum(), !offloaded);
+        assert_eq!(ip.checksum() == 0, offloaded);
+        let packet = vrrp::packet::new_checked(ip.payload_slice()).unwrap();
+        assert_eq!(packet.verify_checksum(ADDR, vrrp::MULTICAST_ADDR), !offloaded);
+        assert_eq!(packet.checksum() == 0, offloaded);
+    }
+}
@@ -0,0 +1,157 @@
+use core::mem;
+
+use super::{List, Slice};
+
+/// A map on bounded storage evicting the least recently used entry when full.
+///
+/// Entries are kept in a list ordered by their last use, the most recent first. Both inserting and
+/// looking up an entry count as a use. This makes it suitable for caches such as neighbor tables,
+/// where forgetting an old entry is preferable over failing to remember a new one.
+///
+/// All operations traverse the list and are linear in the number of entries, use this only for
+/// rather small maps.
+#[derive(Debug)]
+pub struct LruMap<'a, K, V> {
+    entries: List<'a, (K, V)>,
+}
+
+impl<'a, K: PartialEq, V> LruMap<'a, K, V> {
+    /// Create an empty map with room for as many entries as the storage holds.
+    ///
+    /// The content of the storage is overwritten on insertion.
+    pub fn new<S>(storage: S) -> Self
+        where S: Into<Slice<'a, (K, V)>>,
+    {
+        LruMap {
+            entries: List::new(storage.into()),
+        }
+    }
+
+    /// The number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The maximum number of entries.
+    pub fn capacity(&self) -> usize {
+        self.entries.capacity()
+    }
+
+    /// All entries, the most recently used first.
+    pub fn entries(&self) -> &[(K, V)] {
+        self.entries.as_slice()
+    }
+
+    /// Get the value of a key without marking it as used.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.entries.as_slice()
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, val)| val)
+    }
+
+    /// Get the value of a key, marking it as the most recently used.
+    pub fn lookup(&mut self, key: &K) -> Option<&mut V> {
+        let index = self.position(key)?;
+        Some(&mut self.bump(index).1)
+    }
+
+    /// Insert a value, marking the key as the most recently used.
+    ///
+    /// Returns the entry that was displaced by the insertion. This is the previous value of the
+    /// key if it was already present. Otherwise, if the map was full, it is the least recently used
+    /// entry which was evicted. A map without any storage returns the new entry itself.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some(index) = self.position(&key) {
+            let entry = self.bump(index);
+            let previous = mem::replace(&mut entry.1, value);
+            return Some((key, previous));
+        }
+
+        if let Some(slot) = self.entries.push() {
+            *slot = (key, value);
+            let last = self.entries.len() - 1;
+            self.bump(last);
+            return None;
+        }
+
+        let entries = self.entries.as_mut_slice();
+        let evicted = match entries.last_mut() {
+            Some(last) => mem::replace(last, (key, value)),
+            None => return Some((key, value)),
+        };
+        entries.rotate_right(1);
+        Some(evicted)
+    }
+
+    /// Remove the entry of a key.
+    ///
+    /// Returns `true` if the key was present.
+    pub fn remove(&mut self, key: &K) -> bool {
+        match self.position(key) {
+            Some(index) => {
+                self.entries.remove_at(index);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn position(&self, key: &K) -> Option<usize> {
+        self.entries.as_slice()
+            .iter()
+            .position(|(k, _)| k == key)
+    }
+
+    /// Move the entry at an index to the front.
+    fn bump(&mut self, index: usize) -> &mut (K, V) {
+        let entries = self.entries.as_mut_slice();
+        entries[..=index].rotate_right(1);
+        &mut entries[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recent() {
+        let mut map = LruMap::new(vec![(0u8, 0u32); 3]);
+        assert_eq!(map.insert(1, 10), None);
+        assert_eq!(map.insert(2, 20), None);
+        assert_eq!(map.insert(3, 30), None);
+        assert_eq!(map.len(), map.capacity());
+
+        // Using the oldest entry makes the second one the least recent.
+        assert_eq!(map.lookup(&1).copied(), Some(10));
+        assert_eq!(map.insert(4, 40), Some((2, 20)));
+        assert_eq!(map.peek(&2), None);
+        assert_eq!(map.entries(), [(4, 40), (1, 10), (3, 30)]);
+
+        // Peeking does not count as a use.
+        assert_eq!(map.peek(&3), Some(&30));
+        assert_eq!(map.insert(5, 50), Some((3, 30)));
+
+        // Replacing returns the previous value and evicts nothing.
+        assert_eq!(map.insert(1, 11), Some((1, 10)));
+        assert_eq!(map.entries(), [(1, 11), (5, 50), (4, 40)]);
+
+        assert!(map.remove(&5));
+        assert!(!map.remove(&5));
+        assert_eq!(map.insert(6, 60), None);
+        assert_eq!(map.entries(), [(6, 60), (1, 11), (4, 40)]);
+    }
+
+    #[test]
+    fn no_storage() {
+        let mut map = LruMap::<u8, u8>::new(Vec::new());
+        assert_eq!(map.insert(1, 1), Some((1, 1)));
+        assert!(map.is_empty());
+    }
+}
@@ -2,12 +2,14 @@
 //!
 //! All of these containers have some option to construct them from one (or more) slices of the
 //! underlying types instead of allocating resources dynamically.
+mod lru;
 mod map;
 mod ordered;
 mod partial;
 mod slice;
 pub mod slotmap;
 
+pub use self::lru::LruMap;
 pub use self::map::Map;
 pub use self::ordered::Ordered;
 pub use self::partial::Partial;
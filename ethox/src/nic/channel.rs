@@ -0,0 +1,220 @@
+//! A nic exchanging frames with other threads over channels.
+//!
+//! This is mostly useful for tests spanning threads or tasks where one part of a harness feeds
+//! frames to the stack while another asserts on the frames the stack emits.
+use std::sync::mpsc;
+
+use crate::time::Instant;
+
+use super::{Capabilities, Device, Info, Personality, Recv, Send, Result};
+use super::common::{EnqueueFlag, PacketInfo};
+
+/// The [`nic::Handle`] of [`Channel`].
+///
+/// [`nic::Handle`]: ../trait.Handle.html
+/// [`Channel`]: struct.Channel.html
+pub struct Handle(EnqueueFlag);
+
+/// A device receiving from an injection queue and sending to a capture queue.
+///
+/// Received frames are pulled from the injection channel without blocking. Each queued buffer is
+/// pushed to the capture channel, both for buffers filled by `tx` and for received buffers that
+/// were answered in place.
+pub struct Channel {
+    inject: mpsc::Receiver<Vec<u8>>,
+    capture: mpsc::Sender<Vec<u8>>,
+    /// The length of buffers offered for sending.
+    buffer_len: usize,
+    info: PacketInfo,
+}
+
+/// The ends of the channels connected to a [`Channel`] device.
+///
+/// [`Channel`]: struct.Channel.html
+pub struct Endpoints {
+    /// Feeds frames to be received by the device.
+    pub inject: mpsc::Sender<Vec<u8>>,
+    /// Collects frames sent by the device.
+    pub capture: mpsc::Receiver<Vec<u8>>,
+}
+
+impl Channel {
+    /// Create a device from the ends of two channels.
+    ///
+    /// Buffers offered for sending have a length of `buffer_len` bytes.
+    pub fn new(
+        inject: mpsc::Receiver<Vec<u8>>,
+        capture: mpsc::Sender<Vec<u8>>,
+        buffer_len: usize,
+    ) -> Self {
+        Channel {
+            inject,
+            capture,
+            buffer_len,
+            info: PacketInfo {
+                timestamp: Instant::from_millis(0),
+                capabilities: Capabilities::no_support(),
+            },
+        }
+    }
+
+    /// Create a device along with new channels connected to it.
+    pub fn pair(buffer_len: usize) -> (Self, Endpoints) {
+        let (inject, inject_rx) = mpsc::channel();
+        let (capture_tx, capture) = mpsc::channel();
+        let channel = Channel::new(inject_rx, capture_tx, buffer_len);
+        (channel, Endpoints { inject, capture })
+    }
+
+    /// Update the timestamp on all future packets.
+    pub fn set_current_time(&mut self, instant: Instant) {
+        self.info.timestamp = instant;
+    }
+
+    /// Push a queued buffer to the capture channel.
+    ///
+    /// A disconnected capture channel is not an error, the frame is lost on the wire as it would
+    /// be without anyone listening.
+    fn capture(&mut self, buffer: Vec<u8>) {
+        let _ = self.capture.send(buffer);
+    }
+}
+
+impl Device for Channel {
+    type Handle = Handle;
+    type Payload = Vec<u8>;
+
+    fn personality(&self) -> Personality {
+        Personality::baseline()
+    }
+
+    fn tx(&mut self, max: usize, mut sender: impl Send<Self::Handle, Self::Payload>)
+        -> Result<usize>
+    {
+        let mut count = 0;
+
+        for _ in 0..max {
+            let mut buffer = vec![0; self.buffer_len];
+            let mut handle = Handle(EnqueueFlag::set_true(self.info));
+            sender.send(super::Packet {
+                handle: &mut handle,
+                payload: &mut buffer,
+            });
+
+            if !handle.0.was_sent() {
+                break;
+            }
+
+            self.capture(buffer);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    fn rx(&mut self, max: usize, mut receptor: impl Recv<Self::Handle, Self::Payload>)
+        -> Result<usize>
+    {
+        let mut count = 0;
+
+        for _ in 0..max {
+            let mut buffer = match self.inject.try_recv() {
+                Ok(buffer) => buffer,
+                Err(_) => break,
+            };
+
+            let mut handle = Handle(EnqueueFlag::set_true(self.info));
+            receptor.receive(super::Packet {
+                handle: &mut handle,
+                payload: &mut buffer,
+            });
+
+            if handle.0.was_sent() {
+                self.capture(buffer);
+            }
+
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+impl super::Handle for Handle {
+    fn queue(&mut self) -> Result<()> {
+        self.0.queue()
+    }
+
+    fn info(&self) -> &dyn Info {
+        self.0.info()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::layer::{arp as arp_layer, eth, ip as ip_layer};
+    use crate::managed::Slice;
+    use crate::wire::{arp, ethernet, ip};
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: ip::v4::Address = ip::v4::Address::new(127, 0, 0, 1);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: ip::v4::Address = ip::v4::Address::new(127, 0, 0, 2);
+
+    fn arp_request() -> Vec<u8> {
+        let mut buffer = vec![0; 14 + 28];
+        let eth = ethernet::frame::new_unchecked_mut(&mut buffer);
+        ethernet::Repr {
+            src_addr: MAC_ADDR_OTHER,
+            dst_addr: MAC_ADDR_HOST,
+            ethertype: ethernet::EtherType::Arp,
+            vlan: None,
+        }.emit(eth);
+        let arp = arp::packet::new_unchecked_mut(eth.payload_mut_slice());
+        arp::Repr::EthernetIpv4 {
+            operation: arp::Operation::Request,
+            source_hardware_addr: MAC_ADDR_OTHER,
+            source_protocol_addr: IP_ADDR_OTHER,
+            target_hardware_addr: MAC_ADDR_HOST,
+            target_protocol_addr: IP_ADDR_HOST,
+        }.emit(arp);
+        buffer
+    }
+
+    #[test]
+    fn answer_across_threads() {
+        let (mut nic, Endpoints { inject, capture }) = Channel::pair(1024);
+
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        let mut neighbors = [arp_layer::Neighbor::default(); 1];
+        let mut routes = [ip_layer::Route::unspecified(); 2];
+        let mut ip = ip_layer::Endpoint::new(ip::Cidr::new(IP_ADDR_HOST.into(), 24),
+            ip_layer::Routes::new(&mut routes[..]),
+            arp_layer::NeighborCache::new(Slice::empty()));
+        let mut arp = arp_layer::Endpoint::new(arp_layer::NeighborCache::new(&mut neighbors[..]));
+
+        let injector = thread::spawn(move || inject.send(arp_request()).unwrap());
+        injector.join().unwrap();
+
+        let recv = nic.rx(1, eth.recv(arp.answer(&mut ip)));
+        assert_eq!(recv, Ok(1));
+        // Nothing else was injected.
+        assert_eq!(nic.rx(1, eth.recv(arp.answer(&mut ip))), Ok(0));
+
+        let checker = thread::spawn(move || {
+            let mut reply = capture.recv().unwrap();
+            let eth = ethernet::frame::new_unchecked_mut(&mut reply);
+            assert_eq!(eth.dst_addr(), MAC_ADDR_OTHER);
+            assert_eq!(eth.ethertype(), ethernet::EtherType::Arp);
+            let arp = arp::packet::new_unchecked_mut(eth.payload_mut_slice());
+            assert_eq!(arp.operation(), arp::Operation::Reply);
+            assert_eq!(arp.source_protocol_addr(), IP_ADDR_HOST);
+            assert_eq!(arp.target_hardware_addr(), MAC_ADDR_OTHER);
+            assert!(capture.try_recv().is_err());
+        });
+        checker.join().unwrap();
+    }
+}
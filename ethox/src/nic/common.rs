@@ -1,4 +1,6 @@
 //! Useful base types for implementing a nic.
+use core::ops::{Deref, DerefMut};
+
 use crate::layer::{Error, Result};
 use crate::time::Instant;
 
@@ -25,6 +27,21 @@ pub struct PacketInfo {
     pub capabilities: Capabilities,
 }
 
+/// A bounded set of buffers that are recycled after their packets have been consumed.
+///
+/// Buffers are handed out for sending one at a time and stay in flight until they are released,
+/// for example once the hardware has finished transmitting them. Released buffers are handed out
+/// again such that a fixed number of buffers can serve an unbounded number of packets. No buffer
+/// is ever allocated by the pool.
+///
+/// The buffers are reordered in place, the in-flight ones are kept at the front in the order in
+/// which they were taken.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pool<T> {
+    buffers: T,
+    in_flight: usize,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum FlagState {
     NotPossible,
@@ -48,6 +65,13 @@ impl EnqueueFlag {
         }
     }
 
+    /// Query if the buffer can be queued at all.
+    ///
+    /// Queueing a buffer for which this is `false` fails with `Exhausted`.
+    pub fn is_possible(&self) -> bool {
+        self.flag != FlagState::NotPossible
+    }
+
     /// Query if the flag has been set to queue a buffer.
     ///
     /// This can only return `true` if the flag was created with `set_true`.
@@ -56,6 +80,76 @@ impl EnqueueFlag {
     }
 }
 
+impl<T, P> Pool<T> where T: Deref<Target=[P]> {
+    /// Create a pool from some buffers, all of which are available.
+    pub fn new(buffers: T) -> Self {
+        Pool {
+            buffers,
+            in_flight: 0,
+        }
+    }
+
+    /// The total number of buffers.
+    pub fn capacity(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// The number of buffers that can be taken.
+    pub fn available(&self) -> usize {
+        self.capacity() - self.in_flight
+    }
+
+    /// All buffers, the ones in flight first.
+    pub fn buffers(&self) -> &[P] {
+        &self.buffers
+    }
+
+    /// The buffers in flight, the oldest first.
+    pub fn in_flight(&self) -> &[P] {
+        &self.buffers[..self.in_flight]
+    }
+
+    /// Take an available buffer, putting it in flight.
+    ///
+    /// Returns `None` if all buffers are in flight.
+    pub fn take(&mut self) -> Option<&mut P>
+        where T: DerefMut,
+    {
+        let buffer = self.buffers.get_mut(self.in_flight)?;
+        self.in_flight += 1;
+        Some(buffer)
+    }
+
+    /// Make the most recently taken buffer available again, as it was not used after all.
+    pub fn untake(&mut self) {
+        self.in_flight = self.in_flight.saturating_sub(1);
+    }
+
+    /// Release up to `count` of the oldest buffers in flight when their packets were consumed.
+    ///
+    /// Returns the number of buffers that became available.
+    pub fn release(&mut self, count: usize) -> usize
+        where T: DerefMut,
+    {
+        let count = count.min(self.in_flight);
+        self.buffers[..self.in_flight].rotate_left(count);
+        self.in_flight -= count;
+        count
+    }
+
+    /// Release all buffers in flight.
+    pub fn release_all(&mut self) -> usize
+        where T: DerefMut,
+    {
+        self.release(self.in_flight)
+    }
+
+    /// Unwrap the buffers.
+    pub fn into_inner(self) -> T {
+        self.buffers
+    }
+}
+
 impl FlagState {
     pub(crate) fn was_sent(&self) -> bool {
         match self {
@@ -66,7 +160,7 @@ impl FlagState {
 
     fn queue(&mut self) -> Result<()> {
         match self {
-            FlagState::NotPossible => Err(Error::Illegal),
+            FlagState::NotPossible => Err(Error::Exhausted),
             FlagState::SetTrue(b) => Ok(*b = true),
         }
     }
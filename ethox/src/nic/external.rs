@@ -1,23 +1,53 @@
 //! A stub nic whose buffers come from an external source.
+use core::any::Any;
 use core::ops::{Deref, DerefMut};
-use crate::wire::Payload;
+use crate::wire::{ethernet, Payload};
 use crate::time::Instant;
 
-use super::{Capabilities, Info, Personality, Recv, Send, Result};
-use super::common::{EnqueueFlag, PacketInfo};
+use super::{Capabilities, Device, Info, Personality, Recv, Send, Result};
+use super::common::{EnqueueFlag, PacketInfo, Pool};
 
 /// The [`nic::Handle`] of [`External`].
 ///
+/// Its [`Info`] provides the metadata of the device, if any.
+///
 /// [`nic::Handle`]: ../trait.Handle.html
 /// [`External`]: struct.External.html
-pub struct Handle(EnqueueFlag);
+/// [`Info`]: ../trait.Info.html
+pub struct Handle<M=()> {
+    flag: EnqueueFlag,
+    info: MetaInfo<M>,
+}
+
+/// Packet info extended with the metadata of the device.
+struct MetaInfo<M> {
+    info: PacketInfo,
+    received: Option<Instant>,
+    mtu: usize,
+    metadata: M,
+}
 
 /// An interface with buffers managed externally.
 ///
 /// This implementation can be used in a number of ways. Firstly, it is good to mock a real
 /// interface in tests, allowing full control over the behaviour between operations. Secondly, it
 /// can be used as a temporary software buffer for virtualization purposes.
-pub struct External<T> {
+///
+/// The packets report the initial length of the longest buffer, less the ethernet header, as
+/// their mtu. This can be changed with [`set_mtu`] for buffers that can grow.
+///
+/// Tests can attach arbitrary metadata `M` to the packets, for example a synthetic ingress port.
+/// It is readable by upper layers through [`Info::metadata`] while the default unit type attaches
+/// no meaningful information.
+///
+/// Instead of a slice of buffers that is used up by sending, the nic can also be backed by a
+/// [`Pool`] of buffers which are recycled, see [`new_pooled`].
+///
+/// [`Info::metadata`]: ../trait.Info.html#method.metadata
+/// [`Pool`]: ../common/struct.Pool.html
+/// [`new_pooled`]: #method.new_pooled
+/// [`set_mtu`]: #method.set_mtu
+pub struct External<T, M=()> {
     /// Backing buffer, accessible as a slice of packet payloads.
     buffer: T,
 
@@ -27,14 +57,29 @@ pub struct External<T> {
     /// Number of sent packages.
     sent: usize,
 
+    /// Number of buffers offered but not queued in the last call to `tx`.
+    declined: usize,
+
+    /// Whether packets can be queued at all.
+    queueable: bool,
+
     /// The index of the split.
     split: usize,
 
     /// The info struct just copied for each packet.
     info: PacketInfo,
+
+    /// The hardware timestamp of received packets.
+    received: Option<Instant>,
+
+    /// The mtu reported for all packets.
+    mtu: usize,
+
+    /// The metadata attached to each packet.
+    metadata: M,
 }
 
-impl<T> External<T> {
+impl<T, M> External<T, M> {
     /// Set the index of the last to-receive buffer.
     pub fn set_one_past_receive(&mut self, at: usize) {
         self.split = at;
@@ -49,38 +94,166 @@ impl<T> External<T> {
     pub fn reset_receive(&mut self) {
         self.recv = 0;
     }
+
+    /// Attach some metadata to all future packets.
+    ///
+    /// This changes the type of metadata and keeps the buffer state.
+    pub fn with_metadata<N>(self, metadata: N) -> External<T, N> {
+        External {
+            buffer: self.buffer,
+            recv: self.recv,
+            sent: self.sent,
+            declined: self.declined,
+            queueable: self.queueable,
+            split: self.split,
+            info: self.info,
+            received: self.received,
+            mtu: self.mtu,
+            metadata,
+        }
+    }
+
+    /// Change the metadata attached to future packets.
+    pub fn set_metadata(&mut self, metadata: M) {
+        self.metadata = metadata;
+    }
+
+    /// Set the hardware timestamp of all future received packets.
+    ///
+    /// Setting it before each call to `rx` with a `max` of one attaches a timestamp to an
+    /// individual buffer. Sent packets never carry a hardware timestamp.
+    pub fn set_hardware_timestamp(&mut self, received: Option<Instant>) {
+        self.received = received;
+    }
+
+    /// Allow or refuse queueing of all future packets.
+    ///
+    /// A nic refusing to queue behaves like a device that ran out of transmit resources. Sending a
+    /// packet then fails with `Exhausted` for the layer that tried to send it, and `tx` reports
+    /// the buffer as [`declined`] instead of sent.
+    ///
+    /// [`declined`]: #method.declined
+    pub fn set_queueable(&mut self, queueable: bool) {
+        self.queueable = queueable;
+    }
+
+    /// The number of buffers offered by the last call to `tx` that were not queued.
+    ///
+    /// Distinguishes a sender that had nothing to send or failed to enqueue its packet, both of
+    /// which decline a buffer, from a nic that had no buffer left to offer when `tx` returned `0`.
+    pub fn declined(&self) -> usize {
+        self.declined
+    }
+
+    /// Change the mtu reported for all future packets.
+    pub fn set_mtu(&mut self, mtu: usize) {
+        self.mtu = mtu;
+    }
+
+    /// Change the capabilities advertised for all future packets.
+    ///
+    /// The default are capabilities without any support such that the stack computes and verifies
+    /// all checksums. Offloaded checksums are neither filled in when sending nor checked when
+    /// receiving.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.info.capabilities = capabilities;
+    }
+
+    /// Get a reference to the metadata attached to packets.
+    pub fn metadata(&self) -> &M {
+        &self.metadata
+    }
 }
 
-impl<T, P> External<T> where T: Deref<Target=[P]> {
+impl<T, P> External<T> where T: Deref<Target=[P]>, P: Payload {
     /// A new external nic, only sending packets.
     pub fn new_send(buffer: T) -> Self {
+        let mtu = largest_mtu(&buffer);
         External {
             buffer,
             recv: 0,
             sent: 0,
+            declined: 0,
+            queueable: true,
             split: 0,
             info: PacketInfo {
                 timestamp: Instant::from_millis(0),
                 capabilities: Capabilities::no_support(),
             },
+            received: None,
+            mtu,
+            metadata: (),
         }
     }
 
     /// A new external nic, only receiving packets.
     pub fn new_recv(buffer: T) -> Self {
         let len = buffer.len();
+        let mtu = largest_mtu(&buffer);
         External {
             buffer,
             recv: 0,
             sent: 0,
+            declined: 0,
+            queueable: true,
             split: len,
             info: PacketInfo {
                 timestamp: Instant::from_millis(0),
                 capabilities: Capabilities::no_support(),
             },
+            received: None,
+            mtu,
+            metadata: (),
+        }
+    }
+}
+
+impl<T, P> External<Pool<T>> where T: Deref<Target=[P]>, P: Payload {
+    /// A new external nic, sending packets from a pool of recycled buffers.
+    ///
+    /// The buffers sent in one call to `tx` stay in flight until the next call, when they are
+    /// considered transmitted and released to the pool. Until then, they can be inspected through
+    /// [`pool`]. This nic never receives any packets.
+    ///
+    /// [`pool`]: #method.pool
+    pub fn new_pooled(pool: Pool<T>) -> Self {
+        let mtu = largest_mtu(pool.buffers());
+        External {
+            buffer: pool,
+            recv: 0,
+            sent: 0,
+            declined: 0,
+            queueable: true,
+            split: 0,
+            info: PacketInfo {
+                timestamp: Instant::from_millis(0),
+                capabilities: Capabilities::no_support(),
+            },
+            received: None,
+            mtu,
+            metadata: (),
         }
     }
+}
+
+impl<T, M> External<Pool<T>, M> {
+    /// Get a reference to the pool of buffers.
+    pub fn pool(&self) -> &Pool<T> {
+        &self.buffer
+    }
+
+    /// Get a mutable reference to the pool of buffers.
+    pub fn pool_mut(&mut self) -> &mut Pool<T> {
+        &mut self.buffer
+    }
 
+    /// The total number of packets sent.
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+}
+
+impl<T, P, M> External<T, M> where T: Deref<Target=[P]> {
     /// Remaining number of buffers to receive.
     pub fn to_recv(&self) -> usize {
         self.buffer.len()
@@ -96,9 +269,12 @@ impl<T, P> External<T> where T: Deref<Target=[P]> {
     }
 
     /// Change the buffer state such that all packets are pending to be received.
+    ///
+    /// Received packets can be answered in place, which queues them for sending again.
     pub fn receive_all(&mut self) {
         self.set_one_past_receive(self.buffer.len());
         self.reset_receive();
+        self.reset_send();
     }
 
     /// Change the buffer state such that all packets are pending to be sent.
@@ -119,6 +295,14 @@ impl<T, P> External<T> where T: Deref<Target=[P]> {
         self.buffer.get_mut(idx)
     }
 
+    /// The buffers that have been queued for sending.
+    ///
+    /// This includes the received buffers that were answered in place. These are moved from the
+    /// receive to the send portion of the buffer, before all other sent buffers.
+    pub fn queued(&self) -> &[P] {
+        &self.buffer[self.split..self.next_send()]
+    }
+
     /// Update the timestamp on all future received packets.
     pub fn set_current_time(&mut self, instant: Instant) {
         self.info.timestamp = instant;
@@ -135,40 +319,72 @@ impl<T, P> External<T> where T: Deref<Target=[P]> {
     }
 }
 
-impl<T, P> super::Device for External<T>
+/// Send a single packet from an owned buffer and hand the buffer back.
+///
+/// The buffer is offered to the sender exactly once, as the only buffer of an `External` device.
+/// Since that device completes transmission immediately, the buffer is passed to `reclaim` before
+/// this returns, regardless of whether it was sent or an error occurred. This closes the loop for
+/// senders that manage a small fixed pool of buffers themselves.
+///
+/// Returns the number of sent packets, that is `1` if the sender queued the buffer.
+pub fn fire<P, S, R>(buffer: P, sender: S, reclaim: R) -> Result<usize>
+where
+    P: Payload,
+    S: Send<Handle, P>,
+    R: FnOnce(P),
+{
+    let mut buffers = [buffer];
+    let sent = External::new_send(&mut buffers[..]).tx(1, sender);
+    let [buffer] = buffers;
+    reclaim(buffer);
+    sent
+}
+
+impl<T, P, M> Device for External<T, M>
 where
     T: Deref<Target=[P]> + DerefMut,
     P: Payload,
+    M: Clone + 'static,
 {
-    type Handle = Handle;
+    type Handle = Handle<M>;
     type Payload = P;
 
     fn personality(&self) -> Personality {
-        Personality::baseline()
+        let mut personality = Personality::baseline();
+        *personality.capabilities_mut() = self.info.capabilities;
+        *personality.mtu_mut() = Some(self.mtu);
+        personality
     }
 
     fn tx(&mut self, max: usize, mut sender: impl Send<Self::Handle, Self::Payload>)
         -> Result<usize> 
     {
-        if max == 0 || self.to_send() == 0 {
-            return Ok(0)
+        let mut count = 0;
+        self.declined = 0;
+
+        for _ in 0..max {
+            if self.to_send() == 0 {
+                break;
+            }
+
+            let next_id = self.next_send();
+            let mut flag = self.handle();
+            let buffer = &mut self.buffer[next_id];
+
+            sender.send(super::Packet {
+                handle: &mut flag,
+                payload: buffer,
+            });
+
+            if flag.flag.was_sent() {
+                self.sent += 1;
+                count += 1;
+            } else {
+                self.declined += 1;
+            }
         }
 
-        let next_id = self.next_send();
-        let buffer = &mut self.buffer[next_id];
-
-        let mut flag = Handle(EnqueueFlag::set_true(self.info));
-        sender.send(super::Packet {
-            handle: &mut flag,
-            payload: buffer,
-        });
-
-        if flag.0.was_sent() {
-            self.sent += 1;
-            Ok(1)
-        } else {
-            Ok(0)
-        }
+        Ok(count)
     }
 
     fn rx(&mut self, max: usize, mut receptor: impl Recv<Self::Handle, Self::Payload>)
@@ -179,25 +395,241 @@ where
         }
 
         let next_id = self.next_recv();
+        let mut flag = self.handle();
+        flag.info.received = self.received;
         let buffer = &mut self.buffer[next_id];
 
-        let mut flag = Handle(EnqueueFlag::not_possible(self.info));
         receptor.receive(super::Packet {
             handle: &mut flag,
             payload: buffer,
         });
 
-        self.recv += 1;
+        if flag.flag.was_sent() {
+            // The buffer was answered in place. Move it to the front of the send buffers, right
+            // before the split, and account for it as sent. No payload is copied.
+            self.buffer[next_id..self.split].rotate_left(1);
+            self.split -= 1;
+            self.sent += 1;
+        } else {
+            self.recv += 1;
+        }
+
         Ok(1)
     }
 }
 
-impl super::Handle for Handle {
+impl<T, P, M> Device for External<Pool<T>, M>
+where
+    T: Deref<Target=[P]> + DerefMut,
+    P: Payload,
+    M: Clone + 'static,
+{
+    type Handle = Handle<M>;
+    type Payload = P;
+
+    fn personality(&self) -> Personality {
+        let mut personality = Personality::baseline();
+        *personality.capabilities_mut() = self.info.capabilities;
+        *personality.mtu_mut() = Some(self.mtu);
+        personality
+    }
+
+    fn tx(&mut self, max: usize, mut sender: impl Send<Self::Handle, Self::Payload>)
+        -> Result<usize>
+    {
+        // All packets of the previous call have been transmitted by now.
+        self.buffer.release_all();
+        let mut count = 0;
+        self.declined = 0;
+
+        for _ in 0..max {
+            let mut flag = self.handle();
+            let buffer = match self.buffer.take() {
+                Some(buffer) => buffer,
+                None => break,
+            };
+
+            sender.send(super::Packet {
+                handle: &mut flag,
+                payload: buffer,
+            });
+
+            if flag.flag.was_sent() {
+                self.sent += 1;
+                count += 1;
+            } else {
+                self.buffer.untake();
+                self.declined += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn rx(&mut self, _: usize, _: impl Recv<Self::Handle, Self::Payload>)
+        -> Result<usize>
+    {
+        Ok(0)
+    }
+}
+
+impl<T, M: Clone> External<T, M> {
+    fn handle(&self) -> Handle<M> {
+        let flag = if self.queueable {
+            EnqueueFlag::set_true(self.info)
+        } else {
+            EnqueueFlag::not_possible(self.info)
+        };
+
+        Handle {
+            flag,
+            info: MetaInfo {
+                info: self.info,
+                received: None,
+                mtu: self.mtu,
+                metadata: self.metadata.clone(),
+            },
+        }
+    }
+}
+
+/// The largest ethernet payload that fits into the longest of the buffers.
+fn largest_mtu<P: Payload>(buffers: &[P]) -> usize {
+    buffers.iter()
+        .map(|buffer| buffer.payload().as_slice().len())
+        .max()
+        .unwrap_or(0)
+        .saturating_sub(ethernet::frame::header_len())
+}
+
+impl<M: 'static> super::Handle for Handle<M> {
     fn queue(&mut self) -> super::Result<()> {
-        self.0.queue()
+        self.flag.queue()
     }
 
     fn info(&self) -> &dyn Info {
-        self.0.info()
+        &self.info
+    }
+}
+
+impl<M: 'static> Info for MetaInfo<M> {
+    fn timestamp(&self) -> Instant {
+        self.info.timestamp()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.info.capabilities()
+    }
+
+    fn hardware_timestamp(&self) -> Option<Instant> {
+        self.received
+    }
+
+    fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    fn metadata(&self) -> Option<&dyn Any> {
+        Some(&self.metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::eth;
+    use crate::nic::tests::LengthIo;
+    use crate::wire::ethernet;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct IngressPort(u16);
+
+    #[test]
+    fn batched_send() {
+        let mut nic = External::new_send(vec![vec![0; 64]; 4]);
+        assert_eq!(nic.tx(3, LengthIo), Ok(3));
+        assert_eq!(nic.queued().len(), 3);
+
+        // Only a single buffer remains.
+        assert_eq!(nic.tx(3, LengthIo), Ok(1));
+        assert_eq!(nic.tx(3, LengthIo), Ok(0));
+        // Because no buffer was left, not because the sender declined one.
+        assert_eq!(nic.declined(), 0);
+    }
+
+    #[test]
+    fn refused_send() {
+        const MAC_ADDR: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+
+        let mut nic = External::new_send(vec![vec![0; 64]; 2]);
+        nic.set_queueable(false);
+
+        let mut eth = eth::Endpoint::new(MAC_ADDR);
+        let sent = nic.tx(1, eth.send_with(|packet: eth::RawPacket<_>| {
+            let init = eth::Init {
+                src_addr: MAC_ADDR,
+                dst_addr: ethernet::Address::BROADCAST,
+                ethertype: ethernet::EtherType::Unknown(0xBEEF),
+                vlan: None,
+                payload: 16,
+            };
+            let prepared = packet.prepare(init).expect("Buffer large enough");
+            assert_eq!(prepared.send(), Err(crate::layer::Error::Exhausted));
+        }));
+        assert_eq!(sent, Ok(0));
+        assert_eq!(nic.declined(), 1);
+        assert_eq!(nic.to_send(), 2);
+
+        nic.set_queueable(true);
+        assert_eq!(nic.tx(1, LengthIo), Ok(1));
+        assert_eq!(nic.declined(), 0);
+    }
+
+    #[test]
+    fn pooled_send() {
+        let mut nic = External::new_pooled(Pool::new(vec![vec![0; 64]; 2]));
+
+        for _ in 0..10 {
+            assert_eq!(nic.tx(1, LengthIo), Ok(1));
+            assert_eq!(nic.pool().in_flight().len(), 1);
+        }
+
+        // No more than the buffers of the pool are sent at once.
+        assert_eq!(nic.tx(3, LengthIo), Ok(2));
+        assert_eq!(nic.pool().available(), 0);
+        assert_eq!(nic.tx(3, LengthIo), Ok(2));
+        assert_eq!(nic.sent(), 14);
+        assert_eq!(nic.pool().capacity(), 2);
+    }
+
+    #[test]
+    fn ingress_port_metadata() {
+        const MAC_ADDR: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+
+        let mut nic = External::new_recv(vec![vec![0; 64], vec![0; 64]])
+            .with_metadata(IngressPort(0));
+        for idx in 0..2 {
+            let frame = ethernet::frame::new_unchecked_mut(nic.get_mut(idx).unwrap());
+            frame.set_dst_addr(MAC_ADDR);
+            frame.set_src_addr(ethernet::Address([6, 5, 4, 3, 2, 1]));
+            frame.set_ethertype(ethernet::EtherType::Unknown(0xBEEF));
+        }
+
+        let mut eth = eth::Endpoint::new(MAC_ADDR);
+        let mut ports = vec![];
+
+        for port in [3, 7].iter() {
+            nic.set_metadata(IngressPort(*port));
+            let recv = nic.rx(1, eth.recv_with(|frame: eth::InPacket<_>| {
+                let port = frame.control.info()
+                    .metadata()
+                    .and_then(|metadata| metadata.downcast_ref::<IngressPort>())
+                    .copied();
+                ports.push(port);
+            }));
+            assert_eq!(recv, Ok(1));
+        }
+
+        assert_eq!(ports, [Some(IngressPort(3)), Some(IngressPort(7))]);
     }
 }
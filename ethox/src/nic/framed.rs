@@ -0,0 +1,331 @@
+//! A device wrapper running the ethernet based layers on a layer 3 device.
+//!
+//! Tunnels and TUN devices exchange bare IP packets, without any link layer header. The layers of
+//! the stack on the other hand always start with an ethernet frame. The wrapper translates between
+//! the two by adding a header to each received packet and removing it again from each queued
+//! frame, such that no ethernet bytes ever reach the device.
+use crate::wire::{ethernet, ip, Payload, PayloadMut, Reframe};
+
+use super::{Device, Info, Medium, Packet, Personality, Recv, Send, Result};
+
+/// A layer 3 device presented as an ethernet device.
+///
+/// Received packets are moved back in their buffer to make room for an ethernet header, which is
+/// addressed to `hw_addr` such that an ethernet endpoint with this address accepts it. The
+/// ethertype is chosen by the version of the packet and packets of any other version are dropped
+/// before reaching the layers.
+///
+/// Frames are converted after the sender returns. A request to queue a frame is recorded and only
+/// forwarded to the device once the header and any ethernet padding was removed. Frames that do
+/// not carry an untagged IPv4 or IPv6 packet, such as ARP, can not be represented on the device
+/// and are dropped instead. Packets whose queueing the device refuses at that point are dropped as
+/// well. All kinds of drops are counted in [`dropped`].
+///
+/// The handle reports [`Medium::Ip`] for all packets. The ip layer then does not resolve the
+/// hardware addresses of neighbors, which would be meaningless without a link layer.
+///
+/// [`dropped`]: #method.dropped
+/// [`Medium::Ip`]: ../enum.Medium.html#variant.Ip
+pub struct Framed<D> {
+    device: D,
+    hw_addr: ethernet::Address,
+    dropped: usize,
+}
+
+/// The [`nic::Handle`] of [`Framed`].
+///
+/// Holds a copy of the information of the underlying handle, the request to queue is only applied
+/// to the underlying handle after the conversion. Device specific metadata can not be copied and is
+/// not available through this handle.
+///
+/// [`nic::Handle`]: ../trait.Handle.html
+/// [`Framed`]: struct.Framed.html
+pub struct Handle {
+    timestamp: crate::time::Instant,
+    hardware_timestamp: Option<crate::time::Instant>,
+    capabilities: super::Capabilities,
+    mtu: usize,
+    requested: bool,
+}
+
+/// Wraps the sender to remove the ethernet header of queued frames.
+struct Strip<'a, S> {
+    inner: S,
+    dropped: &'a mut usize,
+}
+
+/// Wraps the receiver to add an ethernet header to received packets.
+struct Prepend<'a, R> {
+    inner: R,
+    hw_addr: ethernet::Address,
+    dropped: &'a mut usize,
+}
+
+impl<D> Framed<D> {
+    /// Present the layer 3 `device` to an ethernet endpoint with address `hw_addr`.
+    pub fn new(device: D, hw_addr: ethernet::Address) -> Self {
+        Framed {
+            device,
+            hw_addr,
+            dropped: 0,
+        }
+    }
+
+    /// The number of packets and frames that could not be converted.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Get a reference to the underlying device.
+    pub fn inner(&self) -> &D {
+        &self.device
+    }
+
+    /// Get a mutable reference to the underlying device.
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    /// Unwrap the underlying device.
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+}
+
+impl Handle {
+    fn new<H: super::Handle + ?Sized>(handle: &H) -> Self {
+        let info = handle.info();
+        Handle {
+            timestamp: info.timestamp(),
+            hardware_timestamp: info.hardware_timestamp(),
+            capabilities: info.capabilities(),
+            mtu: info.mtu(),
+            requested: false,
+        }
+    }
+}
+
+/// The length of the ip packet at the start of a buffer, excluding any trailing bytes.
+fn ip_len(packet: &[u8]) -> Option<usize> {
+    match packet.first()? >> 4 {
+        4 => ip::v4::packet::new_checked(packet).ok().map(|packet| packet.total_len().into()),
+        6 => ip::v6::packet::new_checked(packet).ok().map(|packet| packet.total_len()),
+        _ => None,
+    }
+}
+
+/// Remove the ethernet header and padding of a frame, leaving only the ip packet.
+fn strip<P: PayloadMut + ?Sized>(payload: &mut P) -> Option<()> {
+    let header_len = ethernet::frame::header_len();
+    let frame = ethernet::frame::new_checked(payload.payload().as_slice()).ok()?;
+    match frame.ethertype() {
+        ethernet::EtherType::Ipv4 | ethernet::EtherType::Ipv6 => (),
+        _ => return None,
+    }
+
+    let len = ip_len(frame.payload_slice())?;
+    let bytes = payload.payload_mut().as_mut_slice();
+    bytes.copy_within(header_len..header_len + len, 0);
+    payload.reframe(Reframe { length: len, range: 0..len }).ok()
+}
+
+/// Move an ip packet back in its buffer and put an ethernet header in front of it.
+fn prepend<P: PayloadMut + ?Sized>(payload: &mut P, hw_addr: ethernet::Address) -> Option<()> {
+    let header_len = ethernet::frame::header_len();
+    let packet = payload.payload().as_slice();
+    let ethertype = match packet.first()? >> 4 {
+        4 => ethernet::EtherType::Ipv4,
+        6 => ethernet::EtherType::Ipv6,
+        _ => return None,
+    };
+
+    let len = packet.len();
+    payload.reframe(Reframe { length: header_len + len, range: 0..len }).ok()?;
+    let bytes = payload.payload_mut().as_mut_slice();
+    bytes.copy_within(0..len, header_len);
+
+    let frame = ethernet::frame::new_unchecked_mut(bytes);
+    frame.set_dst_addr(hw_addr);
+    frame.set_src_addr(ethernet::Address([0; 6]));
+    frame.set_ethertype(ethertype);
+    Some(())
+}
+
+/// Forward a frame whose queueing was requested, without its ethernet header.
+fn forward<H, P>(requested: bool, packet: Packet<H, P>, dropped: &mut usize)
+where
+    H: super::Handle + ?Sized,
+    P: PayloadMut + ?Sized,
+{
+    if !requested {
+        return;
+    }
+
+    let queued = match strip(packet.payload) {
+        Some(()) => packet.handle.queue().is_ok(),
+        None => false,
+    };
+
+    if !queued {
+        *dropped += 1;
+    }
+}
+
+impl<H, P, S> Send<H, P> for Strip<'_, S>
+where
+    H: super::Handle + ?Sized,
+    P: PayloadMut + ?Sized,
+    S: Send<Handle, P>,
+{
+    fn send(&mut self, packet: Packet<H, P>) {
+        let mut handle = Handle::new(&*packet.handle);
+        self.inner.send(Packet {
+            handle: &mut handle,
+            payload: &mut *packet.payload,
+        });
+
+        forward(handle.requested, packet, self.dropped);
+    }
+}
+
+impl<H, P, R> Recv<H, P> for Prepend<'_, R>
+where
+    H: super::Handle + ?Sized,
+    P: PayloadMut + ?Sized,
+    R: Recv<Handle, P>,
+{
+    fn receive(&mut self, packet: Packet<H, P>) {
+        if prepend(&mut *packet.payload, self.hw_addr).is_none() {
+            *self.dropped += 1;
+            return;
+        }
+
+        let mut handle = Handle::new(&*packet.handle);
+        self.inner.receive(Packet {
+            handle: &mut handle,
+            payload: &mut *packet.payload,
+        });
+
+        // Answered in place.
+        forward(handle.requested, packet, self.dropped);
+    }
+}
+
+impl super::Handle for Handle {
+    fn queue(&mut self) -> Result<()> {
+        self.requested = true;
+        Ok(())
+    }
+
+    fn info(&self) -> &dyn Info {
+        self
+    }
+}
+
+impl Info for Handle {
+    fn timestamp(&self) -> crate::time::Instant {
+        self.timestamp
+    }
+
+    fn hardware_timestamp(&self) -> Option<crate::time::Instant> {
+        self.hardware_timestamp
+    }
+
+    fn capabilities(&self) -> super::Capabilities {
+        self.capabilities
+    }
+
+    fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    fn medium(&self) -> Medium {
+        Medium::Ip
+    }
+}
+
+impl<D> Device for Framed<D>
+where
+    D: Device,
+    D::Payload: PayloadMut,
+{
+    type Handle = Handle;
+    type Payload = D::Payload;
+
+    fn personality(&self) -> Personality {
+        let mut personality = self.device.personality();
+        *personality.medium_mut() = Medium::Ethernet;
+        personality
+    }
+
+    fn tx(&mut self, max: usize, sender: impl Send<Self::Handle, Self::Payload>)
+        -> Result<usize>
+    {
+        self.device.tx(max, Strip {
+            inner: sender,
+            dropped: &mut self.dropped,
+        })
+    }
+
+    fn rx(&mut self, max: usize, receptor: impl Recv<Self::Handle, Self::Payload>)
+        -> Result<usize>
+    {
+        self.device.rx(max, Prepend {
+            inner: receptor,
+            hw_addr: self.hw_addr,
+            dropped: &mut self.dropped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nic::external::External;
+    use crate::nic::tests::LengthIo;
+
+    #[test]
+    fn drops_non_ip() {
+        const MAC_ADDR: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+
+        // Writes some frame which is not ip.
+        let mut nic = Framed::new(External::new_send(vec![vec![0; 64]; 1]), MAC_ADDR);
+        assert_eq!(nic.tx(1, LengthIo), Ok(0));
+        assert_eq!(nic.dropped(), 1);
+
+        let mut nic = Framed::new(External::new_recv(vec![vec![0xff; 64]; 1]), MAC_ADDR);
+        let mut received = 0;
+        let recv = nic.rx(1, crate::layer::FnHandler(|_: Packet<_, Vec<u8>>| received += 1));
+        assert_eq!(recv, Ok(1));
+        assert_eq!(received, 0);
+        assert_eq!(nic.dropped(), 1);
+    }
+
+    #[test]
+    fn counts_refused() {
+        const MAC_ADDR: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+
+        // Queues an ethernet frame with a bare ipv4 header.
+        let send_ip = |packet: Packet<Handle, Vec<u8>>| {
+            let bytes = packet.payload.as_mut_slice();
+            let frame = ethernet::frame::new_unchecked_mut(&mut bytes[..]);
+            frame.set_ethertype(ethernet::EtherType::Ipv4);
+            let header = &mut frame.payload_mut_slice()[..20];
+            header.copy_from_slice(&[0; 20]);
+            header[0] = 0x45;
+            header[3] = 20;
+            assert_eq!(super::super::Handle::queue(packet.handle), Ok(()));
+        };
+
+        let mut nic = Framed::new(External::new_send(vec![vec![0; 64]; 1]), MAC_ADDR);
+        assert_eq!(nic.tx(1, crate::layer::FnHandler(send_ip)), Ok(1));
+        assert_eq!(nic.inner().queued().len(), 1);
+        assert_eq!(nic.dropped(), 0);
+
+        // The device refuses, the packet is lost.
+        let mut nic = Framed::new(External::new_send(vec![vec![0; 64]; 1]), MAC_ADDR);
+        nic.inner_mut().set_queueable(false);
+        assert_eq!(nic.tx(1, crate::layer::FnHandler(send_ip)), Ok(0));
+        assert_eq!(nic.dropped(), 1);
+    }
+}
@@ -1,19 +1,25 @@
 //! Implementation of a software loop-back device.
+#[cfg(feature = "alloc")]
+use crate::alloc::vec::Vec;
+use crate::layer::Error;
 use crate::managed::Slice;
 use crate::time::Instant;
 use crate::wire::PayloadMut;
 
 use super::common::{EnqueueFlag, PacketInfo};
-use super::{Capabilities, Info, Personality, Recv, Send, Result};
+use super::{Capabilities, Info, Medium, Personality, Recv, Send, Result};
 
 /// A software loop-back device.
 ///
-/// Maintains a ring buffer of packet buffers in flight.
+/// Maintains a ring buffer of packet buffers in flight. Each frame queued in `tx` is handed back
+/// unchanged on a later `rx`, in the order they were sent. Sending fails with `Exhausted` while
+/// all buffers of the ring are in flight.
 pub struct Loopback<'r, C> {
     buffer: Slice<'r, C>,
     next_recv: usize,
     sent: usize,
     info: PacketInfo,
+    medium: Medium,
 }
 
 /// A newtype wrapper for the `nic::Handle` of `Loopback`.
@@ -27,10 +33,9 @@ struct AckRecv<'a>(&'a mut usize, usize, &'a mut usize);
 struct AckSend<'a>(&'a mut usize);
 
 impl<'r, C: PayloadMut> Loopback<'r, C> {
-    /// Create a loop-back device with mtu.
+    /// Create a loop-back device using each of the buffers as one slot of its ring.
     ///
-    /// It will divide the packet buffer into chunks of the provided mtu and keep track of free and
-    /// used buffers
+    /// The number of buffers determines the number of frames that can be in flight at once.
     pub fn new(buffer: Slice<'r, C>) -> Self {
         Loopback {
             buffer,
@@ -40,6 +45,7 @@ impl<'r, C: PayloadMut> Loopback<'r, C> {
                 timestamp: Instant::from_millis(0),
                 capabilities: Capabilities::no_support(),
             },
+            medium: Medium::Ethernet,
         }
     }
 
@@ -48,6 +54,16 @@ impl<'r, C: PayloadMut> Loopback<'r, C> {
         self.info.timestamp = instant;
     }
 
+    /// Change the medium advertised by the device.
+    ///
+    /// Frames are looped back unchanged regardless, this only affects the [`Personality`]. Use
+    /// `Medium::Ip` to emulate a layer 3 device.
+    ///
+    /// [`Personality`]: ../struct.Personality.html
+    pub fn set_medium(&mut self, medium: Medium) {
+        self.medium = medium;
+    }
+
     fn next_recv(&mut self) -> Option<(AckRecv, &mut C)> {
         if self.sent == 0 {
             return None
@@ -90,12 +106,28 @@ impl<'r, C: PayloadMut> Loopback<'r, C> {
     }
 }
 
+#[cfg(feature = "alloc")]
+impl Loopback<'_, Vec<u8>> {
+    /// Create a loop-back device with a ring of `capacity` frames.
+    ///
+    /// Each buffer is allocated up-front with room for frames of `len` bytes and reused for all
+    /// frames afterwards. The buffers shrink or grow to the exact length of each frame.
+    pub fn with_capacity(capacity: usize, len: usize) -> Self {
+        let buffers = (0..capacity)
+            .map(|_| Vec::with_capacity(len))
+            .collect();
+        Loopback::new(Slice::Many(buffers))
+    }
+}
+
 impl<'a, C: PayloadMut> super::Device for Loopback<'a, C> {
     type Handle = Handle;
     type Payload = C;
 
     fn personality(&self) -> Personality {
-        Personality::baseline()
+        let mut personality = Personality::baseline();
+        *personality.medium_mut() = self.medium;
+        personality
     }
 
     fn tx(&mut self, max: usize, mut sender: impl Send<Self::Handle, Self::Payload>)
@@ -106,6 +138,8 @@ impl<'a, C: PayloadMut> super::Device for Loopback<'a, C> {
 
         for _ in 0..max {
             let (ack, packet) = match self.next_send() {
+                // The ring overflowed before any frame could be sent.
+                None if count == 0 => return Err(Error::Exhausted),
                 None => return Ok(count),
                 Some(packet) => packet,
             };
@@ -199,4 +233,18 @@ mod tests {
         assert_eq!(loopback.tx(1, length_io), Ok(1));
         assert_eq!(loopback.rx(1, length_io), Ok(1));
     }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn overflow() {
+        let mut loopback = Loopback::with_capacity(2, 64);
+        let length_io = crate::nic::tests::LengthIo;
+        assert_eq!(loopback.tx(3, length_io), Ok(2));
+        assert_eq!(loopback.tx(1, length_io), Err(Error::Exhausted));
+
+        // Receiving frees a slot of the ring again.
+        assert_eq!(loopback.rx(1, length_io), Ok(1));
+        assert_eq!(loopback.tx(1, length_io), Ok(1));
+        assert_eq!(loopback.rx(3, length_io), Ok(2));
+    }
 }
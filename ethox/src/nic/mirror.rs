@@ -0,0 +1,277 @@
+//! A device wrapper copying all traffic to a second device.
+//!
+//! This is comparable to a port mirror (SPAN) on a switch. All received frames and all frames
+//! queued for transmission on the primary device are additionally transmitted on the sink device.
+//! In contrast to a packet capture the sink may be a live device itself.
+use crate::wire::{Payload, PayloadMut};
+
+use super::{Device, Handle as _, Info, Packet, Personality, Recv, Send, Result};
+
+/// A device that mirrors the traffic of another device to a sink.
+///
+/// All operations are forwarded to the primary device `D`. Additionally, each frame received and
+/// each frame queued for sending is copied into a buffer of the sink `S` and queued there. The
+/// primary buffer is only ever read by the mirror and its handle behaves exactly as the handle of
+/// the primary device, so upper layers are not affected by the presence of the mirror.
+///
+/// Mirroring is best-effort. When the sink has no free buffer or can not fit the frame, the copy
+/// is dropped and counted in [`missed`].
+///
+/// [`missed`]: #method.missed
+pub struct Mirror<D, S> {
+    primary: D,
+    sink: S,
+    missed: usize,
+}
+
+/// The [`nic::Handle`] of [`Mirror`].
+///
+/// This pretends to be static but internally wraps a reference to the underlying handle. The
+/// reasoning is the same as for [`LossyHandle`]: the `Device` trait requires a *single* associated
+/// type as the handle which can not include a lifetime parameter. The handle is only ever visible
+/// behind a mutable reference with the lifetime of the wrapped handle and can not be copied.
+///
+/// [`nic::Handle`]: ../trait.Handle.html
+/// [`Mirror`]: struct.Mirror.html
+/// [`LossyHandle`]: ../../layer/loss/struct.LossyHandle.html
+pub struct Handle<H: ?Sized> {
+    handle: *mut H,
+    queued: bool,
+}
+
+/// Wraps a receiver or sender of the primary device.
+struct Tap<'a, I, S> {
+    inner: I,
+    sink: &'a mut S,
+    missed: &'a mut usize,
+}
+
+impl<D, S> Mirror<D, S> {
+    /// Mirror all traffic of `primary` to `sink`.
+    pub fn new(primary: D, sink: S) -> Self {
+        Mirror {
+            primary,
+            sink,
+            missed: 0,
+        }
+    }
+
+    /// Get a reference to the primary device.
+    pub fn primary(&self) -> &D {
+        &self.primary
+    }
+
+    /// Get a mutable reference to the primary device.
+    pub fn primary_mut(&mut self) -> &mut D {
+        &mut self.primary
+    }
+
+    /// Get a reference to the sink device.
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Get a mutable reference to the sink device.
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// The number of frames that could not be copied to the sink.
+    pub fn missed(&self) -> usize {
+        self.missed
+    }
+
+    /// Unwrap the primary and the sink device.
+    pub fn into_inner(self) -> (D, S) {
+        (self.primary, self.sink)
+    }
+}
+
+impl<H: ?Sized> Handle<H> {
+    fn new(handle: &mut H) -> Self {
+        Handle {
+            handle,
+            queued: false,
+        }
+    }
+}
+
+impl<'a, I, S> Tap<'a, I, S>
+where
+    S: Device,
+    S::Payload: PayloadMut,
+{
+    /// Copy one frame into a buffer of the sink.
+    fn copy(&mut self, frame: &[u8]) {
+        let mut done = false;
+        let result = self.sink.tx(1, crate::layer::FnHandler(|packet: Packet<S::Handle, S::Payload>| {
+            if packet.payload.resize(frame.len()).is_err() {
+                return;
+            }
+
+            packet.payload.payload_mut()
+                .as_mut_slice()
+                .copy_from_slice(frame);
+            done = packet.handle.queue().is_ok();
+        }));
+
+        if result != Ok(1) || !done {
+            *self.missed += 1;
+        }
+    }
+}
+
+impl<H, P, I, S> Recv<H, P> for Tap<'_, I, S>
+where
+    H: super::Handle + ?Sized,
+    P: Payload + ?Sized,
+    I: Recv<Handle<H>, P>,
+    S: Device,
+    S::Payload: PayloadMut,
+{
+    fn receive(&mut self, packet: Packet<H, P>) {
+        // Copy before the receiver gets the chance to modify the buffer in-place.
+        self.copy(packet.payload.payload().as_slice());
+
+        let mut handle = Handle::new(packet.handle);
+        self.inner.receive(Packet {
+            handle: &mut handle,
+            payload: &mut *packet.payload,
+        });
+
+        // The buffer has been reused for an answer.
+        if handle.queued {
+            self.copy(packet.payload.payload().as_slice());
+        }
+    }
+}
+
+impl<H, P, I, S> Send<H, P> for Tap<'_, I, S>
+where
+    H: super::Handle + ?Sized,
+    P: Payload + ?Sized,
+    I: Send<Handle<H>, P>,
+    S: Device,
+    S::Payload: PayloadMut,
+{
+    fn send(&mut self, packet: Packet<H, P>) {
+        let mut handle = Handle::new(packet.handle);
+        self.inner.send(Packet {
+            handle: &mut handle,
+            payload: &mut *packet.payload,
+        });
+
+        if handle.queued {
+            self.copy(packet.payload.payload().as_slice());
+        }
+    }
+}
+
+impl<H: super::Handle + ?Sized> super::Handle for Handle<H> {
+    fn queue(&mut self) -> Result<()> {
+        let result = unsafe { &mut *self.handle }.queue();
+        self.queued |= result.is_ok();
+        result
+    }
+
+    fn info(&self) -> &dyn Info {
+        unsafe { &*self.handle }.info()
+    }
+}
+
+impl<D, S> Device for Mirror<D, S>
+where
+    D: Device,
+    S: Device,
+    S::Payload: PayloadMut,
+{
+    type Handle = Handle<D::Handle>;
+    type Payload = D::Payload;
+
+    fn personality(&self) -> Personality {
+        self.primary.personality()
+    }
+
+    fn tx(&mut self, max: usize, sender: impl Send<Self::Handle, Self::Payload>)
+        -> Result<usize>
+    {
+        self.primary.tx(max, Tap {
+            inner: sender,
+            sink: &mut self.sink,
+            missed: &mut self.missed,
+        })
+    }
+
+    fn rx(&mut self, max: usize, receptor: impl Recv<Self::Handle, Self::Payload>)
+        -> Result<usize>
+    {
+        self.primary.rx(max, Tap {
+            inner: receptor,
+            sink: &mut self.sink,
+            missed: &mut self.missed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nic::{external::External, loopback::Loopback};
+    use crate::nic::tests::LengthIo;
+
+    #[test]
+    fn mirror_external() {
+        let primary = External::new_send(vec![vec![0; 64], vec![0; 128]]);
+        let sink = Loopback::<Vec<u8>>::new(vec![vec![], vec![]].into());
+        let mut mirror = Mirror::new(primary, sink);
+
+        assert_eq!(mirror.tx(1, LengthIo), Ok(1));
+        assert_eq!(mirror.tx(1, LengthIo), Ok(1));
+        assert_eq!(mirror.missed(), 0);
+
+        // The primary path has both frames.
+        assert_eq!(mirror.primary().to_send(), 0);
+        assert_eq!(mirror.primary().get(0).map(Vec::len), Some(64));
+        assert_eq!(mirror.primary().get(1).map(Vec::len), Some(128));
+
+        // And the mirror observed them in order with equal content.
+        let mut seen = vec![];
+        let (primary, mut sink) = mirror.into_inner();
+        assert_eq!(sink.rx(2, crate::layer::FnHandler(|packet: Packet<_, Vec<u8>>| {
+            seen.push(packet.payload.clone());
+        })), Ok(2));
+        assert_eq!(seen.len(), 2);
+        assert_eq!(Some(&seen[0]), primary.get(0));
+        assert_eq!(Some(&seen[1]), primary.get(1));
+        assert_eq!(sink.rx(2, LengthIo), Ok(0));
+    }
+
+    #[test]
+    fn mirror_receive() {
+        let mut primary = External::new_recv(vec![vec![0; 32]]);
+        primary.send_all();
+        primary.tx(1, LengthIo).unwrap();
+        primary.receive_all();
+
+        let sink = Loopback::<Vec<u8>>::new(vec![vec![]].into());
+        let mut mirror = Mirror::new(primary, sink);
+        assert_eq!(mirror.rx(1, LengthIo), Ok(1));
+        assert_eq!(mirror.primary().to_recv(), 0);
+
+        assert_eq!(mirror.sink_mut().rx(1, LengthIo), Ok(1));
+        assert_eq!(mirror.missed(), 0);
+    }
+
+    #[test]
+    fn mirror_full_sink() {
+        let primary = External::new_send(vec![vec![0; 16]]);
+        let sink = Loopback::<Vec<u8>>::new(vec![vec![]].into());
+        let mut mirror = Mirror::new(primary, sink);
+        // Fill the sink.
+        assert_eq!(mirror.sink_mut().tx(1, LengthIo), Ok(1));
+
+        // The primary path is unaffected.
+        assert_eq!(mirror.tx(1, LengthIo), Ok(1));
+        assert_eq!(mirror.missed(), 1);
+    }
+}
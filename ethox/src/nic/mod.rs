@@ -1,29 +1,44 @@
 //! Encapsulates a network interface card.
 //!
 //! Also permits software emulation or implementation of one as well, of course.
+#[cfg(feature = "std")]
+pub mod channel;
 pub mod common;
 pub mod loopback;
 pub mod external;
+pub mod framed;
+pub mod mirror;
+pub mod reserve;
 mod personality;
+mod tee;
 
 #[cfg(feature = "sys")]
 #[path="sys/mod.rs"]
 mod sys_internal;
 
+use core::any::Any;
+
 use crate::wire::Payload;
-use crate::layer::{Result, FnHandler};
+use crate::layer::{Result, FnHandler, StatefulHandler};
 #[cfg(feature = "std")]
 use crate::wire::{ethernet, pretty_print::{Formatter, PrettyPrinter}};
 use crate::time::Instant;
 
 pub use self::personality::{
     Capabilities,
+    Medium,
     Personality,
     Protocol};
 
 #[cfg(feature = "sys")]
 pub use self::sys_internal::exports as sys;
 
+#[cfg(feature = "std")]
+pub use self::channel::Channel;
+pub use self::framed::Framed;
+pub use self::mirror::Mirror;
+pub use self::reserve::Reserve;
+pub use self::tee::{recv_tee, Observe, Tee};
 pub use crate::layer::loss::{Lossy, PrngLoss};
 
 /// A reference to memory holding packet data and a handle.
@@ -74,11 +89,47 @@ pub trait Info {
     /// The reference time stamp for this packet.
     fn timestamp(&self) -> Instant;
 
+    /// The time at which the device received the packet, if it records one.
+    ///
+    /// Unlike the reference `timestamp` this is taken by the hardware upon arrival and is suited
+    /// for latency measurements. Not provided by default.
+    fn hardware_timestamp(&self) -> Option<Instant> {
+        None
+    }
+
     /// Capabilities used for the packet.
     ///
     /// Indicates pre-checked checksums for incoming packets and hardware support for checksums of
     /// outgoing packets across the layers of the network stack.
     fn capabilities(&self) -> Capabilities;
+
+    /// The maximum transmission unit for the packet buffer.
+    ///
+    /// As for the [`Personality`], this is the largest payload of a frame and excludes the
+    /// ethernet header. Layers check it before initializing their headers. Unlimited by default.
+    ///
+    /// [`Personality`]: struct.Personality.html#method.mtu
+    fn mtu(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Additional device specific metadata of the packet.
+    ///
+    /// This can be used to pass information such as the ingress port to upper layers that know
+    /// about the concrete device, by downcasting to the expected type. No metadata is provided
+    /// by default.
+    fn metadata(&self) -> Option<&dyn Any> {
+        None
+    }
+
+    /// The medium on which the packet is transmitted.
+    ///
+    /// On an `Ip` medium the ethernet header of the buffer never reaches the wire, so its
+    /// addresses are placeholders and the layers do not resolve hardware addresses. Ethernet by
+    /// default.
+    fn medium(&self) -> Medium {
+        Medium::Ethernet
+    }
 }
 
 /// A layer 2 device.
@@ -105,6 +156,9 @@ pub trait Device {
     /// Up to `max` packet buffers are chosen by the device. They are provided to the sender callback
     /// which may initialize their contents and decide to queue them. Afterwards, the device is
     /// responsible for cleaning up unused buffers and physically sending queued buffers.
+    ///
+    /// Devices should offer as many buffers as they have available, up to `max`, within a single
+    /// call such that senders can batch their packets. Returns the number of queued buffers.
     fn tx(&mut self, max: usize, sender: impl Send<Self::Handle, Self::Payload>)
         -> Result<usize>;
 
@@ -168,6 +222,22 @@ impl<F, H: Handle + ?Sized, P: Payload + ?Sized> Send<H, P> for FnHandler<F>
     }
 }
 
+impl<S, F, H: Handle + ?Sized, P: Payload + ?Sized> Recv<H, P> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, Packet<H, P>)
+{
+    fn receive(&mut self, packet: Packet<H, P>) {
+        (self.handler)(&mut self.state, packet)
+    }
+}
+
+impl<S, F, H: Handle + ?Sized, P: Payload + ?Sized> Send<H, P> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, Packet<H, P>)
+{
+    fn send(&mut self, packet: Packet<H, P>) {
+        (self.handler)(&mut self.state, packet)
+    }
+}
+
 impl<F, H: Handle + ?Sized, P: Payload + ?Sized> Recv<H, P> for &'_ mut F
     where F: Recv<H, P>
 {
@@ -208,7 +278,7 @@ impl<H: Handle + ?Sized, P: Payload + ?Sized> Recv<H, P> for Formatter<ethernet:
 
 /// Some base types and methods for other tests.
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::wire::PayloadMut;
 
@@ -9,6 +9,24 @@ use crate::wire::{ip, udp, tcp, Checksum};
 #[derive(Clone, Debug)]
 pub struct Personality {
     capabilities: Capabilities,
+    mtu: Option<usize>,
+    medium: Medium,
+}
+
+/// The kind of frames a device exchanges.
+///
+/// The layers of the stack always operate on ethernet frames. Devices on an `Ip` medium, such as
+/// tunnels and TUN devices, carry bare IP packets instead and are used through the [`Framed`]
+/// adapter.
+///
+/// [`Framed`]: framed/struct.Framed.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Medium {
+    /// Ethernet II frames, a layer 2 device.
+    #[default]
+    Ethernet,
+    /// IPv4 and IPv6 packets without any link layer header, a layer 3 device.
+    Ip,
 }
 
 /// Operations supported natively by the card.
@@ -22,6 +40,7 @@ pub struct Capabilities {
     icmpv4: Protocol,
     udp: Udp,
     tcp: Tcp,
+    vrrp: Protocol,
 }
 
 /// The extent of support for a specific protocol.
@@ -67,6 +86,8 @@ impl Personality {
     pub fn baseline() -> Self {
         Personality {
             capabilities: Capabilities::no_support(),
+            mtu: None,
+            medium: Medium::Ethernet,
         }
     }
 
@@ -79,6 +100,28 @@ impl Personality {
     pub fn capabilities_mut(&mut self) -> &mut Capabilities {
         &mut self.capabilities
     }
+
+    /// The maximum transmission unit of the interface, if known.
+    ///
+    /// As usual, this is the largest payload of a frame and excludes the ethernet header.
+    pub fn mtu(&self) -> Option<usize> {
+        self.mtu
+    }
+
+    /// Mutably get the maximum transmission unit which allows for modifications.
+    pub fn mtu_mut(&mut self) -> &mut Option<usize> {
+        &mut self.mtu
+    }
+
+    /// The kind of frames sent and received by the device, ethernet by default.
+    pub fn medium(&self) -> Medium {
+        self.medium
+    }
+
+    /// Mutably get the medium which allows for modifications.
+    pub fn medium_mut(&mut self) -> &mut Medium {
+        &mut self.medium
+    }
 }
 
 impl Capabilities {
@@ -91,6 +134,20 @@ impl Capabilities {
             icmpv4: Protocol::no_support(),
             udp: Udp::no_support(),
             tcp: Tcp::no_support(),
+            vrrp: Protocol::no_support(),
+        }
+    }
+
+    /// Instantiates capabilities where the nic handles all checksums of all protocols.
+    ///
+    /// The stack then neither computes checksums when sending nor verifies them when receiving.
+    pub fn offloaded() -> Self {
+        Capabilities {
+            ipv4: Protocol::offloaded(),
+            icmpv4: Protocol::offloaded(),
+            udp: Udp::offloaded(),
+            tcp: Tcp::offloaded(),
+            vrrp: Protocol::offloaded(),
         }
     }
 
@@ -99,6 +156,11 @@ impl Capabilities {
         &self.icmpv4
     }
 
+    /// Mutably get ICMPv4 support descriptor.
+    pub fn icmpv4_mut(&mut self) -> &mut Protocol {
+        &mut self.icmpv4
+    }
+
     /// Check IPv4 support descriptor.
     pub fn ipv4(&self) -> &Protocol {
         &self.ipv4
@@ -128,6 +190,16 @@ impl Capabilities {
     pub fn tcp_mut(&mut self) -> &mut Tcp {
         &mut self.tcp
     }
+
+    /// Check VRRP support descriptor.
+    pub fn vrrp(&self) -> &Protocol {
+        &self.vrrp
+    }
+
+    /// Mutably get VRRP support descriptor.
+    pub fn vrrp_mut(&mut self) -> &mut Protocol {
+        &mut self.vrrp
+    }
 }
 
 impl Protocol {
@@ -182,6 +254,23 @@ impl Udp {
         }
     }
 
+    /// Create a UDP descriptor for a nic handling all checksums.
+    pub fn offloaded() -> Self {
+        Udp {
+            inner: Protocol::offloaded(),
+        }
+    }
+
+    /// Get the underlying protocol descriptor.
+    pub fn protocol(&self) -> &Protocol {
+        &self.inner
+    }
+
+    /// Mutably get the underlying protocol descriptor, to offload a single direction.
+    pub fn protocol_mut(&mut self) -> &mut Protocol {
+        &mut self.inner
+    }
+
     /// Create the `UdpChecksum` instance necessary for sending a header.
     ///
     /// The enum `UdpChecksum` controls when and how the checksum is filled in by the `wire`
@@ -201,10 +290,11 @@ impl Udp {
     ///
     /// The enum `UdpChecksum` controls when and how the checksum is filled in by the `wire`
     /// portion of the library. This creates an instance which corresponds to the requirements of
-    /// the nic.
+    /// the nic. A manual checksum is verified whenever present, that is always except for an
+    /// omitted checksum in UDP-over-IPv4.
     pub fn rx_checksum(&self, ip: ip::Repr) -> udp::Checksum {
         match self.inner.rx_checksum() {
-            Checksum::Manual => udp::Checksum::Lazy {
+            Checksum::Manual => udp::Checksum::Manual {
                 src_addr: ip.src_addr(),
                 dst_addr: ip.dst_addr(),
             },
@@ -221,6 +311,23 @@ impl Tcp {
         }
     }
 
+    /// Create a TCP descriptor for a nic handling all checksums.
+    pub fn offloaded() -> Self {
+        Tcp {
+            inner: Protocol::offloaded(),
+        }
+    }
+
+    /// Get the underlying protocol descriptor.
+    pub fn protocol(&self) -> &Protocol {
+        &self.inner
+    }
+
+    /// Mutably get the underlying protocol descriptor, to offload a single direction.
+    pub fn protocol_mut(&mut self) -> &mut Protocol {
+        &mut self.inner
+    }
+
     /// Create the `UdpChecksum` instance necessary for sending a header.
     ///
     /// The enum `UdpChecksum` controls when and how the checksum is filled in by the `wire`
@@ -0,0 +1,428 @@
+//! A device wrapper reserving buffers for control traffic.
+//!
+//! When all layers draw their outgoing buffers from the same device, a layer flooding the link
+//! with data could leave no buffer for address resolution or ICMP. The wrapper restricts data
+//! traffic to a quota of the buffer pool so that the remainder is always available for control
+//! traffic.
+use crate::layer::Error;
+use crate::wire::{ethernet, ip, Payload};
+
+use super::{Device, Info, Packet, Personality, Recv, Send, Result};
+
+/// A device keeping some of its send buffers for control traffic.
+///
+/// Of a pool of `capacity` send buffers, data traffic may only have all but `reserved` in flight.
+/// Control traffic (ARP, ICMP and ICMPv6) may queue any buffer. Buffers reused for an answer
+/// during `rx` are not restricted.
+///
+/// A device created with [`new`] transmits its buffers synchronously, all data frames are done
+/// when its `tx` returns and the quota applies to each call on its own. Devices that transmit in
+/// the background, such as a ring of DMA descriptors, are wrapped with [`with_release`] instead.
+/// Their data frames count as in flight until the owner of the device gives them back with
+/// [`release`], for example when the completion of a transmit queue is observed.
+///
+/// Frames are classified when queued, the frame must be complete at that point. Once the quota
+/// is exhausted, queueing a data frame fails with `Exhausted` and it is counted in [`denied`].
+///
+/// [`new`]: #method.new
+/// [`with_release`]: #method.with_release
+/// [`release`]: #method.release
+/// [`denied`]: #method.denied
+pub struct Reserve<D> {
+    device: D,
+    capacity: usize,
+    reserved: usize,
+    in_flight: usize,
+    denied: usize,
+    synchronous: bool,
+}
+
+/// The [`nic::Handle`] of [`Reserve`].
+///
+/// Wraps a reference to the handle of the underlying device, see [`mirror::Handle`] for why this
+/// uses a pointer. It also points to the buffer of the packet to classify the frame when it is
+/// queued. The buffer is only read, through the same reborrow which the sender writes to.
+///
+/// [`nic::Handle`]: ../trait.Handle.html
+/// [`Reserve`]: struct.Reserve.html
+/// [`mirror::Handle`]: ../mirror/struct.Handle.html
+pub struct Handle<H: ?Sized, P: ?Sized> {
+    handle: *mut H,
+    payload: *const P,
+    mode: Mode,
+    /// A data frame was queued on the device.
+    queued_data: bool,
+    /// A data frame was refused as the quota was exhausted.
+    denied: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    /// Answers in place, queueing is forwarded directly.
+    Answer,
+    /// Sending, data frames are refused if `limited`.
+    Send { limited: bool },
+}
+
+/// Wraps the sender to enforce the quota.
+struct Share<'a, S> {
+    inner: S,
+    quota: usize,
+    in_flight: &'a mut usize,
+    denied: &'a mut usize,
+}
+
+/// Passes received buffers through without restrictions.
+struct Pass<R>(R);
+
+impl<D> Reserve<D> {
+    /// Reserve `reserved` of the `capacity` send buffers of a synchronous device.
+    ///
+    /// Buffers are released automatically after each call to `tx`.
+    pub fn new(device: D, capacity: usize, reserved: usize) -> Self {
+        Reserve {
+            device,
+            capacity,
+            reserved,
+            in_flight: 0,
+            denied: 0,
+            synchronous: true,
+        }
+    }
+
+    /// Reserve `reserved` of the `capacity` send buffers of a device transmitting in the
+    /// background.
+    ///
+    /// Sent data frames must be given back with [`release`].
+    ///
+    /// [`release`]: #method.release
+    pub fn with_release(device: D, capacity: usize, reserved: usize) -> Self {
+        Reserve {
+            synchronous: false,
+            ..Reserve::new(device, capacity, reserved)
+        }
+    }
+
+    /// The number of buffers reserved for control traffic.
+    pub fn reserved(&self) -> usize {
+        self.reserved
+    }
+
+    /// Change the number of reserved buffers.
+    pub fn set_reserved(&mut self, reserved: usize) {
+        self.reserved = reserved;
+    }
+
+    /// The number of data frames queued and not yet released.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Return buffers of sent data frames to the pool.
+    ///
+    /// Only required for devices wrapped with [`with_release`].
+    ///
+    /// [`with_release`]: #method.with_release
+    pub fn release(&mut self, count: usize) {
+        self.in_flight = self.in_flight.saturating_sub(count);
+    }
+
+    /// The number of data frames that were refused as they exceeded the quota.
+    pub fn denied(&self) -> usize {
+        self.denied
+    }
+
+    /// Get a reference to the underlying device.
+    pub fn inner(&self) -> &D {
+        &self.device
+    }
+
+    /// Get a mutable reference to the underlying device.
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    /// Unwrap the underlying device.
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+}
+
+/// Check if a frame carries control traffic.
+fn is_control(frame: &[u8]) -> bool {
+    let frame = match ethernet::frame::new_checked(frame) {
+        Ok(frame) => frame,
+        Err(_) => return false,
+    };
+
+    let (protocol, control) = match frame.ethertype() {
+        ethernet::EtherType::Arp => return true,
+        ethernet::EtherType::Ipv4 => match ip::v4::packet::new_checked(frame.payload_slice()) {
+            Ok(packet) => (packet.protocol(), ip::Protocol::Icmp),
+            Err(_) => return false,
+        },
+        ethernet::EtherType::Ipv6 => match ip::v6::packet::new_checked(frame.payload_slice()) {
+            Ok(packet) => (packet.next_header(), ip::Protocol::Icmpv6),
+            Err(_) => return false,
+        },
+        _ => return false,
+    };
+
+    protocol == control
+}
+
+impl<H: ?Sized, P: ?Sized> Handle<H, P> {
+    fn new(handle: &mut H, payload: *mut P, mode: Mode) -> Self {
+        Handle {
+            handle,
+            payload,
+            mode,
+            queued_data: false,
+            denied: false,
+        }
+    }
+}
+
+impl<H, P, S> Send<H, P> for Share<'_, S>
+where
+    H: super::Handle + ?Sized,
+    P: Payload + ?Sized,
+    S: Send<Handle<H, P>, P>,
+{
+    fn send(&mut self, packet: Packet<H, P>) {
+        let limited = *self.in_flight >= self.quota;
+        let payload: *mut P = &mut *packet.payload;
+        let mut handle = Handle::new(&mut *packet.handle, payload, Mode::Send { limited });
+        self.inner.send(Packet {
+            handle: &mut handle,
+            payload: unsafe { &mut *payload },
+        });
+
+        if handle.queued_data {
+            *self.in_flight += 1;
+        }
+
+        if handle.denied {
+            *self.denied += 1;
+        }
+    }
+}
+
+impl<H, P, R> Recv<H, P> for Pass<R>
+where
+    H: super::Handle + ?Sized,
+    P: Payload + ?Sized,
+    R: Recv<Handle<H, P>, P>,
+{
+    fn receive(&mut self, packet: Packet<H, P>) {
+        let payload: *mut P = &mut *packet.payload;
+        let mut handle = Handle::new(&mut *packet.handle, payload, Mode::Answer);
+        self.0.receive(Packet {
+            handle: &mut handle,
+            payload: unsafe { &mut *payload },
+        });
+    }
+}
+
+impl<H: super::Handle + ?Sized, P: Payload + ?Sized> super::Handle for Handle<H, P> {
+    fn queue(&mut self) -> Result<()> {
+        let limited = match self.mode {
+            Mode::Answer => return unsafe { &mut *self.handle }.queue(),
+            Mode::Send { limited } => limited,
+        };
+
+        let data = !is_control(unsafe { &*self.payload }.payload().as_slice());
+        if data && limited {
+            self.denied = true;
+            return Err(Error::Exhausted);
+        }
+
+        let result = unsafe { &mut *self.handle }.queue();
+        self.queued_data |= data && result.is_ok();
+        result
+    }
+
+    fn info(&self) -> &dyn Info {
+        unsafe { &*self.handle }.info()
+    }
+}
+
+impl<D: Device> Device for Reserve<D> {
+    type Handle = Handle<D::Handle, D::Payload>;
+    type Payload = D::Payload;
+
+    fn personality(&self) -> Personality {
+        self.device.personality()
+    }
+
+    fn tx(&mut self, max: usize, sender: impl Send<Self::Handle, Self::Payload>)
+        -> Result<usize>
+    {
+        let result = self.device.tx(max, Share {
+            inner: sender,
+            quota: self.capacity.saturating_sub(self.reserved),
+            in_flight: &mut self.in_flight,
+            denied: &mut self.denied,
+        });
+
+        // All frames of a synchronous device have been transmitted by now.
+        if self.synchronous {
+            self.in_flight = 0;
+        }
+
+        result
+    }
+
+    fn rx(&mut self, max: usize, receptor: impl Recv<Self::Handle, Self::Payload>)
+        -> Result<usize>
+    {
+        self.device.rx(max, Pass(receptor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::managed::Slice;
+    use crate::nic::external::External;
+    use crate::layer::{arp, eth, ip as ip_layer, udp};
+    use crate::wire::{ethernet, PayloadMut};
+    use crate::wire::ip::{v4, Cidr, Subnet};
+
+    const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+    const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+    const MAC_ADDR_OTHER: ethernet::Address = ethernet::Address([6, 5, 4, 3, 2, 1]);
+    const IP_ADDR_OTHER: v4::Address = v4::Address::new(10, 0, 0, 2);
+    const IP_ADDR_UNKNOWN: v4::Address = v4::Address::new(10, 0, 0, 3);
+
+    fn send_data<P: PayloadMut>(raw: udp::RawPacket<P>) -> Result<()> {
+        let init = udp::Init {
+            source: Subnet::from(v4::Subnet::ANY).into(),
+            src_port: 80,
+            dst_addr: IP_ADDR_OTHER.into(),
+            dst_port: 80,
+            payload: 64,
+            fragmentation: ip_layer::Fragmentation::default(),
+            hop_limit: None,
+        };
+        raw.prepare(init).and_then(udp::Packet::send)
+    }
+
+    fn flood<P: PayloadMut>(raw: udp::RawPacket<P>) {
+        match send_data(raw) {
+            Ok(()) | Err(Error::Exhausted) => (),
+            Err(err) => panic!("Can always send data: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn arp_despite_flood() {
+        let nic = External::new_send(vec![vec![0; 128]; 4]);
+        let mut nic = Reserve::with_release(nic, 4, 1);
+
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+
+        let mut neighbors = [arp::Neighbor::default(); 1];
+        let neighbors = {
+            let mut cache = arp::NeighborCache::new(&mut neighbors[..]);
+            cache.fill(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER, None).unwrap();
+            cache
+        };
+        let mut ip = ip_layer::Endpoint::new(Cidr::new(IP_ADDR_HOST.into(), 24),
+            ip_layer::Routes::new(Slice::empty()),
+            neighbors);
+        let mut udp = udp::Endpoint::new(80);
+
+        // The data path tries to fill every buffer but is held to its quota.
+        for expected in [1, 1, 1, 0, 0].iter() {
+            let sent = nic.tx(1, eth.send(ip.send(udp.send_with(flood))));
+            assert_eq!(sent, Ok(*expected));
+        }
+        assert_eq!(nic.in_flight(), 3);
+        assert_eq!(nic.denied(), 2);
+
+        // A pending lookup, with the address resolution drawing from the same buffers.
+        let mut lookups = [arp::Neighbor::default(); 1];
+        let lookups = {
+            let mut cache = arp::NeighborCache::new(&mut lookups[..]);
+            cache.fill_looking(IP_ADDR_UNKNOWN.into(), None).unwrap();
+            cache
+        };
+        let mut resolver = ip_layer::Endpoint::new(Cidr::new(IP_ADDR_HOST.into(), 24),
+            ip_layer::Routes::new(Slice::empty()),
+            lookups);
+
+        let sent = nic.tx(1, eth.send(resolver.layer_internal()));
+        assert_eq!(sent, Ok(1));
+
+        let queued = nic.inner().queued();
+        assert_eq!(queued.len(), 4);
+        for frame in &queued[..3] {
+            let frame = ethernet::frame::new_checked(&frame[..]).unwrap();
+            assert_eq!(frame.ethertype(), ethernet::EtherType::Ipv4);
+        }
+        let frame = ethernet::frame::new_checked(&queued[3][..]).unwrap();
+        assert_eq!(frame.ethertype(), ethernet::EtherType::Arp);
+    }
+
+    #[test]
+    fn release_data() {
+        let nic = External::new_send(vec![vec![0; 128]; 2]);
+        let mut nic = Reserve::with_release(nic, 2, 1);
+
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        let mut neighbors = [arp::Neighbor::default(); 1];
+        let neighbors = {
+            let mut cache = arp::NeighborCache::new(&mut neighbors[..]);
+            cache.fill(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER, None).unwrap();
+            cache
+        };
+        let mut ip = ip_layer::Endpoint::new(Cidr::new(IP_ADDR_HOST.into(), 24),
+            ip_layer::Routes::new(Slice::empty()),
+            neighbors);
+        let mut udp = udp::Endpoint::new(80);
+
+        assert_eq!(nic.tx(1, eth.send(ip.send(udp.send_with(flood)))), Ok(1));
+        assert_eq!(nic.tx(1, eth.send(ip.send(udp.send_with(flood)))), Ok(0));
+
+        // The device transmitted all buffers.
+        nic.inner_mut().reset_send();
+        nic.release(1);
+        assert_eq!(nic.in_flight(), 0);
+        assert_eq!(nic.tx(1, eth.send(ip.send(udp.send_with(flood)))), Ok(1));
+        assert_eq!(nic.denied(), 1);
+    }
+
+    #[test]
+    fn synchronous_release() {
+        let nic = External::new_send(vec![vec![0; 128]; 4]);
+        let mut nic = Reserve::new(nic, 4, 1);
+
+        let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+        let mut neighbors = [arp::Neighbor::default(); 1];
+        let neighbors = {
+            let mut cache = arp::NeighborCache::new(&mut neighbors[..]);
+            cache.fill(IP_ADDR_OTHER.into(), MAC_ADDR_OTHER, None).unwrap();
+            cache
+        };
+        let mut ip = ip_layer::Endpoint::new(Cidr::new(IP_ADDR_HOST.into(), 24),
+            ip_layer::Routes::new(Slice::empty()),
+            neighbors);
+        let mut udp = udp::Endpoint::new(80);
+
+        for _ in 0..2 {
+            let mut results = Vec::new();
+            let sent = nic.tx(4, eth.send(ip.send(udp.send_with(|raw: udp::RawPacket<Vec<u8>>| {
+                results.push(send_data(raw))
+            }))));
+            assert_eq!(sent, Ok(3));
+            assert_eq!(results, [Ok(()), Ok(()), Ok(()), Err(Error::Exhausted)]);
+            assert_eq!(nic.in_flight(), 0);
+
+            // The device transmitted all buffers, no release is required.
+            nic.inner_mut().reset_send();
+        }
+
+        assert_eq!(nic.denied(), 2);
+    }
+}
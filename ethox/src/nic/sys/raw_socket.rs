@@ -43,7 +43,8 @@ pub struct RawSocketDesc {
 /// Uses the errno principle for storing the last underlying error on a failed operation.
 ///
 /// The device capabilities are mutable and not automatically deduced. For example, a local veth
-/// pair (connecting two network namespaces) could be allowed to elide all checksums.
+/// pair (connecting two network namespaces) could be allowed to elide all checksums. The interface
+/// MTU on the other hand is queried when the device is created and reported in its personality.
 ///
 /// The `nic::Device` implementation always sends and receives at most one buffer at a time. It
 /// will also block on sending but is non-blocking during receiving. This is not quite a bug. It's
@@ -57,6 +58,7 @@ pub struct RawSocket<C> {
     buffer: Partial<C>,
     last_err: Option<Errno>,
     capabilities: Capabilities,
+    mtu: Option<usize>,
 }
 
 enum Received {
@@ -176,14 +178,16 @@ impl<C: PayloadMut> RawSocket<C> {
     /// The socket needs to already be bound to the interface otherwise errors to all calls will be
     /// the consequence.
     pub fn with_descriptor(
-        inner: RawSocketDesc,
+        mut inner: RawSocketDesc,
         buffer: C,
     ) -> Result<Self, Errno> {
+        let mtu = inner.interface_mtu()?;
         Ok(RawSocket {
             inner,
             buffer: Partial::new(buffer),
             last_err: None,
             capabilities: Capabilities::no_support(),
+            mtu: Some(mtu),
         })
     }
 
@@ -265,7 +269,10 @@ impl<C: PayloadMut> Device for RawSocket<C> {
     /// Could be dynamically configured but the optimizer and the user is likely happier if the
     /// implementation does not take advantage of this fact.
     fn personality(&self) -> Personality {
-        Personality::baseline()
+        let mut personality = Personality::baseline();
+        *personality.capabilities_mut() = self.capabilities;
+        *personality.mtu_mut() = self.mtu;
+        personality
     }
 
     fn tx(&mut self, _: usize, mut sender: impl nic::Send<Self::Handle, Self::Payload>)
@@ -280,9 +287,10 @@ impl<C: PayloadMut> Device for RawSocket<C> {
 
         if handle.was_sent() {
             self.send()?;
+            Ok(1)
+        } else {
+            Ok(0)
         }
-
-        Ok(1)
     }
 
     fn rx(&mut self, _: usize, mut receptor: impl nic::Recv<Self::Handle, Self::Payload>)
@@ -308,3 +316,62 @@ impl<C: PayloadMut> Device for RawSocket<C> {
     }
 }
 
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::RawSocket;
+    use crate::nic::{Device, Handle, Packet};
+    use crate::nic::common::EnqueueFlag;
+    use crate::layer::FnHandler;
+    use crate::managed::Partial;
+    use crate::wire::{Payload, PayloadMut};
+
+    /// Interfaces of a veth pair, overridden with `ETHOX_VETH_A` and `ETHOX_VETH_B`.
+    fn veth_pair() -> (String, String) {
+        let a = std::env::var("ETHOX_VETH_A").unwrap_or_else(|_| "veth0".into());
+        let b = std::env::var("ETHOX_VETH_B").unwrap_or_else(|_| "veth1".into());
+        (a, b)
+    }
+
+    /// Requires a veth pair with both ends up and the capability to open raw sockets, e.g.:
+    ///
+    /// ```text
+    /// ip link add veth0 type veth peer name veth1
+    /// ip link set veth0 up && ip link set veth1 up
+    /// ```
+    #[test]
+    #[ignore]
+    fn veth_loop() {
+        let (a, b) = veth_pair();
+        let mut sender = RawSocket::new(&a, vec![0; 1514]).expect("Opening first veth end");
+        let mut receiver = RawSocket::new(&b, vec![0; 1514]).expect("Opening second veth end");
+        assert!(sender.personality().mtu().is_some());
+
+        // A broadcast frame with an unused local experimental ethertype.
+        let mut frame = [0u8; 64];
+        frame[..6].copy_from_slice(&[0xff; 6]);
+        frame[6..12].copy_from_slice(&[0x02, 0, 0, 0, 0, 1]);
+        frame[12..14].copy_from_slice(&[0x88, 0xb5]);
+        frame[14..].iter_mut().enumerate().for_each(|(i, byte)| *byte = i as u8);
+
+        let sent = sender.tx(1, FnHandler(|packet: Packet<EnqueueFlag, Partial<Vec<u8>>>| {
+            packet.payload.set_len_unchecked(frame.len());
+            packet.payload.payload_mut().as_mut_slice().copy_from_slice(&frame);
+            packet.handle.queue().unwrap();
+        }));
+        assert_eq!(sent, Ok(1));
+
+        // Other traffic may appear on the link as well, wait for our frame.
+        let mut found = false;
+        for _ in 0..1000 {
+            let _ = receiver.rx(1, FnHandler(|packet: Packet<EnqueueFlag, Partial<Vec<u8>>>| {
+                found |= packet.payload.payload().as_slice() == &frame[..];
+            }));
+            if found {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert!(found, "Frame was not received on the other end");
+    }
+}
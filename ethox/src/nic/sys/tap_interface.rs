@@ -39,17 +39,23 @@ pub struct TapInterfaceDesc {
 
 /// A tap interface with buffer, usable as a network device.
 ///
-/// The `nic::Device` implementation always sends and receives at most one buffer at a time. It
-/// will also block on sending but is non-blocking during receiving. This is not quite a bug. It's
-/// intended as while the buffer is filled for sending, there are no resources for any other
-/// operation. But it arguably could instead simply yield no buffer in any rx-tx block while the
-/// buffer is already in-use. However, this implementation was slightly simpler and tap interface
-/// is not the main use case. Patches are accepted.
+/// The `nic::Device` implementation handles one frame at a time in its single buffer, which is
+/// reused for all frames and never reallocated. A call to `rx` or `tx` handles up to the requested
+/// number of frames one after another, until no frame is available or none is queued. It will
+/// block on sending but is non-blocking during receiving. This is not quite a bug. It's intended
+/// as while the buffer is filled for sending, there are no resources for any other operation. But
+/// it arguably could instead simply yield no buffer in any rx-tx block while the buffer is already
+/// in-use. However, this implementation was slightly simpler and tap interface is not the main use
+/// case. Patches are accepted.
+///
+/// A tap offers no checksum offloading, the personality reflects this so that all checksums are
+/// computed in software. It also reports the MTU of the interface.
 #[derive(Debug)]
 pub struct TapInterface<C> {
     inner: TapInterfaceDesc,
     buffer: Partial<C>,
     last_err: Option<Errno>,
+    mtu: Option<usize>,
 }
 
 enum Received {
@@ -113,7 +119,7 @@ impl TapInterfaceDesc {
 
         FdResult(lower).errno()?;
 
-        let mtu = self.ifreq.get_mtu(self.lower)
+        let mtu = self.ifreq.get_mtu(lower)
             .map(|mtu| mtu as usize);
 
         unsafe { libc::close(lower); }
@@ -159,10 +165,12 @@ impl<C: PayloadMut> TapInterface<C> {
         buffer: C,
     ) -> Result<Self, Errno> {
         inner.attach_interface()?;
+        let mtu = inner.interface_mtu()?;
         Ok(TapInterface {
             inner,
             buffer: Partial::new(buffer),
             last_err: None,
+            mtu: Some(mtu),
         })
     }
 
@@ -232,47 +240,62 @@ impl<C: PayloadMut> Device for TapInterface<C> {
     /// Could be dynamically configured but the optimizer and the user is likely happier if the
     /// implementation does not take advantage of this fact.
     fn personality(&self) -> Personality {
-        Personality::baseline()
+        // No offloading, `Capabilities::no_support`.
+        let mut personality = Personality::baseline();
+        *personality.mtu_mut() = self.mtu;
+        personality
     }
 
-    fn tx(&mut self, _: usize, mut sender: impl nic::Send<Self::Handle, Self::Payload>)
+    fn tx(&mut self, max: usize, mut sender: impl nic::Send<Self::Handle, Self::Payload>)
         -> nic::Result<usize>
     {
-        let mut handle = EnqueueFlag::set_true(Self::current_info());
-        self.recycle();
-        sender.send(Packet {
-            handle: &mut handle,
-            payload: &mut self.buffer,
-        });
+        let mut count = 0;
+
+        while count < max {
+            let mut handle = EnqueueFlag::set_true(Self::current_info());
+            self.recycle();
+            sender.send(Packet {
+                handle: &mut handle,
+                payload: &mut self.buffer,
+            });
+
+            if !handle.was_sent() {
+                break;
+            }
 
-        if handle.was_sent() {
             self.send()?;
-            Ok(1)
-        } else {
-            Ok(0)
+            count += 1;
         }
+
+        Ok(count)
     }
 
-    fn rx(&mut self, _: usize, mut receptor: impl nic::Recv<Self::Handle, Self::Payload>)
+    fn rx(&mut self, max: usize, mut receptor: impl nic::Recv<Self::Handle, Self::Payload>)
         -> nic::Result<usize>
     {
-        match self.recv() {
-            Received::Ok => (),
-            Received::Err(err) => return Err(err),
-            Received::NoData => return Ok(0),
-        }
-
-        let mut handle = EnqueueFlag::set_true(Self::current_info());
-        receptor.receive(Packet {
-            handle: &mut handle,
-            payload: &mut self.buffer,
-        });
-
-        if handle.was_sent() {
-            self.send()?;
+        let mut count = 0;
+
+        while count < max {
+            match self.recv() {
+                Received::Ok => (),
+                Received::Err(err) => return Err(err),
+                Received::NoData => break,
+            }
+
+            let mut handle = EnqueueFlag::set_true(Self::current_info());
+            receptor.receive(Packet {
+                handle: &mut handle,
+                payload: &mut self.buffer,
+            });
+
+            if handle.was_sent() {
+                self.send()?;
+            }
+
+            count += 1;
         }
 
-        Ok(1)
+        Ok(count)
     }
 }
 
@@ -0,0 +1,84 @@
+//! Fan-out of received packets to an observer and a handler.
+//!
+//! A received buffer can only be handed to a single receiver, as that receiver may modify it,
+//! answer it in place or otherwise consume it. Any number of other parties may however look at
+//! the packet before this happens, for example a sniffer running beside the regular network stack.
+//! The [`Tee`] presents each packet to an [`Observe`] implementation first, read-only and without
+//! copying, and then passes it on to the consuming receiver.
+//!
+//! [`Tee`]: struct.Tee.html
+//! [`Observe`]: trait.Observe.html
+use crate::layer::{FnHandler, StatefulHandler};
+use crate::wire::Payload;
+
+use super::{Handle, Info, Packet, Recv};
+
+/// A receiver that only inspects packets.
+///
+/// It gets shared access to the payload and the packet information but no access to the handle,
+/// so it can not queue the buffer for sending.
+pub trait Observe<P: Payload + ?Sized> {
+    /// Inspect a single received packet.
+    fn observe(&mut self, info: &dyn Info, payload: &P);
+}
+
+/// A receiver presenting each packet to an observer before a consuming handler.
+///
+/// Create it with [`recv_tee`]. The observer `A` always sees the packet in the state in which it
+/// was received. Only the handler `B` gets the packet itself and may modify, answer or drop it.
+/// Further observers can be added by nesting tees as the handler.
+///
+/// [`recv_tee`]: fn.recv_tee.html
+pub struct Tee<A, B> {
+    observer: A,
+    handler: B,
+}
+
+/// Receive all packets with `handler` after presenting them to `observer`.
+pub fn recv_tee<A, B>(observer: A, handler: B) -> Tee<A, B> {
+    Tee { observer, handler, }
+}
+
+impl<A, B> Tee<A, B> {
+    /// Unwrap the observer and the handler.
+    pub fn into_inner(self) -> (A, B) {
+        (self.observer, self.handler)
+    }
+}
+
+impl<H, P, A, B> Recv<H, P> for Tee<A, B>
+where
+    H: Handle + ?Sized,
+    P: Payload + ?Sized,
+    A: Observe<P>,
+    B: Recv<H, P>,
+{
+    fn receive(&mut self, packet: Packet<H, P>) {
+        self.observer.observe(packet.handle.info(), packet.payload);
+        self.handler.receive(packet)
+    }
+}
+
+impl<F, P: Payload + ?Sized> Observe<P> for FnHandler<F>
+    where F: FnMut(&dyn Info, &P)
+{
+    fn observe(&mut self, info: &dyn Info, payload: &P) {
+        (self.0)(info, payload)
+    }
+}
+
+impl<S, F, P: Payload + ?Sized> Observe<P> for StatefulHandler<S, F>
+    where F: FnMut(&mut S, &dyn Info, &P)
+{
+    fn observe(&mut self, info: &dyn Info, payload: &P) {
+        (self.handler)(&mut self.state, info, payload)
+    }
+}
+
+impl<O, P: Payload + ?Sized> Observe<P> for &'_ mut O
+    where O: Observe<P>
+{
+    fn observe(&mut self, info: &dyn Info, payload: &P) {
+        (**self).observe(info, payload)
+    }
+}
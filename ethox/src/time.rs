@@ -5,13 +5,19 @@ absolute and relative time.
 
  - [Instant] is used to represent absolute time.
  - [Duration] is used to represent relative time.
+ - [TimerWheel] holds application timers driven by the poll loop.
+ - [TokenBucket] limits the rate of events over time.
 
 [Instant]: struct.Instant.html
 [Duration]: struct.Duration.html
+[TimerWheel]: struct.TimerWheel.html
+[TokenBucket]: struct.TokenBucket.html
 */
 use core::{cmp, fmt, ops};
 pub use core::time::Duration;
 
+use crate::managed::Slice;
+
 /// A representation of an absolute time value.
 ///
 /// The `Instant` type is a wrapper around a `i64` value that represents a number of milliseconds,
@@ -44,6 +50,126 @@ pub enum Expiration {
 
 use Expiration::{When, Never};
 
+/// A timer scheduled in a [`TimerWheel`].
+///
+/// The storage of a wheel consists of these. Unused entries are linked into a free list, the token
+/// itself is opaque to the wheel.
+///
+/// [`TimerWheel`]: struct.TimerWheel.html
+#[derive(Debug, Clone, Copy)]
+pub struct Timer<T> {
+    deadline: Instant,
+
+    /// Breaks ties between equal deadlines in favor of the earlier scheduled timer.
+    seq: u64,
+
+    token: T,
+
+    /// Changed whenever the timer is removed, such that old keys no longer refer to the entry.
+    generation: u32,
+
+    /// The bucket in which the timer is linked, `None` while the entry is unused.
+    bucket: Option<usize>,
+
+    prev: Option<usize>,
+
+    next: Option<usize>,
+}
+
+/// Refers to a scheduled timer, for example to cancel it.
+///
+/// A key is only valid until its timer expires or is cancelled. Stale keys are recognized even
+/// when the storage of the timer has been reused since.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerKey {
+    index: usize,
+    generation: u32,
+}
+
+/// Application timers, expiring in the order of their deadlines.
+///
+/// Handlers schedule a token with a deadline, for example to time out a request or to send a
+/// keepalive. The wheel does not observe time itself. Instead the poll loop passes its current
+/// time to [`expire`] which hands out the tokens of expired timers one at a time, earliest deadline
+/// first. Timers with equal deadlines expire in the order in which they were scheduled.
+///
+/// The timers are sorted into a hierarchy of four levels with 64 buckets each. A bucket of the
+/// lowest level holds the timers of a single millisecond while each further level spans 64 times
+/// the range of the one below it. Timers due after all levels, more than about four and a half
+/// hours ahead, wait in an additional overflow bucket. Once time reaches a bucket of a higher
+/// level its timers are spread over the lower levels. Scheduling and cancelling a timer are thus
+/// constant in the number of pending timers, expiring one only moves each timer down a few times
+/// during its lifetime. The number of timers is bounded by the provided storage.
+///
+/// [`expire`]: #method.expire
+#[derive(Debug)]
+pub struct TimerWheel<'a, T> {
+    timers: Slice<'a, Timer<T>>,
+
+    /// The first timer of each bucket, the overflow bucket last.
+    heads: [Option<usize>; OVERFLOW + 1],
+
+    /// The last timer of each bucket of the lowest level, which are kept sorted.
+    tails: [Option<usize>; SLOTS],
+
+    /// A bitmap of the non-empty buckets of each level.
+    occupied: [u64; LEVELS],
+
+    /// A lower bound for the deadlines in the overflow bucket.
+    overflow_min: Option<i64>,
+
+    /// Unused entries that were scheduled before.
+    free: Option<usize>,
+
+    /// Entries starting at this index have never been scheduled.
+    unused: usize,
+
+    len: usize,
+
+    /// The millisecond up to which the wheel has advanced.
+    current: i64,
+
+    next_seq: u64,
+}
+
+/// A token bucket, refilled over time up to a maximum burst.
+///
+/// The bucket gains `rate` tokens per second and holds at most `burst` tokens. It starts out full.
+/// Tokens are accounted in thousandths so that refilling for each millisecond is exact. Time that
+/// goes backwards refills nothing.
+///
+/// Events usually take their tokens only if enough are available. When the cost of an event is
+/// only known after it happened, it can instead be charged with [`overdraw`]. The bucket is then
+/// empty until the debt is repaid.
+///
+/// [`overdraw`]: #method.overdraw
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TokenBucket {
+    /// Tokens added per second.
+    rate: u32,
+
+    /// The maximum number of tokens.
+    burst: u32,
+
+    /// Available tokens, in thousandths, negative while overdrawn.
+    tokens: i64,
+
+    /// The time at which the tokens were last updated, `None` if never used.
+    last: Option<Instant>,
+}
+
+/// The tokens of all timers expired at some point in time.
+///
+/// Created by [`TimerWheel::advance`]. Each yielded timer is removed from the wheel, timers that
+/// are not consumed stay scheduled.
+///
+/// [`TimerWheel::advance`]: struct.TimerWheel.html#method.advance
+#[derive(Debug)]
+pub struct Expired<'w, 'a, T> {
+    wheel: &'w mut TimerWheel<'a, T>,
+    now: Instant,
+}
+
 impl Instant {
     /// Create a new `Instant` from a number of milliseconds.
     pub fn from_millis<T: Into<i64>>(millis: T) -> Instant {
@@ -80,6 +206,20 @@ impl Instant {
     pub fn total_millis(&self) -> i64 {
         self.millis
     }
+
+    /// Offset the instant by a signed number of milliseconds.
+    pub fn add_millis(self, millis: i64) -> Instant {
+        Instant { millis: self.millis + millis }
+    }
+
+    /// The duration that has passed since an earlier instant.
+    ///
+    /// Contrary to subtracting the instants, which yields the absolute difference, this is zero if
+    /// `earlier` is in fact later than `self`.
+    pub fn saturating_duration_since(self, earlier: Instant) -> Duration {
+        let millis = self.millis.saturating_sub(earlier.millis).max(0);
+        Duration::from_millis(millis as u64)
+    }
 }
 
 #[cfg(feature = "std")]
@@ -172,6 +312,346 @@ impl ops::Sub<Instant> for Instant {
     }
 }
 
+impl<T> Timer<T> {
+    /// The point in time at which the timer expires.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// The token handed out on expiry.
+    pub fn token(&self) -> &T {
+        &self.token
+    }
+}
+
+impl<T: Default> Default for Timer<T> {
+    fn default() -> Self {
+        Timer {
+            deadline: Instant::from_millis(0),
+            seq: 0,
+            token: T::default(),
+            generation: 0,
+            bucket: None,
+            prev: None,
+            next: None,
+        }
+    }
+}
+
+/// Bits of a deadline selecting the bucket within one level of the wheel.
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 4;
+/// Bits of a deadline covered by all levels together.
+const TOP_BITS: u32 = SLOT_BITS * LEVELS as u32;
+/// Index of the overflow bucket, after the buckets of all levels.
+const OVERFLOW: usize = LEVELS * SLOTS;
+
+impl<'a, T> TimerWheel<'a, T> {
+    /// Create an empty wheel, with space for as many timers as the storage holds.
+    ///
+    /// The content of the storage is overwritten when scheduling timers.
+    pub fn new<S>(storage: S) -> Self
+        where S: Into<Slice<'a, Timer<T>>>
+    {
+        TimerWheel {
+            timers: storage.into(),
+            heads: [None; OVERFLOW + 1],
+            tails: [None; SLOTS],
+            occupied: [0; LEVELS],
+            overflow_min: None,
+            free: None,
+            unused: 0,
+            len: 0,
+            current: 0,
+            next_seq: 0,
+        }
+    }
+
+    /// The number of pending timers.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if no timers are pending.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The pending timer of a key, if it has neither expired nor been cancelled.
+    pub fn get(&self, key: TimerKey) -> Option<&Timer<T>> {
+        self.timers.get(key.index)
+            .filter(|timer| timer.bucket.is_some() && timer.generation == key.generation)
+    }
+
+    /// The deadline of the next timer to expire.
+    ///
+    /// Useful for determining how long the poll loop may sleep. Only the timers of the first
+    /// non-empty bucket are inspected.
+    pub fn next_deadline(&self) -> Expiration {
+        let bucket = (0..LEVELS)
+            .find_map(|level| {
+                // The current bucket of the lowest level may still hold timers, of higher levels
+                // it has already been spread out.
+                let skip = if level == 0 { 0 } else { 1 };
+                let from = self.digit(self.current, level) + skip;
+                next_slot(self.occupied[level], from).map(|slot| level*SLOTS + slot)
+            })
+            .unwrap_or(OVERFLOW);
+
+        if bucket < SLOTS {
+            // Buckets of the lowest level are sorted.
+            return self.heads[bucket].map(|idx| self.timers[idx].deadline).into();
+        }
+
+        let mut earliest = None;
+        let mut next = self.heads[bucket];
+        while let Some(idx) = next {
+            let timer = &self.timers[idx];
+            earliest = Some(earliest.map_or(timer.deadline, |min| cmp::min(min, timer.deadline)));
+            next = timer.next;
+        }
+        earliest.into()
+    }
+
+    /// Schedule a token to expire at a deadline.
+    ///
+    /// Returns a key for cancelling the timer, or the token as an error if the storage has no room
+    /// for another timer.
+    pub fn schedule(&mut self, deadline: Instant, token: T) -> Result<TimerKey, T> {
+        let idx = match self.free {
+            Some(idx) => idx,
+            None if self.unused < self.timers.len() => self.unused,
+            None => return Err(token),
+        };
+
+        let seq = self.next_seq;
+        let timer = &mut self.timers[idx];
+        if self.free == Some(idx) {
+            self.free = timer.next;
+        } else {
+            self.unused += 1;
+        }
+
+        timer.deadline = deadline;
+        timer.seq = seq;
+        timer.token = token;
+        let key = TimerKey { index: idx, generation: timer.generation };
+
+        self.insert(idx);
+        self.len += 1;
+        self.next_seq = seq.wrapping_add(1);
+        Ok(key)
+    }
+
+    /// Cancel a pending timer and return its token.
+    ///
+    /// Returns `None` if the timer has already expired or been cancelled.
+    pub fn cancel(&mut self, key: TimerKey) -> Option<T>
+        where T: Clone,
+    {
+        self.get(key)?;
+        Some(self.remove(key.index))
+    }
+
+    /// Remove the next timer whose deadline has passed at `now` and return its token.
+    ///
+    /// Call this repeatedly until it returns `None` to handle all expired timers.
+    pub fn expire(&mut self, now: Instant) -> Option<T>
+        where T: Clone,
+    {
+        loop {
+            if let Some(idx) = self.heads[self.digit(self.current, 0)] {
+                if self.timers[idx].deadline > now {
+                    return None;
+                }
+                return Some(self.remove(idx));
+            }
+
+            match self.next_bucket() {
+                Some(start) if start <= now.millis => self.advance_to(start),
+                _ => {
+                    if now.millis > self.current {
+                        self.advance_to(now.millis);
+                    }
+                    return None;
+                },
+            }
+        }
+    }
+
+    /// Advance to `now`, iterating the tokens of all expired timers.
+    ///
+    /// The tokens are yielded in the same order as repeated calls to [`expire`] would return them.
+    ///
+    /// [`expire`]: #method.expire
+    pub fn advance(&mut self, now: Instant) -> Expired<'_, 'a, T> {
+        Expired {
+            wheel: self,
+            now,
+        }
+    }
+
+    /// The bucket index within a level to which a time belongs.
+    fn digit(&self, millis: i64, level: usize) -> usize {
+        (millis >> (SLOT_BITS * level as u32)) as usize & (SLOTS - 1)
+    }
+
+    /// The bucket of a deadline at the current time.
+    ///
+    /// Overdue timers are treated as due now.
+    fn bucket(&self, millis: i64) -> usize {
+        let millis = cmp::max(millis, self.current);
+        (0..LEVELS)
+            .find(|&level| {
+                let block = SLOT_BITS * (level as u32 + 1);
+                millis >> block == self.current >> block
+            })
+            .map_or(OVERFLOW, |level| level*SLOTS + self.digit(millis, level))
+    }
+
+    /// The start of the next non-empty bucket after the current millisecond.
+    fn next_bucket(&self) -> Option<i64> {
+        for level in 0..LEVELS {
+            let from = self.digit(self.current, level) + 1;
+            if let Some(slot) = next_slot(self.occupied[level], from) {
+                let shift = SLOT_BITS * level as u32;
+                let block = shift + SLOT_BITS;
+                return Some((self.current >> block << block) | (slot as i64) << shift);
+            }
+        }
+
+        self.overflow_min.map(|min| min >> TOP_BITS << TOP_BITS)
+    }
+
+    /// Move the current time forward, spreading out the buckets that have been reached.
+    fn advance_to(&mut self, millis: i64) {
+        let previous = core::mem::replace(&mut self.current, millis);
+
+        if let Some(min) = self.overflow_min {
+            if min >> TOP_BITS <= millis >> TOP_BITS {
+                self.overflow_min = None;
+                self.cascade(OVERFLOW);
+            }
+        }
+
+        for level in (1..LEVELS).rev() {
+            let shift = SLOT_BITS * level as u32;
+            if millis >> shift != previous >> shift {
+                self.cascade(level*SLOTS + self.digit(millis, level));
+            }
+        }
+    }
+
+    /// Reinsert all timers of a bucket at the current time.
+    fn cascade(&mut self, bucket: usize) {
+        let mut next = self.heads[bucket].take();
+        if bucket < OVERFLOW {
+            self.occupied[bucket / SLOTS] &= !(1 << (bucket % SLOTS));
+        }
+
+        while let Some(idx) = next {
+            next = self.timers[idx].next;
+            self.insert(idx);
+        }
+    }
+
+    /// Link a timer into the bucket of its deadline.
+    fn insert(&mut self, idx: usize) {
+        let (deadline, seq) = (self.timers[idx].deadline, self.timers[idx].seq);
+        let bucket = self.bucket(deadline.millis);
+
+        // Only the lowest level is kept in order, timers are usually scheduled with increasing
+        // deadlines so search from the back. All other buckets are spread out before expiring.
+        let mut prev = if bucket < SLOTS { self.tails[bucket] } else { None };
+        while let Some(other) = prev.map(|other| &self.timers[other]) {
+            if (other.deadline, other.seq) <= (deadline, seq) {
+                break;
+            }
+            prev = other.prev;
+        }
+
+        let next = match prev {
+            Some(prev) => self.timers[prev].next,
+            None => self.heads[bucket],
+        };
+
+        let timer = &mut self.timers[idx];
+        timer.bucket = Some(bucket);
+        timer.prev = prev;
+        timer.next = next;
+
+        match prev {
+            Some(prev) => self.timers[prev].next = Some(idx),
+            None => self.heads[bucket] = Some(idx),
+        }
+
+        match next {
+            Some(next) => self.timers[next].prev = Some(idx),
+            None if bucket < SLOTS => self.tails[bucket] = Some(idx),
+            None => (),
+        }
+
+        if bucket < OVERFLOW {
+            self.occupied[bucket / SLOTS] |= 1 << (bucket % SLOTS);
+        } else {
+            let millis = cmp::max(deadline.millis, self.current);
+            self.overflow_min = Some(self.overflow_min.map_or(millis, |min| min.min(millis)));
+        }
+    }
+
+    /// Unlink a pending timer, free its entry and return its token.
+    fn remove(&mut self, idx: usize) -> T
+        where T: Clone,
+    {
+        let timer = &self.timers[idx];
+        let (bucket, prev, next) = (timer.bucket.unwrap(), timer.prev, timer.next);
+
+        match prev {
+            Some(prev) => self.timers[prev].next = next,
+            None => self.heads[bucket] = next,
+        }
+
+        match next {
+            Some(next) => self.timers[next].prev = prev,
+            None if bucket < SLOTS => self.tails[bucket] = prev,
+            None => (),
+        }
+
+        if bucket < OVERFLOW && self.heads[bucket].is_none() {
+            self.occupied[bucket / SLOTS] &= !(1 << (bucket % SLOTS));
+        }
+
+        let timer = &mut self.timers[idx];
+        timer.bucket = None;
+        timer.generation = timer.generation.wrapping_add(1);
+        timer.next = self.free;
+        self.free = Some(idx);
+        self.len -= 1;
+        timer.token.clone()
+    }
+}
+
+/// The first set bit of a bucket bitmap at or after `from`.
+fn next_slot(occupied: u64, from: usize) -> Option<usize> {
+    if from >= SLOTS {
+        return None;
+    }
+
+    match occupied & (!0 << from) {
+        0 => None,
+        bits => Some(bits.trailing_zeros() as usize),
+    }
+}
+
+impl<T: Clone> Iterator for Expired<'_, '_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.wheel.expire(self.now)
+    }
+}
+
 impl Default for Expiration {
     fn default() -> Self {
         Expiration::Never
@@ -218,10 +698,126 @@ impl cmp::Ord for Expiration {
     }
 }
 
+/// Thousandths of a token.
+const TOKEN: i64 = 1000;
+
+impl TokenBucket {
+    /// Create a full bucket gaining `rate` tokens per second up to `burst` tokens.
+    pub fn new(rate: u32, burst: u32) -> Self {
+        TokenBucket {
+            rate,
+            burst,
+            tokens: i64::from(burst)*TOKEN,
+            last: None,
+        }
+    }
+
+    /// The number of tokens added per second.
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// The maximum number of tokens in the bucket.
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// Check if the bucket is completely refilled at some time.
+    pub fn is_full(&self, now: Instant) -> bool {
+        self.available(now) == self.capacity()
+    }
+
+    /// Check if the bucket has no tokens left at some time.
+    pub fn is_empty(&self, now: Instant) -> bool {
+        self.available(now) <= 0
+    }
+
+    /// Take some tokens at a point in time, if that many are available.
+    ///
+    /// Returns whether the tokens were taken. Nothing is consumed otherwise.
+    pub fn take(&mut self, count: u64, now: Instant) -> bool {
+        self.refill(now);
+        let cost = Self::cost(count);
+        if self.tokens < cost {
+            return false;
+        }
+
+        self.tokens -= cost;
+        true
+    }
+
+    /// Charge tokens for an event that already happened, possibly overdrawing the bucket.
+    ///
+    /// This does not refill the bucket, the tokens are consumed from the last update.
+    pub fn overdraw(&mut self, count: u64) {
+        self.tokens = self.tokens.saturating_sub(Self::cost(count));
+    }
+
+    /// The tokens in the bucket at some time, in thousandths.
+    fn available(&self, now: Instant) -> i64 {
+        let last = match self.last {
+            Some(last) if now > last => last,
+            _ => return self.tokens,
+        };
+
+        let elapsed = (now - last).as_millis() as i64;
+        elapsed
+            .saturating_mul(i64::from(self.rate))
+            .saturating_add(self.tokens)
+            .min(self.capacity())
+    }
+
+    fn refill(&mut self, now: Instant) {
+        self.tokens = self.available(now);
+        match self.last {
+            Some(last) if now <= last => (),
+            _ => self.last = Some(now),
+        }
+    }
+
+    fn capacity(&self) -> i64 {
+        i64::from(self.burst)*TOKEN
+    }
+
+    fn cost(count: u64) -> i64 {
+        (count as i64).saturating_mul(TOKEN)
+    }
+}
+
+/// An unused bucket without any tokens.
+impl Default for TokenBucket {
+    fn default() -> Self {
+        TokenBucket::new(0, 0)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn token_bucket() {
+        let start = Instant::from_millis(1000);
+        let mut bucket = TokenBucket::new(10, 3);
+        assert!(bucket.is_full(start));
+
+        assert!(bucket.take(2, start));
+        assert!(!bucket.take(2, start));
+        assert!(bucket.take(1, start));
+        assert!(bucket.is_empty(start));
+
+        // One token is refilled every 100 milliseconds, time going backwards refills nothing.
+        assert!(!bucket.take(1, Instant::from_millis(1099)));
+        assert!(!bucket.take(1, Instant::from_millis(500)));
+        assert!(bucket.take(1, Instant::from_millis(1100)));
+
+        // An overdrawn bucket stays empty until the debt is repaid.
+        bucket.overdraw(2);
+        assert!(bucket.is_empty(Instant::from_millis(1300)));
+        assert!(!bucket.is_empty(Instant::from_millis(1301)));
+        assert!(bucket.is_full(Instant::from_millis(1600)));
+    }
+
     #[test]
     fn test_instant_ops() {
         // std::ops::Add
@@ -230,6 +826,29 @@ mod test {
         assert_eq!(Instant::from_millis(7) - Duration::from_millis(5), Instant::from_millis(2));
     }
 
+    #[test]
+    fn test_instant_offsets() {
+        let instant = Instant::from_millis(1000);
+        assert_eq!(instant.add_millis(250), Instant::from_millis(1250));
+        assert_eq!(instant.add_millis(-1500), Instant::from_millis(-500));
+        assert_eq!(instant.add_millis(250).add_millis(-250), instant);
+
+        let later = instant + Duration::from_millis(300);
+        assert_eq!(later.saturating_duration_since(instant), Duration::from_millis(300));
+        assert_eq!(instant.saturating_duration_since(later), Duration::from_millis(0));
+        assert_eq!(instant.saturating_duration_since(instant), Duration::from_millis(0));
+        assert_eq!(instant + later.saturating_duration_since(instant), later);
+    }
+
+    #[test]
+    fn test_duration_millis() {
+        for &millis in [0u64, 1, 999, 1000, 86_400_000].iter() {
+            assert_eq!(Duration::from_millis(millis).as_millis(), u128::from(millis));
+            let instant = Instant::from_millis(0) + Duration::from_millis(millis);
+            assert_eq!(instant.total_millis(), millis as i64);
+        }
+    }
+
     #[test]
     fn test_instant_getters() {
         let instant = Instant::from_millis(5674);
@@ -292,6 +911,164 @@ mod test {
         let _ = Duration::from_millis(4) / 0;
     }
 
+    fn expire_all(wheel: &mut TimerWheel<u8>, now: Instant) -> Vec<u8> {
+        core::iter::from_fn(|| wheel.expire(now)).collect()
+    }
+
+    #[test]
+    fn test_timer_wheel_order() {
+        let mut storage = [Timer::default(); 4];
+        let mut wheel = TimerWheel::new(&mut storage[..]);
+        assert_eq!(wheel.next_deadline(), Expiration::Never);
+
+        wheel.schedule(Instant::from_millis(30), 3).unwrap();
+        wheel.schedule(Instant::from_millis(10), 1).unwrap();
+        wheel.schedule(Instant::from_millis(20), 2).unwrap();
+        wheel.schedule(Instant::from_millis(10), 4).unwrap();
+        assert_eq!(wheel.schedule(Instant::from_millis(5), 5), Err(5));
+        assert_eq!(wheel.next_deadline(), Expiration::When(Instant::from_millis(10)));
+
+        assert_eq!(expire_all(&mut wheel, Instant::from_millis(9)), vec![]);
+        // Equal deadlines expire in the order of scheduling.
+        assert_eq!(expire_all(&mut wheel, Instant::from_millis(10)), vec![1, 4]);
+        assert_eq!(wheel.next_deadline(), Expiration::When(Instant::from_millis(20)));
+        assert_eq!(expire_all(&mut wheel, Instant::from_millis(35)), vec![2, 3]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn test_timer_wheel_advance() {
+        let mut storage = [Timer::default(); 8];
+        let mut wheel = TimerWheel::new(&mut storage[..]);
+
+        let keys = (0..8u8).map(|token| {
+            let deadline = Instant::from_millis(100).add_millis(-10*i64::from(token));
+            wheel.schedule(deadline, token).unwrap()
+        }).collect::<Vec<_>>();
+        assert_eq!(wheel.cancel(keys[4]), Some(4));
+
+        let expired = wheel.advance(Instant::from_millis(50)).collect::<Vec<_>>();
+        assert_eq!(expired, vec![7, 6, 5]);
+
+        // Only part of the expired timers are consumed.
+        assert_eq!(wheel.advance(Instant::from_millis(90)).take(2).collect::<Vec<_>>(), vec![3, 2]);
+        assert_eq!(wheel.next_deadline(), Expiration::When(Instant::from_millis(90)));
+        assert_eq!(wheel.advance(Instant::from_millis(99)).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(wheel.advance(Instant::from_millis(100)).collect::<Vec<_>>(), vec![0]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn test_timer_wheel_cancel() {
+        let mut storage = [Timer::default(); 4];
+        let mut wheel = TimerWheel::new(&mut storage[..]);
+
+        let first = wheel.schedule(Instant::from_millis(10), 1).unwrap();
+        wheel.schedule(Instant::from_millis(20), 2).unwrap();
+        let last = wheel.schedule(Instant::from_millis(30), 1).unwrap();
+        assert_eq!(wheel.cancel(first), Some(1));
+        assert_eq!(wheel.cancel(first), None);
+        assert_eq!(wheel.get(last).map(Timer::deadline), Some(Instant::from_millis(30)));
+        assert_eq!(wheel.cancel(last), Some(1));
+        assert_eq!(wheel.len(), 1);
+
+        // Freed space can be used again, without reviving the old keys.
+        let reused = wheel.schedule(Instant::from_millis(15), 3).unwrap();
+        assert!(wheel.get(first).is_none() && wheel.get(last).is_none());
+        assert_eq!(wheel.get(reused).map(Timer::token), Some(&3));
+        assert_eq!(expire_all(&mut wheel, Instant::from_millis(30)), vec![3, 2]);
+        assert_eq!(wheel.cancel(reused), None);
+    }
+
+    #[test]
+    fn test_timer_wheel_levels() {
+        let mut storage = [Timer::default(); 8];
+        let mut wheel = TimerWheel::new(&mut storage[..]);
+
+        // One timer for each level and one beyond all of them.
+        wheel.schedule(Instant::from_millis(100_000_000), 4).unwrap();
+        wheel.schedule(Instant::from_millis(300_000), 3).unwrap();
+        wheel.schedule(Instant::from_millis(5_000), 2).unwrap();
+        wheel.schedule(Instant::from_millis(70), 1).unwrap();
+        wheel.schedule(Instant::from_millis(5), 0).unwrap();
+        // Equal deadlines keep their order after moving down the levels.
+        wheel.schedule(Instant::from_millis(5_000), 5).unwrap();
+
+        assert_eq!(expire_all(&mut wheel, Instant::from_millis(4)), vec![]);
+        assert_eq!(expire_all(&mut wheel, Instant::from_millis(69)), vec![0]);
+        assert_eq!(wheel.next_deadline(), Expiration::When(Instant::from_millis(70)));
+        assert_eq!(expire_all(&mut wheel, Instant::from_millis(4_999)), vec![1]);
+        assert_eq!(wheel.next_deadline(), Expiration::When(Instant::from_millis(5_000)));
+        assert_eq!(expire_all(&mut wheel, Instant::from_millis(5_000)), vec![2, 5]);
+        assert_eq!(wheel.next_deadline(), Expiration::When(Instant::from_millis(300_000)));
+        assert_eq!(expire_all(&mut wheel, Instant::from_millis(99_999_999)), vec![3]);
+        assert_eq!(wheel.next_deadline(), Expiration::When(Instant::from_millis(100_000_000)));
+        assert_eq!(expire_all(&mut wheel, Instant::from_millis(100_000_000)), vec![4]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn test_timer_wheel_late_start() {
+        let mut storage = [Timer::default(); 4];
+        let mut wheel = TimerWheel::new(&mut storage[..]);
+        let now = Instant::from_millis(1_600_000_000_000i64);
+
+        wheel.schedule(now.add_millis(10), 1).unwrap();
+        wheel.schedule(now.add_millis(-10), 0).unwrap();
+        assert_eq!(wheel.next_deadline(), Expiration::When(now.add_millis(-10)));
+        assert_eq!(expire_all(&mut wheel, now), vec![0]);
+        assert_eq!(wheel.next_deadline(), Expiration::When(now.add_millis(10)));
+
+        // Overdue timers are still expired first.
+        wheel.schedule(now.add_millis(-20), 2).unwrap();
+        assert_eq!(expire_all(&mut wheel, now.add_millis(10)), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_timer_wheel_random() {
+        const COUNT: usize = 64;
+        let mut storage = [Timer::default(); COUNT];
+        let mut wheel = TimerWheel::new(&mut storage[..]);
+        let mut pending: Vec<(i64, u8, TimerKey)> = Vec::new();
+        let mut state = 0x2545_f491_u64;
+        let mut random = move |bound: i64| {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            (state >> 33) as i64 % bound
+        };
+
+        let mut now = 0;
+        for token in 0..=255u8 {
+            // Expire at steps of all sizes, at the latest once the storage is full.
+            while pending.len() == COUNT || random(8) == 0 {
+                let scale = random(28);
+                now += random(2 << scale);
+                pending.sort_by_key(|&(deadline, token, _)| (deadline, token));
+                let due = pending.iter().take_while(|&&(deadline, ..)| deadline <= now).count();
+                let expected = pending.drain(..due).map(|(.., token, _)| token).collect::<Vec<_>>();
+                assert_eq!(expire_all(&mut wheel, Instant::from_millis(now)), expected);
+                let next = pending.first().map(|&(deadline, ..)| Instant::from_millis(deadline));
+                assert_eq!(wheel.next_deadline(), next.into());
+            }
+
+            if random(4) == 0 && !pending.is_empty() {
+                let idx = random(pending.len() as i64) as usize;
+                let (_, token, key) = pending.swap_remove(idx);
+                assert_eq!(wheel.cancel(key), Some(token));
+            }
+
+            // Spread over all levels, with some equal and some overdue deadlines.
+            let scale = random(28);
+            let deadline = now + (random(1 << 28) >> scale) - 4;
+            let key = wheel.schedule(Instant::from_millis(deadline), token).unwrap();
+            pending.push((deadline, token, key));
+            assert_eq!(wheel.len(), pending.len());
+        }
+
+        pending.sort_by_key(|&(deadline, token, _)| (deadline, token));
+        let expected = pending.iter().map(|&(_, token, _)| token).collect::<Vec<_>>();
+        assert_eq!(expire_all(&mut wheel, Instant::from_millis(i64::MAX)), expected);
+    }
+
     #[test]
     fn test_duration_getters() {
         let instant = Duration::from_millis(4934);
@@ -1,12 +1,29 @@
-// See https://tools.ietf.org/html/rfc2131 for the DHCP specification.
-
+//! The Dynamic Host Configuration Protocol for IPv4.
+//!
+//! Implements the message format of [RFC2131] with the options of [RFC2132] that are relevant to
+//! a client configuring a single ethernet interface. Other options are skipped when parsing.
+//!
+//! [RFC2131]: https://tools.ietf.org/html/rfc2131
+//! [RFC2132]: https://tools.ietf.org/html/rfc2132
 use byteorder::{ByteOrder, NetworkEndian};
 
-use {Error, Result};
-use super::{EthernetAddress, Ipv4Address};
+use crate::wire::{Error, Result};
 use super::arp::Hardware;
+use super::ethernet::Address as EthernetAddress;
+use super::ipv4::Address as Ipv4Address;
+
+/// The port on which servers receive messages.
+pub const SERVER_PORT: u16 = 67;
+
+/// The port on which clients receive messages.
+pub const CLIENT_PORT: u16 = 68;
+
+const DHCP_MAGIC_NUMBER: u32 = 0x6382_5363;
 
-const DHCP_MAGIC_NUMBER: u32 = 0x63825363;
+byte_wrapper! {
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct dhcp([u8]);
+}
 
 enum_with_unknown! {
     /// The possible opcodes of a DHCP packet.
@@ -31,8 +48,8 @@ enum_with_unknown! {
 }
 
 impl MessageType {
-    fn opcode(&self) -> OpCode {
-        match *self {
+    fn opcode(self) -> OpCode {
+        match self {
             MessageType::Discover | MessageType::Inform | MessageType::Request |
                 MessageType::Decline | MessageType::Release => OpCode::Request,
             MessageType::Offer | MessageType::Ack | MessageType::Nak => OpCode::Reply,
@@ -52,301 +69,226 @@ pub enum DhcpOption<'a> {
     ServerIdentifier(Ipv4Address),
     Router(Ipv4Address),
     SubnetMask(Ipv4Address),
+    /// A time in seconds, such as the lease time.
+    Time { kind: u8, seconds: u32 },
     Other { kind: u8, data: &'a [u8] }
 }
 
+mod field {
+    #![allow(non_snake_case)]
+    #![allow(unused)]
+    use crate::wire::field::{Field, Rest};
+
+    pub(crate) const OP: usize = 0;
+    pub(crate) const HTYPE: usize = 1;
+    pub(crate) const HLEN: usize = 2;
+    pub(crate) const HOPS: usize = 3;
+    pub(crate) const XID: Field = 4..8;
+    pub(crate) const SECS: Field = 8..10;
+    pub(crate) const FLAGS: Field = 10..12;
+    pub(crate) const CIADDR: Field = 12..16;
+    pub(crate) const YIADDR: Field = 16..20;
+    pub(crate) const SIADDR: Field = 20..24;
+    pub(crate) const GIADDR: Field = 24..28;
+    pub(crate) const CHADDR: Field = 28..34;
+    /// The hardware address field has room for 16 bytes, the rest is padding for ethernet.
+    pub(crate) const CHADDR_PAD: Field = 34..44;
+    pub(crate) const SNAME: Field = 44..108;
+    pub(crate) const FILE: Field = 108..236;
+    pub(crate) const MAGIC_NUMBER: Field = 236..240;
+    pub(crate) const OPTIONS: Rest = 240..;
+
+    // Vendor Extensions
+    pub(crate) const OPT_END: u8 = 255;
+    pub(crate) const OPT_PAD: u8 = 0;
+    pub(crate) const OPT_SUBNET_MASK: u8 = 1;
+    pub(crate) const OPT_ROUTER: u8 = 3;
+    pub(crate) const OPT_DOMAIN_NAME_SERVER: u8 = 6;
+
+    // DHCP Extensions
+    pub(crate) const OPT_REQUESTED_IP: u8 = 50;
+    pub(crate) const OPT_IP_LEASE_TIME: u8 = 51;
+    pub(crate) const OPT_DHCP_MESSAGE_TYPE: u8 = 53;
+    pub(crate) const OPT_SERVER_IDENTIFIER: u8 = 54;
+    pub(crate) const OPT_PARAMETER_REQUEST_LIST: u8 = 55;
+    pub(crate) const OPT_RENEWAL_TIME_VALUE: u8 = 58;
+    pub(crate) const OPT_REBINDING_TIME_VALUE: u8 = 59;
+    pub(crate) const OPT_CLIENT_ID: u8 = 61;
+}
+
+/// The option codes that a client requests from the server.
+pub mod option {
+    use super::field;
+
+    pub const SUBNET_MASK: u8 = field::OPT_SUBNET_MASK;
+    pub const ROUTER: u8 = field::OPT_ROUTER;
+    pub const DOMAIN_NAME_SERVER: u8 = field::OPT_DOMAIN_NAME_SERVER;
+    pub const IP_LEASE_TIME: u8 = field::OPT_IP_LEASE_TIME;
+    pub const RENEWAL_TIME_VALUE: u8 = field::OPT_RENEWAL_TIME_VALUE;
+    pub const REBINDING_TIME_VALUE: u8 = field::OPT_REBINDING_TIME_VALUE;
+}
+
 impl<'a> DhcpOption<'a> {
+    /// Parse the first option of a buffer, returning the remaining options.
     pub fn parse(buffer: &'a [u8]) -> Result<(&'a [u8], DhcpOption<'a>)> {
         // See https://tools.ietf.org/html/rfc2132 for all possible DHCP options.
+        let kind = *buffer.first().ok_or(Error::Truncated)?;
+        if kind == field::OPT_END || kind == field::OPT_PAD {
+            let option = if kind == field::OPT_END {
+                DhcpOption::EndOfList
+            } else {
+                DhcpOption::Pad
+            };
+            return Ok((&buffer[1..], option));
+        }
 
-        let (skip_len, option);
-        match *buffer.get(0).ok_or(Error::Truncated)? {
-            field::OPT_END => {
-                skip_len = 1;
-                option = DhcpOption::EndOfList;
-            }
-            field::OPT_PAD => {
-                skip_len = 1;
-                option = DhcpOption::Pad;
-            }
-            kind => {
-                let length = *buffer.get(1).ok_or(Error::Truncated)? as usize;
-                skip_len = length + 2;
-                let data = buffer.get(2..skip_len).ok_or(Error::Truncated)?;
-                match (kind, length) {
-                    (field::OPT_END, _) |
-                    (field::OPT_PAD, _) =>
-                        unreachable!(),
-                    (field::OPT_DHCP_MESSAGE_TYPE, 1) => {
-                        option = DhcpOption::MessageType(MessageType::from(data[0]));
-                    },
-                    (field::OPT_REQUESTED_IP, 4) => {
-                        option = DhcpOption::RequestedIp(Ipv4Address::from_bytes(data));
-                    }
-                    (field::OPT_CLIENT_ID, 7) => {
-                        let hardware_type = Hardware::from(u16::from(data[0]));
-                        if hardware_type != Hardware::Ethernet {
-                            return Err(Error::Unrecognized);
-                        }
-                        option = DhcpOption::ClientIdentifier(EthernetAddress::from_bytes(&data[1..]));
-                    }
-                    (field::OPT_SERVER_IDENTIFIER, 4) => {
-                        option = DhcpOption::ServerIdentifier(Ipv4Address::from_bytes(data));
-                    }
-                    (field::OPT_ROUTER, 4) => {
-                        option = DhcpOption::Router(Ipv4Address::from_bytes(data));
-                    }
-                    (field::OPT_SUBNET_MASK, 4) => {
-                        option = DhcpOption::SubnetMask(Ipv4Address::from_bytes(data));
-                    }
-                    (_, _) => {
-                        option = DhcpOption::Other { kind: kind, data: data };
-                    }
+        let length = usize::from(*buffer.get(1).ok_or(Error::Truncated)?);
+        let skip_len = length + 2;
+        let data = buffer.get(2..skip_len).ok_or(Error::Truncated)?;
+        let option = match (kind, length) {
+            (field::OPT_DHCP_MESSAGE_TYPE, 1) =>
+                DhcpOption::MessageType(MessageType::from(data[0])),
+            (field::OPT_REQUESTED_IP, 4) =>
+                DhcpOption::RequestedIp(Ipv4Address::from_bytes(data)),
+            (field::OPT_CLIENT_ID, 7) => {
+                let hardware_type = Hardware::from(u16::from(data[0]));
+                if hardware_type != Hardware::Ethernet {
+                    return Err(Error::Unrecognized);
                 }
-            }
-        }
+                DhcpOption::ClientIdentifier(EthernetAddress::from_bytes(&data[1..]))
+            },
+            (field::OPT_SERVER_IDENTIFIER, 4) =>
+                DhcpOption::ServerIdentifier(Ipv4Address::from_bytes(data)),
+            (field::OPT_ROUTER, 4) =>
+                DhcpOption::Router(Ipv4Address::from_bytes(data)),
+            (field::OPT_SUBNET_MASK, 4) =>
+                DhcpOption::SubnetMask(Ipv4Address::from_bytes(data)),
+            (field::OPT_IP_LEASE_TIME, 4)
+            | (field::OPT_RENEWAL_TIME_VALUE, 4)
+            | (field::OPT_REBINDING_TIME_VALUE, 4) =>
+                DhcpOption::Time { kind, seconds: NetworkEndian::read_u32(data) },
+            (_, _) => DhcpOption::Other { kind, data },
+        };
+
         Ok((&buffer[skip_len..], option))
     }
 
+    /// The number of bytes of the emitted option.
     pub fn buffer_len(&self) -> usize {
         match self {
-            &DhcpOption::EndOfList => 1,
-            &DhcpOption::Pad => 1,
-            &DhcpOption::MessageType(_) => 3,
-            &DhcpOption::ClientIdentifier(eth_addr) => {
-                3 + eth_addr.as_bytes().len()
-            }
-            &DhcpOption::RequestedIp(ip) |
-            &DhcpOption::ServerIdentifier(ip) |
-            &DhcpOption::Router(ip) |
-            &DhcpOption::SubnetMask(ip) => {
-                2 + ip.as_bytes().len()
-            },
-            &DhcpOption::Other { data, .. } => 2 + data.len()
+            DhcpOption::EndOfList | DhcpOption::Pad => 1,
+            DhcpOption::MessageType(_) => 3,
+            DhcpOption::ClientIdentifier(eth_addr) => 3 + eth_addr.as_bytes().len(),
+            DhcpOption::RequestedIp(ip)
+            | DhcpOption::ServerIdentifier(ip)
+            | DhcpOption::Router(ip)
+            | DhcpOption::SubnetMask(ip) => 2 + ip.as_bytes().len(),
+            DhcpOption::Time { .. } => 6,
+            DhcpOption::Other { data, .. } => 2 + data.len()
         }
     }
 
+    /// Emit the option to the start of a buffer, returning the remaining buffer.
     pub fn emit<'b>(&self, buffer: &'b mut [u8]) -> &'b mut [u8] {
-        let skip_length;
-        match self {
-            &DhcpOption::EndOfList => {
-                skip_length = 1;
-                buffer[0] = field::OPT_END;
-            }
-            &DhcpOption::Pad => {
-                skip_length = 1;
-                buffer[0] = field::OPT_PAD;
-            }
+        let skip_length = self.buffer_len();
+        match *self {
+            DhcpOption::EndOfList => buffer[0] = field::OPT_END,
+            DhcpOption::Pad => buffer[0] = field::OPT_PAD,
             _ => {
-                skip_length = self.buffer_len();
                 buffer[1] = (skip_length - 2) as u8;
-                match self {
-                    &DhcpOption::EndOfList | &DhcpOption::Pad => unreachable!(),
-                    &DhcpOption::MessageType(value) => {
+                match *self {
+                    DhcpOption::EndOfList | DhcpOption::Pad => unreachable!(),
+                    DhcpOption::MessageType(value) => {
                         buffer[0] = field::OPT_DHCP_MESSAGE_TYPE;
                         buffer[2] = value.into();
-                    }
-                    &DhcpOption::ClientIdentifier(eth_addr) => {
+                    },
+                    DhcpOption::ClientIdentifier(eth_addr) => {
                         buffer[0] = field::OPT_CLIENT_ID;
                         buffer[2] = u16::from(Hardware::Ethernet) as u8;
                         buffer[3..9].copy_from_slice(eth_addr.as_bytes());
-                    }
-                    &DhcpOption::RequestedIp(ip)  => {
+                    },
+                    DhcpOption::RequestedIp(ip) => {
                         buffer[0] = field::OPT_REQUESTED_IP;
                         buffer[2..6].copy_from_slice(ip.as_bytes());
-                    }
-                    &DhcpOption::ServerIdentifier(ip)  => {
+                    },
+                    DhcpOption::ServerIdentifier(ip) => {
                         buffer[0] = field::OPT_SERVER_IDENTIFIER;
                         buffer[2..6].copy_from_slice(ip.as_bytes());
-                    }
-                    &DhcpOption::Router(ip)  => {
+                    },
+                    DhcpOption::Router(ip) => {
                         buffer[0] = field::OPT_ROUTER;
                         buffer[2..6].copy_from_slice(ip.as_bytes());
-                    }
-                    &DhcpOption::SubnetMask(mask)  => {
+                    },
+                    DhcpOption::SubnetMask(mask) => {
                         buffer[0] = field::OPT_SUBNET_MASK;
                         buffer[2..6].copy_from_slice(mask.as_bytes());
-                    }
-                    &DhcpOption::Other { kind, data: provided } => {
+                    },
+                    DhcpOption::Time { kind, seconds } => {
+                        buffer[0] = kind;
+                        NetworkEndian::write_u32(&mut buffer[2..6], seconds);
+                    },
+                    DhcpOption::Other { kind, data: provided } => {
                         buffer[0] = kind;
                         buffer[2..skip_length].copy_from_slice(provided);
-                    }
+                    },
                 }
-            }
+            },
         }
         &mut buffer[skip_length..]
     }
 }
 
-/// A read/write wrapper around a Dynamic Host Configuration Protocol packet buffer.
-#[derive(Debug, PartialEq)]
-pub struct Packet<T: AsRef<[u8]>> {
-    buffer: T
-}
-
-pub(crate) mod field {
-    #![allow(non_snake_case)]
-    #![allow(unused)]
-
-    use wire::field::*;
-
-    pub const OP: usize = 0;
-    pub const HTYPE: usize = 1;
-    pub const HLEN: usize = 2;
-    pub const HOPS: usize = 3;
-    pub const XID: Field = 4..8;
-    pub const SECS: Field = 8..10;
-    pub const FLAGS: Field = 10..12;
-    pub const CIADDR: Field = 12..16;
-    pub const YIADDR: Field = 16..20;
-    pub const SIADDR: Field = 20..24;
-    pub const GIADDR: Field = 24..28;
-    pub const CHADDR: Field = 28..34;
-    pub const SNAME: Field = 34..108;
-    pub const FILE: Field = 108..236;
-    pub const MAGIC_NUMBER: Field = 236..240;
-    pub const OPTIONS: Rest = 240..;
+impl dhcp {
+    /// Imbue a raw octet buffer with DHCP packet structure.
+    pub fn new_unchecked(data: &[u8]) -> &Self {
+        Self::__from_macro_new_unchecked(data)
+    }
 
-    // Vendor Extensions
-    pub const OPT_END: u8 = 255;
-    pub const OPT_PAD: u8 = 0;
-    pub const OPT_SUBNET_MASK: u8 = 1;
-    pub const OPT_TIME_OFFSET: u8 = 2;
-    pub const OPT_ROUTER: u8 = 3;
-    pub const OPT_TIME_SERVER: u8 = 4;
-    pub const OPT_NAME_SERVER: u8 = 5;
-    pub const OPT_DOMAIN_NAME_SERVER: u8 = 6;
-    pub const OPT_LOG_SERVER: u8 = 7;
-    pub const OPT_COOKIE_SERVER: u8 = 8;
-    pub const OPT_LPR_SERVER: u8 = 9;
-    pub const OPT_IMPRESS_SERVER: u8 = 10;
-    pub const OPT_RESOURCE_LOCATION_SERVER: u8 = 11;
-    pub const OPT_HOST_NAME: u8 = 12;
-    pub const OPT_BOOT_FILE_SIZE: u8 = 13;
-    pub const OPT_MERIT_DUMP: u8 = 14;
-    pub const OPT_DOMAIN_NAME: u8 = 15;
-    pub const OPT_SWAP_SERVER: u8 = 16;
-    pub const OPT_ROOT_PATH: u8 = 17;
-    pub const OPT_EXTENSIONS_PATH: u8 = 18;
-
-    // IP Layer Parameters per Host
-    pub const OPT_IP_FORWARDING: u8 = 19;
-    pub const OPT_NON_LOCAL_SOURCE_ROUTING: u8 = 20;
-    pub const OPT_POLICY_FILTER: u8 = 21;
-    pub const OPT_MAX_DATAGRAM_REASSEMBLY_SIZE: u8 = 22;
-    pub const OPT_DEFAULT_TTL: u8 = 23;
-    pub const OPT_PATH_MTU_AGING_TIMEOUT: u8 = 24;
-    pub const OPT_PATH_MTU_PLATEU_TABLE: u8 = 25;
-
-    // IP Layer Parameters per Interface
-    pub const OPT_INTERFACE_MTU: u8 = 26;
-    pub const OPT_ALL_SUBNETS_ARE_LOCAL: u8 = 27;
-    pub const OPT_BROADCAST_ADDRESS: u8 = 28;
-    pub const OPT_PERFORM_MASK_DISCOVERY: u8 = 29;
-    pub const OPT_MASK_SUPPLIER: u8 = 30;
-    pub const OPT_PERFORM_ROUTER_DISCOVERY: u8 = 31;
-    pub const OPT_ROUTER_SOLICITATION_ADDRESS: u8 = 32;
-    pub const OPT_STATIC_ROUTE: u8 = 33;
-
-    // Link Layer Parameters per Interface
-    pub const OPT_TRAILER_ENCAPSULATION: u8 = 34;
-    pub const OPT_ARP_CACHE_TIMEOUT: u8 = 35;
-    pub const OPT_ETHERNET_ENCAPSULATION: u8 = 36;
-
-    // TCP Parameters
-    pub const OPT_TCP_DEFAULT_TTL: u8 = 37;
-    pub const OPT_TCP_KEEPALIVE_INTERVAL: u8 = 38;
-    pub const OPT_TCP_KEEPALIVE_GARBAGE: u8 = 39;
-
-    // Application and Service Parameters
-    pub const OPT_NIS_DOMAIN: u8 = 40;
-    pub const OPT_NIS_SERVERS: u8 = 41;
-    pub const OPT_NTP_SERVERS: u8 = 42;
-    pub const OPT_VENDOR_SPECIFIC_INFO: u8 = 43;
-    pub const OPT_NETBIOS_NAME_SERVER: u8 = 44;
-    pub const OPT_NETBIOS_DISTRIBUTION_SERVER: u8 = 45;
-    pub const OPT_NETBIOS_NODE_TYPE: u8 = 46;
-    pub const OPT_NETBIOS_SCOPE: u8 = 47;
-    pub const OPT_X_WINDOW_FONT_SERVER: u8 = 48;
-    pub const OPT_X_WINDOW_DISPLAY_MANAGER: u8 = 49;
-    pub const OPT_NIS_PLUS_DOMAIN: u8 = 64;
-    pub const OPT_NIS_PLUS_SERVERS: u8 = 65;
-    pub const OPT_MOBILE_IP_HOME_AGENT: u8 = 68;
-    pub const OPT_SMTP_SERVER: u8 = 69;
-    pub const OPT_POP3_SERVER: u8 = 70;
-    pub const OPT_NNTP_SERVER: u8 = 71;
-    pub const OPT_WWW_SERVER: u8 = 72;
-    pub const OPT_FINGER_SERVER: u8 = 73;
-    pub const OPT_IRC_SERVER: u8 = 74;
-    pub const OPT_STREETTALK_SERVER: u8 = 75;
-    pub const OPT_STDA_SERVER: u8 = 76;
+    /// Imbue a mutable octet buffer with DHCP packet structure.
+    pub fn new_unchecked_mut(data: &mut [u8]) -> &mut Self {
+        Self::__from_macro_new_unchecked_mut(data)
+    }
 
-    // DHCP Extensions
-    pub const OPT_REQUESTED_IP: u8 = 50;
-    pub const OPT_IP_LEASE_TIME: u8 = 51;
-    pub const OPT_OPTION_OVERLOAD: u8 = 52;
-    pub const OPT_TFTP_SERVER_NAME: u8 = 66;
-    pub const OPT_BOOTFILE_NAME: u8 = 67;
-    pub const OPT_DHCP_MESSAGE_TYPE: u8 = 53;
-    pub const OPT_SERVER_IDENTIFIER: u8 = 54;
-    pub const OPT_PARAMETER_REQUEST_LIST: u8 = 55;
-    pub const OPT_MESSAGE: u8 = 56;
-    pub const OPT_MAX_DHCP_MESSAGE_SIZE: u8 = 57;
-    pub const OPT_RENEWAL_TIME_VALUE: u8 = 58;
-    pub const OPT_REBINDING_TIME_VALUE: u8 = 59;
-    pub const OPT_VENDOR_CLASS_ID: u8 = 60;
-    pub const OPT_CLIENT_ID: u8 = 61;
-}
+    /// Shorthand for a combination of `new_unchecked` and `check_len`.
+    pub fn new_checked(data: &[u8]) -> Result<&Self> {
+        Self::new_unchecked(data).check_len()?;
+        Ok(Self::new_unchecked(data))
+    }
 
-impl<T: AsRef<[u8]>> Packet<T> {
-    /// Imbue a raw octet buffer with DHCP packet structure.
-    pub fn new_unchecked(buffer: T) -> Packet<T> {
-        Packet { buffer }
+    /// Unwrap the packet as a raw byte slice.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
     }
 
-    /// Shorthand for a combination of [new_unchecked] and [check_len].
-    ///
-    /// [new_unchecked]: #method.new_unchecked
-    /// [check_len]: #method.check_len
-    pub fn new_checked(buffer: T) -> Result<Packet<T>> {
-        let packet = Self::new_unchecked(buffer);
-        packet.check_len()?;
-        Ok(packet)
+    /// Unwrap the packet as a mutable raw byte slice.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.0
     }
 
     /// Ensure that no accessor method will panic if called.
-    /// Returns `Err(Error::Truncated)` if the buffer is too short.
     ///
-    /// [set_header_len]: #method.set_header_len
+    /// Returns `Err(Error::Truncated)` if the buffer is too short.
     pub fn check_len(&self) -> Result<()> {
-        let len = self.buffer.as_ref().len();
-        if len < field::MAGIC_NUMBER.end {
+        if self.0.len() < field::MAGIC_NUMBER.end {
             Err(Error::Truncated)
         } else {
             Ok(())
         }
     }
 
-    /// Consume the packet, returning the underlying buffer.
-    pub fn into_inner(self) -> T {
-        self.buffer
-    }
-
     /// Returns the operation code of this packet.
     pub fn opcode(&self) -> OpCode {
-        let data = self.buffer.as_ref();
-        OpCode::from(data[field::OP])
+        OpCode::from(self.0[field::OP])
     }
 
     /// Returns the hardware protocol type (e.g. ethernet).
     pub fn hardware_type(&self) -> Hardware {
-        let data = self.buffer.as_ref();
-        Hardware::from(u16::from(data[field::HTYPE]))
+        Hardware::from(u16::from(self.0[field::HTYPE]))
     }
 
     /// Returns the length of a hardware address in bytes (e.g. 6 for ethernet).
     pub fn hardware_len(&self) -> u8 {
-        self.buffer.as_ref()[field::HLEN]
+        self.0[field::HLEN]
     }
 
     /// Returns the transaction ID.
@@ -355,24 +297,21 @@ impl<T: AsRef<[u8]>> Packet<T> {
     /// associate messages and responses between client and server. The number is chosen by
     /// the client.
     pub fn transaction_id(&self) -> u32 {
-        let field = &self.buffer.as_ref()[field::XID];
-        NetworkEndian::read_u32(field)
+        NetworkEndian::read_u32(&self.0[field::XID])
     }
 
     /// Returns the hardware address of the client (called `chaddr` in the specification).
     ///
-    /// Only ethernet is supported by `smoltcp`, so this functions returns
-    /// an `EthernetAddress`.
+    /// Only ethernet is supported, so this functions returns an ethernet address.
     pub fn client_hardware_address(&self) -> EthernetAddress {
-        let field = &self.buffer.as_ref()[field::CHADDR];
-        EthernetAddress::from_bytes(field)
+        EthernetAddress::from_bytes(&self.0[field::CHADDR])
     }
 
     /// Returns the value of the `hops` field.
     ///
     /// The `hops` field is set to zero by clients and optionally used by relay agents.
     pub fn hops(&self) -> u8 {
-        self.buffer.as_ref()[field::HOPS]
+        self.0[field::HOPS]
     }
 
     /// Returns the value of the `secs` field.
@@ -380,16 +319,14 @@ impl<T: AsRef<[u8]>> Packet<T> {
     /// The secs field is filled by clients and describes the number of seconds elapsed
     /// since client began process.
     pub fn secs(&self) -> u16 {
-        let field = &self.buffer.as_ref()[field::SECS];
-        NetworkEndian::read_u16(field)
+        NetworkEndian::read_u16(&self.0[field::SECS])
     }
 
     /// Returns the value of the `magic cookie` field in the DHCP options.
     ///
     /// This field should be always be `0x63825363`.
     pub fn magic_number(&self) -> u32 {
-        let field = &self.buffer.as_ref()[field::MAGIC_NUMBER];
-        NetworkEndian::read_u32(field)
+        NetworkEndian::read_u32(&self.0[field::MAGIC_NUMBER])
     }
 
     /// Returns the Ipv4 address of the client, zero if not set.
@@ -398,79 +335,65 @@ impl<T: AsRef<[u8]>> Packet<T> {
     /// this field is “only filled in if client is in `BOUND`, `RENEW` or `REBINDING` state
     /// and can respond to ARP requests”.
     pub fn client_ip(&self) -> Ipv4Address {
-        let field = &self.buffer.as_ref()[field::CIADDR];
-        Ipv4Address::from_bytes(field)
+        Ipv4Address::from_bytes(&self.0[field::CIADDR])
     }
 
     /// Returns the value of the `yiaddr` field, zero if not set.
     pub fn your_ip(&self) -> Ipv4Address {
-        let field = &self.buffer.as_ref()[field::YIADDR];
-        Ipv4Address::from_bytes(field)
+        Ipv4Address::from_bytes(&self.0[field::YIADDR])
     }
 
     /// Returns the value of the `siaddr` field, zero if not set.
     pub fn server_ip(&self) -> Ipv4Address {
-        let field = &self.buffer.as_ref()[field::SIADDR];
-        Ipv4Address::from_bytes(field)
+        Ipv4Address::from_bytes(&self.0[field::SIADDR])
     }
 
     /// Returns the value of the `giaddr` field, zero if not set.
     pub fn relay_agent_ip(&self) -> Ipv4Address {
-        let field = &self.buffer.as_ref()[field::GIADDR];
-        Ipv4Address::from_bytes(field)
+        Ipv4Address::from_bytes(&self.0[field::GIADDR])
     }
 
     /// Returns true if the broadcast flag is set.
     pub fn broadcast_flag(&self) -> bool {
-        let field = &self.buffer.as_ref()[field::FLAGS];
-        NetworkEndian::read_u16(field) & 0b1 == 0b1
+        NetworkEndian::read_u16(&self.0[field::FLAGS]) & 0x8000 != 0
     }
-}
 
-impl<'a, T: AsRef<[u8]> + ?Sized> Packet<&'a T> {
-    /// Return a pointer to the options.
-    #[inline]
-    pub fn options(&self) -> Result<&'a [u8]> {
-        let data = self.buffer.as_ref();
-        data.get(field::OPTIONS).ok_or(Error::Malformed)
+    /// Return the options.
+    pub fn options(&self) -> &[u8] {
+        &self.0[field::OPTIONS]
     }
-}
 
-impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
     /// Sets the optional `sname` (“server name”) and `file` (“boot file name”) fields to zero.
     ///
     /// The fields are not commonly used, so we set their value always to zero. **This method
     /// must be called when creating a packet, otherwise the emitted values for these fields
     /// are undefined!**
     pub fn set_sname_and_boot_file_to_zero(&mut self) {
-        let data = self.buffer.as_mut();
-        for byte in &mut data[field::SNAME] {
+        for byte in &mut self.0[field::SNAME] {
             *byte = 0;
         }
-        for byte in &mut data[field::FILE] {
+        for byte in &mut self.0[field::FILE] {
             *byte = 0;
         }
     }
 
     /// Sets the `OpCode` for the packet.
     pub fn set_opcode(&mut self, value: OpCode) {
-        let data = self.buffer.as_mut();
-        data[field::OP] = value.into();
+        self.0[field::OP] = value.into();
     }
 
     /// Sets the hardware address type (only ethernet is supported).
     pub fn set_hardware_type(&mut self, value: Hardware) {
-        let data = self.buffer.as_mut();
         let number: u16 = value.into();
-        assert!(number <= u16::from(u8::max_value())); // TODO: Replace with TryFrom when it's stable
-        data[field::HTYPE] = number as u8;
+        assert!(number <= u16::from(u8::MAX));
+        self.0[field::HTYPE] = number as u8;
     }
 
     /// Sets the hardware address length.
     ///
     /// Only ethernet is supported, so this field should be set to the value `6`.
     pub fn set_hardware_len(&mut self, value: u8) {
-        self.buffer.as_mut()[field::HLEN] = value;
+        self.0[field::HLEN] = value;
     }
 
     /// Sets the transaction ID.
@@ -479,23 +402,24 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
     /// associate messages and responses between client and server. The number is chosen by
     /// the client.
     pub fn set_transaction_id(&mut self, value: u32) {
-        let field = &mut self.buffer.as_mut()[field::XID];
-        NetworkEndian::write_u32(field, value)
+        NetworkEndian::write_u32(&mut self.0[field::XID], value)
     }
 
     /// Sets the ethernet address of the client.
     ///
-    /// Sets the `chaddr` field.
+    /// Sets the `chaddr` field and zeroes its remaining padding.
     pub fn set_client_hardware_address(&mut self, value: EthernetAddress) {
-        let field = &mut self.buffer.as_mut()[field::CHADDR];
-        field.copy_from_slice(value.as_bytes());
+        self.0[field::CHADDR].copy_from_slice(value.as_bytes());
+        for byte in &mut self.0[field::CHADDR_PAD] {
+            *byte = 0;
+        }
     }
 
     /// Sets the hops field.
     ///
     /// The `hops` field is set to zero by clients and optionally used by relay agents.
     pub fn set_hops(&mut self, value: u8) {
-        self.buffer.as_mut()[field::HOPS] = value;
+        self.0[field::HOPS] = value;
     }
 
     /// Sets the `secs` field.
@@ -503,16 +427,14 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
     /// The secs field is filled by clients and describes the number of seconds elapsed
     /// since client began process.
     pub fn set_secs(&mut self, value: u16) {
-        let field = &mut self.buffer.as_mut()[field::SECS];
-        NetworkEndian::write_u16(field, value);
+        NetworkEndian::write_u16(&mut self.0[field::SECS], value);
     }
 
     /// Sets the value of the `magic cookie` field in the DHCP options.
     ///
     /// This field should be always be `0x63825363`.
     pub fn set_magic_number(&mut self, value: u32) {
-        let field = &mut self.buffer.as_mut()[field::MAGIC_NUMBER];
-        NetworkEndian::write_u32(field, value);
+        NetworkEndian::write_u32(&mut self.0[field::MAGIC_NUMBER], value);
     }
 
     /// Sets the Ipv4 address of the client.
@@ -521,41 +443,44 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
     /// this field is “only filled in if client is in `BOUND`, `RENEW` or `REBINDING` state
     /// and can respond to ARP requests”.
     pub fn set_client_ip(&mut self, value: Ipv4Address) {
-        let field = &mut self.buffer.as_mut()[field::CIADDR];
-        field.copy_from_slice(value.as_bytes());
+        self.0[field::CIADDR].copy_from_slice(value.as_bytes());
     }
 
     /// Sets the value of the `yiaddr` field.
     pub fn set_your_ip(&mut self, value: Ipv4Address) {
-        let field = &mut self.buffer.as_mut()[field::YIADDR];
-        field.copy_from_slice(value.as_bytes());
+        self.0[field::YIADDR].copy_from_slice(value.as_bytes());
     }
 
     /// Sets the value of the `siaddr` field.
     pub fn set_server_ip(&mut self, value: Ipv4Address) {
-        let field = &mut self.buffer.as_mut()[field::SIADDR];
-        field.copy_from_slice(value.as_bytes());
+        self.0[field::SIADDR].copy_from_slice(value.as_bytes());
     }
 
     /// Sets the value of the `giaddr` field.
     pub fn set_relay_agent_ip(&mut self, value: Ipv4Address) {
-        let field = &mut self.buffer.as_mut()[field::GIADDR];
-        field.copy_from_slice(value.as_bytes());
+        self.0[field::GIADDR].copy_from_slice(value.as_bytes());
     }
 
     /// Sets the broadcast flag to the specified value.
     pub fn set_broadcast_flag(&mut self, value: bool) {
-        let field = &mut self.buffer.as_mut()[field::FLAGS];
-        NetworkEndian::write_u16(field, if value { 1 } else { 0 });
+        NetworkEndian::write_u16(&mut self.0[field::FLAGS], if value { 0x8000 } else { 0 });
+    }
+
+    /// Return the options mutably.
+    pub fn options_mut(&mut self) -> &mut [u8] {
+        &mut self.0[field::OPTIONS]
+    }
+}
+
+impl AsRef<[u8]> for dhcp {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
     }
 }
 
-impl<'a, T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> Packet<&'a mut T> {
-    /// Return a pointer to the options.
-    #[inline]
-    pub fn options_mut(&mut self) -> Result<&mut [u8]> {
-        let data = self.buffer.as_mut();
-        data.get_mut(field::OPTIONS).ok_or(Error::Truncated)
+impl AsMut<[u8]> for dhcp {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
     }
 }
 
@@ -661,6 +586,12 @@ pub struct Repr<'a> {
     pub parameter_request_list: Option<&'a [u8]>,
     /// DNS servers
     pub dns_servers: Option<[Option<Ipv4Address>; 3]>,
+    /// The lease time of the address in seconds.
+    pub lease_duration: Option<u32>,
+    /// The time in seconds after which the client should renew its lease with the server.
+    pub renew_duration: Option<u32>,
+    /// The time in seconds after which the client should rebind its lease with any server.
+    pub rebind_duration: Option<u32>,
 }
 
 impl<'a> Repr<'a> {
@@ -672,14 +603,22 @@ impl<'a> Repr<'a> {
         if self.requested_ip.is_some() { len += 6; }
         if self.client_identifier.is_some() { len += 9; }
         if self.server_identifier.is_some() { len += 6; }
+        if self.router.is_some() { len += 6; }
+        if self.subnet_mask.is_some() { len += 6; }
         if let Some(list) = self.parameter_request_list { len += list.len() + 2; }
+        if let Some(servers) = self.dns_servers {
+            len += 2 + 4*servers.iter().filter(|server| server.is_some()).count();
+        }
+        if self.lease_duration.is_some() { len += 6; }
+        if self.renew_duration.is_some() { len += 6; }
+        if self.rebind_duration.is_some() { len += 6; }
 
         len
     }
 
     /// Parse a DHCP packet and return a high-level representation.
-    pub fn parse<T>(packet: &Packet<&'a T>) -> Result<Self>
-            where T: AsRef<[u8]> + ?Sized {
+    pub fn parse(packet: &'a dhcp) -> Result<Self> {
+        packet.check_len()?;
 
         let transaction_id = packet.transaction_id();
         let client_hardware_address = packet.client_hardware_address();
@@ -710,9 +649,12 @@ impl<'a> Repr<'a> {
         let mut subnet_mask = None;
         let mut parameter_request_list = None;
         let mut dns_servers = None;
+        let mut lease_duration = None;
+        let mut renew_duration = None;
+        let mut rebind_duration = None;
 
-        let mut options = packet.options()?;
-        while options.len() > 0 {
+        let mut options = packet.options();
+        while !options.is_empty() {
             let (next_options, option) = DhcpOption::parse(options)?;
             match option {
                 DhcpOption::EndOfList => break,
@@ -724,33 +666,39 @@ impl<'a> Repr<'a> {
                 },
                 DhcpOption::RequestedIp(ip) => {
                     requested_ip = Some(ip);
-                }
+                },
                 DhcpOption::ClientIdentifier(eth_addr) => {
                     client_identifier = Some(eth_addr);
-                }
+                },
                 DhcpOption::ServerIdentifier(ip) => {
                     server_identifier = Some(ip);
-                }
+                },
                 DhcpOption::Router(ip) => {
                     router = Some(ip);
-                }
+                },
                 DhcpOption::SubnetMask(mask) => {
                     subnet_mask = Some(mask);
-                }
-                DhcpOption::Other {kind: field::OPT_PARAMETER_REQUEST_LIST, data} => {
+                },
+                DhcpOption::Time { kind: field::OPT_IP_LEASE_TIME, seconds } => {
+                    lease_duration = Some(seconds);
+                },
+                DhcpOption::Time { kind: field::OPT_RENEWAL_TIME_VALUE, seconds } => {
+                    renew_duration = Some(seconds);
+                },
+                DhcpOption::Time { kind: field::OPT_REBINDING_TIME_VALUE, seconds } => {
+                    rebind_duration = Some(seconds);
+                },
+                DhcpOption::Other { kind: field::OPT_PARAMETER_REQUEST_LIST, data } => {
                     parameter_request_list = Some(data);
-                }
-                DhcpOption::Other {kind: field::OPT_DOMAIN_NAME_SERVER, data} => {
-                    let mut dns_servers_inner = [None; 3];
-                    for i in 0.. {
-                        let offset = 4 * i;
-                        let end = offset + 4;
-                        if end > data.len() { break }
-                        dns_servers_inner[i] = Some(Ipv4Address::from_bytes(&data[offset..end]));
+                },
+                DhcpOption::Other { kind: field::OPT_DOMAIN_NAME_SERVER, data } => {
+                    let mut servers = [None; 3];
+                    for (server, address) in servers.iter_mut().zip(data.chunks_exact(4)) {
+                        *server = Some(Ipv4Address::from_bytes(address));
                     }
-                    dns_servers = Some(dns_servers_inner);
-                }
-                DhcpOption::Other {..} => {}
+                    dns_servers = Some(servers);
+                },
+                DhcpOption::Time { .. } | DhcpOption::Other { .. } => {},
             }
             options = next_options;
         }
@@ -761,13 +709,16 @@ impl<'a> Repr<'a> {
             transaction_id, client_hardware_address, client_ip, your_ip, server_ip, relay_agent_ip,
             broadcast, requested_ip, server_identifier, router,
             subnet_mask, client_identifier, parameter_request_list, dns_servers,
+            lease_duration, renew_duration, rebind_duration,
             message_type: message_type?,
         })
     }
 
-    /// Emit a high-level representation into a Transmission Control Protocol packet.
-    pub fn emit<T>(&self, packet: &mut Packet<&mut T>) -> Result<()>
-            where T: AsRef<[u8]> + AsMut<[u8]> + ?Sized {
+    /// Emit a high-level representation into a DHCP packet.
+    ///
+    /// The packet must be at least `buffer_len` bytes long, the options are terminated and any
+    /// remaining bytes are left untouched.
+    pub fn emit(&self, packet: &mut dhcp) {
         packet.set_sname_and_boot_file_to_zero();
         packet.set_opcode(self.message_type.opcode());
         packet.set_hardware_type(Hardware::Ethernet);
@@ -775,49 +726,64 @@ impl<'a> Repr<'a> {
         packet.set_transaction_id(self.transaction_id);
         packet.set_client_hardware_address(self.client_hardware_address);
         packet.set_hops(0);
-        packet.set_secs(0); // TODO
-        packet.set_magic_number(0x63825363);
+        packet.set_secs(0);
+        packet.set_magic_number(DHCP_MAGIC_NUMBER);
         packet.set_client_ip(self.client_ip);
         packet.set_your_ip(self.your_ip);
         packet.set_server_ip(self.server_ip);
         packet.set_relay_agent_ip(self.relay_agent_ip);
         packet.set_broadcast_flag(self.broadcast);
 
-        {
-            let mut options = packet.options_mut()?;
-            let tmp = options; options = DhcpOption::MessageType(self.message_type).emit(tmp);
-            if let Some(eth_addr) = self.client_identifier {
-                let tmp = options; options = DhcpOption::ClientIdentifier(eth_addr).emit(tmp);
-            }
-            if let Some(ip) = self.server_identifier {
-                let tmp = options; options = DhcpOption::ServerIdentifier(ip).emit(tmp);
-            }
-            if let Some(ip) = self.router {
-                let tmp = options; options = DhcpOption::Router(ip).emit(tmp);
-            }
-            if let Some(ip) = self.subnet_mask {
-                let tmp = options; options = DhcpOption::SubnetMask(ip).emit(tmp);
-            }
-            if let Some(ip) = self.requested_ip {
-                let tmp = options; options = DhcpOption::RequestedIp(ip).emit(tmp);
+        let mut options = packet.options_mut();
+        options = DhcpOption::MessageType(self.message_type).emit(options);
+        if let Some(eth_addr) = self.client_identifier {
+            options = DhcpOption::ClientIdentifier(eth_addr).emit(options);
+        }
+        if let Some(ip) = self.server_identifier {
+            options = DhcpOption::ServerIdentifier(ip).emit(options);
+        }
+        if let Some(ip) = self.router {
+            options = DhcpOption::Router(ip).emit(options);
+        }
+        if let Some(ip) = self.subnet_mask {
+            options = DhcpOption::SubnetMask(ip).emit(options);
+        }
+        if let Some(ip) = self.requested_ip {
+            options = DhcpOption::RequestedIp(ip).emit(options);
+        }
+        if let Some(list) = self.parameter_request_list {
+            let option = DhcpOption::Other { kind: field::OPT_PARAMETER_REQUEST_LIST, data: list };
+            options = option.emit(options);
+        }
+        if let Some(servers) = self.dns_servers {
+            let mut data = [0; 12];
+            let mut len = 0;
+            for server in servers.iter().flatten() {
+                data[len..len + 4].copy_from_slice(server.as_bytes());
+                len += 4;
             }
-            if let Some(list) = self.parameter_request_list {
-                let option = DhcpOption::Other{ kind: field::OPT_PARAMETER_REQUEST_LIST, data: list };
-                let tmp = options; options = option.emit(tmp);
+            let option = DhcpOption::Other { kind: field::OPT_DOMAIN_NAME_SERVER, data: &data[..len] };
+            options = option.emit(options);
+        }
+        let times = [
+            (field::OPT_IP_LEASE_TIME, self.lease_duration),
+            (field::OPT_RENEWAL_TIME_VALUE, self.renew_duration),
+            (field::OPT_REBINDING_TIME_VALUE, self.rebind_duration),
+        ];
+        for &(kind, seconds) in times.iter() {
+            if let Some(seconds) = seconds {
+                options = DhcpOption::Time { kind, seconds }.emit(options);
             }
-            DhcpOption::EndOfList.emit(options);
         }
-
-        Ok(())
+        DhcpOption::EndOfList.emit(options);
     }
 }
 
 #[cfg(test)]
 mod test {
-    use wire::Ipv4Address;
     use super::*;
 
-    const MAGIC_COOKIE: u32 = 0x63825363;
+    const MAGIC_COOKIE: u32 = 0x6382_5363;
 
     static DISCOVER_BYTES: &[u8] = &[
         0x01, 0x01, 0x06, 0x00, 0x00, 0x00, 0x3d, 0x1d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -844,7 +810,7 @@ mod test {
 
     #[test]
     fn test_deconstruct_discover() {
-        let packet = Packet::new_unchecked(DISCOVER_BYTES);
+        let packet = dhcp::new_checked(DISCOVER_BYTES).unwrap();
         assert_eq!(packet.magic_number(), MAGIC_COOKIE);
         assert_eq!(packet.opcode(), OpCode::Request);
         assert_eq!(packet.hardware_type(), Hardware::Ethernet);
@@ -857,7 +823,7 @@ mod test {
         assert_eq!(packet.server_ip(), IP_NULL);
         assert_eq!(packet.relay_agent_ip(), IP_NULL);
         assert_eq!(packet.client_hardware_address(), CLIENT_MAC);
-        let options = packet.options().unwrap();
+        let options = packet.options();
         assert_eq!(options.len(), 3 + 9 + 6 + 6 + 1 + 7);
 
         let (options, message_type) = DhcpOption::parse(options).unwrap();
@@ -886,7 +852,7 @@ mod test {
     #[test]
     fn test_construct_discover() {
         let mut bytes = vec![0xa5; 272];
-        let mut packet = Packet::new_unchecked(&mut bytes);
+        let packet = dhcp::new_unchecked_mut(&mut bytes);
         packet.set_magic_number(MAGIC_COOKIE);
         packet.set_sname_and_boot_file_to_zero();
         packet.set_opcode(OpCode::Request);
@@ -902,24 +868,21 @@ mod test {
         packet.set_relay_agent_ip(IP_NULL);
         packet.set_client_hardware_address(CLIENT_MAC);
 
-        {
-            let mut options = packet.options_mut().unwrap();
-            let tmp = options; options = DhcpOption::MessageType(MessageType::Discover).emit(tmp);
-            let tmp = options; options = DhcpOption::ClientIdentifier(CLIENT_MAC).emit(tmp);
-            let tmp = options; options = DhcpOption::RequestedIp(IP_NULL).emit(tmp);
-            let option = DhcpOption::Other {
-                kind: field::OPT_PARAMETER_REQUEST_LIST, data: &[1, 3, 6, 42],
-            };
-            let tmp = options; options = option.emit(tmp);
-            DhcpOption::EndOfList.emit(options);
-        }
+        let mut options = packet.options_mut();
+        options = DhcpOption::MessageType(MessageType::Discover).emit(options);
+        options = DhcpOption::ClientIdentifier(CLIENT_MAC).emit(options);
+        options = DhcpOption::RequestedIp(IP_NULL).emit(options);
+        let option = DhcpOption::Other {
+            kind: field::OPT_PARAMETER_REQUEST_LIST, data: &[1, 3, 6, 42],
+        };
+        options = option.emit(options);
+        DhcpOption::EndOfList.emit(options);
 
-        let packet = &mut packet.into_inner()[..];
-        for byte in &mut packet[265..272] {
+        for byte in &mut bytes[265..272] {
             *byte = 0; // padding bytes
         }
 
-        assert_eq!(packet, DISCOVER_BYTES);
+        assert_eq!(&bytes[..], DISCOVER_BYTES);
     }
 
     fn discover_repr() -> Repr<'static> {
@@ -939,13 +902,16 @@ mod test {
             server_identifier: None,
             parameter_request_list: Some(&[1, 3, 6, 42]),
             dns_servers: None,
+            lease_duration: None,
+            renew_duration: None,
+            rebind_duration: None,
         }
     }
 
     #[test]
     fn test_parse_discover() {
-        let packet = Packet::new_unchecked(DISCOVER_BYTES);
-        let repr = Repr::parse(&packet).unwrap();
+        let packet = dhcp::new_checked(DISCOVER_BYTES).unwrap();
+        let repr = Repr::parse(packet).unwrap();
         assert_eq!(repr, discover_repr());
     }
 
@@ -953,16 +919,38 @@ mod test {
     fn test_emit_discover() {
         let repr = discover_repr();
         let mut bytes = vec![0xa5; repr.buffer_len()];
-        let mut packet = Packet::new_unchecked(&mut bytes);
-        repr.emit(&mut packet).unwrap();
-        let packet = &packet.into_inner()[..];
-        let packet_len = packet.len();
-        assert_eq!(packet, &DISCOVER_BYTES[..packet_len]);
+        repr.emit(dhcp::new_unchecked_mut(&mut bytes));
+        let packet_len = bytes.len();
+        assert_eq!(&bytes[..], &DISCOVER_BYTES[..packet_len]);
         for byte in &DISCOVER_BYTES[packet_len..] {
             assert_eq!(*byte, 0); // padding bytes
         }
     }
 
+    #[test]
+    fn test_roundtrip_ack() {
+        let repr = Repr {
+            message_type: MessageType::Ack,
+            your_ip: Ipv4Address([192, 168, 1, 20]),
+            router: Some(Ipv4Address([192, 168, 1, 1])),
+            subnet_mask: Some(Ipv4Address([255, 255, 255, 0])),
+            requested_ip: None,
+            client_identifier: None,
+            server_identifier: Some(Ipv4Address([192, 168, 1, 1])),
+            parameter_request_list: None,
+            dns_servers: Some([Some(Ipv4Address([8, 8, 8, 8])), Some(Ipv4Address([1, 1, 1, 1])), None]),
+            lease_duration: Some(3600),
+            renew_duration: Some(1800),
+            rebind_duration: None,
+            ..discover_repr()
+        };
+        let mut bytes = vec![0; repr.buffer_len()];
+        repr.emit(dhcp::new_unchecked_mut(&mut bytes));
+        assert_eq!(bytes[bytes.len() - 1], field::OPT_END);
+        let packet = dhcp::new_checked(&bytes).unwrap();
+        assert_eq!(Repr::parse(packet), Ok(repr));
+    }
+
     #[test]
     fn test_emit_dhcp_option() {
         static DATA: &[u8] = &[1, 3, 6];
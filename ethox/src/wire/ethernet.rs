@@ -11,6 +11,7 @@ enum_with_unknown! {
         Ipv4 = 0x0800,
         Arp  = 0x0806,
         Ipv6 = 0x86DD,
+        Vlan = 0x8100,
         JumboFrame = 0x8870,
     }
 }
@@ -21,12 +22,18 @@ impl fmt::Display for EtherType {
             EtherType::Ipv4 => write!(f, "IPv4"),
             EtherType::Ipv6 => write!(f, "IPv6"),
             EtherType::Arp  => write!(f, "ARP"),
+            EtherType::Vlan => write!(f, "802.1Q"),
             EtherType::JumboFrame => write!(f, "JumboFrame"),
             EtherType::Unknown(id) => write!(f, "0x{:04x}", id)
         }
     }
 }
 
+/// The minimum length of a frame, excluding the frame check sequence.
+///
+/// Shorter frames must be padded, the padding is not part of the payload of upper protocols.
+pub const MIN_FRAME_LEN: usize = 60;
+
 /// A six-octet Ethernet II address.
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
 pub struct Address(pub [u8; 6]);
@@ -70,6 +77,77 @@ impl Address {
     pub fn is_local(&self) -> bool {
         self.0[0] & 0x02 != 0
     }
+
+    /// The address to which frames for an ip multicast group are sent.
+    ///
+    /// IPv4 groups map their low 23 bits into `01:00:5e:00:00:00` ([RFC 1112], section 6.4),
+    /// IPv6 groups their low 32 bits into `33:33:00:00:00:00` ([RFC 2464], section 7). Returns
+    /// `None` if the address is not a multicast address.
+    ///
+    /// [RFC 1112]: https://tools.ietf.org/html/rfc1112
+    /// [RFC 2464]: https://tools.ietf.org/html/rfc2464
+    pub fn from_multicast(group: ip::Address) -> Option<Address> {
+        if !group.is_multicast() {
+            return None;
+        }
+
+        match group {
+            ip::Address::Ipv4(group) => {
+                let bytes = group.as_bytes();
+                Some(Address([0x01, 0x00, 0x5e, bytes[1] & 0x7f, bytes[2], bytes[3]]))
+            },
+            ip::Address::Ipv6(group) => {
+                let mut mac = [0x33, 0x33, 0, 0, 0, 0];
+                mac[2..].copy_from_slice(&group.as_bytes()[12..]);
+                Some(Address(mac))
+            },
+            _ => None,
+        }
+    }
+}
+
+/// An IEEE 802.1Q tag identifying the virtual lan of a frame.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Default)]
+pub struct VlanTag {
+    /// The priority code point, only the lowest three bits are used.
+    pub priority: u8,
+    /// The drop eligible indicator.
+    pub drop_eligible: bool,
+    /// The vlan identifier, only the lowest twelve bits are used.
+    pub vlan_id: u16,
+}
+
+impl VlanTag {
+    /// Construct a tag for a vlan, with default priority.
+    pub fn new(vlan_id: u16) -> Self {
+        VlanTag {
+            priority: 0,
+            drop_eligible: false,
+            vlan_id: vlan_id & 0xfff,
+        }
+    }
+
+    /// Decode the tag control information field.
+    pub fn from_tci(tci: u16) -> Self {
+        VlanTag {
+            priority: (tci >> 13) as u8,
+            drop_eligible: tci & 0x1000 != 0,
+            vlan_id: tci & 0xfff,
+        }
+    }
+
+    /// Encode the tag control information field.
+    pub fn tci(&self) -> u16 {
+        (u16::from(self.priority & 0x7) << 13)
+            | (u16::from(self.drop_eligible) << 12)
+            | (self.vlan_id & 0xfff)
+    }
+}
+
+impl fmt::Display for VlanTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "vlan={} pcp={}", self.vlan_id, self.priority)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -148,6 +226,11 @@ mod field {
     pub(crate) const SOURCE:      Field =  6..12;
     pub(crate) const ETHERTYPE:   Field = 12..14;
     pub(crate) const PAYLOAD:     Rest  = 14..;
+
+    // Only present in frames tagged with 802.1Q.
+    pub(crate) const VLAN_TCI:    Field = 14..16;
+    pub(crate) const INNER_TYPE:  Field = 16..18;
+    pub(crate) const VLAN_PAYLOAD: Rest = 18..;
 }
 
 impl ethernet {
@@ -183,13 +266,19 @@ impl ethernet {
 
     /// Ensure that no accessor method will panic if called.
     /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    ///
+    /// Frames tagged with 802.1Q must contain the complete tag.
     pub fn check_len(&self) -> Result<()> {
         let len = self.0.len();
         if len < field::PAYLOAD.start {
-            Err(Error::Truncated)
-        } else {
-            Ok(())
+            return Err(Error::Truncated);
+        }
+
+        if self.ethertype() == EtherType::Vlan && len < field::VLAN_PAYLOAD.start {
+            return Err(Error::Truncated);
         }
+
+        Ok(())
     }
 
     /// Return the length of a frame header.
@@ -219,6 +308,27 @@ impl ethernet {
         EtherType::from(raw)
     }
 
+    /// Return the 802.1Q tag, if the frame is tagged.
+    pub fn vlan_tag(&self) -> Option<VlanTag> {
+        if self.ethertype() != EtherType::Vlan {
+            return None;
+        }
+
+        let tci = NetworkEndian::read_u16(&self.0[field::VLAN_TCI]);
+        Some(VlanTag::from_tci(tci))
+    }
+
+    /// Return the EtherType of the payload, following an 802.1Q tag.
+    pub fn inner_ethertype(&self) -> EtherType {
+        match self.ethertype() {
+            EtherType::Vlan => {
+                let raw = NetworkEndian::read_u16(&self.0[field::INNER_TYPE]);
+                EtherType::from(raw)
+            },
+            other => other,
+        }
+    }
+
     /// Set the destination address field.
     pub fn set_dst_addr(&mut self, value: Address) {
         self.0[field::DESTINATION].copy_from_slice(value.as_bytes())
@@ -234,12 +344,21 @@ impl ethernet {
         NetworkEndian::write_u16(&mut self.0[field::ETHERTYPE], value.into())
     }
 
-    /// Return the payload as a byte slice.
+    /// Set the 802.1Q tag and the EtherType of the payload following it.
+    ///
+    /// The frame must be long enough to hold the tag.
+    pub fn set_vlan_tag(&mut self, tag: VlanTag, inner: EtherType) {
+        self.set_ethertype(EtherType::Vlan);
+        NetworkEndian::write_u16(&mut self.0[field::VLAN_TCI], tag.tci());
+        NetworkEndian::write_u16(&mut self.0[field::INNER_TYPE], inner.into())
+    }
+
+    /// Return the payload as a byte slice, without checking for 802.1Q.
     pub fn payload_slice(&self) -> &[u8] {
         &self.0[field::PAYLOAD]
     }
 
-    /// Return the payload as a mutable byte slice.
+    /// Return the payload as a mutable byte slice, without checking for 802.1Q.
     pub fn payload_mut_slice(&mut self) -> &mut [u8] {
         &mut self.0[field::PAYLOAD]
     }
@@ -293,24 +412,34 @@ impl<T: Payload> Frame<T> {
         self.buffer
     }
 
+    /// The vlan identifier of a tagged frame.
+    pub fn vlan_id(&self) -> Option<u16> {
+        self.repr.vlan.map(|tag| tag.vlan_id)
+    }
+
+    /// The priority code point of a tagged frame.
+    pub fn priority(&self) -> Option<u8> {
+        self.repr.vlan.map(|tag| tag.priority)
+    }
+
     /// Return the payload as a byte slice.
     pub fn payload_slice(&self) -> &[u8] {
-        &self.0[field::PAYLOAD]
+        &self.buffer.payload()[self.repr.header_len()..]
     }
 
     /// Return the payload as a mutable byte slice.
     pub fn payload_mut_slice(&mut self) -> &mut [u8] where T: PayloadMut {
         // Keeps header values unchanged.
-        ethernet::new_unchecked_mut(self.buffer.payload_mut())
-            .payload_mut_slice()
+        let header_len = self.repr.header_len();
+        &mut self.buffer.payload_mut()[header_len..]
     }
 }
 
 impl<'a, T: Payload + ?Sized> Frame<&'a T> {
-    /// Return a pointer to the payload, without checking for 802.1Q.
+    /// Return a pointer to the payload.
     #[inline]
     pub fn payload_bytes(&self) -> &'a [u8] {
-        &self.buffer.payload()[field::PAYLOAD]
+        &self.buffer.payload()[self.repr.header_len()..]
     }
 }
 
@@ -341,21 +470,31 @@ impl<T: Payload + PayloadMut> PayloadMut for Frame<T> {
     }
 
     fn resize(&mut self, length: usize) -> core::result::Result<(), PayloadError> {
-        self.buffer.resize(length + field::PAYLOAD.start)
+        self.buffer.resize(length + self.repr.header_len())
     }
 
     fn reframe(&mut self, mut reframe: Reframe)
         -> core::result::Result<(), PayloadError> 
     {
-        reframe.within_header(field::PAYLOAD.start);
+        reframe.within_header(self.repr.header_len());
         self.buffer.reframe(reframe)
     }
 }
 
 impl<T: Payload> fmt::Display for Frame<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.repr, f)
+    }
+}
+
+impl fmt::Display for Repr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "EthernetII src={} dst={} type={}",
-               self.src_addr(), self.dst_addr(), self.ethertype())
+               self.src_addr, self.dst_addr, self.ethertype)?;
+        match self.vlan {
+            Some(tag) => write!(f, " {}", tag),
+            None => Ok(()),
+        }
     }
 }
 
@@ -368,7 +507,7 @@ impl PrettyPrint for ethernet {
         };
         write!(f, "{}{}", indent, frame)?;
 
-        match frame.ethertype() {
+        match frame.repr().ethertype {
             EtherType::Arp => {
                 indent.increase(f)?;
                 arp::packet::pretty_print(&frame.payload(), f, indent)
@@ -386,12 +525,16 @@ impl PrettyPrint for ethernet {
     }
 }
 
-/// A high-level representation of an Internet Protocol version 4 packet header.
+/// A high-level representation of an Ethernet II frame header.
+///
+/// The `ethertype` is always the type of the payload, for frames tagged with 802.1Q it is the
+/// type following the tag.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Repr {
     pub src_addr:    Address,
     pub dst_addr:    Address,
     pub ethertype:   EtherType,
+    pub vlan:        Option<VlanTag>,
 }
 
 impl Repr {
@@ -401,20 +544,32 @@ impl Repr {
         Ok(Repr {
             src_addr: frame.src_addr(),
             dst_addr: frame.dst_addr(),
-            ethertype: frame.ethertype(),
+            ethertype: frame.inner_ethertype(),
+            vlan: frame.vlan_tag(),
         })
     }
 
     /// Return the length of a header that will be emitted from this high-level representation.
     pub fn header_len(&self) -> usize {
-        field::PAYLOAD.start
+        match self.vlan {
+            Some(_) => field::VLAN_PAYLOAD.start,
+            None => field::PAYLOAD.start,
+        }
+    }
+
+    /// Return the length of a buffer required to hold a frame with the payload of a given length.
+    pub fn buffer_len(&self, payload_len: usize) -> usize {
+        self.header_len() + payload_len
     }
 
     /// Emit a high-level representation into an Ethernet II frame.
     pub fn emit(&self, frame: &mut ethernet) {
         frame.set_src_addr(self.src_addr);
         frame.set_dst_addr(self.dst_addr);
-        frame.set_ethertype(self.ethertype);
+        match self.vlan {
+            Some(tag) => frame.set_vlan_tag(tag, self.ethertype),
+            None => frame.set_ethertype(self.ethertype),
+        }
     }
 }
 
@@ -431,6 +586,55 @@ mod test {
         assert!(Address::BROADCAST.is_multicast());
         assert!(Address::BROADCAST.is_local());
     }
+
+    #[test]
+    fn test_from_multicast() {
+        let group = ip::Address::v4(224, 0, 0, 251);
+        assert_eq!(Address::from_multicast(group), Some(Address([0x01, 0x00, 0x5e, 0, 0, 0xfb])));
+        let group = ip::Address::v6(0xff02, 0, 0, 0, 0, 1, 0xff12, 0x3456);
+        let mac = Address([0x33, 0x33, 0xff, 0x12, 0x34, 0x56]);
+        assert_eq!(Address::from_multicast(group), Some(mac));
+        assert_eq!(Address::from_multicast(ip::Address::v4(192, 168, 1, 1)), None);
+    }
+
+    #[test]
+    fn test_repr_display() {
+        let repr = Repr {
+            src_addr: Address([0x11, 0x12, 0x13, 0x14, 0x15, 0x16]),
+            dst_addr: Address::BROADCAST,
+            ethertype: EtherType::Arp,
+            vlan: None,
+        };
+        assert_eq!(format!("{}", repr),
+                   "EthernetII src=11-12-13-14-15-16 dst=ff-ff-ff-ff-ff-ff type=ARP");
+    }
+
+    #[test]
+    fn test_vlan() {
+        static FRAME_BYTES: [u8; 20] =
+            [0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+             0x11, 0x12, 0x13, 0x14, 0x15, 0x16,
+             0x81, 0x00,
+             0xa0, 0x2a,
+             0x08, 0x06,
+             0xaa, 0xbb];
+
+        let frame = Frame::new_checked(&FRAME_BYTES[..]).unwrap();
+        let repr = frame.repr();
+        assert_eq!(repr.ethertype, EtherType::Arp);
+        assert_eq!(repr.header_len(), 18);
+        assert_eq!(frame.vlan_id(), Some(42));
+        assert_eq!(frame.priority(), Some(5));
+        assert_eq!(frame.payload_slice(), &[0xaa, 0xbb]);
+
+        let mut bytes = vec![0; 20];
+        repr.emit(ethernet::new_unchecked_mut(&mut bytes));
+        bytes[18..].copy_from_slice(&[0xaa, 0xbb]);
+        assert_eq!(bytes, &FRAME_BYTES[..]);
+
+        // The tag itself is truncated.
+        assert_eq!(ethernet::new_checked(&FRAME_BYTES[..16]).err(), Some(Error::Truncated));
+    }
 }
 
 #[cfg(test)]
@@ -195,6 +195,10 @@ mod field {
     pub(crate) const ECHO_IDENT: Field = 4..6;
     pub(crate) const ECHO_SEQNO: Field = 6..8;
 
+    pub(crate) const POINTER:    usize = 4;
+
+    pub(crate) const GATEWAY:    Field = 4..8;
+
     pub(crate) const HEADER_END: usize = 8;
 }
 
@@ -279,6 +283,24 @@ impl icmpv4 {
         NetworkEndian::read_u16(&self.0[field::ECHO_SEQNO])
     }
 
+    /// Return the pointer field (for parameter problem packets).
+    ///
+    /// # Panics
+    /// This function may panic if this packet is not a parameter problem packet.
+    #[inline]
+    pub fn param_problem_pointer(&self) -> u8 {
+        self.0[field::POINTER]
+    }
+
+    /// Return the gateway address field (for redirect packets).
+    ///
+    /// # Panics
+    /// This function may panic if this packet is not a redirect packet.
+    #[inline]
+    pub fn redirect_gateway(&self) -> v4::Address {
+        v4::Address::from_bytes(&self.0[field::GATEWAY])
+    }
+
     /// Return the header length.
     /// The result depends on the value of the message type field.
     pub fn header_len(&self) -> usize {
@@ -286,6 +308,7 @@ impl icmpv4 {
             Message::EchoRequest    => field::ECHO_SEQNO.end,
             Message::EchoReply      => field::ECHO_SEQNO.end,
             Message::DstUnreachable => field::UNUSED.end,
+            Message::Redirect       => field::GATEWAY.end,
             _ => field::UNUSED.end // make a conservative assumption
         }
     }
@@ -336,6 +359,24 @@ impl icmpv4 {
         NetworkEndian::write_u16(&mut self.0[field::ECHO_SEQNO], value);
     }
 
+    /// Set the pointer field (for parameter problem packets).
+    ///
+    /// # Panics
+    /// This function may panic if this packet is not a parameter problem packet.
+    #[inline]
+    pub fn set_param_problem_pointer(&mut self, value: u8) {
+        self.0[field::POINTER] = value;
+    }
+
+    /// Set the gateway address field (for redirect packets).
+    ///
+    /// # Panics
+    /// This function may panic if this packet is not a redirect packet.
+    #[inline]
+    pub fn set_redirect_gateway(&mut self, value: v4::Address) {
+        self.0[field::GATEWAY].copy_from_slice(value.as_bytes());
+    }
+
     /// Compute and fill in the header checksum.
     pub fn fill_checksum(&mut self) {
         self.set_checksum(0);
@@ -343,6 +384,19 @@ impl icmpv4 {
         self.set_checksum(checksum);
     }
 
+    /// Change the message type and code, updating the checksum incrementally.
+    ///
+    /// Unlike `fill_checksum` this does not read the rest of the message. The checksum is only
+    /// correct afterwards if it was correct before.
+    pub fn update_msg_type(&mut self, msg_type: Message, msg_code: u8) {
+        let old = NetworkEndian::read_u16(&self.0[field::TYPE..=field::CODE]);
+        self.set_msg_type(msg_type);
+        self.set_msg_code(msg_code);
+        let new = NetworkEndian::read_u16(&self.0[field::TYPE..=field::CODE]);
+        let checksum = checksum::update(self.checksum(), old, new);
+        self.set_checksum(checksum);
+    }
+
     /// Return the payload as a byte slice.
     pub fn payload_slice(&self) -> &[u8] {
         &self.0[field::HEADER_END..]
@@ -453,6 +507,16 @@ pub enum Repr {
         reason: DstUnreachable,
         header: v4::Repr,
     },
+    ParamProblem {
+        reason: ParamProblem,
+        pointer: u8,
+        header: v4::Repr,
+    },
+    Redirect {
+        reason: Redirect,
+        gateway: v4::Address,
+        header: v4::Repr,
+    },
     #[doc(hidden)]
     __Nonexhaustive
 }
@@ -512,6 +576,53 @@ impl Repr {
                 })
             }
 
+            (Message::ParamProblem, code) => {
+                // The quote contains the complete original header, including the options that the
+                // pointer may refer to, but only the start of its payload.
+                let quote = packet.payload_slice();
+                if quote.len() < 20 { return Err(Error::Truncated) }
+                let ip_packet = v4::packet::new_unchecked(quote);
+                let header_len = usize::from(ip_packet.header_len());
+                if header_len < 20 { return Err(Error::Malformed) }
+                if quote.len() < header_len { return Err(Error::Truncated) }
+
+                Ok(Repr::ParamProblem {
+                    reason: ParamProblem::from(code),
+                    pointer: packet.param_problem_pointer(),
+                    header: v4::Repr {
+                        src_addr: ip_packet.src_addr(),
+                        dst_addr: ip_packet.dst_addr(),
+                        protocol: ip_packet.protocol(),
+                        payload_len: usize::from(ip_packet.total_len()).saturating_sub(header_len),
+                        hop_limit: ip_packet.hop_limit(),
+                    },
+                })
+            }
+
+            (Message::Redirect, code) => {
+                // Like other errors, the quote is the original header and the start of its payload
+                // but the header must be understood to apply the redirect to the right route.
+                let quote = packet.payload_slice();
+                if quote.len() < 20 { return Err(Error::Truncated) }
+                let ip_packet = v4::packet::new_unchecked(quote);
+                let header_len = usize::from(ip_packet.header_len());
+                if header_len < 20 { return Err(Error::Malformed) }
+                // RFC 792 requires the first eight bytes of the original payload.
+                if quote.len() < header_len + 8 { return Err(Error::Truncated) }
+
+                Ok(Repr::Redirect {
+                    reason: Redirect::from(code),
+                    gateway: packet.redirect_gateway(),
+                    header: v4::Repr {
+                        src_addr: ip_packet.src_addr(),
+                        dst_addr: ip_packet.dst_addr(),
+                        protocol: ip_packet.protocol(),
+                        payload_len: usize::from(ip_packet.total_len()).saturating_sub(header_len),
+                        hop_limit: ip_packet.hop_limit(),
+                    },
+                })
+            }
+
             // Unknown types are not as specified in the standard and iana registry.
             (Message::Unknown(_), _) => Err(Error::Unrecognized),
             // Others are just not supported (yet). // TODO: more reprs.
@@ -526,7 +637,9 @@ impl Repr {
             Repr::EchoReply { payload, .. } => {
                 field::HEADER_END + payload
             },
-            Repr::DstUnreachable { header, .. } => {
+            Repr::DstUnreachable { header, .. } |
+            Repr::ParamProblem { header, .. } |
+            Repr::Redirect { header, .. } => {
                 // Be strict in what to emit. Exactly eight beytes as required.
                 field::HEADER_END + header.buffer_len() + 8
             }
@@ -561,6 +674,25 @@ impl Repr {
                 header.emit(ip_packet, checksum);
             },
 
+            &Repr::ParamProblem { reason, pointer, header, } => {
+                packet.set_msg_type(Message::ParamProblem);
+                packet.set_msg_code(reason.into());
+                packet.as_bytes_mut()[field::UNUSED].copy_from_slice(&[0; 4]);
+                packet.set_param_problem_pointer(pointer);
+
+                let ip_packet = v4::packet::new_unchecked_mut(packet.payload_mut_slice());
+                header.emit(ip_packet, checksum);
+            },
+
+            &Repr::Redirect { reason, gateway, header, } => {
+                packet.set_msg_type(Message::Redirect);
+                packet.set_msg_code(reason.into());
+                packet.set_redirect_gateway(gateway);
+
+                let ip_packet = v4::packet::new_unchecked_mut(packet.payload_mut_slice());
+                header.emit(ip_packet, checksum);
+            },
+
             &Repr::__Nonexhaustive => unreachable!()
         }
 
@@ -603,6 +735,12 @@ impl fmt::Display for Repr {
             &Repr::DstUnreachable { reason, .. } =>
                 write!(f, "ICMPv4 destination unreachable ({})",
                        reason),
+            &Repr::ParamProblem { pointer, .. } =>
+                write!(f, "ICMPv4 parameter problem pointer={}",
+                       pointer),
+            &Repr::Redirect { reason, gateway, header } =>
+                write!(f, "ICMPv4 redirect ({:?}) dst={} gateway={}",
+                       reason, header.dst_addr, gateway),
             &Repr::__Nonexhaustive => unreachable!()
         }
     }
@@ -624,7 +762,7 @@ impl PrettyPrint for icmpv4 {
 
         write!(f, "{}{}", indent, repr)?;
         match packet.msg_type() {
-            Message::DstUnreachable => {
+            Message::DstUnreachable | Message::Redirect => {
                 indent.increase(f)?;
                 v4::packet::pretty_print(packet.payload_slice(), f, indent)
             }
@@ -696,6 +834,88 @@ mod test {
         assert_eq!(packet.as_bytes(), &ECHO_PACKET_BYTES[..]);
     }
 
+    #[test]
+    fn test_echo_display() {
+        assert_eq!(format!("{}", echo_packet_repr()),
+                   "ICMPv4 echo request id=4660 seq=43981 len=4");
+    }
+
+    static REDIRECT_PACKET_BYTES: [u8; 36] =
+        [0x05, 0x01, 0x00, 0x00,
+         0xc0, 0xa8, 0x00, 0xfe,
+         0x45, 0x00, 0x00, 0x1c,
+         0x00, 0x00, 0x40, 0x00,
+         0x40, 0x11, 0x00, 0x00,
+         0xc0, 0xa8, 0x00, 0x14,
+         0x0a, 0x00, 0x00, 0x01,
+         0x24, 0xb8, 0x00, 0x2b,
+         0x00, 0x08, 0x12, 0x34];
+
+    fn redirect_packet_repr() -> Repr {
+        Repr::Redirect {
+            reason: Redirect::Host,
+            gateway: v4::Address::new(192, 168, 0, 254),
+            header: v4::Repr {
+                src_addr: v4::Address::new(192, 168, 0, 20),
+                dst_addr: v4::Address::new(10, 0, 0, 1),
+                protocol: crate::wire::ip::Protocol::Udp,
+                payload_len: 8,
+                hop_limit: 64,
+            },
+        }
+    }
+
+    /// The bytes with the checksums of the message and the quoted header filled in.
+    fn redirect_packet_bytes() -> Vec<u8> {
+        let mut bytes = REDIRECT_PACKET_BYTES.to_vec();
+        let packet = icmpv4::new_unchecked_mut(&mut bytes);
+        v4::packet::new_unchecked_mut(packet.payload_mut_slice()).fill_checksum();
+        packet.fill_checksum();
+        bytes
+    }
+
+    #[test]
+    fn test_redirect_parse() {
+        let bytes = redirect_packet_bytes();
+        let packet = icmpv4::new_checked(&bytes).unwrap();
+        assert_eq!(packet.msg_type(), Message::Redirect);
+        assert_eq!(packet.redirect_gateway(), v4::Address::new(192, 168, 0, 254));
+        assert_eq!(Repr::parse(packet, Checksum::Manual), Ok(redirect_packet_repr()));
+
+        // The quote must contain the first eight bytes of the original payload.
+        let truncated = icmpv4::new_unchecked(&bytes[..bytes.len() - 1]);
+        assert_eq!(Repr::parse(truncated, Checksum::Ignored), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn test_redirect_emit() {
+        let repr = redirect_packet_repr();
+        let expected = redirect_packet_bytes();
+        assert_eq!(repr.buffer_len(), expected.len());
+
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        let packet = icmpv4::new_unchecked_mut(&mut bytes);
+        // The start of the original payload is copied by the caller.
+        packet.payload_mut_slice()[20..].copy_from_slice(&expected[28..]);
+        repr.emit(packet, Checksum::Manual);
+        assert_eq!(packet.as_bytes(), &expected[..]);
+        assert_eq!(format!("{}", repr),
+                   "ICMPv4 redirect (Host) dst=10.0.0.1 gateway=192.168.0.254");
+    }
+
+    #[test]
+    fn test_update_msg_type() {
+        let mut bytes = ECHO_PACKET_BYTES.to_vec();
+        let packet = icmpv4::new_unchecked_mut(&mut bytes);
+        packet.update_msg_type(Message::EchoReply, 0);
+        assert_eq!(packet.msg_type(), Message::EchoReply);
+        assert!(packet.verify_checksum());
+
+        let updated = packet.checksum();
+        packet.fill_checksum();
+        assert_eq!(packet.checksum(), updated);
+    }
+
     #[test]
     fn test_check_len() {
         let bytes = [0x08, 0x00, 0x00, 0x00,
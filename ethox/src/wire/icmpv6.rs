@@ -1,11 +1,33 @@
-use core::{cmp, fmt};
+//! The Internet Control Message Protocol for IPv6.
+//!
+//! The error messages of [RFC4443], echo and the messages required for neighbor discovery
+//! ([RFC4861]) are represented. Error messages represent the header of the invoking packet, the
+//! rest of the quote follows it in the payload. Neighbor discovery messages carry at most the
+//! link-layer address option in their representation, other options are skipped when parsing.
+//! Router advertisements additionally represent the mtu option, their prefix information options
+//! are available through [`icmpv6::prefixes`].
+//!
+//! Router solicitations, redirects and the multicast listener discovery messages are not
+//! represented yet. Their message types are recognized but parsing them returns
+//! `Err(Error::Unsupported)`.
+//!
+//! [`icmpv6::prefixes`]: struct.icmpv6.html#method.prefixes
+//!
+//! [RFC4443]: https://tools.ietf.org/html/rfc4443
+//! [RFC4861]: https://tools.ietf.org/html/rfc4861
+use core::fmt;
 use byteorder::{ByteOrder, NetworkEndian};
 
-use {Error, Result};
-use phy::ChecksumCapabilities;
+use crate::wire::{ethernet, ip, Error, Result};
+use crate::wire::ip::v6::{self, Address, Cidr, Subnet};
+use crate::wire::pretty_print::{PrettyPrint, PrettyIndent};
+
 use super::ip::checksum;
-use super::{IpAddress, IpProtocol, Ipv6Packet, Ipv6Repr};
-use super::{MldRepr, NdiscRepr};
+
+/// The hop limit required for neighbor discovery messages.
+///
+/// Receivers discard messages with any other hop limit, proving that they were not forwarded.
+pub const NDISC_HOP_LIMIT: u8 = 255;
 
 enum_with_unknown! {
     /// Internet protocol control message type.
@@ -35,61 +57,53 @@ enum_with_unknown! {
         /// Redirect
         Redirect        = 0x89,
         /// Multicast Listener Report
-        MldReport       = 0x8f
+        MldReport       = 0x8f,
     }
 }
 
 impl Message {
-    /// Per [RFC 4443 § 2.1] ICMPv6 message types with the highest order
-    /// bit set are informational messages while message types without
-    /// the highest order bit set are error messages.
+    /// Per [RFC 4443 § 2.1] ICMPv6 message types with the highest order bit set are informational
+    /// messages while message types without the highest order bit set are error messages.
     ///
     /// [RFC 4443 § 2.1]: https://tools.ietf.org/html/rfc4443#section-2.1
-    pub fn is_error(&self) -> bool {
-        (u8::from(*self) & 0x80) != 0x80
+    pub fn is_error(self) -> bool {
+        (u8::from(self) & 0x80) != 0x80
     }
 
-    /// Return a boolean value indicating if the given message type
-    /// is an [NDISC] message type.
+    /// Return a boolean value indicating if the given message type is an [NDISC] message type.
     ///
     /// [NDISC]: https://tools.ietf.org/html/rfc4861
-    pub fn is_ndisc(&self) -> bool {
-        match *self {
+    pub fn is_ndisc(self) -> bool {
+        matches!(self,
             Message::RouterSolicit | Message::RouterAdvert | Message::NeighborSolicit |
-            Message::NeighborAdvert | Message::Redirect => true,
-            _ => false,
-        }
+            Message::NeighborAdvert | Message::Redirect)
     }
 
-    /// Return a boolean value indicating if the given message type
-    /// is an [MLD] message type.
+    /// Return a boolean value indicating if the given message type is an [MLD] message type.
     ///
     /// [MLD]: https://tools.ietf.org/html/rfc3810
-    pub fn is_mld(&self) -> bool {
-        match *self {
-            Message::MldQuery | Message::MldReport => true,
-            _ => false,
-        }
+    pub fn is_mld(self) -> bool {
+        matches!(self, Message::MldQuery | Message::MldReport)
     }
 }
 
 impl fmt::Display for Message {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &Message::DstUnreachable  => write!(f, "destination unreachable"),
-            &Message::PktTooBig       => write!(f, "packet too big"),
-            &Message::TimeExceeded    => write!(f, "time exceeded"),
-            &Message::ParamProblem    => write!(f, "parameter problem"),
-            &Message::EchoReply       => write!(f, "echo reply"),
-            &Message::EchoRequest     => write!(f, "echo request"),
-            &Message::RouterSolicit   => write!(f, "router solicitation"),
-            &Message::RouterAdvert    => write!(f, "router advertisement"),
-            &Message::NeighborSolicit => write!(f, "neighbor solicitation"),
-            &Message::NeighborAdvert  => write!(f, "neighbor advert"),
-            &Message::Redirect        => write!(f, "redirect"),
-            &Message::MldQuery        => write!(f, "multicast listener query"),
-            &Message::MldReport       => write!(f, "multicast listener report"),
-            &Message::Unknown(id)     => write!(f, "{}", id)
+            Message::DstUnreachable  => write!(f, "destination unreachable"),
+            Message::PktTooBig       => write!(f, "packet too big"),
+            Message::TimeExceeded    => write!(f, "time exceeded"),
+            Message::ParamProblem    => write!(f, "parameter problem"),
+            Message::EchoRequest     => write!(f, "echo request"),
+            Message::EchoReply       => write!(f, "echo reply"),
+            Message::MldQuery        => write!(f, "multicast listener query"),
+            Message::RouterSolicit   => write!(f, "router solicitation"),
+            Message::RouterAdvert    => write!(f, "router advertisement"),
+            Message::NeighborSolicit => write!(f, "neighbor solicitation"),
+            Message::NeighborAdvert  => write!(f, "neighbor advertisement"),
+            Message::Redirect        => write!(f, "redirect"),
+            Message::MldReport       => write!(f, "multicast listener report"),
+            Message::Unknown(id)     => write!(f, "{}", id)
         }
     }
 }
@@ -110,28 +124,28 @@ enum_with_unknown! {
         /// Source address failed ingress/egress policy.
         FailedPolicy    = 5,
         /// Reject route to destination.
-        RejectRoute     = 6
+        RejectRoute     = 6,
     }
 }
 
 impl fmt::Display for DstUnreachable {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &DstUnreachable::NoRoute =>
+            DstUnreachable::NoRoute =>
                 write!(f, "no route to destination"),
-            &DstUnreachable::AdminProhibit =>
+            DstUnreachable::AdminProhibit =>
                 write!(f, "communication with destination administratively prohibited"),
-            &DstUnreachable::BeyondScope =>
+            DstUnreachable::BeyondScope =>
                 write!(f, "beyond scope of source address"),
-            &DstUnreachable::AddrUnreachable =>
+            DstUnreachable::AddrUnreachable =>
                 write!(f, "address unreachable"),
-            &DstUnreachable::PortUnreachable =>
+            DstUnreachable::PortUnreachable =>
                 write!(f, "port unreachable"),
-            &DstUnreachable::FailedPolicy =>
+            DstUnreachable::FailedPolicy =>
                 write!(f, "source address failed ingress/egress policy"),
-            &DstUnreachable::RejectRoute =>
+            DstUnreachable::RejectRoute =>
                 write!(f, "reject route to destination"),
-            &DstUnreachable::Unknown(id) =>
+            DstUnreachable::Unknown(id) =>
                 write!(f, "{}", id)
         }
     }
@@ -145,20 +159,20 @@ enum_with_unknown! {
         /// Unrecognized Next Header type encountered.
         UnrecognizedNxtHdr = 1,
         /// Unrecognized IPv6 option encountered.
-        UnrecognizedOption = 2
+        UnrecognizedOption = 2,
     }
 }
 
 impl fmt::Display for ParamProblem {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &ParamProblem::ErroneousHdrField  =>
-                write!(f, "erroneous header field."),
-            &ParamProblem::UnrecognizedNxtHdr =>
-                write!(f, "unrecognized next header type."),
-            &ParamProblem::UnrecognizedOption =>
-                write!(f, "unrecognized IPv6 option."),
-            &ParamProblem::Unknown(id) =>
+            ParamProblem::ErroneousHdrField =>
+                write!(f, "erroneous header field"),
+            ParamProblem::UnrecognizedNxtHdr =>
+                write!(f, "unrecognized next header type"),
+            ParamProblem::UnrecognizedOption =>
+                write!(f, "unrecognized IPv6 option"),
+            ParamProblem::Unknown(id) =>
                 write!(f, "{}", id)
         }
     }
@@ -170,729 +184,1124 @@ enum_with_unknown! {
         /// Hop limit exceeded in transit.
         HopLimitExceeded    = 0,
         /// Fragment reassembly time exceeded.
-        FragReassemExceeded = 1
+        FragReassemExceeded = 1,
     }
 }
 
 impl fmt::Display for TimeExceeded {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &TimeExceeded::HopLimitExceeded =>
+            TimeExceeded::HopLimitExceeded =>
                 write!(f, "hop limit exceeded in transit"),
-            &TimeExceeded::FragReassemExceeded =>
+            TimeExceeded::FragReassemExceeded =>
                 write!(f, "fragment reassembly time exceeded"),
-            &TimeExceeded::Unknown(id) =>
+            TimeExceeded::Unknown(id) =>
                 write!(f, "{}", id)
         }
     }
 }
 
-/// A read/write wrapper around an Internet Control Message Protocol version 6 packet buffer.
-#[derive(Debug, PartialEq, Clone)]
-pub struct Packet<T: AsRef<[u8]>> {
-    pub(super) buffer: T
+/// The flags of a neighbor advertisement.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct NeighborFlags(pub u8);
+
+impl NeighborFlags {
+    /// A constant with no flag bit set.
+    pub const NONE: Self = NeighborFlags(0x00);
+    /// The sender is a router.
+    pub const ROUTER: Self = NeighborFlags(0x80);
+    /// The advertisement was sent in response to a solicitation.
+    pub const SOLICITED: Self = NeighborFlags(0x40);
+    /// The advertisement should override an existing cache entry.
+    pub const OVERRIDE: Self = NeighborFlags(0x20);
+
+    /// Return the router flag.
+    pub fn router(self) -> bool {
+        self.0 & Self::ROUTER.0 != 0
+    }
+
+    /// Return the solicited flag.
+    pub fn solicited(self) -> bool {
+        self.0 & Self::SOLICITED.0 != 0
+    }
+
+    /// Return the override flag.
+    pub fn override_(self) -> bool {
+        self.0 & Self::OVERRIDE.0 != 0
+    }
 }
 
-// Ranges and constants describing key boundaries in the ICMPv6 header.
-pub(super) mod field {
-    use wire::field::*;
-
-    // ICMPv6: See https://tools.ietf.org/html/rfc4443
-    pub const TYPE:              usize = 0;
-    pub const CODE:              usize = 1;
-    pub const CHECKSUM:          Field = 2..4;
-
-    pub const UNUSED:            Field = 4..8;
-    pub const MTU:               Field = 4..8;
-    pub const POINTER:           Field = 4..8;
-    pub const ECHO_IDENT:        Field = 4..6;
-    pub const ECHO_SEQNO:        Field = 6..8;
-
-    pub const HEADER_END:        usize = 8;
-
-    // NDISC: See https://tools.ietf.org/html/rfc4861
-    // Router Advertisement message offsets
-    pub const CUR_HOP_LIMIT:     usize = 4;
-    pub const ROUTER_FLAGS:      usize = 5;
-    pub const ROUTER_LT:         Field = 6..8;
-    pub const REACHABLE_TM:      Field = 8..12;
-    pub const RETRANS_TM:        Field = 12..16;
-
-    // Neighbor Solicitation message offsets
-    pub const TARGET_ADDR:       Field = 8..24;
-
-    // Neighbor Advertisement message offsets
-    pub const NEIGH_FLAGS:       usize = 4;
-
-    // Redirected Header message offsets
-    pub const DEST_ADDR:         Field = 24..40;
-
-    // MLD:
-    //   - https://tools.ietf.org/html/rfc3810
-    //   - https://tools.ietf.org/html/rfc3810
-    // Multicast Listener Query message
-    pub const MAX_RESP_CODE:     Field = 4..6;
-    pub const QUERY_RESV:        Field = 6..8;
-    pub const QUERY_MCAST_ADDR:  Field = 8..24;
-    pub const SQRV:              usize = 24;
-    pub const QQIC:              usize = 25;
-    pub const QUERY_NUM_SRCS:    Field = 26..28;
-
-    // Multicast Listener Report Message
-    pub const RECORD_RESV:       Field = 4..6;
-    pub const NR_MCAST_RCRDS:    Field = 6..8;
-
-    // Multicast Address Record Offsets
-    pub const RECORD_TYPE:       usize = 0;
-    pub const AUX_DATA_LEN:      usize = 1;
-    pub const RECORD_NUM_SRCS:   Field = 2..4;
-    pub const RECORD_MCAST_ADDR: Field = 4..20;
+impl core::ops::BitOr for NeighborFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        NeighborFlags(self.0 | other.0)
+    }
 }
 
-impl<T: AsRef<[u8]>> Packet<T> {
-    /// Imbue a raw octet buffer with ICMPv6 packet structure.
-    pub fn new_unchecked(buffer: T) -> Packet<T> {
-        Packet { buffer }
+/// The flags of a router advertisement.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RouterFlags(pub u8);
+
+impl RouterFlags {
+    /// A constant with no flag bit set.
+    pub const NONE: Self = RouterFlags(0x00);
+    /// Addresses are available via DHCPv6.
+    pub const MANAGED: Self = RouterFlags(0x80);
+    /// Other configuration is available via DHCPv6.
+    pub const OTHER: Self = RouterFlags(0x40);
+
+    /// Return the managed address configuration flag.
+    pub fn managed(self) -> bool {
+        self.0 & Self::MANAGED.0 != 0
+    }
+
+    /// Return the other configuration flag.
+    pub fn other(self) -> bool {
+        self.0 & Self::OTHER.0 != 0
+    }
+}
+
+impl core::ops::BitOr for RouterFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        RouterFlags(self.0 | other.0)
     }
+}
 
-    /// Shorthand for a combination of [new_unchecked] and [check_len].
+/// A prefix information option of a router advertisement.
+///
+/// Lifetimes are in seconds where `u32::MAX` represents infinity. See [RFC4861], section 4.6.2.
+///
+/// [RFC4861]: https://tools.ietf.org/html/rfc4861
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PrefixInfo {
+    /// The advertised prefix, with all bits past its length cleared.
+    pub prefix: Subnet,
+    /// Addresses with the prefix are on the link and can be reached directly.
+    pub on_link: bool,
+    /// The prefix can be used for stateless address autoconfiguration.
+    pub autonomous: bool,
+    /// The time for which the prefix is valid.
+    pub valid_lifetime: u32,
+    /// The time for which addresses generated from the prefix are preferred.
+    pub preferred_lifetime: u32,
+}
+
+/// An iterator over the prefix information options of a router advertisement.
+///
+/// Ends early at the first malformed option, which `Repr::parse` would reject.
+pub struct Prefixes<'a> {
+    options: &'a [u8],
+}
+
+byte_wrapper! {
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct icmpv6([u8]);
+}
+
+mod field {
+    use crate::wire::field::{Field, Rest};
+
+    pub(crate) const TYPE:       usize = 0;
+    pub(crate) const CODE:       usize = 1;
+    pub(crate) const CHECKSUM:   Field = 2..4;
+
+    pub(crate) const UNUSED:     Field = 4..8;
+    pub(crate) const PKT_TOO_BIG_MTU: Field = 4..8;
+    pub(crate) const POINTER:    Field = 4..8;
+
+    pub(crate) const ECHO_IDENT: Field = 4..6;
+    pub(crate) const ECHO_SEQNO: Field = 6..8;
+
+    pub(crate) const HEADER_END: usize = 8;
+
+    pub(crate) const NEIGH_FLAGS: usize = 4;
+    pub(crate) const TARGET_ADDR: Field = 8..24;
+    pub(crate) const NEIGH_OPTIONS: Rest = 24..;
+
+    pub(crate) const CUR_HOP_LIMIT: usize = 4;
+    pub(crate) const ROUTER_FLAGS: usize = 5;
+    pub(crate) const ROUTER_LIFETIME: Field = 6..8;
+    pub(crate) const REACHABLE_TIME: Field = 8..12;
+    pub(crate) const RETRANS_TIME: Field = 12..16;
+    pub(crate) const ROUTER_OPTIONS: Rest = 16..;
+
+    /// The type of the source link-layer address option.
+    pub(crate) const OPT_SOURCE_LLADDR: u8 = 1;
+    /// The type of the target link-layer address option.
+    pub(crate) const OPT_TARGET_LLADDR: u8 = 2;
+    /// The length of a link-layer address option for Ethernet.
+    pub(crate) const OPT_LLADDR_LEN: usize = 8;
+    /// The type of the prefix information option.
+    pub(crate) const OPT_PREFIX_INFO: u8 = 3;
+    /// The length of the prefix information option.
+    pub(crate) const OPT_PREFIX_INFO_LEN: usize = 32;
+    /// The type of the mtu option.
+    pub(crate) const OPT_MTU: u8 = 5;
+    /// The length of the mtu option.
+    pub(crate) const OPT_MTU_LEN: usize = 8;
+
+    pub(crate) const PREFIX_LEN: usize = 2;
+    pub(crate) const PREFIX_FLAGS: usize = 3;
+    pub(crate) const PREFIX_VALID: Field = 4..8;
+    pub(crate) const PREFIX_PREFERRED: Field = 8..12;
+    pub(crate) const PREFIX: Field = 16..32;
+    pub(crate) const MTU: Field = 4..8;
+}
+
+/// Control the checksum handling.
+///
+/// The checksum of ICMPv6 always covers the IPv6 pseudo header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Checksum {
+    /// Fill and verify the checksum.
+    Manual {
+        /// The source address of the enclosing ip packet.
+        src_addr: Address,
+        /// The destination address of the enclosing ip packet.
+        dst_addr: Address,
+    },
+
+    /// Never inspect the checksum.
+    Ignored,
+}
+
+/// A high-level representation of an Internet Control Message Protocol version 6 packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repr {
+    /// A destination unreachable error, quoting the header of the invoking packet.
     ///
-    /// [new_unchecked]: #method.new_unchecked
-    /// [check_len]: #method.check_len
-    pub fn new_checked(buffer: T) -> Result<Packet<T>> {
-        let packet = Self::new_unchecked(buffer);
-        packet.check_len()?;
-        Ok(packet)
+    /// The payload length of the header is the length of the quoted payload following it, not the
+    /// length of the original packet.
+    DstUnreachable {
+        reason: DstUnreachable,
+        header: v6::Repr,
+    },
+    /// A packet too big error with the mtu of the next hop, quoting the invoking packet.
+    PktTooBig {
+        mtu: u32,
+        header: v6::Repr,
+    },
+    /// A time exceeded error, quoting the invoking packet.
+    TimeExceeded {
+        reason: TimeExceeded,
+        header: v6::Repr,
+    },
+    /// A parameter problem with the offset of the offending octet in the invoking packet.
+    ParamProblem {
+        reason: ParamProblem,
+        pointer: u32,
+        header: v6::Repr,
+    },
+    /// An echo request, with the length of the payload following the header.
+    EchoRequest {
+        ident:  u16,
+        seq_no: u16,
+        payload: usize,
+    },
+    /// An echo reply, with the length of the payload following the header.
+    EchoReply {
+        ident:  u16,
+        seq_no: u16,
+        payload: usize,
+    },
+    /// A neighbor solicitation, optionally with the link-layer address of the sender.
+    NeighborSolicit {
+        target_addr: Address,
+        lladdr: Option<ethernet::Address>,
+    },
+    /// A neighbor advertisement, optionally with the link-layer address of the target.
+    NeighborAdvert {
+        flags: NeighborFlags,
+        target_addr: Address,
+        lladdr: Option<ethernet::Address>,
+    },
+    /// A router advertisement, optionally with the link-layer address of the router and the mtu
+    /// of the link.
+    ///
+    /// The router lifetime is in seconds, the reachable and retransmission time in milliseconds.
+    RouterAdvert {
+        hop_limit: u8,
+        flags: RouterFlags,
+        router_lifetime: u16,
+        reachable_time: u32,
+        retrans_time: u32,
+        lladdr: Option<ethernet::Address>,
+        mtu: Option<u32>,
+    },
+}
+
+impl icmpv6 {
+    /// Imbue a raw octet buffer with ICMPv6 packet structure.
+    pub fn new_unchecked(data: &[u8]) -> &Self {
+        Self::__from_macro_new_unchecked(data)
+    }
+
+    /// Imbue a mutable octet buffer with ICMPv6 packet structure.
+    pub fn new_unchecked_mut(data: &mut [u8]) -> &mut Self {
+        Self::__from_macro_new_unchecked_mut(data)
+    }
+
+    /// Shorthand for a combination of `new_unchecked` and `check_len`.
+    pub fn new_checked(data: &[u8]) -> Result<&Self> {
+        Self::new_unchecked(data).check_len()?;
+        Ok(Self::new_unchecked(data))
+    }
+
+    /// Unwrap the packet as a raw byte slice.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Unwrap the packet as a mutable raw byte slice.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.0
     }
 
     /// Ensure that no accessor method will panic if called.
-    /// Returns `Err(Error::Truncated)` if the buffer is too short.
+    ///
+    /// Returns `Err(Error::Truncated)` if the buffer is too short for the header, including the
+    /// target address of neighbor discovery messages and the timers of router advertisements.
     pub fn check_len(&self) -> Result<()> {
-        let len = self.buffer.as_ref().len();
-        if len < field::HEADER_END {
-            Err(Error::Truncated)
-        } else {
-            if len < self.header_len() {
-                Err(Error::Truncated)
-            } else {
-                Ok(())
-            }
+        if self.0.len() < field::HEADER_END {
+            return Err(Error::Truncated);
         }
-    }
 
-    /// Consume the packet, returning the underlying buffer.
-    pub fn into_inner(self) -> T {
-        self.buffer
+        match self.msg_type() {
+            Message::NeighborSolicit | Message::NeighborAdvert
+                if self.0.len() < field::TARGET_ADDR.end => Err(Error::Truncated),
+            Message::RouterAdvert
+                if self.0.len() < field::RETRANS_TIME.end => Err(Error::Truncated),
+            _ => Ok(()),
+        }
     }
 
     /// Return the message type field.
-    #[inline]
     pub fn msg_type(&self) -> Message {
-        let data = self.buffer.as_ref();
-        Message::from(data[field::TYPE])
+        Message::from(self.0[field::TYPE])
     }
 
     /// Return the message code field.
-    #[inline]
     pub fn msg_code(&self) -> u8 {
-        let data = self.buffer.as_ref();
-        data[field::CODE]
+        self.0[field::CODE]
     }
 
     /// Return the checksum field.
-    #[inline]
     pub fn checksum(&self) -> u16 {
-        let data = self.buffer.as_ref();
-        NetworkEndian::read_u16(&data[field::CHECKSUM])
+        NetworkEndian::read_u16(&self.0[field::CHECKSUM])
     }
 
-    /// Return the identifier field (for echo request and reply packets).
-    #[inline]
+    /// Return the mtu field of a packet too big message.
+    pub fn pkt_too_big_mtu(&self) -> u32 {
+        NetworkEndian::read_u32(&self.0[field::PKT_TOO_BIG_MTU])
+    }
+
+    /// Return the pointer field of a parameter problem message.
+    pub fn param_problem_ptr(&self) -> u32 {
+        NetworkEndian::read_u32(&self.0[field::POINTER])
+    }
+
+    /// Return the identifier field of an echo message.
     pub fn echo_ident(&self) -> u16 {
-        let data = self.buffer.as_ref();
-        NetworkEndian::read_u16(&data[field::ECHO_IDENT])
+        NetworkEndian::read_u16(&self.0[field::ECHO_IDENT])
     }
 
-    /// Return the sequence number field (for echo request and reply packets).
-    #[inline]
+    /// Return the sequence number field of an echo message.
     pub fn echo_seq_no(&self) -> u16 {
-        let data = self.buffer.as_ref();
-        NetworkEndian::read_u16(&data[field::ECHO_SEQNO])
+        NetworkEndian::read_u16(&self.0[field::ECHO_SEQNO])
     }
 
-    /// Return the MTU field (for packet too big messages).
-    #[inline]
-    pub fn pkt_too_big_mtu(&self) -> u32 {
-        let data = self.buffer.as_ref();
-        NetworkEndian::read_u32(&data[field::MTU])
+    /// Return the flags of a neighbor advertisement.
+    pub fn neighbor_flags(&self) -> NeighborFlags {
+        NeighborFlags(self.0[field::NEIGH_FLAGS] & 0xe0)
     }
 
-    /// Return the pointer field (for parameter problem messages).
-    #[inline]
-    pub fn param_problem_ptr(&self) -> u32 {
-        let data = self.buffer.as_ref();
-        NetworkEndian::read_u32(&data[field::POINTER])
+    /// Return the target address of a neighbor discovery message.
+    pub fn target_addr(&self) -> Address {
+        Address::from_bytes(&self.0[field::TARGET_ADDR])
+    }
+
+    /// Return the hop limit advertised by a router, zero if unspecified.
+    pub fn cur_hop_limit(&self) -> u8 {
+        self.0[field::CUR_HOP_LIMIT]
     }
 
+    /// Return the flags of a router advertisement.
+    pub fn router_flags(&self) -> RouterFlags {
+        RouterFlags(self.0[field::ROUTER_FLAGS] & 0xc0)
+    }
+
+    /// Return the lifetime of the router as a default router, in seconds.
+    pub fn router_lifetime(&self) -> u16 {
+        NetworkEndian::read_u16(&self.0[field::ROUTER_LIFETIME])
+    }
+
+    /// Return the reachable time advertised by a router, in milliseconds.
+    pub fn reachable_time(&self) -> u32 {
+        NetworkEndian::read_u32(&self.0[field::REACHABLE_TIME])
+    }
+
+    /// Return the retransmission timer advertised by a router, in milliseconds.
+    pub fn retrans_time(&self) -> u32 {
+        NetworkEndian::read_u32(&self.0[field::RETRANS_TIME])
+    }
+
+    /// Return the payload following the header of an echo or error message.
+    pub fn payload_slice(&self) -> &[u8] {
+        &self.0[field::HEADER_END..]
+    }
 
-    /// Return the header length. The result depends on the value of
-    /// the message type field.
-    pub fn header_len(&self) -> usize {
+    /// Return the options of a neighbor discovery message.
+    ///
+    /// The options of router advertisements follow their timers, those of other messages the
+    /// target address.
+    pub fn neighbor_options(&self) -> &[u8] {
         match self.msg_type() {
-            Message::DstUnreachable  => field::UNUSED.end,
-            Message::PktTooBig       => field::MTU.end,
-            Message::TimeExceeded    => field::UNUSED.end,
-            Message::ParamProblem    => field::POINTER.end,
-            Message::EchoRequest     => field::ECHO_SEQNO.end,
-            Message::EchoReply       => field::ECHO_SEQNO.end,
-            Message::RouterSolicit   => field::UNUSED.end,
-            Message::RouterAdvert    => field::RETRANS_TM.end,
-            Message::NeighborSolicit => field::TARGET_ADDR.end,
-            Message::NeighborAdvert  => field::TARGET_ADDR.end,
-            Message::Redirect        => field::DEST_ADDR.end,
-            Message::MldQuery        => field::QUERY_NUM_SRCS.end,
-            Message::MldReport       => field::NR_MCAST_RCRDS.end,
-            // For packets that are not included in RFC 4443, do not
-            // include the last 32 bits of the ICMPv6 header in
-            // `header_bytes`. This must be done so that these bytes
-            // can be accessed in the `payload`.
-            _ => field::CHECKSUM.end
+            Message::RouterAdvert => &self.0[field::ROUTER_OPTIONS],
+            _ => &self.0[field::NEIGH_OPTIONS],
         }
     }
 
-    /// Validate the header checksum.
+    /// Find the link-layer address option of a neighbor discovery message.
     ///
-    /// # Fuzzing
-    /// This function always returns `true` when fuzzing.
-    pub fn verify_checksum(&self, src_addr: &IpAddress, dst_addr: &IpAddress) -> bool {
-        if cfg!(fuzzing) { return true }
+    /// Returns the source link-layer address of a solicitation or router advertisement and the
+    /// target link-layer address of a neighbor advertisement. Returns `Err(Error::Malformed)` if
+    /// an option has length zero.
+    pub fn lladdr(&self) -> Result<Option<ethernet::Address>> {
+        let kind = match self.msg_type() {
+            Message::NeighborSolicit | Message::RouterAdvert => field::OPT_SOURCE_LLADDR,
+            Message::NeighborAdvert => field::OPT_TARGET_LLADDR,
+            _ => return Ok(None),
+        };
 
-        let data = self.buffer.as_ref();
-        checksum::combine(&[
-            checksum::pseudo_header(src_addr, dst_addr, IpProtocol::Icmpv6,
-                                    data.len() as u32),
-            checksum::data(data)
-        ]) == !0
+        let option = self.find_option(kind, field::OPT_LLADDR_LEN)?;
+        Ok(option.map(|option| ethernet::Address::from_bytes(&option[2..8])))
     }
-}
 
-impl<'a, T: AsRef<[u8]> + ?Sized> Packet<&'a T> {
-    /// Return a pointer to the type-specific data.
-    #[inline]
-    pub fn payload(&self) -> &'a [u8] {
-        let data = self.buffer.as_ref();
-        &data[self.header_len()..]
+    /// Find the mtu option of a router advertisement.
+    pub fn mtu(&self) -> Result<Option<u32>> {
+        if self.msg_type() != Message::RouterAdvert {
+            return Ok(None);
+        }
+
+        let option = self.find_option(field::OPT_MTU, field::OPT_MTU_LEN)?;
+        Ok(option.map(|option| NetworkEndian::read_u32(&option[field::MTU])))
+    }
+
+    /// Iterate over the prefix information options of a router advertisement.
+    ///
+    /// Yields nothing for all other messages.
+    pub fn prefixes(&self) -> Prefixes<'_> {
+        let options = match self.msg_type() {
+            Message::RouterAdvert => self.neighbor_options(),
+            _ => &[],
+        };
+
+        Prefixes { options }
+    }
+
+    /// Find the last option of a kind with the expected length, validating all options.
+    fn find_option(&self, kind: u8, expected: usize) -> Result<Option<&[u8]>> {
+        let mut options = self.neighbor_options();
+        let mut found = None;
+        while !options.is_empty() {
+            let len = option_len(options)?;
+            if options[0] == kind && len == expected {
+                found = Some(&options[..len]);
+            }
+
+            options = &options[len..];
+        }
+
+        Ok(found)
+    }
+
+    /// Return a mutable slice of the payload following the header of an echo or error message.
+    pub fn payload_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.0[field::HEADER_END..]
     }
-}
 
-impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
     /// Set the message type field.
-    #[inline]
     pub fn set_msg_type(&mut self, value: Message) {
-        let data = self.buffer.as_mut();
-        data[field::TYPE] = value.into()
+        self.0[field::TYPE] = value.into()
     }
 
     /// Set the message code field.
-    #[inline]
     pub fn set_msg_code(&mut self, value: u8) {
-        let data = self.buffer.as_mut();
-        data[field::CODE] = value
+        self.0[field::CODE] = value
     }
 
-    /// Clear any reserved fields in the message header.
-    ///
-    /// # Panics
-    /// This function panics if the message type has not been set.
-    /// See [set_msg_type].
-    ///
-    /// [set_msg_type]: #method.set_msg_type
-    #[inline]
-    pub fn clear_reserved(&mut self) {
-        match self.msg_type() {
-            Message::RouterSolicit | Message::NeighborSolicit |
-            Message::NeighborAdvert | Message::Redirect => {
-                let data = self.buffer.as_mut();
-                NetworkEndian::write_u32(&mut data[field::UNUSED], 0);
-            },
-            Message::MldQuery => {
-                let data = self.buffer.as_mut();
-                NetworkEndian::write_u16(&mut data[field::QUERY_RESV], 0);
-                data[field::SQRV] = data[field::SQRV] & 0xf;
-            },
-            Message::MldReport => {
-                let data = self.buffer.as_mut();
-                NetworkEndian::write_u16(&mut data[field::RECORD_RESV], 0);
-            }
-            ty => panic!("Message type `{}` does not have any reserved fields.", ty),
-        }
+    /// Set the checksum field.
+    pub fn set_checksum(&mut self, value: u16) {
+        NetworkEndian::write_u16(&mut self.0[field::CHECKSUM], value)
     }
 
-    #[inline]
-    pub fn set_checksum(&mut self, value: u16) {
-        let data = self.buffer.as_mut();
-        NetworkEndian::write_u16(&mut data[field::CHECKSUM], value)
+    /// Set the mtu field of a packet too big message.
+    pub fn set_pkt_too_big_mtu(&mut self, value: u32) {
+        NetworkEndian::write_u32(&mut self.0[field::PKT_TOO_BIG_MTU], value)
     }
 
-    /// Set the identifier field (for echo request and reply packets).
-    ///
-    /// # Panics
-    /// This function may panic if this packet is not an echo request or reply packet.
-    #[inline]
+    /// Set the pointer field of a parameter problem message.
+    pub fn set_param_problem_ptr(&mut self, value: u32) {
+        NetworkEndian::write_u32(&mut self.0[field::POINTER], value)
+    }
+
+    /// Set the identifier field of an echo message.
     pub fn set_echo_ident(&mut self, value: u16) {
-        let data = self.buffer.as_mut();
-        NetworkEndian::write_u16(&mut data[field::ECHO_IDENT], value)
+        NetworkEndian::write_u16(&mut self.0[field::ECHO_IDENT], value)
     }
 
-    /// Set the sequence number field (for echo request and reply packets).
-    ///
-    /// # Panics
-    /// This function may panic if this packet is not an echo request or reply packet.
-    #[inline]
+    /// Set the sequence number field of an echo message.
     pub fn set_echo_seq_no(&mut self, value: u16) {
-        let data = self.buffer.as_mut();
-        NetworkEndian::write_u16(&mut data[field::ECHO_SEQNO], value)
+        NetworkEndian::write_u16(&mut self.0[field::ECHO_SEQNO], value)
     }
 
-    /// Set the MTU field (for packet too big messages).
-    ///
-    /// # Panics
-    /// This function may panic if this packet is not an packet too big packet.
-    #[inline]
-    pub fn set_pkt_too_big_mtu(&mut self, value: u32) {
-        let data = self.buffer.as_mut();
-        NetworkEndian::write_u32(&mut data[field::MTU], value)
+    /// Set the flags of a neighbor advertisement, clearing the reserved bits.
+    pub fn set_neighbor_flags(&mut self, value: NeighborFlags) {
+        self.0[field::NEIGH_FLAGS..field::TARGET_ADDR.start].copy_from_slice(&[value.0 & 0xe0, 0, 0, 0]);
     }
 
-    /// Set the pointer field (for parameter problem messages).
-    ///
-    /// # Panics
-    /// This function may panic if this packet is not a parameter problem message.
-    #[inline]
-    pub fn set_param_problem_ptr(&mut self, value: u32) {
-        let data = self.buffer.as_mut();
-        NetworkEndian::write_u32(&mut data[field::POINTER], value)
+    /// Set the target address of a neighbor discovery message.
+    pub fn set_target_addr(&mut self, value: Address) {
+        self.0[field::TARGET_ADDR].copy_from_slice(value.as_bytes())
     }
 
-    /// Compute and fill in the header checksum.
-    pub fn fill_checksum(&mut self, src_addr: &IpAddress, dst_addr: &IpAddress) {
+    fn compute_checksum(&self, src_addr: Address, dst_addr: Address) -> u16 {
+        checksum::combine(&[
+            checksum::pseudo_header(&src_addr.into(), &dst_addr.into(),
+                                    ip::Protocol::Icmpv6, self.0.len() as u32),
+            checksum::data(&self.0),
+        ])
+    }
+
+    /// Compute and fill in the checksum.
+    pub fn fill_checksum(&mut self, src_addr: Address, dst_addr: Address) {
         self.set_checksum(0);
-        let checksum = {
-            let data = self.buffer.as_ref();
-            !checksum::combine(&[
-                checksum::pseudo_header(src_addr, dst_addr, IpProtocol::Icmpv6,
-                                        data.len() as u32),
-                checksum::data(data)
-            ])
-        };
+        let checksum = !self.compute_checksum(src_addr, dst_addr);
         self.set_checksum(checksum)
     }
 
-    /// Return a mutable pointer to the type-specific data.
-    #[inline]
-    pub fn payload_mut(&mut self) -> &mut [u8] {
-        let range = self.header_len()..;
-        let data = self.buffer.as_mut();
-        &mut data[range]
+    /// Validate the checksum.
+    ///
+    /// # Fuzzing
+    /// This function always returns `true` when fuzzing.
+    pub fn verify_checksum(&self, src_addr: Address, dst_addr: Address) -> bool {
+        if cfg!(fuzzing) { return true }
+
+        self.compute_checksum(src_addr, dst_addr) == !0
+    }
+}
+
+impl<'a> Iterator for Prefixes<'a> {
+    type Item = PrefixInfo;
+
+    fn next(&mut self) -> Option<PrefixInfo> {
+        while !self.options.is_empty() {
+            let len = match option_len(self.options) {
+                Ok(len) => len,
+                Err(_) => {
+                    self.options = &[];
+                    return None;
+                },
+            };
+
+            let (option, rest) = self.options.split_at(len);
+            self.options = rest;
+            if option[0] != field::OPT_PREFIX_INFO || len != field::OPT_PREFIX_INFO_LEN {
+                continue;
+            }
+
+            let prefix_len = option[field::PREFIX_LEN];
+            if prefix_len > 128 {
+                continue;
+            }
+
+            let flags = option[field::PREFIX_FLAGS];
+            let address = Address::from_bytes(&option[field::PREFIX]);
+            return Some(PrefixInfo {
+                prefix: Cidr::new(address, prefix_len).subnet(),
+                on_link: flags & 0x80 != 0,
+                autonomous: flags & 0x40 != 0,
+                valid_lifetime: NetworkEndian::read_u32(&option[field::PREFIX_VALID]),
+                preferred_lifetime: NetworkEndian::read_u32(&option[field::PREFIX_PREFERRED]),
+            });
+        }
+
+        None
+    }
+}
+
+/// The length of the first of some options.
+fn option_len(options: &[u8]) -> Result<usize> {
+    if options.len() < 2 {
+        return Err(Error::Truncated);
+    }
+
+    let len = usize::from(options[1]) * 8;
+    if len == 0 {
+        return Err(Error::Malformed);
     }
+
+    if options.len() < len {
+        return Err(Error::Truncated);
+    }
+
+    Ok(len)
 }
 
-impl<T: AsRef<[u8]>> AsRef<[u8]> for Packet<T> {
+impl AsRef<[u8]> for icmpv6 {
     fn as_ref(&self) -> &[u8] {
-        self.buffer.as_ref()
+        &self.0
     }
 }
 
-/// A high-level representation of an Internet Control Message Protocol version 6 packet header.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub enum Repr<'a> {
-    DstUnreachable {
-        reason: DstUnreachable,
-        header: Ipv6Repr,
-        data:   &'a [u8]
-    },
-    PktTooBig {
-        mtu: u32,
-        header: Ipv6Repr,
-        data:   &'a [u8]
-    },
-    TimeExceeded {
-        reason: TimeExceeded,
-        header: Ipv6Repr,
-        data:   &'a [u8]
-    },
-    ParamProblem {
-        reason:  ParamProblem,
-        pointer: u32,
-        header:  Ipv6Repr,
-        data:    &'a [u8]
-    },
-    EchoRequest {
-        ident:  u16,
-        seq_no: u16,
-        data:   &'a [u8]
-    },
-    EchoReply {
-        ident:  u16,
-        seq_no: u16,
-        data:   &'a [u8]
-    },
-    Ndisc(NdiscRepr<'a>),
-    Mld(MldRepr<'a>),
-    #[doc(hidden)]
-    __Nonexhaustive
+impl AsMut<[u8]> for icmpv6 {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
 }
 
-impl<'a> Repr<'a> {
-    /// Parse an Internet Control Message Protocol version 6 packet and return
-    /// a high-level representation.
-    pub fn parse<T>(src_addr: &IpAddress, dst_addr: &IpAddress,
-                    packet: &Packet<&'a T>, checksum_caps: &ChecksumCapabilities)
-                   -> Result<Repr<'a>>
-                where T: AsRef<[u8]> + ?Sized {
-        fn create_packet_from_payload<'a, T>(packet: &Packet<&'a T>)
-                                            -> Result<(&'a [u8], Ipv6Repr)>
-                where T: AsRef<[u8]> + ?Sized {
-            let ip_packet = Ipv6Packet::new_checked(packet.payload())?;
-
-            let payload = &packet.payload()[ip_packet.header_len() as usize..];
-            if payload.len() < 8 { return Err(Error::Truncated) }
-            let repr = Ipv6Repr {
-                src_addr: ip_packet.src_addr(),
-                dst_addr: ip_packet.dst_addr(),
-                next_header: ip_packet.next_header(),
-                payload_len: payload.len(),
-                hop_limit: ip_packet.hop_limit()
-            };
-            Ok((payload, repr))
-        }
-        // Valid checksum is expected.
-        if checksum_caps.icmpv6.rx() && !packet.verify_checksum(src_addr, dst_addr) {
-            return Err(Error::Checksum)
+impl Repr {
+    /// Parse an Internet Control Message Protocol version 6 packet and return a high-level
+    /// representation.
+    pub fn parse(packet: &icmpv6, checksum: Checksum) -> Result<Repr> {
+        packet.check_len()?;
+
+        if let Checksum::Manual { src_addr, dst_addr } = checksum {
+            if !packet.verify_checksum(src_addr, dst_addr) {
+                return Err(Error::WrongChecksum);
+            }
         }
 
         match (packet.msg_type(), packet.msg_code()) {
-            (Message::DstUnreachable, code) => {
-                let (payload, repr) = create_packet_from_payload(packet)?;
-                Ok(Repr::DstUnreachable {
-                    reason: DstUnreachable::from(code),
-                    header: repr,
-                    data: payload
-                })
-            },
-            (Message::PktTooBig, 0) => {
-                let (payload, repr) = create_packet_from_payload(packet)?;
-                Ok(Repr::PktTooBig {
-                    mtu: packet.pkt_too_big_mtu(),
-                    header: repr,
-                    data: payload
-                })
-            },
-            (Message::TimeExceeded, code) => {
-                let (payload, repr) = create_packet_from_payload(packet)?;
-                Ok(Repr::TimeExceeded {
-                    reason: TimeExceeded::from(code),
-                    header: repr,
-                    data: payload
-                })
-            },
-            (Message::ParamProblem, code) => {
-                let (payload, repr) = create_packet_from_payload(packet)?;
-                Ok(Repr::ParamProblem {
-                    reason: ParamProblem::from(code),
-                    pointer: packet.param_problem_ptr(),
-                    header: repr,
-                    data: payload
-                })
-            },
-            (Message::EchoRequest, 0) => {
-                Ok(Repr::EchoRequest {
-                    ident:  packet.echo_ident(),
-                    seq_no: packet.echo_seq_no(),
-                    data:   packet.payload()
-                })
-            },
-            (Message::EchoReply, 0) => {
-                Ok(Repr::EchoReply {
-                    ident:  packet.echo_ident(),
-                    seq_no: packet.echo_seq_no(),
-                    data:   packet.payload()
-                })
-            },
-            (msg_type, 0) if msg_type.is_ndisc() => {
-                NdiscRepr::parse(packet).map(|repr| Repr::Ndisc(repr))
-            },
-            (msg_type, 0) if msg_type.is_mld() => {
-                MldRepr::parse(packet).map(|repr| Repr::Mld(repr))
-            },
-            _ => Err(Error::Unrecognized)
+            (Message::DstUnreachable, code) => Ok(Repr::DstUnreachable {
+                reason: DstUnreachable::from(code),
+                header: quoted_header(packet)?,
+            }),
+            (Message::PktTooBig, 0) => Ok(Repr::PktTooBig {
+                mtu: packet.pkt_too_big_mtu(),
+                header: quoted_header(packet)?,
+            }),
+            (Message::TimeExceeded, code) => Ok(Repr::TimeExceeded {
+                reason: TimeExceeded::from(code),
+                header: quoted_header(packet)?,
+            }),
+            (Message::ParamProblem, code) => Ok(Repr::ParamProblem {
+                reason: ParamProblem::from(code),
+                pointer: packet.param_problem_ptr(),
+                header: quoted_header(packet)?,
+            }),
+            (Message::EchoRequest, 0) => Ok(Repr::EchoRequest {
+                ident: packet.echo_ident(),
+                seq_no: packet.echo_seq_no(),
+                payload: packet.payload_slice().len(),
+            }),
+            (Message::EchoReply, 0) => Ok(Repr::EchoReply {
+                ident: packet.echo_ident(),
+                seq_no: packet.echo_seq_no(),
+                payload: packet.payload_slice().len(),
+            }),
+            (Message::NeighborSolicit, 0) => Ok(Repr::NeighborSolicit {
+                target_addr: packet.target_addr(),
+                lladdr: packet.lladdr()?,
+            }),
+            (Message::NeighborAdvert, 0) => Ok(Repr::NeighborAdvert {
+                flags: packet.neighbor_flags(),
+                target_addr: packet.target_addr(),
+                lladdr: packet.lladdr()?,
+            }),
+            (Message::RouterAdvert, 0) => Ok(Repr::RouterAdvert {
+                hop_limit: packet.cur_hop_limit(),
+                flags: packet.router_flags(),
+                router_lifetime: packet.router_lifetime(),
+                reachable_time: packet.reachable_time(),
+                retrans_time: packet.retrans_time(),
+                lladdr: packet.lladdr()?,
+                mtu: packet.mtu()?,
+            }),
+            (Message::Unknown(_), _) => Err(Error::Unrecognized),
+            _ => Err(Error::Unsupported),
         }
     }
 
     /// Return the length of a packet that will be emitted from this high-level representation.
     pub fn buffer_len(&self) -> usize {
         match self {
-            &Repr::DstUnreachable { header, data, .. } | &Repr::PktTooBig { header, data, .. } |
-            &Repr::TimeExceeded { header, data, .. } | &Repr::ParamProblem { header, data, .. } => {
-                field::UNUSED.end + header.buffer_len() + data.len()
-            }
-            &Repr::EchoRequest { data, .. } |
-            &Repr::EchoReply { data, .. } => {
-                field::ECHO_SEQNO.end + data.len()
+            Repr::DstUnreachable { header, .. } |
+            Repr::PktTooBig { header, .. } |
+            Repr::TimeExceeded { header, .. } |
+            Repr::ParamProblem { header, .. } => {
+                field::HEADER_END + header.buffer_len() + header.payload_len
             },
-            &Repr::Ndisc(ndisc) => {
-                ndisc.buffer_len()
+            Repr::EchoRequest { payload, .. } |
+            Repr::EchoReply { payload, .. } => field::HEADER_END + payload,
+            Repr::NeighborSolicit { lladdr, .. } |
+            Repr::NeighborAdvert { lladdr, .. } => {
+                field::TARGET_ADDR.end + lladdr.map_or(0, |_| field::OPT_LLADDR_LEN)
             },
-            &Repr::Mld(mld) => {
-                mld.buffer_len()
+            Repr::RouterAdvert { lladdr, mtu, .. } => {
+                field::RETRANS_TIME.end
+                    + lladdr.map_or(0, |_| field::OPT_LLADDR_LEN)
+                    + mtu.map_or(0, |_| field::OPT_MTU_LEN)
             },
-            &Repr::__Nonexhaustive => unreachable!()
         }
     }
 
-    /// Emit a high-level representation into an Internet Control Message Protocol version 6
-    /// packet.
-    pub fn emit<T>(&self, src_addr: &IpAddress, dst_addr: &IpAddress,
-                   packet: &mut Packet<&mut T>, checksum_caps: &ChecksumCapabilities)
-            where T: AsRef<[u8]> + AsMut<[u8]> + ?Sized {
-        fn emit_contained_packet(buffer: &mut [u8], header: Ipv6Repr, data: &[u8]) {
-            let mut ip_packet = Ipv6Packet::new_unchecked(buffer);
-            header.emit(&mut ip_packet);
-            let payload = &mut ip_packet.into_inner()[header.buffer_len()..];
-            payload.copy_from_slice(&data[..]);
-        }
-
-        match self {
-            &Repr::DstUnreachable { reason, header, data } => {
+    /// Emit a high-level representation into an ICMPv6 packet.
+    ///
+    /// The payload of echo messages and the quoted payload of error messages must be filled in
+    /// before the checksum is computed, so with `Checksum::Manual` it must already be present in
+    /// the buffer. Error messages only emit the quoted header in front of it.
+    pub fn emit(&self, packet: &mut icmpv6, checksum: Checksum) {
+        packet.set_msg_code(0);
+        match *self {
+            Repr::DstUnreachable { reason, header } => {
                 packet.set_msg_type(Message::DstUnreachable);
                 packet.set_msg_code(reason.into());
-
-                emit_contained_packet(packet.payload_mut(), header, &data);
+                packet.0[field::UNUSED].copy_from_slice(&[0; 4]);
+                emit_quoted_header(packet, header);
             },
-
-            &Repr::PktTooBig { mtu, header, data } => {
+            Repr::PktTooBig { mtu, header } => {
                 packet.set_msg_type(Message::PktTooBig);
-                packet.set_msg_code(0);
                 packet.set_pkt_too_big_mtu(mtu);
-
-                emit_contained_packet(packet.payload_mut(), header, &data);
+                emit_quoted_header(packet, header);
             },
-
-            &Repr::TimeExceeded { reason, header, data } => {
+            Repr::TimeExceeded { reason, header } => {
                 packet.set_msg_type(Message::TimeExceeded);
                 packet.set_msg_code(reason.into());
-
-                emit_contained_packet(packet.payload_mut(), header, &data);
+                packet.0[field::UNUSED].copy_from_slice(&[0; 4]);
+                emit_quoted_header(packet, header);
             },
-
-            &Repr::ParamProblem { reason, pointer, header, data } => {
+            Repr::ParamProblem { reason, pointer, header } => {
                 packet.set_msg_type(Message::ParamProblem);
                 packet.set_msg_code(reason.into());
                 packet.set_param_problem_ptr(pointer);
-
-                emit_contained_packet(packet.payload_mut(), header, &data);
+                emit_quoted_header(packet, header);
             },
-
-            &Repr::EchoRequest { ident, seq_no, data } => {
+            Repr::EchoRequest { ident, seq_no, .. } => {
                 packet.set_msg_type(Message::EchoRequest);
-                packet.set_msg_code(0);
                 packet.set_echo_ident(ident);
                 packet.set_echo_seq_no(seq_no);
-                let data_len = cmp::min(packet.payload_mut().len(), data.len());
-                packet.payload_mut()[..data_len].copy_from_slice(&data[..data_len])
             },
-
-            &Repr::EchoReply { ident, seq_no, data } => {
+            Repr::EchoReply { ident, seq_no, .. } => {
                 packet.set_msg_type(Message::EchoReply);
-                packet.set_msg_code(0);
                 packet.set_echo_ident(ident);
                 packet.set_echo_seq_no(seq_no);
-                let data_len = cmp::min(packet.payload_mut().len(), data.len());
-                packet.payload_mut()[..data_len].copy_from_slice(&data[..data_len])
             },
-
-            &Repr::Ndisc(ndisc) => {
-                ndisc.emit(packet)
+            Repr::NeighborSolicit { target_addr, lladdr } => {
+                packet.set_msg_type(Message::NeighborSolicit);
+                packet.set_neighbor_flags(NeighborFlags::NONE);
+                packet.set_target_addr(target_addr);
+                if let Some(lladdr) = lladdr {
+                    emit_lladdr(packet, field::OPT_SOURCE_LLADDR, lladdr);
+                }
             },
-
-            &Repr::Mld(mld) => {
-                mld.emit(packet)
+            Repr::NeighborAdvert { flags, target_addr, lladdr } => {
+                packet.set_msg_type(Message::NeighborAdvert);
+                packet.set_neighbor_flags(flags);
+                packet.set_target_addr(target_addr);
+                if let Some(lladdr) = lladdr {
+                    emit_lladdr(packet, field::OPT_TARGET_LLADDR, lladdr);
+                }
+            },
+            Repr::RouterAdvert {
+                hop_limit, flags, router_lifetime, reachable_time, retrans_time, lladdr, mtu,
+            } => {
+                packet.set_msg_type(Message::RouterAdvert);
+                let header = &mut packet.0[..field::RETRANS_TIME.end];
+                header[field::CUR_HOP_LIMIT] = hop_limit;
+                header[field::ROUTER_FLAGS] = flags.0 & 0xc0;
+                NetworkEndian::write_u16(&mut header[field::ROUTER_LIFETIME], router_lifetime);
+                NetworkEndian::write_u32(&mut header[field::REACHABLE_TIME], reachable_time);
+                NetworkEndian::write_u32(&mut header[field::RETRANS_TIME], retrans_time);
+
+                let mut options = &mut packet.0[field::ROUTER_OPTIONS];
+                if let Some(lladdr) = lladdr {
+                    options[0] = field::OPT_SOURCE_LLADDR;
+                    options[1] = (field::OPT_LLADDR_LEN / 8) as u8;
+                    options[2..field::OPT_LLADDR_LEN].copy_from_slice(lladdr.as_bytes());
+                    options = &mut options[field::OPT_LLADDR_LEN..];
+                }
+                if let Some(mtu) = mtu {
+                    options[..field::MTU.start].copy_from_slice(&[field::OPT_MTU, 1, 0, 0]);
+                    NetworkEndian::write_u32(&mut options[field::MTU], mtu);
+                }
             },
+        }
 
-            &Repr::__Nonexhaustive => unreachable!(),
+        match checksum {
+            Checksum::Manual { src_addr, dst_addr } => packet.fill_checksum(src_addr, dst_addr),
+            Checksum::Ignored => packet.set_checksum(0),
+        }
+    }
+}
+
+/// Parse the header of the packet quoted by an error message.
+///
+/// The payload length is that of the quoted payload, which is usually shorter than the original.
+fn quoted_header(packet: &icmpv6) -> Result<v6::Repr> {
+    let quote = packet.payload_slice();
+    let ip_packet = v6::packet::new_checked(quote)?;
+    let header = v6::Repr::parse(ip_packet)?;
+    let payload = &quote[header.buffer_len()..];
+    // The quote should include as much as possible, require at least a transport header start.
+    if payload.len() < 8 {
+        return Err(Error::Truncated);
+    }
+
+    Ok(v6::Repr { payload_len: payload.len(), ..header })
+}
+
+fn emit_quoted_header(packet: &mut icmpv6, header: v6::Repr) {
+    let ip_packet = v6::packet::new_unchecked_mut(&mut packet.0[field::HEADER_END..]);
+    header.emit(ip_packet);
+}
+
+fn emit_lladdr(packet: &mut icmpv6, kind: u8, lladdr: ethernet::Address) {
+    let option = &mut packet.0[field::NEIGH_OPTIONS][..field::OPT_LLADDR_LEN];
+    option[0] = kind;
+    option[1] = (field::OPT_LLADDR_LEN / 8) as u8;
+    option[2..].copy_from_slice(lladdr.as_bytes());
+}
+
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Repr::DstUnreachable { reason, header } =>
+                write!(f, "ICMPv6 destination unreachable ({}) {}", reason, header),
+            Repr::PktTooBig { mtu, header } =>
+                write!(f, "ICMPv6 packet too big mtu={} {}", mtu, header),
+            Repr::TimeExceeded { reason, header } =>
+                write!(f, "ICMPv6 time exceeded ({}) {}", reason, header),
+            Repr::ParamProblem { reason, pointer, header } =>
+                write!(f, "ICMPv6 parameter problem ({}) pointer={} {}", reason, pointer, header),
+            Repr::EchoRequest { ident, seq_no, payload } =>
+                write!(f, "ICMPv6 echo request id={} seq={} len={}", ident, seq_no, payload),
+            Repr::EchoReply { ident, seq_no, payload } =>
+                write!(f, "ICMPv6 echo reply id={} seq={} len={}", ident, seq_no, payload),
+            Repr::NeighborSolicit { target_addr, lladdr } => {
+                write!(f, "ICMPv6 neighbor solicitation target={}", target_addr)?;
+                match lladdr {
+                    Some(lladdr) => write!(f, " source_lladdr={}", lladdr),
+                    None => Ok(()),
+                }
+            },
+            Repr::NeighborAdvert { flags, target_addr, lladdr } => {
+                write!(f, "ICMPv6 neighbor advertisement target={}", target_addr)?;
+                if flags.router() { write!(f, " router")? }
+                if flags.solicited() { write!(f, " solicited")? }
+                if flags.override_() { write!(f, " override")? }
+                match lladdr {
+                    Some(lladdr) => write!(f, " target_lladdr={}", lladdr),
+                    None => Ok(()),
+                }
+            },
+            Repr::RouterAdvert { hop_limit, flags, router_lifetime, lladdr, mtu, .. } => {
+                write!(f, "ICMPv6 router advertisement hop_limit={} lifetime={}",
+                    hop_limit, router_lifetime)?;
+                if flags.managed() { write!(f, " managed")? }
+                if flags.other() { write!(f, " other")? }
+                if let Some(lladdr) = lladdr { write!(f, " source_lladdr={}", lladdr)? }
+                match mtu {
+                    Some(mtu) => write!(f, " mtu={}", mtu),
+                    None => Ok(()),
+                }
+            },
         }
+    }
+}
 
-        if checksum_caps.icmpv6.tx() {
-            packet.fill_checksum(src_addr, dst_addr);
-        } else {
-            // make sure we get a consistently zeroed checksum, since implementations might rely on it
-            packet.set_checksum(0);
+impl PrettyPrint for icmpv6 {
+    fn pretty_print(buffer: &[u8], f: &mut fmt::Formatter,
+                    indent: &mut PrettyIndent) -> fmt::Result {
+        let packet = match icmpv6::new_checked(buffer) {
+            Err(err) => return write!(f, "{}({})", indent, err),
+            Ok(packet) => packet,
+        };
+
+        match Repr::parse(packet, Checksum::Ignored) {
+            Err(err) => write!(f, "{}({})", indent, err),
+            Ok(repr) => write!(f, "{}{}", indent, repr),
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use wire::{Ipv6Address, Ipv6Repr, IpProtocol};
-    use wire::ip::test::{MOCK_IP_ADDR_1, MOCK_IP_ADDR_2};
     use super::*;
 
-    static ECHO_PACKET_BYTES: [u8; 12] =
-        [0x80, 0x00, 0x19, 0xb3,
-         0x12, 0x34, 0xab, 0xcd,
-         0xaa, 0x00, 0x00, 0xff];
-
-    static ECHO_PACKET_PAYLOAD: [u8; 4] =
-        [0xaa, 0x00, 0x00, 0xff];
-
-    static PKT_TOO_BIG_BYTES: [u8; 60] =
-        [0x02, 0x00, 0x0f, 0xc9,
-         0x00, 0x00, 0x05, 0xdc,
-         0x60, 0x00, 0x00, 0x00,
-         0x00, 0x0c, 0x11, 0x40,
-         0xfe, 0x80, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x01,
-         0xfe, 0x80, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x02,
-         0xbf, 0x00, 0x00, 0x35,
-         0x00, 0x0c, 0x12, 0x4d,
-         0xaa, 0x00, 0x00, 0xff];
-
-    static PKT_TOO_BIG_IP_PAYLOAD: [u8; 52] =
-        [0x60, 0x00, 0x00, 0x00,
-         0x00, 0x0c, 0x11, 0x40,
-         0xfe, 0x80, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x01,
-         0xfe, 0x80, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x00,
-         0x00, 0x00, 0x00, 0x02,
-         0xbf, 0x00, 0x00, 0x35,
-         0x00, 0x0c, 0x12, 0x4d,
-         0xaa, 0x00, 0x00, 0xff];
-
-    static PKT_TOO_BIG_UDP_PAYLOAD: [u8; 12] =
-        [0xbf, 0x00, 0x00, 0x35,
-         0x00, 0x0c, 0x12, 0x4d,
-         0xaa, 0x00, 0x00, 0xff];
-
-    fn echo_packet_repr() -> Repr<'static> {
+    const SRC_ADDR: Address = Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    const DST_ADDR: Address = Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+    const MAC_ADDR: ethernet::Address = ethernet::Address([0, 1, 2, 3, 4, 5]);
+
+    static NEIGHBOR_SOLICIT_BYTES: [u8; 32] = [
+        0x87, 0x00, 0x76, 0x13, 0x00, 0x00, 0x00, 0x00,
+        0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+        0x01, 0x01, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
+    ];
+
+    #[test]
+    fn parse_neighbor_solicit() {
+        let packet = icmpv6::new_checked(&NEIGHBOR_SOLICIT_BYTES[..]).unwrap();
+        assert_eq!(packet.msg_type(), Message::NeighborSolicit);
+        assert!(packet.verify_checksum(SRC_ADDR, DST_ADDR));
+
+        let checksum = Checksum::Manual { src_addr: SRC_ADDR, dst_addr: DST_ADDR };
+        assert_eq!(Repr::parse(packet, checksum), Ok(Repr::NeighborSolicit {
+            target_addr: DST_ADDR,
+            lladdr: Some(MAC_ADDR),
+        }));
+    }
+
+    #[test]
+    fn emit_neighbor_solicit() {
+        let repr = Repr::NeighborSolicit { target_addr: DST_ADDR, lladdr: Some(MAC_ADDR) };
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        let packet = icmpv6::new_unchecked_mut(&mut bytes);
+        repr.emit(packet, Checksum::Manual { src_addr: SRC_ADDR, dst_addr: DST_ADDR });
+        assert_eq!(&bytes[..], &NEIGHBOR_SOLICIT_BYTES[..]);
+    }
+
+    #[test]
+    fn neighbor_advert_roundtrip() {
+        let repr = Repr::NeighborAdvert {
+            flags: NeighborFlags::SOLICITED | NeighborFlags::OVERRIDE,
+            target_addr: SRC_ADDR,
+            lladdr: Some(MAC_ADDR),
+        };
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        assert_eq!(bytes.len(), 32);
+
+        let checksum = Checksum::Manual { src_addr: SRC_ADDR, dst_addr: DST_ADDR };
+        let packet = icmpv6::new_unchecked_mut(&mut bytes);
+        repr.emit(packet, checksum);
+        assert_eq!(&packet.as_bytes()[4..8], &[0x60, 0, 0, 0]);
+        assert_eq!(Repr::parse(packet, checksum), Ok(repr));
+
+        packet.as_bytes_mut()[25] = 0;
+        assert_eq!(Repr::parse(packet, Checksum::Ignored), Err(Error::Malformed));
+    }
+
+    /// An advertisement of `fe80::1` to all nodes, for the prefix `2001:db8:1::/64`.
+    static ROUTER_ADVERT_BYTES: [u8; 64] = [
+        0x86, 0x00, 0xa7, 0x1c, 0x40, 0x00, 0x07, 0x08,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x01, 0x01, 0x52, 0x54, 0x00, 0x12, 0x34, 0x56,
+        0x05, 0x01, 0x00, 0x00, 0x00, 0x00, 0x05, 0xdc,
+        0x03, 0x04, 0x40, 0xc0, 0x00, 0x01, 0x51, 0x80,
+        0x00, 0x00, 0x38, 0x40, 0x00, 0x00, 0x00, 0x00,
+        0x20, 0x01, 0x0d, 0xb8, 0x00, 0x01, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn parse_router_advert() {
+        let all_nodes = Address::LINK_LOCAL_ALL_NODES;
+        let packet = icmpv6::new_checked(&ROUTER_ADVERT_BYTES[..]).unwrap();
+        assert_eq!(packet.msg_type(), Message::RouterAdvert);
+
+        let checksum = Checksum::Manual { src_addr: SRC_ADDR, dst_addr: all_nodes };
+        let repr = Repr::parse(packet, checksum).unwrap();
+        assert_eq!(repr, Repr::RouterAdvert {
+            hop_limit: 64,
+            flags: RouterFlags::NONE,
+            router_lifetime: 1800,
+            reachable_time: 0,
+            retrans_time: 0,
+            lladdr: Some(ethernet::Address([0x52, 0x54, 0x00, 0x12, 0x34, 0x56])),
+            mtu: Some(1500),
+        });
+
+        let mut prefixes = packet.prefixes();
+        assert_eq!(prefixes.next(), Some(PrefixInfo {
+            prefix: Cidr::new(Address::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0), 64).subnet(),
+            on_link: true,
+            autonomous: true,
+            valid_lifetime: 86400,
+            preferred_lifetime: 14400,
+        }));
+        assert_eq!(prefixes.next(), None);
+
+        // The representation omits the prefix but is otherwise emitted identically.
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        assert_eq!(bytes.len(), 32);
+        repr.emit(icmpv6::new_unchecked_mut(&mut bytes), Checksum::Ignored);
+        assert_eq!(&bytes[4..], &ROUTER_ADVERT_BYTES[4..32]);
+    }
+
+    #[test]
+    fn echo_roundtrip() {
+        let repr = Repr::EchoRequest { ident: 0x1234, seq_no: 1, payload: 4 };
+        let mut bytes = vec![0; repr.buffer_len()];
+        let packet = icmpv6::new_unchecked_mut(&mut bytes);
+        packet.payload_mut_slice().copy_from_slice(b"ping");
+
+        let checksum = Checksum::Manual { src_addr: SRC_ADDR, dst_addr: DST_ADDR };
+        repr.emit(packet, checksum);
+        assert_eq!(Repr::parse(packet, checksum), Ok(repr));
+        assert_eq!(packet.payload_slice(), b"ping");
+    }
+
+    static ECHO_PACKET_BYTES: [u8; 12] = [
+        0x80, 0x00, 0x19, 0xb3,
+        0x12, 0x34, 0xab, 0xcd,
+        0xaa, 0x00, 0x00, 0xff,
+    ];
+
+    static ECHO_PACKET_PAYLOAD: [u8; 4] = [0xaa, 0x00, 0x00, 0xff];
+
+    static PKT_TOO_BIG_BYTES: [u8; 60] = [
+        0x02, 0x00, 0x0f, 0xc9,
+        0x00, 0x00, 0x05, 0xdc,
+        0x60, 0x00, 0x00, 0x00,
+        0x00, 0x0c, 0x11, 0x40,
+        0xfe, 0x80, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x01,
+        0xfe, 0x80, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x02,
+        0xbf, 0x00, 0x00, 0x35,
+        0x00, 0x0c, 0x12, 0x4d,
+        0xaa, 0x00, 0x00, 0xff,
+    ];
+
+    fn echo_packet_repr() -> Repr {
         Repr::EchoRequest {
             ident: 0x1234,
             seq_no: 0xabcd,
-            data: &ECHO_PACKET_PAYLOAD
+            payload: ECHO_PACKET_PAYLOAD.len(),
         }
     }
 
-    fn too_big_packet_repr() -> Repr<'static> {
+    fn too_big_packet_repr() -> Repr {
         Repr::PktTooBig {
             mtu: 1500,
-            header: Ipv6Repr {
-                src_addr: Ipv6Address([0xfe, 0x80, 0x00, 0x00,
-                                       0x00, 0x00, 0x00, 0x00,
-                                       0x00, 0x00, 0x00, 0x00,
-                                       0x00, 0x00, 0x00, 0x01]),
-                dst_addr: Ipv6Address([0xfe, 0x80, 0x00, 0x00,
-                                       0x00, 0x00, 0x00, 0x00,
-                                       0x00, 0x00, 0x00, 0x00,
-                                       0x00, 0x00, 0x00, 0x02]),
-                next_header: IpProtocol::Udp,
+            header: v6::Repr {
+                src_addr: SRC_ADDR,
+                dst_addr: DST_ADDR,
+                next_header: ip::Protocol::Udp,
                 payload_len: 12,
-                hop_limit: 0x40
+                hop_limit: 0x40,
             },
-            data: &PKT_TOO_BIG_UDP_PAYLOAD,
         }
     }
 
     #[test]
     fn test_echo_deconstruct() {
-        let packet = Packet::new_unchecked(&ECHO_PACKET_BYTES[..]);
+        let packet = icmpv6::new_unchecked(&ECHO_PACKET_BYTES[..]);
         assert_eq!(packet.msg_type(), Message::EchoRequest);
         assert_eq!(packet.msg_code(), 0);
         assert_eq!(packet.checksum(), 0x19b3);
         assert_eq!(packet.echo_ident(), 0x1234);
         assert_eq!(packet.echo_seq_no(), 0xabcd);
-        assert_eq!(packet.payload(), &ECHO_PACKET_PAYLOAD[..]);
-        assert_eq!(packet.verify_checksum(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2), true);
+        assert_eq!(packet.payload_slice(), &ECHO_PACKET_PAYLOAD[..]);
+        assert!(packet.verify_checksum(SRC_ADDR, DST_ADDR));
         assert!(!packet.msg_type().is_error());
     }
 
     #[test]
     fn test_echo_construct() {
         let mut bytes = vec![0xa5; 12];
-        let mut packet = Packet::new_unchecked(&mut bytes);
+        let packet = icmpv6::new_unchecked_mut(&mut bytes);
         packet.set_msg_type(Message::EchoRequest);
         packet.set_msg_code(0);
         packet.set_echo_ident(0x1234);
         packet.set_echo_seq_no(0xabcd);
-        packet.payload_mut().copy_from_slice(&ECHO_PACKET_PAYLOAD[..]);
-        packet.fill_checksum(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2);
-        assert_eq!(&packet.into_inner()[..], &ECHO_PACKET_BYTES[..]);
+        packet.payload_mut_slice().copy_from_slice(&ECHO_PACKET_PAYLOAD[..]);
+        packet.fill_checksum(SRC_ADDR, DST_ADDR);
+        assert_eq!(&bytes[..], &ECHO_PACKET_BYTES[..]);
     }
 
     #[test]
     fn test_echo_repr_parse() {
-        let packet = Packet::new_unchecked(&ECHO_PACKET_BYTES[..]);
-        let repr = Repr::parse(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
-                               &packet, &ChecksumCapabilities::default()).unwrap();
-        assert_eq!(repr, echo_packet_repr());
+        let packet = icmpv6::new_checked(&ECHO_PACKET_BYTES[..]).unwrap();
+        let checksum = Checksum::Manual { src_addr: SRC_ADDR, dst_addr: DST_ADDR };
+        assert_eq!(Repr::parse(packet, checksum), Ok(echo_packet_repr()));
     }
 
     #[test]
     fn test_echo_emit() {
         let repr = echo_packet_repr();
         let mut bytes = vec![0xa5; repr.buffer_len()];
-        let mut packet = Packet::new_unchecked(&mut bytes);
-        repr.emit(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
-                  &mut packet, &ChecksumCapabilities::default());
-        assert_eq!(&packet.into_inner()[..], &ECHO_PACKET_BYTES[..]);
+        let packet = icmpv6::new_unchecked_mut(&mut bytes);
+        packet.payload_mut_slice().copy_from_slice(&ECHO_PACKET_PAYLOAD[..]);
+        repr.emit(packet, Checksum::Manual { src_addr: SRC_ADDR, dst_addr: DST_ADDR });
+        assert_eq!(&bytes[..], &ECHO_PACKET_BYTES[..]);
     }
 
     #[test]
     fn test_too_big_deconstruct() {
-        let packet = Packet::new_unchecked(&PKT_TOO_BIG_BYTES[..]);
+        let packet = icmpv6::new_unchecked(&PKT_TOO_BIG_BYTES[..]);
         assert_eq!(packet.msg_type(), Message::PktTooBig);
         assert_eq!(packet.msg_code(), 0);
         assert_eq!(packet.checksum(), 0x0fc9);
         assert_eq!(packet.pkt_too_big_mtu(), 1500);
-        assert_eq!(packet.payload(), &PKT_TOO_BIG_IP_PAYLOAD[..]);
-        assert_eq!(packet.verify_checksum(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2), true);
+        assert_eq!(packet.payload_slice(), &PKT_TOO_BIG_BYTES[8..]);
+        assert!(packet.verify_checksum(SRC_ADDR, DST_ADDR));
         assert!(packet.msg_type().is_error());
     }
 
     #[test]
     fn test_too_big_construct() {
         let mut bytes = vec![0xa5; 60];
-        let mut packet = Packet::new_unchecked(&mut bytes);
+        let packet = icmpv6::new_unchecked_mut(&mut bytes);
         packet.set_msg_type(Message::PktTooBig);
         packet.set_msg_code(0);
         packet.set_pkt_too_big_mtu(1500);
-        packet.payload_mut().copy_from_slice(&PKT_TOO_BIG_IP_PAYLOAD[..]);
-        packet.fill_checksum(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2);
-        assert_eq!(&packet.into_inner()[..], &PKT_TOO_BIG_BYTES[..]);
+        packet.payload_mut_slice().copy_from_slice(&PKT_TOO_BIG_BYTES[8..]);
+        packet.fill_checksum(SRC_ADDR, DST_ADDR);
+        assert_eq!(&bytes[..], &PKT_TOO_BIG_BYTES[..]);
     }
 
     #[test]
     fn test_too_big_repr_parse() {
-        let packet = Packet::new_unchecked(&PKT_TOO_BIG_BYTES[..]);
-        let repr = Repr::parse(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
-                               &packet, &ChecksumCapabilities::default()).unwrap();
-        assert_eq!(repr, too_big_packet_repr());
+        let packet = icmpv6::new_checked(&PKT_TOO_BIG_BYTES[..]).unwrap();
+        let checksum = Checksum::Manual { src_addr: SRC_ADDR, dst_addr: DST_ADDR };
+        assert_eq!(Repr::parse(packet, checksum), Ok(too_big_packet_repr()));
     }
 
     #[test]
     fn test_too_big_emit() {
         let repr = too_big_packet_repr();
         let mut bytes = vec![0xa5; repr.buffer_len()];
-        let mut packet = Packet::new_unchecked(&mut bytes);
-        repr.emit(&MOCK_IP_ADDR_1, &MOCK_IP_ADDR_2,
-                  &mut packet, &ChecksumCapabilities::default());
-        assert_eq!(&packet.into_inner()[..], &PKT_TOO_BIG_BYTES[..]);
+        assert_eq!(bytes.len(), PKT_TOO_BIG_BYTES.len());
+        let packet = icmpv6::new_unchecked_mut(&mut bytes);
+        // The quoted payload is filled in before the checksum.
+        packet.payload_mut_slice()[40..].copy_from_slice(&PKT_TOO_BIG_BYTES[48..]);
+        repr.emit(packet, Checksum::Manual { src_addr: SRC_ADDR, dst_addr: DST_ADDR });
+        assert_eq!(&bytes[..], &PKT_TOO_BIG_BYTES[..]);
+    }
+
+    #[test]
+    fn error_roundtrip() {
+        let header = v6::Repr {
+            src_addr: SRC_ADDR,
+            dst_addr: DST_ADDR,
+            next_header: ip::Protocol::Tcp,
+            payload_len: 8,
+            hop_limit: 1,
+        };
+        let checksum = Checksum::Manual { src_addr: DST_ADDR, dst_addr: SRC_ADDR };
+        for &repr in &[
+            Repr::DstUnreachable { reason: DstUnreachable::PortUnreachable, header },
+            Repr::TimeExceeded { reason: TimeExceeded::HopLimitExceeded, header },
+            Repr::ParamProblem { reason: ParamProblem::UnrecognizedNxtHdr, pointer: 6, header },
+        ] {
+            let mut bytes = vec![0xa5; repr.buffer_len()];
+            let packet = icmpv6::new_unchecked_mut(&mut bytes);
+            repr.emit(packet, checksum);
+            assert!(packet.msg_type().is_error());
+            assert_eq!(Repr::parse(packet, checksum), Ok(repr));
+
+            // The quote must hold the start of the transport header.
+            let truncated = icmpv6::new_unchecked(&bytes[..bytes.len() - 1]);
+            assert_eq!(Repr::parse(truncated, Checksum::Ignored), Err(Error::Truncated));
+        }
     }
 }
@@ -60,13 +60,16 @@ enum_with_unknown! {
         HopByHop  = 0x00,
         Icmp      = 0x01,
         Igmp      = 0x02,
+        Ipv4      = 0x04,
         Tcp       = 0x06,
         Udp       = 0x11,
+        Ipv6      = 0x29,
         Ipv6Route = 0x2b,
         Ipv6Frag  = 0x2c,
         Icmpv6    = 0x3a,
         Ipv6NoNxt = 0x3b,
-        Ipv6Opts  = 0x3c
+        Ipv6Opts  = 0x3c,
+        Vrrp      = 0x70
     }
 }
 
@@ -76,13 +79,16 @@ impl fmt::Display for Protocol {
             Protocol::HopByHop    => write!(f, "Hop-by-Hop"),
             Protocol::Icmp        => write!(f, "ICMP"),
             Protocol::Igmp        => write!(f, "IGMP"),
+            Protocol::Ipv4        => write!(f, "IPv4-in-IP"),
             Protocol::Tcp         => write!(f, "TCP"),
             Protocol::Udp         => write!(f, "UDP"),
+            Protocol::Ipv6        => write!(f, "IPv6-in-IP"),
             Protocol::Ipv6Route   => write!(f, "IPv6-Route"),
             Protocol::Ipv6Frag    => write!(f, "IPv6-Frag"),
             Protocol::Icmpv6      => write!(f, "ICMPv6"),
             Protocol::Ipv6NoNxt   => write!(f, "IPv6-NoNxt"),
             Protocol::Ipv6Opts    => write!(f, "IPv6-Opts"),
+            Protocol::Vrrp        => write!(f, "VRRP"),
             Protocol::Unknown(id) => write!(f, "0x{:02x}", id)
         }
     }
@@ -131,7 +137,7 @@ impl Address {
     }
 
     /// Query whether the address is a valid unicast address.
-    pub fn is_unicast(&self) -> bool {
+    pub const fn is_unicast(&self) -> bool {
         match self {
             Address::Unspecified     => false,
             Address::Ipv4(addr)      => addr.is_unicast(),
@@ -141,7 +147,7 @@ impl Address {
     }
 
     /// Query whether the address is a valid multicast address.
-    pub fn is_multicast(&self) -> bool {
+    pub const fn is_multicast(&self) -> bool {
         match self {
             Address::Unspecified     => false,
             Address::Ipv4(addr)      => addr.is_multicast(),
@@ -151,7 +157,7 @@ impl Address {
     }
 
     /// Query whether the address is the broadcast address.
-    pub fn is_broadcast(&self) -> bool {
+    pub const fn is_broadcast(&self) -> bool {
         match self {
             Address::Unspecified     => false,
             Address::Ipv4(addr)      => addr.is_broadcast(),
@@ -161,7 +167,7 @@ impl Address {
     }
 
     /// Query whether the address falls into the "unspecified" range.
-    pub fn is_unspecified(&self) -> bool {
+    pub const fn is_unspecified(&self) -> bool {
         match self {
             Address::Unspecified     => true,
             Address::Ipv4(addr)      => addr.is_unspecified(),
@@ -170,6 +176,26 @@ impl Address {
         }
     }
 
+    /// Query whether the address falls into the "loopback" range.
+    pub const fn is_loopback(&self) -> bool {
+        match self {
+            Address::Unspecified     => false,
+            Address::Ipv4(addr)      => addr.is_loopback(),
+            Address::Ipv6(addr)      => addr.is_loopback(),
+            Address::__Nonexhaustive => unreachable!()
+        }
+    }
+
+    /// Query whether the address falls into the "link-local" range.
+    pub const fn is_link_local(&self) -> bool {
+        match self {
+            Address::Unspecified     => false,
+            Address::Ipv4(addr)      => addr.is_link_local(),
+            Address::Ipv6(addr)      => addr.is_link_local(),
+            Address::__Nonexhaustive => unreachable!()
+        }
+    }
+
     /// Return an unspecified address that has the same IP version as `self`.
     pub fn to_unspecified(&self) -> Address {
         match self {
@@ -264,6 +290,10 @@ impl fmt::Display for Address {
 
 /// A specification of a CIDR block, containing an address and a variable-length
 /// subnet masking prefix length.
+///
+/// All IPv4 blocks sort before IPv6 blocks, within each version the order is that of the inner
+/// CIDR type: by network, then prefix length, then host address. This makes a search for the
+/// longest matching prefix possible by walking an ordered collection backwards from the address.
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub enum Cidr {
     Ipv4(Ipv4Cidr),
@@ -768,6 +798,121 @@ impl Repr {
     }
 }
 
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Repr::Unspecified { src_addr, dst_addr, protocol, payload_len, .. } =>
+                write!(f, "IP src={} dst={} proto={} len={}",
+                       src_addr, dst_addr, protocol, payload_len),
+            Repr::Ipv4(repr) => write!(f, "{}", repr),
+            Repr::Ipv6(repr) => write!(f, "{}", repr),
+            Repr::__Nonexhaustive => unreachable!()
+        }
+    }
+}
+
+/// Compute the checksum of an IP pseudo header, as used by UDP and TCP.
+///
+/// The result is the RFC 1071 sum of the pseudo header *without* the final complement. Combine it
+/// with the sum of the upper layer header and payload, for example with a [`ChecksumAccumulator`],
+/// to get the checksum of a packet. The `length` is the length of the upper layer packet. Both
+/// address families are supported, IPv4 as in RFC 768 and IPv6 as in RFC 8200.
+///
+/// # Panics
+/// This function panics unless `src_addr` and `dst_addr` belong to the same family,
+/// and that family is IPv4 or IPv6.
+///
+/// [`ChecksumAccumulator`]: struct.ChecksumAccumulator.html
+pub fn pseudo_header_checksum(src_addr: Address, dst_addr: Address, protocol: Protocol, length: u32)
+    -> u16
+{
+    checksum::pseudo_header(&src_addr, &dst_addr, protocol, length)
+}
+
+/// Incrementally computes an RFC 1071 internet checksum.
+///
+/// Data can be added in chunks of arbitrary length, including odd ones, the result is the same as
+/// for the concatenated data. This is the checksum used by the IPv4 header and by the upper layer
+/// protocols over IP, where it is preceded by a pseudo header.
+///
+/// ## Example
+///
+/// Compute the checksum of a UDP packet, whose checksum field is zero.
+///
+/// ```
+/// use ethox::wire::ip::{ChecksumAccumulator, Protocol, v4::Address};
+///
+/// let (src, dst) = (Address::new(192, 168, 1, 1), Address::new(192, 168, 1, 2));
+/// let packet = [0xbf, 0x00, 0x00, 0x35, 0x00, 0x0c, 0x00, 0x00, 0xaa, 0x00, 0x00, 0xff];
+///
+/// let mut checksum = ChecksumAccumulator::for_pseudo_header(
+///     src.into(), dst.into(), Protocol::Udp, packet.len() as u32);
+/// checksum.add_bytes(&packet);
+/// assert_eq!(checksum.finish(), 0x124d);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ChecksumAccumulator {
+    /// The sum so far, with carries propagated.
+    sum: u16,
+    /// The last byte of an odd length chunk, to be paired with the first byte of the next.
+    odd: Option<u8>,
+}
+
+impl ChecksumAccumulator {
+    /// Create an accumulator without any data.
+    pub fn new() -> Self {
+        ChecksumAccumulator::default()
+    }
+
+    /// Create an accumulator starting with an IP pseudo header.
+    ///
+    /// See [`pseudo_header_checksum`] for the arguments and panics.
+    ///
+    /// [`pseudo_header_checksum`]: fn.pseudo_header_checksum.html
+    pub fn for_pseudo_header(src_addr: Address, dst_addr: Address, protocol: Protocol, length: u32)
+        -> Self
+    {
+        ChecksumAccumulator {
+            sum: pseudo_header_checksum(src_addr, dst_addr, protocol, length),
+            odd: None,
+        }
+    }
+
+    /// Add the next chunk of data.
+    pub fn add_bytes(&mut self, mut data: &[u8]) {
+        if let (Some(high), Some((&low, rest))) = (self.odd, data.split_first()) {
+            self.sum = checksum::combine(&[self.sum, u16::from_be_bytes([high, low])]);
+            self.odd = None;
+            data = rest;
+        }
+
+        if data.len() % 2 == 1 {
+            let (&last, even) = data.split_last().unwrap();
+            self.odd = Some(last);
+            data = even;
+        }
+
+        self.sum = checksum::combine(&[self.sum, checksum::data(data)]);
+    }
+
+    /// The sum of all data, without the final complement.
+    ///
+    /// A packet with a correct checksum field sums to `0xffff`.
+    pub fn sum(&self) -> u16 {
+        match self.odd {
+            Some(high) => checksum::combine(&[self.sum, u16::from_be_bytes([high, 0])]),
+            None => self.sum,
+        }
+    }
+
+    /// The checksum of all data, to be written into the checksum field.
+    ///
+    /// This is the complement of the sum. Note that UDP transmits a zero checksum as `0xffff`.
+    pub fn finish(&self) -> u16 {
+        !self.sum()
+    }
+}
+
 pub(crate) mod checksum {
     use byteorder::{ByteOrder, NetworkEndian};
 
@@ -819,6 +964,28 @@ pub(crate) mod checksum {
         propagate_carries(accum)
     }
 
+    /// Update a stored checksum after one 16-bit word of the data changed.
+    ///
+    /// Implements equation 3 of RFC 1624. The result equals the checksum recomputed over the
+    /// changed data, provided the stored checksum was correct, but its cost does not depend on the
+    /// length of the data.
+    pub(crate) fn update(checksum: u16, old: u16, new: u16) -> u16 {
+        !combine(&[!checksum, !old, new])
+    }
+
+    /// Update a stored checksum after a sequence of 16-bit words changed.
+    ///
+    /// Both slices must have the same, even length.
+    pub(crate) fn update_bytes(checksum: u16, old: &[u8], new: &[u8]) -> u16 {
+        debug_assert_eq!(old.len(), new.len());
+        old.chunks_exact(2)
+            .zip(new.chunks_exact(2))
+            .fold(checksum, |checksum, (old, new)| update(
+                checksum,
+                NetworkEndian::read_u16(old),
+                NetworkEndian::read_u16(new)))
+    }
+
     /// Compute an IP pseudo header checksum.
     pub(crate) fn pseudo_header(src_addr: &Address, dst_addr: &Address,
                          protocol: Protocol, length: u32) -> u16 {
@@ -1164,8 +1331,94 @@ pub(crate) mod test {
         test_eq(128, Ipv6Address::new(0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff));
     }
 
+    #[test]
+    fn repr_display() {
+        let repr = Repr::Unspecified {
+            src_addr: IpAddress::v4(192, 168, 0, 20),
+            dst_addr: IpAddress::v4(192, 168, 0, 1),
+            protocol: IpProtocol::Udp,
+            payload_len: 21,
+            hop_limit: 64,
+        };
+        assert_eq!(format!("{}", repr),
+                   "IP src=192.168.0.20 dst=192.168.0.1 proto=UDP len=21");
+
+        let repr = Repr::Ipv4(Ipv4Repr {
+            src_addr: Ipv4Address::new(192, 168, 0, 20),
+            dst_addr: Ipv4Address::new(192, 168, 0, 1),
+            protocol: IpProtocol::Udp,
+            payload_len: 21,
+            hop_limit: 64,
+        });
+        assert_eq!(format!("{}", repr),
+                   "IPv4 src=192.168.0.20 dst=192.168.0.1 proto=UDP");
+    }
+
     #[test]
     fn to_prefix_len_ipv6_error() {
         assert_eq!(None, IpAddress::from(Ipv6Address::new(0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0, 1)).to_prefix_len());
     }
+
+    #[test]
+    fn checksum_chunks() {
+        let data = [0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0];
+        let mut whole = ChecksumAccumulator::new();
+        whole.add_bytes(&data);
+        assert_eq!(whole.sum(), checksum::data(&data));
+
+        for split in 0..data.len() {
+            let mut chunked = ChecksumAccumulator::new();
+            chunked.add_bytes(&data[..split]);
+            chunked.add_bytes(&data[split..split]);
+            chunked.add_bytes(&data[split..]);
+            assert_eq!(chunked.finish(), whole.finish(), "Split at {}", split);
+        }
+    }
+
+    #[test]
+    fn checksum_pseudo_header_v6() {
+        let src = IpAddress::from(Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+        let dst = IpAddress::from(Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 2));
+        // Sum of both addresses, the length and the next header.
+        let expected = checksum::combine(&[0xfe80, 1, 0xfe80, 2, 12, 17]);
+        assert_eq!(pseudo_header_checksum(src, dst, IpProtocol::Udp, 12), expected);
+    }
+
+    #[test]
+    fn classification() {
+        const ALL_SYSTEMS: IpAddress = IpAddress::v4(224, 0, 0, 1);
+        const IS_MULTICAST: bool = ALL_SYSTEMS.is_multicast();
+        assert!(IS_MULTICAST);
+        assert!(!ALL_SYSTEMS.is_unicast());
+        assert!(!ALL_SYSTEMS.is_loopback());
+
+        let loopback = IpAddress::v4(127, 0, 0, 1);
+        assert!(loopback.is_loopback());
+        assert!(loopback.is_unicast());
+        assert!(!loopback.is_link_local());
+
+        let link_local = IpAddress::v4(169, 254, 12, 34);
+        assert!(link_local.is_link_local());
+        assert!(!link_local.is_multicast());
+        assert!(!IpAddress::v4(169, 253, 12, 34).is_link_local());
+
+        assert!(IpAddress::v4(0, 0, 0, 0).is_unspecified());
+        assert!(IpAddress::Unspecified.is_unspecified());
+        assert!(!IpAddress::Unspecified.is_loopback());
+        assert!(!IpAddress::Unspecified.is_link_local());
+
+        let all_nodes = IpAddress::from(Ipv6Address::LINK_LOCAL_ALL_NODES);
+        assert_eq!(all_nodes, IpAddress::from(Ipv6Address::new(0xff02, 0, 0, 0, 0, 0, 0, 1)));
+        assert!(all_nodes.is_multicast());
+        assert!(!all_nodes.is_link_local());
+
+        assert!(IpAddress::from(Ipv6Address::LOOPBACK).is_loopback());
+        assert!(IpAddress::from(Ipv6Address::UNSPECIFIED).is_unspecified());
+
+        // The full prefix is fe80::/10.
+        assert!(IpAddress::from(Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)).is_link_local());
+        assert!(IpAddress::from(Ipv6Address::new(0xfebf, 0, 0, 0, 0, 0, 0, 1)).is_link_local());
+        assert!(!IpAddress::from(Ipv6Address::new(0xfec0, 0, 0, 0, 0, 0, 0, 1)).is_link_local());
+        assert!(!IpAddress::from(Ipv6Address::new(0xfe40, 0, 0, 0, 0, 0, 0, 1)).is_link_local());
+    }
 }
@@ -1,4 +1,5 @@
 use core::{fmt, ops};
+use core::cmp::Ordering;
 #[cfg(feature = "std")]
 use core::str::FromStr;
 use byteorder::{ByteOrder, NetworkEndian};
@@ -71,34 +72,34 @@ impl Address {
     }
 
     /// Query whether the address is an unicast address.
-    pub fn is_unicast(&self) -> bool {
+    pub const fn is_unicast(&self) -> bool {
         !(self.is_broadcast() ||
           self.is_multicast() ||
           self.is_unspecified())
     }
 
     /// Query whether the address is the broadcast address.
-    pub fn is_broadcast(&self) -> bool {
-        self.0[0..4] == [255; 4]
+    pub const fn is_broadcast(&self) -> bool {
+        u32::from_be_bytes(self.0) == u32::MAX
     }
 
     /// Query whether the address is a multicast address.
-    pub fn is_multicast(&self) -> bool {
+    pub const fn is_multicast(&self) -> bool {
         self.0[0] & 0xf0 == 224
     }
 
     /// Query whether the address falls into the "unspecified" range.
-    pub fn is_unspecified(&self) -> bool {
+    pub const fn is_unspecified(&self) -> bool {
         self.0[0] == 0
     }
 
     /// Query whether the address falls into the "link-local" range.
-    pub fn is_link_local(&self) -> bool {
-        self.0[0..2] == [169, 254]
+    pub const fn is_link_local(&self) -> bool {
+        self.0[0] == 169 && self.0[1] == 254
     }
 
     /// Query whether the address falls into the "loopback" range.
-    pub fn is_loopback(&self) -> bool {
+    pub const fn is_loopback(&self) -> bool {
         self.0[0] == 127
     }
 
@@ -149,13 +150,18 @@ impl fmt::Display for Address {
 
 /// An IPv4 CIDR host: an address and a variable-length subnet masking prefix length.
 ///
+/// Blocks are ordered by their network first, then by prefix length and only then by the host
+/// address. All blocks containing some address are therefore sorted by their prefix length and
+/// the longest matching prefix is the last of them that is not larger than the address as a full
+/// length prefix.
+///
 /// Relevant RFCs:
 /// * [RFC 1519: Classless Inter-Domain Routing (CIDR)][RFC1519]
 /// * [RFC 3021: Using 31-Bit Prefixes on IPv4 Point-to-Point Links][RFC3021]
 ///
 /// [RFC1519]: https://tools.ietf.org/html/rfc1519
 /// [RFC3021]: https://tools.ietf.org/html/rfc3021
-#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Default)]
 pub struct Cidr {
     address:    Address,
     prefix_len: u8,
@@ -401,6 +407,19 @@ enum ParseCidrErrorKind {
     InvalidPrefix,
 }
 
+impl Ord for Cidr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.subnet().cmp(&other.subnet())
+            .then(self.address.cmp(&other.address))
+    }
+}
+
+impl PartialOrd for Cidr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl fmt::Display for Cidr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}/{}", self.address, self.prefix_len)
@@ -448,6 +467,59 @@ impl FromStr for Cidr {
     }
 }
 
+enum_with_unknown! {
+    /// The type of an IPv4 header option.
+    ///
+    /// Only contains the options registered with IANA that are still in use.
+    pub doc enum OptionType(u8) {
+        /// Marks the end of the option list.
+        EndOfList = 0,
+        /// Padding between options.
+        NoOperation = 1,
+        /// Record route.
+        RecordRoute = 7,
+        /// Internet timestamp.
+        Timestamp = 68,
+        /// Security and handling restrictions.
+        Security = 130,
+        /// Loose source and record route.
+        LooseSourceRoute = 131,
+        /// Stream identifier.
+        StreamId = 136,
+        /// Strict source and record route.
+        StrictSourceRoute = 137,
+        /// Router alert.
+        RouterAlert = 148
+    }
+}
+
+impl OptionType {
+    /// Query if the option must be copied into all fragments.
+    pub fn is_copied(self) -> bool {
+        u8::from(self) & 0x80 != 0
+    }
+}
+
+/// An option in the header of an IPv4 packet.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct HeaderOption<'a> {
+    /// The offset of the option from the start of the header.
+    pub offset: usize,
+    /// The type of the option.
+    pub kind: OptionType,
+    /// The option data, after the type and length octets.
+    pub data: &'a [u8],
+}
+
+/// An iterator over the options of an IPv4 header.
+///
+/// Yields an error and then stops if an option does not fit into the header.
+#[derive(Debug, Clone)]
+pub struct Options<'a> {
+    header: &'a [u8],
+    offset: usize,
+}
+
 /// A read/write wrapper around an Internet Protocol version 4 packet buffer.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Packet<T: Payload> {
@@ -474,6 +546,7 @@ mod field {
     pub(crate) const CHECKSUM: Field = 10..12;
     pub(crate) const SRC_ADDR: Field = 12..16;
     pub(crate) const DST_ADDR: Field = 16..20;
+    pub(crate) const OPTIONS:  usize = DST_ADDR.end;
 }
 
 impl ipv4 {
@@ -509,8 +582,8 @@ impl ipv4 {
 
     /// Ensure that no accessor method will panic if called.
     /// Returns `Err(Error::Truncated)` if the buffer is too short.
-    /// Returns `Err(Error::Malformed)` if the header length is greater
-    /// than total length.
+    /// Returns `Err(Error::Malformed)` if the header length is shorter
+    /// than the fixed header or greater than total length.
     ///
     /// The result of this check is invalidated by calling [set_header_len]
     /// and [set_total_len].
@@ -521,6 +594,8 @@ impl ipv4 {
         let len = self.0.len();
         if len < field::DST_ADDR.end {
             Err(Error::Truncated)
+        } else if (self.header_len() as usize) < field::DST_ADDR.end {
+            Err(Error::Malformed)
         } else if len < self.header_len() as usize {
             Err(Error::Truncated)
         } else if self.header_len() as u16 > self.total_len() {
@@ -729,6 +804,20 @@ impl ipv4 {
         self.set_checksum(checksum)
     }
 
+    /// Iterate over the options in the header.
+    pub fn options(&self) -> Options<'_> {
+        let header_end = usize::from(self.header_len()).max(field::OPTIONS);
+        Options {
+            header: &self.0[..header_end],
+            offset: field::OPTIONS,
+        }
+    }
+
+    /// Ensure that all options are contained in the header.
+    pub fn check_options(&self) -> Result<()> {
+        self.options().try_for_each(|option| option.map(drop))
+    }
+
     /// Compute the range of the payload without accessing it.
     ///
     /// Contrary to `payload_slice`, this only requires the packet to have a valid header but need
@@ -752,6 +841,40 @@ impl ipv4 {
     }
 }
 
+impl<'a> Iterator for Options<'a> {
+    type Item = Result<HeaderOption<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        let kind = OptionType::from(*self.header.get(offset)?);
+
+        let len = match kind {
+            OptionType::EndOfList => {
+                self.offset = self.header.len();
+                return None;
+            },
+            OptionType::NoOperation => 1,
+            _ => match self.header.get(offset + 1) {
+                Some(&len) if len >= 2 && offset + usize::from(len) <= self.header.len() => {
+                    usize::from(len)
+                },
+                _ => {
+                    self.offset = self.header.len();
+                    return Some(Err(Error::Malformed));
+                },
+            },
+        };
+
+        self.offset = offset + len;
+        let data = &self.header[offset..offset + len];
+        Some(Ok(HeaderOption {
+            offset,
+            kind,
+            data: data.get(2..).unwrap_or(&[]),
+        }))
+    }
+}
+
 impl AsRef<[u8]> for ipv4 {
     fn as_ref(&self) -> &[u8] {
         &self.0
@@ -780,6 +903,20 @@ impl<T: Payload> Packet<T> {
         })
     }
 
+    /// Check a packet that may be a fragment, see [`Repr::parse_fragment`].
+    ///
+    /// [`Repr::parse_fragment`]: struct.Repr.html#method.parse_fragment
+    pub fn new_checked_fragment(buffer: T, checksum: Checksum) -> Result<Packet<T>> {
+        let repr = {
+            let packet = ipv4::new_checked(buffer.payload())?;
+            Repr::parse_fragment(packet, checksum)?
+        };
+        Ok(Packet {
+            buffer,
+            repr,
+        })
+    }
+
     /// Get an immutable reference to the whole buffer.
     ///
     /// Useful if the buffer is some other packet encapsulation.
@@ -823,6 +960,24 @@ impl<T: Payload + PayloadMut> Packet<T> {
                 .fill_checksum()
         }
     }
+
+    /// Set the Don't Fragment flag.
+    ///
+    /// This invalidates the checksum, use `fill_checksum` afterwards.
+    pub fn set_dont_frag(&mut self, value: bool) {
+        ipv4::new_unchecked_mut(self.buffer.payload_mut())
+            .set_dont_frag(value)
+    }
+
+    /// Set the identification, More Fragments flag and fragment offset in octets.
+    ///
+    /// This invalidates the checksum, use `fill_checksum` afterwards.
+    pub fn set_fragment(&mut self, ident: u16, more_frags: bool, offset: u16) {
+        let header = ipv4::new_unchecked_mut(self.buffer.payload_mut());
+        header.set_ident(ident);
+        header.set_more_frags(more_frags);
+        header.set_frag_offset(offset);
+    }
 }
 
 impl<'a, T: Payload + ?Sized> Packet<&'a T> {
@@ -894,14 +1049,30 @@ pub struct Repr {
 impl Repr {
     /// Parse an Internet Protocol version 4 packet and return a high-level representation.
     pub fn parse(packet: &ipv4, checksum: Checksum) -> Result<Repr> {
+        Repr::parse_with(packet, checksum, false)
+    }
+
+    /// Parse a packet that may be a fragment of a larger datagram.
+    ///
+    /// The payload length is the one of the fragment itself. The representation is only useful
+    /// for passing the fragment on unchanged, such as when forwarding. Its payload can not be
+    /// interpreted before the datagram is reassembled.
+    pub fn parse_fragment(packet: &ipv4, checksum: Checksum) -> Result<Repr> {
+        Repr::parse_with(packet, checksum, true)
+    }
+
+    fn parse_with(packet: &ipv4, checksum: Checksum, fragment: bool) -> Result<Repr> {
         packet.check_len()?;
         // Version 4 is expected.
         if packet.version() != 4 { return Err(Error::Malformed) }
         // Valid checksum is expected.
         if checksum.manual() && !packet.verify_checksum() { return Err(Error::WrongChecksum) }
-        // We do not support fragmentation.
-        if packet.more_frags() || packet.frag_offset() != 0 { return Err(Error::Unsupported) }
-        // Since the packet is not fragmented, it must include the entire payload.
+        // We do not support fragmentation, unless the caller only passes the fragment on.
+        let is_fragment = packet.more_frags() || packet.frag_offset() != 0;
+        if is_fragment && !fragment { return Err(Error::Unsupported) }
+        // Options are skipped but must not extend past the header.
+        packet.check_options()?;
+        // The packet must include the entire payload of the fragment.
         let payload_len = packet.total_len() as usize - packet.header_len() as usize;
         if packet.payload_slice().len() < payload_len  { return Err(Error::Truncated) }
 
@@ -1142,6 +1313,16 @@ mod test {
         assert_eq!(Repr::parse(packet, Checksum::Manual), Err(Error::Malformed));
     }
 
+    #[test]
+    fn test_header_len_less_than_minimum() {
+        let mut bytes = vec![0; 24];
+        bytes.copy_from_slice(&REPR_PACKET_BYTES[..]);
+        for ihl in 0..5 {
+            bytes[0] = 0x40 | ihl;
+            assert_eq!(ipv4::new_checked(&bytes[..]).unwrap_err(), Error::Malformed);
+        }
+    }
+
     #[test]
     fn test_parse_total_len_less_than_header_len() {
         let mut bytes = vec![0; 40];
@@ -1319,4 +1500,85 @@ mod test {
         assert_eq!(Cidr::new(Address([255, 255, 255, 255]), 32).network(),
                    None);
     }
+
+    #[test]
+    fn test_cidr_ordering() {
+        let cidrs = [
+            Cidr::new(Address([10, 1, 2, 3]), 24),
+            Cidr::new(Address([10, 1, 0, 0]), 16),
+            Cidr::new(Address([10, 0, 0, 0]), 8),
+            Cidr::new(Address([10, 1, 2, 0]), 24),
+            Cidr::new(Address([10, 1, 2, 128]), 25),
+            Cidr::new(Address([0, 0, 0, 0]), 0),
+            Cidr::new(Address([10, 1, 0, 0]), 24),
+        ];
+
+        let mut sorted = cidrs.to_vec();
+        sorted.sort();
+        assert_eq!(sorted, [
+            Cidr::new(Address([0, 0, 0, 0]), 0),
+            Cidr::new(Address([10, 0, 0, 0]), 8),
+            Cidr::new(Address([10, 1, 0, 0]), 16),
+            Cidr::new(Address([10, 1, 0, 0]), 24),
+            Cidr::new(Address([10, 1, 2, 0]), 24),
+            Cidr::new(Address([10, 1, 2, 3]), 24),
+            Cidr::new(Address([10, 1, 2, 128]), 25),
+        ]);
+
+        // Walking backwards from the address finds the longest matching prefix first.
+        let longest = |address: Address| sorted.iter()
+            .rev()
+            .filter(|cidr| **cidr <= Cidr::new(address, 32))
+            .find(|cidr| cidr.subnet().contains(address))
+            .map(Cidr::prefix_len);
+        assert_eq!(longest(Address([10, 1, 2, 200])), Some(25));
+        assert_eq!(longest(Address([10, 1, 2, 7])), Some(24));
+        assert_eq!(longest(Address([10, 1, 3, 7])), Some(16));
+        assert_eq!(longest(Address([10, 2, 0, 0])), Some(8));
+        assert_eq!(longest(Address([11, 0, 0, 0])), Some(0));
+
+        for &cidr in &cidrs {
+            let expected = cidrs.iter()
+                .filter(|other| other.subnet().contains(cidr.address()))
+                .map(Cidr::prefix_len)
+                .max();
+            assert_eq!(longest(cidr.address()), expected);
+        }
+    }
+
+    #[test]
+    fn test_options() {
+        let mut bytes = vec![0; 32];
+        let packet = ipv4::new_unchecked_mut(&mut bytes);
+        packet.set_version(4);
+        packet.set_header_len(32);
+        packet.set_total_len(32);
+        packet.as_bytes_mut()[20..32].copy_from_slice(&[
+            0x01,                   // no-op
+            0x94, 0x04, 0x00, 0x00, // router alert
+            0x9e, 0x03, 0xab,       // unknown, copied
+            0x00, 0x00, 0x00, 0x00, // end of list and padding
+        ]);
+
+        let mut options = packet.options();
+        let nop = options.next().unwrap().unwrap();
+        assert_eq!((nop.offset, nop.kind), (20, OptionType::NoOperation));
+        let alert = options.next().unwrap().unwrap();
+        assert_eq!((alert.offset, alert.kind, alert.data), (21, OptionType::RouterAlert, &[0, 0][..]));
+        let unknown = options.next().unwrap().unwrap();
+        assert_eq!((unknown.offset, unknown.kind), (25, OptionType::Unknown(0x9e)));
+        assert!(unknown.kind.is_copied());
+        assert_eq!(unknown.data, &[0xab]);
+        assert!(options.next().is_none());
+        assert_eq!(packet.check_options(), Ok(()));
+
+        // An option that claims to extend past the header.
+        packet.as_bytes_mut()[26] = 0x10;
+        let mut options = packet.options();
+        assert!(options.next().unwrap().is_ok());
+        assert!(options.next().unwrap().is_ok());
+        assert_eq!(options.next(), Some(Err(Error::Malformed)));
+        assert!(options.next().is_none());
+        assert_eq!(packet.check_options(), Err(Error::Malformed));
+    }
 }
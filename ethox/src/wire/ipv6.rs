@@ -1,4 +1,5 @@
 use core::{fmt, ops};
+use core::cmp::Ordering;
 use byteorder::{ByteOrder, NetworkEndian};
 
 use crate::wire::{Error, Result, Payload, PayloadError, PayloadMut, Reframe, payload};
@@ -184,37 +185,38 @@ impl Address {
     /// Query whether the IPv6 address is an [unicast address].
     ///
     /// [unicast address]: https://tools.ietf.org/html/rfc4291#section-2.5
-    pub fn is_unicast(&self) -> bool {
+    pub const fn is_unicast(&self) -> bool {
         !(self.is_multicast() || self.is_unspecified())
     }
 
     /// Query whether the IPv6 address is a [multicast address].
     ///
     /// [multicast address]: https://tools.ietf.org/html/rfc4291#section-2.7
-    pub fn is_multicast(&self) -> bool {
+    pub const fn is_multicast(&self) -> bool {
         self.0[0] == 0xff
     }
 
     /// Query whether the IPv6 address is the [unspecified address].
     ///
     /// [unspecified address]: https://tools.ietf.org/html/rfc4291#section-2.5.2
-    pub fn is_unspecified(&self) -> bool {
-        self.0 == [0x00; 16]
+    pub const fn is_unspecified(&self) -> bool {
+        u128::from_be_bytes(self.0) == 0
     }
 
     /// Query whether the IPv6 address is in the [link-local] scope.
     ///
+    /// This is the whole prefix `fe80::/10`, even though only the first `/64` of it is in use.
+    ///
     /// [link-local]: https://tools.ietf.org/html/rfc4291#section-2.5.6
-    pub fn is_link_local(&self) -> bool {
-        self.0[0..8] == [0xfe, 0x80, 0x00, 0x00,
-                         0x00, 0x00, 0x00, 0x00]
+    pub const fn is_link_local(&self) -> bool {
+        self.0[0] == 0xfe && self.0[1] & 0xc0 == 0x80
     }
 
     /// Query whether the IPv6 address is the [loopback address].
     ///
     /// [loopback address]: https://tools.ietf.org/html/rfc4291#section-2.5.3
-    pub fn is_loopback(&self) -> bool {
-        *self == Self::LOOPBACK
+    pub const fn is_loopback(&self) -> bool {
+        u128::from_be_bytes(self.0) == 1
     }
 
     /// Query whether the IPv6 address is an [IPv4 mapped IPv6 address].
@@ -254,14 +256,19 @@ impl Address {
 
     /// The solicited node for the given unicast address.
     ///
+    /// This is the prefix `ff02::1:ff00:0/104` followed by the low 24 bits of the address, see
+    /// [RFC 4291], section 2.7.1.
+    ///
+    /// [RFC 4291]: https://tools.ietf.org/html/rfc4291
+    ///
     /// # Panics
     /// This function panics if the given address is not
     /// unicast.
     pub fn solicited_node_multicast(&self) -> Address {
         assert!(self.is_unicast());
         let mut bytes = [0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
-        bytes[14..].copy_from_slice(&self.0[14..]);
+                     0x00, 0x00, 0x00, 0x01, 0xff, 0x00, 0x00, 0x00];
+        bytes[13..].copy_from_slice(&self.0[13..]);
         Address(bytes)
     }
 
@@ -377,7 +384,9 @@ impl fmt::Display for Address {
 }
 
 /// An IPv6 CIDR host: an address and a variable-length subnet masking prefix length.
-#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+///
+/// Ordered by the network, then the prefix length and then the host address, as IPv4 blocks.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Default)]
 pub struct Cidr {
     address:    Address,
     prefix_len: u8,
@@ -509,6 +518,19 @@ impl Subnet {
     }
 }
 
+impl Ord for Cidr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.subnet().cmp(&other.subnet())
+            .then(self.address.cmp(&other.address))
+    }
+}
+
+impl PartialOrd for Cidr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl fmt::Display for Cidr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         // https://tools.ietf.org/html/rfc4291#section-2.3
@@ -1070,6 +1092,14 @@ mod test {
         assert_eq!(Some(ipv4), Address::from_mapped_ipv4(ipv4).as_ipv4());
     }
 
+    #[test]
+    fn test_solicited_node_multicast() {
+        let addr = Address::new(0xfe80, 0, 0, 0, 0x0201, 0x02ff, 0xfe03, 0x0405);
+        let group = Address::new(0xff02, 0, 0, 0, 0, 1, 0xff03, 0x0405);
+        assert_eq!(addr.solicited_node_multicast(), group);
+        assert!(addr.accepts(group));
+    }
+
     #[test]
     fn test_from_ipv4_address() {
         assert_eq!(Address([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 192, 168, 1, 1]),
@@ -1153,6 +1183,27 @@ mod test {
         assert!(cidr_without_prefix.subnet().contains(Address::LOOPBACK));
     }
 
+    #[test]
+    fn test_cidr_ordering() {
+        let host = Address::from_parts(&[0xfe80, 0, 0, 0, 0x11, 0, 0, 1]);
+        let mut cidrs = [
+            Cidr::new(host, 128),
+            Cidr::new(host, 64),
+            Cidr::new(Address::from_parts(&[0xfe80, 0, 0, 0, 0, 0, 0, 0]), 64),
+            Cidr::new(Address::from_parts(&[0xfe80, 0, 0, 0, 0, 0, 0, 0]), 10),
+            Cidr::new(Address::from_parts(&[0xfe80, 0, 0, 0, 0x11, 0, 0, 0]), 80),
+        ];
+        cidrs.sort();
+
+        assert_eq!(cidrs, [
+            Cidr::new(Address::from_parts(&[0xfe80, 0, 0, 0, 0, 0, 0, 0]), 10),
+            Cidr::new(Address::from_parts(&[0xfe80, 0, 0, 0, 0, 0, 0, 0]), 64),
+            Cidr::new(host, 64),
+            Cidr::new(Address::from_parts(&[0xfe80, 0, 0, 0, 0x11, 0, 0, 0]), 80),
+            Cidr::new(host, 128),
+        ]);
+    }
+
     #[test]
     #[should_panic(expected = "destination and source slices have different lengths")]
     fn test_from_bytes_too_long() {
@@ -148,7 +148,7 @@ mod raw {
     pub(crate) mod ipv6fragment;
     pub(crate) mod ipv6routing;
     pub(crate) mod icmpv4;
-    // mod icmpv6;
+    pub(crate) mod icmpv6;
     // mod icmp;
     // #[cfg(feature = "proto-igmp")]
     // mod igmp;
@@ -157,11 +157,13 @@ mod raw {
     // mod mld;
     pub(crate) mod udp;
     pub(crate) mod tcp;
+    pub(crate) mod tftp;
+    pub(crate) mod vrrp;
+    pub(crate) mod dhcpv4;
 }
 
 // mod ethernet;
 mod error;
-// pub(crate) mod dhcpv4;
 
 #[path = "payload.rs"]
 mod payload_impl;
@@ -198,6 +200,8 @@ pub mod ethernet {
         Address,
         Frame,
         Repr,
+        VlanTag,
+        MIN_FRAME_LEN,
     };
 }
 
@@ -213,6 +217,8 @@ pub mod arp {
 
 pub mod ip {
     pub use super::raw::ip::{
+        pseudo_header_checksum,
+        ChecksumAccumulator,
         Version,
         Protocol,
         Address,
@@ -226,6 +232,9 @@ pub mod ip {
         pub use super::super::raw::ipv4::{
             ipv4 as packet,
             Address,
+            HeaderOption,
+            Options,
+            OptionType,
             Packet,
             Repr,
             Cidr,
@@ -291,6 +300,23 @@ pub mod icmpv4 {
     };
 }
 
+pub mod icmpv6 {
+    pub use super::raw::icmpv6::{
+        icmpv6 as packet,
+        Checksum,
+        DstUnreachable,
+        Message,
+        NeighborFlags,
+        ParamProblem,
+        PrefixInfo,
+        Prefixes,
+        Repr,
+        RouterFlags,
+        TimeExceeded,
+        NDISC_HOP_LIMIT,
+    };
+}
+
 /*
 #[cfg(feature = "proto-igmp")]
 pub use self::igmp::{
@@ -298,14 +324,6 @@ pub use self::igmp::{
     Repr as IgmpRepr,
     IgmpVersion};
 
-pub use self::icmpv6::{
-    Message as Icmpv6Message,
-    DstUnreachable as Icmpv6DstUnreachable,
-    TimeExceeded as Icmpv6TimeExceeded,
-    ParamProblem as Icmpv6ParamProblem,
-    Packet as Icmpv6Packet,
-    Repr as Icmpv6Repr};
-
 pub use self::icmp::Repr as IcmpRepr;
 */
 
@@ -333,6 +351,7 @@ pub use self::mld::{
 pub mod udp {
     pub use super::raw::udp::{
         udp as packet,
+        Flow,
         Packet,
         Repr,
         Checksum,
@@ -350,13 +369,55 @@ pub mod tcp {
     };
 }
 
-#[cfg(feature = "proto-dhcpv4")]
-pub use self::dhcpv4::{
-    Packet as DhcpPacket,
-    Repr as DhcpRepr,
-    MessageType as DhcpMessageType};
+pub mod tftp {
+    pub use super::raw::tftp::{
+        tftp as packet,
+        ErrorCode,
+        Mode,
+        Opcode,
+        Repr,
+        BLOCK_SIZE,
+        PORT,
+    };
+}
+
+pub mod vrrp {
+    pub use super::raw::vrrp::{
+        vrrp as packet,
+        Checksum,
+        Repr,
+        Version,
+        HOP_LIMIT,
+        MULTICAST_ADDR,
+        MULTICAST_MAC,
+        PRIORITY_OWNER,
+        PRIORITY_RELEASE,
+    };
+}
+
+pub mod dhcpv4 {
+    pub use super::raw::dhcpv4::{
+        dhcp as packet,
+        option,
+        DhcpOption as Option,
+        MessageType,
+        OpCode,
+        Repr,
+        CLIENT_PORT,
+        SERVER_PORT,
+    };
+}
 
 impl Checksum {
+    /// Update an internet checksum after a single 16-bit word of the data changed.
+    ///
+    /// Implements equation 3 of RFC 1624, the cost does not depend on the length of the data. The
+    /// result is the same as recomputing the checksum over the changed data, provided that the
+    /// previous `checksum` was correct.
+    pub fn update_word(checksum: u16, old: u16, new: u16) -> u16 {
+        raw::ip::checksum::update(checksum, old, new)
+    }
+
     /// Check if a checksum should be calculated by the library.
     ///
     /// Otherwise it is ignored due to the assumption that it was offloaded or is otherwise
@@ -51,6 +51,11 @@ pub trait PayloadMutExt: PayloadMut {
     /// # Ok::<(), ethox::wire::PayloadError>(())
     /// ```
     ///
+    /// # Errors
+    ///
+    /// When the underlying `reframe` fails the contents of the buffer are left unchanged, provided
+    /// the failed `reframe` did not modify the buffer either.
+    ///
     /// # Panics
     ///
     /// This methods panics if the reframe is inconsistent, that is:
@@ -88,23 +93,25 @@ pub trait PayloadMutExt: PayloadMut {
                 )
             }
         } else { // Need to move the frame before reframing.
+            // The payload moves towards the front, `new_payload.start < old_payload.start`. Rotate
+            // instead of copying so that the bytes in front of it are kept and the move can be
+            // undone when the reframe fails.
+            let moved = frame.new_payload.start..frame.old_payload.end;
+            let shift = frame.old_payload.start - frame.new_payload.start;
             let slice = self.payload_mut().as_mut_slice();
             assert!(slice.len() == current_len);
-
-            // FIXME: `slice::copy_within`, see above.
-            unsafe {
-                core::ptr::copy(
-                    slice.get_unchecked(frame.old_payload.start),
-                    slice.get_unchecked_mut(frame.new_payload.start),
-                    frame.old_payload.len(),
-                )
-            }
+            slice[moved.clone()].rotate_left(shift);
 
             if frame.length != current_len {
-                self.reframe(Reframe {
+                let reframed = self.reframe(Reframe {
                     length: frame.length,
                     range: frame.new_payload,
-                })?;
+                });
+
+                if let Err(err) = reframed {
+                    self.payload_mut().as_mut_slice()[moved].rotate_right(shift);
+                    return Err(err);
+                }
             }
         }
 
@@ -164,4 +171,17 @@ mod tests {
         }).expect("Should work fine");
         assert_eq!(packet[10..15].iter().cloned().cmp(0..5), Ordering::Equal);
     }
+
+    /// A failed retraction leaves the buffer untouched.
+    #[test]
+    fn reframe_retract_fails() {
+        let mut bytes = (0..10).collect::<Vec<u8>>();
+        let mut packet: &mut [u8] = &mut bytes[..];
+        packet.reframe_payload(ReframePayload {
+            length: 7,
+            old_payload: 4..9,
+            new_payload: 2..7,
+        }).expect_err("Fixed size buffer can not shrink");
+        assert_eq!(packet.iter().cloned().cmp(0..10), Ordering::Equal);
+    }
 }
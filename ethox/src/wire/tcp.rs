@@ -414,6 +414,16 @@ impl<T: PayloadMut> Packet<T> {
         self.set_checksum(checksum)
     }
 
+    /// Fill in the header checksum if required.
+    ///
+    /// An `Ignored` checksum is left to the nic and the field is not touched.
+    pub fn fill_checksum_with(&mut self, checksum: Checksum) {
+        match checksum {
+            Checksum::Manual { src_addr, dst_addr } => self.fill_checksum(src_addr, dst_addr),
+            Checksum::Ignored => (),
+        }
+    }
+
     /// Return a pointer to the options.
     #[inline]
     pub fn options_mut(&mut self) -> &mut [u8] {
@@ -0,0 +1,411 @@
+//! The Trivial File Transfer Protocol.
+//!
+//! Implements the packet format of [RFC1350]. Option extensions of later standards are not
+//! supported, a request containing options is still parsed but they are ignored.
+//!
+//! [RFC1350]: https://tools.ietf.org/html/rfc1350
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use crate::wire::{Error, Result};
+use crate::wire::pretty_print::{PrettyPrint, PrettyIndent};
+
+/// The well-known port on which servers listen for requests.
+pub const PORT: u16 = 69;
+
+/// The size of all data blocks except the last one of a transfer.
+pub const BLOCK_SIZE: usize = 512;
+
+byte_wrapper! {
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct tftp([u8]);
+}
+
+enum_with_unknown! {
+    /// The type of a TFTP packet.
+    pub doc enum Opcode(u16) {
+        /// Read request.
+        Read = 1,
+        /// Write request.
+        Write = 2,
+        /// Data block.
+        Data = 3,
+        /// Acknowledgement of a data block.
+        Ack = 4,
+        /// Error message, terminating the transfer.
+        Error = 5,
+    }
+}
+
+enum_with_unknown! {
+    /// The error code of an error packet.
+    pub doc enum ErrorCode(u16) {
+        /// Not defined, see the error message.
+        Undefined = 0,
+        /// File not found.
+        FileNotFound = 1,
+        /// Access violation.
+        AccessViolation = 2,
+        /// Disk full or allocation exceeded.
+        DiskFull = 3,
+        /// Illegal TFTP operation.
+        IllegalOperation = 4,
+        /// Unknown transfer ID.
+        UnknownTid = 5,
+        /// File already exists.
+        FileExists = 6,
+        /// No such user.
+        NoSuchUser = 7,
+    }
+}
+
+/// The transfer mode of a request.
+///
+/// The mode has no influence on the transfer itself, converting the data is the responsibility of
+/// the user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// Ascii with the line endings of the network.
+    NetAscii,
+    /// Raw octets.
+    Octet,
+    /// Obsolete mail mode.
+    Mail,
+}
+
+mod field {
+    use crate::wire::field::{Field, Rest};
+
+    pub(crate) const OPCODE: Field = 0..2;
+    pub(crate) const BLOCK: Field = 2..4;
+    pub(crate) const ERROR_CODE: Field = 2..4;
+    pub(crate) const DATA: Rest = 4..;
+    pub(crate) const ERROR_MSG: Rest = 4..;
+    pub(crate) const FILENAME: Rest = 2..;
+}
+
+/// A high-level representation of a TFTP packet.
+///
+/// The content of data blocks is not part of the representation, only their length. It is
+/// accessed on the packet instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repr<'a> {
+    /// Request to read a file from the server.
+    Read {
+        /// The name of the requested file.
+        filename: &'a [u8],
+        /// The requested transfer mode.
+        mode: Mode,
+    },
+    /// Request to write a file to the server.
+    Write {
+        /// The name of the written file.
+        filename: &'a [u8],
+        /// The requested transfer mode.
+        mode: Mode,
+    },
+    /// A block of data.
+    Data {
+        /// The number of the block, starting at 1.
+        block: u16,
+        /// The length of the data, a block shorter than `BLOCK_SIZE` ends the transfer.
+        len: usize,
+    },
+    /// Acknowledgement of a data block, or of a write request with block 0.
+    Ack {
+        /// The acknowledged block number.
+        block: u16,
+    },
+    /// An error, terminating the transfer.
+    Error {
+        /// The error code.
+        code: ErrorCode,
+        /// A human readable message.
+        message: &'a [u8],
+    },
+}
+
+impl Mode {
+    /// The textual representation in requests.
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Mode::NetAscii => b"netascii",
+            Mode::Octet => b"octet",
+            Mode::Mail => b"mail",
+        }
+    }
+
+    /// Parse the mode of a request, ignoring case.
+    pub fn from_bytes(mode: &[u8]) -> Option<Self> {
+        [Mode::NetAscii, Mode::Octet, Mode::Mail].iter()
+            .cloned()
+            .find(|known| known.as_bytes().eq_ignore_ascii_case(mode))
+    }
+}
+
+impl tftp {
+    /// Imbue a raw octet buffer with TFTP packet structure.
+    pub fn new_unchecked(data: &[u8]) -> &Self {
+        Self::__from_macro_new_unchecked(data)
+    }
+
+    /// Imbue a mutable octet buffer with TFTP packet structure.
+    pub fn new_unchecked_mut(data: &mut [u8]) -> &mut Self {
+        Self::__from_macro_new_unchecked_mut(data)
+    }
+
+    /// Shorthand for a combination of `new_unchecked` and `check_len`.
+    pub fn new_checked(data: &[u8]) -> Result<&Self> {
+        Self::new_unchecked(data).check_len()?;
+        Ok(Self::new_unchecked(data))
+    }
+
+    /// Unwrap the packet as a raw byte slice.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Unwrap the packet as a mutable raw byte slice.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    ///
+    /// Returns `Err(Error::Truncated)` if the buffer is too short for the fixed header fields of
+    /// any packet type. Requests are only fully checked when parsing.
+    pub fn check_len(&self) -> Result<()> {
+        if self.0.len() < field::DATA.start {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Return the opcode field.
+    pub fn opcode(&self) -> Opcode {
+        Opcode::from(NetworkEndian::read_u16(&self.0[field::OPCODE]))
+    }
+
+    /// Return the block number of a data or acknowledgement packet.
+    pub fn block(&self) -> u16 {
+        NetworkEndian::read_u16(&self.0[field::BLOCK])
+    }
+
+    /// Return the error code of an error packet.
+    pub fn error_code(&self) -> ErrorCode {
+        ErrorCode::from(NetworkEndian::read_u16(&self.0[field::ERROR_CODE]))
+    }
+
+    /// Return the data of a data packet.
+    pub fn data(&self) -> &[u8] {
+        &self.0[field::DATA]
+    }
+
+    /// Set the opcode field.
+    pub fn set_opcode(&mut self, value: Opcode) {
+        NetworkEndian::write_u16(&mut self.0[field::OPCODE], value.into())
+    }
+
+    /// Set the block number of a data or acknowledgement packet.
+    pub fn set_block(&mut self, value: u16) {
+        NetworkEndian::write_u16(&mut self.0[field::BLOCK], value)
+    }
+
+    /// Set the error code of an error packet.
+    pub fn set_error_code(&mut self, value: ErrorCode) {
+        NetworkEndian::write_u16(&mut self.0[field::ERROR_CODE], value.into())
+    }
+
+    /// Return the data of a data packet mutably.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.0[field::DATA]
+    }
+
+    /// Split the filename and mode of a request.
+    fn request(&self) -> Result<(&[u8], &[u8])> {
+        let mut strings = self.0[field::FILENAME].split(|&b| b == 0);
+        let filename = strings.next().ok_or(Error::Malformed)?;
+        let mode = strings.next().ok_or(Error::Malformed)?;
+        // Both strings must be terminated, the split yields the rest after the last terminator.
+        if strings.next().is_none() {
+            return Err(Error::Malformed);
+        }
+        Ok((filename, mode))
+    }
+}
+
+impl AsRef<[u8]> for tftp {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsMut<[u8]> for tftp {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl<'a> Repr<'a> {
+    /// Parse a TFTP packet and return a high-level representation.
+    pub fn parse(packet: &'a tftp) -> Result<Self> {
+        packet.check_len()?;
+
+        match packet.opcode() {
+            Opcode::Read | Opcode::Write => {
+                let (filename, mode) = packet.request()?;
+                let mode = Mode::from_bytes(mode).ok_or(Error::Unrecognized)?;
+                Ok(match packet.opcode() {
+                    Opcode::Read => Repr::Read { filename, mode },
+                    _ => Repr::Write { filename, mode },
+                })
+            },
+            Opcode::Data => Ok(Repr::Data {
+                block: packet.block(),
+                len: packet.data().len(),
+            }),
+            Opcode::Ack => Ok(Repr::Ack {
+                block: packet.block(),
+            }),
+            Opcode::Error => {
+                let message = &packet.0[field::ERROR_MSG];
+                let end = message.iter()
+                    .position(|&b| b == 0)
+                    .ok_or(Error::Malformed)?;
+                Ok(Repr::Error {
+                    code: packet.error_code(),
+                    message: &message[..end],
+                })
+            },
+            Opcode::Unknown(_) => Err(Error::Unrecognized),
+        }
+    }
+
+    /// Return the length of a packet that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        match self {
+            Repr::Read { filename, mode } | Repr::Write { filename, mode } =>
+                field::FILENAME.start + filename.len() + 1 + mode.as_bytes().len() + 1,
+            Repr::Data { len, .. } => field::DATA.start + len,
+            Repr::Ack { .. } => field::BLOCK.end,
+            Repr::Error { message, .. } => field::ERROR_MSG.start + message.len() + 1,
+        }
+    }
+
+    /// Emit a high-level representation into a TFTP packet.
+    ///
+    /// The data of a data packet must be filled in separately.
+    pub fn emit(&self, packet: &mut tftp) {
+        match *self {
+            Repr::Read { filename, mode } | Repr::Write { filename, mode } => {
+                packet.set_opcode(match self {
+                    Repr::Read { .. } => Opcode::Read,
+                    _ => Opcode::Write,
+                });
+                let mode = mode.as_bytes();
+                let strings = &mut packet.0[field::FILENAME];
+                strings[..filename.len()].copy_from_slice(filename);
+                strings[filename.len()] = 0;
+                let strings = &mut strings[filename.len() + 1..];
+                strings[..mode.len()].copy_from_slice(mode);
+                strings[mode.len()] = 0;
+            },
+            Repr::Data { block, .. } => {
+                packet.set_opcode(Opcode::Data);
+                packet.set_block(block);
+            },
+            Repr::Ack { block } => {
+                packet.set_opcode(Opcode::Ack);
+                packet.set_block(block);
+            },
+            Repr::Error { code, message } => {
+                packet.set_opcode(Opcode::Error);
+                packet.set_error_code(code);
+                let data = &mut packet.0[field::ERROR_MSG];
+                data[..message.len()].copy_from_slice(message);
+                data[message.len()] = 0;
+            },
+        }
+    }
+}
+
+impl fmt::Display for Repr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Repr::Read { filename, mode } =>
+                write!(f, "TFTP read request file={:?} mode={:?}", filename, mode),
+            Repr::Write { filename, mode } =>
+                write!(f, "TFTP write request file={:?} mode={:?}", filename, mode),
+            Repr::Data { block, len } =>
+                write!(f, "TFTP data block={} len={}", block, len),
+            Repr::Ack { block } =>
+                write!(f, "TFTP ack block={}", block),
+            Repr::Error { code, .. } =>
+                write!(f, "TFTP error code={:?}", code),
+        }
+    }
+}
+
+impl PrettyPrint for tftp {
+    fn pretty_print(buffer: &[u8], f: &mut fmt::Formatter,
+                    indent: &mut PrettyIndent) -> fmt::Result {
+        let packet = match tftp::new_checked(buffer) {
+            Err(err) => return write!(f, "{}({})", indent, err),
+            Ok(packet) => packet,
+        };
+
+        match Repr::parse(packet) {
+            Err(err) => write!(f, "{}({})", indent, err),
+            Ok(repr) => write!(f, "{}{}", indent, repr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static READ_BYTES: [u8; 16] = [
+        0x00, 0x01, b'f', b'i', b'l', b'e', 0x00, b'O',
+        b'C', b'T', b'E', b'T', 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn parse_request() {
+        let packet = tftp::new_checked(&READ_BYTES[..13]).unwrap();
+        assert_eq!(Repr::parse(packet), Ok(Repr::Read {
+            filename: b"file",
+            mode: Mode::Octet,
+        }));
+
+        // Unterminated mode.
+        let packet = tftp::new_checked(&READ_BYTES[..12]).unwrap();
+        assert_eq!(Repr::parse(packet), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn emit_request() {
+        let repr = Repr::Read { filename: b"file", mode: Mode::Octet };
+        assert_eq!(repr.buffer_len(), 13);
+        let mut bytes = [0xff; 13];
+        repr.emit(tftp::new_unchecked_mut(&mut bytes));
+        assert_eq!(&bytes[..7], &READ_BYTES[..7]);
+        assert_eq!(&bytes[7..], b"octet\0");
+    }
+
+    #[test]
+    fn roundtrip() {
+        let reprs = [
+            Repr::Data { block: 3, len: 4 },
+            Repr::Ack { block: 0xffff },
+            Repr::Error { code: ErrorCode::FileNotFound, message: b"missing" },
+        ];
+        for repr in reprs.iter() {
+            let mut bytes = vec![0; repr.buffer_len()];
+            repr.emit(tftp::new_unchecked_mut(&mut bytes));
+            let packet = tftp::new_checked(&bytes).unwrap();
+            assert_eq!(Repr::parse(packet).as_ref(), Ok(repr));
+        }
+    }
+}
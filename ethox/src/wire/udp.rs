@@ -174,6 +174,55 @@ impl udp {
         ]) == !0
     }
 
+    /// Change the ports, updating the checksum incrementally.
+    ///
+    /// Unlike `fill_checksum` this does not read the payload. The checksum is only correct
+    /// afterwards if it was correct before. A zero checksum, which the sender did not compute, is
+    /// kept.
+    pub fn update_ports(&mut self, src_port: u16, dst_port: u16) {
+        let mut old = [0; 4];
+        old.copy_from_slice(&self.0[field::SRC_PORT.start..field::DST_PORT.end]);
+        self.set_src_port(src_port);
+        self.set_dst_port(dst_port);
+
+        let checksum = self.checksum();
+        if checksum != 0 {
+            let new = &self.0[field::SRC_PORT.start..field::DST_PORT.end];
+            let checksum = checksum::update_bytes(checksum, &old, new);
+            self.set_checksum(if checksum == 0 { 0xffff } else { checksum })
+        }
+    }
+
+    /// Update the checksum incrementally for a change of the addresses in the pseudo header.
+    ///
+    /// Unlike `fill_checksum` this does not read the payload. The checksum is only correct
+    /// afterwards if it was correct before. A zero checksum, which the sender did not compute, is
+    /// kept.
+    ///
+    /// # Panics
+    /// This function panics unless all addresses belong to the same family, and that family is
+    /// IPv4 or IPv6.
+    pub fn update_pseudo_header(
+        &mut self,
+        (old_src, old_dst): (ip::Address, ip::Address),
+        (new_src, new_dst): (ip::Address, ip::Address),
+    ) {
+        use crate::wire::ip::Address::{Ipv4, Ipv6};
+        let same_family = matches!((old_src, old_dst, new_src, new_dst),
+            (Ipv4(_), Ipv4(_), Ipv4(_), Ipv4(_)) | (Ipv6(_), Ipv6(_), Ipv6(_), Ipv6(_)));
+        assert!(same_family, "Unexpected pseudo header addresses: {}, {}, {}, {}",
+            old_src, old_dst, new_src, new_dst);
+
+        let checksum = self.checksum();
+        if checksum == 0 {
+            return;
+        }
+
+        let checksum = checksum::update_bytes(checksum, old_src.as_bytes(), new_src.as_bytes());
+        let checksum = checksum::update_bytes(checksum, old_dst.as_bytes(), new_dst.as_bytes());
+        self.set_checksum(if checksum == 0 { 0xffff } else { checksum })
+    }
+
     pub fn payload_slice(&self) -> &[u8] {
         let len = self.len();
         &self.0[field::PAYLOAD(len)]
@@ -336,6 +385,19 @@ pub struct Repr {
     pub length: u16,
 }
 
+/// A rendering of a datagram together with the addresses of its ip packet.
+///
+/// The udp header itself contains only ports. Created with [`Repr::flow`], it displays in the
+/// form `UDP 192.168.0.20:9400 -> 192.168.0.1:43 len=13`.
+///
+/// [`Repr::flow`]: struct.Repr.html#method.flow
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Flow {
+    pub src: ip::Endpoint,
+    pub dst: ip::Endpoint,
+    pub repr: Repr,
+}
+
 /// Abstraction for checksum behaviour.
 ///
 /// The checksum requires calculating a pseudo header for the upper layer protocol consisting of
@@ -360,6 +422,15 @@ pub enum Checksum {
 }
 
 impl Repr {
+    /// Combine with the addresses of the ip packet for display.
+    pub fn flow(&self, src_addr: ip::Address, dst_addr: ip::Address) -> Flow {
+        Flow {
+            src: ip::Endpoint::new(src_addr, self.src_port),
+            dst: ip::Endpoint::new(dst_addr, self.dst_port),
+            repr: *self,
+        }
+    }
+
     /// Parse an User Datagram Protocol packet and return a high-level representation.
     pub fn parse(packet: &udp, checksum: Checksum) -> Result<Repr> {
         packet.check_len()?;
@@ -444,6 +515,18 @@ impl fmt::Display for Repr {
     }
 }
 
+impl fmt::Display for Flow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let payload_len = usize::from(self.repr.length)
+            .checked_sub(field::CHECKSUM.end);
+        if let Some(payload_len) = payload_len {
+            write!(f, "UDP {} -> {} len={}", self.src, self.dst, payload_len)
+        } else {
+            write!(f, "UDP {} -> {} len=??", self.src, self.dst)
+        }
+    }
+}
+
 impl PrettyPrint for udp {
     fn pretty_print(buffer: &[u8], f: &mut fmt::Formatter,
                     indent: &mut PrettyIndent) -> fmt::Result {
@@ -494,6 +577,68 @@ mod test {
         assert_eq!(packet.as_bytes(), &PACKET_BYTES[..]);
     }
 
+    #[test]
+    fn test_checksum_accumulator() {
+        let mut bytes = PACKET_BYTES;
+        let packet = udp::new_unchecked_mut(&mut bytes[..]);
+        packet.set_checksum(0);
+
+        let mut checksum = ip::ChecksumAccumulator::for_pseudo_header(
+            SRC_ADDR.into(), DST_ADDR.into(), ip::Protocol::Udp, 12);
+        checksum.add_bytes(&bytes[..5]);
+        checksum.add_bytes(&bytes[5..]);
+        assert_eq!(checksum.finish(), 0x124d);
+
+        // The sum including a correct checksum field.
+        let mut verify = ip::ChecksumAccumulator::for_pseudo_header(
+            SRC_ADDR.into(), DST_ADDR.into(), ip::Protocol::Udp, 12);
+        verify.add_bytes(&PACKET_BYTES);
+        assert_eq!(verify.sum(), !0);
+    }
+
+    #[test]
+    fn test_update_incremental() {
+        use crate::wire::ip::v6::Address as Ipv6Address;
+
+        let mut bytes = PACKET_BYTES;
+        let packet = udp::new_unchecked_mut(&mut bytes[..]);
+        packet.update_ports(53, 48896);
+        packet.update_pseudo_header(
+            (SRC_ADDR.into(), DST_ADDR.into()),
+            (DST_ADDR.into(), SRC_ADDR.into()));
+        assert!(packet.verify_checksum(DST_ADDR.into(), SRC_ADDR.into()));
+
+        let other = Ipv4Address([10, 0, 0, 1]);
+        packet.update_pseudo_header(
+            (DST_ADDR.into(), SRC_ADDR.into()),
+            (other.into(), SRC_ADDR.into()));
+        let expected = packet.checksum();
+        packet.fill_checksum(other.into(), SRC_ADDR.into());
+        assert_eq!(packet.checksum(), expected);
+
+        let v6_src = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let v6_dst = Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+        packet.fill_checksum(v6_src.into(), v6_dst.into());
+        packet.update_ports(7, 1234);
+        packet.update_pseudo_header((v6_src.into(), v6_dst.into()), (v6_dst.into(), v6_src.into()));
+        assert!(packet.verify_checksum(v6_dst.into(), v6_src.into()));
+
+        // A checksum that was not computed stays absent.
+        packet.set_checksum(0);
+        packet.update_ports(48896, 53);
+        packet.update_pseudo_header((v6_dst.into(), v6_src.into()), (v6_src.into(), v6_dst.into()));
+        assert_eq!(packet.checksum(), 0);
+    }
+
+    #[test]
+    fn test_update_word() {
+        let mut bytes = PACKET_BYTES;
+        let packet = udp::new_unchecked_mut(&mut bytes[..]);
+        packet.set_dst_port(5353);
+        packet.fill_checksum(SRC_ADDR.into(), DST_ADDR.into());
+        assert_eq!(crate::wire::Checksum::update_word(0x124d, 53, 5353), packet.checksum());
+    }
+
     #[test]
     fn test_impossible_len() {
         let mut bytes = vec![0; 12];
@@ -532,6 +677,24 @@ mod test {
         assert_eq!(packet.payload_slice(), &PAYLOAD_BYTES[..]);
     }
 
+    #[test]
+    fn test_display() {
+        let repr = Repr {
+            src_port: 9400,
+            dst_port: 43,
+            length: 8 + 13,
+        };
+        assert_eq!(format!("{}", repr), "UDP src=9400 dst=43 len=13");
+
+        let flow = repr.flow(
+            Ipv4Address::new(192, 168, 0, 20).into(),
+            Ipv4Address::new(192, 168, 0, 1).into());
+        assert_eq!(format!("{}", flow), "UDP 192.168.0.20:9400 -> 192.168.0.1:43 len=13");
+
+        let truncated = Repr { length: 4, ..repr };
+        assert_eq!(format!("{}", truncated), "UDP src=9400 dst=43 len=??");
+    }
+
     #[test]
     fn test_emit() {
         let repr = packet_repr();
@@ -0,0 +1,471 @@
+//! The Virtual Router Redundancy Protocol.
+//!
+//! Supports the IPv4 variants of both version 2 ([RFC3768]) and version 3 ([RFC5798]). The
+//! versions differ in the unit of the advertisement interval, the authentication trailer of
+//! version 2 and the checksum which includes the IP pseudo header only in version 3.
+//!
+//! [RFC3768]: https://tools.ietf.org/html/rfc3768
+//! [RFC5798]: https://tools.ietf.org/html/rfc5798
+use core::fmt;
+use byteorder::{ByteOrder, NetworkEndian};
+
+use crate::wire::{ethernet, ip, Error, Result};
+use crate::wire::ip::v4::Address;
+use crate::wire::pretty_print::{PrettyPrint, PrettyIndent};
+
+use super::ip::checksum;
+
+/// The multicast group to which advertisements are sent.
+pub const MULTICAST_ADDR: Address = Address([224, 0, 0, 18]);
+
+/// The link layer address corresponding to `MULTICAST_ADDR`.
+pub const MULTICAST_MAC: ethernet::Address = ethernet::Address([0x01, 0x00, 0x5e, 0x00, 0x00, 0x12]);
+
+/// The required hop limit of advertisements.
+pub const HOP_LIMIT: u8 = 255;
+
+/// The priority of the router that owns the virtual addresses.
+pub const PRIORITY_OWNER: u8 = 255;
+
+/// The priority announcing that the current master stops participating.
+pub const PRIORITY_RELEASE: u8 = 0;
+
+byte_wrapper! {
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct vrrp([u8]);
+}
+
+enum_with_unknown! {
+    /// The version of the protocol.
+    pub doc enum Version(u8) {
+        /// Version 2, RFC3768.
+        V2 = 2,
+        /// Version 3, RFC5798.
+        V3 = 3,
+    }
+}
+
+mod field {
+    #![allow(non_snake_case)]
+    use crate::wire::field::Field;
+
+    pub(crate) const VER_TYPE: usize = 0;
+    pub(crate) const VRID: usize = 1;
+    pub(crate) const PRIORITY: usize = 2;
+    pub(crate) const COUNT: usize = 3;
+    /// Authentication type in v2.
+    pub(crate) const AUTH_TYPE: usize = 4;
+    /// Advertisement interval in seconds in v2.
+    pub(crate) const ADVER_INT_V2: usize = 5;
+    /// Max advertisement interval in centiseconds in v3, upper 4 bits reserved.
+    pub(crate) const ADVER_INT_V3: Field = 4..6;
+    pub(crate) const CHECKSUM: Field = 6..8;
+
+    pub(crate) fn ADDRESSES(count: u8) -> Field {
+        CHECKSUM.end..CHECKSUM.end + 4*usize::from(count)
+    }
+
+    pub(crate) const AUTH_DATA_LEN: usize = 8;
+}
+
+/// The only defined type of VRRP message.
+const TYPE_ADVERTISEMENT: u8 = 1;
+
+/// A high-level representation of a VRRP advertisement.
+///
+/// The virtual addresses themselves are not part of the representation, only their count. They
+/// are accessed on the packet instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Repr {
+    /// The protocol version.
+    pub version: Version,
+    /// The virtual router identifier.
+    pub vrid: u8,
+    /// The priority of the sending router.
+    pub priority: u8,
+    /// The number of virtual addresses.
+    pub addr_count: u8,
+    /// The advertisement interval in centiseconds.
+    ///
+    /// Version 2 only supports full seconds, the value is rounded up to at least one second when
+    /// emitting.
+    pub interval: u16,
+}
+
+/// Control the checksum handling.
+///
+/// In version 3 the checksum covers the IPv4 pseudo header so that the addresses must be known.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Checksum {
+    /// Fill and verify the checksum.
+    Manual {
+        /// The source address of the enclosing ip packet.
+        src_addr: Address,
+        /// The destination address of the enclosing ip packet.
+        dst_addr: Address,
+    },
+
+    /// Never inspect the checksum.
+    Ignored,
+}
+
+impl vrrp {
+    /// Imbue a raw octet buffer with VRRP packet structure.
+    pub fn new_unchecked(data: &[u8]) -> &Self {
+        Self::__from_macro_new_unchecked(data)
+    }
+
+    /// Imbue a mutable octet buffer with VRRP packet structure.
+    pub fn new_unchecked_mut(data: &mut [u8]) -> &mut Self {
+        Self::__from_macro_new_unchecked_mut(data)
+    }
+
+    /// Shorthand for a combination of `new_unchecked` and `check_len`.
+    pub fn new_checked(data: &[u8]) -> Result<&Self> {
+        Self::new_unchecked(data).check_len()?;
+        Ok(Self::new_unchecked(data))
+    }
+
+    /// Unwrap the packet as a raw byte slice.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Unwrap the packet as a mutable raw byte slice.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    ///
+    /// Returns `Err(Error::Truncated)` if the buffer is too short for the header, the addresses
+    /// or the authentication data of version 2.
+    pub fn check_len(&self) -> Result<()> {
+        if self.0.len() < field::CHECKSUM.end {
+            return Err(Error::Truncated);
+        }
+
+        if self.0.len() < self.packet_len() {
+            return Err(Error::Truncated);
+        }
+
+        Ok(())
+    }
+
+    /// The length of the packet as indicated by its header.
+    pub fn packet_len(&self) -> usize {
+        let trailer = match self.version() {
+            Version::V2 => field::AUTH_DATA_LEN,
+            _ => 0,
+        };
+        field::ADDRESSES(self.addr_count()).end + trailer
+    }
+
+    /// Return the version field.
+    pub fn version(&self) -> Version {
+        Version::from(self.0[field::VER_TYPE] >> 4)
+    }
+
+    /// Return the message type field.
+    pub fn msg_type(&self) -> u8 {
+        self.0[field::VER_TYPE] & 0xf
+    }
+
+    /// Return the virtual router identifier.
+    pub fn vrid(&self) -> u8 {
+        self.0[field::VRID]
+    }
+
+    /// Return the priority field.
+    pub fn priority(&self) -> u8 {
+        self.0[field::PRIORITY]
+    }
+
+    /// Return the number of virtual addresses.
+    pub fn addr_count(&self) -> u8 {
+        self.0[field::COUNT]
+    }
+
+    /// Return the authentication type of a version 2 packet.
+    pub fn auth_type(&self) -> u8 {
+        self.0[field::AUTH_TYPE]
+    }
+
+    /// Return the advertisement interval in centiseconds.
+    pub fn interval(&self) -> u16 {
+        match self.version() {
+            Version::V2 => u16::from(self.0[field::ADVER_INT_V2]) * 100,
+            _ => NetworkEndian::read_u16(&self.0[field::ADVER_INT_V3]) & 0xfff,
+        }
+    }
+
+    /// Return the checksum field.
+    pub fn checksum(&self) -> u16 {
+        NetworkEndian::read_u16(&self.0[field::CHECKSUM])
+    }
+
+    /// Return a virtual address.
+    ///
+    /// # Panics
+    /// This function panics if `idx` is not smaller than the address count.
+    pub fn addr(&self, idx: u8) -> Address {
+        assert!(idx < self.addr_count());
+        let start = field::ADDRESSES(idx).end;
+        Address::from_bytes(&self.0[start..start + 4])
+    }
+
+    /// Set the version and type field.
+    pub fn set_version(&mut self, version: Version) {
+        self.0[field::VER_TYPE] = (u8::from(version) << 4) | TYPE_ADVERTISEMENT;
+    }
+
+    /// Set the virtual router identifier.
+    pub fn set_vrid(&mut self, value: u8) {
+        self.0[field::VRID] = value;
+    }
+
+    /// Set the priority field.
+    pub fn set_priority(&mut self, value: u8) {
+        self.0[field::PRIORITY] = value;
+    }
+
+    /// Set the number of virtual addresses.
+    pub fn set_addr_count(&mut self, value: u8) {
+        self.0[field::COUNT] = value;
+    }
+
+    /// Set the advertisement interval in centiseconds.
+    ///
+    /// This uses the version field of the packet to determine the encoding. Also clears the
+    /// authentication type of version 2 packets. Their interval is rounded up to full seconds and
+    /// never zero, as a peer would otherwise consider the master down right away.
+    pub fn set_interval(&mut self, value: u16) {
+        match self.version() {
+            Version::V2 => {
+                self.0[field::AUTH_TYPE] = 0;
+                self.0[field::ADVER_INT_V2] = value.div_ceil(100).clamp(1, 0xff) as u8;
+            },
+            _ => NetworkEndian::write_u16(&mut self.0[field::ADVER_INT_V3], value & 0xfff),
+        }
+    }
+
+    /// Set the checksum field.
+    pub fn set_checksum(&mut self, value: u16) {
+        NetworkEndian::write_u16(&mut self.0[field::CHECKSUM], value)
+    }
+
+    /// Set a virtual address.
+    ///
+    /// # Panics
+    /// This function panics if `idx` is not smaller than the address count.
+    pub fn set_addr(&mut self, idx: u8, addr: Address) {
+        assert!(idx < self.addr_count());
+        let start = field::ADDRESSES(idx).end;
+        self.0[start..start + 4].copy_from_slice(addr.as_bytes())
+    }
+
+    /// Compute the checksum of the packet.
+    ///
+    /// The pseudo header addresses are ignored in version 2.
+    fn compute_checksum(&self, src_addr: Address, dst_addr: Address) -> u16 {
+        let data = checksum::data(&self.0[..self.packet_len()]);
+        match self.version() {
+            Version::V2 => data,
+            _ => checksum::combine(&[
+                checksum::pseudo_header(&src_addr.into(), &dst_addr.into(),
+                                        ip::Protocol::Vrrp, self.packet_len() as u32),
+                data,
+            ]),
+        }
+    }
+
+    /// Compute and fill in the checksum.
+    pub fn fill_checksum(&mut self, src_addr: Address, dst_addr: Address) {
+        self.set_checksum(0);
+        let checksum = !self.compute_checksum(src_addr, dst_addr);
+        self.set_checksum(checksum)
+    }
+
+    /// Validate the checksum.
+    ///
+    /// # Fuzzing
+    /// This function always returns `true` when fuzzing.
+    pub fn verify_checksum(&self, src_addr: Address, dst_addr: Address) -> bool {
+        if cfg!(fuzzing) { return true }
+
+        self.compute_checksum(src_addr, dst_addr) == !0
+    }
+}
+
+impl AsRef<[u8]> for vrrp {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsMut<[u8]> for vrrp {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl Repr {
+    /// Parse a VRRP advertisement and return a high-level representation.
+    pub fn parse(packet: &vrrp, checksum: Checksum) -> Result<Repr> {
+        packet.check_len()?;
+
+        match packet.version() {
+            Version::V2 | Version::V3 => (),
+            Version::Unknown(_) => return Err(Error::Unrecognized),
+        }
+
+        if packet.msg_type() != TYPE_ADVERTISEMENT {
+            return Err(Error::Unrecognized);
+        }
+
+        if let Checksum::Manual { src_addr, dst_addr } = checksum {
+            if !packet.verify_checksum(src_addr, dst_addr) {
+                return Err(Error::WrongChecksum);
+            }
+        }
+
+        Ok(Repr {
+            version: packet.version(),
+            vrid: packet.vrid(),
+            priority: packet.priority(),
+            addr_count: packet.addr_count(),
+            interval: packet.interval(),
+        })
+    }
+
+    /// Return the length of a packet that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        let trailer = match self.version {
+            Version::V2 => field::AUTH_DATA_LEN,
+            _ => 0,
+        };
+        field::ADDRESSES(self.addr_count).end + trailer
+    }
+
+    /// Emit the header into a packet.
+    ///
+    /// The addresses must be filled in afterwards and only then the checksum can be computed. The
+    /// authentication data of version 2 is cleared.
+    pub fn emit(&self, packet: &mut vrrp) {
+        packet.set_version(self.version);
+        packet.set_vrid(self.vrid);
+        packet.set_priority(self.priority);
+        packet.set_addr_count(self.addr_count);
+        packet.set_interval(self.interval);
+        packet.set_checksum(0);
+
+        if let Version::V2 = self.version {
+            let auth = field::ADDRESSES(self.addr_count).end;
+            for byte in &mut packet.0[auth..auth + field::AUTH_DATA_LEN] {
+                *byte = 0;
+            }
+        }
+    }
+}
+
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VRRP version={} vrid={} priority={} addresses={} interval={}cs",
+            u8::from(self.version), self.vrid, self.priority, self.addr_count, self.interval)
+    }
+}
+
+impl PrettyPrint for vrrp {
+    fn pretty_print(buffer: &[u8], f: &mut fmt::Formatter,
+                    indent: &mut PrettyIndent) -> fmt::Result {
+        let packet = match vrrp::new_checked(buffer) {
+            Err(err) => return write!(f, "{}({})", indent, err),
+            Ok(packet) => packet,
+        };
+
+        match Repr::parse(packet, Checksum::Ignored) {
+            Err(err) => write!(f, "{}({})", indent, err),
+            Ok(repr) => write!(f, "{}{}", indent, repr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SRC_ADDR: Address = Address([192, 168, 1, 2]);
+    const VIRTUAL_ADDR: Address = Address([192, 168, 1, 1]);
+
+    #[test]
+    fn v3_roundtrip() {
+        let repr = Repr {
+            version: Version::V3,
+            vrid: 7,
+            priority: 100,
+            addr_count: 1,
+            interval: 100,
+        };
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        assert_eq!(bytes.len(), 12);
+
+        let packet = vrrp::new_unchecked_mut(&mut bytes);
+        repr.emit(packet);
+        packet.set_addr(0, VIRTUAL_ADDR);
+        packet.fill_checksum(SRC_ADDR, MULTICAST_ADDR);
+
+        assert_eq!(&packet.as_bytes()[..6], &[0x31, 7, 100, 1, 0, 100]);
+        assert!(packet.verify_checksum(SRC_ADDR, MULTICAST_ADDR));
+        // The pseudo header is part of the checksum.
+        assert!(!packet.verify_checksum(VIRTUAL_ADDR, MULTICAST_ADDR));
+
+        let checksum = Checksum::Manual { src_addr: SRC_ADDR, dst_addr: MULTICAST_ADDR };
+        let packet = vrrp::new_checked(&bytes).unwrap();
+        assert_eq!(Repr::parse(packet, checksum), Ok(repr));
+        assert_eq!(packet.addr(0), VIRTUAL_ADDR);
+    }
+
+    #[test]
+    fn v2_roundtrip() {
+        let repr = Repr {
+            version: Version::V2,
+            vrid: 1,
+            priority: 255,
+            addr_count: 2,
+            interval: 300,
+        };
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        assert_eq!(bytes.len(), 24);
+
+        let packet = vrrp::new_unchecked_mut(&mut bytes);
+        repr.emit(packet);
+        packet.set_addr(0, VIRTUAL_ADDR);
+        packet.set_addr(1, SRC_ADDR);
+        packet.fill_checksum(SRC_ADDR, MULTICAST_ADDR);
+
+        assert_eq!(&packet.as_bytes()[..6], &[0x21, 1, 255, 2, 0, 3]);
+        assert_eq!(&packet.as_bytes()[16..], &[0; 8]);
+        // The pseudo header is not part of the checksum.
+        assert!(packet.verify_checksum(VIRTUAL_ADDR, VIRTUAL_ADDR));
+
+        let packet = vrrp::new_checked(&bytes).unwrap();
+        assert_eq!(Repr::parse(packet, Checksum::Ignored), Ok(repr));
+        assert_eq!(packet.addr(1), SRC_ADDR);
+
+        // Sub-second intervals are rounded up.
+        let packet = vrrp::new_unchecked_mut(&mut bytes);
+        packet.set_interval(50);
+        assert_eq!(packet.interval(), 100);
+        packet.set_interval(0);
+        assert_eq!(packet.interval(), 100);
+        packet.set_interval(301);
+        assert_eq!(packet.interval(), 400);
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = [0x31, 7, 100, 2, 0, 100, 0, 0, 192, 168, 1, 1];
+        assert_eq!(vrrp::new_checked(&bytes[..]), Err(Error::Truncated));
+        assert_eq!(vrrp::new_checked(&bytes[..6]), Err(Error::Truncated));
+    }
+}
@@ -0,0 +1,74 @@
+//! Pings the host across a tap interface.
+//!
+//! Requires a tap interface that is set up for the current user (likely requires root or sudo):
+//!
+//! > $ ip tuntap add mode tap user $USER name tap0
+//! > $ ip addr add 10.0.0.2/24 dev tap0
+//! > $ ip link set up dev tap0
+//!
+//! Then run the test with the `tap-test` feature. Another interface name can be chosen with the
+//! `ETHOX_TAP` environment variable.
+//!
+//! > $ cargo test --features tap-test --test tap_ping
+use std::env;
+use std::os::unix::io::AsRawFd;
+
+use ethox::managed::Slice;
+use ethox::nic::{Device, sys::{sys_wait, TapInterface}};
+use ethox::layer::{arp, eth, ip, icmp};
+use ethox::time::Duration;
+use ethox::wire::{ip::v4, ethernet};
+
+const MAC_ADDR_HOST: ethernet::Address = ethernet::Address([0x02, 0, 0, 0, 0, 1]);
+const IP_ADDR_HOST: v4::Address = v4::Address::new(10, 0, 0, 1);
+const IP_ADDR_TAP: v4::Address = v4::Address::new(10, 0, 0, 2);
+const PATTERN: icmp::EchoPattern = icmp::EchoPattern::Repeat(b"ethox");
+
+#[test]
+fn ping_host() {
+    let name = env::var("ETHOX_TAP").unwrap_or_else(|_| "tap0".into());
+    let mut interface = TapInterface::new(&name, vec![0; 1 << 14])
+        .expect("Couldn't initialize interface");
+    assert!(interface.personality().mtu().is_some());
+
+    let mut eth = eth::Endpoint::new(MAC_ADDR_HOST);
+    let mut neighbors = [arp::Neighbor::default(); 1];
+    let mut ip = ip::Endpoint::new(
+        Slice::One(v4::Cidr::new(IP_ADDR_HOST, 24).into()),
+        ip::Routes::new(Slice::empty()),
+        arp::NeighborCache::new(&mut neighbors[..]));
+    let mut icmp = icmp::Endpoint::new();
+
+    let mut reply = None;
+    // Retries give the address resolution some time.
+    for seq_no in 0..200 {
+        let _ = interface.tx(1, eth.send(ip.send(
+            icmp.send_with(|raw: icmp::RawPacket<_>| {
+                let init = icmp::Init::EchoRequest {
+                    source: ip::Source::Exact(IP_ADDR_HOST.into()),
+                    dst_addr: IP_ADDR_TAP.into(),
+                    ident: 0x4242,
+                    seq_no,
+                    payload: 32,
+                };
+                let time = raw.control.info().timestamp();
+                if let Ok(mut packet) = raw.prepare(init) {
+                    PATTERN.fill(packet.payload_mut_slice(), time);
+                    let _ = packet.send();
+                }
+            }))));
+
+        let _ = sys_wait(interface.as_raw_fd(), Some(Duration::from_millis(10)));
+        interface.rx(10, eth.recv(ip.recv(
+            icmp.recv_with(|packet: icmp::InPacket<_>| {
+                reply = reply.or(packet.verify_echo(&PATTERN));
+            }))))
+            .unwrap_or_else(|err| panic!("Error during receive {:?} {:?}", err, interface.last_err()));
+
+        if reply.is_some() {
+            break;
+        }
+    }
+
+    assert_eq!(reply, Some(icmp::EchoStatus::Intact { sent: None }));
+}